@@ -322,6 +322,7 @@ fn main() {
         "-serial".into(), "stdio".into(),
         "-display".into(), "gtk".into(),
         "-m".into(), ram,
+        "-nic".into(), "user,model=e1000,hostfwd=tcp::8080-:80".into(),
     ];
 
     if cfg.data_mb > 0 && data_path.exists() {
@@ -342,6 +343,7 @@ fn main() {
 
     println!("\n  drive 1 → disk.img ({} MB)", cfg.main_mb);
     if cfg.data_mb > 0 { println!("  drive 2 → data.img ({} MB)", cfg.data_mb); }
+    println!("  host :8080 → guest :80");
 
     println!("Starting QEMU...");
     let refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
@@ -0,0 +1,556 @@
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use crate::pci::{self, PciDevice, CLASS_STORAGE, PROG_IF_AHCI, SUBCLASS_SATA};
+use crate::vfs::block_dev::{check_io, BlockDevice, BlockError, SECTOR_SIZE};
+
+const HBA_CAP: u32 = 0x00;
+const HBA_GHC: u32 = 0x04;
+const HBA_IS:  u32 = 0x08;
+const HBA_PI:  u32 = 0x0C;
+const HBA_VS:  u32 = 0x10;
+
+const GHC_HR: u32 = 1 << 0;
+const GHC_IE: u32 = 1 << 1;
+const GHC_AE: u32 = 1 << 31;
+
+const CAP_S64A: u32 = 1 << 31;
+
+const PORT_BASE:   u32 = 0x100;
+const PORT_STRIDE: u32 = 0x80;
+
+const PX_CLB:  u32 = 0x00;
+const PX_CLBU: u32 = 0x04;
+const PX_FB:   u32 = 0x08;
+const PX_FBU:  u32 = 0x0C;
+const PX_IS:   u32 = 0x10;
+const PX_IE:   u32 = 0x14;
+const PX_CMD:  u32 = 0x18;
+const PX_TFD:  u32 = 0x20;
+const PX_SIG:  u32 = 0x24;
+const PX_SSTS: u32 = 0x28;
+const PX_SERR: u32 = 0x30;
+const PX_SACT: u32 = 0x34;
+const PX_CI:   u32 = 0x38;
+
+const PXCMD_ST:  u32 = 1 << 0;
+const PXCMD_SUD: u32 = 1 << 1;
+const PXCMD_POD: u32 = 1 << 2;
+const PXCMD_FRE: u32 = 1 << 4;
+const PXCMD_FR:  u32 = 1 << 14;
+const PXCMD_CR:  u32 = 1 << 15;
+
+const PXIS_DHRS: u32 = 1 << 0;
+const PXIS_PSS:  u32 = 1 << 1;
+const PXIS_DPS:  u32 = 1 << 5;
+const PXIS_IFS:  u32 = 1 << 27;
+const PXIS_HBDS: u32 = 1 << 28;
+const PXIS_HBFS: u32 = 1 << 29;
+const PXIS_TFES: u32 = 1 << 30;
+const PXIS_ERRORS: u32 = PXIS_IFS | PXIS_HBDS | PXIS_HBFS | PXIS_TFES;
+
+const TFD_ERR: u32 = 0x01;
+const TFD_DRQ: u32 = 0x08;
+const TFD_BSY: u32 = 0x80;
+
+const SIG_SATA:    u32 = 0x0000_0101;
+const DET_PRESENT: u32 = 3;
+const IPM_ACTIVE:  u32 = 1;
+
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+const FIS_REG_H2D_DWORDS: u16 = 5;
+
+const CMD_READ_DMA_EXT:  u8 = 0x25;
+const CMD_WRITE_DMA_EXT: u8 = 0x35;
+const CMD_FLUSH_EXT:     u8 = 0xEA;
+const CMD_IDENTIFY:      u8 = 0xEC;
+
+pub const MAX_DISKS: usize = 4;
+const MAX_PORTS:     usize = 32;
+
+const PRDT_ENTRIES:     usize = 16;
+const PRD_BYTES:        usize = 4096;
+const BOUNCE_BYTES:     usize = PRDT_ENTRIES * PRD_BYTES;
+const MAX_DMA_BATCH:    u32   = (BOUNCE_BYTES / SECTOR_SIZE) as u32;
+const CMD_TABLE_STRIDE: usize = 512;
+const DMA32_LIMIT:      u64   = 1 << 32;
+
+const SPIN_TIMEOUT: u32 = 10_000_000;
+const IRQ_TIMEOUT_TICKS: u64 = 5 * crate::interrupts::PIT_HZ as u64;
+
+#[derive(Debug, Clone, Copy)]
+pub enum AhciError {
+    NoDevice,
+    NoFreeSlot,
+    TaskFile(u8),
+    BufferTooSmall,
+    Timeout,
+}
+
+impl From<AhciError> for BlockError {
+    fn from(e: AhciError) -> Self {
+        match e {
+            AhciError::NoDevice       => BlockError::NoDevice,
+            AhciError::Timeout        => BlockError::Timeout,
+            AhciError::BufferTooSmall => BlockError::BufferTooSmall,
+            _                         => BlockError::Io,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CmdHeader {
+    flags: u16,
+    prdtl: u16,
+    prdbc: u32,
+    ctba:  u64,
+    _rsv:  [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PrdEntry {
+    dba:  u64,
+    _rsv: u32,
+    dbc:  u32,
+}
+
+#[repr(C)]
+struct CmdTable {
+    cfis: [u8; 64],
+    acmd: [u8; 16],
+    _rsv: [u8; 48],
+    prdt: [PrdEntry; PRDT_ENTRIES],
+}
+
+static ABAR:        AtomicU64  = AtomicU64::new(0);
+static IRQ_ENABLED: AtomicBool = AtomicBool::new(false);
+static PORT_EVENTS: [AtomicU32; MAX_PORTS] = [const { AtomicU32::new(0) }; MAX_PORTS];
+static DISKS: [Mutex<Option<AhciPort>>; MAX_DISKS] = [const { Mutex::new(None) }; MAX_DISKS];
+
+#[inline]
+fn hba_read(reg: u32) -> u32 {
+    let abar = ABAR.load(Ordering::Acquire);
+    unsafe { core::ptr::read_volatile((abar + reg as u64) as *const u32) }
+}
+
+#[inline]
+fn hba_write(reg: u32, val: u32) {
+    let abar = ABAR.load(Ordering::Acquire);
+    unsafe { core::ptr::write_volatile((abar + reg as u64) as *mut u32, val) }
+}
+
+#[inline]
+fn port_reg(port: u8, reg: u32) -> u32 {
+    PORT_BASE + port as u32 * PORT_STRIDE + reg
+}
+
+unsafe fn alloc_dma(bytes: usize, dma32: bool) -> Option<(u64, u64)> {
+    let frames = bytes.div_ceil(4096);
+    let phys = if dma32 {
+        crate::pmm::alloc_frames_below(frames, DMA32_LIMIT)?
+    } else {
+        crate::pmm::alloc_frames(frames)?
+    };
+    crate::net::map_mmio(phys, (frames * 4096) as u64);
+    let virt = crate::grub::phys_to_virt(phys);
+    core::ptr::write_bytes(virt as *mut u8, 0, frames * 4096);
+    Some((virt, phys))
+}
+
+fn wait_clear(port: u8, reg: u32, mask: u32) -> Result<(), AhciError> {
+    for _ in 0..SPIN_TIMEOUT {
+        if hba_read(port_reg(port, reg)) & mask == 0 {
+            return Ok(());
+        }
+        spin_loop();
+    }
+    Err(AhciError::Timeout)
+}
+
+pub struct AhciPort {
+    port:        u8,
+    slots:       u32,
+    clb_virt:    u64,
+    tables_virt: u64,
+    tables_phys: u64,
+    bounce_virt: u64,
+    bounce_phys: u64,
+    sectors:     u64,
+    model:       [u8; 40],
+}
+
+unsafe impl Send for AhciPort {}
+
+impl AhciPort {
+    fn read(&self, reg: u32) -> u32 {
+        hba_read(port_reg(self.port, reg))
+    }
+
+    fn write(&self, reg: u32, val: u32) {
+        hba_write(port_reg(self.port, reg), val)
+    }
+
+    fn stop(&self) -> Result<(), AhciError> {
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd & !PXCMD_ST);
+        wait_clear(self.port, PX_CMD, PXCMD_CR)?;
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd & !PXCMD_FRE);
+        wait_clear(self.port, PX_CMD, PXCMD_FR)
+    }
+
+    fn start(&self) -> Result<(), AhciError> {
+        wait_clear(self.port, PX_CMD, PXCMD_CR)?;
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd | PXCMD_FRE | PXCMD_SUD | PXCMD_POD);
+        let cmd = self.read(PX_CMD);
+        self.write(PX_CMD, cmd | PXCMD_ST);
+        Ok(())
+    }
+
+    fn setup(port: u8, slots: u32, dma32: bool) -> Option<Self> {
+        let (clb_virt, clb_phys)       = unsafe { alloc_dma(4096, dma32)? };
+        let (tables_virt, tables_phys) = unsafe { alloc_dma(slots as usize * CMD_TABLE_STRIDE, dma32)? };
+        let (bounce_virt, bounce_phys) = unsafe { alloc_dma(BOUNCE_BYTES, dma32)? };
+        let fb_phys = clb_phys + 1024;
+
+        let p = Self {
+            port, slots,
+            clb_virt, tables_virt, tables_phys, bounce_virt, bounce_phys,
+            sectors: 0,
+            model:   [b' '; 40],
+        };
+
+        p.stop().ok()?;
+        p.write(PX_CLB,  clb_phys as u32);
+        p.write(PX_CLBU, (clb_phys >> 32) as u32);
+        p.write(PX_FB,   fb_phys as u32);
+        p.write(PX_FBU,  (fb_phys >> 32) as u32);
+
+        for slot in 0..slots as usize {
+            let hdr = unsafe { &mut *(clb_virt as *mut CmdHeader).add(slot) };
+            hdr.ctba = tables_phys + (slot * CMD_TABLE_STRIDE) as u64;
+        }
+
+        p.write(PX_SERR, 0xFFFF_FFFF);
+        p.write(PX_IS,   0xFFFF_FFFF);
+        p.write(PX_IE,   PXIS_DHRS | PXIS_PSS | PXIS_DPS | PXIS_ERRORS);
+        p.start().ok()?;
+        Some(p)
+    }
+
+    fn free_slot(&self) -> Option<u32> {
+        let busy = self.read(PX_SACT) | self.read(PX_CI);
+        (0..self.slots).find(|s| busy & (1 << s) == 0)
+    }
+
+    fn exec(&mut self, cmd: u8, lba: u64, count: u16, bytes: usize, write: bool) -> Result<(), AhciError> {
+        if bytes > BOUNCE_BYTES {
+            return Err(AhciError::BufferTooSmall);
+        }
+        wait_clear(self.port, PX_TFD, TFD_BSY | TFD_DRQ)?;
+        let slot = self.free_slot().ok_or(AhciError::NoFreeSlot)?;
+
+        let prds = bytes.div_ceil(PRD_BYTES);
+        let hdr  = unsafe { &mut *(self.clb_virt as *mut CmdHeader).add(slot as usize) };
+        hdr.flags = FIS_REG_H2D_DWORDS | if write { 1 << 6 } else { 0 };
+        hdr.prdtl = prds as u16;
+        hdr.prdbc = 0;
+
+        let table = unsafe {
+            &mut *((self.tables_virt + slot as u64 * CMD_TABLE_STRIDE as u64) as *mut CmdTable)
+        };
+        table.cfis = [0; 64];
+        let fis = &mut table.cfis;
+        fis[0]  = FIS_TYPE_REG_H2D;
+        fis[1]  = 0x80;
+        fis[2]  = cmd;
+        fis[4]  = lba as u8;
+        fis[5]  = (lba >> 8)  as u8;
+        fis[6]  = (lba >> 16) as u8;
+        fis[7]  = 1 << 6;
+        fis[8]  = (lba >> 24) as u8;
+        fis[9]  = (lba >> 32) as u8;
+        fis[10] = (lba >> 40) as u8;
+        fis[12] = count as u8;
+        fis[13] = (count >> 8) as u8;
+
+        for i in 0..prds {
+            let len  = (bytes - i * PRD_BYTES).min(PRD_BYTES);
+            let last = if i + 1 == prds { 1 << 31 } else { 0 };
+            table.prdt[i] = PrdEntry {
+                dba:  self.bounce_phys + (i * PRD_BYTES) as u64,
+                _rsv: 0,
+                dbc:  (len as u32 - 1) | last,
+            };
+        }
+
+        PORT_EVENTS[self.port as usize].store(0, Ordering::Release);
+        fence(Ordering::SeqCst);
+        self.write(PX_CI, 1 << slot);
+
+        let result = self.wait_slot(slot);
+        self.write(PX_IS, 0xFFFF_FFFF);
+        if result.is_err() {
+            self.recover();
+        }
+        result
+    }
+
+    fn wait_slot(&self, slot: u32) -> Result<(), AhciError> {
+        let use_irq  = IRQ_ENABLED.load(Ordering::Acquire) && interrupts::are_enabled();
+        let deadline = crate::interrupts::get_tick() + IRQ_TIMEOUT_TICKS;
+        let mut spins = 0u32;
+        loop {
+            let events = PORT_EVENTS[self.port as usize].load(Ordering::Acquire) | self.read(PX_IS);
+            if events & PXIS_ERRORS != 0 {
+                return Err(AhciError::TaskFile((self.read(PX_TFD) >> 8) as u8));
+            }
+            if self.read(PX_CI) & (1 << slot) == 0 {
+                let tfd = self.read(PX_TFD);
+                if tfd & TFD_ERR != 0 {
+                    return Err(AhciError::TaskFile((tfd >> 8) as u8));
+                }
+                return Ok(());
+            }
+            if use_irq {
+                if crate::interrupts::get_tick() > deadline {
+                    return Err(AhciError::Timeout);
+                }
+                interrupts::disable();
+                if self.read(PX_CI) & (1 << slot) != 0 {
+                    interrupts::enable_and_hlt();
+                } else {
+                    interrupts::enable();
+                }
+            } else {
+                spins += 1;
+                if spins >= SPIN_TIMEOUT {
+                    return Err(AhciError::Timeout);
+                }
+                spin_loop();
+            }
+        }
+    }
+
+    fn recover(&self) {
+        crate::serial_println!(
+            "[ahci] port {} error: tfd={:#x} serr={:#x}, restarting",
+            self.port, self.read(PX_TFD), self.read(PX_SERR)
+        );
+        let _ = self.stop();
+        self.write(PX_SERR, 0xFFFF_FFFF);
+        self.write(PX_IS,   0xFFFF_FFFF);
+        let _ = self.start();
+    }
+
+    fn identify(&mut self) -> Result<u64, AhciError> {
+        self.exec(CMD_IDENTIFY, 0, 0, SECTOR_SIZE, false)?;
+        let words = unsafe { core::slice::from_raw_parts(self.bounce_virt as *const u16, 256) };
+
+        for (i, w) in words[27..47].iter().enumerate() {
+            self.model[i * 2]     = (*w >> 8) as u8;
+            self.model[i * 2 + 1] = *w as u8;
+        }
+
+        self.sectors = if words[83] & (1 << 10) != 0 {
+            words[100] as u64
+                | (words[101] as u64) << 16
+                | (words[102] as u64) << 32
+                | (words[103] as u64) << 48
+        } else {
+            words[60] as u64 | (words[61] as u64) << 16
+        };
+        Ok(self.sectors)
+    }
+
+    pub fn read_sectors(&mut self, lba: u64, buf: &mut [u8], count: u32) -> Result<(), AhciError> {
+        let bytes = count as usize * SECTOR_SIZE;
+        if count == 0 { return Ok(()); }
+        if count > MAX_DMA_BATCH || buf.len() < bytes { return Err(AhciError::BufferTooSmall); }
+        self.exec(CMD_READ_DMA_EXT, lba, count as u16, bytes, false)?;
+        let src = unsafe { core::slice::from_raw_parts(self.bounce_virt as *const u8, bytes) };
+        buf[..bytes].copy_from_slice(src);
+        Ok(())
+    }
+
+    pub fn write_sectors(&mut self, lba: u64, buf: &[u8], count: u32) -> Result<(), AhciError> {
+        let bytes = count as usize * SECTOR_SIZE;
+        if count == 0 { return Ok(()); }
+        if count > MAX_DMA_BATCH || buf.len() < bytes { return Err(AhciError::BufferTooSmall); }
+        let dst = unsafe { core::slice::from_raw_parts_mut(self.bounce_virt as *mut u8, bytes) };
+        dst.copy_from_slice(&buf[..bytes]);
+        self.exec(CMD_WRITE_DMA_EXT, lba, count as u16, bytes, true)
+    }
+
+    pub fn flush(&mut self) -> Result<(), AhciError> {
+        self.exec(CMD_FLUSH_EXT, 0, 0, 0, false)
+    }
+
+    pub fn model(&self) -> &str {
+        core::str::from_utf8(&self.model).unwrap_or("").trim()
+    }
+}
+
+pub fn handle_irq() {
+    if ABAR.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let is = hba_read(HBA_IS);
+    if is == 0 {
+        return;
+    }
+    for port in 0..MAX_PORTS as u8 {
+        if is & (1 << port) == 0 { continue; }
+        let pis = hba_read(port_reg(port, PX_IS));
+        hba_write(port_reg(port, PX_IS), pis);
+        PORT_EVENTS[port as usize].fetch_or(pis, Ordering::Release);
+    }
+    hba_write(HBA_IS, is);
+}
+
+fn reset_hba() -> Result<(), &'static str> {
+    hba_write(HBA_GHC, hba_read(HBA_GHC) | GHC_AE);
+    hba_write(HBA_GHC, hba_read(HBA_GHC) | GHC_HR);
+    for _ in 0..SPIN_TIMEOUT {
+        if hba_read(HBA_GHC) & GHC_HR == 0 {
+            hba_write(HBA_GHC, hba_read(HBA_GHC) | GHC_AE);
+            return Ok(());
+        }
+        spin_loop();
+    }
+    Err("AHCI controller reset timed out")
+}
+
+fn port_has_disk(port: u8) -> bool {
+    let ssts = hba_read(port_reg(port, PX_SSTS));
+    ssts & 0xF == DET_PRESENT
+        && (ssts >> 8) & 0xF == IPM_ACTIVE
+        && hba_read(port_reg(port, PX_SIG)) == SIG_SATA
+}
+
+pub fn init() -> Result<(), &'static str> {
+    crate::serial_println!("[ahci] init: scanning PCI");
+    let dev: PciDevice = pci::find(CLASS_STORAGE, SUBCLASS_SATA, PROG_IF_AHCI)
+        .ok_or("no AHCI controller found")?;
+    let abar_phys = dev.mem_bar(5).ok_or("AHCI controller has no ABAR")?;
+
+    dev.enable_bus_mastering();
+    crate::net::map_mmio(abar_phys, 0x1100);
+    ABAR.store(crate::grub::phys_to_virt(abar_phys), Ordering::Release);
+
+    reset_hba()?;
+
+    let cap   = hba_read(HBA_CAP);
+    let slots = ((cap >> 8) & 0x1F) + 1;
+    let vs    = hba_read(HBA_VS);
+    crate::serial_println!(
+        "[ahci] {:04x}:{:04x} at {:02x}:{:02x}.{} abar={:#x} v{}.{} slots={} 64bit={} irq={}",
+        dev.vendor, dev.device, dev.bus, dev.dev, dev.func, abar_phys,
+        vs >> 16, vs & 0xFFFF, slots, cap & CAP_S64A != 0, dev.irq
+    );
+
+    let pi = hba_read(HBA_PI);
+    let mut found = 0usize;
+    for port in 0..MAX_PORTS as u8 {
+        if pi & (1 << port) == 0 || !port_has_disk(port) { continue; }
+        if found >= MAX_DISKS {
+            crate::serial_println!("[ahci] port {}: disk ignored, {} disks max", port, MAX_DISKS);
+            continue;
+        }
+        let Some(mut p) = AhciPort::setup(port, slots, cap & CAP_S64A == 0) else {
+            crate::serial_println!("[ahci] port {}: setup failed", port);
+            continue;
+        };
+        match p.identify() {
+            Ok(sectors) => {
+                crate::serial_println!(
+                    "[ahci] port {} -> {}: '{}' {} sectors ({} MB)",
+                    port, crate::disk::dev_name(crate::disk::AHCI_BASE + found),
+                    p.model(), sectors, sectors * SECTOR_SIZE as u64 / (1024 * 1024)
+                );
+                *DISKS[found].lock() = Some(p);
+                found += 1;
+            }
+            Err(e) => crate::serial_println!("[ahci] port {}: identify failed: {:?}", port, e),
+        }
+    }
+
+    hba_write(HBA_IS, 0xFFFF_FFFF);
+    if crate::interrupts::unmask_pci_irq(dev.irq) {
+        hba_write(HBA_GHC, hba_read(HBA_GHC) | GHC_IE);
+        IRQ_ENABLED.store(true, Ordering::Release);
+    } else {
+        crate::serial_println!("[ahci] irq line {} not routable, polling for completion", dev.irq);
+    }
+
+    if found == 0 {
+        return Err("no SATA disks attached");
+    }
+    Ok(())
+}
+
+pub struct AhciDisk {
+    idx:     usize,
+    sectors: u64,
+}
+
+impl AhciDisk {
+    pub fn new(idx: usize) -> Self {
+        let sectors = DISKS.get(idx)
+            .and_then(|d| d.lock().as_ref().map(|p| p.sectors))
+            .unwrap_or(0);
+        Self { idx, sectors }
+    }
+
+    fn with_port<R>(&self, f: impl FnOnce(&mut AhciPort) -> Result<R, AhciError>) -> Result<R, BlockError> {
+        let slot = DISKS.get(self.idx).ok_or(BlockError::NoDevice)?;
+        let mut guard = slot.lock();
+        let port = guard.as_mut().ok_or(BlockError::NoDevice)?;
+        f(port).map_err(BlockError::from)
+    }
+}
+
+impl BlockDevice for AhciDisk {
+    fn read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), BlockError> {
+        if self.sectors == 0 { return Err(BlockError::NoDevice); }
+        check_io(self, lba, count, buf.len())?;
+        self.with_port(|port| {
+            let mut done = 0u32;
+            while done < count {
+                let n   = (count - done).min(MAX_DMA_BATCH);
+                let off = done as usize * SECTOR_SIZE;
+                port.read_sectors(lba + done as u64, &mut buf[off..off + n as usize * SECTOR_SIZE], n)?;
+                done += n;
+            }
+            Ok(())
+        })
+    }
+
+    fn write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<(), BlockError> {
+        if self.sectors == 0 { return Err(BlockError::NoDevice); }
+        check_io(self, lba, count, buf.len())?;
+        self.with_port(|port| {
+            let mut done = 0u32;
+            while done < count {
+                let n   = (count - done).min(MAX_DMA_BATCH);
+                let off = done as usize * SECTOR_SIZE;
+                port.write_sectors(lba + done as u64, &buf[off..off + n as usize * SECTOR_SIZE], n)?;
+                done += n;
+            }
+            Ok(())
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        if self.sectors == 0 { return Ok(()); }
+        self.with_port(|port| port.flush())
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+}
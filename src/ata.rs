@@ -1,6 +1,7 @@
 use core::hint::spin_loop;
 use core::sync::atomic::Ordering;
 use x86_64::instructions::port::Port;
+use crate::vfs::block_dev::{check_io, BlockDevice, BlockError};
 
 const STATUS_BSY: u8 = 0x80;
 const STATUS_DRQ: u8 = 0x08;
@@ -12,6 +13,10 @@ const CMD_WRITE_PIO:     u8 = 0x30;
 const CMD_READ_MULTIPLE: u8 = 0xC4;
 const CMD_WRITE_MULTIPLE:u8 = 0xC5;
 const CMD_CACHE_FLUSH:   u8 = 0xE7;
+const CMD_IDENTIFY:      u8 = 0xEC;
+
+const LBA28_LIMIT: u64 = 1 << 28;
+const MAX_PIO_BATCH: u32 = 255;
 
 #[derive(Debug, Clone, Copy)]
 pub enum AtaError {
@@ -28,16 +33,28 @@ pub enum AtaRole {
     Slave,
 }
 
+impl From<AtaError> for BlockError {
+    fn from(e: AtaError) -> Self {
+        match e {
+            AtaError::NoDevice       => BlockError::NoDevice,
+            AtaError::Timeout        => BlockError::Timeout,
+            AtaError::BufferTooSmall => BlockError::BufferTooSmall,
+            _                        => BlockError::Io,
+        }
+    }
+}
+
 pub struct AtaDrive {
     base_port: u16,
     role:      AtaRole,
+    sectors:   u32,
 }
 
 impl AtaDrive {
-    pub const EMPTY: Self = Self { base_port: 0, role: AtaRole::Master };
+    pub const EMPTY: Self = Self { base_port: 0, role: AtaRole::Master, sectors: 0 };
 
     pub const fn new(base_port: u16, role: AtaRole) -> Self {
-        Self { base_port, role }
+        Self { base_port, role, sectors: 0 }
     }
 
     pub fn primary()         -> Self { Self::new(0x1F0, AtaRole::Master) }
@@ -248,6 +265,46 @@ impl AtaDrive {
         Ok(())
     }
 
+    pub fn identify(&mut self) -> Result<u32, AtaError> {
+        if self.base_port == 0 { return Err(AtaError::NoDevice); }
+        let bp = self.base_port;
+
+        unsafe {
+            if Port::<u8>::new(bp + 7).read() == 0xFF {
+                return Err(AtaError::NoDevice);
+            }
+
+            Port::<u8>::new(self.control_port()).write(0x02);
+            Port::<u8>::new(bp + 6).write(self.device_select_byte(0) & 0xF0);
+            self.delay_400ns();
+
+            for reg in 2..6 {
+                Port::<u8>::new(bp + reg).write(0);
+            }
+            Port::<u8>::new(bp + 7).write(CMD_IDENTIFY);
+            self.delay_400ns();
+
+            if Port::<u8>::new(bp + 7).read() == 0 {
+                return Err(AtaError::NoDevice);
+            }
+            self.wait_not_busy()?;
+            if Port::<u8>::new(bp + 4).read() != 0 || Port::<u8>::new(bp + 5).read() != 0 {
+                return Err(AtaError::NoDevice);
+            }
+            self.wait_drq()?;
+
+            let mut data_port = Port::<u16>::new(bp);
+            let mut words = [0u16; 256];
+            for w in words.iter_mut() {
+                *w = data_port.read();
+            }
+            Port::<u8>::new(self.control_port()).write(0x00);
+
+            self.sectors = words[60] as u32 | (words[61] as u32) << 16;
+        }
+        Ok(self.sectors)
+    }
+
     pub fn flush(&mut self) -> Result<(), AtaError> {
         if self.base_port == 0 { return Ok(()); }
 
@@ -266,3 +323,43 @@ impl AtaDrive {
         }
     }
 }
+
+impl BlockDevice for AtaDrive {
+    fn read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_io(self, lba, count, buf.len())?;
+        if lba + count as u64 > LBA28_LIMIT {
+            return Err(BlockError::OutOfRange);
+        }
+        let mut done = 0u32;
+        while done < count {
+            let n   = (count - done).min(MAX_PIO_BATCH);
+            let off = done as usize * 512;
+            self.read_sectors(lba as u32 + done, &mut buf[off..off + n as usize * 512], n as u8)?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<(), BlockError> {
+        check_io(self, lba, count, buf.len())?;
+        if lba + count as u64 > LBA28_LIMIT {
+            return Err(BlockError::OutOfRange);
+        }
+        let mut done = 0u32;
+        while done < count {
+            let n   = (count - done).min(MAX_PIO_BATCH);
+            let off = done as usize * 512;
+            self.write_sectors(lba as u32 + done, &buf[off..off + n as usize * 512], n as u8)?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        AtaDrive::flush(self).map_err(BlockError::from)
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors as u64
+    }
+}
@@ -1,27 +1,26 @@
-use crate::ata::AtaDrive;
+extern crate alloc;
+use alloc::boxed::Box;
+use crate::disk;
 use crate::gpt::{
-    self, GptReadError, GptWriteError,
+    self, GptReadError, GptWriteError, Partition,
     GUID_LINUX_FS, GUID_LINUX_SWAP,
 };
 use crate::swap;
 use crate::{cprintln, print_error, print_success, print_warn, print_info, println};
 
-fn parse_drive(s: &str) -> Option<usize> {
-    match s { "0" => Some(0), "1" => Some(1), "2" => Some(2), "3" => Some(3), _ => None }
-}
-
 pub fn cmd_gpt_show(drive_str: &str) {
-    let idx = match parse_drive(drive_str) {
+    let idx = match disk::parse_index(drive_str) {
         Some(i) => i,
-        None => { print_error!("  usage: gpt <drive 0-3>"); return; }
+        None => { print_error!("  usage: gpt <drive 0-7>"); return; }
     };
-    let mut drive = AtaDrive::from_idx(idx);
+    let mut drive = disk::open(idx);
 
     let tbl = match gpt::gpt_read(&mut drive) {
         Ok(t)  => t,
         Err(GptReadError::NotGpt)       => { print_error!("  disk {} has no GPT", idx); return; }
         Err(GptReadError::InvalidFormat) => { print_error!("  corrupted GPT header"); return; }
         Err(GptReadError::Io(e))        => { print_error!("  I/O error: {:?}", e); return; }
+        Err(GptReadError::NoPartition)  => { print_error!("  disk {} has no GPT", idx); return; }
     };
 
     cprintln!(57, 197, 187, "  GPT partition table -- disk {}", idx);
@@ -55,11 +54,11 @@ pub fn cmd_gpt_show(drive_str: &str) {
 }
 
 pub fn cmd_gpt_init(drive_str: &str) {
-    let idx = match parse_drive(drive_str) {
+    let idx = match disk::parse_index(drive_str) {
         Some(i) => i,
-        None => { print_error!("  usage: gpt.init <drive 0-3>"); return; }
+        None => { print_error!("  usage: gpt.init <drive 0-7>"); return; }
     };
-    let mut drive = AtaDrive::from_idx(idx);
+    let mut drive = disk::open(idx);
 
     let total_sectors = gpt::gpt_probe_sectors(&mut drive);
     if total_sectors < 2048 {
@@ -71,8 +70,7 @@ pub fn cmd_gpt_init(drive_str: &str) {
     cprintln!(255, 80, 80, "  !! data in those sectors will be destroyed !!");
     println!();
 
-    let drive2 = AtaDrive::from_idx(idx);
-    match gpt::gpt_init(drive2, total_sectors) {
+    match gpt::gpt_init(&mut drive, total_sectors) {
         Ok(()) => {
             print_success!("  GPT initialized on disk {}", idx);
             println!("  Total sectors: {}", total_sectors);
@@ -89,7 +87,7 @@ pub fn cmd_gpt_add(args: &str) {
     let size_str  = parts.next().unwrap_or("");
     let name      = parts.next().unwrap_or("partition");
 
-    let idx = match parse_drive(drive_str) {
+    let idx = match disk::parse_index(drive_str) {
         Some(i) => i,
         None => {
             print_error!("  usage: gpt.add <drive> <fs|swap> <size_mb> [name]");
@@ -112,9 +110,9 @@ pub fn cmd_gpt_add(args: &str) {
     };
 
     let size_sectors = size_mb * 1024 * 1024 / 512;
-    let drive = AtaDrive::from_idx(idx);
+    let mut drive = disk::open(idx);
 
-    match gpt::gpt_add_partition(drive, type_guid, size_sectors, name, 0xABCD1234) {
+    match gpt::gpt_add_partition(&mut drive, type_guid, size_sectors, name, 0xABCD1234) {
         Ok(slot) => {
             print_success!("  partition added: slot {}", slot);
             println!("  Type:  {}", type_label);
@@ -135,9 +133,9 @@ pub fn cmd_gpt_add(args: &str) {
 }
 
 pub fn cmd_gpt_del(drive_str: &str, index_str: &str) {
-    let idx = match parse_drive(drive_str) {
+    let idx = match disk::parse_index(drive_str) {
         Some(i) => i,
-        None => { print_error!("  usage: gpt.del <drive 0-3> <partition>"); return; }
+        None => { print_error!("  usage: gpt.del <drive 0-7> <partition>"); return; }
     };
     let part_num: usize = match index_str.parse() {
         Ok(n) if n >= 1 => n,
@@ -145,9 +143,9 @@ pub fn cmd_gpt_del(drive_str: &str, index_str: &str) {
     };
     let part_idx = part_num - 1;
 
-    let drive = AtaDrive::from_idx(idx);
+    let mut drive = disk::open(idx);
 
-    match gpt::gpt_del_partition(drive, part_idx) {
+    match gpt::gpt_del_partition(&mut drive, part_idx) {
         Ok(()) => print_success!("  partition {} deleted from disk {}", part_num, idx),
         Err(GptWriteError::InvalidIndex) => print_error!("  partition {} does not exist", part_num),
         Err(GptWriteError::ReadFailed)   => print_error!("  GPT read failed"),
@@ -157,9 +155,9 @@ pub fn cmd_gpt_del(drive_str: &str, index_str: &str) {
 }
 
 pub fn cmd_mkswap(drive_str: &str, part_str: &str) {
-    let drive_idx = match parse_drive(drive_str) {
+    let drive_idx = match disk::parse_index(drive_str) {
         Some(i) => i,
-        None => { print_error!("  usage: mkswap <drive 0-3> <partition>"); return; }
+        None => { print_error!("  usage: mkswap <drive 0-7> <partition>"); return; }
     };
     let part_num: usize = match part_str.parse() {
         Ok(n) if n >= 1 => n,
@@ -167,7 +165,7 @@ pub fn cmd_mkswap(drive_str: &str, part_str: &str) {
     };
     let part_idx = part_num - 1;
 
-    let mut drive = AtaDrive::from_idx(drive_idx);
+    let mut drive = disk::open(drive_idx);
 
     let tbl = match gpt::gpt_read(&mut drive) {
         Ok(t)  => t,
@@ -186,11 +184,11 @@ pub fn cmd_mkswap(drive_str: &str, part_str: &str) {
         return;
     }
 
-    let partition_lba     = entry.start_lba as u32;
+    let partition_lba     = entry.start_lba;
     let partition_sectors = entry.size_sectors() as u32;
 
-    let drive2 = AtaDrive::from_idx(drive_idx);
-    match swap::mkswap(drive2, partition_lba, partition_sectors, "miku-swap") {
+    let mut part = Partition::new(drive, partition_lba, partition_sectors as u64);
+    match swap::mkswap(&mut part, "miku-swap") {
         Ok(()) => {
             print_success!("  swap formatted: partition {} on disk {}", part_num, drive_idx);
             println!("  Size:  {} MB", partition_sectors as u64 * 512 / (1024 * 1024));
@@ -202,9 +200,9 @@ pub fn cmd_mkswap(drive_str: &str, part_str: &str) {
 }
 
 pub fn cmd_swapon(drive_str: &str, part_str: &str) {
-    let drive_idx = match parse_drive(drive_str) {
+    let drive_idx = match disk::parse_index(drive_str) {
         Some(i) => i,
-        None => { print_error!("  usage: swapon <drive 0-3> <partition>"); return; }
+        None => { print_error!("  usage: swapon <drive 0-7> <partition>"); return; }
     };
     let part_num: usize = match part_str.parse() {
         Ok(n) if n >= 1 => n,
@@ -212,7 +210,7 @@ pub fn cmd_swapon(drive_str: &str, part_str: &str) {
     };
     let part_idx = part_num - 1;
 
-    let mut drive = AtaDrive::from_idx(drive_idx);
+    let mut drive = disk::open(drive_idx);
 
     let tbl = match gpt::gpt_read(&mut drive) {
         Ok(t)  => t,
@@ -230,11 +228,11 @@ pub fn cmd_swapon(drive_str: &str, part_str: &str) {
         return;
     }
 
-    let partition_lba     = entry.start_lba as u32;
-    let partition_sectors = entry.size_sectors() as u32;
+    let partition_lba     = entry.start_lba;
+    let partition_sectors = entry.size_sectors();
 
-    let drive2 = AtaDrive::from_idx(drive_idx);
-    match swap::swapon(drive2, drive_idx, partition_lba, partition_sectors) {
+    let part = Partition::new(drive, partition_lba, partition_sectors);
+    match swap::swapon(Box::new(part), drive_idx, partition_lba as u32) {
         Ok(pages) => {
             print_success!("  swap activated");
             println!("  Drive:   {}", drive_idx);
@@ -286,9 +284,9 @@ pub fn cmd_mkswap_raw(args: &str) {
     let lba_str   = parts.next().unwrap_or("");
     let size_str  = parts.next().unwrap_or("");
 
-    let drive_idx = match parse_drive(drive_str) {
+    let drive_idx = match disk::parse_index(drive_str) {
         Some(i) => i,
-        None => { print_error!("  usage: mkswap.raw <drive 0-3> <start_lba> <size_mb>"); return; }
+        None => { print_error!("  usage: mkswap.raw <drive 0-7> <start_lba> <size_mb>"); return; }
     };
     let start_lba: u32 = match lba_str.parse() {
         Ok(n) => n,
@@ -300,9 +298,9 @@ pub fn cmd_mkswap_raw(args: &str) {
     };
 
     let size_sectors = size_mb * 1024 * 1024 / 512;
-    let drive = AtaDrive::from_idx(drive_idx);
+    let mut part = Partition::new(disk::open(drive_idx), start_lba as u64, size_sectors as u64);
 
-    match swap::mkswap(drive, start_lba, size_sectors, "miku-swap") {
+    match swap::mkswap(&mut part, "miku-swap") {
         Ok(()) => {
             print_success!("  swap formatted on drive {} LBA {} size {} MB", drive_idx, start_lba, size_mb);
             print_info!("  Activate: swapon.raw {} {} {}", drive_idx, start_lba, size_sectors);
@@ -317,9 +315,9 @@ pub fn cmd_swapon_raw(args: &str) {
     let lba_str     = parts.next().unwrap_or("");
     let sectors_str = parts.next().unwrap_or("");
 
-    let drive_idx = match parse_drive(drive_str) {
+    let drive_idx = match disk::parse_index(drive_str) {
         Some(i) => i,
-        None => { print_error!("  usage: swapon.raw <drive 0-3> <start_lba> <size_sectors>"); return; }
+        None => { print_error!("  usage: swapon.raw <drive 0-7> <start_lba> <size_sectors>"); return; }
     };
     let start_lba: u32 = match lba_str.parse() {
         Ok(n) => n,
@@ -330,9 +328,9 @@ pub fn cmd_swapon_raw(args: &str) {
         _ => { print_error!("  invalid size_sectors"); return; }
     };
 
-    let drive = AtaDrive::from_idx(drive_idx);
+    let part = Partition::new(disk::open(drive_idx), start_lba as u64, size_sectors as u64);
 
-    match swap::swapon(drive, drive_idx, start_lba, size_sectors) {
+    match swap::swapon(Box::new(part), drive_idx, start_lba) {
         Ok(pages) => {
             print_success!("  swap activated  drive={} lba={}", drive_idx, start_lba);
             crate::println!("  Pages: {}  Size: {} MB", pages, pages as u64 * 4096 / (1024 * 1024));
@@ -352,21 +350,21 @@ pub fn cmd_swapon_auto() {
 
     crate::println!("  Scanning drives for swap...");
 
-    for drive_idx in 0..4usize {
-        let mut drive = AtaDrive::from_idx(drive_idx);
+    for drive_idx in 0..disk::MAX_DRIVES {
+        let mut drive = disk::open(drive_idx);
 
         let mut probe = [0u8; 512];
-        if drive.read_sector(0, &mut probe).is_err() {
+        if drive.read(0, 1, &mut probe).is_err() {
             continue;
         }
 
         if let Ok(tbl) = gpt::gpt_read(&mut drive) {
             for entry in tbl.entries.iter() {
                 if !entry.is_used() || !entry.is_swap() { continue; }
-                let lba      = entry.start_lba as u32;
-                let sectors  = entry.size_sectors() as u32;
-                let drive2   = AtaDrive::from_idx(drive_idx);
-                match swap::swapon(drive2, drive_idx, lba, sectors) {
+                let lba     = entry.start_lba;
+                let sectors = entry.size_sectors();
+                let part    = Partition::new(disk::open(drive_idx), lba, sectors);
+                match swap::swapon(Box::new(part), drive_idx, lba as u32) {
                     Ok(pages) => {
                         print_success!("  swap found and activated on drive {}", drive_idx);
                         crate::println!("  Pages: {}  Size: {} MB", pages, pages as u64 * 4096 / (1024*1024));
@@ -378,18 +376,15 @@ pub fn cmd_swapon_auto() {
             }
         }
 
-        let drive3 = AtaDrive::from_idx(drive_idx);
-        let total = gpt::gpt_probe_sectors(&mut AtaDrive::from_idx(drive_idx));
+        let total = gpt::gpt_probe_sectors(&mut drive);
         if total > 16 {
-            let drive4 = AtaDrive::from_idx(drive_idx);
-            if let Ok(pages) = swap::swapon(drive4, drive_idx, 0, total as u32) {
+            let whole = Partition::new(drive, 0, total as u64);
+            if let Ok(pages) = swap::swapon(Box::new(whole), drive_idx, 0) {
                 print_success!("  whole-disk swap activated on drive {}", drive_idx);
                 crate::println!("  Pages: {}  Size: {} MB", pages, pages as u64 * 4096 / (1024*1024));
-                let _ = drive3;
                 return;
             }
         }
-        let _ = drive3;
     }
 
     print_error!("  no swap found on any drive");
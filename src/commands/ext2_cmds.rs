@@ -1,61 +1,38 @@
-use crate::ata::AtaDrive;
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::string::String;
+use crate::disk;
+use crate::gpt::Partition;
 use crate::miku_extfs::ext2::write::TreeResult;
 use crate::miku_extfs::ext3::journal::{TxnTag, DEFAULT_JOURNAL_BLOCKS};
 use crate::miku_extfs::reader::DiskReader;
 use crate::miku_extfs::structs::*;
 use crate::miku_extfs::{FsError, MikuFS};
 use crate::{cprint, cprintln, print_error, print_success, println, serial_println};
+use crate::vfs::block_dev::{BlockDevice, FileImage};
 use crate::vfs::path::split_parent_name;
 use spin::Mutex;
 
 const MAX_MOUNTS: usize = 4;
-
-const EMPTY_FS: MikuFS = MikuFS {
-    superblock:       Superblock { data: [0; 1024] },
-    block_size:       0,
-    inodes_per_group: 0,
-    blocks_per_group: 0,
-    group_count:      0,
-    groups:           [GroupDesc { data: [0; 64] }; 32],
-    reader: DiskReader {
-        drive:     AtaDrive::EMPTY,
-        start_lba: 0,
-        io_count:  0,
-    },
-    journal_seq:      0,
-    journal_pos:      0,
-    journal_maxlen:   0,
-    journal_first:    0,
-    journal_active:   false,
-    txn_active:       false,
-    txn_desc_pos:     0,
-    txn_tags:         [TxnTag { fs_block: 0, journal_pos: 0 }; 64],
-    txn_tag_count:    0,
-    txn_revokes:      [0; 128],
-    txn_revoke_count: 0,
-    block_cache:      None,
-    superblock_dirty: false,
-    groups_dirty:     [false; 32],
-    last_sync_ticks:  0,
-    journal_inode_cached: None,
-    alloc_hint: [0u32; 32],
-};
+const IMAGE_DRIVE: usize = usize::MAX;
 
 struct ExtFsState {
     slots:       [MikuFS; MAX_MOUNTS],
     ready:       [bool; MAX_MOUNTS],
     drive_idx:   [usize; MAX_MOUNTS],
     start_lba:   [u32; MAX_MOUNTS],
+    image:       [Option<String>; MAX_MOUNTS],
     active_slot: usize,
 }
 
 impl ExtFsState {
     const fn new() -> Self {
         Self {
-            slots:       [EMPTY_FS; MAX_MOUNTS],
+            slots:       [MikuFS::EMPTY; MAX_MOUNTS],
             ready:       [false; MAX_MOUNTS],
             drive_idx:   [0; MAX_MOUNTS],
             start_lba:   [0; MAX_MOUNTS],
+            image:       [const { None }; MAX_MOUNTS],
             active_slot: 0,
         }
     }
@@ -77,6 +54,10 @@ impl ExtFsState {
         }
         false
     }
+
+    fn image_slot(&self, path: &str) -> Option<usize> {
+        (0..MAX_MOUNTS).find(|&i| self.ready[i] && self.image[i].as_deref() == Some(path))
+    }
 }
 
 static STATE: Mutex<ExtFsState> = Mutex::new(ExtFsState::new());
@@ -93,19 +74,6 @@ pub fn is_ext2_ready() -> bool {
     state.ready[state.active_slot]
 }
 
-pub fn active_fs_type() -> crate::vfs::types::FsType {
-    let state = STATE.lock();
-    let slot = state.active_slot;
-    if !state.ready[slot] {
-        return crate::vfs::types::FsType::Ext2;
-    }
-    match state.slots[slot].superblock.fs_version_str() {
-        "ext4" => crate::vfs::types::FsType::Ext4,
-        "ext3" => crate::vfs::types::FsType::Ext3,
-        _      => crate::vfs::types::FsType::Ext2,
-    }
-}
-
 pub fn ext_fs_version_tag() -> &'static str {
     let state = STATE.lock();
     let slot = state.active_slot;
@@ -120,12 +88,124 @@ where
     STATE.lock().active_fs().map(f)
 }
 
-pub fn force_unmount() {
+fn pick_slot(state: &ExtFsState, slot: u8) -> Option<usize> {
+    let s = slot as usize;
+    if s < MAX_MOUNTS && state.ready[s] { Some(s) } else { None }
+}
+
+pub fn with_ext_slot<F, R>(slot: u8, f: F) -> Option<R>
+where
+    F: FnOnce(&mut MikuFS) -> R,
+{
     let mut state = STATE.lock();
-    let slot = state.active_slot;
-    state.ready[slot] = false;
-    state.slots[slot].block_cache = None;
-    state.slots[slot].journal_inode_cached = None;
+    let s = pick_slot(&state, slot)?;
+    Some(f(&mut state.slots[s]))
+}
+
+pub fn slot_ready(slot: u8) -> bool {
+    let state = STATE.lock();
+    pick_slot(&state, slot).is_some()
+}
+
+pub fn slot_fs_type(slot: u8) -> crate::vfs::types::FsType {
+    let state = STATE.lock();
+    let Some(s) = pick_slot(&state, slot) else {
+        return crate::vfs::types::FsType::Ext2;
+    };
+    match state.slots[s].superblock.fs_version_str() {
+        "ext4" => crate::vfs::types::FsType::Ext4,
+        "ext3" => crate::vfs::types::FsType::Ext3,
+        _      => crate::vfs::types::FsType::Ext2,
+    }
+}
+
+pub fn active_slot() -> u8 {
+    STATE.lock().active_slot as u8
+}
+
+pub fn slot_label(slot: u8) -> Option<String> {
+    let state = STATE.lock();
+    let s = slot as usize;
+    if s >= MAX_MOUNTS || !state.ready[s] {
+        return None;
+    }
+    if let Some(path) = &state.image[s] {
+        return Some(path.clone());
+    }
+    let name = disk::dev_name(state.drive_idx[s]);
+    Some(match state.start_lba[s] {
+        0   => alloc::format!("/dev/{}", name),
+        lba => alloc::format!("{}@{}", name, lba),
+    })
+}
+
+pub fn parse_device(dev: &str) -> Option<(usize, usize)> {
+    let name = dev.strip_prefix("/dev/").unwrap_or(dev);
+    let (drive, part) = disk::parse_name(name)?;
+    if part.is_empty() {
+        return Some((drive, 0));
+    }
+    match part.parse::<usize>() {
+        Ok(n) if n >= 1 => Some((drive, n)),
+        _ => None,
+    }
+}
+
+fn partition_range(drive_idx: usize, part_num: usize) -> Result<(u32, u64), &'static str> {
+    if part_num == 0 {
+        return Ok((0, 0));
+    }
+    let mut drive = disk::open(drive_idx);
+    let tbl = crate::gpt::gpt_read(&mut drive).map_err(|_| "could not read GPT")?;
+    let entry = tbl.entries.get(part_num - 1).ok_or("no such partition")?;
+    if !entry.is_used() {
+        return Err("no such partition");
+    }
+    Ok((entry.start_lba as u32, entry.size_sectors()))
+}
+
+pub fn mount_device(drive_idx: usize, part_num: usize) -> Result<u8, &'static str> {
+    let (start_lba, sectors) = partition_range(drive_idx, part_num)?;
+    {
+        let state = STATE.lock();
+        for i in 0..MAX_MOUNTS {
+            if state.ready[i] && state.drive_idx[i] == drive_idx && state.start_lba[i] == start_lba {
+                return Ok(i as u8);
+            }
+        }
+    }
+    try_mount(drive_idx, start_lba, sectors).map(|s| s as u8).ok_or("no ext filesystem found")
+}
+
+pub fn mount_image(path: &str) -> Result<u8, &'static str> {
+    if let Some(slot) = STATE.lock().image_slot(path) {
+        return Ok(slot as u8);
+    }
+    let dev = FileImage::open(path).map_err(|_| "cannot open image")?;
+    attach(Box::new(dev), IMAGE_DRIVE, 0, Some(path))
+        .map(|s| s as u8)
+        .ok_or("no ext filesystem found")
+}
+
+pub fn release_slot(slot: u8) -> Result<(), FsError> {
+    let mut state = STATE.lock();
+    let s = slot as usize;
+    if s >= MAX_MOUNTS || !state.ready[s] {
+        return Ok(());
+    }
+    let r = state.slots[s].sync();
+    state.ready[s] = false;
+    state.slots[s].block_cache = None;
+    state.slots[s].journal_inode_cached = None;
+    state.slots[s].reader = DiskReader::EMPTY;
+    state.image[s] = None;
+    serial_println!("[miku_extfs] slot {} released", s);
+    if state.active_slot == s {
+        if let Some(other) = (0..MAX_MOUNTS).find(|&i| state.ready[i]) {
+            state.active_slot = other;
+        }
+    }
+    r
 }
 
 pub fn cmd_fs_list() {
@@ -136,15 +216,17 @@ pub fn cmd_fs_list() {
         if state.ready[slot] {
             any = true;
             let version = state.slots[slot].superblock.fs_version_str();
-            let drive   = state.drive_idx[slot];
-            let lba     = state.start_lba[slot];
+            let source  = match &state.image[slot] {
+                Some(path) => alloc::format!("image={}", path),
+                None       => alloc::format!("drive={} lba={}", state.drive_idx[slot], state.start_lba[slot]),
+            };
             let free_b  = state.slots[slot].superblock.free_blocks_count();
             let total_b = state.slots[slot].superblock.blocks_count();
             let bs      = state.slots[slot].block_size;
             let marker  = if slot == state.active_slot { " <- active" } else { "" };
             println!(
-                "  [{}] {} drive={} lba={} free={}/{} ({} MB){}",
-                slot, version, drive, lba,
+                "  [{}] {} {} free={}/{} ({} MB){}",
+                slot, version, source,
                 free_b, total_b,
                 free_b as u64 * bs as u64 / (1024 * 1024),
                 marker
@@ -179,30 +261,30 @@ pub fn cmd_fs_select(args: &str) {
 }
 
 pub fn cmd_fs_umount(args: &str) {
-    let mut state = STATE.lock();
     let slot: usize = if args.trim().is_empty() {
-        state.active_slot
+        STATE.lock().active_slot
     } else {
         match args.trim().parse() {
             Ok(n) if n < MAX_MOUNTS => n,
             _ => { print_error!("  usage: fs.umount [0|1]"); return; }
         }
     };
-    if !state.ready[slot] {
+    if let Some(path) = crate::vfs::core::with_vfs(|v| v.ext_mount_path(slot as u8)) {
+        print_error!("  slot {} is mounted at {} - use umount {}", slot, path, path);
+        return;
+    }
+    if !STATE.lock().ready[slot] {
         crate::print_warn!("  slot {} is already empty", slot);
         return;
     }
-    let _ = state.slots[slot].flush_all_dirty_metadata();
-    state.ready[slot] = false;
-    state.slots[slot].block_cache = None;
-    state.slots[slot].journal_inode_cached = None;
+    let was_active = active_slot() as usize == slot;
+    if let Err(e) = release_slot(slot as u8) {
+        print_error!("  sync failed: {:?}", e);
+    }
     print_success!("  slot {} unmounted", slot);
-    if state.active_slot == slot {
-        let other = 1 - slot;
-        if state.ready[other] {
-            state.active_slot = other;
-            println!("  active slot switched to {}", other);
-        }
+    let now = active_slot() as usize;
+    if was_active && now != slot {
+        println!("  active slot switched to {}", now);
     }
 }
 
@@ -234,15 +316,6 @@ fn parse_u16(s: &str) -> Option<u16> {
     Some(result)
 }
 
-fn make_ata_drive(idx: usize) -> AtaDrive {
-    match idx {
-        0 => AtaDrive::primary(),
-        1 => AtaDrive::primary_slave(),
-        2 => AtaDrive::secondary(),
-        _ => AtaDrive::secondary_slave(),
-    }
-}
-
 pub fn invalidate_drive_mounts(drive_idx: usize, start_lba: u32) {
     let mut state = STATE.lock();
     for i in 0..MAX_MOUNTS {
@@ -266,197 +339,129 @@ pub fn cmd_ext2_mount(args: &str) {
 
     if drive_str.is_empty() {
         serial_println!("[miku_extfs] scanning all drives...");
-        for &i in &[2usize, 1, 3, 0] {
+        for i in [2usize, 1, 3, 0].into_iter().chain(disk::AHCI_BASE..disk::MAX_DRIVES) {
             if STATE.lock().is_already_mounted(i, 0) {
                 serial_println!("[miku_extfs] drive {} lba 0 - already mounted, skip", i);
                 continue;
             }
             serial_println!("[miku_extfs] trying drive {} ...", i);
-            if try_mount(i, 0) { return; }
+            if try_mount(i, 0, 0).is_some() { return; }
         }
         print_error!("  no extfs found on any drive");
         return;
     }
 
-    let drive_idx = match drive_str.parse::<usize>() {
-        Ok(n) if n <= 3 => n,
-        _ => { print_error!("  usage: ext2mount [drive 0-3] [partition]"); return; }
+    let drive_idx = match disk::parse_index(drive_str) {
+        Some(n) => n,
+        None => { print_error!("  usage: ext2mount [drive 0-7] [partition]"); return; }
     };
 
-    let start_lba: u32 = if !part_str.is_empty() {
-        let part_num: usize = match part_str.parse::<usize>() {
+    let part_num: usize = if !part_str.is_empty() {
+        match part_str.parse::<usize>() {
             Ok(n) if n >= 1 => n,
             _ => { print_error!("  invalid partition number"); return; }
-        };
-        let mut drive = make_ata_drive(drive_idx);
-        match crate::gpt::gpt_read(&mut drive) {
-            Ok(tbl) => {
-                let entry = &tbl.entries[part_num - 1];
-                if !entry.is_used() {
-                    print_error!("  partition {} does not exist", part_num);
-                    return;
-                }
-                entry.start_lba as u32
-            }
-            Err(_) => { print_error!("  could not read GPT on drive {}", drive_idx); return; }
         }
     } else {
-        0u32
+        0
     };
 
-    if !try_mount(drive_idx, start_lba) {
+    let (start_lba, sectors) = match partition_range(drive_idx, part_num) {
+        Ok(r) => r,
+        Err(e) => { print_error!("  drive {} partition {}: {}", drive_idx, part_num, e); return; }
+    };
+
+    if try_mount(drive_idx, start_lba, sectors).is_none() {
         print_error!("  no extfs found on drive {} (start_lba={})", drive_idx, start_lba);
     }
 }
 
-fn try_mount(drive_index: usize, start_lba: u32) -> bool {
-    let mut state = STATE.lock();
+fn drive_device(drive_index: usize, start_lba: u32, sectors: u64) -> Box<dyn BlockDevice> {
+    let drive = disk::open(drive_index);
+    if start_lba == 0 {
+        drive
+    } else {
+        Box::new(Partition::new(drive, start_lba as u64, sectors))
+    }
+}
 
-    if state.is_already_mounted(drive_index, start_lba) {
+fn try_mount(drive_index: usize, start_lba: u32, sectors: u64) -> Option<usize> {
+    if STATE.lock().is_already_mounted(drive_index, start_lba) {
         serial_println!("[miku_extfs] drive {} lba {} already mounted", drive_index, start_lba);
-        return false;
+        return None;
     }
+    attach(drive_device(drive_index, start_lba, sectors), drive_index, start_lba, None)
+}
+
+fn attach(
+    dev:         Box<dyn BlockDevice>,
+    drive_index: usize,
+    start_lba:   u32,
+    image:       Option<&str>,
+) -> Option<usize> {
+    let slot = attach_slot(dev, drive_index, start_lba, image);
+    if slot.is_none() {
+        let mut state = STATE.lock();
+        for i in 0..MAX_MOUNTS {
+            if !state.ready[i] {
+                state.slots[i].reader = DiskReader::EMPTY;
+            }
+        }
+    }
+    slot
+}
+
+fn attach_slot(
+    dev:         Box<dyn BlockDevice>,
+    drive_index: usize,
+    start_lba:   u32,
+    image:       Option<&str>,
+) -> Option<usize> {
+    let src = match image {
+        Some(path) => String::from(path),
+        None       => alloc::format!("drive {} lba {}", drive_index, start_lba),
+    };
+    let mut state = STATE.lock();
 
     let slot = match state.find_free_slot() {
         Some(s) => s,
         None => {
             print_error!("  all {} mount slots used - run fs.umount first", MAX_MOUNTS);
-            return false;
+            return None;
         }
     };
 
-    let drive = make_ata_drive(drive_index);
     state.ready[slot] = false;
-    state.slots[slot].reader = DiskReader::new_partitioned(drive, start_lba);
-    state.slots[slot].block_cache = None;
-    state.slots[slot].journal_inode_cached = None;
-
-    let mut sector = [0u8; 512];
-
-    if state.slots[slot].reader.read_sector(2, &mut sector).is_err() {
-        serial_println!(
-            "[miku_extfs] drive {} lba {} - cannot read sector 2",
-            drive_index, start_lba
-        );
-        return false;
-    }
-    state.slots[slot].superblock.data[0..512].copy_from_slice(&sector);
-
-    let magic_lo = u16::from_le_bytes([sector[56], sector[57]]);
-    if magic_lo != EXT2_MAGIC {
-        serial_println!(
-            "[miku_extfs] drive {} lba {} - bad magic 0x{:04X}, skip",
-            drive_index, start_lba, magic_lo
-        );
-        return false;
-    }
-
-    if state.slots[slot].reader.read_sector(3, &mut sector).is_err() {
-        serial_println!(
-            "[miku_extfs] drive {} lba {} - cannot read sector 3",
-            drive_index, start_lba
-        );
-        return false;
-    }
-    state.slots[slot].superblock.data[512..1024].copy_from_slice(&sector);
-
-    serial_println!("[miku_extfs] slot {} drive {} lba {} - found!", slot, drive_index, start_lba);
-
-    let block_size       = state.slots[slot].superblock.block_size();
-    let inodes_per_group = state.slots[slot].superblock.inodes_per_group();
-    let blocks_per_group = state.slots[slot].superblock.blocks_per_group();
-    let blocks_count     = state.slots[slot].superblock.blocks_count();
-    let first_data_block = state.slots[slot].superblock.first_data_block();
-    let usable           = blocks_count.saturating_sub(first_data_block);
-    let group_count      = if blocks_per_group == 0 { 0 }
-        else { (usable + blocks_per_group - 1) / blocks_per_group };
-    let gd_size          = state.slots[slot].superblock.group_desc_size() as usize;
-
-    if group_count as usize > 32 {
-        print_error!("  miku_extfs: too many block groups ({})", group_count);
-        return false;
-    }
-
-    state.slots[slot].block_size       = block_size;
-    state.slots[slot].inodes_per_group = inodes_per_group;
-    state.slots[slot].blocks_per_group = blocks_per_group;
-    state.slots[slot].group_count      = group_count;
-
-    let gdt_block      = if block_size == 1024 { 2 } else { 1 };
-    let spb            = block_size / 512;
-    let gdt_start_lba  = gdt_block * spb;
-    let total_gd_bytes = group_count as usize * gd_size;
-    let total_sectors  = ((total_gd_bytes + 511) / 512) as u32;
-
-    let mut carry     = [0u8; 64];
-    let mut carry_len = 0usize;
-    let mut gd_idx    = 0usize;
-
-    for s in 0..total_sectors {
-        if state.slots[slot].reader.read_sector(gdt_start_lba + s, &mut sector).is_err() {
-            serial_println!("[miku_extfs] gdt read error at lba {}", gdt_start_lba + s);
-            return false;
-        }
-        let mut pos = 0usize;
-        if carry_len > 0 {
-            let need = gd_size - carry_len;
-            carry[carry_len..gd_size].copy_from_slice(&sector[..need]);
-            if gd_idx < group_count as usize {
-                state.slots[slot].groups[gd_idx].data[..gd_size]
-                    .copy_from_slice(&carry[..gd_size]);
-                gd_idx += 1;
-            }
-            pos = need;
-            carry_len = 0;
-        }
-        while pos + gd_size <= 512 && gd_idx < group_count as usize {
-            state.slots[slot].groups[gd_idx].data[..gd_size]
-                .copy_from_slice(&sector[pos..pos + gd_size]);
-            gd_idx += 1;
-            pos    += gd_size;
-        }
-        if pos < 512 && gd_idx < group_count as usize {
-            let remaining = 512 - pos;
-            carry[..remaining].copy_from_slice(&sector[pos..]);
-            carry_len = remaining;
+    if let Err(e) = state.slots[slot].mount(dev) {
+        match e {
+            FsError::TooManyGroups => print_error!("  miku_extfs: too many block groups"),
+            _ => serial_println!("[miku_extfs] {} - {:?}, skip", src, e),
         }
+        return None;
     }
+    serial_println!("[miku_extfs] slot {} {} - found!", slot, src);
 
     state.ready[slot]     = true;
     state.drive_idx[slot] = drive_index;
     state.start_lba[slot] = start_lba;
+    state.image[slot]     = image.map(String::from);
     state.active_slot     = slot;
 
-    state.slots[slot].init_cache();
-    let _ = state.slots[slot].init_journal();
-    let _ = state.slots[slot].warm_cache();
-
-    if state.slots[slot].journal_active
-        && !state.slots[slot]
-            .read_journal_superblock()
-            .map(|j| j.is_clean())
-            .unwrap_or(true)
-    {
-        match state.slots[slot].ext3_recover() {
-            Ok(0) => {}
-            Ok(n) => serial_println!("[ext3] slot {} recovery: replayed {} blocks", slot, n),
-            Err(e) => serial_println!("[ext3] slot {} recovery failed: {:?}", slot, e),
-        }
-    }
-
+    let block_size   = state.slots[slot].block_size;
+    let blocks_count = state.slots[slot].superblock.blocks_count();
+    let group_count  = state.slots[slot].group_count;
     let total_inodes = state.slots[slot].superblock.inodes_count();
     let free_blocks  = state.slots[slot].superblock.free_blocks_count();
     let free_inodes  = state.slots[slot].superblock.free_inodes_count();
     let version      = state.slots[slot].superblock.fs_version_str();
 
-    print_success!("  {} mounted -> slot {} (drive {} lba={})", version, slot, drive_index, start_lba);
+    print_success!("  {} mounted -> slot {} ({})", version, slot, src);
     println!("  Block:   {} bytes", block_size);
     println!("  Blocks:  {} total, {} free", blocks_count, free_blocks);
     println!("  Inodes:  {} total, {} free", total_inodes, free_inodes);
     println!("  Groups:  {}", group_count);
     println!("  Cache:   enabled");
     println!("  Use 'fs.select <0|1>' to switch slots");
-    true
+    Some(slot)
 }
 
 pub fn cmd_ext2_ls(path: &str) {
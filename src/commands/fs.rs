@@ -23,6 +23,7 @@ pub fn cmd_ls(path: &str) {
     let mut pure_vfs_count: usize          = 0;
 
     let mut vfs_has_ext_backing = false;
+    let mut grafted             = false;
 
     with_vfs(|vfs| {
         let did = match vfs.resolve_path(cwd, path) {
@@ -30,6 +31,10 @@ pub fn cmd_ls(path: &str) {
             Err(_) => { err = true; return; }
         };
         if !vfs.nodes[did].is_dir() { notdir = true; return; }
+        if vfs.nodes[did].in_ext_mount() {
+            grafted = true;
+            if vfs.ext2_ensure_children_loaded(did).is_err() { err = true; return; }
+        }
 
         let node_ext = vfs.nodes[did].ext2_ino;
         vfs_has_ext_backing = vfs.nodes[did].fs_type == FsType::Ext2 && node_ext != 0;
@@ -80,7 +85,7 @@ pub fn cmd_ls(path: &str) {
 
     let ext_ready = crate::commands::ext2_cmds::is_ext2_ready();
 
-    let disk_ino: Option<u32> = if ext_ready {
+    let disk_ino: Option<u32> = if ext_ready && !grafted {
         let abs_str = unsafe { core::str::from_utf8_unchecked(&abs_path_buf.0[..abs_path_buf.1]) };
         let lookup = if abs_path_buf.1 == 0 || abs_path_buf.1 == 1 && abs_path_buf.0[0] == b'/' {
             "/"
@@ -269,9 +274,9 @@ pub fn cmd_mkdir(name: &str) {
 
 pub fn cmd_touch(name: &str) {
     let cwd = SESSION.lock().cwd;
-    let parent_fs = with_vfs(|v| v.nodes[cwd].fs_type);
+    let (parent_fs, grafted) = with_vfs(|v| (v.nodes[cwd].fs_type, v.nodes[cwd].in_ext_mount()));
 
-    if parent_fs == FsType::Ext2 && crate::commands::ext2_cmds::is_ext2_ready() {
+    if parent_fs == FsType::Ext2 && !grafted && crate::commands::ext2_cmds::is_ext2_ready() {
         cmd_exttouch(name);
         return;
     }
@@ -298,9 +303,9 @@ pub fn cmd_touch(name: &str) {
 pub fn cmd_cat(name: &str) {
     let cwd = SESSION.lock().cwd;
     let is_dev = name.starts_with("/dev/") || name.starts_with("dev/");
-    let ext2_ino: Option<u32> = with_vfs(|v| {
+    let ext2_ino: Option<(u32, u8)> = with_vfs(|v| {
         match v.resolve_path(cwd, name) {
-            Ok(id) if v.nodes[id].is_ext2_backed() => Some(v.nodes[id].ext2_ino),
+            Ok(id) if v.nodes[id].is_ext2_backed() => Some((v.nodes[id].ext2_ino, v.nodes[id].ext_slot)),
             _ => None,
         }
     });
@@ -309,14 +314,14 @@ pub fn cmd_cat(name: &str) {
         let abs_str = unsafe { core::str::from_utf8_unchecked(&abs.0[..abs.1]) };
         crate::commands::ext2_cmds::with_ext2_pub(|fs| {
             fs.resolve_path(abs_str).ok()
-        }).and_then(|x| x)
+        }).and_then(|x| x).map(|ino| (ino, crate::commands::ext2_cmds::active_slot()))
     } else {
         ext2_ino
     };
 
-    if let Some(ino) = ext2_ino {
-        use crate::commands::ext2_cmds::with_ext2_pub;
-        let result = with_ext2_pub(|fs| -> Result<(), crate::miku_extfs::FsError> {
+    if let Some((ino, slot)) = ext2_ino {
+        use crate::commands::ext2_cmds::with_ext_slot;
+        let result = with_ext_slot(slot, |fs| -> Result<(), crate::miku_extfs::FsError> {
             let inode = fs.read_inode(ino)?;
             let size = inode.size() as usize;
             if size == 0 { println!(""); return Ok(()); }
@@ -409,10 +414,10 @@ pub fn cmd_stat(path: &str) {
 
     let vfs_info = with_vfs(|v| {
         let id = v.resolve_path(cwd, path)?;
-        Ok((v.nodes[id].fs_type, v.nodes[id].ext2_ino, v.nodes[id].stat()))
+        Ok((v.nodes[id].fs_type, v.nodes[id].ext2_ino, v.nodes[id].ext_slot, v.nodes[id].stat()))
     });
 
-    let (fs_type, ext2_ino, st) = match vfs_info {
+    let (fs_type, ext2_ino, slot, st) = match vfs_info {
         Ok(t) => t,
         Err(VfsError::NotFound) if crate::commands::ext2_cmds::is_ext2_ready() => {
             let abs = make_abs_path(path);
@@ -421,15 +426,16 @@ pub fn cmd_stat(path: &str) {
                 fs.resolve_path(abs_str).ok()
             }).flatten();
             match ino {
-                Some(ino) => (FsType::Ext2, ino, unsafe { core::mem::zeroed() }),
+                Some(ino) => (FsType::Ext2, ino, crate::vfs::types::INVALID_U8, unsafe { core::mem::zeroed() }),
                 None => { print_error!("stat: not found"); return; }
             }
         }
         Err(e) => { print_error!("stat: {:?}", e); return; }
     };
 
-    let fs_name: &str = if fs_type == FsType::Ext2 && crate::commands::ext2_cmds::is_ext2_ready() {
-        ext_version()
+    let ext_live = fs_type.is_ext_family() && crate::commands::ext2_cmds::slot_ready(slot);
+    let fs_name: &str = if ext_live {
+        crate::commands::ext2_cmds::slot_fs_type(slot).as_str()
     } else {
         match fs_type {
             FsType::TmpFS  => "tmpfs",
@@ -439,8 +445,8 @@ pub fn cmd_stat(path: &str) {
         }
     };
 
-    if fs_type == FsType::Ext2 && ext2_ino != 0 && crate::commands::ext2_cmds::is_ext2_ready() {
-        let disk = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+    if ext_live && ext2_ino != 0 {
+        let disk = crate::commands::ext2_cmds::with_ext_slot(slot, |fs| {
             fs.read_inode(ext2_ino).ok()
         }).flatten();
 
@@ -478,17 +484,17 @@ pub fn cmd_rm(path: &str) {
         Ok(id) => {
             let ft = v.nodes[id].fs_type;
             let ext_ino = v.nodes[id].ext2_ino;
-            Ok((id, ft, ext_ino))
+            Ok((ft, ext_ino, v.nodes[id].in_ext_mount()))
         }
         Err(e) => Err(e),
     });
 
     match node_info {
-        Ok((_, FsType::ProcFS, _)) | Ok((_, FsType::DevFS, _)) => {
+        Ok((FsType::ProcFS, _, _)) | Ok((FsType::DevFS, _, _)) => {
             print_error!("rm: permission denied (read-only fs)");
             return;
         }
-        Ok((_, FsType::Ext2, ext_ino)) => {
+        Ok((FsType::Ext2, ext_ino, false)) => {
             if ext_ino != 0 {
                 ext_rm(path);
             }
@@ -527,7 +533,7 @@ pub fn cmd_rm_rf(path: &str) {
     let cwd = SESSION.lock().cwd;
 
     let info = with_vfs(|v| match v.resolve_path(cwd, path) {
-        Ok(id) => Ok((id, v.nodes[id].kind, v.nodes[id].fs_type)),
+        Ok(id) => Ok((v.nodes[id].in_ext_mount(), v.nodes[id].kind, v.nodes[id].fs_type)),
         Err(e) => Err(e),
     });
 
@@ -535,14 +541,14 @@ pub fn cmd_rm_rf(path: &str) {
         Ok((_, _, FsType::ProcFS)) | Ok((_, _, FsType::DevFS)) => {
             print_error!("rm -rf: permission denied (read-only fs)");
         }
-        Ok((_, VNodeKind::Directory, FsType::Ext2)) => {
+        Ok((false, VNodeKind::Directory, FsType::Ext2)) => {
             crate::commands::ext2_cmds::cmd_ext2_rm_rf(path);
             let _ = with_vfs(|v| v.rmdir(cwd, path));
         }
         Ok((_, VNodeKind::Directory, _)) => {
             recursive_rm(cwd, path);
         }
-        Ok((_, _, FsType::Ext2)) => {
+        Ok((false, _, FsType::Ext2)) => {
             ext_rm(path);
             let _ = with_vfs(|v| v.unlink(cwd, path));
         }
@@ -571,6 +577,9 @@ fn recursive_rm(cwd: usize, path: &str) {
             Ok(v) => v,
             Err(_) => return,
         };
+        if vfs.nodes[did].is_ext_backed() && vfs.ext2_ensure_children_loaded(did).is_err() {
+            return;
+        }
         let eff = vfs.xm(did);
         for (_, child_id) in vfs.nodes[eff].children.iter() {
             if child_count >= 16 {
@@ -633,7 +642,7 @@ pub fn cmd_rmdir(path: &str) {
 
     let ext_info = with_vfs(|v| {
         let id = v.resolve_path(cwd, path).ok()?;
-        if v.nodes[id].fs_type == crate::vfs::FsType::Ext2 && v.nodes[id].ext2_ino != 0 {
+        if v.nodes[id].fs_type == crate::vfs::FsType::Ext2 && v.nodes[id].ext2_ino != 0 && !v.nodes[id].in_ext_mount() {
             Some(v.nodes[id].ext2_ino)
         } else {
             None
@@ -659,12 +668,12 @@ pub fn cmd_mv(old: &str, new: &str) {
     let cwd = SESSION.lock().cwd;
 
     let node_info = with_vfs(|v| match v.resolve_path(cwd, old) {
-        Ok(id) => Ok((v.nodes[id].fs_type, v.nodes[id].ext2_ino)),
+        Ok(id) => Ok((v.nodes[id].fs_type, v.nodes[id].in_ext_mount())),
         Err(e) => Err(e),
     });
 
     match node_info {
-        Ok((FsType::Ext2, _)) => {
+        Ok((FsType::Ext2, false)) => {
             let abs_old = make_abs_path(old);
             let abs_old_str = unsafe { core::str::from_utf8_unchecked(&abs_old.0[..abs_old.1]) };
             let new_name = new.rsplit('/').next().unwrap_or(new);
@@ -773,105 +782,85 @@ fn parse_octal(s: &str) -> Option<u16> {
 
 pub fn cmd_mount_list() {
     cprintln!(128, 222, 217, "  Filesystem    Mountpoint    Type");
-    cprintln!(230, 240, 240, "  tmpfs         /             tmpfs");
-    cprintln!(230, 240, 240, "  devfs         /dev          devfs");
-    cprintln!(230, 240, 240, "  procfs        /proc         procfs");
 
-    let is_mounted = with_vfs_ro(|vfs| vfs.ext2_mount_active);
+    let mut rows: alloc::vec::Vec<(FsType, u8, alloc::string::String)> = alloc::vec::Vec::new();
+    with_vfs_ro(|vfs| {
+        for m in vfs.mounts.iter() {
+            rows.push((m.fs_type, m.ext_slot, vfs.node_path(m.root_vnode as usize)));
+        }
+    });
 
-    if is_mounted {
-        let fs_name = crate::commands::ext2_cmds::active_fs_type().as_str();
-        cprintln!(230, 240, 240, "  {:<14}/mnt          {}", fs_name, fs_name);
+    for (fs_type, slot, path) in rows.iter() {
+        let source = crate::commands::ext2_cmds::slot_label(*slot)
+            .unwrap_or_else(|| alloc::string::String::from(fs_type.as_str()));
+        cprintln!(230, 240, 240, "  {:<14}{:<14}{}", source, path, fs_type.as_str());
     }
 }
 
-pub fn cmd_mount(fstype: &str, target: &str) {
-    match fstype {
-        "ext2" | "ext3" | "ext4" => {
-            let mountpoint = if target.is_empty() { "/mnt" } else { target };
-            mount_ext2_to_vfs(mountpoint);
+pub fn cmd_mount(source: &str, target: &str) {
+    use crate::commands::ext2_cmds;
+
+    let slot = if source.starts_with("/dev/") {
+        if target.is_empty() {
+            println!("Usage: mount /dev/hdX[N] <dir>");
+            return;
+        }
+        let (drive, part) = match ext2_cmds::parse_device(source) {
+            Some(d) => d,
+            None => { print_error!("mount: unknown device '{}'", source); return; }
+        };
+        match ext2_cmds::mount_device(drive, part) {
+            Ok(slot) => slot,
+            Err(e) => { print_error!("mount: {}: {}", source, e); return; }
+        }
+    } else if !matches!(source, "ext2" | "ext3" | "ext4") {
+        if target.is_empty() {
+            println!("  Usage: mount /dev/hdX[N] <dir>  |  mount <image> <dir>  |  mount ext2|ext3|ext4 [dir]");
+            return;
         }
-        _ => {
-            print_error!("mount: unknown filesystem '{}'", fstype);
-            println!("  Supported: ext2, ext3, ext4");
+        match ext2_cmds::mount_image(source) {
+            Ok(slot) => slot,
+            Err(e) => { print_error!("mount: {}: {}", source, e); return; }
         }
+    } else {
+        if !ext2_cmds::is_ext2_ready() {
+            print_error!("  ext not mounted. Run ext2mount first");
+            return;
+        }
+        ext2_cmds::active_slot()
+    };
+
+    let mountpoint = if target.is_empty() { "/mnt" } else { target };
+    let cwd = SESSION.lock().cwd;
+    match with_vfs(|vfs| vfs.mount_ext(cwd, mountpoint, slot)) {
+        Ok(_) => print_success!("  {} mounted at {} (slot {})", ext2_cmds::slot_fs_type(slot).as_str(), mountpoint, slot),
+        Err(e) => print_error!("mount: {}", e.as_str()),
     }
 }
 
 pub fn cmd_umount(path: &str) {
-    crate::commands::ext2_cmds::with_ext2_pub(|fs| {
-        let _ = fs.flush_all_dirty_metadata();
-    });
-
     let cwd = SESSION.lock().cwd;
-    let result = with_vfs(|vfs| {
-        let id = vfs.resolve_path(cwd, path)?;
-
-        if !vfs.nodes[id].fs_type.is_ext_family() {
-            return Err(VfsError::InvalidArgument);
-        }
-
-        vfs.evict_ext2_children(id);
-        vfs.nodes[id].fs_type = FsType::TmpFS;
-        vfs.nodes[id].ext2_ino = 0;
-        vfs.nodes[id].children_loaded = false;
-        vfs.ext2_mount_active = false;
-
-        Ok(())
+    let inside = with_vfs(|vfs| {
+        let id = vfs.resolve_path(cwd, path).ok()?;
+        let slot = vfs.nodes[id].ext_slot;
+        Some(slot != vfs::types::INVALID_U8 && vfs.nodes[cwd].ext_slot == slot)
     });
-
-    crate::commands::ext2_cmds::force_unmount();
-
-    match result {
-        Ok(()) => print_success!("  unmounted {}", path),
-        Err(e) => print_error!("umount: {:?}", e),
-    }
-}
-
-fn mount_ext2_to_vfs(mountpoint: &str) {
-    use crate::commands::ext2_cmds;
-    use crate::miku_extfs::structs::EXT2_ROOT_INO;
-
-    if !ext2_cmds::is_ext2_ready() {
-        print_error!("  ext2 not mounted. Run ext2mount first");
+    if inside == Some(true) {
+        print_error!("umount: {}: resource busy (cd out first)", path);
         return;
     }
 
-    serial_println!("[mount] mounting ext2 at {} (lazy)", mountpoint);
-
-    let cwd = SESSION.lock().cwd;
-
-    let result = with_vfs(|vfs| {
-        let mount_id = match vfs.resolve_path(cwd, mountpoint) {
-            Ok(id) => {
-                if !vfs.nodes[id].is_dir() {
-                    return Err(VfsError::NotDirectory);
-                }
-                id
-            }
-            Err(VfsError::NotFound) => {
-                let (parent_path, dirname) = vfs::path::PathWalker::split_last(mountpoint);
-                let parent_id = vfs.resolve_path(cwd, parent_path)?;
-                vfs.mkdir(parent_id, dirname, FileMode::default_dir())?
-            }
-            Err(e) => return Err(e),
-        };
-
-        vfs.nodes[mount_id].fs_type = crate::commands::ext2_cmds::active_fs_type();
-        vfs.nodes[mount_id].ext2_ino = EXT2_ROOT_INO;
-        vfs.nodes[mount_id].children_loaded = false;
-        vfs.ext2_mount_active = true;
-
-        Ok(mount_id)
-    });
+    let slot = match with_vfs(|vfs| vfs.umount_ext(cwd, path)) {
+        Ok(slot) => slot,
+        Err(e) => { print_error!("umount: {}", e.as_str()); return; }
+    };
 
-    match result {
-        Ok(_id) => {
-            print_success!("  ext2 mounted at {} (on-demand)", mountpoint);
-        }
-        Err(e) => print_error!("mount: {:?}", e),
+    match crate::commands::ext2_cmds::release_slot(slot) {
+        Ok(()) => print_success!("  unmounted {}", path),
+        Err(e) => print_error!("umount: sync failed: {:?}", e),
     }
 }
+
 pub fn ext_version() -> &'static str {
     crate::commands::ext2_cmds::ext_fs_version_tag()
 }
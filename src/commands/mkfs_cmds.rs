@@ -1,33 +1,14 @@
-use crate::ata::AtaDrive;
+use crate::disk;
 use crate::mkfs::{mkfs, FsType, MkfsError, MkfsParams};
 use crate::mkfs::layout::FsLayout;
+use crate::vfs::block_dev::{self, BlockDevice};
 use crate::{cprintln, print_error, print_success, print_warn, println};
 
-fn drive_from_index(i: usize) -> Option<AtaDrive> {
-    Some(match i {
-        0 => AtaDrive::primary(),
-        1 => AtaDrive::primary_slave(),
-        2 => AtaDrive::secondary(),
-        3 => AtaDrive::secondary_slave(),
-        _ => return None,
-    })
-}
-
-fn parse_drive(s: &str) -> Option<usize> {
-    match s {
-        "0" => Some(0),
-        "1" => Some(1),
-        "2" => Some(2),
-        "3" => Some(3),
-        _ => None,
-    }
-}
-
 pub fn cmd_mkfs_dry(drive_str: &str, type_str: &str) {
-    let drive_idx = match parse_drive(drive_str) {
+    let drive_idx = match disk::parse_index(drive_str) {
         Some(i) => i,
         None => {
-            print_error!("  usage: mkfs.dry <drive 0-3> <ext2|ext3|ext4>");
+            print_error!("  usage: mkfs.dry <drive 0-7> <ext2|ext3|ext4>");
             return;
         }
     };
@@ -43,10 +24,7 @@ pub fn cmd_mkfs_dry(drive_str: &str, type_str: &str) {
 
     let params = MkfsParams::new(fs_type, drive_idx);
 
-    let mut drive = match drive_from_index(drive_idx) {
-        Some(d) => d,
-        None => { print_error!("  invalid drive index"); return; }
-    };
+    let mut drive = disk::open(drive_idx);
 
     let total_sectors = probe_sectors(&mut drive);
     if total_sectors < 2048 {
@@ -95,24 +73,22 @@ fn do_mkfs(args: &str, fs_type: FsType) {
     let drive_str       = parts.next().unwrap_or("");
     let second_str      = parts.next().unwrap_or("0");
 
-    let drive_idx = match parse_drive(drive_str) {
+    let drive_idx = match disk::parse_index(drive_str) {
         Some(i) => i,
         None => {
-            print_error!("  usage: mkfs.{} <drive 0-3> [partition|sectors]", fs_type.name());
+            print_error!("  usage: mkfs.{} <drive 0-7> [partition|sectors]", fs_type.name());
             println!("    drive 0 = primary master");
             println!("    drive 1 = primary slave");
             println!("    drive 2 = secondary master");
             println!("    drive 3 = secondary slave");
+            println!("    drive 4-7 = AHCI/SATA disks");
             println!("    example (whole disk):  mkfs.ext4 1");
             println!("    example (partition 2): mkfs.ext4 1 2");
             return;
         }
     };
 
-    let drive = match drive_from_index(drive_idx) {
-        Some(d) => d,
-        None => { print_error!("  invalid drive index"); return; }
-    };
+    let mut drive = disk::open(drive_idx);
 
     let mut params = MkfsParams::new(fs_type, drive_idx);
 
@@ -120,10 +96,7 @@ fn do_mkfs(args: &str, fs_type: FsType) {
 
     if second_val >= 1 && second_val <= 128 {
         let part_num    = second_val as usize;
-        let mut probe   = match drive_from_index(drive_idx) {
-            Some(d) => d,
-            None    => { print_error!("  invalid drive"); return; }
-        };
+        let mut probe   = disk::open(drive_idx);
         match crate::gpt::gpt_read(&mut probe) {
             Ok(tbl) => {
                 let entry = &tbl.entries[part_num - 1];
@@ -163,7 +136,7 @@ fn do_mkfs(args: &str, fs_type: FsType) {
     println!();
     cprintln!(57, 197, 187, "  mkfs.{} on drive {}...", fs_type.name(), drive_idx);
 
-    match mkfs(drive, &params) {
+    match mkfs(&mut drive, &params) {
         Ok(report) => {
             println!();
             print_success!("  {} filesystem created successfully", report.fs_type);
@@ -202,21 +175,6 @@ fn do_mkfs(args: &str, fs_type: FsType) {
     }
 }
 
-fn probe_sectors(drive: &mut AtaDrive) -> u32 {
-    let mut buf = [0u8; 512];
-    let mut lo: u32 = 2048;
-    let mut hi: u32 = u32::MAX / 2;
-
-    while hi > lo && drive.read_sector(hi - 1, &mut buf).is_err() {
-        hi /= 2;
-    }
-    while lo + 1 < hi {
-        let mid = lo + (hi - lo) / 2;
-        if drive.read_sector(mid, &mut buf).is_ok() {
-            lo = mid;
-        } else {
-            hi = mid;
-        }
-    }
-    lo + 1
+fn probe_sectors(drive: &mut dyn BlockDevice) -> u32 {
+    block_dev::probe_sectors(drive).min(u32::MAX as u64) as u32
 }
@@ -368,19 +368,19 @@ pub fn execute(input: &str) {
         "ext4fsck"   => ext4_cmds::cmd_ext4_fsck(),
 
         "mkfs.ext2" => {
-            if a1.is_empty() { println!("Usage: mkfs.ext2 <drive 0-3>"); }
+            if a1.is_empty() { println!("Usage: mkfs.ext2 <drive 0-7>"); }
             else { mkfs_cmds::cmd_mkfs_ext2(rest); }
         }
         "mkfs.ext3" => {
-            if a1.is_empty() { println!("Usage: mkfs.ext3 <drive 0-3>"); }
+            if a1.is_empty() { println!("Usage: mkfs.ext3 <drive 0-7>"); }
             else { mkfs_cmds::cmd_mkfs_ext3(rest); }
         }
         "mkfs.ext4" => {
-            if a1.is_empty() { println!("Usage: mkfs.ext4 <drive 0-3>"); }
+            if a1.is_empty() { println!("Usage: mkfs.ext4 <drive 0-7>"); }
             else { mkfs_cmds::cmd_mkfs_ext4(rest); }
         }
         "mkfs.dry"  => {
-            if a1.is_empty() || a2.is_empty() { println!("Usage: mkfs.dry <drive 0-3> <ext2|ext3|ext4>"); }
+            if a1.is_empty() || a2.is_empty() { println!("Usage: mkfs.dry <drive 0-7> <ext2|ext3|ext4>"); }
             else { mkfs_cmds::cmd_mkfs_dry(a1, a2); }
         }
 
@@ -434,17 +434,33 @@ pub fn execute(input: &str) {
         "nice"     => system::cmd_nice(a1, a2),
         "affinity" => system::cmd_affinity(a1, a2),
         "kill"     => {
-            if a1.is_empty() { println!("Usage: kill <pid>"); }
-            else if let Ok(pid) = a1.parse::<u64>() {
-                crate::scheduler::kill(pid);
-                crate::cprintln!(100, 220, 150, "  killed pid={}", pid);
+            let (sig, target) = if let Some(name) = a1.strip_prefix('-') {
+                (crate::signal::from_name(name), a2)
             } else {
-                crate::print_error!("  invalid pid");
+                (Some(crate::signal::SIGTERM), a1)
+            };
+            if target.is_empty() { println!("Usage: kill [-SIG] <pid>"); }
+            else if let (Some(sig), Ok(pid)) = (sig, target.parse::<u64>()) {
+                let kthread = crate::scheduler::with_process(pid, |p| p.cr3 == crate::vmm::kernel_cr3());
+                match kthread {
+                    None => crate::print_error!("  no such pid"),
+                    Some(true) => {
+                        crate::scheduler::kill(pid);
+                        crate::cprintln!(100, 220, 150, "  killed pid={}", pid);
+                    }
+                    Some(false) => {
+                        crate::signal::send(pid, sig);
+                        crate::cprintln!(100, 220, 150, "  sent signal {} to pid={}", sig, pid);
+                    }
+                }
+            } else {
+                crate::print_error!("  invalid signal or pid");
             }
         }
 
         "net"  => { crate::net::poll(); crate::net::cmd_net(rest); }
-        "dhcp" => crate::net::cmd_dhcp(),
+        "dhcp" => crate::net::cmd_dhcp(a1),
+        "ip"   => crate::net::cmd_ip(rest),
         "ping" => {
             if a1.is_empty() { println!("Usage: ping <ip|host> [count]"); }
             else {
@@ -453,8 +469,7 @@ pub fn execute(input: &str) {
                     Some(ip) => crate::net::cmd_ping(a1, &ip, count),
                     None => {
                         crate::cprintln!(57, 197, 187, "ping: resolving {}...", a1);
-                        let dns = crate::net::get_dns();
-                        match crate::net::dns::resolve(a1, &dns) {
+                        match crate::net::dns::resolve(a1) {
                             Some(ip) => crate::net::cmd_ping(a1, &ip, count),
                             None => crate::print_error!("ping: cannot resolve '{}'", a1),
                         }
@@ -462,33 +477,64 @@ pub fn execute(input: &str) {
                 }
             }
         }
+        "ping6" => {
+            if a1.is_empty() { println!("Usage: ping6 <ip6|host> [count]"); }
+            else {
+                let count = a2.parse::<usize>().unwrap_or(usize::MAX);
+                match crate::net::ip::IpAddr::parse(a1) {
+                    Some(crate::net::ip::IpAddr::V6(ip)) => crate::net::cmd_ping6(a1, &ip, count),
+                    Some(_) => crate::print_error!("ping6: '{}' is not an ipv6 address", a1),
+                    None => {
+                        crate::cprintln!(57, 197, 187, "ping6: resolving {}...", a1);
+                        match crate::net::dns::resolve6(a1) {
+                            Some(ip) => crate::net::cmd_ping6(a1, &ip, count),
+                            None => crate::print_error!("ping6: cannot resolve '{}'", a1),
+                        }
+                    }
+                }
+            }
+        }
         "fetch" => {
             if a1.is_empty() { println!("Usage: fetch <url|host> [port]"); }
             else { cmd_fetch(a1, a2); }
         }
         "wget"  => {
-            if a1.is_empty() { println!("Usage: wget <url> [-O <file>]"); }
-            else {
+            if a1.is_empty() {
+                println!("Usage: wget <url>... [-O <file>|-] [-c] [-q] [-S] [--header <h>] [--method <m>]");
+                println!("       [--post-data <s>] [--post-file <f>] [--user <u>] [--password <p>] [-k]");
+                crate::shell::set_status(2);
+            } else {
                 x86_64::instructions::interrupts::enable();
-                crate::net::http::cmd_wget(rest);
+                crate::shell::set_status(crate::net::http::cmd_wget(rest));
             }
         }
         "curl"  => {
-            if a1.is_empty() { println!("Usage: curl <url> [-X GET|POST] [-d <data>] [-o <file>] [-I]"); }
-            else {
+            if a1.is_empty() {
+                println!("Usage: curl <url>... [-o <file>|-O] [-C -] [-H <h>|@file] [-X <m>] [-d <s>|@file]");
+                println!("       [--data-binary @file] [-u user:pass] [-I] [-i] [-f] [-s] [-L] [-k]");
+                crate::shell::set_status(2);
+            } else {
                 x86_64::instructions::interrupts::enable();
-                crate::net::http::cmd_curl(rest);
+                crate::shell::set_status(crate::net::http::cmd_curl(rest));
             }
         }
+        "httpd" => {
+            x86_64::instructions::interrupts::enable();
+            crate::net::httpd::cmd_httpd(rest);
+        }
+        "dig"   => {
+            x86_64::instructions::interrupts::enable();
+            crate::net::dns::cmd_dig(rest);
+        }
         "ntp"   => {
             x86_64::instructions::interrupts::enable();
             crate::net::ntp::cmd_ntp(a1);
         }
         "traceroute" | "tr" => {
-            if a1.is_empty() { println!("Usage: traceroute <host|ip>"); }
+            if a1.is_empty() { println!("Usage: traceroute [-n] <host|ip>"); }
             else {
                 x86_64::instructions::interrupts::enable();
-                crate::net::traceroute::cmd_traceroute(a1);
+                crate::net::traceroute::cmd_traceroute(rest);
             }
         }
 
@@ -515,20 +561,16 @@ fn cmd_fetch(host_or_url: &str, port_str: &str) {
         let p: u16 = port_str.parse().unwrap_or(80);
         (host_or_url, p, p == 443)
     };
-    let dns = crate::net::get_dns();
-    let ip  = match parse_ip(host) {
+    if crate::net::ip::IpAddr::parse(host).is_none() {
+        crate::cprintln!(57, 197, 187, "fetch: resolving {}...", host);
+    }
+    let ip = match crate::net::dns::lookup(host) {
         Some(ip) => ip,
-        None => {
-            crate::cprintln!(57, 197, 187, "fetch: resolving {}...", host);
-            match crate::net::dns::resolve(host, &dns) {
-                Some(ip) => ip,
-                None => { crate::print_error!("fetch: cannot resolve '{}'", host); return; }
-            }
-        }
+        None => { crate::print_error!("fetch: cannot resolve '{}'", host); return; }
     };
     crate::cprintln!(57, 197, 187,
-        "fetch: connecting to {}.{}.{}.{}:{} ({})...",
-        ip[0], ip[1], ip[2], ip[3], port,
+        "fetch: connecting to {} port {} ({})...",
+        ip, port,
         if use_tls { "TLS" } else { "plain" }
     );
     x86_64::instructions::interrupts::enable();
@@ -539,9 +581,9 @@ fn cmd_fetch(host_or_url: &str, port_str: &str) {
     let req_bytes = req_str.as_bytes();
     if use_tls {
         crate::cprintln!(120, 200, 200, "fetch: TLS handshake...");
-        let mut stream = match crate::net::tls::TlsStream::connect(host, ip, port) {
-            Some(s) => s,
-            None    => { crate::print_error!("fetch: TLS failed"); return; }
+        let mut stream = match crate::net::tls::TlsStream::connect_verified(host, ip, port, true) {
+            Ok(s)  => s,
+            Err(e) => { crate::print_error!("fetch: TLS {}", e.as_str()); return; }
         };
         crate::print_success!("fetch: TLS ok ({})", stream.cipher_name());
         if !stream.send(req_bytes) { crate::print_error!("fetch: send failed"); stream.close(); return; }
@@ -3,7 +3,9 @@ use crate::vfs::with_vfs_ro;
 use crate::{allocator, console, cprint, cprintln, print, print_info, print_success, println};
 
 pub fn cmd_echo(text: &str) {
-    if !text.is_empty() {
+    if text.contains("$?") {
+        println!("{}", text.replace("$?", &alloc::format!("{}", crate::shell::last_status())));
+    } else if !text.is_empty() {
         println!("{}", text);
     }
 }
@@ -109,6 +111,7 @@ pub fn cmd_poweroff() {
             }
         });
     }
+    crate::net::dhcp::release();
     crate::serial_println!("[kern] poweroff");
     crate::power::shutdown();
 }
@@ -123,6 +126,7 @@ pub fn cmd_reboot() {
             }
         });
     }
+    crate::net::dhcp::release();
     crate::serial_println!("[kern] reboot");
     crate::power::reboot();
 }
@@ -174,8 +178,11 @@ pub fn cmd_help() {
     cprintln!(128, 222, 217, "  ext2cacheflush           flush block cache");
 
     cprintln!(57, 197, 187, "  Mount:");
-    cprintln!(128, 222, 217, "  mount ext2 <path>        mount ext2 at path");
-    cprintln!(128, 222, 217, "  umount <path>            unmount");
+    cprintln!(128, 222, 217, "  mount                    list mounts");
+    cprintln!(128, 222, 217, "  mount /dev/hdX[N] <path> mount ext2/3/4 volume at path");
+    cprintln!(128, 222, 217, "  mount <image> <path>     mount ext2/3/4 image file at path");
+    cprintln!(128, 222, 217, "  mount ext2 <path>        mount active slot at path");
+    cprintln!(128, 222, 217, "  umount <path>            sync and unmount");
 
     cprintln!(57, 197, 187, "  Ext3 Commands:");
     cprintln!(128, 222, 217, "  ext3mkjournal            create journal");
@@ -185,16 +192,25 @@ pub fn cmd_help() {
     cprintln!(128, 222, 217, "  ext3clean                mark journal clean");
 
     cprintln!(57, 197, 187, "  Network:");
-    cprintln!(128, 222, 217, "  dhcp                     get ip via dhcp");
+    cprintln!(128, 222, 217, "  dhcp [status|renew|release] get ip via dhcp / manage lease");
     cprintln!(128, 222, 217, "  ping <ip> [count]        ping host (ctrl+c to stop)");
+    cprintln!(128, 222, 217, "  ping6 <ip6|host> [count] ping host over ipv6");
     cprintln!(128, 222, 217, "  fetch <host> [port]      tcp connect + GET request");
     cprintln!(128, 222, 217, "  net status                adapter info");
     cprintln!(128, 222, 217, "  net pci                   list pci nics");
     cprintln!(128, 222, 217, "  net poll                  receive packets");
     cprintln!(128, 222, 217, "  net arp                   show arp table");
-    cprintln!(128, 222, 217, "  net dns [<ip>]            show/set dns server");
+    cprintln!(128, 222, 217, "  net ndp                   show ipv6 neighbor cache");
+    cprintln!(128, 222, 217, "  net dns [<ip>...]         show/set dns servers");
     cprintln!(128, 222, 217, "  net ip <ip> <gw> <mask>  set ip manually");
-    cprintln!(128, 222, 217, "  traceroute <host>        trace route to host");
+    cprintln!(128, 222, 217, "  ip addr [add|del a/n dev] show/assign interface addresses");
+    cprintln!(128, 222, 217, "  ip link [set <if> ...]   show interfaces, up|down|mtu <n>");
+    cprintln!(128, 222, 217, "  ip route [add|del|get]   show/edit routing table");
+    cprintln!(128, 222, 217, "  traceroute [-n] <host>   trace route to host");
+    cprintln!(128, 222, 217, "  dig [@srv] <name> [type] dns lookup (-x ip, cache, flush)");
+    cprintln!(128, 222, 217, "  httpd [port] [root]      serve files over http (--upload dir enables PUT)");
+    cprintln!(128, 222, 217, "  wget <url> [-O f]        download file (--no-check-certificate)");
+    cprintln!(128, 222, 217, "  curl <url> [-X|-d|-o|-I] http request (-k: skip tls verify)");
     cprintln!(128, 222, 217, "  net send <ip> <port> <m> send udp packet");
 
     cprintln!(57, 197, 187, "  System:");
@@ -340,14 +356,6 @@ pub fn cmd_swaptest() {
     let n = 256usize;
     cprintln!(57, 197, 187, "  swaptest: testing {} pages of swap I/O...", n);
 
-    let drive_idx = swap::swap_drive_idx();
-    let mut drive = match drive_idx {
-        0 => crate::ata::AtaDrive::primary(),
-        1 => crate::ata::AtaDrive::primary_slave(),
-        2 => crate::ata::AtaDrive::secondary(),
-        _ => crate::ata::AtaDrive::secondary_slave(),
-    };
-
     let mut frames: alloc::vec::Vec<(u64, u32, u8)> = alloc::vec::Vec::new();
 
     cprintln!(128, 222, 217, "  Phase 1: allocating and swapping out {} pages...", n);
@@ -360,7 +368,7 @@ pub fn cmd_swaptest() {
         let pattern = ((i & 0xFF) as u8) ^ 0xA5;
         unsafe { core::ptr::write_bytes((phys + hhdm) as *mut u8, pattern, 4096); }
 
-        match swap::swap_out_internal(phys, &mut drive) {
+        match swap::swap_out_internal(phys) {
             Ok(slot) => {
                 unsafe { core::ptr::write_bytes((phys + hhdm) as *mut u8, 0xDE, 4096); }
                 frames.push((phys, slot, pattern));
@@ -381,7 +389,7 @@ pub fn cmd_swaptest() {
     let mut fail = 0usize;
 
     for &(phys, slot, pattern) in frames.iter() {
-        match swap::swap_in_internal(slot, phys, &mut drive) {
+        match swap::swap_in_internal(slot, phys) {
             Ok(()) => {
                 let mut ok = true;
                 unsafe {
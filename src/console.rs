@@ -433,6 +433,10 @@ impl Console {
     fn write_char(&mut self, c: char) {
         match c {
             '\n' => self.new_line(),
+            '\r' => {
+                self.cur_col = 0;
+                self.x_pos   = BORDER_PADDING;
+            }
             '\x08' => self.backspace(),
             ch => {
                 if self.x_pos + CHAR_WIDTH >= self.width {
@@ -0,0 +1,47 @@
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::string::String;
+use crate::ahci::{self, AhciDisk};
+use crate::ata::AtaDrive;
+use crate::vfs::block_dev::BlockDevice;
+
+pub const ATA_DRIVES: usize = 4;
+pub const AHCI_BASE:  usize = ATA_DRIVES;
+pub const MAX_DRIVES: usize = ATA_DRIVES + ahci::MAX_DISKS;
+
+pub fn open(idx: usize) -> Box<dyn BlockDevice> {
+    if idx < ATA_DRIVES {
+        Box::new(AtaDrive::from_idx(idx))
+    } else {
+        Box::new(AhciDisk::new(idx - AHCI_BASE))
+    }
+}
+
+pub fn parse_index(s: &str) -> Option<usize> {
+    match s.parse::<usize>() {
+        Ok(n) if n < MAX_DRIVES => Some(n),
+        _ => None,
+    }
+}
+
+pub fn dev_name(idx: usize) -> String {
+    if idx < ATA_DRIVES {
+        alloc::format!("hd{}", (b'a' + idx as u8) as char)
+    } else {
+        alloc::format!("sd{}", (b'a' + (idx - AHCI_BASE) as u8) as char)
+    }
+}
+
+pub fn parse_name(name: &str) -> Option<(usize, &str)> {
+    let (base, rest) = if let Some(r) = name.strip_prefix("hd") {
+        (0, r)
+    } else {
+        (AHCI_BASE, name.strip_prefix("sd")?)
+    };
+    let limit = if base == 0 { ATA_DRIVES } else { ahci::MAX_DISKS };
+    let letter = *rest.as_bytes().first()?;
+    if !(b'a'..b'a' + limit as u8).contains(&letter) {
+        return None;
+    }
+    Some((base + (letter - b'a') as usize, &rest[1..]))
+}
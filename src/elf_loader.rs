@@ -15,6 +15,7 @@ const INTERP_BASE: u64 = 0x0000_7F00_0000_0000;
 const ASLR_BITS: u32 = 20;
 const ASLR_STEP: u64 = 0x1000;
 const MAX_ARGS: usize = 64;
+const MAX_ENVS: usize = 64;
 
 pub const USER_STACK_TOP: u64 = 0x0000_7FFF_FFFF_0000;
 pub const MAX_ELF_SIZE: usize = 64 * 1024 * 1024;
@@ -65,6 +66,7 @@ pub fn load(
     data: &[u8],
     aspace: &AddressSpace,
     args: &[&str],
+    envp: &[&str],
     read_file: Option<ReadFileFn<'_>>,
 ) -> Result<ElfImage, LoadError> {
     if data.len() > MAX_ELF_SIZE {
@@ -169,7 +171,7 @@ pub fn load(
     }
 
     let stack_top = setup_stack(
-        stack_phys, stack_size, args, &info,
+        stack_phys, stack_size, args, envp, &info,
         load_bias, interp_base, exe_entry, phdr_vaddr,
     );
 
@@ -514,6 +516,7 @@ fn setup_stack(
     stack_phys: u64,
     stack_size: u64,
     args: &[&str],
+    envp: &[&str],
     info: &ElfInfo,
     load_bias: u64,
     interp_base: u64,
@@ -569,8 +572,19 @@ fn setup_stack(
     }
     let execfn_va = argv_va[0];
 
+    let envc = envp.len().min(MAX_ENVS);
+    let mut envp_va = [0u64; MAX_ENVS];
+    for i in 0..envc {
+        envp_va[i] = push_cstr(&mut sp, envp[i]);
+    }
+
     sp &= !15;
 
+    let total_after = 1 + argc + 1 + envc + 1;
+    if total_after % 2 != 0 {
+        push_u64(&mut sp, 0);
+    }
+
     let push_auxv = |sp: &mut u64, key: u64, val: u64| {
         push_u64(sp, val);
         push_u64(sp, key);
@@ -595,13 +609,10 @@ fn setup_stack(
     push_auxv(&mut sp, AT_PHDR, phdr_vaddr);
 
     push_u64(&mut sp, 0);
-    push_u64(&mut sp, 0);
-
-    let total_after = 1 + argc + 2;
-    if total_after % 2 != 0 {
-        push_u64(&mut sp, 0);
+    for i in (0..envc).rev() {
+        push_u64(&mut sp, envp_va[i]);
     }
-
+    push_u64(&mut sp, 0);
     for i in (0..argc).rev() {
         push_u64(&mut sp, argv_va[i]);
     }
@@ -1,6 +1,6 @@
 extern crate alloc;
 use alloc::vec::Vec;
-use crate::elf_loader::{self, LoadError};
+use crate::elf_loader::{self, ElfImage, LoadError};
 use crate::vfs_read::{self, ReadError};
 use crate::vmm::AddressSpace;
 use crate::process::Process;
@@ -44,7 +44,7 @@ impl From<ReadError> for ExecError {
     }
 }
 
-pub fn exec(path: &str, args: &[&str]) -> Result<u64, ExecError> {
+fn load_image(path: &str, args: &[&str], envp: &[&str]) -> Result<(u64, ElfImage), ExecError> {
     let file_data = vfs_read::read_file_strict(path)?;
 
     let aspace = AddressSpace::new_user().ok_or(ExecError::NoAddressSpace)?;
@@ -56,7 +56,7 @@ pub fn exec(path: &str, args: &[&str]) -> Result<u64, ExecError> {
         vfs_read::read_file(interp_path)
     };
 
-    let image = elf_loader::load(&file_data, &aspace, args, Some(&read_file))
+    let image = elf_loader::load(&file_data, &aspace, args, envp, Some(&read_file))
         .map_err(ExecError::Load)?;
 
     crate::serial_println!(
@@ -69,6 +69,12 @@ pub fn exec(path: &str, args: &[&str]) -> Result<u64, ExecError> {
 
     crate::mmap::vma_set_brk(cr3, image.brk);
 
+    Ok((cr3, image))
+}
+
+pub fn exec(path: &str, args: &[&str]) -> Result<u64, ExecError> {
+    let (cr3, image) = load_image(path, args, &[])?;
+
     let mut proc = Process::new_elf(image.entry, image.stack_top, AddressSpace::from_raw(cr3))
         .ok_or_else(|| {
             AddressSpace::from_raw(cr3).free_address_space_manual();
@@ -90,3 +96,27 @@ pub fn exec(path: &str, args: &[&str]) -> Result<u64, ExecError> {
     crate::serial_println!("[exec] spawned pid={} from '{}' argc={}", pid, path, args.len());
     Ok(pid)
 }
+
+pub fn exec_replace(path: &str, args: &[&str], envp: &[&str]) -> Result<ElfImage, ExecError> {
+    let (cr3, image) = load_image(path, args, envp)?;
+
+    let pid     = crate::scheduler::current_pid();
+    let old_cr3 = crate::scheduler::replace_cr3(pid, cr3);
+
+    unsafe {
+        core::arch::asm!("mov cr3, {}", in(reg) cr3, options(nostack, preserves_flags));
+    }
+
+    if old_cr3 != 0 && old_cr3 != crate::vmm::kernel_cr3()
+        && crate::vmm::release_address_space(old_cr3)
+    {
+        crate::mmap::vma_cleanup(old_cr3);
+        AddressSpace::from_raw(old_cr3).free_address_space_manual();
+    }
+
+    crate::serial_println!(
+        "[exec] pid={} replaced image with '{}' argc={} envc={}",
+        pid, path, args.len(), envp.len(),
+    );
+    Ok(image)
+}
@@ -0,0 +1,646 @@
+extern crate alloc;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use crate::syscall::{
+    EACCES, EAGAIN, EBADF, EBUSY, EEXIST, EFBIG, EINTR, EINVAL, EIO, EISDIR, ELOOP, EMFILE,
+    ENAMETOOLONG, ENFILE, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, ENOTSOCK, EPIPE, EROFS, ESPIPE,
+    EXDEV,
+};
+use crate::net::socket::{Proto, Socket};
+use crate::vfs::types::{FileMode, OpenFlags, SeekFrom, VfsError};
+use crate::vfs_stat::{self, MikuStat, S_IFCHR, S_IFIFO, S_IFREG, S_IFSOCK};
+
+pub const MAX_FDS: u64 = 256;
+pub const PIPE_CAPACITY: usize = 65536;
+
+pub const O_RDONLY:   u64 = 0;
+pub const O_WRONLY:   u64 = 1;
+pub const O_RDWR:     u64 = 2;
+pub const O_ACCMODE:  u64 = 3;
+pub const O_CREAT:    u64 = 0x40;
+pub const O_EXCL:     u64 = 0x80;
+pub const O_TRUNC:    u64 = 0x200;
+pub const O_APPEND:   u64 = 0x400;
+pub const O_NONBLOCK: u64 = 0x800;
+pub const O_DIRECTORY: u64 = 0x10000;
+pub const O_CLOEXEC:  u64 = 0x80000;
+
+pub const F_DUPFD:         u64 = 0;
+pub const F_GETFD:         u64 = 1;
+pub const F_SETFD:         u64 = 2;
+pub const F_GETFL:         u64 = 3;
+pub const F_SETFL:         u64 = 4;
+pub const F_DUPFD_CLOEXEC: u64 = 1030;
+pub const FD_CLOEXEC:      u64 = 1;
+
+const PIPE_CAUSE: &str = "pipe";
+
+pub struct Pipe {
+    buf:     VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+    waiters: Vec<u64>,
+}
+
+impl Pipe {
+    fn new() -> Self {
+        Self { buf: VecDeque::new(), readers: 1, writers: 1, waiters: Vec::new() }
+    }
+
+    fn wake_all(&mut self) {
+        for pid in self.waiters.drain(..) {
+            crate::scheduler::wakeup(pid);
+        }
+    }
+
+    fn wait(&mut self, pid: u64) {
+        if !self.waiters.contains(&pid) {
+            self.waiters.push(pid);
+        }
+    }
+}
+
+pub enum Object {
+    Console,
+    Vfs(usize),
+    Memory(Vec<u8>),
+    PipeRead(Arc<Mutex<Pipe>>),
+    PipeWrite(Arc<Mutex<Pipe>>),
+    Socket(Arc<Socket>),
+}
+
+pub struct OpenFile {
+    pub object: Object,
+    offset:     AtomicU64,
+    status:     AtomicU64,
+}
+
+impl OpenFile {
+    pub fn new(object: Object, status: u64) -> Self {
+        Self { object, offset: AtomicU64::new(0), status: AtomicU64::new(status) }
+    }
+
+    pub fn is_console(&self) -> bool {
+        matches!(self.object, Object::Console)
+    }
+
+    pub fn status(&self) -> u64 {
+        self.status.load(Ordering::Relaxed)
+    }
+
+    pub fn set_status(&self, flags: u64) {
+        let keep = self.status() & !O_NONBLOCK;
+        self.status.store(keep | (flags & O_NONBLOCK), Ordering::Relaxed);
+    }
+
+    pub fn nonblock(&self) -> bool {
+        self.status() & O_NONBLOCK != 0
+    }
+
+    pub fn socket(&self) -> Result<Arc<Socket>, i64> {
+        match &self.object {
+            Object::Socket(s) => Ok(s.clone()),
+            _ => Err(ENOTSOCK),
+        }
+    }
+
+    fn readable(&self) -> bool {
+        self.status() & O_ACCMODE != O_WRONLY
+    }
+
+    fn writable(&self) -> bool {
+        self.status() & O_ACCMODE != O_RDONLY
+    }
+
+    fn read_at(&self, buf: &mut [u8], off: u64) -> Result<usize, i64> {
+        match &self.object {
+            Object::Vfs(fd) => crate::vfs::core::with_vfs(|v| {
+                let old = v.seek(*fd, SeekFrom::Current(0))?;
+                v.seek(*fd, SeekFrom::Start(off))?;
+                let r = v.read(*fd, buf);
+                v.seek(*fd, SeekFrom::Start(old))?;
+                r
+            }).map_err(vfs_errno),
+            Object::Memory(data) => {
+                let off = (off as usize).min(data.len());
+                let n   = buf.len().min(data.len() - off);
+                buf[..n].copy_from_slice(&data[off..off + n]);
+                Ok(n)
+            }
+            _ => Err(ESPIPE),
+        }
+    }
+
+    fn write_at(&self, data: &[u8], off: u64) -> Result<usize, i64> {
+        match &self.object {
+            Object::Vfs(fd) => crate::vfs::core::with_vfs(|v| {
+                let old = v.seek(*fd, SeekFrom::Current(0))?;
+                v.seek(*fd, SeekFrom::Start(off))?;
+                let r = v.write(*fd, data);
+                v.seek(*fd, SeekFrom::Start(old))?;
+                r
+            }).map_err(vfs_errno),
+            Object::Memory(_) => Err(EBADF),
+            _ => Err(ESPIPE),
+        }
+    }
+
+    pub fn pread(&self, buf: &mut [u8], off: u64) -> Result<usize, i64> {
+        if !self.readable() {
+            return Err(EBADF);
+        }
+        self.read_at(buf, off)
+    }
+
+    pub fn pwrite(&self, data: &[u8], off: u64) -> Result<usize, i64> {
+        if !self.writable() {
+            return Err(EBADF);
+        }
+        self.write_at(data, off)
+    }
+
+    pub fn truncate(&self, len: u64) -> Result<(), i64> {
+        if !self.writable() {
+            return Err(EBADF);
+        }
+        match &self.object {
+            Object::Vfs(fd) => {
+                crate::vfs::core::with_vfs(|v| v.ftruncate(*fd, len)).map_err(vfs_errno)
+            }
+            _ => Err(EINVAL),
+        }
+    }
+
+    pub fn seek(&self, pos: u64) -> Result<u64, i64> {
+        match &self.object {
+            Object::Vfs(fd) => {
+                crate::vfs::core::with_vfs(|v| v.seek(*fd, SeekFrom::Start(pos))).map_err(vfs_errno)
+            }
+            Object::Memory(_) => {
+                let pos = pos.min(self.size()?);
+                self.offset.store(pos, Ordering::Relaxed);
+                Ok(pos)
+            }
+            _ => Err(ESPIPE),
+        }
+    }
+
+    pub fn size(&self) -> Result<u64, i64> {
+        match &self.object {
+            Object::Console => Ok(0),
+            Object::Vfs(fd) => {
+                crate::vfs::core::with_vfs(|v| v.fstat(*fd)).map(|s| s.size).map_err(vfs_errno)
+            }
+            Object::Memory(data) => Ok(data.len() as u64),
+            Object::PipeRead(p) | Object::PipeWrite(p) => {
+                Ok(interrupts::without_interrupts(|| p.lock().buf.len()) as u64)
+            }
+            Object::Socket(s) => Ok(s.available() as u64),
+        }
+    }
+
+    pub fn stat(&self) -> Result<MikuStat, i64> {
+        match &self.object {
+            Object::Console => Ok(MikuStat::special(S_IFCHR | 0o620, 0)),
+            Object::Vfs(fd) => crate::vfs::core::with_vfs(|v| v.fstat(*fd))
+                .map(|st| MikuStat::from_vnode(&st))
+                .map_err(vfs_errno),
+            Object::Memory(data) => Ok(MikuStat::special(S_IFREG | 0o444, data.len() as u64)),
+            Object::PipeRead(_) | Object::PipeWrite(_) => {
+                Ok(MikuStat::special(S_IFIFO | 0o600, self.size()?))
+            }
+            Object::Socket(_) => Ok(MikuStat::special(S_IFSOCK | 0o777, 0)),
+        }
+    }
+
+    pub fn getdents(&self, buf: &mut [u8]) -> Result<usize, i64> {
+        if !self.readable() {
+            return Err(EBADF);
+        }
+        match &self.object {
+            Object::Vfs(fd) => {
+                let pos = crate::vfs::core::with_vfs(|v| v.seek(*fd, SeekFrom::Current(0)))
+                    .map_err(vfs_errno)?;
+                let entries = vfs_stat::vfs_entries(*fd, pos as usize)?;
+                let (n, used) = vfs_stat::pack_dirents(&entries, pos, buf)?;
+                crate::vfs::core::with_vfs(|v| v.seek(*fd, SeekFrom::Start(pos + used as u64)))
+                    .map_err(vfs_errno)?;
+                Ok(n)
+            }
+            _ => Err(ENOTDIR),
+        }
+    }
+}
+
+impl Drop for OpenFile {
+    fn drop(&mut self) {
+        match &self.object {
+            Object::Vfs(fd) => {
+                let _ = crate::vfs::core::with_vfs(|v| v.close(*fd));
+            }
+            Object::PipeRead(p) => interrupts::without_interrupts(|| {
+                let mut p = p.lock();
+                p.readers -= 1;
+                p.wake_all();
+            }),
+            Object::PipeWrite(p) => interrupts::without_interrupts(|| {
+                let mut p = p.lock();
+                p.writers -= 1;
+                p.wake_all();
+            }),
+            Object::Socket(s) => s.close(),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone)]
+struct FdEntry {
+    file:    Arc<OpenFile>,
+    cloexec: bool,
+}
+
+#[derive(Clone)]
+struct ProcessFds {
+    fds: BTreeMap<u64, FdEntry>,
+}
+
+impl ProcessFds {
+    fn new() -> Self {
+        let console = Arc::new(OpenFile::new(Object::Console, O_RDWR));
+        let mut fds = BTreeMap::new();
+        for fd in 0..3 {
+            fds.insert(fd, FdEntry { file: console.clone(), cloexec: false });
+        }
+        Self { fds }
+    }
+
+    fn lowest_free(&self, min: u64) -> Option<u64> {
+        (min..MAX_FDS).find(|fd| !self.fds.contains_key(fd))
+    }
+}
+
+static FD_TABLES: Mutex<BTreeMap<u64, Arc<Mutex<ProcessFds>>>> = Mutex::new(BTreeMap::new());
+
+fn table(pid: u64) -> Arc<Mutex<ProcessFds>> {
+    FD_TABLES.lock().entry(pid).or_insert_with(|| Arc::new(Mutex::new(ProcessFds::new()))).clone()
+}
+
+fn with_fds<F: FnOnce(&mut ProcessFds) -> R, R>(pid: u64, f: F) -> R {
+    let t = table(pid);
+    let mut pfds = t.lock();
+    f(&mut pfds)
+}
+
+pub fn vfs_errno(e: VfsError) -> i64 {
+    match e {
+        VfsError::NotFound                                 => ENOENT,
+        VfsError::PermissionDenied
+        | VfsError::SecurityViolation                      => EACCES,
+        VfsError::AlreadyExists                            => EEXIST,
+        VfsError::NotDirectory                             => ENOTDIR,
+        VfsError::IsDirectory                              => EISDIR,
+        VfsError::NotEmpty                                 => ENOTEMPTY,
+        VfsError::InvalidPath
+        | VfsError::InvalidArgument                        => EINVAL,
+        VfsError::TooManySymlinks | VfsError::Loop         => ELOOP,
+        VfsError::NoSpace | VfsError::QuotaExceeded        => ENOSPC,
+        VfsError::ReadOnly                                 => EROFS,
+        VfsError::Busy                                     => EBUSY,
+        VfsError::CrossDevice                              => EXDEV,
+        VfsError::NameTooLong                              => ENAMETOOLONG,
+        VfsError::BadFd                                    => EBADF,
+        VfsError::TooManyOpenFiles                         => ENFILE,
+        VfsError::SeekError                                => ESPIPE,
+        VfsError::WouldBlock | VfsError::PipeEmpty
+        | VfsError::PipeFull                               => EAGAIN,
+        VfsError::Interrupted                              => EINTR,
+        VfsError::FileTooLarge                             => EFBIG,
+        VfsError::BrokenPipe                               => EPIPE,
+        _                                                  => EIO,
+    }
+}
+
+pub fn get(pid: u64, fd: u64) -> Option<Arc<OpenFile>> {
+    with_fds(pid, |p| p.fds.get(&fd).map(|e| e.file.clone()))
+}
+
+pub fn install(pid: u64, file: Arc<OpenFile>, cloexec: bool) -> Result<u64, i64> {
+    with_fds(pid, |p| {
+        let fd = p.lowest_free(0).ok_or(EMFILE)?;
+        p.fds.insert(fd, FdEntry { file, cloexec });
+        Ok(fd)
+    })
+}
+
+pub fn close(pid: u64, fd: u64) -> Result<(), i64> {
+    let old = with_fds(pid, |p| p.fds.remove(&fd));
+    match old {
+        Some(_) => Ok(()),
+        None    => Err(EBADF),
+    }
+}
+
+pub fn dup2(pid: u64, old: u64, new: u64) -> Result<u64, i64> {
+    if new >= MAX_FDS {
+        return Err(EBADF);
+    }
+    let replaced = with_fds(pid, |p| -> Result<Option<FdEntry>, i64> {
+        let file = p.fds.get(&old).ok_or(EBADF)?.file.clone();
+        if old == new {
+            return Ok(None);
+        }
+        Ok(p.fds.insert(new, FdEntry { file, cloexec: false }))
+    })?;
+    drop(replaced);
+    Ok(new)
+}
+
+pub fn dup_min(pid: u64, old: u64, min: u64, cloexec: bool) -> Result<u64, i64> {
+    if min >= MAX_FDS {
+        return Err(EINVAL);
+    }
+    with_fds(pid, |p| {
+        let file = p.fds.get(&old).ok_or(EBADF)?.file.clone();
+        let fd   = p.lowest_free(min).ok_or(EMFILE)?;
+        p.fds.insert(fd, FdEntry { file, cloexec });
+        Ok(fd)
+    })
+}
+
+pub fn cloexec(pid: u64, fd: u64) -> Result<bool, i64> {
+    with_fds(pid, |p| p.fds.get(&fd).map(|e| e.cloexec).ok_or(EBADF))
+}
+
+pub fn set_cloexec(pid: u64, fd: u64, on: bool) -> Result<(), i64> {
+    with_fds(pid, |p| {
+        let e = p.fds.get_mut(&fd).ok_or(EBADF)?;
+        e.cloexec = on;
+        Ok(())
+    })
+}
+
+pub fn fork(parent: u64, child: u64) {
+    let fds = with_fds(parent, |p| p.clone());
+    FD_TABLES.lock().insert(child, Arc::new(Mutex::new(fds)));
+}
+
+pub fn share(parent: u64, child: u64) {
+    let t = table(parent);
+    FD_TABLES.lock().insert(child, t);
+}
+
+pub fn exec(pid: u64) {
+    let t = table(pid);
+    if Arc::strong_count(&t) > 2 {
+        let fds = t.lock().clone();
+        FD_TABLES.lock().insert(pid, Arc::new(Mutex::new(fds)));
+    }
+    let closed: Vec<FdEntry> = with_fds(pid, |p| {
+        let fds: Vec<u64> = p.fds.iter().filter(|(_, e)| e.cloexec).map(|(&fd, _)| fd).collect();
+        fds.iter().filter_map(|fd| p.fds.remove(fd)).collect()
+    });
+    if !closed.is_empty() {
+        crate::serial_println!("[fd] pid={} exec closed {} cloexec fds", pid, closed.len());
+    }
+}
+
+pub fn release(pid: u64) {
+    let table = FD_TABLES.lock().remove(&pid);
+    drop(table);
+}
+
+pub fn release_deferred(pid: u64) {
+    crate::scheduler::submit_task(move || release(pid));
+}
+
+pub fn open(path: &str, flags: u64, mode: u16) -> Result<Arc<OpenFile>, i64> {
+    let access = flags & O_ACCMODE;
+    if access == O_ACCMODE {
+        return Err(EINVAL);
+    }
+    let status = access | (flags & (O_APPEND | O_NONBLOCK));
+
+    if access == O_RDONLY && (path.ends_with(".so") || path.contains(".so.")) {
+        if let Some(data) = crate::vfs_read::read_file_or_solib(path) {
+            return Ok(Arc::new(OpenFile::new(Object::Memory(data), status)));
+        }
+    }
+
+    let mut vflags = match access {
+        O_WRONLY => OpenFlags::WRITE,
+        O_RDWR   => OpenFlags::RDWR,
+        _        => OpenFlags::READ,
+    };
+    for (bit, vbit) in [
+        (O_CREAT, OpenFlags::CREATE),
+        (O_EXCL, OpenFlags::EXCLUSIVE),
+        (O_TRUNC, OpenFlags::TRUNCATE),
+        (O_APPEND, OpenFlags::APPEND),
+        (O_DIRECTORY, OpenFlags::DIRECTORY),
+    ] {
+        if flags & bit != 0 {
+            vflags |= vbit;
+        }
+    }
+    let vflags = OpenFlags(vflags);
+
+    let fd = crate::vfs::core::with_vfs(|v| v.open(0, path, vflags, FileMode::new(mode))).map_err(vfs_errno)?;
+    Ok(Arc::new(OpenFile::new(Object::Vfs(fd), status)))
+}
+
+pub fn pipe(pid: u64, flags: u64) -> Result<(u64, u64), i64> {
+    if flags & !(O_NONBLOCK | O_CLOEXEC) != 0 {
+        return Err(EINVAL);
+    }
+    let pipe    = Arc::new(Mutex::new(Pipe::new()));
+    let nb      = flags & O_NONBLOCK;
+    let cloexec = flags & O_CLOEXEC != 0;
+    let rd = Arc::new(OpenFile::new(Object::PipeRead(pipe.clone()), O_RDONLY | nb));
+    let wr = Arc::new(OpenFile::new(Object::PipeWrite(pipe), O_WRONLY | nb));
+
+    with_fds(pid, |p| {
+        let rfd = p.lowest_free(0).ok_or(EMFILE)?;
+        let wfd = p.lowest_free(rfd + 1).ok_or(EMFILE)?;
+        p.fds.insert(rfd, FdEntry { file: rd, cloexec });
+        p.fds.insert(wfd, FdEntry { file: wr, cloexec });
+        Ok((rfd, wfd))
+    })
+}
+
+pub fn socket(pid: u64, proto: Proto, family: u64, flags: u64) -> Result<u64, i64> {
+    install_socket(pid, Socket::with_family(proto, family), flags)
+}
+
+pub fn install_socket(pid: u64, sock: Arc<Socket>, flags: u64) -> Result<u64, i64> {
+    if flags & !(O_NONBLOCK | O_CLOEXEC) != 0 {
+        return Err(EINVAL);
+    }
+    let file = Arc::new(OpenFile::new(Object::Socket(sock), O_RDWR | (flags & O_NONBLOCK)));
+    install(pid, file, flags & O_CLOEXEC != 0)
+}
+
+pub fn read(file: Arc<OpenFile>, buf: &mut [u8]) -> Result<usize, i64> {
+    if !file.readable() {
+        return Err(EBADF);
+    }
+    match &file.object {
+        Object::Console => Ok(0),
+        Object::Vfs(fd) => crate::vfs::core::with_vfs(|v| v.read(*fd, buf)).map_err(vfs_errno),
+        Object::Memory(_) => {
+            let off = file.offset.load(Ordering::Relaxed);
+            let n   = file.read_at(buf, off)?;
+            file.offset.store(off + n as u64, Ordering::Relaxed);
+            Ok(n)
+        }
+        Object::PipeRead(p) => {
+            let pipe = p.clone();
+            let nb   = file.nonblock();
+            drop(file);
+            pipe_read(&pipe, buf, nb)
+        }
+        Object::PipeWrite(_) => Err(EBADF),
+        Object::Socket(s) => {
+            let sock = s.clone();
+            let nb   = file.nonblock();
+            drop(file);
+            sock.recv(buf, nb).map(|(n, _)| n)
+        }
+    }
+}
+
+pub fn write(file: Arc<OpenFile>, data: &[u8]) -> Result<usize, i64> {
+    if !file.writable() {
+        return Err(EBADF);
+    }
+    match &file.object {
+        Object::Console => {
+            match core::str::from_utf8(data) {
+                Ok(t) => crate::print!("{}", t),
+                Err(_) => {
+                    for &b in data {
+                        crate::print!("{}", b as char);
+                    }
+                }
+            }
+            Ok(data.len())
+        }
+        Object::Vfs(fd) => crate::vfs::core::with_vfs(|v| v.write(*fd, data)).map_err(vfs_errno),
+        Object::PipeWrite(p) => {
+            let pipe = p.clone();
+            let nb   = file.nonblock();
+            drop(file);
+            pipe_write(&pipe, data, nb)
+        }
+        Object::Socket(s) => {
+            let sock = s.clone();
+            let nb   = file.nonblock();
+            drop(file);
+            sock_write(&sock, data, nb)
+        }
+        _ => Err(EBADF),
+    }
+}
+
+pub fn sock_write(sock: &Arc<Socket>, data: &[u8], nonblock: bool) -> Result<usize, i64> {
+    let r = sock.send(data, None, nonblock);
+    if r == Err(EPIPE) {
+        crate::signal::send(crate::scheduler::current_pid(), crate::signal::SIGPIPE);
+    }
+    r
+}
+
+fn pipe_read(pipe: &Mutex<Pipe>, buf: &mut [u8], nonblock: bool) -> Result<usize, i64> {
+    let pid = crate::scheduler::current_pid();
+    loop {
+        let r = interrupts::without_interrupts(|| {
+            let mut p = pipe.lock();
+            if !p.buf.is_empty() {
+                let n = buf.len().min(p.buf.len());
+                for (dst, src) in buf.iter_mut().zip(p.buf.drain(..n)) {
+                    *dst = src;
+                }
+                p.wake_all();
+                return Some(Ok(n));
+            }
+            if p.writers == 0 {
+                return Some(Ok(0));
+            }
+            if nonblock {
+                return Some(Err(EAGAIN));
+            }
+            None
+        });
+        if let Some(r) = r {
+            return r;
+        }
+
+        let mut interrupted = false;
+        crate::scheduler::block_unless(PIPE_CAUSE, || {
+            if crate::signal::has_pending(pid) {
+                interrupted = true;
+                return true;
+            }
+            let mut p = pipe.lock();
+            if !p.buf.is_empty() || p.writers == 0 {
+                return true;
+            }
+            p.wait(pid);
+            false
+        });
+        if interrupted {
+            return Err(EINTR);
+        }
+    }
+}
+
+fn pipe_write(pipe: &Mutex<Pipe>, data: &[u8], nonblock: bool) -> Result<usize, i64> {
+    let pid = crate::scheduler::current_pid();
+    let mut done = 0usize;
+    loop {
+        let broken = interrupts::without_interrupts(|| {
+            let mut p = pipe.lock();
+            if p.readers == 0 {
+                return true;
+            }
+            let n = (PIPE_CAPACITY - p.buf.len()).min(data.len() - done);
+            if n > 0 {
+                p.buf.extend(&data[done..done + n]);
+                done += n;
+                p.wake_all();
+            }
+            false
+        });
+        if broken {
+            crate::signal::send(pid, crate::signal::SIGPIPE);
+            return if done > 0 { Ok(done) } else { Err(EPIPE) };
+        }
+        if done == data.len() {
+            return Ok(done);
+        }
+        if nonblock {
+            return if done > 0 { Ok(done) } else { Err(EAGAIN) };
+        }
+
+        let mut interrupted = false;
+        crate::scheduler::block_unless(PIPE_CAUSE, || {
+            if crate::signal::has_pending(pid) {
+                interrupted = true;
+                return true;
+            }
+            let mut p = pipe.lock();
+            if p.buf.len() < PIPE_CAPACITY || p.readers == 0 {
+                return true;
+            }
+            p.wait(pid);
+            false
+        });
+        if interrupted {
+            return if done > 0 { Ok(done) } else { Err(EINTR) };
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::ata::{AtaDrive, AtaError};
+extern crate alloc;
+use alloc::boxed::Box;
+use crate::vfs::block_dev::{self, check_io, BlockDevice, BlockError};
 use spin::Mutex;
 
 pub const GPT_SIGNATURE: u64     = 0x5452415020494645;
@@ -179,7 +181,15 @@ fn guid_pseudo(seed: u32) -> [u8; 16] {
     g
 }
 
-fn write_protective_mbr(drive: &mut AtaDrive, total_sectors: u32) -> Result<(), AtaError> {
+fn read_sector(drive: &mut dyn BlockDevice, lba: u32, buf: &mut [u8; 512]) -> Result<(), BlockError> {
+    drive.read(lba as u64, 1, buf)
+}
+
+fn write_sector(drive: &mut dyn BlockDevice, lba: u32, buf: &[u8; 512]) -> Result<(), BlockError> {
+    drive.write(lba as u64, 1, buf)
+}
+
+fn write_protective_mbr(drive: &mut dyn BlockDevice, total_sectors: u32) -> Result<(), BlockError> {
     let mut mbr = [0u8; 512];
     mbr[446] = 0x00;
     mbr[447] = 0x00;
@@ -194,7 +204,7 @@ fn write_protective_mbr(drive: &mut AtaDrive, total_sectors: u32) -> Result<(),
     mbr[458..462].copy_from_slice(&size.to_le_bytes());
     mbr[510] = 0x55;
     mbr[511] = 0xAA;
-    drive.write_sector(0, &mbr)
+    write_sector(drive, 0, &mbr)
 }
 
 fn gpt_header_to_buf(h: &GptHeader) -> [u8; 512] {
@@ -261,8 +271,8 @@ fn header_crc(h: &GptHeader) -> u32 {
     crc32(&buf[0..GPT_HEADER_SIZE as usize])
 }
 
-fn write_entries(drive: &mut AtaDrive, entries: &[GptEntry; GPT_MAX_ENTRIES], base_lba: u32)
-    -> Result<(), AtaError>
+fn write_entries(drive: &mut dyn BlockDevice, entries: &[GptEntry; GPT_MAX_ENTRIES], base_lba: u32)
+    -> Result<(), BlockError>
 {
     let per_sec = 512 / GPT_ENTRY_SIZE;
     let mut buf = [0u8; 512];
@@ -272,18 +282,18 @@ fn write_entries(drive: &mut AtaDrive, entries: &[GptEntry; GPT_MAX_ENTRIES], ba
             let eb = entry_to_bytes(&entries[idx]);
             buf[j * GPT_ENTRY_SIZE..(j + 1) * GPT_ENTRY_SIZE].copy_from_slice(&eb);
         }
-        drive.write_sector(base_lba + chunk, &buf)?;
+        write_sector(drive, base_lba + chunk, &buf)?;
     }
     Ok(())
 }
 
-fn write_full_table(drive: &mut AtaDrive, tbl: &mut GptTable) -> Result<(), AtaError> {
+fn write_full_table(drive: &mut dyn BlockDevice, tbl: &mut GptTable) -> Result<(), BlockError> {
     tbl.header.partition_entry_array_crc32 = entries_crc(&tbl.entries);
     tbl.header.header_crc32 = 0;
     tbl.header.header_crc32 = header_crc(&tbl.header);
 
     let hbuf = gpt_header_to_buf(&tbl.header);
-    drive.write_sector(GPT_HEADER_LBA, &hbuf)?;
+    write_sector(drive, GPT_HEADER_LBA, &hbuf)?;
     write_entries(drive, &tbl.entries, GPT_ENTRIES_LBA)?;
 
     let mut backup = tbl.header;
@@ -294,14 +304,14 @@ fn write_full_table(drive: &mut AtaDrive, tbl: &mut GptTable) -> Result<(), AtaE
     backup.header_crc32 = header_crc(&backup);
 
     let bbuf = gpt_header_to_buf(&backup);
-    drive.write_sector(tbl.total_sectors - 1, &bbuf)?;
+    write_sector(drive, tbl.total_sectors - 1, &bbuf)?;
     write_entries(drive, &tbl.entries, tbl.total_sectors - 33)?;
 
     Ok(())
 }
 
-pub fn gpt_init(mut drive: AtaDrive, total_sectors: u32) -> Result<(), AtaError> {
-    write_protective_mbr(&mut drive, total_sectors)?;
+pub fn gpt_init(drive: &mut dyn BlockDevice, total_sectors: u32) -> Result<(), BlockError> {
+    write_protective_mbr(drive, total_sectors)?;
 
     let disk_guid = guid_pseudo(total_sectors ^ 0xDEAD_BEEF);
     let last_usable = total_sectors as u64 - 34;
@@ -326,7 +336,7 @@ pub fn gpt_init(mut drive: AtaDrive, total_sectors: u32) -> Result<(), AtaError>
     header.header_crc32 = header_crc(&header);
 
     let mut tbl = GptTable { header, entries, total_sectors };
-    write_full_table(&mut drive, &mut tbl)?;
+    write_full_table(drive, &mut tbl)?;
 
     let mut cache = GPT_CACHE.lock();
     cache.header  = tbl.header;
@@ -337,9 +347,9 @@ pub fn gpt_init(mut drive: AtaDrive, total_sectors: u32) -> Result<(), AtaError>
     Ok(())
 }
 
-pub fn gpt_read(drive: &mut AtaDrive) -> Result<GptTable, GptReadError> {
+pub fn gpt_read(drive: &mut dyn BlockDevice) -> Result<GptTable, GptReadError> {
     let mut buf = [0u8; 512];
-    drive.read_sector(GPT_HEADER_LBA, &mut buf).map_err(GptReadError::Io)?;
+    read_sector(drive, GPT_HEADER_LBA, &mut buf).map_err(GptReadError::Io)?;
 
     let sig = u64::from_le_bytes(buf[0..8].try_into().unwrap_or([0;8]));
     if sig != GPT_SIGNATURE { return Err(GptReadError::NotGpt); }
@@ -370,7 +380,7 @@ pub fn gpt_read(drive: &mut AtaDrive) -> Result<GptTable, GptReadError> {
     let mut entries = [GptEntry::empty(); GPT_MAX_ENTRIES];
     let per_sec = 512 / GPT_ENTRY_SIZE;
     for chunk in 0..32u32 {
-        drive.read_sector(GPT_ENTRIES_LBA + chunk, &mut buf).map_err(GptReadError::Io)?;
+        read_sector(drive, GPT_ENTRIES_LBA + chunk, &mut buf).map_err(GptReadError::Io)?;
         for j in 0..per_sec {
             let idx = chunk as usize * per_sec + j;
             if idx >= GPT_MAX_ENTRIES { break; }
@@ -383,13 +393,13 @@ pub fn gpt_read(drive: &mut AtaDrive) -> Result<GptTable, GptReadError> {
 }
 
 pub fn gpt_add_partition(
-    mut drive:    AtaDrive,
+    drive:        &mut dyn BlockDevice,
     type_guid:    [u8; 16],
     size_sectors: u64,
     name:         &str,
     seed:         u32,
 ) -> Result<usize, GptWriteError> {
-    let mut tbl = gpt_read(&mut drive).map_err(|_| GptWriteError::ReadFailed)?;
+    let mut tbl = gpt_read(drive).map_err(|_| GptWriteError::ReadFailed)?;
 
     let slot  = tbl.first_free_slot().ok_or(GptWriteError::NoFreeSlot)?;
     let start = tbl.next_free_lba();
@@ -404,7 +414,7 @@ pub fn gpt_add_partition(
     entry.set_name(name);
     tbl.entries[slot] = entry;
 
-    write_full_table(&mut drive, &mut tbl).map_err(GptWriteError::Io)?;
+    write_full_table(drive, &mut tbl).map_err(GptWriteError::Io)?;
 
     {
         let mut cache = GPT_CACHE.lock();
@@ -417,14 +427,14 @@ pub fn gpt_add_partition(
     Ok(slot)
 }
 
-pub fn gpt_del_partition(mut drive: AtaDrive, index: usize) -> Result<(), GptWriteError> {
+pub fn gpt_del_partition(drive: &mut dyn BlockDevice, index: usize) -> Result<(), GptWriteError> {
     if index >= GPT_MAX_ENTRIES { return Err(GptWriteError::InvalidIndex); }
 
-    let mut tbl = gpt_read(&mut drive).map_err(|_| GptWriteError::ReadFailed)?;
+    let mut tbl = gpt_read(drive).map_err(|_| GptWriteError::ReadFailed)?;
     if !tbl.entries[index].is_used() { return Err(GptWriteError::InvalidIndex); }
 
     tbl.entries[index] = GptEntry::empty();
-    write_full_table(&mut drive, &mut tbl).map_err(GptWriteError::Io)?;
+    write_full_table(drive, &mut tbl).map_err(GptWriteError::Io)?;
 
     {
         let mut cache = GPT_CACHE.lock();
@@ -437,29 +447,69 @@ pub fn gpt_del_partition(mut drive: AtaDrive, index: usize) -> Result<(), GptWri
     Ok(())
 }
 
-pub fn gpt_probe_sectors(drive: &mut AtaDrive) -> u32 {
-    let mut buf = [0u8; 512];
-    if drive.read_sector(0, &mut buf).is_err() { return 0; }
-    let mut lo: u32 = 2048;
-    let mut hi: u32 = u32::MAX / 2;
-    while hi > lo && drive.read_sector(hi - 1, &mut buf).is_err() { hi /= 2; }
-    while lo + 1 < hi {
-        let mid = lo + (hi - lo) / 2;
-        if drive.read_sector(mid, &mut buf).is_ok() { lo = mid; } else { hi = mid; }
+pub fn gpt_probe_sectors(drive: &mut dyn BlockDevice) -> u32 {
+    block_dev::probe_sectors(drive).min(u32::MAX as u64) as u32
+}
+
+pub struct Partition {
+    dev:     Box<dyn BlockDevice>,
+    start:   u64,
+    sectors: u64,
+}
+
+impl Partition {
+    pub fn new(dev: Box<dyn BlockDevice>, start: u64, sectors: u64) -> Self {
+        Self { dev, start, sectors }
+    }
+
+    pub fn open(mut dev: Box<dyn BlockDevice>, index: usize) -> Result<Self, GptReadError> {
+        let tbl = gpt_read(&mut *dev)?;
+        let entry = tbl.entries.get(index).filter(|e| e.is_used()).ok_or(GptReadError::NoPartition)?;
+        let start   = entry.start_lba;
+        let sectors = entry.size_sectors();
+        Ok(Self::new(dev, start, sectors))
+    }
+
+    pub fn start_lba(&self) -> u64 {
+        self.start
+    }
+}
+
+impl BlockDevice for Partition {
+    fn read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_io(self, lba, count, buf.len())?;
+        self.dev.read(self.start + lba, count, buf)
+    }
+
+    fn write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<(), BlockError> {
+        check_io(self, lba, count, buf.len())?;
+        self.dev.write(self.start + lba, count, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        self.dev.flush()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn sector_size(&self) -> usize {
+        self.dev.sector_size()
     }
-    lo + 1
 }
 
 #[derive(Debug)]
 pub enum GptReadError {
-    Io(AtaError),
+    Io(BlockError),
     NotGpt,
     InvalidFormat,
+    NoPartition,
 }
 
 #[derive(Debug)]
 pub enum GptWriteError {
-    Io(AtaError),
+    Io(BlockError),
     ReadFailed,
     NoFreeSlot,
     NotEnoughSpace,
@@ -79,7 +79,7 @@ unsafe extern "C" fn timer_handler_inner(old_rsp: u64) -> u64 {
         return old_rsp;
     }
 
-    crate::scheduler::schedule_from_isr(old_rsp)
+    crate::signal::deliver_isr(crate::scheduler::schedule_from_isr(old_rsp))
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -87,6 +87,9 @@ unsafe extern "C" fn timer_handler_inner(old_rsp: u64) -> u64 {
 pub enum InterruptIndex {
     Timer    = PIC_1_OFFSET,
     Keyboard = PIC_1_OFFSET + 1,
+    PciIrq9  = PIC_2_OFFSET + 1,
+    PciIrq10 = PIC_2_OFFSET + 2,
+    PciIrq11 = PIC_2_OFFSET + 3,
     AtaIrq14 = PIC_2_OFFSET + 6,
     AtaIrq15 = PIC_2_OFFSET + 7,
 }
@@ -123,6 +126,9 @@ lazy_static! {
             idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_fn);
         }
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::PciIrq9.as_usize()].set_handler_fn(pci_irq9_handler);
+        idt[InterruptIndex::PciIrq10.as_usize()].set_handler_fn(pci_irq10_handler);
+        idt[InterruptIndex::PciIrq11.as_usize()].set_handler_fn(pci_irq11_handler);
         idt[InterruptIndex::AtaIrq14.as_usize()].set_handler_fn(ata_irq14_handler);
         idt[InterruptIndex::AtaIrq15.as_usize()].set_handler_fn(ata_irq15_handler);
         idt
@@ -148,6 +154,29 @@ pub fn init_pics() {
     );
 }
 
+pub fn unmask_pci_irq(line: u8) -> bool {
+    if !(9..=11).contains(&line) {
+        return false;
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        let mut pics = PICS.lock();
+        let masks = pics.read_masks();
+        pics.write_masks(masks[0], masks[1] & !(1 << (line - 8)));
+    });
+    true
+}
+
+pub fn mask_pci_irq(line: u8) {
+    if !(9..=11).contains(&line) {
+        return;
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        let mut pics = PICS.lock();
+        let masks = pics.read_masks();
+        pics.write_masks(masks[0], masks[1] | (1 << (line - 8)));
+    });
+}
+
 pub fn init_pit() {
     const PIT_FREQUENCY: u32 = 1_193_182;
     const DIVISOR: u16 = (PIT_FREQUENCY / PIT_HZ) as u16;
@@ -167,6 +196,24 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_: InterruptStackFrame) {
     unsafe { PICS.lock().notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8()); }
 }
 
+extern "x86-interrupt" fn pci_irq9_handler(_: InterruptStackFrame) {
+    crate::ahci::handle_irq();
+    crate::net::handle_irq(9);
+    unsafe { PICS.lock().notify_end_of_interrupt(InterruptIndex::PciIrq9.as_u8()); }
+}
+
+extern "x86-interrupt" fn pci_irq10_handler(_: InterruptStackFrame) {
+    crate::ahci::handle_irq();
+    crate::net::handle_irq(10);
+    unsafe { PICS.lock().notify_end_of_interrupt(InterruptIndex::PciIrq10.as_u8()); }
+}
+
+extern "x86-interrupt" fn pci_irq11_handler(_: InterruptStackFrame) {
+    crate::ahci::handle_irq();
+    crate::net::handle_irq(11);
+    unsafe { PICS.lock().notify_end_of_interrupt(InterruptIndex::PciIrq11.as_u8()); }
+}
+
 extern "x86-interrupt" fn ata_irq14_handler(_: InterruptStackFrame) {
     ATA_PRIMARY_IRQ.store(true, Ordering::Release);
     unsafe { PICS.lock().notify_end_of_interrupt(InterruptIndex::AtaIrq14.as_u8()); }
@@ -184,7 +231,10 @@ extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
 extern "x86-interrupt" fn ud_handler(stack_frame: InterruptStackFrame) {
     crate::serial_println!("[#UD] invalid opcode\n{:#?}", stack_frame);
     let pid = crate::scheduler::current_pid();
-    if pid != 0 { crate::scheduler::kill(pid); crate::scheduler::yield_now(); }
+    if pid != 0 {
+        crate::scheduler::terminate(pid, crate::signal::SIGILL);
+        crate::scheduler::yield_now();
+    }
     loop { x86_64::instructions::hlt(); }
 }
 
@@ -198,7 +248,10 @@ extern "x86-interrupt" fn nm_handler(stack_frame: InterruptStackFrame) {
         crate::serial_println!("[#NM] cr0={:#x} cr4={:#x}", cr0, cr4);
     }
     let pid = crate::scheduler::current_pid();
-    if pid != 0 { crate::scheduler::kill(pid); crate::scheduler::yield_now(); }
+    if pid != 0 {
+        crate::scheduler::terminate(pid, crate::signal::SIGFPE);
+        crate::scheduler::yield_now();
+    }
     loop { x86_64::instructions::hlt(); }
 }
 
@@ -244,6 +297,14 @@ extern "x86-interrupt" fn page_fault_handler(
         }
     }
 
+    let write_violation = error_code.contains(
+        x86_64::structures::idt::PageFaultErrorCode::PROTECTION_VIOLATION
+            | x86_64::structures::idt::PageFaultErrorCode::CAUSED_BY_WRITE
+    );
+    if write_violation && fault_addr < 0x0000_8000_0000_0000 && crate::vmm::handle_cow_fault(cr3, page_addr) {
+        return;
+    }
+
     let from_user = error_code.contains(
         x86_64::structures::idt::PageFaultErrorCode::USER_MODE
     );
@@ -255,8 +316,8 @@ extern "x86-interrupt" fn page_fault_handler(
 
     if from_user {
         let pid = crate::scheduler::current_pid();
-        crate::serial_println!("[page fault] killing pid={}", pid);
-        crate::scheduler::kill(pid);
+        crate::serial_println!("[page fault] SIGSEGV pid={}", pid);
+        crate::scheduler::terminate(pid, crate::signal::SIGSEGV);
         crate::scheduler::yield_now();
         return;
     }
@@ -275,8 +336,8 @@ extern "x86-interrupt" fn gpf_handler(stack_frame: InterruptStackFrame, error_co
 
     if from_user {
         let pid = crate::scheduler::current_pid();
-        crate::serial_println!("[gpf] killing pid={}", pid);
-        crate::scheduler::kill(pid);
+        crate::serial_println!("[gpf] SIGSEGV pid={}", pid);
+        crate::scheduler::terminate(pid, crate::signal::SIGSEGV);
         crate::scheduler::yield_now();
         return;
     }
@@ -0,0 +1,42 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::sync::Arc;
+
+pub const O_RDONLY: u64 = 0;
+pub const O_WRONLY: u64 = 1;
+pub const O_RDWR:   u64 = 2;
+
+const EIO: i64 = -5;
+
+pub struct OpenFile {
+    file: File,
+}
+
+fn errno(e: io::Error) -> i64 {
+    e.raw_os_error().map_or(EIO, |n| -(n as i64))
+}
+
+impl OpenFile {
+    pub fn pread(&self, buf: &mut [u8], off: u64) -> Result<usize, i64> {
+        self.file.read_at(buf, off).map_err(errno)
+    }
+
+    pub fn pwrite(&self, data: &[u8], off: u64) -> Result<usize, i64> {
+        self.file.write_all_at(data, off).map_err(errno)?;
+        Ok(data.len())
+    }
+
+    pub fn size(&self) -> Result<u64, i64> {
+        self.file.metadata().map(|m| m.len()).map_err(errno)
+    }
+}
+
+pub fn open(path: &str, flags: u64, _mode: u16) -> Result<Arc<OpenFile>, i64> {
+    let file = OpenOptions::new()
+        .read(flags & 3 != O_WRONLY)
+        .write(flags & 3 != O_RDONLY)
+        .open(path)
+        .map_err(errno)?;
+    Ok(Arc::new(OpenFile { file }))
+}
@@ -0,0 +1,57 @@
+#![allow(
+    dead_code,
+    unused_imports,
+    unused_variables,
+    static_mut_refs,
+    mismatched_lifetime_syntaxes,
+    unused_assignments,
+    unused_mut
+)]
+extern crate alloc;
+
+#[macro_export]
+macro_rules! serial_println {
+    () => {};
+    ($($arg:tt)*) => {
+        if std::env::var_os("EXTFS_TRACE").is_some() {
+            std::eprintln!($($arg)*);
+        }
+    };
+}
+
+pub mod fdtable;
+#[path = "../../miku_extfs/mod.rs"]
+pub mod miku_extfs;
+#[path = "../../mkfs/mod.rs"]
+pub mod mkfs;
+
+pub mod vfs;
+
+#[cfg(test)]
+mod tests;
+
+pub mod interrupts {
+    pub const PIT_HZ: u32 = 250;
+}
+
+use alloc::boxed::Box;
+use miku_extfs::{FsError, MikuFS};
+use mkfs::{MkfsError, MkfsParams, MkfsReport};
+use vfs::block_dev::{BlockDevice, BlockError, FileImage};
+
+pub fn create_image(path: &str, sectors: u64) -> std::io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    file.set_len(sectors * vfs::block_dev::SECTOR_SIZE as u64)
+}
+
+pub fn format_image(path: &str, params: &MkfsParams) -> Result<MkfsReport, MkfsError> {
+    let mut dev = FileImage::open(path)?;
+    mkfs::mkfs(&mut dev, params)
+}
+
+pub fn mount_image(path: &str) -> Result<Box<MikuFS>, FsError> {
+    let dev = FileImage::open(path).map_err(|_| FsError::IoError)?;
+    let mut fs = Box::new(MikuFS::EMPTY);
+    fs.mount(Box::new(dev))?;
+    Ok(fs)
+}
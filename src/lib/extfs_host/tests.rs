@@ -0,0 +1,332 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::miku_extfs::structs::EXT2_ROOT_INO;
+use crate::miku_extfs::MikuFS;
+use crate::mkfs::{FsType, MkfsParams};
+
+const IMAGE_SECTORS: u64 = 32 * 1024 * 2;
+const BIG_FILE_LEN:  usize = 200 * 1024;
+
+static SEQ: AtomicU32 = AtomicU32::new(0);
+
+struct Image {
+    path: PathBuf,
+}
+
+impl Image {
+    fn new(tag: &str) -> Self {
+        let name = format!(
+            "miku-extfs-{}-{}-{}.img",
+            std::process::id(), tag, SEQ.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        crate::create_image(path.to_str().unwrap(), IMAGE_SECTORS).unwrap();
+        Self { path }
+    }
+
+    fn formatted(fs_type: FsType) -> Self {
+        let img = Self::new(fs_type.name());
+        let params = MkfsParams::new(fs_type, 0);
+        crate::format_image(img.path(), &params).unwrap();
+        img
+    }
+
+    fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+
+    fn mount(&self) -> Box<MikuFS> {
+        crate::mount_image(self.path()).unwrap()
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn tool(name: &str) -> Option<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|p| std::env::split_paths(&p).collect())
+        .unwrap_or_default();
+    dirs.push("/sbin".into());
+    dirs.push("/usr/sbin".into());
+    dirs.into_iter().map(|d| d.join(name)).find(|p| p.is_file())
+}
+
+fn have_e2fsprogs() -> bool {
+    let ok = tool("e2fsck").is_some() && tool("debugfs").is_some();
+    if !ok {
+        eprintln!("e2fsprogs not found, skipping");
+    }
+    ok
+}
+
+fn e2fsck(img: &Image) {
+    let out = Command::new(tool("e2fsck").unwrap())
+        .args(["-fn", img.path()])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "e2fsck -fn failed ({}):\n{}{}",
+        out.status,
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+fn debugfs(img: &Image, request: &str) -> String {
+    let out = Command::new(tool("debugfs").unwrap())
+        .args(["-R", request, img.path()])
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "debugfs -R '{}' failed", request);
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+fn debugfs_cat(img: &Image, path: &str) -> Vec<u8> {
+    let out = Command::new(tool("debugfs").unwrap())
+        .args(["-R", &format!("cat {}", path), img.path()])
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "debugfs cat {} failed", path);
+    out.stdout
+}
+
+fn ls_names(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .filter_map(|l| l.split_whitespace().last())
+        .map(String::from)
+        .collect()
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 4096) as u8).collect()
+}
+
+fn read_all(fs: &mut MikuFS, path: &str) -> Vec<u8> {
+    let ino = fs.resolve_path(path).unwrap();
+    let inode = fs.read_inode(ino).unwrap();
+    let mut buf = vec![0u8; inode.size() as usize];
+    let n = fs.read_file(&inode, 0, &mut buf).unwrap();
+    buf.truncate(n);
+    buf
+}
+
+fn check_mkfs(fs_type: FsType, features: &[&str]) {
+    if !have_e2fsprogs() { return; }
+    let img = Image::formatted(fs_type);
+    e2fsck(&img);
+
+    let stats = debugfs(&img, "stats");
+    assert!(stats.contains("Filesystem volume name:   miku"), "{}", stats);
+    for f in features {
+        assert!(stats.contains(f), "missing feature {} in:\n{}", f, stats);
+    }
+
+    let names = ls_names(&debugfs(&img, "ls -l /"));
+    assert!(names.iter().any(|n| n == "lost+found"), "{:?}", names);
+}
+
+fn write_sequence(fs_type: FsType) {
+    if !have_e2fsprogs() { return; }
+    let img  = Image::formatted(fs_type);
+    let big  = pattern(BIG_FILE_LEN);
+
+    {
+        let mut fs = img.mount();
+        let docs = fs.ext3_create_dir(EXT2_ROOT_INO, "docs", 0o755).unwrap();
+
+        let a = fs.ext3_create_file(docs, "a.txt", 0o644).unwrap();
+        fs.ext3_write_file(a, b"hello from miku", 0).unwrap();
+
+        let b = fs.ext3_create_file(EXT2_ROOT_INO, "big.bin", 0o644).unwrap();
+        assert_eq!(fs.ext3_write_file(b, &big, 0).unwrap(), big.len());
+
+        fs.ext3_rename(docs, "a.txt", "b.txt").unwrap();
+
+        let t = fs.ext3_create_file(EXT2_ROOT_INO, "tmp.txt", 0o644).unwrap();
+        fs.ext3_write_file(t, &big[..5000], 0).unwrap();
+        fs.ext3_delete_file(EXT2_ROOT_INO, "tmp.txt").unwrap();
+
+        fs.ext3_create_dir(EXT2_ROOT_INO, "gone", 0o755).unwrap();
+        fs.ext3_delete_dir(EXT2_ROOT_INO, "gone").unwrap();
+
+        fs.sync().unwrap();
+    }
+
+    e2fsck(&img);
+
+    let root = ls_names(&debugfs(&img, "ls -l /"));
+    assert!(root.iter().any(|n| n == "docs"), "{:?}", root);
+    assert!(root.iter().any(|n| n == "big.bin"), "{:?}", root);
+    assert!(!root.iter().any(|n| n == "tmp.txt" || n == "gone"), "{:?}", root);
+
+    let docs = ls_names(&debugfs(&img, "ls -l /docs"));
+    assert!(docs.iter().any(|n| n == "b.txt"), "{:?}", docs);
+    assert!(!docs.iter().any(|n| n == "a.txt"), "{:?}", docs);
+
+    assert_eq!(debugfs_cat(&img, "/docs/b.txt"), b"hello from miku");
+    assert_eq!(debugfs_cat(&img, "/big.bin"), big);
+
+    let mut fs = img.mount();
+    assert_eq!(read_all(&mut fs, "/docs/b.txt"), b"hello from miku");
+    assert_eq!(read_all(&mut fs, "/big.bin"), big);
+}
+
+fn shrink_sequence(fs_type: FsType) {
+    if !have_e2fsprogs() { return; }
+    let img = Image::formatted(fs_type);
+    let (bs, data) = {
+        let mut fs = img.mount();
+        let bs = fs.block_size as usize;
+        let data = pattern((12 + bs / 4 + 8) * bs);
+        let f = fs.ext3_create_file(EXT2_ROOT_INO, "shrink.bin", 0o644).unwrap();
+        let g = fs.ext3_create_file(EXT2_ROOT_INO, "gap.bin", 0o644).unwrap();
+        let half = 10 * bs;
+        assert_eq!(fs.ext3_write_file(f, &data[..half], 0).unwrap(), half);
+        fs.ext3_write_file(g, &data[..2 * bs], 0).unwrap();
+        assert_eq!(fs.ext3_write_file(f, &data[half..], half as u64).unwrap(), data.len() - half);
+        fs.sync().unwrap();
+        (bs, data)
+    };
+    e2fsck(&img);
+
+    for len in [(12 + 3) * bs + 100, 5 * bs + 7] {
+        {
+            let mut fs = img.mount();
+            let f = fs.resolve_path("/shrink.bin").unwrap();
+            fs.ext3_resize(f, len as u64).unwrap();
+            fs.sync().unwrap();
+        }
+        e2fsck(&img);
+        assert_eq!(debugfs_cat(&img, "/shrink.bin"), &data[..len]);
+        let mut fs = img.mount();
+        assert_eq!(read_all(&mut fs, "/shrink.bin"), &data[..len]);
+    }
+}
+
+fn replace_sequence(fs_type: FsType) {
+    if !have_e2fsprogs() { return; }
+    let img = Image::formatted(fs_type);
+    let big = pattern(BIG_FILE_LEN);
+
+    {
+        let mut fs = img.mount();
+        let old = fs.ext3_create_file(EXT2_ROOT_INO, "old.bin", 0o644).unwrap();
+        fs.ext3_write_file(old, &big, 0).unwrap();
+        let new = fs.ext3_create_file(EXT2_ROOT_INO, "new.part", 0o644).unwrap();
+        fs.ext3_write_file(new, b"replacement", 0).unwrap();
+        fs.ext3_rename_replace(EXT2_ROOT_INO, "new.part", "old.bin").unwrap();
+
+        fs.ext3_create_dir(EXT2_ROOT_INO, "a", 0o755).unwrap();
+        let b = fs.ext3_create_dir(EXT2_ROOT_INO, "b", 0o755).unwrap();
+        fs.ext3_create_file(b, "keep", 0o644).unwrap();
+        assert!(fs.ext3_rename_replace(EXT2_ROOT_INO, "a", "b").is_err());
+        assert!(fs.ext3_rename_replace(EXT2_ROOT_INO, "old.bin", "b").is_err());
+        fs.ext3_delete_file(b, "keep").unwrap();
+        fs.ext3_rename_replace(EXT2_ROOT_INO, "a", "b").unwrap();
+        fs.sync().unwrap();
+    }
+
+    e2fsck(&img);
+    let root = ls_names(&debugfs(&img, "ls -l /"));
+    assert!(!root.iter().any(|n| n == "new.part" || n == "a"), "{:?}", root);
+    assert!(root.iter().any(|n| n == "b"), "{:?}", root);
+    assert_eq!(debugfs_cat(&img, "/old.bin"), b"replacement");
+}
+
+#[test]
+fn mkfs_ext2_passes_e2fsck() {
+    check_mkfs(FsType::Ext2, &["filetype"]);
+}
+
+#[test]
+fn mkfs_ext3_passes_e2fsck() {
+    check_mkfs(FsType::Ext3, &["has_journal"]);
+}
+
+#[test]
+fn mkfs_ext4_passes_e2fsck() {
+    check_mkfs(FsType::Ext4, &["has_journal", "extent"]);
+}
+
+#[test]
+fn ext2_write_sequence() {
+    write_sequence(FsType::Ext2);
+}
+
+#[test]
+fn ext3_write_sequence() {
+    write_sequence(FsType::Ext3);
+}
+
+#[test]
+fn ext4_write_sequence() {
+    write_sequence(FsType::Ext4);
+}
+
+#[test]
+fn reads_mke2fs_image() {
+    if !have_e2fsprogs() || tool("mke2fs").is_none() { return; }
+    let img  = Image::new("mke2fs");
+    let data = pattern(70 * 1024);
+    let src  = img.path.with_extension("src");
+    std::fs::write(&src, &data).unwrap();
+
+    let status = Command::new(tool("mke2fs").unwrap())
+        .args(["-q", "-F", "-t", "ext2", "-b", "1024", img.path()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    debugfs_write(&img, &format!("write {} data.bin", src.display()));
+    debugfs_write(&img, "mkdir sub");
+    let _ = std::fs::remove_file(&src);
+
+    let mut fs = img.mount();
+    assert_eq!(read_all(&mut fs, "/data.bin"), data);
+    assert!(fs.resolve_path("/sub").is_ok());
+}
+
+#[test]
+fn ext2_shrink_across_indirect() {
+    shrink_sequence(FsType::Ext2);
+}
+
+#[test]
+fn ext3_shrink_across_indirect() {
+    shrink_sequence(FsType::Ext3);
+}
+
+#[test]
+fn ext4_shrink_across_extents() {
+    shrink_sequence(FsType::Ext4);
+}
+
+#[test]
+fn ext2_rename_replaces_target() {
+    replace_sequence(FsType::Ext2);
+}
+
+#[test]
+fn ext3_rename_replaces_target() {
+    replace_sequence(FsType::Ext3);
+}
+
+#[test]
+fn ext4_rename_replaces_target() {
+    replace_sequence(FsType::Ext4);
+}
+
+fn debugfs_write(img: &Image, request: &str) {
+    let out = Command::new(tool("debugfs").unwrap())
+        .args(["-w", "-R", request, img.path()])
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "debugfs -w -R '{}' failed", request);
+}
@@ -0,0 +1,18 @@
+#[path = "../../vfs/block_dev.rs"]
+pub mod block_dev;
+
+pub mod procfs {
+    use std::sync::OnceLock;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    static BOOT: OnceLock<Instant> = OnceLock::new();
+
+    pub fn uptime_ticks() -> u64 {
+        let boot = BOOT.get_or_init(Instant::now);
+        boot.elapsed().as_millis() as u64 * crate::interrupts::PIT_HZ as u64 / 1000
+    }
+
+    pub fn wall_clock() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+    }
+}
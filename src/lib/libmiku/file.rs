@@ -27,6 +27,124 @@ pub extern "C" fn miku_fsize(fd: i64) -> i64 {
     unsafe { sc1(SYS_FSIZE, fd as u64) }
 }
 
+#[no_mangle]
+pub extern "C" fn miku_open2(path: *const u8, flags: u64, mode: u32) -> i64 {
+    if path.is_null() { return -22; }
+    let len = crate::string::miku_strlen(path);
+    unsafe { sc4(SYS_OPEN2, path as u64, len as u64, flags, mode as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_pread(fd: i64, buf: *mut u8, len: usize, offset: u64) -> i64 {
+    unsafe { sc4(SYS_PREAD, fd as u64, buf as u64, len as u64, offset) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_pwrite(fd: i64, buf: *const u8, len: usize, offset: u64) -> i64 {
+    unsafe { sc4(SYS_PWRITE, fd as u64, buf as u64, len as u64, offset) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_ftruncate(fd: i64, len: u64) -> i64 {
+    unsafe { sc2(SYS_FTRUNCATE, fd as u64, len) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_unlink(path: *const u8) -> i64 {
+    if path.is_null() { return -22; }
+    let len = crate::string::miku_strlen(path);
+    unsafe { sc2(SYS_UNLINK, path as u64, len as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_mkdir(path: *const u8, mode: u32) -> i64 {
+    if path.is_null() { return -22; }
+    let len = crate::string::miku_strlen(path);
+    unsafe { sc3(SYS_MKDIR, path as u64, len as u64, mode as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_rmdir(path: *const u8) -> i64 {
+    if path.is_null() { return -22; }
+    let len = crate::string::miku_strlen(path);
+    unsafe { sc2(SYS_RMDIR, path as u64, len as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_rename(old: *const u8, new: *const u8) -> i64 {
+    if old.is_null() || new.is_null() { return -22; }
+    let old_len = crate::string::miku_strlen(old);
+    let new_len = crate::string::miku_strlen(new);
+    unsafe { sc4(SYS_RENAME, old as u64, old_len as u64, new as u64, new_len as u64) }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MikuStat {
+    pub dev:    u64,
+    pub ino:    u64,
+    pub mode:   u32,
+    pub nlink:  u32,
+    pub uid:    u32,
+    pub gid:    u32,
+    pub rdev:   u64,
+    pub size:   u64,
+    pub blocks: u64,
+    pub atime:  u64,
+    pub mtime:  u64,
+    pub ctime:  u64,
+}
+
+#[no_mangle]
+pub extern "C" fn miku_stat(path: *const u8, st: *mut MikuStat) -> i64 {
+    if path.is_null() || st.is_null() { return -22; }
+    let len = crate::string::miku_strlen(path);
+    unsafe { sc3(SYS_STAT, path as u64, len as u64, st as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_lstat(path: *const u8, st: *mut MikuStat) -> i64 {
+    if path.is_null() || st.is_null() { return -22; }
+    let len = crate::string::miku_strlen(path);
+    unsafe { sc3(SYS_LSTAT, path as u64, len as u64, st as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_fstat(fd: i64, st: *mut MikuStat) -> i64 {
+    if st.is_null() { return -22; }
+    unsafe { sc2(SYS_FSTAT, fd as u64, st as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_getdents(fd: i64, buf: *mut u8, len: usize) -> i64 {
+    unsafe { sc3(SYS_GETDENTS, fd as u64, buf as u64, len as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_pipe(fds: *mut i32) -> i64 {
+    unsafe { sc2(SYS_PIPE, fds as u64, 0) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_pipe2(fds: *mut i32, flags: u64) -> i64 {
+    unsafe { sc2(SYS_PIPE, fds as u64, flags) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_dup(fd: i64) -> i64 {
+    unsafe { sc3(SYS_FCNTL, fd as u64, F_DUPFD, 0) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_dup2(old: i64, new: i64) -> i64 {
+    unsafe { sc2(SYS_DUP2, old as u64, new as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_fcntl(fd: i64, cmd: u64, arg: u64) -> i64 {
+    unsafe { sc3(SYS_FCNTL, fd as u64, cmd, arg) }
+}
+
 #[no_mangle]
 pub extern "C" fn miku_read_file(path: *const u8, out_size: *mut usize) -> *mut u8 {
     if path.is_null() { return core::ptr::null_mut(); }
@@ -48,3 +166,17 @@ pub extern "C" fn miku_read_file(path: *const u8, out_size: *mut usize) -> *mut
     if !out_size.is_null() { unsafe { *out_size = done; } }
     buf
 }
+
+#[no_mangle]
+pub extern "C" fn miku_write_file(path: *const u8, buf: *const u8, len: usize) -> i64 {
+    let fd = miku_open2(path, O_WRONLY | O_CREAT | O_TRUNC, 0o644);
+    if fd < 0 { return fd; }
+    let mut done = 0usize;
+    while done < len {
+        let n = crate::io::miku_write(fd as u64, unsafe { buf.add(done) }, len - done);
+        if n <= 0 { miku_close(fd); return if n < 0 { n } else { -5 }; }
+        done += n as usize;
+    }
+    miku_close(fd);
+    done as i64
+}
@@ -10,6 +10,7 @@ pub mod num;
 pub mod string;
 pub mod heap;
 pub mod file;
+pub mod net;
 pub mod time;
 pub mod util;
 pub mod fmt;
@@ -0,0 +1,85 @@
+use crate::sys::*;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SockAddrIn {
+    pub family: u16,
+    pub port:   u16,
+    pub addr:   [u8; 4],
+    pub zero:   [u8; 8],
+}
+
+impl SockAddrIn {
+    pub const fn new(addr: [u8; 4], port: u16) -> Self {
+        Self { family: AF_INET as u16, port: port.to_be(), addr, zero: [0; 8] }
+    }
+
+    pub const fn port(&self) -> u16 {
+        u16::from_be(self.port)
+    }
+}
+
+const SOCKADDR_LEN: u64 = core::mem::size_of::<SockAddrIn>() as u64;
+
+#[no_mangle]
+pub extern "C" fn miku_sockaddr_in(sa: *mut SockAddrIn, a: u8, b: u8, c: u8, d: u8, port: u16) {
+    if sa.is_null() { return; }
+    unsafe { *sa = SockAddrIn::new([a, b, c, d], port) };
+}
+
+#[no_mangle]
+pub extern "C" fn miku_socket(domain: u64, ty: u64, protocol: u64) -> i64 {
+    unsafe { sc3(SYS_SOCKET, domain, ty, protocol) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_bind(fd: i64, sa: *const SockAddrIn) -> i64 {
+    if sa.is_null() { return -22; }
+    unsafe { sc3(SYS_BIND, fd as u64, sa as u64, SOCKADDR_LEN) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_listen(fd: i64, backlog: u64) -> i64 {
+    unsafe { sc2(SYS_LISTEN, fd as u64, backlog) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_accept(fd: i64, peer: *mut SockAddrIn) -> i64 {
+    unsafe { sc3(SYS_ACCEPT, fd as u64, peer as u64, 0) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_accept4(fd: i64, peer: *mut SockAddrIn, flags: u64) -> i64 {
+    unsafe { sc3(SYS_ACCEPT, fd as u64, peer as u64, flags) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_connect(fd: i64, sa: *const SockAddrIn) -> i64 {
+    if sa.is_null() { return -22; }
+    unsafe { sc3(SYS_CONNECT, fd as u64, sa as u64, SOCKADDR_LEN) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_send(fd: i64, buf: *const u8, len: usize) -> i64 {
+    unsafe { sc4(SYS_SENDTO, fd as u64, buf as u64, len as u64, 0) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_sendto(fd: i64, buf: *const u8, len: usize, to: *const SockAddrIn) -> i64 {
+    unsafe { sc4(SYS_SENDTO, fd as u64, buf as u64, len as u64, to as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_recv(fd: i64, buf: *mut u8, len: usize) -> i64 {
+    unsafe { sc4(SYS_RECVFROM, fd as u64, buf as u64, len as u64, 0) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_recvfrom(fd: i64, buf: *mut u8, len: usize, from: *mut SockAddrIn) -> i64 {
+    unsafe { sc4(SYS_RECVFROM, fd as u64, buf as u64, len as u64, from as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_shutdown(fd: i64, how: u64) -> i64 {
+    unsafe { sc2(SYS_SHUTDOWN, fd as u64, how) }
+}
@@ -52,3 +52,129 @@ pub extern "C" fn miku_get_tls() -> u64 {
 pub extern "C" fn miku_map_lib(name: *const u8, name_len: usize) -> i64 {
     unsafe { sc2(SYS_MAP_LIB, name as u64, name_len as u64) }
 }
+
+#[no_mangle]
+pub extern "C" fn miku_fork() -> i64 {
+    unsafe { sc0(SYS_FORK) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_clone(flags: u64, stack: u64, tls: u64) -> i64 {
+    unsafe { sc3(SYS_CLONE, flags, stack, tls) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_execve(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> i64 {
+    if path.is_null() { return -22; }
+    let len = crate::string::miku_strlen(path);
+    unsafe { sc4(SYS_EXECVE, path as u64, len as u64, argv as u64, envp as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_waitpid(pid: i64, status: *mut i32, options: u64) -> i64 {
+    unsafe { sc3(SYS_WAIT4, pid as u64, status as u64, options) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_wait(status: *mut i32) -> i64 {
+    miku_waitpid(-1, status, 0)
+}
+
+#[no_mangle]
+pub extern "C" fn miku_spawn(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> i64 {
+    let pid = miku_fork();
+    if pid == 0 {
+        miku_execve(path, argv, envp);
+        miku_exit(127);
+    }
+    pid
+}
+
+#[no_mangle]
+pub extern "C" fn miku_system(cmd: *const u8) -> i32 {
+    if cmd.is_null() { return -1; }
+    let mut line = crate::sys::zbuf::<256>();
+    let mut argv = [core::ptr::null::<u8>(); 17];
+    let mut argc = 0usize;
+    let mut i    = 0usize;
+    let mut in_word = false;
+    unsafe {
+        while *cmd.add(i) != 0 && i < line.len() - 1 {
+            let c = *cmd.add(i);
+            if c == b' ' || c == b'\t' {
+                line[i] = 0;
+                in_word = false;
+            } else {
+                line[i] = c;
+                if !in_word && argc < argv.len() - 1 {
+                    argv[argc] = line.as_ptr().add(i);
+                    argc += 1;
+                }
+                in_word = true;
+            }
+            i += 1;
+        }
+    }
+    if argc == 0 { return -1; }
+
+    let pid = miku_spawn(argv[0], argv.as_ptr(), core::ptr::null());
+    if pid < 0 { return pid as i32; }
+    let mut status = 0i32;
+    if miku_waitpid(pid, &mut status, 0) < 0 { return -1; }
+    status
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MikuSigaction {
+    pub handler:  u64,
+    pub flags:    u64,
+    pub restorer: u64,
+    pub mask:     u64,
+}
+
+#[unsafe(naked)]
+#[no_mangle]
+pub unsafe extern "C" fn miku_sigreturn() -> ! {
+    core::arch::naked_asm!(
+        "mov eax, {nr}",
+        "syscall",
+        "ud2",
+        nr = const SYS_SIGRETURN,
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn miku_sigaction(sig: i32, act: *const MikuSigaction, old: *mut MikuSigaction) -> i64 {
+    if act.is_null() {
+        return unsafe { sc3(SYS_SIGACTION, sig as u64, 0, old as u64) };
+    }
+    let mut a = unsafe { *act };
+    if a.restorer == 0 {
+        a.restorer = miku_sigreturn as *const () as u64;
+    }
+    unsafe { sc3(SYS_SIGACTION, sig as u64, &a as *const MikuSigaction as u64, old as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_signal(sig: i32, handler: u64) -> u64 {
+    let act = MikuSigaction { handler, flags: 0, restorer: 0, mask: 0 };
+    let mut old = MikuSigaction { handler: 0, flags: 0, restorer: 0, mask: 0 };
+    if miku_sigaction(sig, &act, &mut old) < 0 { return u64::MAX; }
+    old.handler
+}
+
+#[no_mangle]
+pub extern "C" fn miku_sigprocmask(how: i32, set: *const u64, old: *mut u64) -> i64 {
+    unsafe { sc3(SYS_SIGPROCMASK, how as u64, set as u64, old as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_kill(pid: u64, sig: i32) -> i64 {
+    unsafe { sc2(SYS_KILL, pid, sig as u64) }
+}
+
+#[no_mangle]
+pub extern "C" fn miku_raise(sig: i32) -> i64 {
+    miku_kill(miku_getpid(), sig)
+}
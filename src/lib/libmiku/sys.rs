@@ -18,6 +18,72 @@ pub const SYS_FSIZE:    u64 = 14;
 pub const SYS_MAP_LIB:  u64 = 15;
 pub const SYS_SLEEP:    u64 = 16;
 pub const SYS_UPTIME:   u64 = 17;
+pub const SYS_FORK:     u64 = 18;
+pub const SYS_CLONE:    u64 = 19;
+pub const SYS_EXECVE:   u64 = 20;
+pub const SYS_WAIT4:    u64 = 21;
+pub const SYS_SIGACTION:   u64 = 22;
+pub const SYS_SIGPROCMASK: u64 = 23;
+pub const SYS_KILL:        u64 = 24;
+pub const SYS_SIGRETURN:   u64 = 25;
+pub const SYS_PIPE:        u64 = 26;
+pub const SYS_DUP2:        u64 = 27;
+pub const SYS_FCNTL:       u64 = 28;
+pub const SYS_OPEN2:       u64 = 29;
+pub const SYS_PREAD:       u64 = 30;
+pub const SYS_PWRITE:      u64 = 31;
+pub const SYS_FTRUNCATE:   u64 = 32;
+pub const SYS_UNLINK:      u64 = 33;
+pub const SYS_MKDIR:       u64 = 34;
+pub const SYS_RMDIR:       u64 = 35;
+pub const SYS_RENAME:      u64 = 36;
+pub const SYS_STAT:        u64 = 37;
+pub const SYS_LSTAT:       u64 = 38;
+pub const SYS_FSTAT:       u64 = 39;
+pub const SYS_GETDENTS:    u64 = 40;
+pub const SYS_SOCKET:      u64 = 41;
+pub const SYS_BIND:        u64 = 42;
+pub const SYS_LISTEN:      u64 = 43;
+pub const SYS_ACCEPT:      u64 = 44;
+pub const SYS_CONNECT:     u64 = 45;
+pub const SYS_SENDTO:      u64 = 46;
+pub const SYS_RECVFROM:    u64 = 47;
+pub const SYS_SHUTDOWN:    u64 = 48;
+
+pub const CLONE_VM:     u64 = 0x0000_0100;
+pub const CLONE_FILES:  u64 = 0x0000_0400;
+pub const CLONE_SETTLS: u64 = 0x0008_0000;
+
+pub const WNOHANG:      u64 = 1;
+
+pub const SIG_DFL:      u64 = 0;
+pub const SIG_IGN:      u64 = 1;
+pub const SA_NODEFER:   u64 = 0x4000_0000;
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+
+pub const O_RDONLY:     u64 = 0;
+pub const O_WRONLY:     u64 = 1;
+pub const O_RDWR:       u64 = 2;
+pub const O_CREAT:      u64 = 0x40;
+pub const O_EXCL:       u64 = 0x80;
+pub const O_TRUNC:      u64 = 0x200;
+pub const O_APPEND:     u64 = 0x400;
+pub const O_NONBLOCK:   u64 = 0x800;
+pub const O_DIRECTORY:  u64 = 0x10000;
+pub const O_CLOEXEC:    u64 = 0x80000;
+pub const F_DUPFD:      u64 = 0;
+pub const F_GETFD:      u64 = 1;
+pub const F_SETFD:      u64 = 2;
+pub const F_GETFL:      u64 = 3;
+pub const F_SETFL:      u64 = 4;
+pub const FD_CLOEXEC:   u64 = 1;
+
+pub const AF_INET:      u64 = 2;
+pub const SOCK_STREAM:  u64 = 1;
+pub const SOCK_DGRAM:   u64 = 2;
+pub const SHUT_RD:      u64 = 0;
+pub const SHUT_WR:      u64 = 1;
+pub const SHUT_RDWR:    u64 = 2;
 
 #[inline(always)]
 pub unsafe fn sc0(nr: u64) -> i64 {
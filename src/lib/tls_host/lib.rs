@@ -0,0 +1,20 @@
+#![allow(dead_code, unused_mut)]
+extern crate alloc;
+
+#[path = "../../net/tls_crypto.rs"]
+pub mod tls_crypto;
+#[path = "../../net/tls_gcm.rs"]
+pub mod tls_gcm;
+#[path = "../../net/tls_chacha.rs"]
+pub mod tls_chacha;
+#[path = "../../net/tls_x25519.rs"]
+pub mod tls_x25519;
+#[path = "../../net/tls_ecdh.rs"]
+pub mod tls_ecdh;
+#[path = "../../net/tls_p384.rs"]
+pub mod tls_p384;
+#[path = "../../net/hpack.rs"]
+pub mod hpack;
+
+#[cfg(test)]
+mod tests;
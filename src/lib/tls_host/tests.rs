@@ -0,0 +1,308 @@
+use crate::hpack::{huffman_decode, huffman_encode, Decoder, Encoder};
+use crate::tls_chacha::{chacha20_block, chacha20poly1305_open, chacha20poly1305_seal, poly1305};
+use crate::tls_crypto::{hmac, sha384, sha512, Aes, HashAlg};
+use crate::tls_ecdh::ecdsa_verify;
+use crate::tls_gcm::{aes_gcm_open, aes_gcm_seal, hkdf_expand, hkdf_extract};
+use crate::tls_p384::ecdsa_p384_verify;
+use crate::tls_x25519::{x25519, x25519_base, x25519_shared};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+fn arr<const N: usize>(s: &str) -> [u8; N] {
+    hex(s).try_into().unwrap()
+}
+
+const GCM_PT:  &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                       1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39";
+const GCM_AAD: &str = "feedfacedeadbeeffeedfacedeadbeefabaddad2";
+const GCM_IV:  &str = "cafebabefacedbaddecaf888";
+
+fn gcm_roundtrip(key: &str, expect: &str) {
+    let (key, iv, aad, pt) = (hex(key), arr::<12>(GCM_IV), hex(GCM_AAD), hex(GCM_PT));
+    let mut out = [0u8; 128];
+    let n = aes_gcm_seal(&key, &iv, &aad, &pt, &mut out);
+    assert_eq!(&out[..n], &hex(expect)[..]);
+
+    let mut plain = [0u8; 128];
+    assert_eq!(aes_gcm_open(&key, &iv, &aad, &out[..n], &mut plain), Some(pt.len()));
+    assert_eq!(&plain[..pt.len()], &pt[..]);
+
+    out[3] ^= 1;
+    assert_eq!(aes_gcm_open(&key, &iv, &aad, &out[..n], &mut plain), None);
+}
+
+#[test]
+fn aes_fips197() {
+    let pt = arr::<16>("00112233445566778899aabbccddeeff");
+    for (key, ct) in [
+        ("000102030405060708090a0b0c0d0e0f", "69c4e0d86a7b0430d8cdb78070b4c55a"),
+        ("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", "8ea2b7ca516745bfeafc49904b496089"),
+    ] {
+        let aes = Aes::new(&hex(key));
+        let mut b = pt;
+        aes.encrypt_block(&mut b);
+        assert_eq!(b, arr::<16>(ct));
+        aes.decrypt_block(&mut b);
+        assert_eq!(b, pt);
+    }
+}
+
+#[test]
+fn aes128_gcm() {
+    gcm_roundtrip(
+        "feffe9928665731c6d6a8f9467308308",
+        "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+         21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091\
+         5bc94fbc3221a5db94fae95ae7121a47",
+    );
+}
+
+#[test]
+fn aes256_gcm() {
+    gcm_roundtrip(
+        "feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308",
+        "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+         8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662\
+         76fc6ece0f4e1768cddf8853bb2d551b",
+    );
+}
+
+#[test]
+fn sha384_sha512() {
+    assert_eq!(sha384(b"abc").to_vec(), hex(
+        "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed\
+         8086072ba1e7cc2358baeca134c825a7"));
+    assert_eq!(sha512(b"abc").to_vec(), hex(
+        "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+         2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"));
+    assert_eq!(sha384(&[b'a'; 1000]).to_vec(), hex(
+        "f54480689c6b0b11d0303285d9a81b21a93bca6ba5a1b4472765dca4da45ee32\
+         8082d469c650cd3b61b16d3266ab8ced"));
+}
+
+#[test]
+fn hmac_rfc4231() {
+    let data = b"what do ya want for nothing?";
+    assert_eq!(hmac(HashAlg::Sha384, b"Jefe", data), hex(
+        "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e\
+         8e2240ca5e69e2c78b3239ecfab21649"));
+    assert_eq!(hmac(HashAlg::Sha512, b"Jefe", data), hex(
+        "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+         9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"));
+}
+
+#[test]
+fn hkdf_rfc5869() {
+    let ikm  = [0x0bu8; 22];
+    let salt = hex("000102030405060708090a0b0c");
+    let info = hex("f0f1f2f3f4f5f6f7f8f9");
+
+    let prk = hkdf_extract(HashAlg::Sha256, &salt, &ikm);
+    assert_eq!(prk, hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"));
+    let mut okm = [0u8; 42];
+    hkdf_expand(HashAlg::Sha256, &prk, &info, &mut okm);
+    assert_eq!(okm.to_vec(), hex(
+        "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf\
+         34007208d5b887185865"));
+
+    let prk = hkdf_extract(HashAlg::Sha384, &salt, &ikm);
+    let mut okm = [0u8; 80];
+    hkdf_expand(HashAlg::Sha384, &prk, &info, &mut okm);
+    assert_eq!(okm.to_vec(), hex(
+        "9b5097a86038b805309076a44b3a9f38063e25b516dcbf369f394cfab43685f7\
+         48b6457763e4f0204fc5d95d1da3e62587b22eb8943d0fab6bb631a2fe9df1a6\
+         8c6ce5d56116a52005b3f122b88b39b7"));
+}
+
+#[test]
+fn chacha20_rfc8439() {
+    let key   = arr::<32>("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+    let nonce = arr::<12>("000000090000004a00000000");
+    assert_eq!(chacha20_block(&key, 1, &nonce).to_vec(), hex(
+        "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e\
+         d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"));
+}
+
+#[test]
+fn poly1305_rfc8439() {
+    let key = arr::<32>("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+    assert_eq!(poly1305(&key, b"Cryptographic Forum Research Group"),
+        arr::<16>("a8061dc1305136c6c22b8baf0c0127a9"));
+}
+
+#[test]
+fn chacha20poly1305_rfc8439() {
+    let key   = arr::<32>("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+    let nonce = arr::<12>("070000004041424344454647");
+    let aad   = hex("50515253c0c1c2c3c4c5c6c7");
+    let pt    = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    let expect = hex(
+        "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+         3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+         92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+         3ff4def08e4b7a9de576d26586cec64b6116\
+         1ae10b594f09e26a7e902ecbd0600691");
+
+    let mut out = [0u8; 256];
+    let n = chacha20poly1305_seal(&key, &nonce, &aad, pt, &mut out);
+    assert_eq!(&out[..n], &expect[..]);
+
+    let mut plain = [0u8; 256];
+    assert_eq!(chacha20poly1305_open(&key, &nonce, &aad, &out[..n], &mut plain), Some(pt.len()));
+    assert_eq!(&plain[..pt.len()], &pt[..]);
+
+    out[n - 1] ^= 0x80;
+    assert_eq!(chacha20poly1305_open(&key, &nonce, &aad, &out[..n], &mut plain), None);
+}
+
+#[test]
+fn x25519_rfc7748() {
+    let k = arr::<32>("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+    let u = arr::<32>("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+    assert_eq!(x25519(&k, &u), arr::<32>("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552"));
+
+    let alice = arr::<32>("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+    let bob   = arr::<32>("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+    let alice_pub = x25519_base(&alice);
+    let bob_pub   = x25519_base(&bob);
+    assert_eq!(alice_pub, arr::<32>("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"));
+    assert_eq!(bob_pub,   arr::<32>("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"));
+    let shared = arr::<32>("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    assert_eq!(x25519_shared(&alice, &bob_pub), Some(shared));
+    assert_eq!(x25519_shared(&bob, &alice_pub), Some(shared));
+    assert_eq!(x25519_shared(&alice, &[0u8; 32]), None);
+}
+
+#[test]
+fn x25519_iterated() {
+    let mut k = [0u8; 32];
+    k[0] = 9;
+    let mut u = k;
+    for _ in 0..1000 {
+        let next = x25519(&k, &u);
+        u = k;
+        k = next;
+    }
+    assert_eq!(k, arr::<32>("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51"));
+}
+
+const ECDSA_MSG: &[u8] = b"miku-os ecdsa known answer";
+
+#[test]
+fn ecdsa_p256() {
+    let q = hex("04fa746248635f4307e78e14e1ddc57b37a70b8f3f224258b4c8f4acd988a9cb34\
+                 fc1ea8bb2d92f9d10052794e946754fcae6090abc187da675f57a884d70f1757");
+    let r = hex("8e2d1dd535f78e37c775f4b1d425511fedace3a10dab334f49a6ac57932d358a");
+    let s = hex("26b9e38253f261a44ad296274e1a5f9500150d421446930804d498139efc5707");
+    let digest = HashAlg::Sha256.digest(ECDSA_MSG);
+    assert!(ecdsa_verify(&q, &digest, &r, &s));
+    assert!(!ecdsa_verify(&q, &HashAlg::Sha256.digest(b"tampered"), &r, &s));
+    assert!(!ecdsa_verify(&q, &digest, &s, &r));
+}
+
+#[test]
+fn ecdsa_p384() {
+    let q = hex("04f7d9a4755a040450d27d37deedd370d7724fcffb827714b956c40c13d78a891f\
+                 fa27bddaa8eb32562344e61a1e02dbb52c795f2786cafc68060424b2cd4d809a9f\
+                 ec3f5cc13ccec102d48066e5d4732997ba2a739f984e658192375f79cfd812");
+    let r = hex("9be2473cfde98f2f129f2f603cea65325cf4d18fde4658dd5d968635f7e7e86d\
+                 73a9dcb3baa00cc53b88fe5813a7b6e5");
+    let s = hex("4e55c08ae84e8884f8d4acfa2281bd71432feb7c9feb75c6e2f89bf75ec8fec3\
+                 547bc2296ef91a75a51515295b2605cf");
+    let digest = HashAlg::Sha384.digest(ECDSA_MSG);
+    assert!(ecdsa_p384_verify(&q, &digest, &r, &s));
+    assert!(!ecdsa_p384_verify(&q, &HashAlg::Sha384.digest(b"tampered"), &r, &s));
+    assert!(!ecdsa_p384_verify(&q, &digest, &s, &r));
+
+    let mut bad = q.clone();
+    bad[96] ^= 1;
+    assert!(!ecdsa_p384_verify(&bad, &digest, &r, &s));
+}
+
+fn headers(list: &[(&str, &str)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    list.iter().map(|(n, v)| (n.as_bytes().to_vec(), v.as_bytes().to_vec())).collect()
+}
+
+#[test]
+fn hpack_huffman() {
+    let mut out = Vec::new();
+    huffman_encode(b"www.example.com", &mut out);
+    assert_eq!(out, hex("f1e3c2e5f23a6ba0ab90f4ff"));
+    assert_eq!(huffman_decode(&out).unwrap(), b"www.example.com");
+
+    let all: Vec<u8> = (0..=255).collect();
+    let mut out = Vec::new();
+    huffman_encode(&all, &mut out);
+    assert_eq!(huffman_decode(&out).unwrap(), all);
+
+    assert!(huffman_decode(&hex("fffffffc")).is_none());
+    assert!(huffman_decode(&hex("1f00")).is_none());
+}
+
+#[test]
+fn hpack_requests_rfc7541_c4() {
+    let reqs = [
+        (headers(&[(":method", "GET"), (":scheme", "http"), (":path", "/"),
+                   (":authority", "www.example.com")]),
+         "828684418cf1e3c2e5f23a6ba0ab90f4ff"),
+        (headers(&[(":method", "GET"), (":scheme", "http"), (":path", "/"),
+                   (":authority", "www.example.com"), ("cache-control", "no-cache")]),
+         "828684be5886a8eb10649cbf"),
+        (headers(&[(":method", "GET"), (":scheme", "https"), (":path", "/index.html"),
+                   (":authority", "www.example.com"), ("custom-key", "custom-value")]),
+         "828785bf408825a849e95ba97d7f8925a849e95bb8e8b4bf"),
+    ];
+    let mut enc = Encoder::new();
+    let mut dec = Decoder::new(4096);
+    for (list, wire) in reqs.iter() {
+        let refs: Vec<(&[u8], &[u8])> = list.iter().map(|(n, v)| (&n[..], &v[..])).collect();
+        let mut out = Vec::new();
+        enc.encode(&refs, &mut out);
+        assert_eq!(out, hex(wire));
+        assert_eq!(&dec.decode(&out).unwrap(), list);
+    }
+}
+
+#[test]
+fn hpack_responses_rfc7541_c6() {
+    let date1 = "Mon, 21 Oct 2013 20:13:21 GMT";
+    let date2 = "Mon, 21 Oct 2013 20:13:22 GMT";
+    let resps = [
+        ("488264025885aec3771a4b6196d07abe941054d444a8200595040b8166e082a62d1bff\
+          6e919d29ad171863c78f0b97c8e9ae82ae43d3",
+         headers(&[(":status", "302"), ("cache-control", "private"), ("date", date1),
+                   ("location", "https://www.example.com")])),
+        ("4883640effc1c0bf",
+         headers(&[(":status", "307"), ("cache-control", "private"), ("date", date1),
+                   ("location", "https://www.example.com")])),
+        ("88c16196d07abe941054d444a8200595040b8166e084a62d1bffc05a839bd9ab77ad94e7\
+          821dd7f2e6c7b335dfdfcd5b3960d5af27087f3672c1ab270fb5291f9587316065c003ed\
+          4ee5b1063d5007",
+         headers(&[(":status", "200"), ("cache-control", "private"), ("date", date2),
+                   ("location", "https://www.example.com"), ("content-encoding", "gzip"),
+                   ("set-cookie", "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1")])),
+    ];
+    let mut dec = Decoder::new(256);
+    for (wire, list) in resps.iter() {
+        let wire: String = wire.split_whitespace().collect();
+        assert_eq!(&dec.decode(&hex(&wire)).unwrap(), list);
+    }
+}
+
+#[test]
+fn hpack_table_size_update() {
+    let list = headers(&[(":method", "POST"), ("content-type", "application/json"),
+                         ("authorization", "Bearer secret")]);
+    let refs: Vec<(&[u8], &[u8])> = list.iter().map(|(n, v)| (&n[..], &v[..])).collect();
+    let mut enc = Encoder::new();
+    let mut dec = Decoder::new(4096);
+    for max in [4096, 0, 64] {
+        enc.set_max_table_size(max);
+        let mut out = Vec::new();
+        enc.encode(&refs, &mut out);
+        assert_eq!(dec.decode(&out).unwrap(), list);
+    }
+    assert!(Decoder::new(64).decode(&hex("3fe11f")).is_none());
+}
@@ -75,6 +75,24 @@ long miku_close(long fd) { return 0; }
 long miku_fsize(long fd) { return 0; }
 long miku_read(unsigned long fd, void *b, unsigned long l) { return 0; }
 long miku_seek(long fd, unsigned long o) { return 0; }
+long miku_open2(const char *p, unsigned long f, unsigned int m) { return -1; }
+long miku_pread(long fd, void *b, unsigned long l, unsigned long o) { return -1; }
+long miku_pwrite(long fd, const void *b, unsigned long l, unsigned long o) { return -1; }
+long miku_ftruncate(long fd, unsigned long l) { return -1; }
+long miku_unlink(const char *p) { return -1; }
+long miku_mkdir(const char *p, unsigned int m) { return -1; }
+long miku_rmdir(const char *p) { return -1; }
+long miku_rename(const char *o, const char *n) { return -1; }
+long miku_write_file(const char *p, const void *b, unsigned long l) { return -1; }
+long miku_stat(const char *p, void *s) { return -1; }
+long miku_lstat(const char *p, void *s) { return -1; }
+long miku_fstat(long fd, void *s) { return -1; }
+long miku_getdents(long fd, void *b, unsigned long l) { return -1; }
+long miku_pipe(int *f) { return -1; }
+long miku_pipe2(int *f, unsigned long fl) { return -1; }
+long miku_dup(long fd) { return -1; }
+long miku_dup2(long o, long n) { return -1; }
+long miku_fcntl(long fd, unsigned long c, unsigned long a) { return -1; }
 void *miku_read_file(const char *p, unsigned long *s) { return 0; }
 int miku_readline(char *b, unsigned long m) { return -1; }
 char *miku_getline(void) { return 0; }
@@ -88,6 +106,19 @@ char *miku_getcwd(char *b, unsigned long s) { return 0; }
 long miku_set_tls(unsigned long a) { return 0; }
 unsigned long miku_get_tls(void) { return 0; }
 long miku_map_lib(const char *n, unsigned long l) { return 0; }
+long miku_fork(void) { return -1; }
+long miku_clone(unsigned long f, unsigned long s, unsigned long t) { return -1; }
+long miku_execve(const char *p, char *const *a, char *const *e) { return -1; }
+long miku_waitpid(long p, int *s, unsigned long o) { return -1; }
+long miku_wait(int *s) { return -1; }
+long miku_spawn(const char *p, char *const *a, char *const *e) { return -1; }
+int miku_system(const char *c) { return -1; }
+long miku_sigaction(int s, const void *a, void *o) { return -1; }
+unsigned long miku_signal(int s, unsigned long h) { return 0; }
+long miku_sigprocmask(int h, const unsigned long *s, unsigned long *o) { return -1; }
+long miku_kill(unsigned long p, int s) { return -1; }
+long miku_raise(int s) { return -1; }
+void miku_sigreturn(void) { for (;;) {} }
 "#).unwrap();
 
     let status = Command::new("gcc")
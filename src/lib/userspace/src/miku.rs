@@ -1,5 +1,64 @@
 #![allow(dead_code)]
 
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SigAction {
+    pub handler:  u64,
+    pub flags:    u64,
+    pub restorer: u64,
+    pub mask:     u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Stat {
+    pub dev:    u64,
+    pub ino:    u64,
+    pub mode:   u32,
+    pub nlink:  u32,
+    pub uid:    u32,
+    pub gid:    u32,
+    pub rdev:   u64,
+    pub size:   u64,
+    pub blocks: u64,
+    pub atime:  u64,
+    pub mtime:  u64,
+    pub ctime:  u64,
+}
+
+impl Stat {
+    pub fn is_dir(&self) -> bool { self.mode & S_IFMT == S_IFDIR }
+    pub fn is_file(&self) -> bool { self.mode & S_IFMT == S_IFREG }
+    pub fn is_symlink(&self) -> bool { self.mode & S_IFMT == S_IFLNK }
+}
+
+pub const SIGINT:  i32 = 2;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+
+pub const O_RDONLY: u64 = 0;
+pub const O_WRONLY: u64 = 1;
+pub const O_RDWR:   u64 = 2;
+pub const O_CREAT:  u64 = 0x40;
+pub const O_EXCL:   u64 = 0x80;
+pub const O_TRUNC:  u64 = 0x200;
+pub const O_APPEND: u64 = 0x400;
+
+pub const S_IFMT:  u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+
 #[link(name = "miku")]
 extern "C" {
     pub fn miku_exit(code: i64) -> !;
@@ -71,6 +130,24 @@ extern "C" {
     pub fn miku_close(fd: i64) -> i64;
     pub fn miku_seek(fd: i64, offset: u64) -> i64;
     pub fn miku_fsize(fd: i64) -> i64;
+    pub fn miku_open2(path: *const u8, flags: u64, mode: u32) -> i64;
+    pub fn miku_pread(fd: i64, buf: *mut u8, len: usize, offset: u64) -> i64;
+    pub fn miku_pwrite(fd: i64, buf: *const u8, len: usize, offset: u64) -> i64;
+    pub fn miku_ftruncate(fd: i64, len: u64) -> i64;
+    pub fn miku_unlink(path: *const u8) -> i64;
+    pub fn miku_mkdir(path: *const u8, mode: u32) -> i64;
+    pub fn miku_rmdir(path: *const u8) -> i64;
+    pub fn miku_rename(old: *const u8, new: *const u8) -> i64;
+    pub fn miku_write_file(path: *const u8, buf: *const u8, len: usize) -> i64;
+    pub fn miku_stat(path: *const u8, st: *mut Stat) -> i64;
+    pub fn miku_lstat(path: *const u8, st: *mut Stat) -> i64;
+    pub fn miku_fstat(fd: i64, st: *mut Stat) -> i64;
+    pub fn miku_getdents(fd: i64, buf: *mut u8, len: usize) -> i64;
+    pub fn miku_pipe(fds: *mut i32) -> i64;
+    pub fn miku_pipe2(fds: *mut i32, flags: u64) -> i64;
+    pub fn miku_dup(fd: i64) -> i64;
+    pub fn miku_dup2(old: i64, new: i64) -> i64;
+    pub fn miku_fcntl(fd: i64, cmd: u64, arg: u64) -> i64;
     pub fn miku_read_file(path: *const u8, out_size: *mut usize) -> *mut u8;
     pub fn miku_readline(buf: *mut u8, max_len: usize) -> i32;
     pub fn miku_getline() -> *mut u8;
@@ -89,6 +166,19 @@ extern "C" {
     pub fn miku_set_tls(addr: u64) -> i64;
     pub fn miku_get_tls() -> u64;
     pub fn miku_map_lib(name: *const u8, name_len: usize) -> i64;
+    pub fn miku_fork() -> i64;
+    pub fn miku_clone(flags: u64, stack: u64, tls: u64) -> i64;
+    pub fn miku_execve(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> i64;
+    pub fn miku_waitpid(pid: i64, status: *mut i32, options: u64) -> i64;
+    pub fn miku_wait(status: *mut i32) -> i64;
+    pub fn miku_spawn(path: *const u8, argv: *const *const u8, envp: *const *const u8) -> i64;
+    pub fn miku_system(cmd: *const u8) -> i32;
+    pub fn miku_sigaction(sig: i32, act: *const SigAction, old: *mut SigAction) -> i64;
+    pub fn miku_signal(sig: i32, handler: u64) -> u64;
+    pub fn miku_sigprocmask(how: i32, set: *const u64, old: *mut u64) -> i64;
+    pub fn miku_kill(pid: u64, sig: i32) -> i64;
+    pub fn miku_raise(sig: i32) -> i64;
+    pub fn miku_sigreturn() -> !;
 }
 
 pub fn exit(code: i64) -> ! {
@@ -141,6 +231,26 @@ pub fn getpid() -> u64 {
     unsafe { miku_getpid() }
 }
 
+pub fn fork() -> i64 {
+    unsafe { miku_fork() }
+}
+
+pub fn waitpid(pid: i64, status: &mut i32, options: u64) -> i64 {
+    unsafe { miku_waitpid(pid, status, options) }
+}
+
+pub fn wait(status: &mut i32) -> i64 {
+    unsafe { miku_wait(status) }
+}
+
+pub fn signal(sig: i32, handler: extern "C" fn(i32)) -> u64 {
+    unsafe { miku_signal(sig, handler as usize as u64) }
+}
+
+pub fn kill(pid: u64, sig: i32) -> i64 {
+    unsafe { miku_kill(pid, sig) }
+}
+
 pub fn brk(addr: u64) -> u64 {
     unsafe { miku_brk(addr) }
 }
@@ -214,10 +324,107 @@ pub fn seek(fd: i64, offset: u64) {
     unsafe { miku_seek(fd, offset); }
 }
 
+pub fn pipe() -> Result<(i64, i64), i64> {
+    let mut fds = [0i32; 2];
+    let r = unsafe { miku_pipe(fds.as_mut_ptr()) };
+    if r < 0 { Err(r) } else { Ok((fds[0] as i64, fds[1] as i64)) }
+}
+
+pub fn dup2(old: i64, new: i64) -> Result<i64, i64> {
+    let fd = unsafe { miku_dup2(old, new) };
+    if fd < 0 { Err(fd) } else { Ok(fd) }
+}
+
 pub fn read(fd: i64, buf: &mut [u8]) -> i64 {
     unsafe { miku_read(fd as u64, buf.as_mut_ptr(), buf.len()) }
 }
 
+fn cpath(path: &str) -> [u8; 256] {
+    let mut p = [0u8; 256];
+    let len = path.len().min(255);
+    p[..len].copy_from_slice(&path.as_bytes()[..len]);
+    p
+}
+
+pub fn open_with(path: &str, flags: u64, mode: u32) -> Result<i64, i64> {
+    let p = cpath(path);
+    let fd = unsafe { miku_open2(p.as_ptr(), flags, mode) };
+    if fd < 0 { Err(fd) } else { Ok(fd) }
+}
+
+pub fn write_file(path: &str, data: &[u8]) -> i64 {
+    let p = cpath(path);
+    unsafe { miku_write_file(p.as_ptr(), data.as_ptr(), data.len()) }
+}
+
+pub fn unlink(path: &str) -> i64 {
+    let p = cpath(path);
+    unsafe { miku_unlink(p.as_ptr()) }
+}
+
+pub fn mkdir(path: &str, mode: u32) -> i64 {
+    let p = cpath(path);
+    unsafe { miku_mkdir(p.as_ptr(), mode) }
+}
+
+pub fn rename(old: &str, new: &str) -> i64 {
+    let (o, n) = (cpath(old), cpath(new));
+    unsafe { miku_rename(o.as_ptr(), n.as_ptr()) }
+}
+
+pub fn stat(path: &str) -> Result<Stat, i64> {
+    let p = cpath(path);
+    let mut st = Stat::default();
+    let r = unsafe { miku_stat(p.as_ptr(), &mut st) };
+    if r < 0 { Err(r) } else { Ok(st) }
+}
+
+pub fn lstat(path: &str) -> Result<Stat, i64> {
+    let p = cpath(path);
+    let mut st = Stat::default();
+    let r = unsafe { miku_lstat(p.as_ptr(), &mut st) };
+    if r < 0 { Err(r) } else { Ok(st) }
+}
+
+pub fn fstat(fd: i64) -> Result<Stat, i64> {
+    let mut st = Stat::default();
+    let r = unsafe { miku_fstat(fd, &mut st) };
+    if r < 0 { Err(r) } else { Ok(st) }
+}
+
+pub fn getdents(fd: i64, buf: &mut [u8]) -> i64 {
+    unsafe { miku_getdents(fd, buf.as_mut_ptr(), buf.len()) }
+}
+
+pub struct Dirents<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+pub fn dirents(buf: &[u8]) -> Dirents<'_> {
+    Dirents { buf, pos: 0 }
+}
+
+impl<'a> Iterator for Dirents<'a> {
+    type Item = (u64, u8, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rec = self.buf.get(self.pos..)?;
+        if rec.len() < 19 {
+            return None;
+        }
+        let ino    = u64::from_le_bytes(rec[0..8].try_into().ok()?);
+        let reclen = u16::from_le_bytes([rec[16], rec[17]]) as usize;
+        if reclen < 19 || reclen > rec.len() {
+            return None;
+        }
+        let name = &rec[19..reclen];
+        let end  = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        self.pos += reclen;
+        Some((ino, rec[18], core::str::from_utf8(&name[..end]).unwrap_or("?")))
+    }
+}
+
 pub fn read_file(path: &str) -> Option<(*mut u8, usize)> {
     let mut size: usize = 0;
     let mut p = [0u8; 256];
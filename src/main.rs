@@ -13,12 +13,14 @@
 extern crate alloc;
 use core::panic::PanicInfo;
 mod allocator;
+mod ahci;
 mod ata;
 pub mod boot;
 mod boot_entry;
 mod color;
 mod commands;
 mod console;
+mod disk;
 mod font;
 mod gdt;
 mod grub;
@@ -27,12 +29,14 @@ mod miku_extfs;
 mod ldso;
 pub mod mkfs;
 mod net;
+mod pci;
 mod pmm;
 mod power;
 mod process;
 mod ring3;
 mod scheduler;
 mod syscall;
+mod signal;
 pub mod serial;
 mod shell;
 pub mod stdin;
@@ -41,6 +45,7 @@ mod vmm;
 mod elf;
 mod elf_loader;
 mod exec_elf;
+mod fdtable;
 pub mod user_stdin;
 mod vfs;
 pub mod dynlink;
@@ -52,6 +57,8 @@ mod solib;
 mod random;
 mod reloc;
 mod vfs_read;
+mod vfs_write;
+mod vfs_stat;
 
 unsafe extern "C" {
     static _kernel_end: u8;
@@ -74,12 +81,12 @@ fn kernel_main() -> ! {
     unsafe {
         let cr0: u64;
         core::arch::asm!("mov {}, cr0", out(reg) cr0);
-        core::arch::asm!("mov cr0, {}", in(reg) (cr0 & !(1u64 << 2)) | (1u64 << 1));
+        core::arch::asm!("mov cr0, {}", in(reg) (cr0 & !(1u64 << 2)) | (1u64 << 1) | (1u64 << 16));
         let cr4: u64;
         core::arch::asm!("mov {}, cr4", out(reg) cr4);
         core::arch::asm!("mov cr4, {}", in(reg) cr4 | (1u64 << 9) | (1u64 << 10));
     }
-    serial_println!("[sse] enabled (CR0.EM=0 CR0.MP=1 CR4.OSFXSR=1 CR4.OSXMMEXCPT=1)");
+    serial_println!("[sse] enabled (CR0.EM=0 CR0.MP=1 CR0.WP=1 CR4.OSFXSR=1 CR4.OSXMMEXCPT=1)");
     syscall::init();
     interrupts::init_idt();
     interrupts::init_pics();
@@ -118,9 +125,11 @@ fn kernel_main() -> ! {
     crate::solib::preload("libmiku.so", crate::ldso::LIBMIKU_BYTES.to_vec());
     crate::solib::ldconfig();
     boot_step!("Shared library cache",      Ok(()));
+    boot_step!("AHCI storage",              ahci::init());
     boot_step!("Network subsystem",         net::init());
     scheduler::init_main_thread();
     scheduler::init_workers(4);
+    net::start();
     boot_step!("Scheduler (4 workers)",   Ok(()));
     x86_64::instructions::interrupts::enable();
     boot_step!("Interrupts",              Ok(()));
@@ -202,6 +202,13 @@ impl MikuFS {
         if group >= self.group_count as usize || group >= 32 {
             return Err(FsError::InvalidInode);
         }
+        let mut inode = self.read_inode(inode_num)?;
+        if inode.links_count() != 0 || inode.dtime() == 0 {
+            let now = self.get_timestamp();
+            inode.set_links_count(0);
+            inode.set_dtime(now);
+            self.write_inode(inode_num, &inode)?;
+        }
         let bitmap_block = self.groups[group].inode_bitmap();
         self.set_bitmap_bit(bitmap_block, bit, false)?;
         self.update_inode_bitmap_csum(group)?;
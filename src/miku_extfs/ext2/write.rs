@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use crate::miku_extfs::structs::*;
 use crate::miku_extfs::{FsError, MikuFS};
 
@@ -415,31 +416,124 @@ impl MikuFS {
         Ok(())
     }
 
-    fn free_indirect_chain(&mut self, block: u32, depth: u32) -> Result<(), FsError> {
-        if block == 0 { return Ok(()); }
+    fn free_indirect_chain(&mut self, block: u32, depth: u32) -> Result<u32, FsError> {
+        if block == 0 { return Ok(0); }
 
         let ptrs_per_block = self.block_size / 4;
         let total_blocks = self.superblock.blocks_count();
         let first_data = self.superblock.first_data_block();
+        let mut freed = 1u32;
 
         if depth == 1 {
             for i in 0..ptrs_per_block {
                 let ptr = self.read_indirect_entry(block, i)?;
                 if ptr != 0 && ptr >= first_data && ptr < total_blocks {
                     self.free_block(ptr)?;
+                    freed += 1;
                 }
             }
         } else {
             for i in 0..ptrs_per_block {
                 let ptr = self.read_indirect_entry(block, i)?;
                 if ptr != 0 && ptr >= first_data && ptr < total_blocks {
-                    self.free_indirect_chain(ptr, depth - 1)?;
+                    freed += self.free_indirect_chain(ptr, depth - 1)?;
                 }
             }
         }
 
         self.free_block(block)?;
-        Ok(())
+        Ok(freed)
+    }
+
+    fn trim_indirect_chain(
+        &mut self, block: u32, depth: u32, base: u64, keep: u64, detached: &mut Vec<(u32, u32)>,
+    ) -> Result<(), FsError> {
+        let bs = self.block_size as usize;
+        let span = ((bs / 4) as u64).pow(depth - 1);
+        let total_blocks = self.superblock.blocks_count();
+        let first_data = self.superblock.first_data_block();
+        let mut buf = [0u8; 4096];
+        self.read_block_into(block, &mut buf[..bs])?;
+
+        for i in 0..bs / 4 {
+            let start = base + i as u64 * span;
+            if start + span <= keep { continue; }
+            let off = i * 4;
+            let ptr = u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+            if ptr == 0 || ptr < first_data || ptr >= total_blocks { continue; }
+            if start < keep {
+                self.trim_indirect_chain(ptr, depth - 1, start, keep, detached)?;
+                continue;
+            }
+            detached.push((ptr, depth - 1));
+            buf[off..off + 4].fill(0);
+        }
+        self.write_block_data(block, &buf[..bs])?;
+        self.ext3_journal_current_block(block)
+    }
+
+    pub fn zero_tail(&mut self, inode: &Inode, new_size: u64) -> Result<(), FsError> {
+        let bs = self.block_size as usize;
+        let off = (new_size % bs as u64) as usize;
+        if off == 0 || inode.has_inline_data() {
+            return Ok(());
+        }
+        let phys = self.get_file_block(inode, (new_size / bs as u64) as u32)?;
+        if phys == 0 {
+            return Ok(());
+        }
+        let mut buf = [0u8; 4096];
+        self.read_block_into(phys, &mut buf[..bs])?;
+        buf[off..bs].fill(0);
+        self.write_block_data(phys, &buf[..bs])
+    }
+
+    pub fn ext2_shrink(&mut self, inode_num: u32, new_size: u64) -> Result<(), FsError> {
+        let mut inode = self.read_inode(inode_num)?;
+        let bs = self.block_size as u64;
+        let ppb = bs / 4;
+        let keep = new_size.div_ceil(bs);
+        let mut detached = Vec::new();
+        self.zero_tail(&inode, new_size)?;
+
+        for i in keep.min(12) as usize..12 {
+            let blk = inode.block(i);
+            if blk != 0 {
+                detached.push((blk, 0));
+                inode.set_block(i, 0);
+            }
+        }
+
+        let (mut base, mut span) = (12u64, ppb);
+        for (i, depth) in [(12usize, 1u32), (13, 2), (14, 3)] {
+            let blk = inode.block(i);
+            if blk != 0 && base + span > keep {
+                if keep <= base {
+                    detached.push((blk, depth));
+                    inode.set_block(i, 0);
+                } else {
+                    self.trim_indirect_chain(blk, depth, base, keep, &mut detached)?;
+                }
+            }
+            base += span;
+            span *= ppb;
+        }
+
+        inode.set_size_full(new_size);
+        inode.set_mtime(self.get_timestamp());
+        self.write_inode(inode_num, &inode)?;
+
+        let mut freed = 0u32;
+        for (blk, depth) in detached {
+            if depth == 0 {
+                self.free_block(blk)?;
+                freed += 1;
+            } else {
+                freed += self.free_indirect_chain(blk, depth)?;
+            }
+        }
+        inode.set_blocks(inode.blocks().saturating_sub(freed * (self.block_size / 512)));
+        self.write_inode(inode_num, &inode)
     }
 
     pub fn ext2_truncate(&mut self, inode_num: u32) -> Result<(), FsError> {
@@ -477,6 +571,61 @@ impl MikuFS {
         Ok(())
     }
 
+    pub fn ext2_rename_replace(
+        &mut self, parent_ino: u32, old_name: &str, new_name: &str,
+    ) -> Result<(), FsError> {
+        let src_ino = self.ext2_lookup_in_dir(parent_ino, old_name)?.ok_or(FsError::NotFound)?;
+        let dst_ino = self.ext2_lookup_in_dir(parent_ino, new_name)?.ok_or(FsError::NotFound)?;
+        if src_ino == dst_ino {
+            return Ok(());
+        }
+        let src = self.read_inode(src_ino)?;
+        let mut dst = self.read_inode(dst_ino)?;
+        match (src.is_directory(), dst.is_directory()) {
+            (true, false) => return Err(FsError::NotDirectory),
+            (false, true) => return Err(FsError::IsDirectory),
+            (true, true) if !self.is_ext2_dir_empty(dst_ino)? => return Err(FsError::NotEmpty),
+            _ => {}
+        }
+        let ft = match src.file_type() {
+            InodeType::Directory => FT_DIR,
+            InodeType::Symlink => FT_SYMLINK,
+            _ => FT_REG_FILE,
+        };
+
+        self.set_dir_entry_ino(parent_ino, new_name, src_ino, ft)?;
+        self.remove_dir_entry(parent_ino, old_name)?;
+
+        let now = self.get_timestamp();
+        if !dst.is_directory() && dst.links_count() > 1 {
+            dst.set_links_count(dst.links_count() - 1);
+            dst.set_ctime(now);
+            return self.write_inode(dst_ino, &dst);
+        }
+        if dst.uses_extents() {
+            self.ext4_free_extent_blocks(&dst)?;
+        } else if !dst.is_symlink() || !dst.is_fast_symlink() {
+            self.free_all_blocks(&dst)?;
+        }
+        self.free_inode(dst_ino)?;
+
+        if dst.is_directory() {
+            let mut parent_inode = self.read_inode(parent_ino)?;
+            let links = parent_inode.links_count();
+            if links > 1 {
+                parent_inode.set_links_count(links - 1);
+            }
+            parent_inode.set_mtime(now);
+            self.write_inode(parent_ino, &parent_inode)?;
+            let gidx = ((dst_ino - 1) / self.inodes_per_group) as usize;
+            if gidx < 32 {
+                self.groups[gidx].dec_used_dirs();
+                self.flush_group_desc(gidx)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn ext2_chmod(&mut self, inode_num: u32, mode: u16) -> Result<(), FsError> {
         let mut inode = self.read_inode(inode_num)?;
         let type_bits = inode.mode() & 0xF000;
@@ -798,6 +947,46 @@ impl MikuFS {
         Ok(())
     }
 
+    fn set_dir_entry_ino(&mut self, dir_ino: u32, name: &str, ino: u32, file_type: u8) -> Result<(), FsError> {
+        let inode = self.read_inode(dir_ino)?;
+        let bs = self.block_size as usize;
+        let name_bytes = name.as_bytes();
+        let num_blocks = (inode.size_lo() as usize).div_ceil(bs);
+
+        for b in 0..num_blocks {
+            let phys = self.get_file_block(&inode, b as u32)?;
+            if phys == 0 { continue; }
+
+            let mut block_data = [0u8; 4096];
+            self.read_block_into(phys, &mut block_data[..bs])?;
+
+            let mut pos = 0;
+            while pos + 8 <= bs {
+                let rec_ino = u32::from_le_bytes([
+                    block_data[pos], block_data[pos+1],
+                    block_data[pos+2], block_data[pos+3],
+                ]);
+                let rec_len = u16::from_le_bytes([
+                    block_data[pos+4], block_data[pos+5],
+                ]) as usize;
+                let rec_name_len = block_data[pos+6] as usize;
+                if rec_len == 0 { break; }
+
+                if rec_ino != 0
+                    && rec_name_len == name_bytes.len()
+                    && pos + 8 + rec_name_len <= bs
+                    && &block_data[pos+8..pos+8+rec_name_len] == name_bytes
+                {
+                    block_data[pos..pos+4].copy_from_slice(&ino.to_le_bytes());
+                    block_data[pos+7] = file_type;
+                    return self.write_block_data(phys, &block_data[..bs]);
+                }
+                pos += rec_len;
+            }
+        }
+        Err(FsError::NotFound)
+    }
+
     pub fn remove_dir_entry(&mut self, dir_ino: u32, name: &str) -> Result<(), FsError> {
         let inode = self.read_inode(dir_ino)?;
         let bs = self.block_size as usize;
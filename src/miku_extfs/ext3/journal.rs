@@ -400,6 +400,13 @@ impl MikuFS {
         Ok(())
     }
 
+    pub fn journal_group_desc(&mut self, group: usize) -> Result<(), FsError> {
+        if group >= 32 { return Ok(()); }
+        let gdt_block = if self.block_size == 1024 { 2 } else { 1 };
+        let gd_off = group as u32 * self.superblock.group_desc_size();
+        self.ext3_journal_current_block(gdt_block + gd_off / self.block_size)
+    }
+
     pub fn ext3_journal_revoke_block(&mut self, fs_block: u32) -> Result<(), FsError> {
         if !self.journal_active || !self.txn_active { return Ok(()); }
         if self.txn_revoke_count >= 128 { return Ok(()); }
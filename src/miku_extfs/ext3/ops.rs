@@ -295,6 +295,54 @@ impl MikuFS {
         }
     }
 
+    pub fn ext3_rename_replace(
+        &mut self,
+        parent_ino: u32,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<(), FsError> {
+        if !self.journal_active {
+            return self.ext2_rename_replace(parent_ino, old_name, new_name);
+        }
+
+        let (src_ino, dst_ino) = match (
+            self.ext2_lookup_in_dir(parent_ino, old_name)?,
+            self.ext2_lookup_in_dir(parent_ino, new_name)?,
+        ) {
+            (Some(s), Some(d)) => (s, d),
+            _ => return Err(FsError::NotFound),
+        };
+        let dst = self.read_inode(dst_ino)?;
+        let freed = dst.is_directory() || dst.links_count() <= 1;
+
+        self.ext3_begin_txn()?;
+        self.journal_inode_blocks(parent_ino)?;
+        self.journal_inode_metadata(parent_ino)?;
+        self.journal_inode_metadata(src_ino)?;
+        self.journal_inode_metadata(dst_ino)?;
+        if freed {
+            self.ext3_journal_revoke_inode_blocks(dst_ino)?;
+        }
+
+        let result = self.ext2_rename_replace(parent_ino, old_name, new_name).and_then(|()| {
+            for group in 0..(self.group_count as usize).min(32) {
+                if self.groups_dirty[group] {
+                    self.journal_group_metadata(group)?;
+                    self.journal_group_desc(group)?;
+                }
+            }
+            self.flush_all_dirty_metadata()
+        });
+
+        match result {
+            Ok(()) => self.ext3_commit_txn(),
+            Err(e) => {
+                self.ext3_abort_txn();
+                Err(e)
+            }
+        }
+    }
+
     pub fn ext3_hardlink(
         &mut self,
         parent_ino: u32,
@@ -368,6 +416,60 @@ impl MikuFS {
         }
     }
 
+    pub fn ext3_resize(&mut self, inode_num: u32, new_size: u64) -> Result<(), FsError> {
+        let inode = self.read_inode(inode_num)?;
+        if !inode.is_regular() {
+            return Err(FsError::NotRegularFile);
+        }
+        let old_size = inode.size();
+        if new_size == old_size {
+            return Ok(());
+        }
+
+        if new_size > old_size {
+            let zeros = [0u8; 4096];
+            let mut off = old_size;
+            while off < new_size {
+                let n = ((new_size - off) as usize).min(zeros.len());
+                self.ext3_write_file(inode_num, &zeros[..n], off)?;
+                off += n as u64;
+            }
+            return Ok(());
+        }
+
+        if new_size == 0 {
+            return self.ext3_truncate(inode_num);
+        }
+        let use_extents = inode.uses_extents();
+        let shrink = |fs: &mut Self| if use_extents {
+            fs.ext4_shrink(inode_num, new_size)
+        } else {
+            fs.ext2_shrink(inode_num, new_size)
+        };
+        if !self.journal_active {
+            return shrink(self);
+        }
+
+        self.ext3_begin_txn()?;
+        self.journal_inode_metadata(inode_num)?;
+        let result = shrink(self).and_then(|()| {
+            for group in 0..(self.group_count as usize).min(32) {
+                if self.groups_dirty[group] {
+                    self.journal_group_metadata(group)?;
+                    self.journal_group_desc(group)?;
+                }
+            }
+            self.flush_all_dirty_metadata()
+        });
+        match result {
+            Ok(()) => self.ext3_commit_txn(),
+            Err(e) => {
+                self.ext3_abort_txn();
+                Err(e)
+            }
+        }
+    }
+
     pub fn ext3_write_file_create_or_overwrite(
         &mut self, parent_ino: u32, name: &str, mode: u16, data: &[u8],
     ) -> Result<u32, FsError> {
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use crate::miku_extfs::structs::*;
 use crate::miku_extfs::{FsError, MikuFS};
 
@@ -416,6 +417,64 @@ impl MikuFS {
         Ok(freed)
     }
 
+    pub fn ext4_trim_extent_node(
+        &mut self, node: &mut [u8], keep: u32, runs: &mut Vec<(u32, u32)>, trees: &mut Vec<(u32, u16)>,
+    ) -> Result<(), FsError> {
+        let rd16 = |b: &[u8], o: usize| u16::from_le_bytes([b[o], b[o + 1]]);
+        let rd32 = |b: &[u8], o: usize| u32::from_le_bytes([b[o], b[o + 1], b[o + 2], b[o + 3]]);
+        let entries = rd16(node, 2) as usize;
+        let depth = rd16(node, 6);
+        let mut kept = 0u16;
+
+        for i in 0..entries {
+            let base = 12 + i * 12;
+            let lblk = rd32(node, base);
+            if depth == 0 {
+                let ee_len = rd16(node, base + 4);
+                let uninit = ee_len > 32768;
+                let len = if uninit { ee_len - 32768 } else { ee_len } as u32;
+                let start = rd32(node, base + 8);
+                let cut = keep.saturating_sub(lblk).min(len);
+                if cut < len {
+                    runs.push((start + cut, len - cut));
+                }
+                if cut == 0 { continue; }
+                let new_len = cut as u16 + if uninit { 32768 } else { 0 };
+                node[base + 4..base + 6].copy_from_slice(&new_len.to_le_bytes());
+            } else {
+                let child = rd32(node, base + 4);
+                if lblk >= keep {
+                    trees.push((child, depth - 1));
+                    continue;
+                }
+                let bs = self.block_size as usize;
+                let mut buf = [0u8; 4096];
+                self.read_block_into(child, &mut buf[..bs])?;
+                self.ext4_trim_extent_node(&mut buf[..bs], keep, runs, trees)?;
+                self.write_block_data(child, &buf[..bs])?;
+                self.ext3_journal_current_block(child)?;
+            }
+            kept += 1;
+        }
+        node[2..4].copy_from_slice(&kept.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn ext4_free_detached(&mut self, runs: &[(u32, u32)], trees: &[(u32, u16)]) -> Result<u32, FsError> {
+        let mut freed = 0u32;
+        for &(start, len) in runs {
+            for b in 0..len {
+                let _ = self.free_block(start + b);
+            }
+            freed += len;
+        }
+        for &(child, depth) in trees {
+            freed += self.ext4_free_extent_tree_block(child, depth)? + 1;
+            let _ = self.free_block(child);
+        }
+        Ok(freed)
+    }
+
     fn ext4_free_extent_tree_block(&mut self, block_num: u32, depth: u16) -> Result<u32, FsError> {
         let bs = self.block_size as usize;
         let mut buf = [0u8; 4096];
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use crate::miku_extfs::structs::*;
 use crate::miku_extfs::{FsError, MikuFS};
 
@@ -174,6 +175,26 @@ impl MikuFS {
         Ok(())
     }
 
+    pub fn ext4_shrink(&mut self, inode_num: u32, new_size: u64) -> Result<(), FsError> {
+        let mut inode = self.read_inode(inode_num)?;
+        let keep = new_size.div_ceil(self.block_size as u64) as u32;
+        let (mut runs, mut trees) = (Vec::new(), Vec::new());
+        self.zero_tail(&inode, new_size)?;
+        if !inode.has_inline_data() && inode.extent_header().valid() {
+            let mut root = [0u8; 60];
+            root.copy_from_slice(&inode.data[40..100]);
+            self.ext4_trim_extent_node(&mut root, keep, &mut runs, &mut trees)?;
+            inode.data[40..100].copy_from_slice(&root);
+        }
+        inode.set_size_full(new_size);
+        inode.set_mtime(self.get_timestamp());
+        self.write_inode(inode_num, &inode)?;
+
+        let freed = self.ext4_free_detached(&runs, &trees)?;
+        inode.set_blocks(inode.blocks().saturating_sub(freed * (self.block_size / 512)));
+        self.write_inode(inode_num, &inode)
+    }
+
     pub fn ext4_delete_file(&mut self, parent_ino: u32, name: &str) -> Result<(), FsError> {
         let target_ino = match self.ext2_lookup_in_dir(parent_ino, name)? {
             Some(ino) => ino,
@@ -6,9 +6,12 @@ pub mod ext4;
 pub mod reader;
 pub mod structs;
 
+extern crate alloc;
+use alloc::boxed::Box;
 use ext3::journal::TxnTag;
 use reader::DiskReader;
 use structs::*;
+use crate::vfs::block_dev::BlockDevice;
 
 pub use error::FsError;
 
@@ -43,6 +46,79 @@ pub const MAX_DIR_ENTRIES: usize = 64;
 const SYNC_INTERVAL_TICKS: u64 = 90;
 
 impl MikuFS {
+    pub const EMPTY: Self = Self {
+        superblock:       Superblock { data: [0; 1024] },
+        block_size:       0,
+        inodes_per_group: 0,
+        blocks_per_group: 0,
+        group_count:      0,
+        groups:           [GroupDesc { data: [0; 64] }; 32],
+        reader:           DiskReader::EMPTY,
+        journal_seq:      0,
+        journal_pos:      0,
+        journal_maxlen:   0,
+        journal_first:    0,
+        journal_active:   false,
+        txn_active:       false,
+        txn_desc_pos:     0,
+        txn_tags:         [TxnTag { fs_block: 0, journal_pos: 0 }; 64],
+        txn_tag_count:    0,
+        txn_revokes:      [0; 128],
+        txn_revoke_count: 0,
+        block_cache:      None,
+        superblock_dirty: false,
+        groups_dirty:     [false; 32],
+        last_sync_ticks:  0,
+        journal_inode_cached: None,
+        alloc_hint: [0u32; 32],
+    };
+
+    pub fn mount(&mut self, dev: Box<dyn BlockDevice>) -> Result<(), FsError> {
+        *self = Self::EMPTY;
+        self.reader = DiskReader::new(dev);
+
+        let sb = self.reader.read_superblock()?;
+        if sb.magic() != EXT2_MAGIC {
+            return Err(FsError::BadMagic);
+        }
+
+        let block_size       = sb.block_size();
+        let blocks_per_group = sb.blocks_per_group();
+        let usable           = sb.blocks_count().saturating_sub(sb.first_data_block());
+        let group_count      = if blocks_per_group == 0 { 0 }
+            else { (usable + blocks_per_group - 1) / blocks_per_group };
+        if group_count as usize > self.groups.len() {
+            return Err(FsError::TooManyGroups);
+        }
+
+        let gdt_block = if block_size == 1024 { 2 } else { 1 };
+        let gd_size   = sb.group_desc_size() as usize;
+        self.reader.read_group_descriptors(
+            gdt_block, block_size, group_count as usize, gd_size, &mut self.groups,
+        )?;
+
+        self.inodes_per_group = sb.inodes_per_group();
+        self.blocks_per_group = blocks_per_group;
+        self.block_size       = block_size;
+        self.group_count      = group_count;
+        self.superblock       = sb;
+
+        self.init_cache();
+        let _ = self.init_journal();
+        let _ = self.warm_cache();
+
+        if self.journal_active
+            && !self.read_journal_superblock().map(|j| j.is_clean()).unwrap_or(true)
+        {
+            match self.ext3_recover() {
+                Ok(0) => {}
+                Ok(n) => crate::serial_println!("[ext3] recovery: replayed {} blocks", n),
+                Err(e) => crate::serial_println!("[ext3] recovery failed: {:?}", e),
+            }
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn inode_size(&self) -> u32 {
         self.superblock.inode_size_val()
@@ -173,6 +249,9 @@ impl MikuFS {
         sector[offset_in_sector..offset_in_sector + write_len]
             .copy_from_slice(&self.groups[group].data[..write_len]);
         self.reader.write_sector(lba, &sector)?;
+        if let Some(ref mut c) = self.block_cache {
+            c.invalidate(gdt_block + (gd_byte_offset / self.block_size as usize) as u32);
+        }
         self.groups_dirty[group] = false;
         Ok(())
     }
@@ -1,55 +1,59 @@
+extern crate alloc;
+use alloc::boxed::Box;
 use super::structs::*;
 use super::FsError;
-use crate::ata::AtaDrive;
+use crate::vfs::block_dev::BlockDevice;
 
 pub struct DiskReader {
-    pub drive:     AtaDrive,
-    pub start_lba: u32,
-    pub io_count:  u32,
+    pub dev:      Option<Box<dyn BlockDevice>>,
+    pub io_count: u32,
 }
 
 impl DiskReader {
-    pub fn new(drive: AtaDrive) -> Self {
-        Self { drive, start_lba: 0, io_count: 0 }
+    pub const EMPTY: Self = Self { dev: None, io_count: 0 };
+
+    pub fn new(dev: Box<dyn BlockDevice>) -> Self {
+        Self { dev: Some(dev), io_count: 0 }
     }
 
-    pub fn new_partitioned(drive: AtaDrive, start_lba: u32) -> Self {
-        Self { drive, start_lba, io_count: 0 }
+    fn device(&mut self) -> Result<&mut dyn BlockDevice, FsError> {
+        self.io_count += 1;
+        match self.dev.as_deref_mut() {
+            Some(dev) => Ok(dev),
+            None      => Err(FsError::IoError),
+        }
     }
 
     pub fn reset_io(&mut self) { self.io_count = 0; }
 
     pub fn read_sector(&mut self, lba: u32, buf: &mut [u8; 512]) -> Result<(), FsError> {
-        self.io_count += 1;
-        self.drive.read_sector(self.start_lba + lba, buf)
+        self.device()?.read(lba as u64, 1, buf)
             .map_err(|_| FsError::IoError)
     }
 
     pub fn write_sector(&mut self, lba: u32, buf: &[u8; 512]) -> Result<(), FsError> {
-        self.io_count += 1;
-        self.drive.write_sector(self.start_lba + lba, buf)
+        self.device()?.write(lba as u64, 1, buf)
             .map_err(|_| FsError::IoError)
     }
 
     pub fn read_block(
         &mut self, lba: u32, buf: &mut [u8], sectors: u8,
     ) -> Result<(), FsError> {
-        self.io_count += 1;
-        self.drive.read_sectors(self.start_lba + lba, buf, sectors)
+        self.device()?.read(lba as u64, sectors as u32, buf)
             .map_err(|_| FsError::IoError)
     }
 
     pub fn write_block(
         &mut self, lba: u32, buf: &[u8], sectors: u8,
     ) -> Result<(), FsError> {
-        self.io_count += 1;
-        self.drive.write_sectors(self.start_lba + lba, buf, sectors)
+        self.device()?.write(lba as u64, sectors as u32, buf)
             .map_err(|_| FsError::IoError)
     }
 
     pub fn flush_drive(&mut self) {
-        self.io_count += 1;
-        let _ = self.drive.flush();
+        if let Ok(dev) = self.device() {
+            let _ = dev.flush();
+        }
     }
 
     pub fn read_superblock(&mut self) -> Result<Superblock, FsError> {
@@ -1,4 +1,4 @@
-use crate::ata::{AtaDrive, AtaError};
+use crate::vfs::block_dev::{self, BlockDevice, BlockError};
 use super::layout::{FsLayout, group_has_sb};
 use super::params::{FsType, MkfsParams};
 use crate::miku_extfs::structs::*;
@@ -6,14 +6,14 @@ use crate::miku_extfs::ext3::journal::{JBD_MAGIC, JBD_SUPERBLOCK_V2};
 
 #[derive(Debug)]
 pub enum MkfsError {
-    Io(AtaError),
+    Io(BlockError),
     DiskTooSmall,
     TooManyGroups,
     InvalidParams(&'static str),
 }
 
-impl From<AtaError> for MkfsError {
-    fn from(e: AtaError) -> Self { MkfsError::Io(e) }
+impl From<BlockError> for MkfsError {
+    fn from(e: BlockError) -> Self { MkfsError::Io(e) }
 }
 
 pub struct MkfsReport {
@@ -28,14 +28,18 @@ pub struct MkfsReport {
     pub free_inodes:    u32,
 }
 
-struct Writer {
-    drive:     AtaDrive,
+struct Writer<'a> {
+    dev:       &'a mut dyn BlockDevice,
     start_lba: u32,
 }
 
-impl Writer {
+impl Writer<'_> {
+    fn read_sector(&mut self, lba: u32, buf: &mut [u8; 512]) -> Result<(), MkfsError> {
+        self.dev.read((self.start_lba + lba) as u64, 1, buf).map_err(MkfsError::Io)
+    }
+
     fn write_sector(&mut self, lba: u32, buf: &[u8; 512]) -> Result<(), MkfsError> {
-        self.drive.write_sector(self.start_lba + lba, buf).map_err(MkfsError::Io)
+        self.dev.write((self.start_lba + lba) as u64, 1, buf).map_err(MkfsError::Io)
     }
 
     fn zero_sector(&mut self, lba: u32) -> Result<(), MkfsError> {
@@ -57,8 +61,8 @@ impl Writer {
         }
         let spb      = block_size / 512;
         let base_lba = block * spb;
-        self.drive
-            .write_sectors(self.start_lba + base_lba, &data[..block_size as usize], spb as u8)
+        self.dev
+            .write((self.start_lba + base_lba) as u64, spb, &data[..block_size as usize])
             .map_err(MkfsError::Io)
     }
 
@@ -76,35 +80,17 @@ impl Writer {
         static ZEROS: [u8; 255 * 512] = [0u8; 255 * 512];
         let mut done = 0u32;
         while done < count {
-            let n = (count - done).min(CHUNK) as u8;
-            self.drive
-                .write_sectors(self.start_lba + start_lba + done, &ZEROS[..n as usize * 512], n)
+            let n = (count - done).min(CHUNK);
+            self.dev
+                .write((self.start_lba + start_lba + done) as u64, n, &ZEROS[..n as usize * 512])
                 .map_err(MkfsError::Io)?;
-            done += n as u32;
+            done += n;
         }
         Ok(())
     }
 
     fn probe_sectors(&mut self) -> u32 {
-        let mut buf = [0u8; 512];
-        match self.drive.read_sector(0, &mut buf) {
-            Err(crate::ata::AtaError::NoDevice)  => return 0,
-            Err(crate::ata::AtaError::Timeout)   => return 0,
-            Err(_) => return 0,
-            Ok(_)  => {}
-        }
-        let mut lo: u32 = 1;
-        let mut hi: u32 = u32::MAX / 2;
-        while hi > lo && self.drive.read_sector(hi - 1, &mut buf).is_err() {
-            hi /= 2;
-            if hi == 0 { return lo; }
-        }
-        while lo + 1 < hi {
-            let mid = lo + (hi - lo) / 2;
-            if self.drive.read_sector(mid, &mut buf).is_ok() { lo = mid; }
-            else { hi = mid; }
-        }
-        lo + 1
+        block_dev::probe_sectors(self.dev).min(u32::MAX as u64) as u32
     }
 }
 
@@ -157,7 +143,7 @@ fn bitmap_mark_unused_tail(buf: &mut [u8], first_invalid: u32, block_size: u32)
     }
 }
 
-pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsError> {
+pub fn mkfs(dev: &mut dyn BlockDevice, params: &MkfsParams) -> Result<MkfsReport, MkfsError> {
     if params.block_size != 1024 && params.block_size != 4096 {
         return Err(MkfsError::InvalidParams("block_size must be 1024 or 4096"));
     }
@@ -173,7 +159,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
         return Err(MkfsError::InvalidParams("journal_blocks must be >= 16"));
     }
 
-    let mut w = Writer { drive, start_lba: params.start_lba };
+    let mut w = Writer { dev, start_lba: params.start_lba };
 
     let total_sectors = if params.total_sectors > 0 {
         params.total_sectors
@@ -191,7 +177,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
     crate::serial_println!("[mkfs] step 1: zeroing old SB at LBA 2-3 (base={})", params.start_lba);
     w.zero_sector(2)?;
     w.zero_sector(3)?;
-    w.drive.flush().map_err(MkfsError::Io)?;
+    w.dev.flush().map_err(MkfsError::Io)?;
 
     let lay = FsLayout::compute(params, total_sectors);
     if lay.group_count == 0 || lay.total_blocks < 64 {
@@ -235,11 +221,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
     let root_blk = g0.data_start;
     let (j_first, j_last_plus_one) = if lay.journal_blocks > 0 {
         let first = root_blk + 1;
-        let mut last_plus_one = first + lay.journal_blocks;
-        if lay.journal_blocks > 12 {
-            last_plus_one += 1;
-        }
-        (first, last_plus_one)
+        (first, first + lay.journal_blocks + lay.journal_map_blocks)
     } else {
         (root_blk + 1, root_blk + 1)
     };
@@ -318,7 +300,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
 
         let mut raw = [0u8; 256];
         let j_size     = jblks * lay.block_size;
-        let j_blks_val = jblks * (lay.block_size / 512);
+        let j_blks_val = (jblks + lay.journal_map_blocks) * (lay.block_size / 512);
         wu16(&mut raw, 0,  S_IFREG | 0o600);
         wu32(&mut raw, 4,  j_size);
         wu32(&mut raw, 8,  now); wu32(&mut raw, 12, now); wu32(&mut raw, 16, now);
@@ -326,19 +308,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
         wu32(&mut raw, 28, j_blks_val);
         wu32(&mut raw, 32, 0);
 
-        let direct = jblks.min(12) as usize;
-        for i in 0..direct {
-            wu32(&mut raw, 40 + i * 4, j_first + i as u32);
-        }
-        if jblks > 12 {
-            let ind_blk = j_first + jblks;
-            let mut ind = [0u8; 4096];
-            for i in 12..jblks as usize {
-                wu32(&mut ind, (i - 12) * 4, j_first + i as u32);
-            }
-            w.write_block(ind_blk, &ind[..bs], lay.block_size, tb)?;
-            wu32(&mut raw, 40 + 12 * 4, ind_blk);
-        }
+        write_block_map(&mut w, &lay, &mut raw, j_first, jblks, tb)?;
         write_raw_inode(&mut w, &lay, EXT2_JOURNAL_INO, &raw, tb)?;
     }
 
@@ -363,10 +333,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
 
         let g0_extra_used = {
             let mut n = 1u32;
-            if lay.journal_blocks > 0 {
-                n += lay.journal_blocks;
-                if lay.journal_blocks > 12 { n += 1; }
-            }
+            n += lay.journal_blocks + lay.journal_map_blocks;
             if lf_blk < tb { n += 1; }
             n
         };
@@ -406,10 +373,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
 
     let g0_extra_used = {
         let mut n = 1u32;
-        if lay.journal_blocks > 0 {
-            n += lay.journal_blocks;
-            if lay.journal_blocks > 12 { n += 1; }
-        }
+        n += lay.journal_blocks + lay.journal_map_blocks;
         if lf_blk < tb { n += 1; }
         n
     };
@@ -455,7 +419,6 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
     sb[136] = b'/';
 
     if lay.journal_blocks > 0 {
-        sb[208..224].copy_from_slice(&uuid);
         wu32(&mut sb, 224, EXT2_JOURNAL_INO);
         wu32(&mut sb, 228, 0);
     }
@@ -491,7 +454,7 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
         }
     }
 
-    w.drive.flush().map_err(MkfsError::Io)?;
+    w.dev.flush().map_err(MkfsError::Io)?;
 
     crate::serial_println!(
         "[mkfs] done: {} blks {} ino {} grps jblks={} start_lba={}",
@@ -511,6 +474,57 @@ pub fn mkfs(drive: AtaDrive, params: &MkfsParams) -> Result<MkfsReport, MkfsErro
     })
 }
 
+fn write_block_map(
+    w: &mut Writer,
+    lay: &FsLayout,
+    raw: &mut [u8; 256],
+    first: u32,
+    count: u32,
+    tb: u32,
+) -> Result<(), MkfsError> {
+    let bs   = lay.block_size as usize;
+    let ptrs = lay.block_size / 4;
+    let mut meta = first + count;
+
+    for i in 0..count.min(12) {
+        wu32(raw, 40 + i as usize * 4, first + i);
+    }
+
+    let mut next = 12u32;
+    let mut ind  = [0u8; 4096];
+    let mut write_ind = |w: &mut Writer, blk: u32, next: &mut u32| -> Result<(), MkfsError> {
+        ind.fill(0);
+        let n = (count - *next).min(ptrs);
+        for k in 0..n {
+            wu32(&mut ind, k as usize * 4, first + *next + k);
+        }
+        *next += n;
+        w.write_block(blk, &ind[..bs], lay.block_size, tb)
+    };
+
+    if next < count {
+        write_ind(w, meta, &mut next)?;
+        wu32(raw, 40 + 12 * 4, meta);
+        meta += 1;
+    }
+
+    if next < count {
+        let dind_blk = meta;
+        meta += 1;
+        let mut dind = [0u8; 4096];
+        let mut k = 0usize;
+        while next < count {
+            write_ind(w, meta, &mut next)?;
+            wu32(&mut dind, k * 4, meta);
+            meta += 1;
+            k    += 1;
+        }
+        w.write_block(dind_blk, &dind[..bs], lay.block_size, tb)?;
+        wu32(raw, 40 + 13 * 4, dind_blk);
+    }
+    Ok(())
+}
+
 fn build_dir_inode(
     _ino:    u32,
     nlinks:  u16,
@@ -574,7 +588,7 @@ fn write_raw_inode(
     while rem > 0 {
         let chunk = (512 - cur_off).min(rem);
         let mut sec = [0u8; 512];
-        w.drive.read_sector(w.start_lba + cur_lba, &mut sec).map_err(MkfsError::Io)?;
+        w.read_sector(cur_lba, &mut sec)?;
         sec[cur_off..cur_off + chunk].copy_from_slice(&raw[dpos..dpos + chunk]);
         w.write_sector(cur_lba, &sec)?;
         dpos    += chunk;
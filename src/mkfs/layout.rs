@@ -15,6 +15,7 @@ pub struct FsLayout {
     pub total_inodes:       u32,
     pub reserved_blocks:    u32,
     pub journal_blocks:     u32,
+    pub journal_map_blocks: u32,
     pub groups:             [GroupLayout; 32],
 }
 
@@ -38,6 +39,15 @@ fn is_power_of(mut n: u32, base: u32) -> bool {
     n == 1
 }
 
+pub fn map_blocks(data_blocks: u32, block_size: u32) -> u32 {
+    let ptrs = block_size / 4;
+    let rest = data_blocks.saturating_sub(12);
+    if rest == 0 { return 0; }
+    let rest = rest.saturating_sub(ptrs);
+    if rest == 0 { return 1; }
+    2 + (rest + ptrs - 1) / ptrs
+}
+
 pub fn group_has_sb(group: u32) -> bool {
     group == 0
         || group == 1
@@ -86,11 +96,15 @@ impl FsLayout {
         let g0_real     = total_blocks.saturating_sub(first_data_block)
             .min(blocks_per_group);
         let g0_for_journal = g0_real.saturating_sub(g0_overhead + 2 + 4);
-        let journal_blocks = if params.fs_type.needs_journal() {
+        let mut journal_blocks = if params.fs_type.needs_journal() {
             params.journal_blocks.min(g0_for_journal)
         } else {
             0
         };
+        while journal_blocks > 0 && journal_blocks + map_blocks(journal_blocks, block_size) > g0_for_journal {
+            journal_blocks -= 1;
+        }
+        let journal_map_blocks = map_blocks(journal_blocks, block_size);
 
         crate::serial_println!(
             "[mkfs] disk={} blks, bs={}, groups={}, ino/g={}, jblks={}",
@@ -150,6 +164,7 @@ impl FsLayout {
             total_inodes,
             reserved_blocks,
             journal_blocks,
+            journal_map_blocks,
             groups,
         }
     }
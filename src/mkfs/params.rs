@@ -36,8 +36,8 @@ impl MkfsParams {
     pub fn new(fs_type: FsType, drive_index: usize) -> Self {
         let (block_size, inode_size, journal_blocks) = match fs_type {
             FsType::Ext2 => (1024, 128, 0),
-            FsType::Ext3 => (1024, 128, 128),
-            FsType::Ext4 => (4096, 256, 256),
+            FsType::Ext3 => (1024, 128, 1024),
+            FsType::Ext4 => (4096, 256, 1024),
         };
         Self {
             fs_type,
@@ -25,6 +25,7 @@ impl Vma {
     const fn empty() -> Self { Self { start: 0, end: 0, prot: 0, active: false } }
 }
 
+#[derive(Clone)]
 pub struct VmaMap {
     vmas:      [Vma; MAX_VMAS],
     count:     usize,
@@ -93,6 +94,13 @@ pub fn kernel_register_vma(cr3: u64, start: u64, end: u64, prot: u32) -> bool {
 
 pub fn vma_cleanup(cr3: u64) { VMA_MAP.lock().remove(&cr3); }
 
+pub fn vma_fork(parent_cr3: u64, child_cr3: u64) {
+    let mut map = VMA_MAP.lock();
+    if let Some(m) = map.get(&parent_cr3).cloned() {
+        map.insert(child_cr3, m);
+    }
+}
+
 fn prot_to_flags(prot: u32) -> PageTableFlags {
     let mut f = PageTableFlags::USER_ACCESSIBLE;
     if prot & PROT_WRITE != 0 { f |= PageTableFlags::WRITABLE; }
@@ -169,7 +177,17 @@ pub fn sys_mprotect(cr3: u64, addr: u64, length: u64, prot: u32) -> i64 {
     let a = AddressSpace::from_raw(cr3);
     let mut p = addr;
     while p < addr + size {
-        if let Some(phys) = a.virt_to_phys(p) { a.unmap_page_no_free(p); a.map_page(p, phys, flags); }
+        if let Some(phys) = a.virt_to_phys(p) {
+            let shared = pmm::is_shared(phys);
+            let mut f  = flags;
+            if shared && f.contains(PageTableFlags::WRITABLE) {
+                f.remove(PageTableFlags::WRITABLE);
+                f |= crate::vmm::PTE_COW;
+            }
+            a.unmap_page_no_free(p);
+            a.map_page(p, phys, f);
+            if shared { crate::swap_map::set_pinned(phys, true); }
+        }
         p += PAGE_SIZE;
     }
     let _ = a.into_raw();
@@ -1,29 +1,136 @@
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
 use super::eth::{EthFrame, BROADCAST_MAC, ETHERTYPE_IP};
+use super::ip::IpAddr;
 use super::ipv4;
+use super::tcp;
 use super::udp;
+use crate::interrupts::PIT_HZ;
 
 const DHCP_DISCOVER: u8 = 1;
-const DHCP_OFFER: u8 = 2;
-const DHCP_REQUEST: u8 = 3;
-const DHCP_ACK: u8 = 5;
+const DHCP_OFFER:    u8 = 2;
+const DHCP_REQUEST:  u8 = 3;
+const DHCP_ACK:      u8 = 5;
+const DHCP_NAK:      u8 = 6;
+const DHCP_RELEASE:  u8 = 7;
 
 const DHCP_MAGIC: [u8; 4] = [99, 130, 83, 99];
 
-const OPT_SUBNET: u8 = 1;
-const OPT_ROUTER: u8 = 3;
-const OPT_DNS: u8 = 6;
-const OPT_LEASE: u8 = 51;
-const OPT_MSG_TYPE: u8 = 53;
-const OPT_SERVER: u8 = 54;
-const OPT_PARAM_REQ: u8 = 55;
-const OPT_END: u8 = 255;
+const OPT_SUBNET:          u8 = 1;
+const OPT_ROUTER:          u8 = 3;
+const OPT_DNS:             u8 = 6;
+const OPT_DOMAIN:          u8 = 15;
+const OPT_MTU:             u8 = 26;
+const OPT_STATIC_ROUTE:    u8 = 33;
+const OPT_NTP:             u8 = 42;
+const OPT_REQUESTED_IP:    u8 = 50;
+const OPT_LEASE:           u8 = 51;
+const OPT_MSG_TYPE:        u8 = 53;
+const OPT_SERVER:          u8 = 54;
+const OPT_PARAM_REQ:       u8 = 55;
+const OPT_RENEWAL:         u8 = 58;
+const OPT_REBINDING:       u8 = 59;
+const OPT_CLIENT_ID:       u8 = 61;
+const OPT_CLASSLESS_ROUTE: u8 = 121;
+const OPT_END:             u8 = 255;
+
+const PARAMS: [u8; 11] = [
+    OPT_SUBNET, OPT_ROUTER, OPT_DNS, OPT_DOMAIN, OPT_MTU, OPT_NTP,
+    OPT_LEASE, OPT_RENEWAL, OPT_REBINDING, OPT_CLASSLESS_ROUTE, OPT_STATIC_ROUTE,
+];
+
+const CLIENT_PORT:   u16   = 68;
+const SERVER_PORT:   u16   = 67;
+const REPLY_TICKS:   u64   = PIT_HZ as u64;
+const ATTEMPTS:      u32   = 4;
+const MIN_RETRY:     u64   = 60;
+const DEFAULT_LEASE: u32   = 3600;
+const INFINITE:      u32   = u32::MAX;
+const ZERO:          [u8; 4] = [0; 4];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LeaseState {
+    Init,
+    Bound,
+    Renewing,
+    Rebinding,
+    Released,
+}
+
+impl LeaseState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeaseState::Init      => "INIT",
+            LeaseState::Bound     => "BOUND",
+            LeaseState::Renewing  => "RENEWING",
+            LeaseState::Rebinding => "REBINDING",
+            LeaseState::Released  => "RELEASED",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Route {
+    pub dest:   [u8; 4],
+    pub prefix: u8,
+    pub gw:     [u8; 4],
+}
 
+#[derive(Clone, Debug)]
 pub struct DhcpResult {
-    pub ip: [u8; 4],
-    pub mask: [u8; 4],
-    pub gw: [u8; 4],
-    pub dns: [u8; 4],
-    pub server: [u8; 4],
+    pub msg_type: u8,
+    pub ip:       [u8; 4],
+    pub mask:     [u8; 4],
+    pub gw:       [u8; 4],
+    pub routers:  Vec<[u8; 4]>,
+    pub dns:      Vec<[u8; 4]>,
+    pub domain:   Option<String>,
+    pub mtu:      Option<u16>,
+    pub ntp:      Vec<[u8; 4]>,
+    pub routes:   Vec<Route>,
+    pub server:   [u8; 4],
+    pub lease:    u32,
+    pub t1:       u32,
+    pub t2:       u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lease {
+    pub state:    LeaseState,
+    pub info:     DhcpResult,
+    pub acquired: u64,
+}
+
+impl Lease {
+    fn deadline(&self, secs: u32) -> u64 {
+        if secs == INFINITE { u64::MAX } else { self.acquired + secs as u64 * PIT_HZ as u64 }
+    }
+
+    pub fn renew_at(&self) -> u64   { self.deadline(self.info.t1) }
+    pub fn rebind_at(&self) -> u64  { self.deadline(self.info.t2) }
+    pub fn expires_at(&self) -> u64 { self.deadline(self.info.lease) }
+}
+
+static LEASE:   Mutex<Option<Lease>> = Mutex::new(None);
+static CLIENT:  AtomicBool = AtomicBool::new(false);
+static RENEW:   AtomicBool = AtomicBool::new(false);
+
+fn ticks() -> u64 {
+    crate::vfs::procfs::uptime_ticks()
+}
+
+pub fn lease() -> Option<Lease> {
+    LEASE.lock().clone()
+}
+
+fn set_state(state: LeaseState) {
+    if let Some(l) = LEASE.lock().as_mut() {
+        l.state = state;
+    }
 }
 
 fn build_packet(
@@ -31,78 +138,106 @@ fn build_packet(
     xid: u32,
     mac: &[u8; 6],
     ciaddr: &[u8; 4],
-    server_ip: Option<&[u8; 4]>,
+    opts: &[(u8, &[u8])],
     out: &mut [u8; 548],
 ) -> usize {
+    out.fill(0);
     out[0] = 1;
     out[1] = 1;
     out[2] = 6;
-    out[3] = 0;
-    out[4] = (xid >> 24) as u8;
-    out[5] = (xid >> 16) as u8;
-    out[6] = (xid >> 8) as u8;
-    out[7] = xid as u8;
-    out[8..12].fill(0);
+    out[4..8].copy_from_slice(&xid.to_be_bytes());
+    if *ciaddr == ZERO {
+        out[10] = 0x80;
+    }
     out[12..16].copy_from_slice(ciaddr);
-    out[16..20].fill(0);
-    out[20..24].fill(0);
-    out[24..28].fill(0);
     out[28..34].copy_from_slice(mac);
-    out[34..236].fill(0);
     out[236..240].copy_from_slice(&DHCP_MAGIC);
 
     let mut pos = 240usize;
-
-    out[pos] = OPT_MSG_TYPE;
-    out[pos + 1] = 1;
-    out[pos + 2] = msg_type;
-    pos += 3;
-
-    if let Some(srv) = server_ip {
-        out[pos] = OPT_SERVER;
-        out[pos + 1] = 4;
-        out[pos + 2..pos + 6].copy_from_slice(srv);
-        pos += 6;
+    let mut put = |kind: u8, data: &[u8]| {
+        out[pos] = kind;
+        out[pos + 1] = data.len() as u8;
+        out[pos + 2..pos + 2 + data.len()].copy_from_slice(data);
+        pos += 2 + data.len();
+    };
+
+    put(OPT_MSG_TYPE, &[msg_type]);
+    put(OPT_CLIENT_ID, &[1, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]]);
+    for &(kind, data) in opts {
+        put(kind, data);
+    }
+    if msg_type != DHCP_RELEASE {
+        put(OPT_PARAM_REQ, &PARAMS);
     }
-
-    out[pos] = OPT_PARAM_REQ;
-    out[pos + 1] = 3;
-    out[pos + 2] = OPT_SUBNET;
-    out[pos + 3] = OPT_ROUTER;
-    out[pos + 4] = OPT_DNS;
-    pos += 5;
 
     out[pos] = OPT_END;
-    pos += 1;
+    (pos + 1).max(300)
+}
 
-    while pos < 300 {
-        out[pos] = 0;
-        pos += 1;
-    }
+fn quads(data: &[u8]) -> Vec<[u8; 4]> {
+    data.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]).collect()
+}
 
-    300
+fn be32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
 }
 
-fn parse_offer(buf: &[u8], xid: u32) -> Option<DhcpResult> {
-    if buf.len() < 240 {
-        return None;
+fn classful_prefix(dest: &[u8; 4]) -> u8 {
+    match dest[0] {
+        0..=127   => 8,
+        128..=191 => 16,
+        _         => 24,
     }
-    let rx_xid = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
-    if rx_xid != xid {
+}
+
+fn parse_classless(data: &[u8]) -> Vec<Route> {
+    let mut routes = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let prefix = data[i];
+        if prefix > 32 {
+            break;
+        }
+        let n = (prefix as usize).div_ceil(8);
+        if i + 1 + n + 4 > data.len() {
+            break;
+        }
+        let mut dest = ZERO;
+        dest[..n].copy_from_slice(&data[i + 1..i + 1 + n]);
+        let mut gw = ZERO;
+        gw.copy_from_slice(&data[i + 1 + n..i + 5 + n]);
+        routes.push(Route { dest, prefix, gw });
+        i += 5 + n;
+    }
+    routes
+}
+
+fn parse_reply(buf: &[u8], xid: u32) -> Option<DhcpResult> {
+    if buf.len() < 240 || buf[0] != 2 {
         return None;
     }
-    if buf[236..240] != DHCP_MAGIC {
+    if be32(&buf[4..8]) != xid || buf[236..240] != DHCP_MAGIC {
         return None;
     }
 
-    let mut yiaddr = [0u8; 4];
-    yiaddr.copy_from_slice(&buf[16..20]);
-
-    let mut mask = [255u8, 255, 255, 0];
-    let mut gw = [0u8; 4];
-    let mut dns = [8u8, 8, 8, 8];
-    let mut server = [0u8; 4];
-    let mut msg_type = 0u8;
+    let mut r = DhcpResult {
+        msg_type: 0,
+        ip:       [buf[16], buf[17], buf[18], buf[19]],
+        mask:     [255, 255, 255, 0],
+        gw:       ZERO,
+        routers:  Vec::new(),
+        dns:      Vec::new(),
+        domain:   None,
+        mtu:      None,
+        ntp:      Vec::new(),
+        routes:   Vec::new(),
+        server:   ZERO,
+        lease:    DEFAULT_LEASE,
+        t1:       0,
+        t2:       0,
+    };
+    let mut classless = Vec::new();
+    let (mut t1, mut t2) = (None, None);
 
     let mut i = 240;
     while i < buf.len() {
@@ -123,123 +258,329 @@ fn parse_offer(buf: &[u8], xid: u32) -> Option<DhcpResult> {
         }
         let data = &buf[i + 2..i + 2 + len];
         match opt {
-            OPT_MSG_TYPE if len >= 1 => msg_type = data[0],
-            OPT_SUBNET if len >= 4 => mask.copy_from_slice(&data[..4]),
-            OPT_ROUTER if len >= 4 => gw.copy_from_slice(&data[..4]),
-            OPT_DNS if len >= 4 => dns.copy_from_slice(&data[..4]),
-            OPT_SERVER if len >= 4 => server.copy_from_slice(&data[..4]),
+            OPT_MSG_TYPE if len >= 1 => r.msg_type = data[0],
+            OPT_SUBNET if len >= 4   => r.mask.copy_from_slice(&data[..4]),
+            OPT_ROUTER               => r.routers = quads(data),
+            OPT_DNS                  => r.dns = quads(data),
+            OPT_NTP                  => r.ntp = quads(data),
+            OPT_DOMAIN               => {
+                let name = String::from_utf8_lossy(data);
+                r.domain = Some(String::from(name.trim_end_matches('\0')));
+            }
+            OPT_MTU if len >= 2 => {
+                let mtu = u16::from_be_bytes([data[0], data[1]]);
+                if mtu >= 68 {
+                    r.mtu = Some(mtu);
+                }
+            }
+            OPT_STATIC_ROUTE => {
+                for pair in data.chunks_exact(8) {
+                    let dest = [pair[0], pair[1], pair[2], pair[3]];
+                    r.routes.push(Route { dest, prefix: classful_prefix(&dest), gw: [pair[4], pair[5], pair[6], pair[7]] });
+                }
+            }
+            OPT_CLASSLESS_ROUTE  => classless = parse_classless(data),
+            OPT_SERVER if len >= 4    => r.server.copy_from_slice(&data[..4]),
+            OPT_LEASE if len >= 4     => r.lease = be32(data),
+            OPT_RENEWAL if len >= 4   => t1 = Some(be32(data)),
+            OPT_REBINDING if len >= 4 => t2 = Some(be32(data)),
             _ => {}
         }
         i += 2 + len;
     }
 
-    if msg_type != DHCP_OFFER && msg_type != DHCP_ACK {
+    if r.msg_type == 0 {
         return None;
     }
-
-    Some(DhcpResult { ip: yiaddr, mask, gw, dns, server })
+    if !classless.is_empty() {
+        r.routes  = classless;
+        r.routers = r.routes.iter().filter(|rt| rt.prefix == 0).map(|rt| rt.gw).collect();
+    }
+    r.gw = r.routers.first().copied().unwrap_or(ZERO);
+    if r.lease == INFINITE {
+        r.t1 = INFINITE;
+        r.t2 = INFINITE;
+    } else {
+        r.t2 = t2.filter(|&t| t < r.lease).unwrap_or((r.lease as u64 * 7 / 8) as u32);
+        r.t1 = t1.filter(|&t| t < r.t2).unwrap_or(r.lease / 2);
+    }
+    Some(r)
 }
 
-pub fn do_dhcp() -> Option<DhcpResult> {
-    let xid: u32 = 0xDEAD_BEEF;
-    let mac = super::get_mac();
-    let zero_ip = [0u8; 4];
-    let bcast_ip = [255u8; 4];
-    let src_ip = [0u8; 4];
-
-    let mut dhcp_buf = [0u8; 548];
-    let dhcp_len = build_packet(DHCP_DISCOVER, xid, &mac, &zero_ip, None, &mut dhcp_buf);
-
-    let offer = send_and_recv(
-        &src_ip, &bcast_ip, &mac, &BROADCAST_MAC,
-        &dhcp_buf[..dhcp_len], xid, 100,
-    )?;
-
-    let server = offer.server;
-    let _offered_ip = offer.ip;
+fn send(src_ip: &[u8; 4], dst: Option<&[u8; 4]>, payload: &[u8]) -> bool {
+    let dst_ip = dst.copied().unwrap_or([255; 4]);
+    let mut udp_buf = [0u8; 600];
+    let udp_len = udp::build(CLIENT_PORT, SERVER_PORT, payload, src_ip, &dst_ip, &mut udp_buf);
+    if udp_len == 0 {
+        return false;
+    }
+    if dst.is_some() {
+        return super::send_ip(&IpAddr::V4(*src_ip), &IpAddr::V4(dst_ip), ipv4::PROTO_UDP, &udp_buf[..udp_len]);
+    }
 
-    let dhcp_len = build_packet(DHCP_REQUEST, xid, &mac, &zero_ip, Some(&server), &mut dhcp_buf);
+    let mut ip_buf = [0u8; 650];
+    let ip_len = ipv4::build(src_ip, &dst_ip, ipv4::PROTO_UDP, &udp_buf[..udp_len], &mut ip_buf);
+    if ip_len == 0 {
+        return false;
+    }
 
-    let ack = send_and_recv(
-        &src_ip, &bcast_ip, &mac, &BROADCAST_MAC,
-        &dhcp_buf[..dhcp_len], xid, 100,
-    )?;
+    let mut eth_buf = [0u8; 700];
+    let eth_len = EthFrame::build(&BROADCAST_MAC, &super::get_mac(), ETHERTYPE_IP, &ip_buf[..ip_len], &mut eth_buf);
+    if eth_len == 0 {
+        return false;
+    }
 
-    Some(ack)
+    super::send_frame(&eth_buf[..eth_len])
 }
 
 fn send_and_recv(
     src_ip: &[u8; 4],
-    dst_ip: &[u8; 4],
-    src_mac: &[u8; 6],
-    dst_mac: &[u8; 6],
-    dhcp_payload: &[u8],
+    dst: Option<&[u8; 4]>,
+    payload: &[u8],
     xid: u32,
-    timeout_ticks: u64,
+    want: &[u8],
+    attempts: u32,
 ) -> Option<DhcpResult> {
-    let mut udp_buf = [0u8; 600];
-    let udp_len = udp::build(68, 67, dhcp_payload, src_ip, dst_ip, &mut udp_buf);
-    if udp_len == 0 {
-        return None;
+    x86_64::instructions::interrupts::enable();
+    let tap = super::RawTap::open();
+    for attempt in 0..attempts {
+        if !send(src_ip, dst, payload) {
+            return None;
+        }
+        let start = ticks();
+        while ticks().wrapping_sub(start) < REPLY_TICKS << attempt {
+            let mut found: Option<DhcpResult> = None;
+            tap.recv(&mut |raw| {
+                if found.is_some() { return; }
+                let frame = match EthFrame::parse(raw) { Some(f) => f, None => return };
+                if frame.ethertype != ETHERTYPE_IP { return; }
+                let ip = match ipv4::Ipv4Header::parse(frame.payload) { Some(h) => h, None => return };
+                if ip.proto != ipv4::PROTO_UDP { return; }
+                let payload = ip.payload(frame.payload);
+                if payload.len() < 8 { return; }
+                let dst_port = u16::from_be_bytes([payload[2], payload[3]]);
+                if dst_port != CLIENT_PORT { return; }
+                found = parse_reply(&payload[8..], xid).filter(|r| want.contains(&r.msg_type));
+            });
+            if found.is_some() {
+                return found;
+            }
+            crate::scheduler::yield_now();
+        }
     }
+    None
+}
 
-    let mut ip_buf = [0u8; 650];
-    let ip_len = ipv4::build(src_ip, dst_ip, ipv4::PROTO_UDP, &udp_buf[..udp_len], &mut ip_buf);
-    if ip_len == 0 {
-        return None;
+fn apply(info: &DhcpResult) {
+    super::set_ip(info.ip, ZERO, info.mask);
+    let mut routes = info.routes.clone();
+    if info.gw != ZERO && !routes.iter().any(|r| r.prefix == 0) {
+        routes.push(Route { dest: ZERO, prefix: 0, gw: info.gw });
+    }
+    super::set_dhcp_routes(&routes);
+    if let Some(mtu) = info.mtu {
+        super::set_mtu(mtu as usize);
+    }
+    if !info.dns.is_empty() {
+        super::set_dns_servers(info.dns.iter().map(|&d| IpAddr::V4(d)).collect());
     }
+}
 
-    let mut eth_buf = [0u8; 700];
-    let eth_len = EthFrame::build(dst_mac, src_mac, ETHERTYPE_IP, &ip_buf[..ip_len], &mut eth_buf);
-    if eth_len == 0 {
+fn unconfigure() {
+    let mask = lease().map(|l| l.info.mask).unwrap_or([255, 255, 255, 0]);
+    super::set_ip(ZERO, ZERO, mask);
+    super::set_dhcp_routes(&[]);
+}
+
+fn bind(info: DhcpResult) -> Lease {
+    apply(&info);
+    let lease = Lease { state: LeaseState::Bound, info, acquired: ticks() };
+    *LEASE.lock() = Some(lease.clone());
+    lease
+}
+
+pub fn acquire() -> Option<Lease> {
+    let xid = tcp::random_isn();
+    let mac = super::get_mac();
+    let mut buf = [0u8; 548];
+
+    let len = build_packet(DHCP_DISCOVER, xid, &mac, &ZERO, &[], &mut buf);
+    let offer = send_and_recv(&ZERO, None, &buf[..len], xid, &[DHCP_OFFER], ATTEMPTS)?;
+
+    let len = build_packet(DHCP_REQUEST, xid, &mac, &ZERO,
+        &[(OPT_REQUESTED_IP, &offer.ip), (OPT_SERVER, &offer.server)], &mut buf);
+    let ack = send_and_recv(&ZERO, None, &buf[..len], xid, &[DHCP_ACK, DHCP_NAK], ATTEMPTS)?;
+    if ack.msg_type != DHCP_ACK {
         return None;
     }
 
-    {
-        let mut state = super::NET.lock();
-        if let Some(drv) = state.driver.as_mut() {
-            drv.send(&eth_buf[..eth_len]);
-        }
+    let lease = bind(ack);
+    start_client();
+    Some(lease)
+}
+
+fn renew(lease: &Lease, state: LeaseState) -> Option<DhcpResult> {
+    let xid = tcp::random_isn();
+    let mut buf = [0u8; 548];
+    let len = build_packet(DHCP_REQUEST, xid, &super::get_mac(), &lease.info.ip, &[], &mut buf);
+    let server = lease.info.server;
+    let dst = if state == LeaseState::Renewing && server != ZERO { Some(&server) } else { None };
+    send_and_recv(&lease.info.ip, dst, &buf[..len], xid, &[DHCP_ACK, DHCP_NAK], 2)
+}
+
+pub fn release() {
+    let lease = match lease() {
+        Some(l) if matches!(l.state, LeaseState::Bound | LeaseState::Renewing | LeaseState::Rebinding) => l,
+        _ => return,
+    };
+    super::http::pool_flush();
+    let mut buf = [0u8; 548];
+    let len = build_packet(DHCP_RELEASE, tcp::random_isn(), &super::get_mac(), &lease.info.ip,
+        &[(OPT_SERVER, &lease.info.server)], &mut buf);
+    send(&lease.info.ip, Some(&lease.info.server), &buf[..len]);
+    set_state(LeaseState::Released);
+    unconfigure();
+}
+
+fn start_client() {
+    if !CLIENT.swap(true, Ordering::AcqRel) {
+        crate::scheduler::spawn_named(client_thread, "dhcpc", 2);
     }
+}
 
-    let start = crate::vfs::procfs::uptime_ticks();
+fn client_thread() -> ! {
     x86_64::instructions::interrupts::enable();
+    let mut retry_at = 0u64;
     loop {
-        let now = crate::vfs::procfs::uptime_ticks();
-        if now.wrapping_sub(start) > timeout_ticks {
-            return None;
+        crate::scheduler::sleep(PIT_HZ as u64);
+        let lease = match lease() {
+            Some(l) => l,
+            None => continue,
+        };
+        let now    = ticks();
+        let forced = RENEW.swap(false, Ordering::AcqRel);
+
+        match lease.state {
+            LeaseState::Released => {}
+            LeaseState::Init => {
+                if now >= retry_at && acquire().is_none() {
+                    retry_at = now + MIN_RETRY * PIT_HZ as u64;
+                }
+            }
+            _ if now >= lease.expires_at() => {
+                crate::serial_println!("[dhcp] lease on {} expired", IpAddr::V4(lease.info.ip));
+                set_state(LeaseState::Init);
+                unconfigure();
+                retry_at = 0;
+            }
+            _ => {
+                let next = if now >= lease.rebind_at() {
+                    LeaseState::Rebinding
+                } else if now >= lease.renew_at() || forced {
+                    LeaseState::Renewing
+                } else {
+                    continue;
+                };
+                if now < retry_at && !forced {
+                    continue;
+                }
+                set_state(next);
+                match renew(&lease, next) {
+                    Some(ack) if ack.msg_type == DHCP_ACK => {
+                        let l = bind(ack);
+                        crate::serial_println!("[dhcp] lease on {} renewed for {}s",
+                            IpAddr::V4(l.info.ip), l.info.lease);
+                        retry_at = 0;
+                    }
+                    Some(_) => {
+                        crate::serial_println!("[dhcp] server refused {}", IpAddr::V4(lease.info.ip));
+                        set_state(LeaseState::Init);
+                        unconfigure();
+                        retry_at = 0;
+                    }
+                    None => {
+                        let until = if next == LeaseState::Renewing { lease.rebind_at() } else { lease.expires_at() };
+                        retry_at = now + (until.saturating_sub(now) / 2).max(MIN_RETRY * PIT_HZ as u64);
+                    }
+                }
+            }
         }
+    }
+}
 
-        x86_64::instructions::hlt();
+fn fmt_secs(secs: u32) -> String {
+    if secs == INFINITE { String::from("infinite") } else { format!("{}s", secs) }
+}
 
-        let mut result: Option<DhcpResult> = None;
-        {
-            let mut state = super::NET.lock();
-            let mut found: Option<DhcpResult> = None;
-            if let Some(drv) = state.driver.as_mut() {
-                drv.recv(&mut |raw| {
-                    if found.is_some() { return; }
-                    if let Some(frame) = EthFrame::parse(raw) {
-                        if frame.ethertype != ETHERTYPE_IP { return; }
-                        if let Some(ip) = ipv4::Ipv4Header::parse(frame.payload) {
-                            if ip.proto != ipv4::PROTO_UDP { return; }
-                            let payload = ip.payload(frame.payload);
-                            if payload.len() < 8 { return; }
-                            let dst_port = u16::from_be_bytes([payload[2], payload[3]]);
-                            if dst_port != 68 { return; }
-                            let udp_payload = &payload[8..];
-                            if let Some(r) = parse_offer(udp_payload, xid) {
-                                found = Some(r);
-                            }
-                        }
-                    }
-                });
+fn fmt_list(ips: &[[u8; 4]]) -> String {
+    ips.iter().map(|&a| format!("{}", IpAddr::V4(a))).collect::<Vec<_>>().join(" ")
+}
+
+pub fn lease_text() -> String {
+    let l = match lease() {
+        Some(l) => l,
+        None => return String::from("state NONE\n"),
+    };
+    let now  = ticks();
+    let left = |at: u64| if at == u64::MAX { INFINITE } else { (at.saturating_sub(now) / PIT_HZ as u64) as u32 };
+    let i = &l.info;
+    let mut s = format!("state {}\nip {}\nmask {}\nserver {}\n",
+        l.state.as_str(), IpAddr::V4(i.ip), IpAddr::V4(i.mask), IpAddr::V4(i.server));
+    if !i.routers.is_empty() {
+        s += &format!("routers {}\n", fmt_list(&i.routers));
+    }
+    if !i.dns.is_empty() {
+        s += &format!("dns {}\n", fmt_list(&i.dns));
+    }
+    if let Some(d) = &i.domain {
+        s += &format!("domain {}\n", d);
+    }
+    if let Some(m) = i.mtu {
+        s += &format!("mtu {}\n", m);
+    }
+    if !i.ntp.is_empty() {
+        s += &format!("ntp {}\n", fmt_list(&i.ntp));
+    }
+    for r in &i.routes {
+        s += &format!("route {}/{} via {}\n", IpAddr::V4(r.dest), r.prefix, IpAddr::V4(r.gw));
+    }
+    s += &format!("lease {}\nrenew {}\nrebind {}\nexpires {}\n",
+        fmt_secs(i.lease), fmt_secs(left(l.renew_at())), fmt_secs(left(l.rebind_at())), fmt_secs(left(l.expires_at())));
+    s
+}
+
+pub fn cmd_dhcp(arg: &str) {
+    match arg {
+        "" => {
+            crate::print_info!("dhcp: sending discover...");
+            match acquire() {
+                Some(l) => crate::print_success!(
+                    "dhcp: ip={}  gw={}  mask={}  dns={}  lease={}",
+                    IpAddr::V4(l.info.ip), IpAddr::V4(l.info.gw), IpAddr::V4(l.info.mask),
+                    fmt_list(&l.info.dns), fmt_secs(l.info.lease),
+                ),
+                None => crate::print_error!("dhcp: no response (timeout)"),
             }
-            result = found;
         }
-
-        if let Some(r) = result {
-            return Some(r);
+        "status" => {
+            for line in lease_text().lines() {
+                crate::cprintln!(230, 240, 240, "  {}", line);
+            }
         }
+        "renew" => match lease() {
+            Some(l) if l.state != LeaseState::Released => {
+                RENEW.store(true, Ordering::Release);
+                crate::print_info!("dhcp: renewing {}", IpAddr::V4(l.info.ip));
+            }
+            _ => crate::print_error!("dhcp: no lease"),
+        },
+        "release" => match lease() {
+            Some(l) if l.state != LeaseState::Released => {
+                release();
+                crate::print_success!("dhcp: released {}", IpAddr::V4(l.info.ip));
+            }
+            _ => crate::print_error!("dhcp: no lease"),
+        },
+        _ => crate::println!("Usage: dhcp [status|renew|release]"),
     }
 }
@@ -1,185 +1,648 @@
-use super::eth::{EthFrame, ETHERTYPE_IP};
-use super::ipv4;
-use super::udp;
-use super::CTRL_C;
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 use core::sync::atomic::Ordering;
+use spin::Mutex;
+use super::ip::IpAddr;
+use super::socket::{Endpoint, Proto, Socket, AF_INET, AF_INET6};
+use super::tcp;
+use super::CTRL_C;
+use crate::interrupts::PIT_HZ;
+use crate::syscall::ECONNREFUSED;
 
-pub fn resolve(hostname: &str, dns_server: &[u8; 4]) -> Option<[u8; 4]> {
-    if hostname.is_empty() {
-        return None;
-    }
+pub const TYPE_A:     u16 = 1;
+pub const TYPE_NS:    u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA:   u16 = 6;
+pub const TYPE_PTR:   u16 = 12;
+pub const TYPE_MX:    u16 = 15;
+pub const TYPE_TXT:   u16 = 16;
+pub const TYPE_AAAA:  u16 = 28;
 
-    CTRL_C.store(false, Ordering::SeqCst);
+const CLASS_IN: u16 = 1;
+const PORT:     u16 = 53;
 
-    let our_ip = super::get_ip();
-    let our_mac = super::get_mac();
+const FLAG_QR: u16 = 0x8000;
+const FLAG_TC: u16 = 0x0200;
+const FLAG_RD: u16 = 0x0100;
 
-    let dst_mac = super::resolve_arp(dns_server, &our_ip, &our_mac)?;
+const RCODE_OK:       u16 = 0;
+const RCODE_NXDOMAIN: u16 = 3;
+const RCODE_REFUSED:  u16 = 5;
 
-    let mut query = [0u8; 512];
-    let qlen = build_query(hostname, &mut query)?;
+const UDP_TICKS:    u64   = 2 * PIT_HZ as u64;
+const TCP_TICKS:    u64   = 5 * PIT_HZ as u64;
+const RETRIES:      usize = 2;
+const MAX_CNAME:    usize = 8;
+const MAX_CACHE:    usize = 256;
+const MAX_TTL:      u32   = 86400;
+const NEGATIVE_TTL: u32   = 60;
+const MAX_MSG:      usize = 65535;
+const HOSTS_PATH:   &str  = "/etc/hosts";
 
-    let mut udp_buf = [0u8; 560];
-    let udp_len = udp::build(54321, 53, &query[..qlen], &our_ip, dns_server, &mut udp_buf);
-    if udp_len == 0 { return None; }
+#[derive(Clone, Debug, PartialEq)]
+pub enum RData {
+    A([u8; 4]),
+    Aaaa([u8; 16]),
+    Name(String),
+    Mx(u16, String),
+    Txt(Vec<String>),
+    Soa { mname: String, rname: String, serial: u32, minimum: u32 },
+    Raw(Vec<u8>),
+}
 
-    let mut ip_buf = [0u8; 600];
-    let ip_len = ipv4::build(&our_ip, dns_server, ipv4::PROTO_UDP, &udp_buf[..udp_len], &mut ip_buf);
-    if ip_len == 0 { return None; }
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub name:  String,
+    pub rtype: u16,
+    pub ttl:   u32,
+    pub data:  RData,
+}
 
-    let mut eth_buf = [0u8; 650];
-    let eth_len = EthFrame::build(&dst_mac, &our_mac, ETHERTYPE_IP, &ip_buf[..ip_len], &mut eth_buf);
-    if eth_len == 0 { return None; }
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DnsError {
+    NxDomain,
+    ServFail,
+    Refused,
+    Timeout,
+    Malformed,
+    CnameLoop,
+}
 
-    {
-        let mut state = super::NET.lock();
-        if let Some(drv) = state.driver.as_mut() {
-            drv.send(&eth_buf[..eth_len]);
+impl DnsError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnsError::NxDomain  => "NXDOMAIN",
+            DnsError::ServFail  => "SERVFAIL",
+            DnsError::Refused   => "REFUSED",
+            DnsError::Timeout   => "timed out",
+            DnsError::Malformed => "malformed response",
+            DnsError::CnameLoop => "CNAME chain too long",
         }
     }
+}
 
-    for _ in 0..3_000_000 {
-        if CTRL_C.load(Ordering::SeqCst) {
-            return None;
-        }
-
-        let mut raw: [[u8; 1520]; 4] = [[0; 1520]; 4];
-        let mut raw_lens = [0usize; 4];
-        let mut raw_n = 0usize;
-        {
-            let mut state = super::NET.lock();
-            if let Some(drv) = state.driver.as_mut() {
-                drv.recv(&mut |buf| {
-                    if raw_n < 4 {
-                        let l = buf.len().min(1520);
-                        raw[raw_n][..l].copy_from_slice(&buf[..l]);
-                        raw_lens[raw_n] = l;
-                        raw_n += 1;
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RData::A(a)       => write!(f, "{}", IpAddr::V4(*a)),
+            RData::Aaaa(a)    => write!(f, "{}", IpAddr::V6(*a)),
+            RData::Name(n)    => write!(f, "{}.", n),
+            RData::Mx(p, n)   => write!(f, "{} {}.", p, n),
+            RData::Txt(parts) => {
+                for (i, p) in parts.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
                     }
-                });
+                    write!(f, "\"{}\"", p)?;
+                }
+                Ok(())
             }
+            RData::Soa { mname, rname, serial, minimum } => write!(f, "{}. {}. {} {}", mname, rname, serial, minimum),
+            RData::Raw(d)     => write!(f, "\\# {}", d.len()),
         }
+    }
+}
 
-        for i in 0..raw_n {
-            let buf = &raw[i][..raw_lens[i]];
-            if let Some(frame) = EthFrame::parse(buf) {
-                if frame.ethertype == super::eth::ETHERTYPE_ARP {
-                    let mut state = super::NET.lock();
-                    let mc = state.mac; let ic = state.ip;
-                    let mut rep = [0u8; 64];
-                    let rlen = super::arp::handle(&frame, &mc, &ic, &mut state.arp, &mut rep);
-                    if rlen > 0 {
-                        let rc = rep;
-                        if let Some(drv) = state.driver.as_mut() { drv.send(&rc[..rlen]); }
-                    }
-                    continue;
-                }
-                if frame.ethertype != ETHERTYPE_IP { continue; }
-                if let Some(ip) = ipv4::Ipv4Header::parse(frame.payload) {
-                    if ip.proto != ipv4::PROTO_UDP { continue; }
-                    let payload = ip.payload(frame.payload);
-                    if payload.len() < 8 { continue; }
-                    let src_port = u16::from_be_bytes([payload[0], payload[1]]);
-                    if src_port != 53 { continue; }
-                    let udp_data = &payload[8..];
-                    if let Some(ip4) = parse_response(udp_data) {
-                        return Some(ip4);
-                    }
-                }
+pub fn type_name(t: u16) -> &'static str {
+    match t {
+        TYPE_A     => "A",
+        TYPE_NS    => "NS",
+        TYPE_CNAME => "CNAME",
+        TYPE_SOA   => "SOA",
+        TYPE_PTR   => "PTR",
+        TYPE_MX    => "MX",
+        TYPE_TXT   => "TXT",
+        TYPE_AAAA  => "AAAA",
+        _          => "?",
+    }
+}
+
+pub fn type_from_name(s: &str) -> Option<u16> {
+    [TYPE_A, TYPE_NS, TYPE_CNAME, TYPE_SOA, TYPE_PTR, TYPE_MX, TYPE_TXT, TYPE_AAAA]
+        .into_iter()
+        .find(|&t| type_name(t).eq_ignore_ascii_case(s))
+}
+
+struct Answer {
+    rcode:     u16,
+    truncated: bool,
+    answers:   Vec<Record>,
+    authority: Vec<Record>,
+}
+
+struct CacheEntry {
+    result:  Result<Vec<Record>, DnsError>,
+    expires: u64,
+}
+
+static CACHE: Mutex<BTreeMap<(String, u16), CacheEntry>> = Mutex::new(BTreeMap::new());
+
+fn ticks() -> u64 {
+    crate::vfs::procfs::uptime_ticks()
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+pub fn resolve(hostname: &str) -> Option<[u8; 4]> {
+    query(hostname, TYPE_A).ok()?.iter().find_map(|r| match r.data {
+        RData::A(a) => Some(a),
+        _ => None,
+    })
+}
+
+pub fn resolve6(hostname: &str) -> Option<[u8; 16]> {
+    query(hostname, TYPE_AAAA).ok()?.iter().find_map(|r| match r.data {
+        RData::Aaaa(a) => Some(a),
+        _ => None,
+    })
+}
+
+pub fn lookup(hostname: &str) -> Option<IpAddr> {
+    if let Some(ip) = IpAddr::parse(hostname) {
+        return Some(ip);
+    }
+    if let Some(ip) = resolve(hostname) {
+        return Some(IpAddr::V4(ip));
+    }
+    if super::source_for(&IpAddr::UNSPECIFIED_V6).is_some() {
+        return resolve6(hostname).map(IpAddr::V6);
+    }
+    None
+}
+
+pub fn reverse(ip: &IpAddr) -> Option<String> {
+    query(&reverse_name(ip), TYPE_PTR).ok()?.into_iter().find_map(|r| match r.data {
+        RData::Name(n) if r.rtype == TYPE_PTR => Some(n),
+        _ => None,
+    })
+}
+
+pub fn reverse_name(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(a) => format!("{}.{}.{}.{}.in-addr.arpa", a[3], a[2], a[1], a[0]),
+        IpAddr::V6(a) => {
+            let mut s = String::with_capacity(72);
+            for b in a.iter().rev() {
+                s.push_str(&format!("{:x}.{:x}.", b & 0xf, b >> 4));
             }
+            s.push_str("ip6.arpa");
+            s
         }
-        core::hint::spin_loop();
     }
-    None
 }
 
-fn build_query(hostname: &str, out: &mut [u8; 512]) -> Option<usize> {
-    out[0] = 0x13;
-    out[1] = 0x37;
-    out[2] = 0x01;
-    out[3] = 0x00;
-    out[4] = 0x00;
-    out[5] = 0x01;
-    out[6] = 0x00; out[7] = 0x00;
-    out[8] = 0x00; out[9] = 0x00;
-    out[10] = 0x00; out[11] = 0x00;
+pub fn query(name: &str, qtype: u16) -> Result<Vec<Record>, DnsError> {
+    let name = normalize(name);
+    if name.is_empty() {
+        return Err(DnsError::Malformed);
+    }
+    if let Some(r) = hosts_lookup(&name, qtype) {
+        return Ok(r);
+    }
+
+    let mut chain  = Vec::new();
+    let mut target = name;
+    for _ in 0..MAX_CNAME {
+        let records = match cache_get(&target, qtype) {
+            Some(r) => r?,
+            None    => answer_to_records(&target, qtype, ask(&target, qtype, None)?)?,
+        };
+        let next = match records.last() {
+            Some(Record { rtype: TYPE_CNAME, data: RData::Name(n), .. }) if qtype != TYPE_CNAME => Some(n.clone()),
+            _ => None,
+        };
+        chain.extend(records);
+        match next {
+            Some(n) => target = n,
+            None    => return Ok(chain),
+        }
+    }
+    Err(DnsError::CnameLoop)
+}
+
+fn answer_to_records(target: &str, qtype: u16, a: Answer) -> Result<Vec<Record>, DnsError> {
+    let negative_ttl = a.authority.iter()
+        .find_map(|r| match r.data {
+            RData::Soa { minimum, .. } => Some(r.ttl.min(minimum)),
+            _ => None,
+        })
+        .unwrap_or(NEGATIVE_TTL);
 
-    let mut pos = 12usize;
+    if a.rcode == RCODE_NXDOMAIN {
+        cache_put(target, qtype, Err(DnsError::NxDomain), negative_ttl);
+        return Err(DnsError::NxDomain);
+    }
 
-    for label in hostname.split('.') {
-        if label.is_empty() { continue; }
-        let lb = label.as_bytes();
-        if pos + 1 + lb.len() >= 512 { return None; }
-        out[pos] = lb.len() as u8;
-        pos += 1;
-        out[pos..pos + lb.len()].copy_from_slice(lb);
-        pos += lb.len();
+    let mut out   = Vec::new();
+    let mut owner = target.to_string();
+    for _ in 0..MAX_CNAME {
+        let matched: Vec<Record> = a.answers.iter()
+            .filter(|r| r.rtype == qtype && r.name.eq_ignore_ascii_case(&owner))
+            .cloned()
+            .collect();
+        if !matched.is_empty() {
+            out.extend(matched);
+            break;
+        }
+        let cname = a.answers.iter().find(|r| r.rtype == TYPE_CNAME && r.name.eq_ignore_ascii_case(&owner));
+        match cname {
+            Some(c @ Record { data: RData::Name(n), .. }) => {
+                owner = n.clone();
+                out.push(c.clone());
+            }
+            _ => break,
+        }
     }
 
-    if pos + 5 >= 512 { return None; }
-    out[pos] = 0x00;
-    pos += 1;
-    out[pos] = 0x00; out[pos+1] = 0x01;
-    pos += 2;
-    out[pos] = 0x00; out[pos+1] = 0x01;
-    pos += 2;
+    let ttl = out.iter().map(|r| r.ttl).min().unwrap_or(negative_ttl);
+    cache_put(target, qtype, Ok(out.clone()), ttl);
+    Ok(out)
+}
+
+fn cache_get(name: &str, qtype: u16) -> Option<Result<Vec<Record>, DnsError>> {
+    let now = ticks();
+    let mut cache = CACHE.lock();
+    let key = (name.to_string(), qtype);
+    let entry = cache.get(&key)?;
+    if entry.expires <= now {
+        cache.remove(&key);
+        return None;
+    }
+    let left = ((entry.expires - now) / PIT_HZ as u64) as u32;
+    Some(entry.result.clone().map(|mut records| {
+        for r in records.iter_mut() {
+            r.ttl = r.ttl.min(left);
+        }
+        records
+    }))
+}
+
+fn cache_put(name: &str, qtype: u16, result: Result<Vec<Record>, DnsError>, ttl: u32) {
+    let ttl = ttl.min(MAX_TTL);
+    if ttl == 0 {
+        return;
+    }
+    let now = ticks();
+    let mut cache = CACHE.lock();
+    if cache.len() >= MAX_CACHE {
+        cache.retain(|_, e| e.expires > now);
+    }
+    if cache.len() >= MAX_CACHE {
+        let oldest = cache.iter().min_by_key(|(_, e)| e.expires).map(|(k, _)| k.clone());
+        if let Some(k) = oldest {
+            cache.remove(&k);
+        }
+    }
+    cache.insert((name.to_string(), qtype), CacheEntry { result, expires: now + ttl as u64 * PIT_HZ as u64 });
+}
 
-    Some(pos)
+pub fn flush() {
+    CACHE.lock().clear();
 }
 
-fn parse_response(buf: &[u8]) -> Option<[u8; 4]> {
-    if buf.len() < 12 { return None; }
+fn hosts_lookup(name: &str, qtype: u16) -> Option<Vec<Record>> {
+    let data = crate::vfs_read::read_file(HOSTS_PATH)?;
+    let text = core::str::from_utf8(&data).ok()?;
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.split('#').next().unwrap_or("").split_whitespace();
+        let ip = match fields.next().and_then(IpAddr::parse) {
+            Some(ip) => ip,
+            None     => continue,
+        };
+        let names: Vec<&str> = fields.collect();
+        let data = match (qtype, ip) {
+            (TYPE_PTR, _) if reverse_name(&ip) == name => names.first().map(|n| RData::Name(normalize(n))),
+            (TYPE_A, IpAddr::V4(a)) if names.iter().any(|n| normalize(n) == name) => Some(RData::A(a)),
+            (TYPE_AAAA, IpAddr::V6(a)) if names.iter().any(|n| normalize(n) == name) => Some(RData::Aaaa(a)),
+            _ => None,
+        };
+        if let Some(data) = data {
+            out.push(Record { name: name.to_string(), rtype: qtype, ttl: 0, data });
+        }
+    }
+    if out.is_empty() { None } else { Some(out) }
+}
 
-    let flags = u16::from_be_bytes([buf[2], buf[3]]);
-    if flags & 0x8000 == 0 { return None; }
-    if flags & 0x000F != 0 { return None; }
+fn ask(name: &str, qtype: u16, server: Option<IpAddr>) -> Result<Answer, DnsError> {
+    let servers = match server {
+        Some(s) => vec![s],
+        None    => super::dns_servers(),
+    };
+    x86_64::instructions::interrupts::enable();
+    let mut last = DnsError::Timeout;
+    for _ in 0..RETRIES {
+        for &server in &servers {
+            if CTRL_C.load(Ordering::SeqCst) {
+                return Err(DnsError::Timeout);
+            }
+            let id  = tcp::random_isn() as u16;
+            let msg = build_query(id, name, qtype).ok_or(DnsError::Malformed)?;
+            let r = exchange_udp(server, &msg).and_then(|resp| {
+                let a = parse_message(&resp, id, name, qtype).ok_or(DnsError::Malformed)?;
+                if !a.truncated {
+                    return Ok(a);
+                }
+                let resp = exchange_tcp(server, &msg)?;
+                parse_message(&resp, id, name, qtype).ok_or(DnsError::Malformed)
+            });
+            match r {
+                Ok(a) if a.rcode == RCODE_OK || a.rcode == RCODE_NXDOMAIN => return Ok(a),
+                Ok(a) if a.rcode == RCODE_REFUSED => last = DnsError::Refused,
+                Ok(_) => last = DnsError::ServFail,
+                Err(e) => last = e,
+            }
+        }
+    }
+    Err(last)
+}
 
-    let qdcount = u16::from_be_bytes([buf[4], buf[5]]) as usize;
-    let ancount = u16::from_be_bytes([buf[6], buf[7]]) as usize;
+fn family_for(server: &IpAddr) -> u64 {
+    if server.is_v6() { AF_INET6 } else { AF_INET }
+}
 
-    if ancount == 0 { return None; }
+fn exchange_udp(server: IpAddr, msg: &[u8]) -> Result<Vec<u8>, DnsError> {
+    let sock = Socket::with_family(Proto::Udp, family_for(&server));
+    let r = (|| {
+        sock.connect(Endpoint::new(server, PORT), false).map_err(|_| DnsError::Timeout)?;
+        sock.send(msg, None, false).map_err(|_| DnsError::Timeout)?;
+        sock.set_timeout(UDP_TICKS);
+        let mut buf = vec![0u8; 4096];
+        match sock.recv(&mut buf, false) {
+            Ok((n, _)) => {
+                buf.truncate(n);
+                Ok(buf)
+            }
+            Err(ECONNREFUSED) => Err(DnsError::Refused),
+            Err(_) => Err(DnsError::Timeout),
+        }
+    })();
+    sock.close();
+    r
+}
 
-    let mut pos = 12usize;
+fn exchange_tcp(server: IpAddr, msg: &[u8]) -> Result<Vec<u8>, DnsError> {
+    let sock = Socket::with_family(Proto::Tcp, family_for(&server));
+    sock.set_timeout(TCP_TICKS);
+    let r = (|| {
+        sock.connect(Endpoint::new(server, PORT), false).map_err(|_| DnsError::Timeout)?;
+        let mut framed = (msg.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(msg);
+        let mut off = 0;
+        while off < framed.len() {
+            off += sock.send(&framed[off..], None, false).map_err(|_| DnsError::Timeout)?;
+        }
+        let mut resp = Vec::new();
+        let mut buf  = [0u8; 2048];
+        loop {
+            if resp.len() >= 2 {
+                let want = 2 + u16::from_be_bytes([resp[0], resp[1]]) as usize;
+                if resp.len() >= want {
+                    resp.truncate(want);
+                    resp.drain(..2);
+                    return Ok(resp);
+                }
+            }
+            match sock.recv(&mut buf, false) {
+                Ok((0, _)) | Err(_) => return Err(DnsError::Timeout),
+                Ok((n, _)) if resp.len() + n <= MAX_MSG + 2 => resp.extend_from_slice(&buf[..n]),
+                Ok(_) => return Err(DnsError::Malformed),
+            }
+        }
+    })();
+    sock.close();
+    r
+}
 
-    for _ in 0..qdcount {
-        pos = skip_name(buf, pos)?;
-        pos += 4;
-        if pos > buf.len() { return None; }
+fn build_query(id: u16, name: &str, qtype: u16) -> Option<Vec<u8>> {
+    let mut msg = Vec::with_capacity(64);
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&FLAG_RD.to_be_bytes());
+    msg.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    let start = msg.len();
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        if label.len() > 63 {
+            return None;
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    if msg.len() - start > 255 {
+        return None;
     }
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Some(msg)
+}
 
-    for _ in 0..ancount {
-        pos = skip_name(buf, pos)?;
-        if pos + 10 > buf.len() { return None; }
+fn be16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]))
+}
 
-        let rtype = u16::from_be_bytes([buf[pos], buf[pos+1]]);
-        let _rclass = u16::from_be_bytes([buf[pos+2], buf[pos+3]]);
-        let rdlen = u16::from_be_bytes([buf[pos+8], buf[pos+9]]) as usize;
-        pos += 10;
+fn be32(buf: &[u8], pos: usize) -> Option<u32> {
+    Some(((be16(buf, pos)? as u32) << 16) | be16(buf, pos + 2)? as u32)
+}
 
-        if pos + rdlen > buf.len() { return None; }
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let mut end  = None;
+    for _ in 0..128 {
+        let len = *msg.get(pos)? as usize;
+        match len {
+            0 => return Some((name, end.unwrap_or(pos + 1))),
+            l if l & 0xC0 == 0xC0 => {
+                end.get_or_insert(pos + 2);
+                pos = ((l & 0x3F) << 8) | *msg.get(pos + 1)? as usize;
+            }
+            l if l > 63 => return None,
+            l => {
+                let label = msg.get(pos + 1..pos + 1 + l)?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label).to_ascii_lowercase());
+                pos += 1 + l;
+            }
+        }
+    }
+    None
+}
 
-        if rtype == 1 && rdlen == 4 {
-            let mut ip = [0u8; 4];
-            ip.copy_from_slice(&buf[pos..pos+4]);
-            return Some(ip);
+fn parse_record(msg: &[u8], pos: usize) -> Option<(Record, usize)> {
+    let (name, pos) = read_name(msg, pos)?;
+    let rtype = be16(msg, pos)?;
+    let ttl   = be32(msg, pos + 4)?.min(i32::MAX as u32);
+    let rdlen = be16(msg, pos + 8)? as usize;
+    let start = pos + 10;
+    let rdata = msg.get(start..start + rdlen)?;
+    let data = match rtype {
+        TYPE_A if rdlen == 4 => RData::A([rdata[0], rdata[1], rdata[2], rdata[3]]),
+        TYPE_AAAA if rdlen == 16 => {
+            let mut a = [0u8; 16];
+            a.copy_from_slice(rdata);
+            RData::Aaaa(a)
+        }
+        TYPE_CNAME | TYPE_PTR | TYPE_NS => RData::Name(read_name(msg, start)?.0),
+        TYPE_MX => RData::Mx(be16(msg, start)?, read_name(msg, start + 2)?.0),
+        TYPE_TXT => {
+            let mut parts = Vec::new();
+            let mut i = 0;
+            while i < rdata.len() {
+                let l = rdata[i] as usize;
+                parts.push(String::from_utf8_lossy(rdata.get(i + 1..i + 1 + l)?).into_owned());
+                i += 1 + l;
+            }
+            RData::Txt(parts)
         }
+        TYPE_SOA => {
+            let (mname, p) = read_name(msg, start)?;
+            let (rname, p) = read_name(msg, p)?;
+            RData::Soa { mname, rname, serial: be32(msg, p)?, minimum: be32(msg, p + 16)? }
+        }
+        _ => RData::Raw(rdata.to_vec()),
+    };
+    Some((Record { name, rtype, ttl, data }, start + rdlen))
+}
 
-        pos += rdlen;
+fn parse_message(msg: &[u8], id: u16, name: &str, qtype: u16) -> Option<Answer> {
+    if msg.len() < 12 || be16(msg, 0)? != id {
+        return None;
+    }
+    let flags = be16(msg, 2)?;
+    if flags & FLAG_QR == 0 {
+        return None;
+    }
+    let qdcount = be16(msg, 4)?;
+    let ancount = be16(msg, 6)?;
+    let nscount = be16(msg, 8)?;
+    if qdcount != 1 {
+        return None;
+    }
+    let (qname, pos) = read_name(msg, 12)?;
+    if !qname.eq_ignore_ascii_case(name.trim_end_matches('.')) || be16(msg, pos)? != qtype {
+        return None;
     }
 
-    None
+    let mut a = Answer {
+        rcode:     flags & 0x000F,
+        truncated: flags & FLAG_TC != 0,
+        answers:   Vec::new(),
+        authority: Vec::new(),
+    };
+    if a.truncated {
+        return Some(a);
+    }
+    let mut pos = pos + 4;
+    for i in 0..ancount as usize + nscount as usize {
+        let (r, next) = parse_record(msg, pos)?;
+        pos = next;
+        if i < ancount as usize { a.answers.push(r) } else { a.authority.push(r) }
+    }
+    Some(a)
 }
 
-fn skip_name(buf: &[u8], mut pos: usize) -> Option<usize> {
-    loop {
-        if pos >= buf.len() { return None; }
-        let b = buf[pos];
-        if b == 0 {
-            return Some(pos + 1);
+pub fn cmd_dig(args: &str) {
+    let mut server = None;
+    let mut name   = None;
+    let mut qtype  = TYPE_A;
+    let mut rev    = false;
+
+    for arg in args.split_whitespace() {
+        if let Some(s) = arg.strip_prefix('@') {
+            match IpAddr::parse(s) {
+                Some(ip) => server = Some(ip),
+                None => {
+                    crate::print_error!("dig: bad server '{}'", s);
+                    return;
+                }
+            }
+        } else if arg == "-x" {
+            rev = true;
+        } else if let (true, Some(t)) = (name.is_some(), type_from_name(arg)) {
+            qtype = t;
+        } else {
+            name = Some(arg);
+        }
+    }
+    if let Some("flush") = name {
+        flush();
+        crate::print_success!("dig: cache flushed");
+        return;
+    }
+    if let Some("cache") = name {
+        show_cache();
+        return;
+    }
+
+    let mut name = match name {
+        Some(n) => n.to_string(),
+        None => {
+            crate::println!("Usage: dig [@server] <name> [A|AAAA|MX|TXT|PTR|NS|CNAME|SOA]");
+            crate::println!("       dig -x <ip> | dig cache | dig flush");
+            return;
+        }
+    };
+    if rev {
+        match IpAddr::parse(&name) {
+            Some(ip) => name = reverse_name(&ip),
+            None => {
+                crate::print_error!("dig: bad address '{}'", name);
+                return;
+            }
         }
-        if b & 0xC0 == 0xC0 {
-            return Some(pos + 2);
+        qtype = TYPE_PTR;
+    }
+
+    CTRL_C.store(false, Ordering::SeqCst);
+    let start = ticks();
+    let result = match server {
+        Some(s) => ask(&normalize(&name), qtype, Some(s)).and_then(|a| match a.rcode {
+            RCODE_NXDOMAIN => Err(DnsError::NxDomain),
+            _ => Ok(a.answers),
+        }),
+        None => query(&name, qtype),
+    };
+    let ms = ticks().wrapping_sub(start) * 1000 / PIT_HZ as u64;
+
+    crate::println!(";; QUESTION: {}. IN {}", normalize(&name), type_name(qtype));
+    match result {
+        Ok(records) => {
+            crate::println!(";; ANSWER: {}", records.len());
+            for r in &records {
+                print_record(r);
+            }
+        }
+        Err(e) => crate::print_error!("dig: {}: {}", name, e.as_str()),
+    }
+    match server {
+        Some(s) => crate::println!(";; SERVER: {}#{}  time {} ms", s, PORT, ms),
+        None    => crate::println!(";; time {} ms", ms),
+    }
+}
+
+fn print_record(r: &Record) {
+    crate::println!("{:<32} {:>6} IN {:<5} {}", format!("{}.", r.name), r.ttl, type_name(r.rtype), r.data);
+}
+
+fn show_cache() {
+    let now   = ticks();
+    let cache = CACHE.lock();
+    crate::println!("{:<40} {:<5} {:>6} RESULT", "NAME", "TYPE", "TTL");
+    for ((name, qtype), e) in cache.iter().filter(|(_, e)| e.expires > now) {
+        let left = (e.expires - now) / PIT_HZ as u64;
+        match &e.result {
+            Ok(records) if records.is_empty() => crate::println!("{:<40} {:<5} {:>6} NODATA", name, type_name(*qtype), left),
+            Ok(records) => crate::println!("{:<40} {:<5} {:>6} {}", name, type_name(*qtype), left,
+                records.last().map(|r| r.data.to_string()).unwrap_or_default()),
+            Err(err) => crate::println!("{:<40} {:<5} {:>6} {}", name, type_name(*qtype), left, err.as_str()),
         }
-        pos += 1 + b as usize;
     }
 }
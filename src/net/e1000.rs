@@ -1,4 +1,4 @@
-use super::pci::PciDevice;
+use crate::pci::PciDevice;
 use super::NetworkDriver;
 use alloc::boxed::Box;
 use core::sync::atomic::{fence, Ordering};
@@ -9,6 +9,11 @@ const E1000_EERD: u32 = 0x0014;
 const E1000_ICR: u32 = 0x00C0;
 const E1000_IMS: u32 = 0x00D0;
 const E1000_IMC: u32 = 0x00D8;
+
+const E1000_INT_LSC:    u32 = 1 << 2;
+const E1000_INT_RXDMT0: u32 = 1 << 4;
+const E1000_INT_RXO:    u32 = 1 << 6;
+const E1000_INT_RXT0:   u32 = 1 << 7;
 const E1000_RCTL: u32 = 0x0100;
 const E1000_TCTL: u32 = 0x0400;
 const E1000_TIPG: u32 = 0x0410;
@@ -319,7 +324,6 @@ impl NetworkDriver for E1000 {
             self.rx_tail = next;
             self.write32(E1000_RDT, self.rx_tail as u32);
         }
-        let _ = self.read32(E1000_ICR);
     }
 
     fn has_packet(&self) -> bool {
@@ -339,4 +343,13 @@ impl NetworkDriver for E1000 {
     fn get_mac(&self) -> [u8; 6] {
         self.mac
     }
+
+    fn enable_irq(&mut self) {
+        let _ = self.read32(E1000_ICR);
+        self.write32(E1000_IMS, E1000_INT_RXT0 | E1000_INT_RXO | E1000_INT_RXDMT0 | E1000_INT_LSC);
+    }
+
+    fn ack_irq(&mut self) {
+        let _ = self.read32(E1000_ICR);
+    }
 }
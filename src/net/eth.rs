@@ -1,5 +1,6 @@
 pub const ETHERTYPE_IP: u16 = 0x0800;
 pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
 pub const BROADCAST_MAC: [u8; 6] = [0xFF; 6];
 
 #[derive(Clone, Copy, Debug)]
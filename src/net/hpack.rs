@@ -0,0 +1,391 @@
+extern crate alloc;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+const ENTRY_OVERHEAD:         usize = 32;
+
+pub type Header = (Vec<u8>, Vec<u8>);
+
+static STATIC_TABLE: &[(&[u8], &[u8])] = &[
+    (b":authority",                  b""),
+    (b":method",                     b"GET"),
+    (b":method",                     b"POST"),
+    (b":path",                       b"/"),
+    (b":path",                       b"/index.html"),
+    (b":scheme",                     b"http"),
+    (b":scheme",                     b"https"),
+    (b":status",                     b"200"),
+    (b":status",                     b"204"),
+    (b":status",                     b"206"),
+    (b":status",                     b"304"),
+    (b":status",                     b"400"),
+    (b":status",                     b"404"),
+    (b":status",                     b"500"),
+    (b"accept-charset",              b""),
+    (b"accept-encoding",             b"gzip, deflate"),
+    (b"accept-language",             b""),
+    (b"accept-ranges",               b""),
+    (b"accept",                      b""),
+    (b"access-control-allow-origin", b""),
+    (b"age",                         b""),
+    (b"allow",                       b""),
+    (b"authorization",               b""),
+    (b"cache-control",               b""),
+    (b"content-disposition",         b""),
+    (b"content-encoding",            b""),
+    (b"content-language",            b""),
+    (b"content-length",              b""),
+    (b"content-location",            b""),
+    (b"content-range",               b""),
+    (b"content-type",                b""),
+    (b"cookie",                      b""),
+    (b"date",                        b""),
+    (b"etag",                        b""),
+    (b"expect",                      b""),
+    (b"expires",                     b""),
+    (b"from",                        b""),
+    (b"host",                        b""),
+    (b"if-match",                    b""),
+    (b"if-modified-since",           b""),
+    (b"if-none-match",               b""),
+    (b"if-range",                    b""),
+    (b"if-unmodified-since",         b""),
+    (b"last-modified",               b""),
+    (b"link",                        b""),
+    (b"location",                    b""),
+    (b"max-forwards",                b""),
+    (b"proxy-authenticate",          b""),
+    (b"proxy-authorization",         b""),
+    (b"range",                       b""),
+    (b"referer",                     b""),
+    (b"refresh",                     b""),
+    (b"retry-after",                 b""),
+    (b"server",                      b""),
+    (b"set-cookie",                  b""),
+    (b"strict-transport-security",   b""),
+    (b"transfer-encoding",           b""),
+    (b"user-agent",                  b""),
+    (b"vary",                        b""),
+    (b"via",                         b""),
+    (b"www-authenticate",            b""),
+];
+
+const HUFFMAN: [(u32, u8); 257] = [
+    (0x1ff8, 13), (0x7fffd8, 23), (0xfffffe2, 28), (0xfffffe3, 28), (0xfffffe4, 28), (0xfffffe5, 28),
+    (0xfffffe6, 28), (0xfffffe7, 28), (0xfffffe8, 28), (0xffffea, 24), (0x3ffffffc, 30), (0xfffffe9, 28),
+    (0xfffffea, 28), (0x3ffffffd, 30), (0xfffffeb, 28), (0xfffffec, 28), (0xfffffed, 28), (0xfffffee, 28),
+    (0xfffffef, 28), (0xffffff0, 28), (0xffffff1, 28), (0xffffff2, 28), (0x3ffffffe, 30), (0xffffff3, 28),
+    (0xffffff4, 28), (0xffffff5, 28), (0xffffff6, 28), (0xffffff7, 28), (0xffffff8, 28), (0xffffff9, 28),
+    (0xffffffa, 28), (0xffffffb, 28), (0x14, 6), (0x3f8, 10), (0x3f9, 10), (0xffa, 12),
+    (0x1ff9, 13), (0x15, 6), (0xf8, 8), (0x7fa, 11), (0x3fa, 10), (0x3fb, 10),
+    (0xf9, 8), (0x7fb, 11), (0xfa, 8), (0x16, 6), (0x17, 6), (0x18, 6),
+    (0x0, 5), (0x1, 5), (0x2, 5), (0x19, 6), (0x1a, 6), (0x1b, 6),
+    (0x1c, 6), (0x1d, 6), (0x1e, 6), (0x1f, 6), (0x5c, 7), (0xfb, 8),
+    (0x7ffc, 15), (0x20, 6), (0xffb, 12), (0x3fc, 10), (0x1ffa, 13), (0x21, 6),
+    (0x5d, 7), (0x5e, 7), (0x5f, 7), (0x60, 7), (0x61, 7), (0x62, 7),
+    (0x63, 7), (0x64, 7), (0x65, 7), (0x66, 7), (0x67, 7), (0x68, 7),
+    (0x69, 7), (0x6a, 7), (0x6b, 7), (0x6c, 7), (0x6d, 7), (0x6e, 7),
+    (0x6f, 7), (0x70, 7), (0x71, 7), (0x72, 7), (0xfc, 8), (0x73, 7),
+    (0xfd, 8), (0x1ffb, 13), (0x7fff0, 19), (0x1ffc, 13), (0x3ffc, 14), (0x22, 6),
+    (0x7ffd, 15), (0x3, 5), (0x23, 6), (0x4, 5), (0x24, 6), (0x5, 5),
+    (0x25, 6), (0x26, 6), (0x27, 6), (0x6, 5), (0x74, 7), (0x75, 7),
+    (0x28, 6), (0x29, 6), (0x2a, 6), (0x7, 5), (0x2b, 6), (0x76, 7),
+    (0x2c, 6), (0x8, 5), (0x9, 5), (0x2d, 6), (0x77, 7), (0x78, 7),
+    (0x79, 7), (0x7a, 7), (0x7b, 7), (0x7ffe, 15), (0x7fc, 11), (0x3ffd, 14),
+    (0x1ffd, 13), (0xffffffc, 28), (0xfffe6, 20), (0x3fffd2, 22), (0xfffe7, 20), (0xfffe8, 20),
+    (0x3fffd3, 22), (0x3fffd4, 22), (0x3fffd5, 22), (0x7fffd9, 23), (0x3fffd6, 22), (0x7fffda, 23),
+    (0x7fffdb, 23), (0x7fffdc, 23), (0x7fffdd, 23), (0x7fffde, 23), (0xffffeb, 24), (0x7fffdf, 23),
+    (0xffffec, 24), (0xffffed, 24), (0x3fffd7, 22), (0x7fffe0, 23), (0xffffee, 24), (0x7fffe1, 23),
+    (0x7fffe2, 23), (0x7fffe3, 23), (0x7fffe4, 23), (0x1fffdc, 21), (0x3fffd8, 22), (0x7fffe5, 23),
+    (0x3fffd9, 22), (0x7fffe6, 23), (0x7fffe7, 23), (0xffffef, 24), (0x3fffda, 22), (0x1fffdd, 21),
+    (0xfffe9, 20), (0x3fffdb, 22), (0x3fffdc, 22), (0x7fffe8, 23), (0x7fffe9, 23), (0x1fffde, 21),
+    (0x7fffea, 23), (0x3fffdd, 22), (0x3fffde, 22), (0xfffff0, 24), (0x1fffdf, 21), (0x3fffdf, 22),
+    (0x7fffeb, 23), (0x7fffec, 23), (0x1fffe0, 21), (0x1fffe1, 21), (0x3fffe0, 22), (0x1fffe2, 21),
+    (0x7fffed, 23), (0x3fffe1, 22), (0x7fffee, 23), (0x7fffef, 23), (0xfffea, 20), (0x3fffe2, 22),
+    (0x3fffe3, 22), (0x3fffe4, 22), (0x7ffff0, 23), (0x3fffe5, 22), (0x3fffe6, 22), (0x7ffff1, 23),
+    (0x3ffffe0, 26), (0x3ffffe1, 26), (0xfffeb, 20), (0x7fff1, 19), (0x3fffe7, 22), (0x7ffff2, 23),
+    (0x3fffe8, 22), (0x1ffffec, 25), (0x3ffffe2, 26), (0x3ffffe3, 26), (0x3ffffe4, 26), (0x7ffffde, 27),
+    (0x7ffffdf, 27), (0x3ffffe5, 26), (0xfffff1, 24), (0x1ffffed, 25), (0x7fff2, 19), (0x1fffe3, 21),
+    (0x3ffffe6, 26), (0x7ffffe0, 27), (0x7ffffe1, 27), (0x3ffffe7, 26), (0x7ffffe2, 27), (0xfffff2, 24),
+    (0x1fffe4, 21), (0x1fffe5, 21), (0x3ffffe8, 26), (0x3ffffe9, 26), (0xffffffd, 28), (0x7ffffe3, 27),
+    (0x7ffffe4, 27), (0x7ffffe5, 27), (0xfffec, 20), (0xfffff3, 24), (0xfffed, 20), (0x1fffe6, 21),
+    (0x3fffe9, 22), (0x1fffe7, 21), (0x1fffe8, 21), (0x7ffff3, 23), (0x3fffea, 22), (0x3fffeb, 22),
+    (0x1ffffee, 25), (0x1ffffef, 25), (0xfffff4, 24), (0xfffff5, 24), (0x3ffffea, 26), (0x7ffff4, 23),
+    (0x3ffffeb, 26), (0x7ffffe6, 27), (0x3ffffec, 26), (0x3ffffed, 26), (0x7ffffe7, 27), (0x7ffffe8, 27),
+    (0x7ffffe9, 27), (0x7ffffea, 27), (0x7ffffeb, 27), (0xffffffe, 28), (0x7ffffec, 27), (0x7ffffed, 27),
+    (0x7ffffee, 27), (0x7ffffef, 27), (0x7fffff0, 27), (0x3ffffee, 26), (0x3fffffff, 30),
+];
+
+struct Canon {
+    first:  [u32; 31],
+    count:  [u16; 31],
+    offset: [u16; 31],
+    syms:   [u16; 257],
+}
+
+const fn build_canon() -> Canon {
+    let mut c = Canon { first: [0; 31], count: [0; 31], offset: [0; 31], syms: [0; 257] };
+    let mut n   = 0;
+    let mut len = 1;
+    while len <= 30 {
+        c.offset[len] = n as u16;
+        let mut s = 0;
+        while s < 257 {
+            if HUFFMAN[s].1 as usize == len {
+                if c.count[len] == 0 { c.first[len] = HUFFMAN[s].0; }
+                c.count[len] += 1;
+                c.syms[n] = s as u16;
+                n += 1;
+            }
+            s += 1;
+        }
+        len += 1;
+    }
+    c
+}
+
+static CANON: Canon = build_canon();
+
+pub fn huffman_encoded_len(src: &[u8]) -> usize {
+    src.iter().map(|&b| HUFFMAN[b as usize].1 as usize).sum::<usize>().div_ceil(8)
+}
+
+pub fn huffman_encode(src: &[u8], out: &mut Vec<u8>) {
+    let mut acc  = 0u64;
+    let mut bits = 0u32;
+    for &b in src {
+        let (code, len) = HUFFMAN[b as usize];
+        acc   = (acc << len) | code as u64;
+        bits += len as u32;
+        while bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+        acc &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        out.push(((acc << (8 - bits)) as u8) | (0xFF >> bits));
+    }
+}
+
+pub fn huffman_decode(src: &[u8]) -> Option<Vec<u8>> {
+    let mut out  = Vec::with_capacity(src.len() * 8 / 5);
+    let mut code = 0u32;
+    let mut len  = 0usize;
+    for &byte in src {
+        for i in (0..8).rev() {
+            code = (code << 1) | ((byte >> i) & 1) as u32;
+            len += 1;
+            let count = CANON.count[len] as u32;
+            if count > 0 && code >= CANON.first[len] && code - CANON.first[len] < count {
+                let sym = CANON.syms[CANON.offset[len] as usize + (code - CANON.first[len]) as usize];
+                if sym == 256 { return None; }
+                out.push(sym as u8);
+                code = 0;
+                len  = 0;
+            } else if len >= 30 {
+                return None;
+            }
+        }
+    }
+    if len > 7 || code != (1 << len) - 1 { return None; }
+    Some(out)
+}
+
+fn encode_int(out: &mut Vec<u8>, first: u8, prefix_bits: u8, value: usize) {
+    let max = (1usize << prefix_bits) - 1;
+    if value < max {
+        out.push(first | value as u8);
+        return;
+    }
+    out.push(first | max as u8);
+    let mut v = value - max;
+    while v >= 128 {
+        out.push((v & 0x7F) as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn decode_int(data: &[u8], pos: &mut usize, prefix_bits: u8) -> Option<usize> {
+    let mask  = (1usize << prefix_bits) - 1;
+    let mut v = *data.get(*pos)? as usize & mask;
+    *pos += 1;
+    if v < mask { return Some(v); }
+    let mut shift = 0;
+    loop {
+        let b = *data.get(*pos)?;
+        *pos += 1;
+        if shift > 28 { return None; }
+        v += ((b & 0x7F) as usize) << shift;
+        shift += 7;
+        if b & 0x80 == 0 { return Some(v); }
+    }
+}
+
+fn encode_string(out: &mut Vec<u8>, s: &[u8]) {
+    let hlen = huffman_encoded_len(s);
+    if hlen < s.len() {
+        encode_int(out, 0x80, 7, hlen);
+        huffman_encode(s, out);
+    } else {
+        encode_int(out, 0x00, 7, s.len());
+        out.extend_from_slice(s);
+    }
+}
+
+fn decode_string(data: &[u8], pos: &mut usize) -> Option<Vec<u8>> {
+    let huffman = *data.get(*pos)? & 0x80 != 0;
+    let len     = decode_int(data, pos, 7)?;
+    let raw     = data.get(*pos..pos.checked_add(len)?)?;
+    *pos += len;
+    if huffman { huffman_decode(raw) } else { Some(raw.to_vec()) }
+}
+
+struct Table {
+    entries: VecDeque<Header>,
+    size:    usize,
+    max:     usize,
+}
+
+impl Table {
+    fn new(max: usize) -> Self {
+        Self { entries: VecDeque::new(), size: 0, max }
+    }
+
+    fn get(&self, idx: usize) -> Option<(&[u8], &[u8])> {
+        match idx {
+            0 => None,
+            i if i <= STATIC_TABLE.len() => Some(STATIC_TABLE[i - 1]),
+            i => self.entries.get(i - STATIC_TABLE.len() - 1).map(|(n, v)| (&n[..], &v[..])),
+        }
+    }
+
+    fn find(&self, name: &[u8], value: &[u8]) -> (Option<usize>, Option<usize>) {
+        let dynamic = self.entries.iter().map(|(n, v)| (&n[..], &v[..]));
+        let mut by_name = None;
+        for (i, (n, v)) in STATIC_TABLE.iter().copied().chain(dynamic).enumerate() {
+            if n != name { continue; }
+            if v == value { return (Some(i + 1), Some(i + 1)); }
+            by_name.get_or_insert(i + 1);
+        }
+        (None, by_name)
+    }
+
+    fn evict_to(&mut self, limit: usize) {
+        while self.size > limit {
+            match self.entries.pop_back() {
+                Some((n, v)) => self.size -= n.len() + v.len() + ENTRY_OVERHEAD,
+                None         => { self.size = 0; break; }
+            }
+        }
+    }
+
+    fn set_max(&mut self, max: usize) {
+        self.max = max;
+        self.evict_to(max);
+    }
+
+    fn insert(&mut self, name: &[u8], value: &[u8]) {
+        let sz = name.len() + value.len() + ENTRY_OVERHEAD;
+        if sz > self.max {
+            self.entries.clear();
+            self.size = 0;
+            return;
+        }
+        self.evict_to(self.max - sz);
+        self.entries.push_front((name.to_vec(), value.to_vec()));
+        self.size += sz;
+    }
+}
+
+pub struct Decoder {
+    table: Table,
+    limit: usize,
+}
+
+impl Decoder {
+    pub fn new(limit: usize) -> Self {
+        Self { table: Table::new(limit), limit }
+    }
+
+    pub fn decode(&mut self, block: &[u8]) -> Option<Vec<Header>> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos < block.len() {
+            let b = block[pos];
+            if b & 0x80 != 0 {
+                let idx = decode_int(block, &mut pos, 7)?;
+                let (n, v) = self.table.get(idx)?;
+                out.push((n.to_vec(), v.to_vec()));
+            } else if b & 0x40 != 0 {
+                let (n, v) = self.literal(block, &mut pos, 6)?;
+                self.table.insert(&n, &v);
+                out.push((n, v));
+            } else if b & 0x20 != 0 {
+                if !out.is_empty() { return None; }
+                let max = decode_int(block, &mut pos, 5)?;
+                if max > self.limit { return None; }
+                self.table.set_max(max);
+            } else {
+                out.push(self.literal(block, &mut pos, 4)?);
+            }
+        }
+        Some(out)
+    }
+
+    fn literal(&self, block: &[u8], pos: &mut usize, prefix_bits: u8) -> Option<Header> {
+        let idx  = decode_int(block, pos, prefix_bits)?;
+        let name = if idx == 0 { decode_string(block, pos)? } else { self.table.get(idx)?.0.to_vec() };
+        Some((name, decode_string(block, pos)?))
+    }
+}
+
+pub struct Encoder {
+    table:   Table,
+    pending: Option<usize>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self { Self::new() }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self { table: Table::new(DEFAULT_TABLE_SIZE), pending: None }
+    }
+
+    pub fn set_max_table_size(&mut self, max: usize) {
+        let max = max.min(DEFAULT_TABLE_SIZE);
+        if max == self.table.max { return; }
+        self.table.set_max(max);
+        self.pending = Some(self.pending.map_or(max, |p| p.min(max)));
+    }
+
+    pub fn encode(&mut self, headers: &[(&[u8], &[u8])], out: &mut Vec<u8>) {
+        if let Some(min) = self.pending.take() {
+            encode_int(out, 0x20, 5, min);
+            if min != self.table.max {
+                encode_int(out, 0x20, 5, self.table.max);
+            }
+        }
+        for &(name, value) in headers {
+            let sensitive = matches!(name, b"authorization" | b"proxy-authorization" | b"cookie");
+            let (full, by_name) = self.table.find(name, value);
+            if let (Some(idx), false) = (full, sensitive) {
+                encode_int(out, 0x80, 7, idx);
+                continue;
+            }
+            let (first, prefix_bits) = if sensitive { (0x10, 4) } else { (0x40, 6) };
+            match by_name {
+                Some(idx) => encode_int(out, first, prefix_bits, idx),
+                None      => { encode_int(out, first, prefix_bits, 0); encode_string(out, name); }
+            }
+            encode_string(out, value);
+            if !sensitive {
+                self.table.insert(name, value);
+            }
+        }
+    }
+}
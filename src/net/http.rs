@@ -1,8 +1,36 @@
 extern crate alloc;
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use alloc::string::String;
+use alloc::sync::Arc;
 use core::sync::atomic::Ordering;
+use spin::Mutex;
+use super::ip::IpAddr;
+use super::http2::{H2Conn, H2Request, H2Response};
+use super::tcp::TcpSocket;
+use super::tls::{TlsError, TlsStream};
 use super::CTRL_C;
+use crate::fdtable::{self, OpenFile, O_APPEND, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+use crate::syscall::{EACCES, EFBIG, EISDIR, ENOENT, ENOSPC, ENOTDIR, EPERM, EROFS};
+
+const POOL_MAX:       usize = 4;
+const POOL_IDLE_SECS: u64   = 30;
+const READ_IDLE_SECS: usize = 30;
+const MAX_REDIRECTS:  usize = 5;
+const SCREEN_LIMIT:   usize = 8192;
+const EXIT_USAGE:     u8    = 2;
+
+struct Pooled {
+    key:        String,
+    stream:     Box<TlsStream>,
+    idle_since: u64,
+}
+
+static POOL: Mutex<Vec<Pooled>> = Mutex::new(Vec::new());
+
+fn ticks() -> u64 {
+    crate::vfs::procfs::uptime_ticks()
+}
 
 pub struct ParsedUrl<'a> {
     pub scheme:   &'a str,
@@ -30,11 +58,19 @@ impl<'a> ParsedUrl<'a> {
             (rest, "/")
         };
 
-        let (hostname, port) = if let Some(colon) = host.rfind(':') {
+        let default = if use_tls { 443 } else { 80 };
+        let (hostname, port) = if let Some(v6) = host.strip_prefix('[') {
+            let close = v6.find(']')?;
+            let port = match &v6[close + 1..] {
+                "" => default,
+                p  => p.strip_prefix(':')?.parse().ok()?,
+            };
+            (&v6[..close], port)
+        } else if let Some(colon) = host.rfind(':') {
             let p: u16 = host[colon + 1..].parse().ok()?;
             (&host[..colon], p)
         } else {
-            (host, if use_tls { 443 } else { 80 })
+            (host, default)
         };
 
         Some(ParsedUrl { scheme, host, hostname, port, path, use_tls })
@@ -45,11 +81,20 @@ pub struct HttpRequest<'a> {
     pub method:  &'a str,
     pub path:    &'a str,
     pub host:    &'a str,
-    pub body:    Option<&'a [u8]>,
-    pub headers: &'a [(&'a str, &'a str)],
+    pub body:       Option<&'a [u8]>,
+    pub headers:    &'a [(&'a str, &'a str)],
+    pub keep_alive: bool,
 }
 
 impl<'a> HttpRequest<'a> {
+    fn replayable(&self) -> bool {
+        match self.method {
+            "GET" | "HEAD" | "OPTIONS" => true,
+            "PUT" => self.body.is_none_or(|b| b.is_empty()),
+            _     => false,
+        }
+    }
+
     pub fn write_to(&self, buf: &mut Vec<u8>) {
         let push = |buf: &mut Vec<u8>, s: &str| buf.extend_from_slice(s.as_bytes());
 
@@ -58,7 +103,9 @@ impl<'a> HttpRequest<'a> {
         push(buf, self.path);
         push(buf, " HTTP/1.1\r\nHost: ");
         push(buf, self.host);
-        push(buf, "\r\nUser-Agent: MikuOS/0.1\r\nConnection: close\r\nAccept: */*\r\n");
+        push(buf, "\r\nUser-Agent: MikuOS/0.1\r\nConnection: ");
+        push(buf, if self.keep_alive { "keep-alive" } else { "close" });
+        push(buf, "\r\nAccept: */*\r\n");
 
         for (k, v) in self.headers {
             push(buf, k); push(buf, ": "); push(buf, v); push(buf, "\r\n");
@@ -77,32 +124,76 @@ impl<'a> HttpRequest<'a> {
     }
 }
 
+
 pub struct HttpResponse {
     pub status:         u16,
     pub reason:         String,
     pub location:       Option<String>,
+    pub headers:        Vec<(String, String)>,
     pub body:           Vec<u8>,
     pub content_length: Option<usize>,
     pub chunked:        bool,
+    pub keep_alive:     bool,
 }
 
 impl HttpResponse {
     fn new() -> Self {
-        Self { status: 0, reason: String::new(), location: None,
-               body: Vec::new(), content_length: None, chunked: false }
+        Self { status: 0, reason: String::new(), location: None, headers: Vec::new(),
+               body: Vec::new(), content_length: None, chunked: false, keep_alive: false }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
     }
 }
 
-pub fn parse_response(data: &[u8]) -> Option<HttpResponse> {
-    let hdr_end = {
-        let mut pos = None;
-        let mut i   = 0usize;
-        while i + 3 < data.len() {
-            if &data[i..i+4] == b"\r\n\r\n" { pos = Some(i); break; }
-            i += 1;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FetchError {
+    BadUrl,
+    NotReady,
+    Resolve,
+    Connect,
+    Tls,
+    Cert,
+    Send,
+    Recv,
+    Timeout,
+    Aborted,
+    Redirects,
+}
+
+impl FetchError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FetchError::BadUrl    => "malformed URL",
+            FetchError::NotReady  => "net not ready (run dhcp first)",
+            FetchError::Resolve   => "cannot resolve host",
+            FetchError::Connect   => "connect failed",
+            FetchError::Tls       => "TLS handshake failed",
+            FetchError::Cert      => "certificate verification failed",
+            FetchError::Send      => "send failed",
+            FetchError::Recv      => "connection closed mid-response",
+            FetchError::Timeout   => "timed out",
+            FetchError::Aborted   => "interrupted",
+            FetchError::Redirects => "too many redirects",
         }
-        pos?
-    };
+    }
+}
+
+pub struct Fetch<'a> {
+    pub method:   &'a str,
+    pub headers:  &'a [(&'a str, &'a str)],
+    pub body:     Option<&'a [u8]>,
+    pub insecure: bool,
+    pub follow:   bool,
+}
+
+fn header_end(data: &[u8]) -> Option<usize> {
+    data.windows(4).position(|w| w == b"\r\n\r\n")
+}
+
+pub fn parse_response(data: &[u8]) -> Option<HttpResponse> {
+    let hdr_end = header_end(data)?;
 
     let mut resp  = HttpResponse::new();
     let mut lines = data[..hdr_end].split(|&b| b == b'\n');
@@ -110,9 +201,10 @@ pub fn parse_response(data: &[u8]) -> Option<HttpResponse> {
     {
         let sl = trim_crlf(lines.next()?);
         let mut p = sl.splitn(3, |&b| b == b' ');
-        let _ver  = p.next()?;
+        let ver   = p.next()?;
         let code  = p.next()?;
         let rsn   = p.next().unwrap_or(b"");
+        resp.keep_alive = ver == b"HTTP/1.1";
         resp.status = core::str::from_utf8(code).ok()?.trim().parse().ok()?;
         resp.reason = String::from(core::str::from_utf8(rsn).unwrap_or("").trim());
     }
@@ -121,8 +213,11 @@ pub fn parse_response(data: &[u8]) -> Option<HttpResponse> {
         let line = trim_crlf(line);
         if line.is_empty() { continue; }
         if let Some(colon) = line.iter().position(|&b| b == b':') {
-            let key   = ascii_lower(trim_ws(&line[..colon]));
+            let name  = trim_ws(&line[..colon]);
+            let key   = ascii_lower(name);
             let value = trim_ws(&line[colon + 1..]);
+            resp.headers.push((String::from_utf8_lossy(name).into_owned(),
+                               String::from_utf8_lossy(value).into_owned()));
             match key.as_str() {
                 "content-length" => {
                     if let Ok(s) = core::str::from_utf8(value) {
@@ -134,6 +229,13 @@ pub fn parse_response(data: &[u8]) -> Option<HttpResponse> {
                         if s.trim().eq_ignore_ascii_case("chunked") { resp.chunked = true; }
                     }
                 }
+                "connection" => {
+                    if let Ok(s) = core::str::from_utf8(value) {
+                        let s = s.trim();
+                        if s.eq_ignore_ascii_case("close") { resp.keep_alive = false; }
+                        if s.eq_ignore_ascii_case("keep-alive") { resp.keep_alive = true; }
+                    }
+                }
                 "location" => {
                     if let Ok(s) = core::str::from_utf8(value) {
                         resp.location = Some(String::from(s.trim()));
@@ -154,22 +256,85 @@ pub fn parse_response(data: &[u8]) -> Option<HttpResponse> {
 }
 
 fn decode_chunked(data: &[u8]) -> Vec<u8> {
-    let mut out = Vec::new();
-    let mut pos = 0usize;
-    loop {
-        if CTRL_C.load(Ordering::SeqCst) { break; }
-        let crlf = match find_crlf(data, pos) { Some(e) => e, None => break };
-        let size  = parse_hex(trim_crlf(&data[pos..crlf]));
-        pos = crlf + 2;
-        if size == 0 { break; }
-        let end = (pos + size).min(data.len());
-        out.extend_from_slice(&data[pos..end]);
-        if pos + size >= data.len() { break; }
-        pos = pos + size + 2;
-    }
+    let mut out  = Vec::new();
+    let mut body = Body::ChunkSize;
+    let _ = body.feed(data, &mut |d| { out.extend_from_slice(d); true });
     out
 }
 
+enum Body {
+    Length(u64),
+    Close,
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd,
+    Trailer,
+    Done,
+}
+
+impl Body {
+    fn for_response(resp: &HttpResponse, head: bool) -> Self {
+        if head || matches!(resp.status, 204 | 304) {
+            Body::Done
+        } else if resp.chunked {
+            Body::ChunkSize
+        } else if let Some(cl) = resp.content_length {
+            Body::Length(cl as u64)
+        } else {
+            Body::Close
+        }
+    }
+
+    fn done(&self) -> bool {
+        matches!(self, Body::Length(0) | Body::Done)
+    }
+
+    fn feed(&mut self, data: &[u8], emit: &mut dyn FnMut(&[u8]) -> bool) -> Result<usize, FetchError> {
+        let mut pos = 0;
+        loop {
+            let avail = data.len() - pos;
+            match *self {
+                Body::Length(0) | Body::Done => break,
+                Body::Length(n) | Body::ChunkData(n) => {
+                    let take = (n.min(avail as u64)) as usize;
+                    if take == 0 { break; }
+                    let left = n - take as u64;
+                    *self = match self {
+                        Body::Length(_)          => Body::Length(left),
+                        _ if left == 0           => Body::ChunkEnd,
+                        _                        => Body::ChunkData(left),
+                    };
+                    pos += take;
+                    if !emit(&data[pos - take..pos]) { break; }
+                }
+                Body::Close => {
+                    let rest = &data[pos..];
+                    pos = data.len();
+                    if rest.is_empty() || !emit(rest) { break; }
+                }
+                Body::ChunkSize => {
+                    let crlf = match find_crlf(data, pos) { Some(e) => e, None => break };
+                    let size = parse_hex(trim_ws(&data[pos..crlf])) as u64;
+                    pos = crlf + 2;
+                    *self = if size == 0 { Body::Trailer } else { Body::ChunkData(size) };
+                }
+                Body::ChunkEnd => {
+                    if avail < 2 { break; }
+                    if &data[pos..pos + 2] != b"\r\n" { return Err(FetchError::Recv); }
+                    pos += 2;
+                    *self = Body::ChunkSize;
+                }
+                Body::Trailer => {
+                    let end = match find_crlf(data, pos) { Some(e) => e, None => break };
+                    if end == pos { *self = Body::Done; }
+                    pos = end + 2;
+                }
+            }
+        }
+        Ok(pos)
+    }
+}
+
 fn find_crlf(data: &[u8], from: usize) -> Option<usize> {
     for i in from..data.len().saturating_sub(1) {
         if data[i] == b'\r' && data[i+1] == b'\n' { return Some(i); }
@@ -209,21 +374,115 @@ fn ascii_lower(s: &[u8]) -> String {
     o
 }
 
-fn do_request(url: &ParsedUrl, req_bytes: &[u8]) -> Option<Vec<u8>> {
-    if !crate::net::is_ready() {
-        crate::print_error!("http: net not ready (run dhcp first)");
-        return None;
+fn pool_key(url: &ParsedUrl) -> String {
+    alloc::format!("{}:{}", url.hostname.to_ascii_lowercase(), url.port)
+}
+
+fn pool_take(key: &str, verify: bool) -> Option<Box<TlsStream>> {
+    let now   = ticks();
+    let limit = POOL_IDLE_SECS * crate::interrupts::PIT_HZ as u64;
+    let mut pool = POOL.lock();
+    let mut i = 0;
+    while i < pool.len() {
+        if pool[i].stream.is_closed() || now.saturating_sub(pool[i].idle_since) > limit {
+            pool.remove(i).stream.close();
+        } else {
+            i += 1;
+        }
     }
-    for _ in 0..3 { crate::net::poll(); }
+    let idx = pool.iter().position(|p| p.key == key && (p.stream.is_verified() || !verify))?;
+    Some(pool.remove(idx).stream)
+}
 
-    let dns = crate::net::get_dns();
-    let ip  = if let Some(ip) = parse_ip(url.hostname) {
-        ip
-    } else {
+fn release(key: String, mut stream: Box<TlsStream>, reusable: bool) {
+    if !reusable || stream.is_closed() {
+        stream.close();
+        return;
+    }
+    let mut pool = POOL.lock();
+    if pool.len() >= POOL_MAX {
+        pool.remove(0).stream.close();
+    }
+    pool.push(Pooled { key, stream, idle_since: ticks() });
+}
+
+pub fn pool_flush() {
+    for mut p in POOL.lock().drain(..) {
+        p.stream.close();
+    }
+}
+
+type Sink<'a> = dyn FnMut(&HttpResponse, &[u8]) -> bool + 'a;
+
+fn read_stream(recv: &mut dyn FnMut(&mut Vec<u8>) -> Option<usize>, head: bool, sink: &mut Sink)
+    -> Result<(HttpResponse, bool), FetchError>
+{
+    let mut buf  = Vec::new();
+    let mut resp: Option<HttpResponse> = None;
+    let mut body = Body::Close;
+    let mut idle = 0usize;
+    loop {
+        if CTRL_C.load(Ordering::SeqCst) {
+            crate::print_error!("http: {}", FetchError::Aborted.as_str());
+            return Err(FetchError::Aborted);
+        }
+        let got = recv(&mut buf);
+        match got {
+            Some(0) => {
+                idle += 100;
+                if idle > READ_IDLE_SECS * crate::interrupts::PIT_HZ as usize {
+                    crate::print_error!("http: {}", FetchError::Timeout.as_str());
+                    return Err(FetchError::Timeout);
+                }
+            }
+            Some(_) => idle = 0,
+            None    => {}
+        }
+
+        while resp.is_none() {
+            let end = match header_end(&buf) { Some(e) => e + 4, None => break };
+            let r = match parse_response(&buf[..end]) {
+                Some(r) => r,
+                None => { crate::print_error!("http: malformed response"); return Err(FetchError::Recv); }
+            };
+            buf.drain(..end);
+            if (100..200).contains(&r.status) { continue; }
+            body = Body::for_response(&r, head);
+            if !sink(&r, &[]) { return Ok((r, false)); }
+            resp = Some(r);
+        }
+
+        if let Some(r) = resp.as_ref() {
+            let mut stopped = false;
+            let used = body.feed(&buf, &mut |d| { stopped = !sink(r, d); !stopped })?;
+            buf.drain(..used);
+            if stopped || body.done() {
+                let reusable = !stopped && r.keep_alive && buf.is_empty();
+                return Ok((resp.unwrap(), reusable));
+            }
+        }
+
+        if got.is_none() {
+            return match (resp, body) {
+                (Some(r), Body::Close) => Ok((r, false)),
+                _ => {
+                    crate::print_error!("http: {}", FetchError::Recv.as_str());
+                    Err(FetchError::Recv)
+                }
+            };
+        }
+    }
+}
+
+fn resolve(url: &ParsedUrl) -> Result<IpAddr, FetchError> {
+    if IpAddr::parse(url.hostname).is_none() {
         crate::cprintln!(57, 197, 187, "http: resolving {}...", url.hostname);
-        match crate::net::dns::resolve(url.hostname, &dns) {
-            Some(ip) => ip,
-            None => { crate::print_error!("http: cannot resolve '{}'", url.hostname); return None; }
+    }
+    let ip = match crate::net::dns::lookup(url.hostname) {
+        Some(ip) => ip,
+        None => {
+            crate::print_error!("http: cannot resolve '{}'", url.hostname);
+            return Err(FetchError::Resolve);
         }
     };
 
@@ -232,213 +491,820 @@ fn do_request(url: &ParsedUrl, req_bytes: &[u8]) -> Option<Vec<u8>> {
         if url.use_tls { "TLS" } else { "plain" });
 
     x86_64::instructions::interrupts::enable();
+    Ok(ip)
+}
+
+fn tls_connect(url: &ParsedUrl, insecure: bool) -> Result<Box<TlsStream>, FetchError> {
+    let ip = resolve(url)?;
+    crate::cprintln!(120, 200, 200, "http: TLS handshake...");
+    if insecure {
+        crate::print_warn!("http: certificate verification disabled");
+    }
+    let s = match TlsStream::connect_verified(url.hostname, ip, url.port, !insecure) {
+        Ok(s) => Box::new(s),
+        Err(TlsError::Cert(e)) => {
+            crate::print_error!("http: certificate verification failed: {}", e.as_str());
+            crate::print_info!("http: use --insecure to skip verification");
+            return Err(FetchError::Cert);
+        }
+        Err(e) => {
+            crate::print_error!("http: TLS {}", e.as_str());
+            return Err(if matches!(e, TlsError::Connect) { FetchError::Connect } else { FetchError::Tls });
+        }
+    };
+    crate::print_success!("http: TLS ok ({}{}{})", s.cipher_name(),
+        if s.is_h2() { ", h2" } else { "" },
+        if s.is_resumed() { ", resumed" } else { "" });
+    Ok(s)
+}
+
+fn h2_to_raw(resp: H2Response) -> Vec<u8> {
+    let mut out = Vec::from(alloc::format!("HTTP/2 {} \r\n", resp.status).as_bytes());
+    for (k, v) in &resp.headers {
+        out.extend_from_slice(k.as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(v.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
+    out.extend_from_slice(&resp.body);
+    out
+}
+
+fn h2_head(resp: &H2Response) -> HttpResponse {
+    let mut r = HttpResponse::new();
+    r.status   = resp.status;
+    r.location = resp.location.clone();
+    r.headers  = resp.headers.clone();
+    r.content_length = r.header("content-length").and_then(|v| v.trim().parse().ok());
+    r
+}
+
+fn h2_fetch(s: &mut TlsStream, url: &ParsedUrl, req: &HttpRequest, sink: &mut Sink)
+    -> Result<HttpResponse, FetchError>
+{
+    crate::cprintln!(57, 197, 187, "http: using HTTP/2");
+    let h2req = H2Request { method: req.method, path: req.path, headers: req.headers, body: req.body };
+    let mut head: Option<HttpResponse> = None;
+    let mut stopped = false;
+    let mut conn = H2Conn::open(s, "https", url.host);
+    let done = conn.submit(&h2req).and_then(|id| conn.wait_streaming(id, &mut |r, d| {
+        let h = head.get_or_insert_with(|| h2_head(r));
+        stopped = !sink(h, d);
+        !stopped
+    }));
+    conn.close();
+    match head {
+        Some(h) if done.is_some() || stopped => Ok(h),
+        _ if CTRL_C.load(Ordering::SeqCst) => {
+            crate::print_error!("http: {}", FetchError::Aborted.as_str());
+            Err(FetchError::Aborted)
+        }
+        _ => {
+            crate::print_error!("http: h2 stream failed");
+            Err(FetchError::Recv)
+        }
+    }
+}
+
+fn fetch_once(url: &ParsedUrl, req: &HttpRequest, insecure: bool, sink: &mut Sink)
+    -> Result<HttpResponse, FetchError>
+{
+    let mut req_bytes = Vec::new();
+    req.write_to(&mut req_bytes);
+    let head = req.method == "HEAD";
 
-    if url.use_tls {
-        crate::cprintln!(120, 200, 200, "http: TLS handshake...");
-        let mut s = match crate::net::tls::TlsStream::connect(url.hostname, ip, url.port) {
+    if !url.use_tls {
+        let ip = resolve(url)?;
+        let mut s = match TcpSocket::connect(ip, url.port) {
             Some(s) => s,
-            None => { crate::print_error!("http: TLS failed"); return None; }
+            None => { crate::print_error!("http: connect failed"); return Err(FetchError::Connect); }
         };
-        crate::print_success!("http: TLS ok ({})", s.cipher_name());
-        
-        if s.is_h2() {
-            crate::cprintln!(57, 197, 187, "http: using HTTP/2");
-            let resp = crate::net::http2::h2_request(
-                &mut s, "GET", "https", url.hostname, url.path, None,
-            )?;
+        crate::print_success!("http: connected");
+        if !s.send(&req_bytes) {
+            crate::print_error!("http: send failed");
             s.close();
-            let status_line = alloc::format!("HTTP/2 {} \r\n\r\n", resp.status);
-            let mut out = Vec::from(status_line.as_bytes());
-            out.extend_from_slice(&resp.body);
-            return Some(out);
-        }
-        
-        if !s.send(req_bytes) { crate::print_error!("http: send failed"); s.close(); return None; }
-        let out = Vec::from(s.recv_all(10_000_000));
-        crate::log!("http: {} bytes: 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x}", out.len(), out.get(0).copied().unwrap_or(0), out.get(1).copied().unwrap_or(0), out.get(2).copied().unwrap_or(0), out.get(3).copied().unwrap_or(0), out.get(4).copied().unwrap_or(0), out.get(5).copied().unwrap_or(0), out.get(6).copied().unwrap_or(0), out.get(7).copied().unwrap_or(0));
+            return Err(FetchError::Send);
+        }
+        let out = read_stream(&mut |b| s.recv_into(b, 100), head, sink);
         s.close();
-        Some(out)
+        return out.map(|(r, _)| r);
+    }
+
+    let key = pool_key(url);
+    if let Some(mut s) = pool_take(&key, !insecure) {
+        crate::cprintln!(120, 200, 200, "http: reusing connection to {}", key);
+        x86_64::instructions::interrupts::enable();
+        if s.send(&req_bytes) {
+            let mut seen = false;
+            let out = read_stream(&mut |b| s.recv_into(b, 100), head,
+                                  &mut |r, d| { seen = true; sink(r, d) });
+            match out {
+                Ok((r, reusable)) => { release(key, s, reusable); return Ok(r); }
+                Err(e) if seen || e == FetchError::Aborted || !req.replayable() => {
+                    s.close();
+                    return Err(e);
+                }
+                Err(_) => {}
+            }
+        }
+        crate::log!("http: pooled connection to {} went stale", key);
+        s.close();
+    }
+
+    let mut s = tls_connect(url, insecure)?;
+    if s.is_h2() {
+        let out = h2_fetch(&mut s, url, req, sink);
+        s.close();
+        return out;
+    }
+    if !s.send(&req_bytes) {
+        crate::print_error!("http: send failed");
+        s.close();
+        return Err(FetchError::Send);
+    }
+    match read_stream(&mut |b| s.recv_into(b, 100), head, sink) {
+        Ok((r, reusable)) => { release(key, s, reusable); Ok(r) }
+        Err(e) => { s.close(); Err(e) }
+    }
+}
+
+fn resolve_location(url: &ParsedUrl, loc: &str) -> String {
+    if loc.starts_with("http://") || loc.starts_with("https://") {
+        return String::from(loc);
+    }
+    if let Some(rest) = loc.strip_prefix("//") {
+        return alloc::format!("{}://{}", url.scheme, rest);
+    }
+    let path = url.path.split(['?', '#']).next().unwrap_or("/");
+    let base = if loc.starts_with('/') {
+        ""
+    } else if loc.starts_with('?') {
+        path
     } else {
-        let mut s = match crate::net::tcp::TcpSocket::connect(ip, url.port) {
-            Some(s) => s,
-            None => { crate::print_error!("http: connect failed"); return None; }
+        &path[..path.rfind('/').map_or(0, |i| i + 1)]
+    };
+    alloc::format!("{}://{}{}{}", url.scheme, url.host, base, loc)
+}
+
+pub fn fetch(url_str: &str, f: &Fetch, sink: &mut Sink) -> Result<HttpResponse, FetchError> {
+    if !crate::net::is_ready() {
+        crate::print_error!("http: {}", FetchError::NotReady.as_str());
+        return Err(FetchError::NotReady);
+    }
+    for _ in 0..3 { crate::net::poll(); }
+
+    let mut current = String::from(url_str);
+    let mut method  = f.method;
+    let mut body    = f.body;
+    let mut headers = f.headers.to_vec();
+    for _ in 0..=MAX_REDIRECTS {
+        if CTRL_C.load(Ordering::SeqCst) { return Err(FetchError::Aborted); }
+        let url = match ParsedUrl::parse(&current) {
+            Some(u) => u,
+            None => { crate::print_error!("http: malformed URL '{}'", current); return Err(FetchError::BadUrl); }
         };
-        crate::print_success!("http: connected");
-        if !s.send(req_bytes) { crate::print_error!("http: send failed"); s.close(); return None; }
-        let out = Vec::from(s.recv_all(10_000_000));
-        crate::log!("http: {} bytes: 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x} 0x{:02x}", out.len(), out.get(0).copied().unwrap_or(0), out.get(1).copied().unwrap_or(0), out.get(2).copied().unwrap_or(0), out.get(3).copied().unwrap_or(0), out.get(4).copied().unwrap_or(0), out.get(5).copied().unwrap_or(0), out.get(6).copied().unwrap_or(0), out.get(7).copied().unwrap_or(0));
-        s.close();
-        Some(out)
+        let req = HttpRequest { method, path: url.path, host: url.host, body,
+                                headers: &headers, keep_alive: url.use_tls };
+        let redirect = |r: &HttpResponse| f.follow && r.location.is_some()
+            && matches!(r.status, 301 | 302 | 303 | 307 | 308);
+        let resp = fetch_once(&url, &req, f.insecure, &mut |r, d| redirect(r) || sink(r, d))?;
+        if !redirect(&resp) { return Ok(resp); }
+
+        let loc = resp.location.unwrap_or_default();
+        crate::cprintln!(120, 200, 200, "http: redirect {} -> {}", resp.status, loc);
+        if resp.status == 303 || (matches!(resp.status, 301 | 302) && method == "POST") {
+            method = "GET";
+            body   = None;
+        }
+        let next = resolve_location(&url, &loc);
+        let same_origin = ParsedUrl::parse(&next)
+            .is_some_and(|n| n.scheme == url.scheme && n.hostname == url.hostname && n.port == url.port);
+        if !same_origin {
+            headers.retain(|(k, _)| !k.eq_ignore_ascii_case("authorization") && !k.eq_ignore_ascii_case("range"));
+        }
+        current = next;
     }
+    crate::print_error!("http: {}", FetchError::Redirects.as_str());
+    Err(FetchError::Redirects)
+}
+
+fn request(url_str: &str, f: &Fetch) -> Option<HttpResponse> {
+    let mut body = Vec::new();
+    let mut resp = fetch(url_str, f, &mut |_, d| { body.extend_from_slice(d); true }).ok()?;
+    resp.body = body;
+    Some(resp)
 }
 
 pub fn get(url_str: &str) -> Option<HttpResponse> {
-    let mut current = String::from(url_str);
-    for _ in 0..5 {
-        if CTRL_C.load(Ordering::SeqCst) { return None; }
-        let url = ParsedUrl::parse(&current)?;
-        let mut req = Vec::new();
-        HttpRequest { method: "GET", path: url.path, host: url.host,
-                      body: None, headers: &[] }.write_to(&mut req);
-        let raw  = do_request(&url, &req)?;
-        let resp = parse_response(&raw)?;
-
-        match resp.status {
-            301 | 302 | 303 | 307 | 308 => {
-                if let Some(ref loc) = resp.location.clone() {
-                    crate::cprintln!(120, 200, 200, "http: redirect {} -> {}", resp.status, loc);
-                    current = if loc.starts_with("http") {
-                        loc.clone()
-                    } else {
-                        alloc::format!("{}://{}{}", url.scheme, url.host, loc)
-                    };
-                    continue;
-                }
-                return Some(resp);
+    get_with(url_str, false)
+}
+
+pub fn get_with(url_str: &str, insecure: bool) -> Option<HttpResponse> {
+    request(url_str, &Fetch { method: "GET", headers: &[], body: None, insecure, follow: true })
+}
+
+pub fn post(url_str: &str, body: &[u8], content_type: &str) -> Option<HttpResponse> {
+    post_with(url_str, body, content_type, false)
+}
+
+pub fn post_with(url_str: &str, body: &[u8], content_type: &str, insecure: bool) -> Option<HttpResponse> {
+    request(url_str, &Fetch { method: "POST", headers: &[("Content-Type", content_type)],
+                              body: Some(body), insecure, follow: false })
+}
+
+pub fn get_many(urls: &[&str], insecure: bool, follow: bool) -> Vec<Option<HttpResponse>> {
+    let parsed: Vec<ParsedUrl> = urls.iter().filter_map(|u| ParsedUrl::parse(u)).collect();
+    let shared = parsed.len() > 1 && parsed.len() == urls.len() && crate::net::is_ready()
+        && parsed.iter().all(|u| u.use_tls && u.hostname == parsed[0].hostname && u.port == parsed[0].port);
+    if shared {
+        if let Ok(mut s) = tls_connect(&parsed[0], insecure) {
+            if s.is_h2() {
+                crate::cprintln!(57, 197, 187, "http: HTTP/2, {} streams on one connection", parsed.len());
+                let reqs: Vec<H2Request> = parsed.iter()
+                    .map(|u| H2Request { method: "GET", path: u.path, headers: &[], body: None })
+                    .collect();
+                let mut conn = H2Conn::open(&mut s, "https", parsed[0].host);
+                let out = conn.request_all(&reqs);
+                conn.close();
+                s.close();
+                return out.into_iter().map(|r| r.and_then(|r| parse_response(&h2_to_raw(r)))).collect();
             }
-            _ => return Some(resp),
+            release(pool_key(&parsed[0]), s, true);
         }
     }
-    crate::print_error!("http: too many redirects");
-    None
+    urls.iter()
+        .map(|u| request(u, &Fetch { method: "GET", headers: &[], body: None, insecure, follow }))
+        .collect()
 }
 
-pub fn post(url_str: &str, body: &[u8], content_type: &str) -> Option<HttpResponse> {
-    if CTRL_C.load(Ordering::SeqCst) { return None; }
-    let url = ParsedUrl::parse(url_str)?;
-    let mut req = Vec::new();
-    HttpRequest { method: "POST", path: url.path, host: url.host,
-                  body: Some(body), headers: &[("Content-Type", content_type)] }
-        .write_to(&mut req);
-    let raw = do_request(&url, &req)?;
-    parse_response(&raw)
-}
-
-fn vfs_write_bytes(path: &str, data: &[u8]) -> Result<usize, &'static str> {
-    use crate::vfs::{with_vfs, OpenFlags, FileMode};
-    use crate::shell::SESSION;
-
-    let cwd = SESSION.lock().cwd;
-    let fl  = OpenFlags(OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE);
-
-    with_vfs(|v| {
-        let fd = match v.open(cwd, path, fl, FileMode::default_file()) {
-            Ok(fd) => fd,
-            Err(_) => return Err("open failed"),
+struct OutFile {
+    file: Arc<OpenFile>,
+    pos:  u64,
+}
+
+impl OutFile {
+    fn open(path: &str, append: bool) -> Result<Self, &'static str> {
+        let mode = if append { O_APPEND } else { O_TRUNC };
+        let file = fdtable::open(&abs_path(path), O_WRONLY | O_CREAT | mode, 0o644).map_err(errno_str)?;
+        let pos  = file.size().map_err(errno_str)?;
+        Ok(Self { file, pos })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        let n = fdtable::write(self.file.clone(), data).map_err(errno_str)?;
+        self.pos += n as u64;
+        if n < data.len() { Err("short write (disk full?)") } else { Ok(()) }
+    }
+}
+
+fn errno_str(e: i64) -> &'static str {
+    match e {
+        ENOENT          => "no such file or directory",
+        ENOTDIR         => "not a directory",
+        EISDIR          => "is a directory",
+        EACCES | EPERM  => "permission denied",
+        EROFS           => "read-only file system",
+        ENOSPC          => "no space left on device",
+        EFBIG           => "file too large",
+        _               => "I/O error",
+    }
+}
+
+fn file_size(path: &str) -> u64 {
+    fdtable::open(&abs_path(path), O_RDONLY, 0).and_then(|f| f.size()).unwrap_or(0)
+}
+
+fn abs_path(path: &str) -> String {
+    if path.starts_with('/') { return String::from(path); }
+    let s   = crate::shell::SESSION.lock();
+    let cwd = core::str::from_utf8(&s.path[..s.plen]).unwrap_or("/");
+    alloc::format!("{}/{}", cwd.trim_end_matches('/'), path)
+}
+
+fn read_input(path: &str) -> Result<Vec<u8>, String> {
+    crate::vfs_read::read_file(&abs_path(path)).ok_or_else(|| alloc::format!("cannot read '{}'", path))
+}
+
+fn split_args(s: &str) -> Vec<String> {
+    let mut out   = Vec::new();
+    let mut cur   = String::new();
+    let mut quote = None;
+    let mut any   = false;
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c)           => cur.push(c),
+            (None, '"' | '\'')     => { quote = Some(c); any = true; }
+            (None, ' ' | '\t')     => {
+                if any || !cur.is_empty() { out.push(core::mem::take(&mut cur)); }
+                any = false;
+            }
+            (None, c)              => cur.push(c),
+        }
+    }
+    if any || !cur.is_empty() { out.push(cur); }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const T: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for c in data.chunks(3) {
+        let n = (c[0] as u32) << 16
+            | (c.get(1).copied().unwrap_or(0) as u32) << 8
+            | c.get(2).copied().unwrap_or(0) as u32;
+        for i in 0..4 {
+            out.push(if i <= c.len() { T[(n >> (18 - 6 * i)) as usize & 63] as char } else { '=' });
+        }
+    }
+    out
+}
+
+#[derive(Default)]
+struct Opts {
+    urls:      Vec<String>,
+    method:    Option<String>,
+    headers:   Vec<(String, String)>,
+    data:      Option<Vec<u8>>,
+    user:      Option<String>,
+    password:  Option<String>,
+    out:       Option<String>,
+    remote:    bool,
+    resume:    bool,
+    insecure:  bool,
+    fail:      bool,
+    follow:    bool,
+    quiet:     bool,
+    show_hdrs: bool,
+    head:      bool,
+}
+
+impl Opts {
+    fn add_header(&mut self, spec: &str) -> Result<(), String> {
+        if let Some(path) = spec.strip_prefix('@') {
+            let text = read_input(path)?;
+            for line in String::from_utf8_lossy(&text).lines().filter(|l| !l.trim().is_empty()) {
+                self.add_header(line)?;
+            }
+            return Ok(());
+        }
+        let (k, v) = spec.split_once(':').ok_or_else(|| alloc::format!("bad header '{}'", spec))?;
+        self.headers.push((String::from(k.trim()), String::from(v.trim())));
+        Ok(())
+    }
+
+    fn add_data(&mut self, spec: &str, from_file: bool, strip: bool) -> Result<(), String> {
+        let chunk = match spec.strip_prefix('@') {
+            Some(path) if from_file => {
+                let mut d = read_input(path)?;
+                if strip { d.retain(|&b| b != b'\r' && b != b'\n'); }
+                d
+            }
+            _ => Vec::from(spec.as_bytes()),
         };
-        let n = match v.write(fd, data) {
-            Ok(n) => n,
-            Err(_) => { let _ = v.close(fd); return Err("write failed"); }
+        let data = self.data.get_or_insert_with(Vec::new);
+        if !data.is_empty() { data.push(b'&'); }
+        data.extend_from_slice(&chunk);
+        Ok(())
+    }
+
+    fn has_header(&self, name: &str) -> bool {
+        self.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name))
+    }
+
+    fn method(&self) -> &str {
+        match &self.method {
+            Some(m) => m,
+            None if self.head => "HEAD",
+            None if self.data.is_some() => "POST",
+            None => "GET",
+        }
+    }
+}
+
+fn parse_opts(tool: Tool, args: &str) -> Result<Opts, String> {
+    let args = split_args(args);
+    let mut o  = Opts::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if arg.starts_with("--") => (f, Some(String::from(v))),
+            _ => (arg.as_str(), None),
         };
-        let _ = v.close(fd);
-        Ok(n)
-    })
+        let mut val = || inline.clone().or_else(|| it.next().cloned())
+            .ok_or_else(|| alloc::format!("option {} needs a value", flag));
+        match (tool, flag) {
+            (Tool::Curl, "-o" | "--output")        => o.out = Some(val()?),
+            (Tool::Curl, "-O" | "--remote-name")   => o.remote = true,
+            (Tool::Curl, "-C" | "--continue-at")   => {
+                if val()? != "-" { return Err(String::from("only '-C -' is supported")); }
+                o.resume = true;
+            }
+            (Tool::Curl, "-X" | "--request")       => o.method = Some(val()?),
+            (Tool::Curl, "-d" | "--data")          => o.add_data(&val()?, true, true)?,
+            (Tool::Curl, "--data-binary")          => o.add_data(&val()?, true, false)?,
+            (Tool::Curl, "--data-raw")             => o.add_data(&val()?, false, false)?,
+            (Tool::Curl, "-H" | "--header")        => o.add_header(&val()?)?,
+            (Tool::Curl, "-u" | "--user")          => o.user = Some(val()?),
+            (Tool::Curl, "-I" | "--head")          => o.head = true,
+            (Tool::Curl, "-i" | "--include")       => o.show_hdrs = true,
+            (Tool::Curl, "-f" | "--fail")          => o.fail = true,
+            (Tool::Curl, "-s" | "--silent")        => o.quiet = true,
+            (Tool::Curl, "-L" | "--location")      => o.follow = true,
+            (Tool::Wget, "-O" | "--output-document") => o.out = Some(val()?),
+            (Tool::Wget, "-c" | "--continue")      => o.resume = true,
+            (Tool::Wget, "-q" | "--quiet")         => o.quiet = true,
+            (Tool::Wget, "-S" | "--server-response") => o.show_hdrs = true,
+            (Tool::Wget, "--header")               => o.add_header(&val()?)?,
+            (Tool::Wget, "--method")               => o.method = Some(val()?),
+            (Tool::Wget, "--body-data" | "--post-data") => o.add_data(&val()?, false, false)?,
+            (Tool::Wget, "--body-file" | "--post-file") => {
+                let path = alloc::format!("@{}", val()?);
+                o.add_data(&path, true, false)?;
+            }
+            (Tool::Wget, "--user" | "--http-user") => o.user = Some(val()?),
+            (Tool::Wget, "--password" | "--http-password") => o.password = Some(val()?),
+            (_, "-k" | "--insecure" | "--no-check-certificate") => o.insecure = true,
+            (_, f) if f.starts_with('-') && f.len() > 1 => return Err(alloc::format!("unknown option {}", f)),
+            _ => o.urls.push(arg.clone()),
+        }
+    }
+    if o.urls.is_empty() { return Err(String::from("no URL specified")); }
+    if tool == Tool::Wget { o.follow = true; }
+    if let Some(user) = o.user.take() {
+        let cred = match (&o.password, user.contains(':')) {
+            (Some(p), _) => alloc::format!("{}:{}", user, p),
+            (None, true) => user,
+            (None, false) => alloc::format!("{}:", user),
+        };
+        o.headers.push((String::from("Authorization"), alloc::format!("Basic {}", base64(cred.as_bytes()))));
+    }
+    if o.data.is_some() && !o.has_header("content-type") {
+        o.headers.push((String::from("Content-Type"), String::from("application/x-www-form-urlencoded")));
+    }
+    Ok(o)
 }
 
-pub fn cmd_wget(args: &str) {
-    super::CTRL_C.store(false, Ordering::SeqCst);
-    let mut parts   = args.split_whitespace();
-    let url_str     = match parts.next() {
-        Some(u) => u,
-        None => { crate::println!("Usage: wget <url> [-O <file>]"); return; }
-    };
+#[derive(Clone, Copy, PartialEq)]
+enum Tool {
+    Curl,
+    Wget,
+}
 
-    let mut out_path: Option<&str> = None;
-    while let Some(flag) = parts.next() {
-        if flag == "-O" || flag == "-o" { out_path = parts.next(); }
+impl Tool {
+    fn name(self) -> &'static str {
+        match self { Tool::Curl => "curl", Tool::Wget => "wget" }
     }
 
-    let inferred;
-    let save_as = match out_path {
-        Some(p) => p,
-        None => { inferred = infer_filename(url_str); inferred.as_str() }
-    };
+    fn code(self, e: FetchError) -> u8 {
+        match self {
+            Tool::Curl => match e {
+                FetchError::BadUrl    => 3,
+                FetchError::Resolve   => 6,
+                FetchError::NotReady | FetchError::Connect => 7,
+                FetchError::Timeout   => 28,
+                FetchError::Tls       => 35,
+                FetchError::Aborted   => 42,
+                FetchError::Redirects => 47,
+                FetchError::Send      => 55,
+                FetchError::Recv      => 56,
+                FetchError::Cert      => 60,
+            },
+            Tool::Wget => match e {
+                FetchError::BadUrl | FetchError::Aborted => 1,
+                FetchError::Cert      => 5,
+                FetchError::Redirects => 7,
+                _                     => 4,
+            },
+        }
+    }
 
-    crate::cprintln!(57, 197, 187, "wget: {}", url_str);
+    fn io_code(self) -> u8 {
+        match self { Tool::Curl => 23, Tool::Wget => 3 }
+    }
 
-    let resp = match get(url_str) { Some(r) => r, None => return };
+    fn http_code(self) -> u8 {
+        match self { Tool::Curl => 22, Tool::Wget => 8 }
+    }
 
-    let sc = status_color(resp.status);
-    crate::cprintln!(sc.0, sc.1, sc.2, "HTTP {} {}", resp.status, resp.reason);
+    fn range_code(self) -> u8 {
+        match self { Tool::Curl => 33, Tool::Wget => 8 }
+    }
+}
+
+fn fmt_size(n: u64) -> String {
+    if n < 1024 {
+        alloc::format!("{} B", n)
+    } else if n < 1024 * 1024 {
+        alloc::format!("{}.{} KB", n / 1024, n % 1024 * 10 / 1024)
+    } else {
+        alloc::format!("{}.{} MB", n >> 20, ((n & 0xFFFFF) * 10) >> 20)
+    }
+}
 
-    if resp.body.is_empty() { crate::print_warn!("wget: empty response"); return; }
-    crate::cprintln!(120, 200, 200, "wget: {} bytes received", resp.body.len());
+fn rate(bytes: u64, since: u64) -> u64 {
+    bytes * crate::interrupts::PIT_HZ as u64 / ticks().saturating_sub(since).max(1)
+}
 
-    match vfs_write_bytes(save_as, &resp.body) {
-        Ok(n)  => crate::print_success!("wget: '{}' saved ({} bytes)", save_as, n),
-        Err(e) => {
-            crate::print_error!("wget: save failed ({}), printing to screen:", e);
-            print_body(&resp.body, 4096);
+struct Progress {
+    start: u64,
+    last:  u64,
+    base:  u64,
+    total: Option<u64>,
+}
+
+impl Progress {
+    fn draw(&self, done: u64) {
+        let got  = self.base + done;
+        let line = match self.total {
+            Some(t) if t > 0 => alloc::format!("  {:>3}%  {} / {}  {}/s",
+                got.min(t) * 100 / t, fmt_size(got), fmt_size(t), fmt_size(rate(done, self.start))),
+            _ => alloc::format!("  {}  {}/s", fmt_size(got), fmt_size(rate(done, self.start))),
+        };
+        crate::cprint!(120, 200, 200, "\r{:<48}", line);
+    }
+
+    fn update(&mut self, done: u64) {
+        let now = ticks();
+        if now.saturating_sub(self.last) >= crate::interrupts::PIT_HZ as u64 / 4 {
+            self.last = now;
+            self.draw(done);
         }
     }
 }
 
-pub fn cmd_curl(args: &str) {
-    super::CTRL_C.store(false, Ordering::SeqCst);
-    let mut parts   = args.split_whitespace();
-    let url_str     = match parts.next() {
-        Some(u) => u,
-        None => {
-            crate::println!("Usage: curl <url> [-X GET|POST] [-d <data>] [-o <file>] [-I]");
-            return;
+enum Outcome {
+    Done,
+    HttpError(u16),
+    BadRange(Option<u64>),
+    Complete,
+    WriteError(&'static str),
+}
+
+struct Xfer<'a> {
+    tool:     Tool,
+    opts:     &'a Opts,
+    save_as:  Option<&'a str>,
+    file:     Option<OutFile>,
+    started:  bool,
+    offset:   u64,
+    start:    u64,
+    received: u64,
+    shown:    usize,
+    progress: Option<Progress>,
+    outcome:  Outcome,
+}
+
+impl Xfer<'_> {
+    fn on_head(&mut self, r: &HttpResponse) -> bool {
+        let sc = status_color(r.status);
+        match self.tool {
+            Tool::Curl => crate::cprintln!(sc.0, sc.1, sc.2, "< HTTP/1.1 {} {}", r.status, r.reason),
+            Tool::Wget => crate::cprintln!(sc.0, sc.1, sc.2, "HTTP {} {}", r.status, r.reason),
         }
-    };
+        if self.opts.show_hdrs || self.opts.head {
+            for (k, v) in &r.headers {
+                crate::cprintln!(120, 200, 200, "< {}: {}", k, v);
+            }
+        }
+        if r.status >= 400 && (self.opts.fail || self.tool == Tool::Wget) {
+            self.outcome = Outcome::HttpError(r.status);
+            return false;
+        }
+        if self.offset > 0 && r.status == 416 {
+            self.outcome = Outcome::Complete;
+            return false;
+        }
+        let path = match self.save_as {
+            Some(p) if !self.opts.head => p,
+            _ => return true,
+        };
 
-    let mut method    = "GET";
-    let mut post_data: Option<&str> = None;
-    let mut out_path:  Option<&str> = None;
-    let mut show_hdrs = false;
+        let mut total = r.content_length.map(|n| n as u64);
+        if self.offset > 0 {
+            if r.status == 206 {
+                let start = r.header("content-range")
+                    .and_then(|v| v.trim().strip_prefix("bytes "))
+                    .and_then(|v| v.split('-').next())
+                    .and_then(|s| s.trim().parse().ok());
+                if start != Some(self.offset) {
+                    self.outcome = Outcome::BadRange(start);
+                    return false;
+                }
+                crate::print_info!("{}: resuming at byte {}", self.tool.name(), self.offset);
+                total = r.header("content-range")
+                    .and_then(|v| v.rsplit('/').next())
+                    .and_then(|t| t.trim().parse().ok())
+                    .or(total.map(|n| n + self.offset));
+            } else {
+                if (200..300).contains(&r.status) {
+                    crate::print_warn!("{}: server ignored the range, restarting", self.tool.name());
+                }
+                self.offset = 0;
+            }
+        }
+        match OutFile::open(path, self.offset > 0) {
+            Ok(f)  => self.file = Some(f),
+            Err(e) => { self.outcome = Outcome::WriteError(e); return false; }
+        }
+        if !self.opts.quiet {
+            let now = ticks();
+            self.progress = Some(Progress { start: now, last: now, base: self.offset, total });
+        }
+        true
+    }
 
-    while let Some(flag) = parts.next() {
-        match flag {
-            "-X"        => { method = parts.next().unwrap_or("GET"); }
-            "-d"        => { post_data = parts.next(); method = "POST"; }
-            "-o" | "-O" => { out_path  = parts.next(); }
-            "-I" | "-i" => { show_hdrs = true; }
-            _ => {}
+    fn on_data(&mut self, r: &HttpResponse, data: &[u8]) -> bool {
+        if !self.started {
+            self.started = true;
+            if !self.on_head(r) { return false; }
         }
+        if data.is_empty() { return true; }
+        self.received += data.len() as u64;
+        if let Some(f) = self.file.as_mut() {
+            if let Err(e) = f.write(data) {
+                self.outcome = Outcome::WriteError(e);
+                return false;
+            }
+        } else if self.shown < SCREEN_LIMIT {
+            let take = data.len().min(SCREEN_LIMIT - self.shown);
+            crate::print!("{}", printable(&data[..take]));
+            self.shown += take;
+        }
+        if let Some(p) = self.progress.as_mut() { p.update(self.received); }
+        true
     }
 
-    crate::cprintln!(57, 197, 187, "curl: {} {}", method, url_str);
+    fn finish(self, res: Result<HttpResponse, FetchError>) -> u8 {
+        let name = self.tool.name();
+        if let Some(p) = &self.progress {
+            p.draw(self.received);
+            crate::println!();
+        }
+        if self.shown > 0 {
+            crate::println!();
+            if self.received > self.shown as u64 {
+                crate::cprintln!(120, 140, 140, "... ({} bytes total, showing {})", self.received, self.shown);
+            }
+        }
+        let saved = self.file.as_ref().map(|f| f.pos);
+        drop(self.file);
 
-    let resp = if method == "POST" {
-        post(url_str, post_data.unwrap_or("").as_bytes(),
-             "application/x-www-form-urlencoded")
-    } else {
-        get(url_str)
+        let resp = match res {
+            Ok(r) => r,
+            Err(e) => {
+                if let (Some(path), true) = (self.save_as, self.received > 0) {
+                    crate::print_warn!("{}: '{}' is incomplete ({} bytes), retry with {} to resume",
+                        name, path, self.offset + self.received,
+                        if self.tool == Tool::Curl { "-C -" } else { "-c" });
+                }
+                return self.tool.code(e);
+            }
+        };
+        match self.outcome {
+            Outcome::WriteError(e) => {
+                crate::print_error!("{}: cannot write '{}': {}", name, self.save_as.unwrap_or(""), e);
+                self.tool.io_code()
+            }
+            Outcome::HttpError(s) => {
+                crate::print_error!("{}: server returned HTTP {}", name, s);
+                self.tool.http_code()
+            }
+            Outcome::BadRange(start) => {
+                match start {
+                    Some(s) => crate::print_error!("{}: server resumed at byte {} instead of {}", name, s, self.offset),
+                    None    => crate::print_error!("{}: partial response without a usable Content-Range", name),
+                }
+                self.tool.range_code()
+            }
+            Outcome::Complete => {
+                crate::print_success!("{}: '{}' is already complete", name, self.save_as.unwrap_or(""));
+                0
+            }
+            Outcome::Done => {
+                match (saved, self.save_as) {
+                    (Some(size), Some(path)) => crate::print_success!(
+                        "{}: '{}' saved ({} bytes, {}/s)", name, path, size, fmt_size(rate(self.received, self.start))),
+                    _ if self.received == 0 && !self.opts.head && resp.status != 204 =>
+                        crate::print_warn!("{}: empty body", name),
+                    _ => {}
+                }
+                0
+            }
+        }
+    }
+}
+
+fn transfer(tool: Tool, url: &str, o: &Opts, save_as: Option<&str>) -> u8 {
+    let offset = match save_as {
+        Some(p) if o.resume => file_size(p),
+        _ => 0,
     };
+    let range = alloc::format!("bytes={}-", offset);
+    let mut headers: Vec<(&str, &str)> = o.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+    if offset > 0 { headers.push(("Range", range.as_str())); }
 
-    let resp = match resp { Some(r) => r, None => return };
+    crate::cprintln!(57, 197, 187, "{}: {} {}", tool.name(), o.method(), url);
+    let f = Fetch { method: o.method(), headers: &headers, body: o.data.as_deref(),
+                    insecure: o.insecure, follow: o.follow };
+    let mut x = Xfer { tool, opts: o, save_as, file: None, started: false, offset, start: ticks(),
+                       received: 0, shown: 0, progress: None, outcome: Outcome::Done };
+    let res = fetch(url, &f, &mut |r, d| x.on_data(r, d));
+    x.finish(res)
+}
 
+pub fn cmd_wget(args: &str) -> u8 {
+    super::CTRL_C.store(false, Ordering::SeqCst);
+    let o = match parse_opts(Tool::Wget, args) {
+        Ok(o) => o,
+        Err(e) => {
+            crate::print_error!("wget: {}", e);
+            crate::println!("Usage: wget <url>... [-O <file>] [-c] [-q] [--header <h>] [--method <m>]");
+            crate::println!("       [--post-data <s>] [--post-file <f>] [--user <u>] [--password <p>] [-S] [-k]");
+            return EXIT_USAGE;
+        }
+    };
+    if o.out.is_some() && o.urls.len() > 1 {
+        crate::print_error!("wget: -O with several URLs is not supported");
+        return EXIT_USAGE;
+    }
+    let mut code = 0;
+    for url in &o.urls {
+        let inferred = infer_filename(url);
+        let save_as  = match o.out.as_deref() {
+            Some("-") => None,
+            Some(p)   => Some(p),
+            None      => Some(inferred.as_str()),
+        };
+        let rc = transfer(Tool::Wget, url, &o, save_as);
+        if rc != 0 { code = rc; }
+    }
+    code
+}
+
+pub fn cmd_curl(args: &str) -> u8 {
+    super::CTRL_C.store(false, Ordering::SeqCst);
+    let o = match parse_opts(Tool::Curl, args) {
+        Ok(o) => o,
+        Err(e) => {
+            crate::print_error!("curl: {}", e);
+            crate::println!("Usage: curl <url>... [-o <file>|-O] [-C -] [-H <h>|@file] [-X <m>] [-d <s>|@file]");
+            crate::println!("       [--data-binary @file] [-u user:pass] [-I] [-i] [-f] [-s] [-L] [-k]");
+            return EXIT_USAGE;
+        }
+    };
+
+    let plain = o.method.is_none() && o.data.is_none() && o.headers.is_empty() && o.out.is_none()
+        && !o.remote && !o.head && !o.fail;
+    if o.urls.len() > 1 && plain {
+        let urls: Vec<&str> = o.urls.iter().map(|u| u.as_str()).collect();
+        let mut code = 0;
+        for (url, resp) in urls.iter().zip(get_many(&urls, o.insecure, o.follow)) {
+            crate::cprintln!(57, 197, 187, "curl: GET {}", url);
+            match resp {
+                Some(r) => curl_output(&r, o.show_hdrs),
+                None    => { crate::print_error!("curl: {} failed", url); code = Tool::Curl.code(FetchError::Recv); }
+            }
+        }
+        return code;
+    }
+
+    let mut code = 0;
+    for (i, url) in o.urls.iter().enumerate() {
+        let inferred = infer_filename(url);
+        let save_as  = if o.remote {
+            Some(inferred.as_str())
+        } else if i == 0 {
+            o.out.as_deref()
+        } else {
+            None
+        };
+        let rc = transfer(Tool::Curl, url, &o, save_as);
+        if rc != 0 { code = rc; }
+    }
+    code
+}
+
+fn curl_output(resp: &HttpResponse, show_hdrs: bool) {
     let sc = status_color(resp.status);
     crate::cprintln!(sc.0, sc.1, sc.2, "< HTTP/1.1 {} {}", resp.status, resp.reason);
 
     if show_hdrs {
-        if let Some(cl) = resp.content_length {
-            crate::cprintln!(120, 200, 200, "< Content-Length: {}", cl);
-        }
-        if resp.chunked {
-            crate::cprintln!(120, 200, 200, "< Transfer-Encoding: chunked");
+        for (k, v) in &resp.headers {
+            crate::cprintln!(120, 200, 200, "< {}: {}", k, v);
         }
         crate::println!("");
     }
 
     if resp.body.is_empty() { crate::print_warn!("curl: empty body"); return; }
     crate::cprintln!(120, 200, 200, "curl: {} bytes", resp.body.len());
-
-    match out_path {
-        Some(path) => match vfs_write_bytes(path, &resp.body) {
-            Ok(n)  => crate::print_success!("curl: saved '{}' ({} bytes)", path, n),
-            Err(e) => crate::print_error!("curl: save failed: {}", e),
-        },
-        None => print_body(&resp.body, 8192),
-    }
+    print_body(&resp.body, SCREEN_LIMIT);
 }
 
-fn print_body(body: &[u8], limit: usize) {
-    let show = body.len().min(limit);
-    let mut s = String::with_capacity(show);
-    for &b in &body[..show] {
+fn printable(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len());
+    for &b in data {
         match b {
             b'\n' | b'\t' => s.push(b as char),
             b'\r'         => {}
@@ -446,7 +1312,12 @@ fn print_body(body: &[u8], limit: usize) {
             _             => s.push('.'),
         }
     }
-    crate::println!("{}", s);
+    s
+}
+
+fn print_body(body: &[u8], limit: usize) {
+    let show = body.len().min(limit);
+    crate::println!("{}", printable(&body[..show]));
     if body.len() > show {
         crate::cprintln!(120, 140, 140,
             "... ({} bytes total, showing {})", body.len(), limit);
@@ -466,12 +1337,6 @@ fn status_color(s: u16) -> (u8, u8, u8) {
     if s < 300 { (100, 220, 150) } else if s < 400 { (57, 197, 187) } else { (255, 80, 80) }
 }
 
-fn parse_ip(s: &str) -> Option<[u8; 4]> {
-    let mut p = s.split('.');
-    Some([p.next()?.parse().ok()?, p.next()?.parse().ok()?,
-          p.next()?.parse().ok()?, p.next()?.parse().ok()?])
-}
-
 fn fmt_u64(mut n: u64, buf: &mut [u8; 20]) -> usize {
     if n == 0 { buf[0] = b'0'; return 1; }
     let mut tmp = [0u8; 20]; let mut len = 0;
@@ -1,7 +1,9 @@
 extern crate alloc;
-use alloc::vec::Vec;
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::sync::atomic::Ordering;
+use super::hpack::{Decoder, Encoder, DEFAULT_TABLE_SIZE};
+use super::tls::TlsStream;
 use super::CTRL_C;
 
 const CLIENT_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
@@ -10,217 +12,66 @@ const FT_DATA:          u8 = 0x0;
 const FT_HEADERS:       u8 = 0x1;
 const FT_RST_STREAM:    u8 = 0x3;
 const FT_SETTINGS:      u8 = 0x4;
+const FT_PUSH_PROMISE:  u8 = 0x5;
 const FT_PING:          u8 = 0x6;
 const FT_GOAWAY:        u8 = 0x7;
 const FT_WINDOW_UPDATE: u8 = 0x8;
 const FT_CONTINUATION:  u8 = 0x9;
 
 const FL_END_STREAM:  u8 = 0x1;
-const FL_END_HEADERS: u8 = 0x4;
 const FL_ACK:         u8 = 0x1;
+const FL_END_HEADERS: u8 = 0x4;
+const FL_PADDED:      u8 = 0x8;
+const FL_PRIORITY:    u8 = 0x20;
 
 const SETTINGS_HEADER_TABLE_SIZE:      u16 = 0x1;
 const SETTINGS_ENABLE_PUSH:            u16 = 0x2;
 const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
 const SETTINGS_INITIAL_WINDOW_SIZE:    u16 = 0x4;
 const SETTINGS_MAX_FRAME_SIZE:         u16 = 0x5;
-
-const INIT_WINDOW: u32 = 65535;
-const MAX_FRAME:   u32 = 16384;
-
-static HPACK_STATIC: &[(&[u8], &[u8])] = &[
-    (b":authority",                  b""),
-    (b":method",                     b"GET"),
-    (b":method",                     b"POST"),
-    (b":path",                       b"/"),
-    (b":path",                       b"/index.html"),
-    (b":scheme",                     b"http"),
-    (b":scheme",                     b"https"),
-    (b":status",                     b"200"),
-    (b":status",                     b"204"),
-    (b":status",                     b"206"),
-    (b":status",                     b"304"),
-    (b":status",                     b"400"),
-    (b":status",                     b"404"),
-    (b":status",                     b"500"),
-    (b"accept-charset",              b""),
-    (b"accept-encoding",             b"gzip, deflate"),
-    (b"accept-language",             b""),
-    (b"accept-ranges",               b""),
-    (b"accept",                      b""),
-    (b"access-control-allow-origin", b""),
-    (b"age",                         b""),
-    (b"allow",                       b""),
-    (b"authorization",               b""),
-    (b"cache-control",               b""),
-    (b"content-disposition",         b""),
-    (b"content-encoding",            b""),
-    (b"content-language",            b""),
-    (b"content-length",              b""),
-    (b"content-location",            b""),
-    (b"content-range",               b""),
-    (b"content-type",                b""),
-    (b"cookie",                      b""),
-    (b"date",                        b""),
-    (b"etag",                        b""),
-    (b"expect",                      b""),
-    (b"expires",                     b""),
-    (b"from",                        b""),
-    (b"host",                        b""),
-    (b"if-match",                    b""),
-    (b"if-modified-since",           b""),
-    (b"if-none-match",               b""),
-    (b"if-range",                    b""),
-    (b"if-unmodified-since",         b""),
-    (b"last-modified",               b""),
-    (b"link",                        b""),
-    (b"location",                    b""),
-    (b"max-forwards",                b""),
-    (b"proxy-authenticate",          b""),
-    (b"proxy-authorization",         b""),
-    (b"range",                       b""),
-    (b"referer",                     b""),
-    (b"refresh",                     b""),
-    (b"retry-after",                 b""),
-    (b"server",                      b""),
-    (b"set-cookie",                  b""),
-    (b"strict-transport-security",   b""),
-    (b"transfer-encoding",           b""),
-    (b"user-agent",                  b""),
-    (b"vary",                        b""),
-    (b"via",                         b""),
-    (b"www-authenticate",            b""),
-];
-
-fn hpack_static_index(name: &[u8], value: &[u8]) -> Option<usize> {
-    HPACK_STATIC.iter().enumerate()
-        .find(|(_, &(n, v))| n == name && v == value)
-        .map(|(i, _)| i + 1)
-}
-
-fn hpack_static_name_index(name: &[u8]) -> Option<usize> {
-    HPACK_STATIC.iter().enumerate()
-        .find(|(_, &(n, _))| n == name)
-        .map(|(i, _)| i + 1)
-}
-
-fn hpack_encode_int(prefix_bits: u8, value: usize, buf: &mut Vec<u8>, first_byte_prefix: u8) {
-    let max = (1usize << prefix_bits) - 1;
-    if value < max {
-        buf.push(first_byte_prefix | value as u8);
-    } else {
-        buf.push(first_byte_prefix | max as u8);
-        let mut v = value - max;
-        loop {
-            if v < 128 { buf.push(v as u8); break; }
-            buf.push((v & 0x7F) as u8 | 0x80);
-            v >>= 7;
-        }
-    }
-}
-
-fn hpack_encode_literal(buf: &mut Vec<u8>, s: &[u8]) {
-    hpack_encode_int(7, s.len(), buf, 0x00);
-    buf.extend_from_slice(s);
-}
-
-fn hpack_encode_header(buf: &mut Vec<u8>, name: &[u8], value: &[u8]) {
-    if let Some(idx) = hpack_static_index(name, value) {
-        buf.push(0x80 | idx as u8);
-        return;
-    }
-    if let Some(idx) = hpack_static_name_index(name) {
-        hpack_encode_int(4, idx, buf, 0x00);
-    } else {
-        buf.push(0x00);
-        hpack_encode_literal(buf, name);
+const SETTINGS_MAX_HEADER_LIST_SIZE:   u16 = 0x6;
+
+const ERR_NO_ERROR:       u32 = 0x0;
+const ERR_PROTOCOL:       u32 = 0x1;
+const ERR_INTERNAL:       u32 = 0x2;
+const ERR_FLOW_CONTROL:   u32 = 0x3;
+const ERR_FRAME_SIZE:     u32 = 0x6;
+const ERR_REFUSED_STREAM: u32 = 0x7;
+const ERR_CANCEL:         u32 = 0x8;
+const ERR_COMPRESSION:    u32 = 0x9;
+const ERR_CALM:           u32 = 0xB;
+
+const DEFAULT_WINDOW: i64   = 65535;
+const MAX_WINDOW:     i64   = (1 << 31) - 1;
+const STREAM_WINDOW:  u32   = 1 << 20;
+const CONN_WINDOW:    u32   = 1 << 24;
+const MAX_FRAME:      usize = 16384;
+const MAX_HEADERS:    usize = 64 * 1024;
+const MAX_BODY:       usize = 64 << 20;
+const IDLE_SECS:      usize = 30;
+
+fn error_name(code: u32) -> &'static str {
+    match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xA => "CONNECT_ERROR",
+        0xB => "ENHANCE_YOUR_CALM",
+        0xC => "INADEQUATE_SECURITY",
+        0xD => "HTTP_1_1_REQUIRED",
+        _   => "UNKNOWN",
     }
-    hpack_encode_literal(buf, value);
 }
 
-pub fn hpack_encode_request(method: &str, scheme: &str, authority: &str, path: &str) -> Vec<u8> {
-    let mut out = Vec::new();
-    hpack_encode_header(&mut out, b":method",    method.as_bytes());
-    hpack_encode_header(&mut out, b":scheme",    scheme.as_bytes());
-    hpack_encode_header(&mut out, b":path",      path.as_bytes());
-    hpack_encode_header(&mut out, b":authority", authority.as_bytes());
-    hpack_encode_header(&mut out, b"user-agent", b"MikuOS/0.1");
-    hpack_encode_header(&mut out, b"accept",     b"*/*");
-    out
-}
-
-fn hpack_decode_int(data: &[u8], pos: &mut usize, prefix_bits: u8) -> usize {
-    if *pos >= data.len() { return 0; }
-    let mask  = (1u8 << prefix_bits) - 1;
-    let first = (data[*pos] & mask) as usize;
-    *pos += 1;
-    if first < mask as usize { return first; }
-    let mut value = first;
-    let mut shift = 0usize;
-    while *pos < data.len() {
-        let b = data[*pos]; *pos += 1;
-        value += ((b & 0x7F) as usize) << shift;
-        shift += 7;
-        if b & 0x80 == 0 { break; }
-    }
-    value
-}
-
-fn hpack_decode_string(data: &[u8], pos: &mut usize) -> Vec<u8> {
-    if *pos >= data.len() { return Vec::new(); }
-    let huffman = data[*pos] & 0x80 != 0;
-    let len     = hpack_decode_int(data, pos, 7);
-    if *pos + len > data.len() { return Vec::new(); }
-    let s = &data[*pos..*pos + len];
-    *pos += len;
-    if huffman { huffman_decode(s) } else { Vec::from(s) }
-}
-
-fn decode_headers_block(block: &[u8]) -> (u16, Option<String>) {
-    let mut pos      = 0usize;
-    let mut status   = 0u16;
-    let mut location = None;
-
-    while pos < block.len() {
-        let b = block[pos];
-
-        let (name, value) = if b & 0x80 != 0 {
-            let idx = hpack_decode_int(block, &mut pos, 7);
-            if idx == 0 || idx > HPACK_STATIC.len() { continue; }
-            (Vec::from(HPACK_STATIC[idx-1].0), Vec::from(HPACK_STATIC[idx-1].1))
-        } else if b & 0x40 != 0 {
-            let idx = hpack_decode_int(block, &mut pos, 6);
-            let n = if idx > 0 && idx <= HPACK_STATIC.len() {
-                Vec::from(HPACK_STATIC[idx-1].0)
-            } else {
-                hpack_decode_string(block, &mut pos)
-            };
-            let v = hpack_decode_string(block, &mut pos);
-            (n, v)
-        } else if b & 0x20 != 0 {
-            hpack_decode_int(block, &mut pos, 5);
-            continue;
-        } else {
-            let idx = hpack_decode_int(block, &mut pos, 4);
-            let n = if idx > 0 && idx <= HPACK_STATIC.len() {
-                Vec::from(HPACK_STATIC[idx-1].0)
-            } else {
-                hpack_decode_string(block, &mut pos)
-            };
-            let v = hpack_decode_string(block, &mut pos);
-            (n, v)
-        };
-
-        if name == b":status" {
-            status = core::str::from_utf8(&value).ok()
-                .and_then(|s| s.parse().ok()).unwrap_or(0);
-        } else if name == b"location" {
-            if let Ok(s) = core::str::from_utf8(&value) {
-                location = Some(String::from(s));
-            }
-        }
-    }
-    (status, location)
+fn be32(b: &[u8]) -> u32 {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
 }
 
 fn make_frame(ft: u8, flags: u8, sid: u32, payload: &[u8], out: &mut Vec<u8>) {
@@ -230,231 +81,578 @@ fn make_frame(ft: u8, flags: u8, sid: u32, payload: &[u8], out: &mut Vec<u8>) {
     out.push(len         as u8);
     out.push(ft);
     out.push(flags);
-    out.push(((sid >> 24) & 0x7F) as u8);
-    out.push((sid >> 16) as u8);
-    out.push((sid >> 8)  as u8);
-    out.push(sid         as u8);
+    out.extend_from_slice(&(sid & 0x7FFF_FFFF).to_be_bytes());
     out.extend_from_slice(payload);
 }
 
-fn settings_frame(settings: &[(u16, u32)]) -> Vec<u8> {
-    let mut payload = Vec::new();
-    for &(id, val) in settings {
-        payload.push((id  >> 8) as u8); payload.push(id  as u8);
-        payload.push((val >> 24) as u8); payload.push((val >> 16) as u8);
-        payload.push((val >> 8)  as u8); payload.push(val         as u8);
+fn frame_body(flags: u8, payload: &[u8], priority: bool) -> Option<&[u8]> {
+    let mut body = payload;
+    let mut pad  = 0;
+    if flags & FL_PADDED != 0 {
+        pad  = *body.first()? as usize;
+        body = &body[1..];
     }
-    let mut out = Vec::new();
-    make_frame(FT_SETTINGS, 0, 0, &payload, &mut out);
-    out
-}
-
-fn settings_ack() -> Vec<u8> {
-    let mut out = Vec::new();
-    make_frame(FT_SETTINGS, FL_ACK, 0, &[], &mut out);
-    out
+    if priority && flags & FL_PRIORITY != 0 {
+        body = body.get(5..)?;
+    }
+    body.get(..body.len().checked_sub(pad)?)
 }
 
-fn window_update(sid: u32, inc: u32) -> Vec<u8> {
-    let p = [(( inc >> 24) & 0x7F) as u8, (inc >> 16) as u8, (inc >> 8) as u8, inc as u8];
-    let mut out = Vec::new();
-    make_frame(FT_WINDOW_UPDATE, 0, sid, &p, &mut out);
-    out
+pub struct H2Request<'a> {
+    pub method:  &'a str,
+    pub path:    &'a str,
+    pub headers: &'a [(&'a str, &'a str)],
+    pub body:    Option<&'a [u8]>,
 }
 
 pub struct H2Response {
     pub status:   u16,
+    pub headers:  Vec<(String, String)>,
     pub body:     Vec<u8>,
     pub location: Option<String>,
 }
 
-pub fn h2_request(
-    tls:    &mut super::tls::TlsStream,
-    method: &str,
-    scheme: &str,
-    host:   &str,
-    path:   &str,
-    body:   Option<&[u8]>,
-) -> Option<H2Response> {
-    if CTRL_C.load(Ordering::SeqCst) { return None; }
+struct Stream {
+    id:           u32,
+    send_window:  i64,
+    recv_unacked: u32,
+    body:         Vec<u8>,
+    sent:         usize,
+    local_done:   bool,
+    remote_done:  bool,
+    got_final:    bool,
+    error:        Option<u32>,
+    resp:         H2Response,
+}
+
+pub struct H2Conn<'a> {
+    tls:               &'a mut TlsStream,
+    scheme:            &'a str,
+    authority:         &'a str,
+    encoder:           Encoder,
+    decoder:           Decoder,
+    streams:           Vec<Stream>,
+    next_id:           u32,
+    send_window:       i64,
+    recv_unacked:      u32,
+    peer_window:       i64,
+    peer_max_frame:    usize,
+    peer_max_streams:  usize,
+    header_block:      Vec<u8>,
+    header_stream:     u32,
+    header_end_stream: bool,
+    buf:               Vec<u8>,
+    goaway:            Option<u32>,
+    dead:              bool,
+}
+
+impl<'a> H2Conn<'a> {
+    pub fn open(tls: &'a mut TlsStream, scheme: &'a str, authority: &'a str) -> Self {
+        let mut settings = Vec::new();
+        for (id, val) in [
+            (SETTINGS_HEADER_TABLE_SIZE,      DEFAULT_TABLE_SIZE as u32),
+            (SETTINGS_ENABLE_PUSH,            0),
+            (SETTINGS_MAX_CONCURRENT_STREAMS, 100),
+            (SETTINGS_INITIAL_WINDOW_SIZE,    STREAM_WINDOW),
+            (SETTINGS_MAX_FRAME_SIZE,         MAX_FRAME as u32),
+            (SETTINGS_MAX_HEADER_LIST_SIZE,   MAX_HEADERS as u32),
+        ] {
+            settings.extend_from_slice(&id.to_be_bytes());
+            settings.extend_from_slice(&val.to_be_bytes());
+        }
+        let mut out = Vec::from(CLIENT_PREFACE);
+        make_frame(FT_SETTINGS, 0, 0, &settings, &mut out);
+        make_frame(FT_WINDOW_UPDATE, 0, 0, &(CONN_WINDOW - DEFAULT_WINDOW as u32).to_be_bytes(), &mut out);
+        let dead = !tls.send(&out);
+
+        Self {
+            tls,
+            scheme,
+            authority,
+            encoder:           Encoder::new(),
+            decoder:           Decoder::new(DEFAULT_TABLE_SIZE),
+            streams:           Vec::new(),
+            next_id:           1,
+            send_window:       DEFAULT_WINDOW,
+            recv_unacked:      0,
+            peer_window:       DEFAULT_WINDOW,
+            peer_max_frame:    MAX_FRAME,
+            peer_max_streams:  100,
+            header_block:      Vec::new(),
+            header_stream:     0,
+            header_end_stream: false,
+            buf:               Vec::new(),
+            goaway:            None,
+            dead,
+        }
+    }
+
+    fn send_frame(&mut self, ft: u8, flags: u8, sid: u32, payload: &[u8]) {
+        if self.dead { return; }
+        let mut f = Vec::with_capacity(9 + payload.len());
+        make_frame(ft, flags, sid, payload, &mut f);
+        if !self.tls.send(&f) { self.dead = true; }
+    }
+
+    fn conn_error(&mut self, code: u32) {
+        if self.dead { return; }
+        crate::log_err!("h2: connection error {}", error_name(code));
+        let mut p = [0u8; 8];
+        p[4..].copy_from_slice(&code.to_be_bytes());
+        self.send_frame(FT_GOAWAY, 0, 0, &p);
+        self.dead = true;
+    }
+
+    fn reset(&mut self, i: usize, code: u32) {
+        let id = self.streams[i].id;
+        self.send_frame(FT_RST_STREAM, 0, id, &code.to_be_bytes());
+        let st = &mut self.streams[i];
+        if !st.remote_done { st.error = Some(code); }
+        st.local_done  = true;
+        st.remote_done = true;
+    }
+
+    fn index(&self, id: u32) -> Option<usize> {
+        self.streams.iter().position(|s| s.id == id)
+    }
+
+    pub fn is_dead(&self) -> bool { self.dead }
 
-    tls.send(CLIENT_PREFACE);
-    tls.send(&settings_frame(&[
-        (SETTINGS_HEADER_TABLE_SIZE,      4096),
-        (SETTINGS_ENABLE_PUSH,            0),
-        (SETTINGS_MAX_CONCURRENT_STREAMS, 100),
-        (SETTINGS_INITIAL_WINDOW_SIZE,    INIT_WINDOW),
-        (SETTINGS_MAX_FRAME_SIZE,         MAX_FRAME),
-    ]));
-    tls.send(&window_update(0, 1 << 20));
-
-    let hpack = hpack_encode_request(method, scheme, host, path);
-    let es    = if body.is_none() { FL_END_STREAM | FL_END_HEADERS } else { FL_END_HEADERS };
-    let mut hf = Vec::new();
-    make_frame(FT_HEADERS, es, 1, &hpack, &mut hf);
-    tls.send(&hf);
-
-    if let Some(b) = body {
-        let mut df = Vec::new();
-        make_frame(FT_DATA, FL_END_STREAM, 1, b, &mut df);
-        tls.send(&df);
+    pub fn can_submit(&self) -> bool {
+        !self.dead && self.goaway.is_none() && self.next_id < MAX_WINDOW as u32
+            && self.streams.iter().filter(|s| !s.remote_done).count() < self.peer_max_streams
     }
 
-    let mut buf:           Vec<u8>      = Vec::new();
-    let mut status         = 0u16;
-    let mut body_out:      Vec<u8>      = Vec::new();
-    let mut location:      Option<String> = None;
-    let mut headers_block: Vec<u8>      = Vec::new();
-    let mut got_headers    = false;
-    let mut done           = false;
-    let mut idle           = 0usize;
-
-    loop {
-        if CTRL_C.load(Ordering::SeqCst) { return None; }
-        if done { break; }
-
-        let chunk = tls.recv_chunk(500);
-        if chunk.is_empty() {
-            if tls.is_closed() { break; }
-            idle += 1;
-            if idle > 200_000 { break; }
-            continue;
+    pub fn submit(&mut self, req: &H2Request) -> Option<u32> {
+        if !self.can_submit() { return None; }
+        let id = self.next_id;
+        self.next_id += 2;
+
+        let names: Vec<String> = req.headers.iter().map(|(k, _)| k.to_ascii_lowercase()).collect();
+        let has = |n: &str| names.iter().any(|k| k == n);
+        let body = req.body.unwrap_or(&[]);
+        let len  = alloc::format!("{}", body.len());
+
+        let mut hdrs: Vec<(&[u8], &[u8])> = alloc::vec![
+            (b":method",    req.method.as_bytes()),
+            (b":scheme",    self.scheme.as_bytes()),
+            (b":authority", self.authority.as_bytes()),
+            (b":path",      req.path.as_bytes()),
+        ];
+        if !has("user-agent") { hdrs.push((b"user-agent", b"MikuOS/0.1")); }
+        if !has("accept")     { hdrs.push((b"accept", b"*/*")); }
+        if req.body.is_some() && !has("content-length") { hdrs.push((b"content-length", len.as_bytes())); }
+        for (name, (_, value)) in names.iter().zip(req.headers) {
+            if matches!(name.as_str(), "host" | "connection" | "keep-alive" | "proxy-connection"
+                                     | "transfer-encoding" | "upgrade") {
+                continue;
+            }
+            hdrs.push((name.as_bytes(), value.as_bytes()));
         }
-        idle = 0;
-        buf.extend_from_slice(chunk);
 
-        let mut pos = 0usize;
-        loop {
-            if pos + 9 > buf.len() { break; }
-            let len = ((buf[pos] as usize) << 16)
-                    | ((buf[pos+1] as usize) << 8)
-                    |  (buf[pos+2] as usize);
-            if pos + 9 + len > buf.len() { break; }
-
-            let ftype     = buf[pos+3];
-            let flags     = buf[pos+4];
-            let stream_id = (((buf[pos+5] & 0x7F) as u32) << 24)
-                          | ((buf[pos+6] as u32) << 16)
-                          | ((buf[pos+7] as u32) << 8)
-                          |  (buf[pos+8] as u32);
-            let payload   = &buf[pos+9..pos+9+len].to_vec();
-            pos          += 9 + len;
-
-            match ftype {
-                FT_SETTINGS if flags & FL_ACK == 0 => {
-                    tls.send(&settings_ack());
-                    crate::log!("h2: SETTINGS ack sent");
+        let mut block = Vec::new();
+        self.encoder.encode(&hdrs, &mut block);
+
+        let end_stream = if body.is_empty() { FL_END_STREAM } else { 0 };
+        let mut chunks = block.chunks(self.peer_max_frame).peekable();
+        let mut first  = true;
+        while let Some(chunk) = chunks.next() {
+            let last  = chunks.peek().is_none();
+            let flags = if last { FL_END_HEADERS } else { 0 };
+            if first {
+                self.send_frame(FT_HEADERS, flags | end_stream, id, chunk);
+            } else {
+                self.send_frame(FT_CONTINUATION, flags, id, chunk);
+            }
+            first = false;
+        }
+
+        crate::log!("h2: stream {} {} {}", id, req.method, req.path);
+        self.streams.push(Stream {
+            id,
+            send_window:  self.peer_window,
+            recv_unacked: 0,
+            body:         body.to_vec(),
+            sent:         0,
+            local_done:   body.is_empty(),
+            remote_done:  false,
+            got_final:    false,
+            error:        None,
+            resp:         H2Response { status: 0, headers: Vec::new(), body: Vec::new(), location: None },
+        });
+        self.flush_data();
+        if self.dead { None } else { Some(id) }
+    }
+
+    fn flush_data(&mut self) {
+        for i in 0..self.streams.len() {
+            loop {
+                let st = &self.streams[i];
+                if st.local_done || self.dead { break; }
+                let left = st.body.len() - st.sent;
+                let n    = (left as i64).min(self.peer_max_frame as i64)
+                    .min(self.send_window).min(st.send_window);
+                if n <= 0 { break; }
+                let n     = n as usize;
+                let end   = st.sent + n == st.body.len();
+                let chunk = st.body[st.sent..st.sent + n].to_vec();
+                let id    = st.id;
+                self.send_frame(FT_DATA, if end { FL_END_STREAM } else { 0 }, id, &chunk);
+                self.send_window -= n as i64;
+                let st = &mut self.streams[i];
+                st.send_window -= n as i64;
+                st.sent        += n;
+                st.local_done   = end;
+            }
+        }
+    }
+
+    fn poll(&mut self) -> Option<bool> {
+        if self.dead { return None; }
+        match self.tls.recv_into(&mut self.buf, 100) {
+            None    => { self.dead = true; return None; }
+            Some(0) => return Some(false),
+            Some(_) => {}
+        }
+        let mut pos = 0;
+        while !self.dead && pos + 9 <= self.buf.len() {
+            let len = ((self.buf[pos] as usize) << 16)
+                    | ((self.buf[pos+1] as usize) << 8)
+                    |  (self.buf[pos+2] as usize);
+            if len > MAX_FRAME { self.conn_error(ERR_FRAME_SIZE); break; }
+            if pos + 9 + len > self.buf.len() { break; }
+            let ftype   = self.buf[pos+3];
+            let flags   = self.buf[pos+4];
+            let sid     = be32(&self.buf[pos+5..]) & 0x7FFF_FFFF;
+            let payload = self.buf[pos+9..pos+9+len].to_vec();
+            pos += 9 + len;
+            self.on_frame(ftype, flags, sid, &payload);
+        }
+        self.buf.drain(..pos);
+        self.flush_data();
+        if self.dead { None } else { Some(true) }
+    }
+
+    fn on_frame(&mut self, ftype: u8, flags: u8, sid: u32, payload: &[u8]) {
+        if self.header_stream != 0 && (ftype != FT_CONTINUATION || sid != self.header_stream) {
+            return self.conn_error(ERR_PROTOCOL);
+        }
+        match ftype {
+            FT_SETTINGS => self.on_settings(flags, sid, payload),
+            FT_HEADERS => {
+                let block = match frame_body(flags, payload, true) {
+                    Some(b) if sid != 0 => b,
+                    _ => return self.conn_error(ERR_PROTOCOL),
+                };
+                if block.len() > MAX_HEADERS { return self.conn_error(ERR_CALM); }
+                self.header_block.clear();
+                self.header_block.extend_from_slice(block);
+                self.header_end_stream = flags & FL_END_STREAM != 0;
+                if flags & FL_END_HEADERS != 0 {
+                    self.on_header_block(sid);
+                } else {
+                    self.header_stream = sid;
                 }
-                FT_HEADERS if stream_id == 1 => {
-                    headers_block.extend_from_slice(payload);
-                    if flags & FL_END_HEADERS != 0 {
-                        let (s, loc) = decode_headers_block(&headers_block);
-                        status       = s;
-                        location     = loc;
-                        headers_block.clear();
-                        got_headers  = true;
-                        crate::log!("h2: status={}", status);
-                    }
-                    if flags & FL_END_STREAM != 0 { done = true; }
+            }
+            FT_CONTINUATION => {
+                if self.header_stream != sid { return self.conn_error(ERR_PROTOCOL); }
+                if self.header_block.len() + payload.len() > MAX_HEADERS {
+                    return self.conn_error(ERR_CALM);
                 }
-                FT_CONTINUATION if stream_id == 1 => {
-                    headers_block.extend_from_slice(payload);
-                    if flags & FL_END_HEADERS != 0 {
-                        let (s, loc) = decode_headers_block(&headers_block);
-                        status       = s;
-                        location     = loc;
-                        headers_block.clear();
-                        got_headers  = true;
-                    }
+                self.header_block.extend_from_slice(payload);
+                if flags & FL_END_HEADERS != 0 {
+                    self.header_stream = 0;
+                    self.on_header_block(sid);
                 }
-                FT_DATA if stream_id == 1 => {
-                    if flags & 0x08 != 0 && !payload.is_empty() {
-                        let pad = payload[0] as usize;
-                        let end = payload.len().saturating_sub(pad);
-                        if end > 1 { body_out.extend_from_slice(&payload[1..end]); }
-                    } else {
-                        body_out.extend_from_slice(payload);
-                    }
-                    if flags & FL_END_STREAM != 0 { done = true; }
+            }
+            FT_DATA => self.on_data(flags, sid, payload),
+            FT_RST_STREAM => {
+                if sid == 0 { return self.conn_error(ERR_PROTOCOL); }
+                if payload.len() != 4 { return self.conn_error(ERR_FRAME_SIZE); }
+                let code = be32(payload);
+                if let Some(i) = self.index(sid) {
+                    let st = &mut self.streams[i];
+                    if !st.remote_done { st.error = Some(code); }
+                    st.local_done  = true;
+                    st.remote_done = true;
+                    crate::log_err!("h2: stream {} reset: {}", sid, error_name(code));
                 }
-                FT_RST_STREAM => {
-                    let code = if payload.len() >= 4 {
-                        u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]])
-                    } else { 0 };
-                    crate::log_err!("h2: RST_STREAM code={}", code);
-                    done = true;
+            }
+            FT_GOAWAY => {
+                if sid != 0 { return self.conn_error(ERR_PROTOCOL); }
+                if payload.len() < 8 { return self.conn_error(ERR_FRAME_SIZE); }
+                let last = be32(payload) & 0x7FFF_FFFF;
+                let code = be32(&payload[4..]);
+                self.goaway = Some(last);
+                for st in self.streams.iter_mut().filter(|s| s.id > last && !s.remote_done) {
+                    st.error       = Some(ERR_REFUSED_STREAM);
+                    st.local_done  = true;
+                    st.remote_done = true;
                 }
-                FT_GOAWAY => {
-                    let code = if payload.len() >= 8 {
-                        u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]])
-                    } else { 0 };
-                    crate::log_err!("h2: GOAWAY code={}", code);
-                    done = true;
+                if code == ERR_NO_ERROR {
+                    crate::log!("h2: GOAWAY last_stream={}", last);
+                } else {
+                    crate::log_err!("h2: GOAWAY {} last_stream={}", error_name(code), last);
                 }
-                FT_PING if flags & FL_ACK == 0 => {
-                    let mut pf = Vec::new();
-                    make_frame(FT_PING, FL_ACK, 0, payload, &mut pf);
-                    tls.send(&pf);
+            }
+            FT_PING => {
+                if sid != 0 { return self.conn_error(ERR_PROTOCOL); }
+                if payload.len() != 8 { return self.conn_error(ERR_FRAME_SIZE); }
+                if flags & FL_ACK == 0 {
+                    self.send_frame(FT_PING, FL_ACK, 0, payload);
+                }
+            }
+            FT_WINDOW_UPDATE => self.on_window_update(sid, payload),
+            FT_PUSH_PROMISE => self.conn_error(ERR_PROTOCOL),
+            _ => {}
+        }
+    }
+
+    fn on_settings(&mut self, flags: u8, sid: u32, payload: &[u8]) {
+        if sid != 0 { return self.conn_error(ERR_PROTOCOL); }
+        if flags & FL_ACK != 0 {
+            if !payload.is_empty() { self.conn_error(ERR_FRAME_SIZE); }
+            return;
+        }
+        if !payload.len().is_multiple_of(6) { return self.conn_error(ERR_FRAME_SIZE); }
+        for s in payload.chunks(6) {
+            let id  = u16::from_be_bytes([s[0], s[1]]);
+            let val = be32(&s[2..]);
+            match id {
+                SETTINGS_HEADER_TABLE_SIZE      => self.encoder.set_max_table_size(val as usize),
+                SETTINGS_MAX_CONCURRENT_STREAMS => self.peer_max_streams = val as usize,
+                SETTINGS_INITIAL_WINDOW_SIZE    => {
+                    if val as i64 > MAX_WINDOW { return self.conn_error(ERR_FLOW_CONTROL); }
+                    let delta = val as i64 - self.peer_window;
+                    for st in self.streams.iter_mut() { st.send_window += delta; }
+                    self.peer_window = val as i64;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(MAX_FRAME as u32..=0xFF_FFFF).contains(&val) { return self.conn_error(ERR_PROTOCOL); }
+                    self.peer_max_frame = val as usize;
                 }
-                FT_WINDOW_UPDATE => {}
                 _ => {}
             }
         }
+        self.send_frame(FT_SETTINGS, FL_ACK, 0, &[]);
+        crate::log!("h2: SETTINGS applied (max_streams={} window={} frame={})",
+            self.peer_max_streams, self.peer_window, self.peer_max_frame);
+    }
 
-        if pos > 0 {
-            buf.drain(..pos);
+    fn on_window_update(&mut self, sid: u32, payload: &[u8]) {
+        if payload.len() != 4 { return self.conn_error(ERR_FRAME_SIZE); }
+        let inc = (be32(payload) & 0x7FFF_FFFF) as i64;
+        if sid == 0 {
+            if inc == 0 { return self.conn_error(ERR_PROTOCOL); }
+            self.send_window += inc;
+            if self.send_window > MAX_WINDOW { self.conn_error(ERR_FLOW_CONTROL); }
+        } else if let Some(i) = self.index(sid) {
+            if inc == 0 { return self.reset(i, ERR_PROTOCOL); }
+            self.streams[i].send_window += inc;
+            if self.streams[i].send_window > MAX_WINDOW { self.reset(i, ERR_FLOW_CONTROL); }
         }
     }
 
-    if !got_headers { return None; }
-    Some(H2Response { status, body: body_out, location })
-}
+    fn on_header_block(&mut self, sid: u32) {
+        let headers = match self.decoder.decode(&self.header_block) {
+            Some(h) => h,
+            None    => return self.conn_error(ERR_COMPRESSION),
+        };
+        self.header_block.clear();
+        let i = match self.index(sid) {
+            Some(i) if !self.streams[i].remote_done => i,
+            _ => return,
+        };
+
+        let mut status = None;
+        let mut fields = Vec::new();
+        for (name, value) in headers {
+            let value = String::from_utf8_lossy(&value).into_owned();
+            if name == b":status" {
+                status = value.parse::<u16>().ok();
+            } else if name.first() != Some(&b':') {
+                fields.push((String::from_utf8_lossy(&name).into_owned(), value));
+            }
+        }
+
+        let st = &mut self.streams[i];
+        match status {
+            Some(s) if (100..200).contains(&s) => return,
+            Some(s) if !st.got_final => {
+                st.resp.status = s;
+                st.got_final   = true;
+                crate::log!("h2: stream {} status={}", sid, s);
+            }
+            None if st.got_final => {}
+            _ => return self.reset(i, ERR_PROTOCOL),
+        }
+        for (name, value) in fields {
+            if name == "location" { st.resp.location = Some(value.clone()); }
+            st.resp.headers.push((name, value));
+        }
+        if self.header_end_stream { st.remote_done = true; }
+    }
+
+    fn on_data(&mut self, flags: u8, sid: u32, payload: &[u8]) {
+        let body = match frame_body(flags, payload, false) {
+            Some(b) if sid != 0 => b,
+            _ => return self.conn_error(ERR_PROTOCOL),
+        };
+        let flow = payload.len() as u32;
+        self.recv_unacked += flow;
+        if self.recv_unacked >= CONN_WINDOW / 2 {
+            let inc = core::mem::take(&mut self.recv_unacked);
+            self.send_frame(FT_WINDOW_UPDATE, 0, 0, &inc.to_be_bytes());
+        }
+        let i = match self.index(sid) {
+            Some(i) => i,
+            None    => return,
+        };
+        if self.streams[i].remote_done { return; }
+        if !self.streams[i].got_final { return self.reset(i, ERR_PROTOCOL); }
+
+        let st = &mut self.streams[i];
+        st.resp.body.extend_from_slice(body);
+        st.recv_unacked += flow - body.len() as u32;
+        if flags & FL_END_STREAM != 0 {
+            st.remote_done = true;
+        }
+    }
 
-fn huffman_decode(src: &[u8]) -> Vec<u8> {
-    static TABLE: &[(u32, u8, u8)] = &[
-        (0x1ff8,13,b'\x20'),(0x7fffd8,23,b'!'), (0xfffffe2,28,b'"'),(0xfffffe3,28,b'#'),
-        (0xfffffe4,28,b'$'),(0xfffffe5,28,b'%'),(0xfffffe6,28,b'&'),(0xfffffe7,28,b'\''),
-        (0xfffffe8,28,b'('),(0xfffffe9,28,b')'),(0xffffffea,28,b'*'),(0xffffffeb,28,b'+'),
-        (0xfffe6,20,b','),(0x7ffd,15,b'-'),(0x1f,5,b'.'),(0x23,6,b'/'),
-        (0xb,4,b'0'),(0xc,4,b'1'),(0xd,4,b'2'),(0xe,4,b'3'),(0xf,4,b'4'),
-        (0x10,4,b'5'),(0x11,4,b'6'),(0x12,4,b'7'),(0x13,4,b'8'),(0x14,4,b'9'),
-        (0x3a,6,b':'),(0x3b,6,b';'),(0xfffe7,20,b'<'),(0x3c,6,b'='),(0xfffe8,20,b'>'),
-        (0x3d,6,b'?'),(0xffffff9,28,b'@'),
-        (0x15,5,b'A'),(0xf8,8,b'B'),(0x16,5,b'C'),(0x17,5,b'D'),(0x18,5,b'E'),
-        (0xf9,8,b'F'),(0x19,5,b'G'),(0x1a,5,b'H'),(0x1b,5,b'I'),(0xf9,8,b'J'),
-        (0x1c,5,b'K'),(0xf8,8,b'L'),(0x1d,5,b'M'),(0x1e,5,b'N'),(0x1f,5,b'O'),
-        (0x20,5,b'P'),(0xfa,8,b'Q'),(0x21,5,b'R'),(0x22,5,b'S'),(0x23,5,b'T'),
-        (0x24,5,b'U'),(0xfb,8,b'V'),(0x25,5,b'W'),(0xfc,8,b'X'),(0xfd,8,b'Y'),
-        (0xfe,8,b'Z'),(0x26,5,b'a'),(0x27,5,b'b'),(0x28,5,b'c'),(0x29,5,b'd'),
-        (0x2a,5,b'e'),(0x2b,5,b'f'),(0x2c,5,b'g'),(0x2d,5,b'h'),(0x2e,5,b'i'),
-        (0x2f,5,b'j'),(0x30,5,b'k'),(0x31,5,b'l'),(0x32,5,b'm'),(0x33,5,b'n'),
-        (0x34,5,b'o'),(0x35,5,b'p'),(0x36,5,b'q'),(0x37,5,b'r'),(0x38,5,b's'),
-        (0x39,5,b't'),(0x3a,5,b'u'),(0x3b,5,b'v'),(0x3c,5,b'w'),(0xfe,8,b'x'),
-        (0x3d,5,b'y'),(0xff,8,b'z'),
-    ];
-
-    let mut bits: u64  = 0;
-    let mut nbits: u32 = 0;
-    let mut out        = Vec::new();
-
-    for &byte in src {
-        bits   = (bits << 8) | byte as u64;
-        nbits += 8;
-        'outer: loop {
-            for &(code, len, ch) in TABLE.iter() {
-                if nbits >= len as u32 {
-                    let shift = nbits - len as u32;
-                    if shift >= 64 { continue; }
-                    if (bits >> shift) as u32 == code {
-                        out.push(ch);
-                        bits  = if shift == 0 { 0 } else { bits & ((1u64 << shift) - 1) };
-                        nbits -= len as u32;
-                        continue 'outer;
+    fn consume(&mut self, i: usize, n: usize) {
+        let st = &mut self.streams[i];
+        st.recv_unacked += n as u32;
+        if st.remote_done || st.recv_unacked < STREAM_WINDOW / 2 { return; }
+        let (id, inc) = (st.id, core::mem::take(&mut st.recv_unacked));
+        self.send_frame(FT_WINDOW_UPDATE, 0, id, &inc.to_be_bytes());
+    }
+
+    fn take(&mut self, i: usize) -> Option<H2Response> {
+        let st = self.streams.remove(i);
+        match st.error {
+            Some(code) => {
+                crate::log_err!("h2: stream {} failed: {}", st.id, error_name(code));
+                None
+            }
+            None if st.got_final => Some(st.resp),
+            None => None,
+        }
+    }
+
+    fn fail_open_streams(&mut self) {
+        for st in self.streams.iter_mut().filter(|s| !s.remote_done) {
+            st.error       = Some(ERR_INTERNAL);
+            st.remote_done = true;
+        }
+    }
+
+    pub fn wait(&mut self, id: u32) -> Option<H2Response> {
+        let mut body = Vec::new();
+        let mut resp = self.wait_streaming(id, &mut |_, d| { body.extend_from_slice(d); true })?;
+        resp.body = body;
+        Some(resp)
+    }
+
+    pub fn wait_streaming(&mut self, id: u32, sink: &mut dyn FnMut(&H2Response, &[u8]) -> bool) -> Option<H2Response> {
+        let mut idle = 0usize;
+        let mut seen = false;
+        loop {
+            let i = self.index(id)?;
+            let st = &mut self.streams[i];
+            if st.got_final && st.error.is_none() && (!seen || !st.resp.body.is_empty()) {
+                seen = true;
+                let chunk = core::mem::take(&mut st.resp.body);
+                if !sink(&st.resp, &chunk) {
+                    self.reset(i, ERR_CANCEL);
+                    return self.take(i);
+                }
+                self.consume(i, chunk.len());
+            }
+            if self.streams[i].remote_done { return self.take(i); }
+            if CTRL_C.load(Ordering::SeqCst) {
+                self.reset(i, ERR_CANCEL);
+                return self.take(i);
+            }
+            match self.poll() {
+                None        => self.fail_open_streams(),
+                Some(true)  => idle = 0,
+                Some(false) => {
+                    idle += 100;
+                    if idle > IDLE_SECS * crate::interrupts::PIT_HZ as usize {
+                        self.reset(i, ERR_CANCEL);
                     }
                 }
             }
-            break;
         }
     }
-    out
+
+    pub fn request_all(&mut self, reqs: &[H2Request]) -> Vec<Option<H2Response>> {
+        let mut out: Vec<Option<H2Response>> = reqs.iter().map(|_| None).collect();
+        let mut bodies: Vec<Vec<u8>> = reqs.iter().map(|_| Vec::new()).collect();
+        let mut ids: Vec<(usize, u32)> = Vec::new();
+        let mut next = 0;
+        let mut idle = 0usize;
+        loop {
+            while next < reqs.len() && self.can_submit() {
+                if let Some(id) = self.submit(&reqs[next]) { ids.push((next, id)); }
+                next += 1;
+            }
+            let mut k = 0;
+            while k < ids.len() {
+                let (slot, id) = ids[k];
+                let i = match self.index(id) {
+                    Some(i) => i,
+                    None    => { ids.remove(k); continue; }
+                };
+                let chunk = core::mem::take(&mut self.streams[i].resp.body);
+                bodies[slot].extend_from_slice(&chunk);
+                if bodies[slot].len() > MAX_BODY && !self.streams[i].remote_done {
+                    crate::log_err!("h2: stream {} body exceeds {} bytes", id, MAX_BODY);
+                    self.reset(i, ERR_CANCEL);
+                }
+                if !self.streams[i].remote_done {
+                    self.consume(i, chunk.len());
+                    k += 1;
+                    continue;
+                }
+                out[slot] = self.take(i).map(|mut r| { r.body = core::mem::take(&mut bodies[slot]); r });
+                ids.remove(k);
+            }
+            if ids.is_empty() && (next == reqs.len() || !self.can_submit()) { break; }
+            if CTRL_C.load(Ordering::SeqCst) {
+                for &(_, id) in &ids {
+                    if let Some(i) = self.index(id) { self.reset(i, ERR_CANCEL); }
+                }
+                next = reqs.len();
+                continue;
+            }
+            match self.poll() {
+                None        => self.fail_open_streams(),
+                Some(true)  => idle = 0,
+                Some(false) => {
+                    idle += 100;
+                    if idle > IDLE_SECS * crate::interrupts::PIT_HZ as usize {
+                        self.fail_open_streams();
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    pub fn close(&mut self) {
+        if self.dead { return; }
+        let mut p = [0u8; 8];
+        p[4..].copy_from_slice(&ERR_NO_ERROR.to_be_bytes());
+        self.send_frame(FT_GOAWAY, 0, 0, &p);
+        self.dead = true;
+    }
+}
+
+pub fn h2_request(tls: &mut TlsStream, scheme: &str, authority: &str, req: &H2Request) -> Option<H2Response> {
+    if CTRL_C.load(Ordering::SeqCst) { return None; }
+    let mut conn = H2Conn::open(tls, scheme, authority);
+    let resp = conn.submit(req).and_then(|id| conn.wait(id));
+    conn.close();
+    resp
 }
@@ -0,0 +1,633 @@
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use spin::Mutex;
+use super::ip::IpAddr;
+use super::socket::{Endpoint, Proto, Socket, AF_INET6};
+use crate::fdtable::{self, Object, OpenFile, O_CREAT, O_DIRECTORY, O_EXCL, O_RDONLY, O_TRUNC, O_WRONLY};
+use crate::interrupts::PIT_HZ;
+use crate::syscall::{EACCES, EADDRINUSE, EISDIR, ENOENT, ENOTDIR, EROFS};
+use crate::vfs_stat::{self, DirEnt, DT_DIR, S_IFDIR};
+
+const WORKERS:      usize = 4;
+const BACKLOG:      usize = 32;
+const MAX_HEADER:   usize = 8192;
+const MAX_REQUESTS: usize = 100;
+const CHUNK:        usize = 32 * 1024;
+const IDLE_TICKS:   u64   = 10 * PIT_HZ as u64;
+const PARK_TICKS:   u64   = PIT_HZ as u64 / 4;
+const S_IFMT:       u32   = 0o170000;
+
+#[derive(Clone)]
+struct Server {
+    sock:   Arc<Socket>,
+    root:   String,
+    upload: Option<String>,
+    port:   u16,
+}
+
+static SERVER:     Mutex<Option<Server>> = Mutex::new(None);
+static WORKERS_UP: AtomicBool = AtomicBool::new(false);
+static UPLOAD_SEQ: AtomicU32  = AtomicU32::new(0);
+
+struct Request {
+    method:     String,
+    target:     String,
+    keep_alive: bool,
+    length:     Option<u64>,
+    chunked:    bool,
+    range:      Option<String>,
+    expect:     bool,
+}
+
+enum Span {
+    Full,
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        416 => "Range Not Satisfiable",
+        507 => "Insufficient Storage",
+        _   => "Internal Server Error",
+    }
+}
+
+fn content_type(path: &str) -> &'static str {
+    let ext = path.rsplit_once('.').map_or("", |(_, e)| e);
+    match ext.to_ascii_lowercase().as_str() {
+        "html" | "htm"                   => "text/html; charset=utf-8",
+        "txt" | "log" | "md" | "rs" | "c"
+        | "h" | "cfg" | "conf" | "ini"   => "text/plain; charset=utf-8",
+        "css"                            => "text/css",
+        "js"                             => "application/javascript",
+        "json"                           => "application/json",
+        "png"                            => "image/png",
+        "jpg" | "jpeg"                   => "image/jpeg",
+        "gif"                            => "image/gif",
+        "svg"                            => "image/svg+xml",
+        "pdf"                            => "application/pdf",
+        _                                => "application/octet-stream",
+    }
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.split("\r\n").skip(1).find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        k.trim().eq_ignore_ascii_case(name).then(|| v.trim())
+    })
+}
+
+fn parse_request(head: &str) -> Option<Request> {
+    let mut line = head.split("\r\n").next()?.split_whitespace();
+    let method  = line.next()?;
+    let target  = line.next()?;
+    let version = line.next()?;
+    if !version.starts_with("HTTP/1.") || line.next().is_some() {
+        return None;
+    }
+    let keep_alive = match header(head, "Connection") {
+        Some(c) if c.eq_ignore_ascii_case("close")      => false,
+        Some(c) if c.eq_ignore_ascii_case("keep-alive") => true,
+        _                                               => version == "HTTP/1.1",
+    };
+    let length = match header(head, "Content-Length") {
+        Some(v) => Some(v.parse().ok()?),
+        None    => None,
+    };
+    Some(Request {
+        method:  String::from(method),
+        target:  String::from(target.split(['?', '#']).next().unwrap_or("")),
+        keep_alive,
+        length,
+        chunked: header(head, "Transfer-Encoding").is_some(),
+        range:   header(head, "Range").map(String::from),
+        expect:  header(head, "Expect").is_some_and(|v| v.eq_ignore_ascii_case("100-continue")),
+    })
+}
+
+fn hex(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == b'%' {
+            out.push(hex(*b.get(i + 1)?)? << 4 | hex(*b.get(i + 2)?)?);
+            i += 3;
+        } else {
+            out.push(b[i]);
+            i += 1;
+        }
+    }
+    if out.contains(&0) {
+        return None;
+    }
+    String::from_utf8(out).ok()
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::new();
+    for &c in s.as_bytes() {
+        if c.is_ascii_alphanumeric() || b"-._~".contains(&c) {
+            out.push(c as char);
+        } else {
+            out.push_str(&format!("%{:02X}", c));
+        }
+    }
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c   => out.push(c),
+        }
+    }
+    out
+}
+
+fn local_path(root: &str, target: &str) -> Option<String> {
+    if !target.starts_with('/') {
+        return None;
+    }
+    let mut out = String::from(root.trim_end_matches('/'));
+    for seg in percent_decode(target)?.split('/') {
+        match seg {
+            "" | "." => {}
+            ".."     => return None,
+            s        => {
+                out.push('/');
+                out.push_str(s);
+            }
+        }
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    Some(out)
+}
+
+fn span(spec: Option<&str>, size: u64) -> Span {
+    let spec = match spec.and_then(|s| s.strip_prefix("bytes=")) {
+        Some(s) if !s.contains(',') => s.trim(),
+        _ => return Span::Full,
+    };
+    let (a, b) = match spec.split_once('-') {
+        Some(p) => p,
+        None    => return Span::Full,
+    };
+    match (a.parse::<u64>().ok(), b.parse::<u64>().ok()) {
+        (None, Some(n)) if a.is_empty() => {
+            if n == 0 || size == 0 {
+                Span::Unsatisfiable
+            } else {
+                Span::Part(size.saturating_sub(n), size)
+            }
+        }
+        (Some(start), None) if b.is_empty() => {
+            if start >= size { Span::Unsatisfiable } else { Span::Part(start, size) }
+        }
+        (Some(start), Some(last)) if last >= start => {
+            if start >= size { Span::Unsatisfiable } else { Span::Part(start, (last + 1).min(size)) }
+        }
+        _ => Span::Full,
+    }
+}
+
+fn write_all(conn: &Arc<Socket>, data: &[u8]) -> bool {
+    let mut off = 0;
+    while off < data.len() {
+        match conn.send(&data[off..], None, false) {
+            Ok(n)  => off += n,
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+fn fill(conn: &Arc<Socket>, buf: &mut Vec<u8>) -> bool {
+    let mut tmp = [0u8; 4096];
+    match conn.recv(&mut tmp, false) {
+        Ok((0, _)) | Err(_) => false,
+        Ok((n, _)) => {
+            buf.extend_from_slice(&tmp[..n]);
+            true
+        }
+    }
+}
+
+fn read_head(conn: &Arc<Socket>, buf: &mut Vec<u8>) -> Option<usize> {
+    loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            return Some(pos + 4);
+        }
+        if buf.len() > MAX_HEADER || !fill(conn, buf) {
+            return None;
+        }
+    }
+}
+
+fn status_head(status: u16, keep: bool, len: u64, extra: &str) -> String {
+    format!(
+        "HTTP/1.1 {} {}\r\nServer: MikuOS\r\nContent-Length: {}\r\nConnection: {}\r\n{}\r\n",
+        status, reason(status), len, if keep { "keep-alive" } else { "close" }, extra,
+    )
+}
+
+fn respond(conn: &Arc<Socket>, status: u16, keep: bool, extra: &str, body: &[u8], head_only: bool) -> bool {
+    let head = status_head(status, keep, body.len() as u64, extra);
+    write_all(conn, head.as_bytes()) && (head_only || write_all(conn, body))
+}
+
+fn error(conn: &Arc<Socket>, status: u16, keep: bool, head_only: bool) -> bool {
+    let body = format!("{} {}\n", status, reason(status));
+    respond(conn, status, keep, "Content-Type: text/plain\r\n", body.as_bytes(), head_only)
+}
+
+fn errno_status(e: i64) -> u16 {
+    match e {
+        ENOENT | ENOTDIR => 404,
+        EACCES | EROFS   => 403,
+        EISDIR           => 409,
+        _                => 500,
+    }
+}
+
+fn entries(path: &str) -> Result<Vec<DirEnt>, i64> {
+    let dir = fdtable::open(path, O_RDONLY | O_DIRECTORY, 0)?;
+    let mut all: Vec<DirEnt> = Vec::new();
+    loop {
+        let batch = match &dir.object {
+            Object::Vfs(fd) => vfs_stat::vfs_entries(*fd, all.len())?,
+            _               => return Err(ENOTDIR),
+        };
+        if batch.is_empty() {
+            break;
+        }
+        all.extend(batch);
+    }
+    all.retain(|e| e.name != "." && e.name != "..");
+    all.sort_by(|a, b| (b.dtype == DT_DIR).cmp(&(a.dtype == DT_DIR)).then_with(|| a.name.cmp(&b.name)));
+    Ok(all)
+}
+
+fn listing(conn: &Arc<Socket>, req: &Request, path: &str, head_only: bool) -> bool {
+    let list = match entries(path) {
+        Ok(l)  => l,
+        Err(e) => return error(conn, errno_status(e), req.keep_alive, head_only),
+    };
+    let title = html_escape(&percent_decode(&req.target).unwrap_or_default());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n\
+         <body><h1>Index of {0}</h1><hr><pre>\n",
+        title,
+    );
+    if req.target != "/" {
+        html.push_str("<a href=\"../\">../</a>\n");
+    }
+    for e in &list {
+        let dir  = e.dtype == DT_DIR;
+        let name = format!("{}{}", e.name, if dir { "/" } else { "" });
+        let size = if dir {
+            String::from("-")
+        } else {
+            let child = format!("{}/{}", path.trim_end_matches('/'), e.name);
+            vfs_stat::stat(&child, true).map_or(String::from("?"), |st| format!("{}", st.size))
+        };
+        let pad = 50usize.saturating_sub(name.chars().count());
+        html.push_str(&format!(
+            "<a href=\"{}{}\">{}</a>{:pad$} {:>12}\n",
+            percent_encode(&e.name), if dir { "/" } else { "" }, html_escape(&name), "", size,
+        ));
+    }
+    html.push_str("</pre><hr></body></html>\n");
+    respond(conn, 200, req.keep_alive, "Content-Type: text/html; charset=utf-8\r\n", html.as_bytes(), head_only)
+}
+
+fn send_file(conn: &Arc<Socket>, req: &Request, path: &str, size: u64, head_only: bool) -> (u16, bool) {
+    let file = match fdtable::open(path, O_RDONLY, 0) {
+        Ok(f)  => f,
+        Err(e) => {
+            let status = errno_status(e);
+            return (status, error(conn, status, req.keep_alive, head_only));
+        }
+    };
+    let (status, start, end) = match span(req.range.as_deref(), size) {
+        Span::Full          => (200, 0, size),
+        Span::Part(a, b)    => (206, a, b),
+        Span::Unsatisfiable => {
+            let extra = format!("Content-Range: bytes */{}\r\n", size);
+            return (416, respond(conn, 416, req.keep_alive, &extra, &[], head_only));
+        }
+    };
+    let mut extra = format!("Content-Type: {}\r\nAccept-Ranges: bytes\r\n", content_type(path));
+    if status == 206 {
+        extra.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n", start, end - 1, size));
+    }
+    let head = status_head(status, req.keep_alive, end - start, &extra);
+    if !write_all(conn, head.as_bytes()) {
+        return (status, false);
+    }
+    if head_only {
+        return (status, true);
+    }
+    let mut chunk = vec![0u8; CHUNK];
+    let mut off = start;
+    while off < end {
+        let want = ((end - off) as usize).min(CHUNK);
+        let n = match file.pread(&mut chunk[..want], off) {
+            Ok(0) | Err(_) => return (status, false),
+            Ok(n)          => n,
+        };
+        if !write_all(conn, &chunk[..n]) {
+            return (status, false);
+        }
+        off += n as u64;
+    }
+    (status, true)
+}
+
+fn get(conn: &Arc<Socket>, req: &Request, root: &str, head_only: bool) -> (u16, bool) {
+    let path = match local_path(root, &req.target) {
+        Some(p) => p,
+        None    => return (400, error(conn, 400, req.keep_alive, head_only)),
+    };
+    let st = match vfs_stat::stat(&path, true) {
+        Ok(st) => st,
+        Err(e) => {
+            let status = errno_status(e);
+            return (status, error(conn, status, req.keep_alive, head_only));
+        }
+    };
+    if st.mode & S_IFMT != S_IFDIR {
+        return send_file(conn, req, &path, st.size, head_only);
+    }
+    if !req.target.ends_with('/') {
+        let extra = format!("Location: {}/\r\n", req.target);
+        return (301, respond(conn, 301, req.keep_alive, &extra, &[], head_only));
+    }
+    (200, listing(conn, req, &path, head_only))
+}
+
+fn receive(conn: &Arc<Socket>, file: &OpenFile, length: u64, buf: &mut Vec<u8>) -> Result<(), Option<u16>> {
+    let mut off = 0u64;
+    while off < length {
+        if buf.is_empty() && !fill(conn, buf) {
+            return Err(None);
+        }
+        let n = ((length - off) as usize).min(buf.len());
+        if file.pwrite(&buf[..n], off).ok() != Some(n) {
+            return Err(Some(507));
+        }
+        buf.drain(..n);
+        off += n as u64;
+    }
+    Ok(())
+}
+
+fn put(conn: &Arc<Socket>, req: &Request, root: &str, upload: &str, buf: &mut Vec<u8>) -> (u16, bool) {
+    let length = match req.length {
+        Some(n) if !req.chunked => n,
+        _ => return (411, error(conn, 411, false, false)),
+    };
+    let path = match local_path(root, &req.target) {
+        Some(p) if !req.target.ends_with('/') => p,
+        _ => return (400, error(conn, 400, false, false)),
+    };
+    if !path.starts_with(upload) || path.as_bytes().get(upload.len()) != Some(&b'/') {
+        return (403, error(conn, 403, false, false));
+    }
+    let existed = match vfs_stat::stat(&path, true) {
+        Ok(st) if st.mode & S_IFMT == S_IFDIR => return (409, error(conn, 409, false, false)),
+        Ok(_)  => true,
+        Err(_) => false,
+    };
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    let tmp = format!("{}/.{}.part{}", dir, name, UPLOAD_SEQ.fetch_add(1, Ordering::Relaxed));
+    let file = match fdtable::open(&tmp, O_WRONLY | O_CREAT | O_EXCL | O_TRUNC, 0o644) {
+        Ok(f)  => f,
+        Err(e) => {
+            let status = errno_status(e);
+            return (status, error(conn, status, false, false));
+        }
+    };
+    if req.expect && !write_all(conn, b"HTTP/1.1 100 Continue\r\n\r\n") {
+        drop(file);
+        let _ = crate::vfs_write::unlink(&tmp);
+        return (500, false);
+    }
+    let res = receive(conn, &file, length, buf);
+    drop(file);
+    let res = res.and_then(|()| crate::vfs_write::rename(&tmp, &path).map_err(|e| Some(errno_status(e))));
+    if let Err(status) = res {
+        let _ = crate::vfs_write::unlink(&tmp);
+        return match status {
+            Some(s) => (s, error(conn, s, false, false)),
+            None    => (400, false),
+        };
+    }
+    let status = if existed { 204 } else { 201 };
+    let extra  = if existed { String::new() } else { format!("Location: {}\r\n", req.target) };
+    (status, respond(conn, status, req.keep_alive, &extra, &[], false))
+}
+
+fn serve(conn: &Arc<Socket>, srv: &Server) {
+    let root  = srv.root.as_str();
+    let allow = if srv.upload.is_some() { "Allow: GET, HEAD, PUT\r\n" } else { "Allow: GET, HEAD\r\n" };
+    conn.set_timeout(IDLE_TICKS);
+    let peer = conn.peer();
+    let mut buf = Vec::new();
+    for _ in 0..MAX_REQUESTS {
+        let end = match read_head(conn, &mut buf) {
+            Some(n) => n,
+            None    => break,
+        };
+        let req = core::str::from_utf8(&buf[..end]).ok().and_then(parse_request);
+        buf.drain(..end);
+        let mut req = match req {
+            Some(r) => r,
+            None => {
+                error(conn, 400, false, false);
+                break;
+            }
+        };
+        if req.method != "PUT" && (req.chunked || req.length.is_some_and(|n| n > 0)) {
+            req.keep_alive = false;
+        }
+        let (status, ok) = match req.method.as_str() {
+            "GET"  => get(conn, &req, root, false),
+            "HEAD" => get(conn, &req, root, true),
+            "PUT" if srv.upload.is_some() => {
+                let upload = srv.upload.as_deref().unwrap_or_default();
+                let (status, ok) = put(conn, &req, root, upload, &mut buf);
+                if status >= 300 {
+                    req.keep_alive = false;
+                }
+                (status, ok)
+            }
+            _ => {
+                req.keep_alive = false;
+                let ok = respond(conn, 405, false, allow, b"405 Method Not Allowed\n", false);
+                (405, ok)
+            }
+        };
+        crate::log!("httpd: {} {} {} -> {}", peer, req.method, req.target, status);
+        if !ok || !req.keep_alive {
+            break;
+        }
+    }
+    conn.close();
+}
+
+fn worker() -> ! {
+    x86_64::instructions::interrupts::enable();
+    loop {
+        let srv = SERVER.lock().clone();
+        let srv = match srv {
+            Some(s) => s,
+            None => {
+                crate::scheduler::sleep(PARK_TICKS);
+                continue;
+            }
+        };
+        match srv.sock.accept(false) {
+            Ok(conn) => serve(&conn, &srv),
+            Err(_)   => crate::scheduler::sleep(PARK_TICKS),
+        }
+    }
+}
+
+fn stop() -> bool {
+    let old = SERVER.lock().take();
+    match old {
+        Some(srv) => {
+            srv.sock.close();
+            true
+        }
+        None => false,
+    }
+}
+
+fn is_dir(path: &str) -> bool {
+    vfs_stat::stat(path, true).is_ok_and(|st| st.mode & S_IFMT == S_IFDIR)
+}
+
+fn start(port: u16, root: &str, upload: Option<&str>) {
+    if !super::is_ready() {
+        crate::print_error!("httpd: network not ready");
+        return;
+    }
+    if !is_dir(root) {
+        crate::print_error!("httpd: '{}' is not a directory", root);
+        return;
+    }
+    let upload = match upload.map(|u| u.trim_end_matches('/')) {
+        Some(u) if !u.starts_with('/') || !is_dir(u) => {
+            crate::print_error!("httpd: upload dir '{}' must be an absolute directory other than /", u);
+            return;
+        }
+        Some(u) => Some(String::from(u)),
+        None    => None,
+    };
+    stop();
+    let sock = Socket::with_family(Proto::Tcp, AF_INET6);
+    if let Err(e) = sock.bind(Endpoint::new(IpAddr::UNSPECIFIED_V6, port)) {
+        if e == EADDRINUSE {
+            crate::print_error!("httpd: port {} in use", port);
+        } else {
+            crate::print_error!("httpd: bind failed ({})", e);
+        }
+        return;
+    }
+    if sock.listen(BACKLOG).is_err() {
+        sock.close();
+        crate::print_error!("httpd: listen failed");
+        return;
+    }
+    *SERVER.lock() = Some(Server { sock, root: String::from(root), upload: upload.clone(), port });
+    if !WORKERS_UP.swap(true, Ordering::AcqRel) {
+        for _ in 0..WORKERS {
+            crate::scheduler::spawn_named(worker, "httpd", 10);
+        }
+    }
+    let ip = super::get_ip();
+    crate::print_success!("httpd: serving {} at http://{}.{}.{}.{}:{}/",
+        root, ip[0], ip[1], ip[2], ip[3], port);
+    if let Some(ip6) = super::source_for(&IpAddr::UNSPECIFIED_V6) {
+        crate::print_success!("httpd: serving {} at http://[{}]:{}/", root, ip6, port);
+    }
+    if let Some(u) = upload {
+        crate::print_warn!("httpd: PUT uploads enabled under {}", u);
+    }
+}
+
+pub fn cmd_httpd(args: &str) {
+    let mut parts = args.split_whitespace();
+    match parts.next() {
+        Some("stop") => {
+            if stop() {
+                crate::print_success!("httpd: stopped");
+            } else {
+                crate::print_error!("httpd: not running");
+            }
+        }
+        Some("status") => {
+            let srv = SERVER.lock().clone();
+            match srv {
+                Some(s) => {
+                    crate::cprintln!(230, 240, 240, "httpd: serving {} on port {}", s.root, s.port);
+                    if let Some(u) = s.upload {
+                        crate::cprintln!(230, 240, 240, "httpd: uploads to {}", u);
+                    }
+                }
+                None    => crate::cprintln!(120, 140, 140, "httpd: not running"),
+            }
+        }
+        _ => {
+            let mut upload = None;
+            let mut pos = Vec::new();
+            let mut parts = args.split_whitespace();
+            while let Some(p) = parts.next() {
+                match p {
+                    "--upload" => upload = parts.next().or(Some("")),
+                    _          => pos.push(p),
+                }
+            }
+            let port = match pos.first().map(|s| s.parse::<u16>()) {
+                None        => Some(80),
+                Some(Ok(p)) => Some(p),
+                Some(Err(_)) => None,
+            };
+            match port {
+                Some(port) if pos.len() <= 2 => start(port, pos.get(1).copied().unwrap_or("/"), upload),
+                _ => crate::println!("Usage: httpd [port] [root] [--upload dir] | httpd stop | httpd status"),
+            }
+        }
+    }
+}
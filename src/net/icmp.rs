@@ -1,7 +1,45 @@
-use super::ipv4;
+use super::ipv4::{self, Ipv4Header};
 
 pub const ICMP_ECHO_REQUEST: u8 = 8;
 pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACH: u8 = 3;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+pub const CODE_NET_UNREACH: u8 = 0;
+pub const CODE_HOST_UNREACH: u8 = 1;
+pub const CODE_PROTO_UNREACH: u8 = 2;
+pub const CODE_PORT_UNREACH: u8 = 3;
+pub const CODE_FRAG_NEEDED: u8 = 4;
+
+pub struct IcmpError<'a> {
+    pub kind: u8,
+    pub code: u8,
+    pub mtu: u16,
+    pub inner: Ipv4Header,
+    pub transport: &'a [u8],
+}
+
+pub fn parse_error(icmp: &[u8]) -> Option<IcmpError<'_>> {
+    if icmp.len() < 8 + 20 + 8 {
+        return None;
+    }
+    if icmp[0] != ICMP_DEST_UNREACH && icmp[0] != ICMP_TIME_EXCEEDED {
+        return None;
+    }
+    let orig = &icmp[8..];
+    let inner = Ipv4Header::parse(orig)?;
+    let transport = &orig[inner.ihl as usize..];
+    if transport.len() < 8 {
+        return None;
+    }
+    Some(IcmpError {
+        kind: icmp[0],
+        code: icmp[1],
+        mtu: u16::from_be_bytes([icmp[6], icmp[7]]),
+        inner,
+        transport,
+    })
+}
 
 pub fn build_echo_request(id: u16, seq: u16, payload: &[u8], out: &mut [u8]) -> usize {
     let icmp_len = 8 + payload.len();
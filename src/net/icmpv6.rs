@@ -0,0 +1,239 @@
+extern crate alloc;
+use alloc::vec;
+use alloc::vec::Vec;
+use super::icmp::EchoReply;
+use super::ip::{self, IpAddr};
+use super::ipv6::{self, Ipv6Header};
+
+pub const ICMPV6_DEST_UNREACH:   u8 = 1;
+pub const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+pub const ICMPV6_TIME_EXCEEDED:  u8 = 3;
+pub const ICMPV6_ECHO_REQUEST:   u8 = 128;
+pub const ICMPV6_ECHO_REPLY:     u8 = 129;
+pub const ND_ROUTER_SOLICIT:     u8 = 133;
+pub const ND_ROUTER_ADVERT:      u8 = 134;
+pub const ND_NEIGHBOR_SOLICIT:   u8 = 135;
+pub const ND_NEIGHBOR_ADVERT:    u8 = 136;
+
+pub const CODE_NO_ROUTE:     u8 = 0;
+pub const CODE_ADMIN:        u8 = 1;
+pub const CODE_ADDR_UNREACH: u8 = 3;
+pub const CODE_PORT_UNREACH: u8 = 4;
+
+const OPT_SOURCE_LLADDR: u8 = 1;
+const OPT_TARGET_LLADDR: u8 = 2;
+const OPT_PREFIX_INFO:   u8 = 3;
+const OPT_MTU:           u8 = 5;
+
+const NA_SOLICITED:      u8 = 0x40;
+const NA_OVERRIDE:       u8 = 0x20;
+const PREFIX_AUTONOMOUS: u8 = 0x40;
+
+pub const NEIGHBOR_CACHE_SIZE: usize = 16;
+
+#[derive(Clone, Copy)]
+pub struct Neighbor {
+    pub ip:    [u8; 16],
+    pub mac:   [u8; 6],
+    pub valid: bool,
+}
+
+pub struct NeighborCache {
+    pub entries: [Neighbor; NEIGHBOR_CACHE_SIZE],
+    next: usize,
+}
+
+impl NeighborCache {
+    pub const fn new() -> Self {
+        Self {
+            entries: [Neighbor { ip: [0; 16], mac: [0; 6], valid: false }; NEIGHBOR_CACHE_SIZE],
+            next:    0,
+        }
+    }
+
+    pub fn lookup(&self, ip: &[u8; 16]) -> Option<[u8; 6]> {
+        self.entries.iter().find(|e| e.valid && &e.ip == ip).map(|e| e.mac)
+    }
+
+    pub fn insert(&mut self, ip: [u8; 16], mac: [u8; 6]) {
+        if let Some(e) = self.entries.iter_mut().find(|e| e.valid && e.ip == ip) {
+            e.mac = mac;
+            return;
+        }
+        self.entries[self.next] = Neighbor { ip, mac, valid: true };
+        self.next = (self.next + 1) % NEIGHBOR_CACHE_SIZE;
+    }
+}
+
+pub fn seal(src: &[u8; 16], dst: &[u8; 16], msg: &mut [u8]) {
+    msg[2] = 0;
+    msg[3] = 0;
+    let csum = ip::pseudo_checksum(&IpAddr::V6(*src), &IpAddr::V6(*dst), ipv6::NEXT_ICMPV6, msg);
+    msg[2..4].copy_from_slice(&csum.to_be_bytes());
+}
+
+pub fn build_echo(kind: u8, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut msg = vec![kind, 0, 0, 0];
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&seq.to_be_bytes());
+    msg.extend_from_slice(payload);
+    msg
+}
+
+fn lladdr_option(kind: u8, mac: &[u8; 6]) -> [u8; 8] {
+    [kind, 1, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]]
+}
+
+pub fn build_router_solicit(mac: &[u8; 6]) -> Vec<u8> {
+    let mut msg = vec![ND_ROUTER_SOLICIT, 0, 0, 0, 0, 0, 0, 0];
+    msg.extend_from_slice(&lladdr_option(OPT_SOURCE_LLADDR, mac));
+    msg
+}
+
+pub fn build_neighbor_solicit(target: &[u8; 16], mac: Option<&[u8; 6]>) -> Vec<u8> {
+    let mut msg = vec![ND_NEIGHBOR_SOLICIT, 0, 0, 0, 0, 0, 0, 0];
+    msg.extend_from_slice(target);
+    if let Some(mac) = mac {
+        msg.extend_from_slice(&lladdr_option(OPT_SOURCE_LLADDR, mac));
+    }
+    msg
+}
+
+pub fn build_neighbor_advert(target: &[u8; 16], mac: &[u8; 6], solicited: bool) -> Vec<u8> {
+    let flags = NA_OVERRIDE | if solicited { NA_SOLICITED } else { 0 };
+    let mut msg = vec![ND_NEIGHBOR_ADVERT, 0, 0, 0, flags, 0, 0, 0];
+    msg.extend_from_slice(target);
+    msg.extend_from_slice(&lladdr_option(OPT_TARGET_LLADDR, mac));
+    msg
+}
+
+fn options(mut opts: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut out = Vec::new();
+    while opts.len() >= 8 {
+        let len = opts[1] as usize * 8;
+        if len == 0 || len > opts.len() {
+            break;
+        }
+        out.push((opts[0], &opts[2..len]));
+        opts = &opts[len..];
+    }
+    out
+}
+
+fn lladdr(opts: &[u8], kind: u8) -> Option<[u8; 6]> {
+    options(opts).into_iter()
+        .find(|&(k, body)| k == kind && body.len() >= 6)
+        .map(|(_, body)| [body[0], body[1], body[2], body[3], body[4], body[5]])
+}
+
+pub struct NeighborMsg {
+    pub target: [u8; 16],
+    pub mac:    Option<[u8; 6]>,
+}
+
+pub fn parse_neighbor(msg: &[u8]) -> Option<NeighborMsg> {
+    if msg.len() < 24 {
+        return None;
+    }
+    let kind = match msg[0] {
+        ND_NEIGHBOR_SOLICIT => OPT_SOURCE_LLADDR,
+        ND_NEIGHBOR_ADVERT  => OPT_TARGET_LLADDR,
+        _ => return None,
+    };
+    let mut target = [0u8; 16];
+    target.copy_from_slice(&msg[8..24]);
+    Some(NeighborMsg { target, mac: lladdr(&msg[24..], kind) })
+}
+
+pub struct Prefix {
+    pub prefix:     [u8; 16],
+    pub len:        u8,
+    pub autonomous: bool,
+    pub valid:      u32,
+}
+
+pub struct RouterAdvert {
+    pub lifetime: u16,
+    pub mac:      Option<[u8; 6]>,
+    pub mtu:      Option<u32>,
+    pub prefixes: Vec<Prefix>,
+}
+
+pub fn parse_router_advert(msg: &[u8]) -> Option<RouterAdvert> {
+    if msg.len() < 16 || msg[0] != ND_ROUTER_ADVERT {
+        return None;
+    }
+    let opts = &msg[16..];
+    let mut ra = RouterAdvert {
+        lifetime: u16::from_be_bytes([msg[6], msg[7]]),
+        mac:      lladdr(opts, OPT_SOURCE_LLADDR),
+        mtu:      None,
+        prefixes: Vec::new(),
+    };
+    for (kind, body) in options(opts) {
+        match kind {
+            OPT_MTU if body.len() >= 6 => {
+                ra.mtu = Some(u32::from_be_bytes([body[2], body[3], body[4], body[5]]));
+            }
+            OPT_PREFIX_INFO if body.len() >= 30 => {
+                let mut prefix = [0u8; 16];
+                prefix.copy_from_slice(&body[14..30]);
+                ra.prefixes.push(Prefix {
+                    prefix,
+                    len:        body[0],
+                    autonomous: body[1] & PREFIX_AUTONOMOUS != 0,
+                    valid:      u32::from_be_bytes([body[2], body[3], body[4], body[5]]),
+                });
+            }
+            _ => {}
+        }
+    }
+    Some(ra)
+}
+
+pub struct Icmpv6Error<'a> {
+    pub kind:      u8,
+    pub code:      u8,
+    pub mtu:       u32,
+    pub inner:     Ipv6Header,
+    pub transport: &'a [u8],
+}
+
+pub fn parse_error(msg: &[u8]) -> Option<Icmpv6Error<'_>> {
+    if msg.len() < 8 + ipv6::HEADER_LEN + 8 {
+        return None;
+    }
+    if !matches!(msg[0], ICMPV6_DEST_UNREACH | ICMPV6_PACKET_TOO_BIG | ICMPV6_TIME_EXCEEDED) {
+        return None;
+    }
+    let orig  = &msg[8..];
+    let inner = Ipv6Header::parse_quoted(orig)?;
+    let transport = &orig[inner.offset..];
+    if transport.len() < 8 {
+        return None;
+    }
+    Some(Icmpv6Error {
+        kind: msg[0],
+        code: msg[1],
+        mtu:  u32::from_be_bytes([msg[4], msg[5], msg[6], msg[7]]),
+        inner,
+        transport,
+    })
+}
+
+pub fn parse_echo_reply(ip_buf: &[u8]) -> Option<EchoReply> {
+    let ip = Ipv6Header::parse(ip_buf)?;
+    if ip.next != ipv6::NEXT_ICMPV6 {
+        return None;
+    }
+    let msg = ip.payload(ip_buf);
+    if msg.len() < 8 || msg[0] != ICMPV6_ECHO_REPLY {
+        return None;
+    }
+    Some(EchoReply {
+        id:       u16::from_be_bytes([msg[4], msg[5]]),
+        seq:      u16::from_be_bytes([msg[6], msg[7]]),
+        ttl:      ip.hop_limit,
+        data_len: msg.len() - 8,
+    })
+}
@@ -0,0 +1,139 @@
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use super::arp::ArpTable;
+use super::icmpv6::NeighborCache;
+use super::ipv4;
+use super::ipv6::{self, UNSPECIFIED};
+use super::NetworkDriver;
+
+pub const LOOPBACK:     usize    = 0;
+pub const MAX_IFACES:   usize    = 5;
+pub const LOOPBACK_IP:  [u8; 4]  = [127, 0, 0, 1];
+pub const LOOPBACK_IP6: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+pub const LOOPBACK_MTU: usize    = 16384;
+
+const LOOPBACK_QUEUE: usize = 256;
+
+#[derive(Default)]
+pub struct Loopback {
+    queue: VecDeque<Vec<u8>>,
+}
+
+impl NetworkDriver for Loopback {
+    fn send(&mut self, data: &[u8]) -> bool {
+        if self.queue.len() >= LOOPBACK_QUEUE {
+            return false;
+        }
+        self.queue.push_back(data.to_vec());
+        true
+    }
+
+    fn recv(&mut self, handler: &mut dyn FnMut(&[u8])) {
+        while let Some(frame) = self.queue.pop_front() {
+            handler(&frame);
+        }
+    }
+
+    fn has_packet(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    fn link_up(&self) -> bool {
+        true
+    }
+
+    fn get_mac(&self) -> [u8; 6] {
+        [0; 6]
+    }
+}
+
+pub struct Interface {
+    pub name:     String,
+    pub kind:     &'static str,
+    pub driver:   Box<dyn NetworkDriver>,
+    pub irq:      u8,
+    pub mac:      [u8; 6],
+    pub ip:       [u8; 4],
+    pub mask:     [u8; 4],
+    pub mtu:      usize,
+    pub up:       bool,
+    pub arp:      ArpTable,
+    pub ip6_ll:   [u8; 16],
+    pub ip6:      [u8; 16],
+    pub prefix6:  u8,
+    pub gw6:      [u8; 16],
+    pub ndp:      NeighborCache,
+    pub tx_count: u64,
+    pub rx_count: u64,
+}
+
+impl Interface {
+    pub fn new(name: String, kind: &'static str, driver: Box<dyn NetworkDriver>, irq: u8) -> Self {
+        let mac = driver.get_mac();
+        Self {
+            name,
+            kind,
+            driver,
+            irq,
+            mac,
+            ip:       [0; 4],
+            mask:     [0; 4],
+            mtu:      ipv4::MTU,
+            up:       true,
+            arp:      ArpTable::new(),
+            ip6_ll:   ipv6::link_local(&mac),
+            ip6:      UNSPECIFIED,
+            prefix6:  0,
+            gw6:      UNSPECIFIED,
+            ndp:      NeighborCache::new(),
+            tx_count: 0,
+            rx_count: 0,
+        }
+    }
+
+    pub fn loopback() -> Self {
+        let mut lo = Self::new(String::from("lo"), "loopback", Box::new(Loopback::default()), 0);
+        lo.ip      = LOOPBACK_IP;
+        lo.mask    = [255, 0, 0, 0];
+        lo.mtu     = LOOPBACK_MTU;
+        lo.ip6_ll  = UNSPECIFIED;
+        lo.ip6     = LOOPBACK_IP6;
+        lo.prefix6 = 128;
+        lo
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.kind == "loopback"
+    }
+
+    pub fn transmit(&mut self, frame: &[u8]) -> bool {
+        let ok = self.up && self.driver.send(frame);
+        if ok {
+            self.tx_count += 1;
+        }
+        ok
+    }
+
+    pub fn on_link(&self, dst: &[u8; 4]) -> bool {
+        self.ip != [0; 4] && (0..4).all(|i| dst[i] & self.mask[i] == self.ip[i] & self.mask[i])
+    }
+
+    pub fn broadcast(&self) -> [u8; 4] {
+        core::array::from_fn(|i| self.ip[i] | !self.mask[i])
+    }
+
+    pub fn owns(&self, a: &[u8; 4]) -> bool {
+        if self.is_loopback() { self.on_link(a) } else { self.ip != [0; 4] && *a == self.ip }
+    }
+
+    pub fn owns6(&self, a: &[u8; 16]) -> bool {
+        *a != UNSPECIFIED && (*a == self.ip6_ll || *a == self.ip6)
+    }
+
+    pub fn on_link6(&self, dst: &[u8; 16]) -> bool {
+        self.ip6 != UNSPECIFIED && ipv6::in_prefix(dst, &self.ip6, self.prefix6)
+    }
+}
@@ -0,0 +1,190 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum IpAddr {
+    V4([u8; 4]),
+    V6([u8; 16]),
+}
+
+impl IpAddr {
+    pub const UNSPECIFIED_V4: Self = IpAddr::V4([0; 4]);
+    pub const UNSPECIFIED_V6: Self = IpAddr::V6([0; 16]);
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+        if s.contains(':') {
+            parse_v6(s).map(IpAddr::V6)
+        } else {
+            parse_v4(s).map(IpAddr::V4)
+        }
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            IpAddr::V4(a) => *a == [0; 4],
+            IpAddr::V6(a) => *a == [0; 16],
+        }
+    }
+
+    pub fn is_v6(&self) -> bool {
+        matches!(self, IpAddr::V6(_))
+    }
+
+    pub fn header_len(&self) -> usize {
+        match self {
+            IpAddr::V4(_) => 20,
+            IpAddr::V6(_) => super::ipv6::HEADER_LEN,
+        }
+    }
+
+    pub fn to_v6(self) -> [u8; 16] {
+        match self {
+            IpAddr::V4(a) => {
+                let mut out = [0u8; 16];
+                out[10] = 0xff;
+                out[11] = 0xff;
+                out[12..].copy_from_slice(&a);
+                out
+            }
+            IpAddr::V6(a) => a,
+        }
+    }
+
+    pub fn from_v6(a: [u8; 16]) -> Self {
+        if a[..10] == [0; 10] && a[10] == 0xff && a[11] == 0xff {
+            IpAddr::V4([a[12], a[13], a[14], a[15]])
+        } else {
+            IpAddr::V6(a)
+        }
+    }
+}
+
+impl From<[u8; 4]> for IpAddr {
+    fn from(a: [u8; 4]) -> Self {
+        IpAddr::V4(a)
+    }
+}
+
+impl From<[u8; 16]> for IpAddr {
+    fn from(a: [u8; 16]) -> Self {
+        IpAddr::V6(a)
+    }
+}
+
+impl fmt::Display for IpAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpAddr::V4(a) => write!(f, "{}.{}.{}.{}", a[0], a[1], a[2], a[3]),
+            IpAddr::V6(a) => fmt_v6(a, f),
+        }
+    }
+}
+
+fn fmt_v6(a: &[u8; 16], f: &mut fmt::Formatter) -> fmt::Result {
+    let w: [u16; 8] = core::array::from_fn(|i| u16::from_be_bytes([a[2 * i], a[2 * i + 1]]));
+    let (mut best, mut best_len, mut i) = (8, 0, 0);
+    while i < 8 {
+        let start = i;
+        while i < 8 && w[i] == 0 {
+            i += 1;
+        }
+        if i - start > best_len {
+            best     = start;
+            best_len = i - start;
+        }
+        i += 1;
+    }
+    if best_len < 2 {
+        best = 8;
+    }
+    let mut i = 0;
+    while i < 8 {
+        if i == best {
+            f.write_str("::")?;
+            i += best_len;
+            continue;
+        }
+        if i > 0 && i != best + best_len {
+            f.write_str(":")?;
+        }
+        write!(f, "{:x}", w[i])?;
+        i += 1;
+    }
+    Ok(())
+}
+
+fn parse_v4(s: &str) -> Option<[u8; 4]> {
+    let mut p = s.split('.');
+    let ip = [p.next()?.parse().ok()?, p.next()?.parse().ok()?,
+              p.next()?.parse().ok()?, p.next()?.parse().ok()?];
+    if p.next().is_some() { None } else { Some(ip) }
+}
+
+fn parse_v6(s: &str) -> Option<[u8; 16]> {
+    let groups = |part: &str| -> Option<Vec<u16>> {
+        if part.is_empty() {
+            return Some(Vec::new());
+        }
+        part.split(':')
+            .map(|g| if g.is_empty() || g.len() > 4 { None } else { u16::from_str_radix(g, 16).ok() })
+            .collect()
+    };
+    let (head, tail) = match s.find("::") {
+        Some(i) => (groups(&s[..i])?, Some(groups(&s[i + 2..])?)),
+        None    => (groups(s)?, None),
+    };
+    let words = match &tail {
+        Some(t) if head.len() + t.len() <= 7 => {
+            let mut w = [0u16; 8];
+            w[..head.len()].copy_from_slice(&head);
+            w[8 - t.len()..].copy_from_slice(t);
+            w
+        }
+        None if head.len() == 8 => core::array::from_fn(|i| head[i]),
+        _ => return None,
+    };
+    let mut out = [0u8; 16];
+    for (i, w) in words.iter().enumerate() {
+        out[2 * i..2 * i + 2].copy_from_slice(&w.to_be_bytes());
+    }
+    Some(out)
+}
+
+pub fn pseudo_checksum(src: &IpAddr, dst: &IpAddr, proto: u8, data: &[u8]) -> u16 {
+    let mut pseudo = [0u8; 40];
+    let n = match (src, dst) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            pseudo[0..4].copy_from_slice(s);
+            pseudo[4..8].copy_from_slice(d);
+            pseudo[9] = proto;
+            pseudo[10..12].copy_from_slice(&(data.len() as u16).to_be_bytes());
+            12
+        }
+        (s, d) => {
+            pseudo[0..16].copy_from_slice(&s.to_v6());
+            pseudo[16..32].copy_from_slice(&d.to_v6());
+            pseudo[32..36].copy_from_slice(&(data.len() as u32).to_be_bytes());
+            pseudo[39] = proto;
+            40
+        }
+    };
+
+    let mut sum = 0u32;
+    for chunk in [&pseudo[..n], data] {
+        let mut i = 0;
+        while i + 1 < chunk.len() {
+            sum += u16::from_be_bytes([chunk[i], chunk[i + 1]]) as u32;
+            i += 2;
+        }
+        if i < chunk.len() {
+            sum += (chunk[i] as u32) << 8;
+        }
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    let r = !(sum as u16);
+    if r == 0 { 0xFFFF } else { r }
+}
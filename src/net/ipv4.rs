@@ -1,7 +1,23 @@
+extern crate alloc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+
 pub const PROTO_ICMP: u8 = 1;
 pub const PROTO_TCP: u8 = 6;
 pub const PROTO_UDP: u8 = 17;
 
+pub const MTU:          usize = 1500;
+pub const MAX_DATAGRAM: usize = 65535;
+
+const FLAG_DF:     u16   = 0x4000;
+const FLAG_MF:     u16   = 0x2000;
+const OFFSET_MASK: u16   = 0x1FFF;
+const MAX_PARTIAL: usize = 16;
+const MAX_PIECES:  usize = 64;
+
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
 const ICMP_ECHO_REQUEST: u8 = 8;
 const ICMP_ECHO_REPLY: u8 = 0;
 
@@ -13,6 +29,10 @@ pub struct Ipv4Header {
     pub ttl: u8,
     pub ihl: u8,
     pub total_len: u16,
+    pub id: u16,
+    pub dont_frag: bool,
+    pub more_frags: bool,
+    pub frag_off: usize,
 }
 
 impl Ipv4Header {
@@ -29,6 +49,10 @@ impl Ipv4Header {
             return None;
         }
         let total_len = u16::from_be_bytes([buf[2], buf[3]]);
+        if (total_len as usize) < ihl as usize {
+            return None;
+        }
+        let frag = u16::from_be_bytes([buf[6], buf[7]]);
         let ttl = buf[8];
         let proto = buf[9];
         let mut src = [0u8; 4];
@@ -42,9 +66,17 @@ impl Ipv4Header {
             ttl,
             ihl,
             total_len,
+            id: u16::from_be_bytes([buf[4], buf[5]]),
+            dont_frag: frag & FLAG_DF != 0,
+            more_frags: frag & FLAG_MF != 0,
+            frag_off: (frag & OFFSET_MASK) as usize * 8,
         })
     }
 
+    pub fn is_fragment(&self) -> bool {
+        self.more_frags || self.frag_off != 0
+    }
+
     pub fn payload<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
         let end = self.total_len as usize;
         let end = end.min(buf.len());
@@ -52,8 +84,24 @@ impl Ipv4Header {
     }
 }
 
+pub fn next_id() -> u16 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 pub fn build(src: &[u8; 4], dst: &[u8; 4], proto: u8, payload: &[u8], out: &mut [u8]) -> usize {
-    if out.len() < 20 + payload.len() {
+    build_fragment(src, dst, proto, next_id(), FLAG_DF, payload, out)
+}
+
+fn build_fragment(
+    src: &[u8; 4],
+    dst: &[u8; 4],
+    proto: u8,
+    id: u16,
+    frag: u16,
+    payload: &[u8],
+    out: &mut [u8],
+) -> usize {
+    if out.len() < 20 + payload.len() || 20 + payload.len() > MAX_DATAGRAM {
         return 0;
     }
     let total = (20 + payload.len()) as u16;
@@ -61,10 +109,10 @@ pub fn build(src: &[u8; 4], dst: &[u8; 4], proto: u8, payload: &[u8], out: &mut
     out[1] = 0;
     out[2] = (total >> 8) as u8;
     out[3] = total as u8;
-    out[4] = 0;
-    out[5] = 0;
-    out[6] = 0x40;
-    out[7] = 0;
+    out[4] = (id >> 8) as u8;
+    out[5] = id as u8;
+    out[6] = (frag >> 8) as u8;
+    out[7] = frag as u8;
     out[8] = 64;
     out[9] = proto;
     out[10] = 0;
@@ -80,6 +128,156 @@ pub fn build(src: &[u8; 4], dst: &[u8; 4], proto: u8, payload: &[u8], out: &mut
     20 + payload.len()
 }
 
+pub fn fragment(
+    src: &[u8; 4],
+    dst: &[u8; 4],
+    proto: u8,
+    payload: &[u8],
+    mtu: usize,
+    dont_frag: bool,
+) -> Vec<Vec<u8>> {
+    let id = next_id();
+    if 20 + payload.len() <= mtu {
+        let mut pkt = vec![0u8; 20 + payload.len()];
+        let flags = if dont_frag { FLAG_DF } else { 0 };
+        let n = build_fragment(src, dst, proto, id, flags, payload, &mut pkt);
+        return if n == 0 { Vec::new() } else { vec![pkt] };
+    }
+    if dont_frag || 20 + payload.len() > MAX_DATAGRAM {
+        return Vec::new();
+    }
+    let step = (mtu - 20) & !7;
+    payload
+        .chunks(step)
+        .enumerate()
+        .map(|(i, piece)| {
+            let off   = (i * step / 8) as u16;
+            let more  = (i + 1) * step < payload.len();
+            let flags = off | if more { FLAG_MF } else { 0 };
+            let mut pkt = vec![0u8; 20 + piece.len()];
+            build_fragment(src, dst, proto, id, flags, piece, &mut pkt);
+            pkt
+        })
+        .collect()
+}
+
+struct Partial {
+    src:      [u8; 4],
+    dst:      [u8; 4],
+    proto:    u8,
+    id:       u16,
+    header:   Vec<u8>,
+    data:     Vec<u8>,
+    pieces:   Vec<(usize, usize)>,
+    total:    Option<usize>,
+    deadline: u64,
+}
+
+impl Partial {
+    fn complete(&self) -> bool {
+        let total = match self.total {
+            Some(t) => t,
+            None => return false,
+        };
+        let mut covered = 0;
+        for &(start, end) in &self.pieces {
+            if start > covered {
+                return false;
+            }
+            covered = covered.max(end);
+        }
+        covered >= total
+    }
+}
+
+pub struct Reassembler {
+    partial: Vec<Partial>,
+}
+
+impl Reassembler {
+    pub const fn new() -> Self {
+        Self { partial: Vec::new() }
+    }
+
+    pub fn push(&mut self, hdr: &Ipv4Header, pkt: &[u8], now: u64, ttl: u64) -> Option<Vec<u8>> {
+        let data  = hdr.payload(pkt);
+        let start = hdr.frag_off;
+        let end   = start + data.len();
+        if end > MAX_DATAGRAM - hdr.ihl as usize || (hdr.more_frags && !data.len().is_multiple_of(8)) {
+            return None;
+        }
+        let pos = match self.partial.iter().position(|p| {
+            p.src == hdr.src && p.dst == hdr.dst && p.proto == hdr.proto && p.id == hdr.id
+        }) {
+            Some(i) => i,
+            None => {
+                if self.partial.len() >= MAX_PARTIAL {
+                    self.partial.remove(0);
+                }
+                self.partial.push(Partial {
+                    src:      hdr.src,
+                    dst:      hdr.dst,
+                    proto:    hdr.proto,
+                    id:       hdr.id,
+                    header:   Vec::new(),
+                    data:     Vec::new(),
+                    pieces:   Vec::new(),
+                    total:    None,
+                    deadline: now + ttl,
+                });
+                self.partial.len() - 1
+            }
+        };
+        let p = &mut self.partial[pos];
+        if p.pieces.len() >= MAX_PIECES {
+            self.partial.remove(pos);
+            return None;
+        }
+        if !hdr.more_frags {
+            if p.total.is_some_and(|t| t != end) || p.pieces.iter().any(|&(_, e)| e > end) {
+                self.partial.remove(pos);
+                return None;
+            }
+            p.total = Some(end);
+        } else if p.total.is_some_and(|t| end > t) {
+            self.partial.remove(pos);
+            return None;
+        }
+        if start == 0 {
+            p.header = pkt[..hdr.ihl as usize].to_vec();
+        }
+        if p.data.len() < end {
+            p.data.resize(end, 0);
+        }
+        p.data[start..end].copy_from_slice(data);
+        let at = p.pieces.partition_point(|&(s, _)| s < start);
+        p.pieces.insert(at, (start, end));
+        if !p.complete() || p.header.is_empty() {
+            return None;
+        }
+
+        let p = self.partial.remove(pos);
+        let ihl = p.header.len();
+        let total = ihl + p.data.len();
+        let mut out = p.header;
+        out.extend_from_slice(&p.data);
+        out[2] = (total >> 8) as u8;
+        out[3] = total as u8;
+        out[6] = 0;
+        out[7] = 0;
+        out[10] = 0;
+        out[11] = 0;
+        let csum = checksum(&out[..ihl]);
+        out[10] = (csum >> 8) as u8;
+        out[11] = csum as u8;
+        Some(out)
+    }
+
+    pub fn expire(&mut self, now: u64) {
+        self.partial.retain(|p| now < p.deadline);
+    }
+}
+
 pub fn checksum(data: &[u8]) -> u16 {
     let mut sum = 0u32;
     let mut i = 0;
@@ -0,0 +1,116 @@
+pub const NEXT_HOP_BY_HOP: u8 = 0;
+pub const NEXT_TCP:        u8 = 6;
+pub const NEXT_UDP:        u8 = 17;
+pub const NEXT_ROUTING:    u8 = 43;
+pub const NEXT_FRAGMENT:   u8 = 44;
+pub const NEXT_ICMPV6:     u8 = 58;
+pub const NEXT_DEST_OPTS:  u8 = 60;
+
+pub const HEADER_LEN: usize = 40;
+pub const MIN_MTU:    usize = 1280;
+pub const HOP_LIMIT:  u8    = 64;
+pub const NDP_HOPS:   u8    = 255;
+
+pub const UNSPECIFIED: [u8; 16] = [0; 16];
+pub const ALL_NODES:   [u8; 16] = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+pub const ALL_ROUTERS: [u8; 16] = [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ipv6Header {
+    pub src:       [u8; 16],
+    pub dst:       [u8; 16],
+    pub next:      u8,
+    pub hop_limit: u8,
+    pub offset:    usize,
+    pub end:       usize,
+}
+
+impl Ipv6Header {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let hdr = Self::parse_quoted(buf)?;
+        let declared = HEADER_LEN + u16::from_be_bytes([buf[4], buf[5]]) as usize;
+        if declared > buf.len() { None } else { Some(hdr) }
+    }
+
+    pub fn parse_quoted(buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN || buf[0] >> 4 != 6 {
+            return None;
+        }
+        let end = (HEADER_LEN + u16::from_be_bytes([buf[4], buf[5]]) as usize).min(buf.len());
+        let mut next   = buf[6];
+        let mut offset = HEADER_LEN;
+        while matches!(next, NEXT_HOP_BY_HOP | NEXT_ROUTING | NEXT_DEST_OPTS) {
+            if offset + 2 > end {
+                return None;
+            }
+            next    = buf[offset];
+            offset += (buf[offset + 1] as usize + 1) * 8;
+        }
+        if offset > end {
+            return None;
+        }
+        let mut src = [0u8; 16];
+        let mut dst = [0u8; 16];
+        src.copy_from_slice(&buf[8..24]);
+        dst.copy_from_slice(&buf[24..40]);
+        Some(Self { src, dst, next, hop_limit: buf[7], offset, end })
+    }
+
+    pub fn payload<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        &buf[self.offset..self.end]
+    }
+}
+
+pub fn build(src: &[u8; 16], dst: &[u8; 16], next: u8, hop_limit: u8, payload: &[u8], out: &mut [u8]) -> usize {
+    let len = HEADER_LEN + payload.len();
+    if out.len() < len || payload.len() > u16::MAX as usize {
+        return 0;
+    }
+    out[0..4].copy_from_slice(&[0x60, 0, 0, 0]);
+    out[4..6].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+    out[6] = next;
+    out[7] = hop_limit;
+    out[8..24].copy_from_slice(src);
+    out[24..40].copy_from_slice(dst);
+    out[HEADER_LEN..len].copy_from_slice(payload);
+    len
+}
+
+pub fn link_local(mac: &[u8; 6]) -> [u8; 16] {
+    let mut a = [0u8; 16];
+    a[0] = 0xfe;
+    a[1] = 0x80;
+    a[8..16].copy_from_slice(&[mac[0] ^ 0x02, mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]);
+    a
+}
+
+pub fn with_prefix(prefix: &[u8; 16], iid: &[u8; 16]) -> [u8; 16] {
+    let mut a = *iid;
+    a[..8].copy_from_slice(&prefix[..8]);
+    a
+}
+
+pub fn solicited_node(a: &[u8; 16]) -> [u8; 16] {
+    [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, a[13], a[14], a[15]]
+}
+
+pub fn multicast_mac(a: &[u8; 16]) -> [u8; 6] {
+    [0x33, 0x33, a[12], a[13], a[14], a[15]]
+}
+
+pub fn is_multicast(a: &[u8; 16]) -> bool {
+    a[0] == 0xff
+}
+
+pub fn is_link_local(a: &[u8; 16]) -> bool {
+    a[0] == 0xfe && a[1] & 0xc0 == 0x80
+}
+
+pub fn in_prefix(a: &[u8; 16], prefix: &[u8; 16], len: u8) -> bool {
+    let len = len.min(128);
+    let (bytes, bits) = (len as usize / 8, len % 8);
+    if a[..bytes] != prefix[..bytes] {
+        return false;
+    }
+    bits == 0 || (a[bytes] ^ prefix[bytes]) >> (8 - bits) == 0
+}
@@ -4,10 +4,15 @@ pub mod dhcp;
 pub mod e1000;
 pub mod eth;
 pub mod icmp;
+pub mod icmpv6;
+pub mod iface;
+pub mod ip;
 pub mod ipv4;
-pub mod pci;
+pub mod ipv6;
 pub mod rtl8139;
+pub mod route;
 pub mod rtl8168;
+pub mod socket;
 pub mod tcp;
 pub mod tls;
 pub mod tls_bignum;
@@ -19,21 +24,38 @@ pub mod virtio;
 pub mod ntp;
 pub mod traceroute;
 pub mod http;
+pub mod httpd;
 pub mod tls_ecdh;
 pub mod http2;
+pub mod hpack;
 pub mod tls_gcm;
+pub mod tls_chacha;
+pub mod tls_x25519;
+pub mod tls_p384;
+pub mod tls_x509;
+pub mod tls_trust;
+pub mod tls_session;
 
 extern crate alloc;
 use alloc::boxed::Box;
-use arp::ArpTable;
-use core::sync::atomic::{AtomicBool, Ordering};
-use eth::{EthFrame, ETHERTYPE_ARP, ETHERTYPE_IP};
-use pci::{
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use eth::{EthFrame, BROADCAST_MAC, ETHERTYPE_ARP, ETHERTYPE_IP, ETHERTYPE_IPV6};
+use iface::{Interface, LOOPBACK, MAX_IFACES};
+use ip::IpAddr;
+use ipv6::{Ipv6Header, UNSPECIFIED};
+use route::{Origin, Route, RouteTable};
+use crate::pci::{
+    self,
     DEV_E1000_82540EM, DEV_E1000_82545EM, DEV_E1000_82574L, DEV_E1000_82579LM, DEV_E1000_I217,
     DEV_RTL8139, DEV_RTL8168, DEV_RTL8169, VENDOR_INTEL, VENDOR_REALTEK, DEV_VIRTIO_NET, VENDOR_VIRTIO,
 };
 use spin::Mutex;
-use udp::UdpSocket;
+use x86_64::instructions::interrupts;
 use x86_64::registers::control::Cr3;
 use x86_64::structures::paging::{PageTable, PageTableFlags};
 
@@ -42,7 +64,15 @@ pub use crate::grub::{phys_to_virt, virt_to_phys};
 
 static NET_READY: AtomicBool = AtomicBool::new(false);
 pub static CTRL_C: AtomicBool = AtomicBool::new(false);
-static DRIVER_NAME: Mutex<&'static str> = Mutex::new("none");
+static NET_IRQS: [AtomicU8; MAX_IFACES] = [const { AtomicU8::new(0) }; MAX_IFACES];
+static NET_THREAD: AtomicU64 = AtomicU64::new(0);
+static IRQ_PENDING: AtomicBool = AtomicBool::new(false);
+static RAW_TAPS: AtomicUsize = AtomicUsize::new(0);
+static RAW_RX: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+static REASM: Mutex<ipv4::Reassembler> = Mutex::new(ipv4::Reassembler::new());
+static PMTU: Mutex<BTreeMap<IpAddr, (usize, u64)>> = Mutex::new(BTreeMap::new());
+static RS_SENT: AtomicU8 = AtomicU8::new(0);
+static RS_AT: AtomicU64 = AtomicU64::new(0);
 
 pub trait NetworkDriver: Send {
     fn send(&mut self, data: &[u8]) -> bool;
@@ -50,36 +80,101 @@ pub trait NetworkDriver: Send {
     fn has_packet(&self) -> bool;
     fn link_up(&self) -> bool;
     fn get_mac(&self) -> [u8; 6];
+    fn enable_irq(&mut self) {}
+    fn ack_irq(&mut self) {}
 }
 
 pub(crate) struct NetState {
-    driver: Option<Box<dyn NetworkDriver>>,
-    mac: [u8; 6],
-    ip: [u8; 4],
-    gw: [u8; 4],
-    mask: [u8; 4],
-    dns: [u8; 4],
-    arp: ArpTable,
-    udp: UdpSocket,
-    tx_count: u64,
-    rx_count: u64,
+    ifaces: Vec<Interface>,
+    routes: RouteTable,
+    dns: Vec<IpAddr>,
 }
 
 impl NetState {
     const fn new() -> Self {
         Self {
-            driver: None,
-            mac: [0; 6],
-            ip: [10, 0, 2, 15],
-            gw: [10, 0, 2, 2],
-            mask: [255, 255, 255, 0],
-            dns: [8, 8, 8, 8],
-            arp: ArpTable::new(),
-            udp: UdpSocket::new(6969),
-            tx_count: 0,
-            rx_count: 0,
+            ifaces: Vec::new(),
+            routes: RouteTable::new(),
+            dns: Vec::new(),
         }
     }
+
+    fn primary(&self) -> usize {
+        if self.ifaces.len() > 1 { 1 } else { LOOPBACK }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.ifaces.iter().position(|i| i.name == name)
+    }
+
+    fn set_addr(&mut self, idx: usize, ip: [u8; 4], mask: [u8; 4]) {
+        self.ifaces[idx].ip   = ip;
+        self.ifaces[idx].mask = mask;
+        self.routes.retain(|r| !(r.iface == idx && r.origin == Origin::Kernel));
+        if ip != [0; 4] {
+            let prefix = route::prefix_len(&mask);
+            self.routes.add(Route { dest: ip, prefix, gw: None, iface: idx, metric: 0, origin: Origin::Kernel });
+        }
+    }
+
+    fn route4(&self, dst: &[u8; 4]) -> Option<(usize, [u8; 4])> {
+        if *dst == [255; 4] {
+            return Some((self.primary(), *dst));
+        }
+        if self.ifaces.iter().any(|i| i.owns(dst)) {
+            return Some((LOOPBACK, *dst));
+        }
+        let r = self.routes.lookup(dst, |i| self.ifaces.get(i).is_some_and(|f| f.up))?;
+        Some((r.iface, r.gw.unwrap_or(*dst)))
+    }
+
+    fn route6(&self, src: Option<&[u8; 16]>, dst: &[u8; 16]) -> Option<(usize, [u8; 16])> {
+        if self.ifaces.iter().any(|i| i.owns6(dst)) {
+            return Some((LOOPBACK, *dst));
+        }
+        let by_src = src.and_then(|s| self.ifaces.iter().position(|i| i.owns6(s)));
+        if ipv6::is_multicast(dst) || ipv6::is_link_local(dst) {
+            return Some((by_src.unwrap_or(self.primary()), *dst));
+        }
+        if let Some(i) = self.ifaces.iter().position(|i| i.up && i.on_link6(dst)) {
+            return Some((i, *dst));
+        }
+        let via = |i: usize| {
+            let f = &self.ifaces[i];
+            (f.up && f.gw6 != UNSPECIFIED).then_some((i, f.gw6))
+        };
+        by_src.and_then(via).or_else(|| (0..self.ifaces.len()).find_map(via))
+    }
+
+    fn source4(&self, idx: usize, dst: &[u8; 4]) -> [u8; 4] {
+        let iface = &self.ifaces[idx];
+        match idx {
+            LOOPBACK if !iface.on_link(dst) => *dst,
+            _ => iface.ip,
+        }
+    }
+
+    fn neighbour(&self, idx: usize, hop: &[u8; 4]) -> Option<[u8; 6]> {
+        let iface = self.ifaces.get(idx)?;
+        if iface.is_loopback() {
+            return Some([0; 6]);
+        }
+        if *hop == [255; 4] || *hop == iface.broadcast() {
+            return Some(BROADCAST_MAC);
+        }
+        iface.arp.lookup(hop)
+    }
+
+    fn neighbour6(&self, idx: usize, hop: &[u8; 16]) -> Option<[u8; 6]> {
+        let iface = self.ifaces.get(idx)?;
+        if iface.is_loopback() {
+            return Some([0; 6]);
+        }
+        if ipv6::is_multicast(hop) {
+            return Some(ipv6::multicast_mac(hop));
+        }
+        iface.ndp.lookup(hop)
+    }
 }
 
 pub static NET: Mutex<NetState> = Mutex::new(NetState::new());
@@ -212,23 +307,7 @@ pub fn map_mmio(phys_addr: u64, size: u64) {
     }
 }
 
-pub fn init() -> Result<(), &'static str> {
-    crate::serial_println!("[net] init: scanning PCI");
-    let pci_dev = match pci::find_nic() {
-        Some(d) => d,
-        None => return Err("no network adapter found"),
-    };
-
-    crate::serial_println!(
-        "[net] found: vendor={:04x} device={:04x} bus={:02x}:{:02x}.{}",
-        pci_dev.vendor, pci_dev.device,
-        pci_dev.bus, pci_dev.dev, pci_dev.func
-    );
-
-    let mut state = NET.lock();
-    let mut initialized_driver: Option<Box<dyn NetworkDriver>> = None;
-    let mut drv_name: &'static str = "unknown";
-
+fn probe(pci_dev: &pci::PciDevice) -> Option<(Box<dyn NetworkDriver>, &'static str)> {
     match (pci_dev.vendor, pci_dev.device) {
         (VENDOR_INTEL, DEV_E1000_82540EM | DEV_E1000_82545EM | DEV_E1000_82574L
             | DEV_E1000_82579LM | DEV_E1000_I217) => {
@@ -237,13 +316,15 @@ pub fn init() -> Result<(), &'static str> {
                 map_mmio(mem_phys, 128 * 1024);
             }
             crate::serial_println!("[net] init: e1000 driver init");
-            if let Some(drv) = e1000::E1000::new(&pci_dev) {
-                state.mac = drv.get_mac();
-                drv_name = pci::device_name(pci_dev.vendor, pci_dev.device);
-                initialized_driver = Some(drv);
-                crate::serial_println!("[net] init: e1000 ok");
-            } else {
-                crate::serial_println!("[net] init: e1000 driver returned None");
+            match e1000::E1000::new(pci_dev) {
+                Some(drv) => {
+                    crate::serial_println!("[net] init: e1000 ok");
+                    Some((drv, pci::device_name(pci_dev.vendor, pci_dev.device)))
+                }
+                None => {
+                    crate::serial_println!("[net] init: e1000 driver returned None");
+                    None
+                }
             }
         }
         (VENDOR_REALTEK, DEV_RTL8168) => {
@@ -252,301 +333,748 @@ pub fn init() -> Result<(), &'static str> {
                 map_mmio(mem_phys, 0x1000);
             }
             crate::serial_println!("[net] init: rtl8168 driver init");
-            if let Some(drv) = rtl8168::Rtl8168::new(&pci_dev) {
-                state.mac = drv.get_mac();
-                drv_name = "RTL8168 (r8168)";
-                initialized_driver = Some(Box::new(drv));
-            }
+            let drv = rtl8168::Rtl8168::new(pci_dev)?;
+            Some((Box::new(drv), "RTL8168 (r8168)"))
         }
         (VENDOR_REALTEK, DEV_RTL8139 | DEV_RTL8169) => {
             crate::serial_println!("[net] init: rtl8139 driver init");
-            if let Some(drv) = rtl8139::Rtl8139::new(&pci_dev) {
-                state.mac = drv.get_mac();
-                drv_name = pci::device_name(pci_dev.vendor, pci_dev.device);
-                initialized_driver = Some(Box::new(drv));
-            }
+            let drv = rtl8139::Rtl8139::new(pci_dev)?;
+            Some((Box::new(drv), pci::device_name(pci_dev.vendor, pci_dev.device)))
         }
         (VENDOR_VIRTIO, DEV_VIRTIO_NET) => {
             crate::serial_println!("[net] init: virtio-net driver init");
-            if let Some(drv) = virtio::VirtioNet::new(&pci_dev) {
-                state.mac = drv.get_mac();
-                drv_name = "VirtIO-net (legacy)";
-                initialized_driver = Some(Box::new(drv));
-            } else {
-                return Err("virtio-net driver init failed");
+            match virtio::VirtioNet::new(pci_dev) {
+                Some(drv) => Some((Box::new(drv), "VirtIO-net (legacy)")),
+                None => {
+                    crate::serial_println!("[net] init: virtio-net driver init failed");
+                    None
+                }
             }
         }
-        _ => return Err("unsupported network adapter"),
+        _ => None,
     }
+}
+
+pub fn init() -> Result<(), &'static str> {
+    let mut state = NET.lock();
+    state.ifaces.push(Interface::loopback());
+    state.set_addr(LOOPBACK, iface::LOOPBACK_IP, [255, 0, 0, 0]);
 
-    if let Some(drv) = initialized_driver {
-        state.driver = Some(drv);
-        let mac = state.mac;
-        drop(state);
-        *DRIVER_NAME.lock() = drv_name;
-        NET_READY.store(true, Ordering::Release);
+    crate::serial_println!("[net] init: scanning PCI");
+    let (devs, n) = pci::scan();
+    for pci_dev in devs.iter().take(n).filter(|d| d.class == pci::CLASS_NETWORK) {
+        if state.ifaces.len() >= MAX_IFACES {
+            break;
+        }
         crate::serial_println!(
-            "[net] {} ready  mac: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-            drv_name, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+            "[net] found: vendor={:04x} device={:04x} bus={:02x}:{:02x}.{}",
+            pci_dev.vendor, pci_dev.device,
+            pci_dev.bus, pci_dev.dev, pci_dev.func
         );
-        Ok(())
-    } else {
-        Err("driver init failed")
+        let (mut drv, kind) = match probe(pci_dev) {
+            Some(d) => d,
+            None => continue,
+        };
+        drv.enable_irq();
+        let idx   = state.ifaces.len();
+        let iface = Interface::new(format!("eth{}", idx - 1), kind, drv, pci_dev.irq);
+        let mac   = iface.mac;
+        crate::serial_println!(
+            "[net] {} {} ready  mac: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            iface.name, kind, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+        );
+        state.ifaces.push(iface);
+        if crate::interrupts::unmask_pci_irq(pci_dev.irq) {
+            NET_IRQS[idx].store(pci_dev.irq, Ordering::Release);
+            crate::serial_println!("[net] irq line {} unmasked", pci_dev.irq);
+        } else {
+            crate::serial_println!("[net] irq line {} not routable, polling", pci_dev.irq);
+        }
+    }
+
+    let nics = state.ifaces.len() - 1;
+    if nics > 0 {
+        let eth0 = state.primary();
+        state.set_addr(eth0, [10, 0, 2, 15], [255, 255, 255, 0]);
+        state.routes.add(Route {
+            dest:   [0; 4],
+            prefix: 0,
+            gw:     Some([10, 0, 2, 2]),
+            iface:  eth0,
+            metric: 0,
+            origin: Origin::Static,
+        });
     }
+    drop(state);
+    NET_READY.store(true, Ordering::Release);
+    if nics == 0 { Err("no network adapter found") } else { Ok(()) }
+}
+
+pub fn has_nic() -> bool {
+    NET.lock().ifaces.len() > 1
 }
 
 pub fn is_ready() -> bool {
     NET_READY.load(Ordering::Acquire)
 }
 
-pub fn poll_from_irq() {
-    if !NET_READY.load(Ordering::Acquire) {
+const RAW_QUEUE:   usize = 32;
+const NET_TICK:    u64   = crate::interrupts::PIT_HZ as u64 / 50;
+const REASM_TICKS: u64   = 30 * crate::interrupts::PIT_HZ as u64;
+const PMTU_TICKS:  u64   = 600 * crate::interrupts::PIT_HZ as u64;
+const RS_TICKS:    u64   = 4 * crate::interrupts::PIT_HZ as u64;
+const MAX_RS:      u8    = 3;
+
+pub fn start() {
+    if !is_ready() {
         return;
     }
+    let pid = crate::scheduler::spawn_named(net_thread, "netd", 2);
+    NET_THREAD.store(pid, Ordering::Release);
+}
 
-    let mut state = match NET.try_lock() {
-        Some(s) => s,
-        None    => return,
-    };
-
-    let mut packets = [[0u8; 1520]; 4];
-    let mut pkt_lens = [0usize; 4];
-    let mut pkt_count = 0usize;
-
-    if let Some(drv) = state.driver.as_mut() {
-        drv.recv(&mut |buf| {
-            if pkt_count < 4 {
-                let len = buf.len().min(1520);
-                packets[pkt_count][..len].copy_from_slice(&buf[..len]);
-                pkt_lens[pkt_count] = len;
-                pkt_count += 1;
+fn net_thread() -> ! {
+    interrupts::enable();
+    loop {
+        let fired = IRQ_PENDING.swap(false, Ordering::AcqRel);
+        if fired {
+            for iface in NET.lock().ifaces.iter_mut() {
+                iface.driver.ack_irq();
             }
-        });
-    }
-
-    let mac = state.mac;
-    let ip  = state.ip;
-    let mut tx_frames = [[0u8; 64]; 4];
-    let mut tx_lens = [0usize; 4];
-    let mut tx_count = 0usize;
-
-    for i in 0..pkt_count {
-        state.rx_count += 1;
-        let buf = &packets[i][..pkt_lens[i]];
-
-        if let Some(frame) = eth::EthFrame::parse(buf) {
-            if frame.ethertype == eth::ETHERTYPE_ARP && tx_count < 4 {
-                let mut arp_buf = [0u8; 64];
-                let n = arp::handle(&frame, &mac, &ip, &mut state.arp, &mut arp_buf);
-                if n > 0 {
-                    tx_frames[tx_count][..n].copy_from_slice(&arp_buf[..n]);
-                    tx_lens[tx_count] = n;
-                    tx_count += 1;
-                }
+        }
+        poll();
+        socket::tick();
+        let now = crate::vfs::procfs::uptime_ticks();
+        REASM.lock().expire(now);
+        router_solicit(now);
+        if fired {
+            for line in NET_IRQS.iter().map(|l| l.load(Ordering::Acquire)).filter(|&l| l != 0) {
+                crate::interrupts::unmask_pci_irq(line);
             }
         }
+        let looped = NET.lock().ifaces.get(LOOPBACK).is_some_and(|lo| lo.driver.has_packet());
+        if !looped && !IRQ_PENDING.load(Ordering::Acquire) {
+            crate::scheduler::sleep(NET_TICK);
+        }
     }
+}
 
-    let mut tx_ok = 0u64;
-    for i in 0..tx_count {
-        if let Some(drv) = state.driver.as_mut() {
-            if drv.send(&tx_frames[i][..tx_lens[i]]) {
-                tx_ok += 1;
-            }
-        }
+pub fn handle_irq(line: u8) {
+    if line == 0 || !NET_IRQS.iter().any(|l| l.load(Ordering::Acquire) == line) {
+        return;
+    }
+    crate::interrupts::mask_pci_irq(line);
+    IRQ_PENDING.store(true, Ordering::Release);
+    let pid = NET_THREAD.load(Ordering::Acquire);
+    if pid != 0 {
+        crate::scheduler::wakeup(pid);
     }
-    state.tx_count += tx_ok;
 }
 
 pub fn poll() {
     if !is_ready() {
         return;
     }
-    let mut state = NET.lock();
+    loop {
+        let frames = receive(&mut NET.lock());
+        if frames.is_empty() {
+            break;
+        }
+        for (idx, frame) in &frames {
+            input(*idx, frame);
+        }
+    }
+}
 
-    let mut packets = [[0u8; 1520]; 8];
-    let mut pkt_lens = [0usize; 8];
-    let mut pkt_count = 0usize;
-
-    if let Some(drv) = state.driver.as_mut() {
-        drv.recv(&mut |buf| {
-            if pkt_count < 8 {
-                let len = buf.len().min(1520);
-                packets[pkt_count][..len].copy_from_slice(&buf[..len]);
-                pkt_lens[pkt_count] = len;
-                pkt_count += 1;
-            }
-        });
+fn receive(state: &mut NetState) -> Vec<(usize, Vec<u8>)> {
+    let mut frames = Vec::new();
+    for (idx, iface) in state.ifaces.iter_mut().enumerate() {
+        let (before, up) = (frames.len(), iface.up);
+        iface.driver.recv(&mut |buf| if up { frames.push((idx, buf.to_vec())) });
+        iface.rx_count += (frames.len() - before) as u64;
     }
+    frames
+}
 
-    let mac = state.mac;
-    let ip = state.ip;
-    let mut tx_frames = [[0u8; 1520]; 4];
-    let mut tx_lens = [0usize; 4];
-    let mut tx_count = 0usize;
-
-    for i in 0..pkt_count {
-        state.rx_count += 1;
-        let buf = &packets[i][..pkt_lens[i]];
-
-        if let Some(frame) = EthFrame::parse(buf) {
-            match frame.ethertype {
-                ETHERTYPE_ARP => {
-                    if tx_count < 4 {
-                        let mut arp_buf = [0u8; 64];
-                        let n = arp::handle(&frame, &mac, &ip, &mut state.arp, &mut arp_buf);
-                        if n > 0 {
-                            tx_frames[tx_count][..n].copy_from_slice(&arp_buf[..n]);
-                            tx_lens[tx_count] = n;
-                            tx_count += 1;
-                        }
-                    }
+fn wake_netd() {
+    let pid = NET_THREAD.load(Ordering::Acquire);
+    if pid != 0 && pid != crate::scheduler::current_pid() {
+        crate::scheduler::wakeup(pid);
+    }
+}
+
+pub struct RawTap;
+
+impl RawTap {
+    pub fn open() -> Self {
+        RAW_TAPS.fetch_add(1, Ordering::AcqRel);
+        RawTap
+    }
+
+    pub fn recv(&self, handler: &mut dyn FnMut(&[u8])) {
+        poll();
+        let frames: Vec<Vec<u8>> = interrupts::without_interrupts(|| RAW_RX.lock().drain(..).collect());
+        for frame in &frames {
+            handler(frame);
+        }
+    }
+}
+
+impl Drop for RawTap {
+    fn drop(&mut self) {
+        if RAW_TAPS.fetch_sub(1, Ordering::AcqRel) == 1 {
+            interrupts::without_interrupts(|| RAW_RX.lock().clear());
+        }
+    }
+}
+
+fn tap(buf: &[u8]) {
+    if RAW_TAPS.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    interrupts::without_interrupts(|| {
+        let mut q = RAW_RX.lock();
+        if q.len() >= RAW_QUEUE {
+            q.pop_front();
+        }
+        q.push_back(buf.to_vec());
+    });
+}
+
+fn input(idx: usize, buf: &[u8]) {
+    let frame = match EthFrame::parse(buf) {
+        Some(f) => f,
+        None => return,
+    };
+    match frame.ethertype {
+        ETHERTYPE_ARP => {
+            let mut state = NET.lock();
+            let iface = match state.ifaces.get_mut(idx) {
+                Some(i) => i,
+                None => return,
+            };
+            let (mac, ip) = (iface.mac, iface.ip);
+            let mut reply = [0u8; 64];
+            let n = arp::handle(&frame, &mac, &ip, &mut iface.arp, &mut reply);
+            if n > 0 {
+                iface.transmit(&reply[..n]);
+            }
+        }
+        ETHERTYPE_IP => {
+            let hdr = match ipv4::Ipv4Header::parse(frame.payload) {
+                Some(h) => h,
+                None => return,
+            };
+            if hdr.is_fragment() {
+                let now = crate::vfs::procfs::uptime_ticks();
+                let whole = REASM.lock().push(&hdr, frame.payload, now, REASM_TICKS);
+                if let Some(pkt) = whole {
+                    let mut joined = buf[..14].to_vec();
+                    joined.extend_from_slice(&pkt);
+                    input(idx, &joined);
                 }
-                ETHERTYPE_IP => {
-                    if let Some(ip_hdr) = ipv4::Ipv4Header::parse(frame.payload) {
-                        let payload = ip_hdr.payload(frame.payload);
-                        match ip_hdr.proto {
-                            ipv4::PROTO_ICMP => {
-                                if tx_count < 4 {
-                                    let mut ip_reply = [0u8; 1500];
-                                    let ip_len = ipv4::handle_icmp(
-                                        &ip_hdr, &ip, payload, &mut ip_reply,
-                                    );
-                                    if ip_len > 0 {
-                                        let n = EthFrame::build(
-                                            &frame.src, &mac, ETHERTYPE_IP,
-                                            &ip_reply[..ip_len], &mut tx_frames[tx_count],
-                                        );
-                                        if n > 0 {
-                                            tx_lens[tx_count] = n;
-                                            tx_count += 1;
-                                        }
-                                    }
-                                }
-                            }
-                            ipv4::PROTO_UDP => {
-                                if let Some(udp_hdr) = udp::UdpHeader::parse(payload) {
-                                    if udp_hdr.dst_port == state.udp.port {
-                                        state.udp.on_recv(
-                                            &ip_hdr.src,
-                                            udp_hdr.src_port,
-                                            udp_hdr.payload(payload),
-                                        );
-                                    }
-                                }
-                            }
-                            ipv4::PROTO_TCP => {
-                                if let Some(seg) = tcp::TcpSegment::parse(payload) {
-                                    let mut rst_buf = [0u8; 1500];
-                                    let rst_len = tcp::build(
-                                        seg.dst_port, seg.src_port,
-                                        0, seg.seq.wrapping_add(1),
-                                        tcp::FLAG_RST | tcp::FLAG_ACK,
-                                        0, &[], &ip, &ip_hdr.src, &mut rst_buf,
-                                    );
-                                    if rst_len > 0 && tx_count < 4 {
-                                        let n = EthFrame::build(
-                                            &frame.src, &mac, ETHERTYPE_IP,
-                                            &rst_buf[..rst_len], &mut tx_frames[tx_count],
-                                        );
-                                        if n > 0 {
-                                            tx_lens[tx_count] = n;
-                                            tx_count += 1;
-                                        }
-                                    }
-                                }
+                return;
+            }
+            let payload = hdr.payload(frame.payload);
+            let (local, mac) = {
+                let mut state = NET.lock();
+                let local = state.ifaces.iter().any(|i| i.owns(&hdr.dst));
+                let iface = match state.ifaces.get_mut(idx) {
+                    Some(i) => i,
+                    None => return,
+                };
+                let accept = local
+                    || hdr.dst == [255; 4]
+                    || hdr.dst == iface.broadcast()
+                    || hdr.dst[0] & 0xf0 == 0xe0
+                    || iface.ip == [0; 4];
+                if !accept {
+                    return;
+                }
+                if iface.on_link(&hdr.src) {
+                    iface.arp.insert(hdr.src, frame.src);
+                }
+                (local, iface.mac)
+            };
+            match hdr.proto {
+                ipv4::PROTO_ICMP => {
+                    let mut reply = [0u8; 1500];
+                    let n = if local { ipv4::handle_icmp(&hdr, &hdr.dst, payload, &mut reply) } else { 0 };
+                    if n > 0 {
+                        let mut out = [0u8; 1520];
+                        let len = EthFrame::build(&frame.src, &mac, ETHERTYPE_IP, &reply[..n], &mut out);
+                        if len > 0 {
+                            if let Some(iface) = NET.lock().ifaces.get_mut(idx) {
+                                iface.transmit(&out[..len]);
                             }
-                            _ => {}
                         }
+                    } else {
+                        if let Some(err) = icmp::parse_error(payload) {
+                            socket::icmp_input(&err);
+                        }
+                        tap(buf);
+                    }
+                }
+                ipv4::PROTO_UDP => {
+                    if !socket::udp_input(hdr.src.into(), hdr.dst.into(), payload) {
+                        tap(buf);
                     }
                 }
-                _ => {}
+                ipv4::PROTO_TCP => socket::tcp_input(hdr.src.into(), hdr.dst.into(), payload),
+                _ => tap(buf),
             }
         }
+        ETHERTYPE_IPV6 => ipv6_input(idx, &frame, buf),
+        _ => {}
     }
+}
 
-    let mut tx_success = 0;
-    for i in 0..tx_count {
-        if let Some(drv) = state.driver.as_mut() {
-            if drv.send(&tx_frames[i][..tx_lens[i]]) {
-                tx_success += 1;
+fn ipv6_input(idx: usize, frame: &EthFrame, buf: &[u8]) {
+    let hdr = match Ipv6Header::parse(frame.payload) {
+        Some(h) => h,
+        None => return,
+    };
+    let ours = {
+        let state = NET.lock();
+        let iface = match state.ifaces.get(idx) {
+            Some(i) => i,
+            None => return,
+        };
+        hdr.dst == ipv6::ALL_NODES
+            || state.ifaces.iter().any(|i| i.owns6(&hdr.dst))
+            || [iface.ip6_ll, iface.ip6].iter().any(|a| *a != UNSPECIFIED && hdr.dst == ipv6::solicited_node(a))
+    };
+    if !ours {
+        return;
+    }
+    let payload = hdr.payload(frame.payload);
+    let (src, dst) = (IpAddr::V6(hdr.src), IpAddr::V6(hdr.dst));
+    match hdr.next {
+        ipv6::NEXT_ICMPV6 => icmpv6_input(idx, frame, &hdr, payload, buf),
+        ipv6::NEXT_UDP => {
+            if !socket::udp_input(src, dst, payload) {
+                tap(buf);
             }
         }
+        ipv6::NEXT_TCP => socket::tcp_input(src, dst, payload),
+        _ => tap(buf),
     }
-    state.tx_count += tx_success;
 }
 
-pub fn send_udp(dst_ip: &[u8; 4], dst_port: u16, src_port: u16, data: &[u8]) -> bool {
+fn icmpv6_input(idx: usize, frame: &EthFrame, hdr: &Ipv6Header, msg: &[u8], buf: &[u8]) {
+    if msg.len() < 8 {
+        return;
+    }
+    let ndp = hdr.hop_limit == ipv6::NDP_HOPS;
+    match msg[0] {
+        icmpv6::ICMPV6_ECHO_REQUEST => {
+            let src = if ipv6::is_multicast(&hdr.dst) {
+                match source_for(&IpAddr::V6(hdr.src)) {
+                    Some(IpAddr::V6(a)) => a,
+                    _ => return,
+                }
+            } else {
+                hdr.dst
+            };
+            let mut reply = msg.to_vec();
+            reply[0] = icmpv6::ICMPV6_ECHO_REPLY;
+            send_icmpv6(&src, &hdr.src, reply, ipv6::HOP_LIMIT);
+        }
+        icmpv6::ND_NEIGHBOR_SOLICIT if ndp => {
+            let ns = match icmpv6::parse_neighbor(msg) {
+                Some(n) => n,
+                None => return,
+            };
+            let mac = {
+                let mut state = NET.lock();
+                let iface = match state.ifaces.get_mut(idx) {
+                    Some(i) if i.owns6(&ns.target) => i,
+                    _ => return,
+                };
+                if let (Some(mac), false) = (ns.mac, hdr.src == UNSPECIFIED) {
+                    iface.ndp.insert(hdr.src, mac);
+                }
+                iface.mac
+            };
+            let solicited = hdr.src != UNSPECIFIED;
+            let dst = if solicited { hdr.src } else { ipv6::ALL_NODES };
+            let reply = icmpv6::build_neighbor_advert(&ns.target, &mac, solicited);
+            send_icmpv6_on(Some(idx), &ns.target, &dst, reply, ipv6::NDP_HOPS);
+        }
+        icmpv6::ND_NEIGHBOR_ADVERT if ndp => {
+            if let Some(na) = icmpv6::parse_neighbor(msg) {
+                let mut state = NET.lock();
+                if let Some(iface) = state.ifaces.get_mut(idx) {
+                    if na.target == iface.ip6 && frame.src != iface.mac {
+                        crate::serial_println!("[net] {}: duplicate address {}", iface.name, IpAddr::V6(na.target));
+                        iface.ip6 = UNSPECIFIED;
+                    }
+                    iface.ndp.insert(na.target, na.mac.unwrap_or(frame.src));
+                }
+            }
+        }
+        icmpv6::ND_ROUTER_ADVERT if ndp && ipv6::is_link_local(&hdr.src) => {
+            if let Some(ra) = icmpv6::parse_router_advert(msg) {
+                router_advert(idx, &hdr.src, &ra, frame.src);
+            }
+        }
+        icmpv6::ICMPV6_DEST_UNREACH | icmpv6::ICMPV6_PACKET_TOO_BIG | icmpv6::ICMPV6_TIME_EXCEEDED => {
+            if let Some(err) = icmpv6::parse_error(msg) {
+                socket::icmpv6_input(&err);
+            }
+            tap(buf);
+        }
+        _ => tap(buf),
+    }
+}
+
+fn router_advert(idx: usize, router: &[u8; 16], ra: &icmpv6::RouterAdvert, mac: [u8; 6]) {
+    let mut state = NET.lock();
+    let iface = match state.ifaces.get_mut(idx) {
+        Some(i) if !i.is_loopback() => i,
+        _ => return,
+    };
+    iface.ndp.insert(*router, ra.mac.unwrap_or(mac));
+    if ra.lifetime != 0 {
+        iface.gw6 = *router;
+    } else if iface.gw6 == *router {
+        iface.gw6 = UNSPECIFIED;
+    }
+    let ll = iface.ip6_ll;
+    for p in ra.prefixes.iter().filter(|p| p.autonomous && p.len == 64 && p.valid != 0) {
+        if ipv6::is_link_local(&p.prefix) {
+            continue;
+        }
+        let addr = ipv6::with_prefix(&p.prefix, &ll);
+        if iface.ip6 != addr {
+            iface.ip6     = addr;
+            iface.prefix6 = p.len;
+            crate::serial_println!("[net] {}: slaac {}/{} via {}", iface.name, IpAddr::V6(addr), p.len, IpAddr::V6(*router));
+            drop(state);
+            let probe = icmpv6::build_neighbor_solicit(&addr, None);
+            send_icmpv6_on(Some(idx), &UNSPECIFIED, &ipv6::solicited_node(&addr), probe, ipv6::NDP_HOPS);
+            return;
+        }
+    }
+}
+
+fn router_solicit(now: u64) {
+    if RS_SENT.load(Ordering::Relaxed) >= MAX_RS || now < RS_AT.load(Ordering::Relaxed) {
+        return;
+    }
+    let pending: Vec<(usize, [u8; 16], [u8; 6])> = NET.lock().ifaces.iter().enumerate()
+        .filter(|(_, i)| !i.is_loopback() && i.up && i.ip6_ll != UNSPECIFIED && i.gw6 == UNSPECIFIED)
+        .map(|(idx, i)| (idx, i.ip6_ll, i.mac))
+        .collect();
+    if pending.is_empty() {
+        return;
+    }
+    RS_SENT.fetch_add(1, Ordering::Relaxed);
+    RS_AT.store(now + RS_TICKS, Ordering::Relaxed);
+    for (idx, ll, mac) in pending {
+        send_icmpv6_on(Some(idx), &ll, &ipv6::ALL_ROUTERS, icmpv6::build_router_solicit(&mac), ipv6::NDP_HOPS);
+    }
+}
+
+fn send_icmpv6(src: &[u8; 16], dst: &[u8; 16], msg: Vec<u8>, hops: u8) -> bool {
+    send_icmpv6_on(None, src, dst, msg, hops)
+}
+
+fn send_icmpv6_on(idx: Option<usize>, src: &[u8; 16], dst: &[u8; 16], mut msg: Vec<u8>, hops: u8) -> bool {
+    icmpv6::seal(src, dst, &mut msg);
+    send_ipv6_on(idx, src, dst, ipv6::NEXT_ICMPV6, hops, &msg)
+}
+
+pub fn neighbour_known(dst: &IpAddr) -> bool {
+    let state = NET.lock();
+    match dst {
+        IpAddr::V4(d) => state.route4(d).and_then(|(i, hop)| state.neighbour(i, &hop)).is_some(),
+        IpAddr::V6(d) => state.route6(None, d).and_then(|(i, hop)| state.neighbour6(i, &hop)).is_some(),
+    }
+}
+
+pub fn solicit(dst: &IpAddr) {
+    let mut state = NET.lock();
+    match dst {
+        IpAddr::V4(d) => {
+            let (idx, hop) = match state.route4(d) {
+                Some(r) if r.0 != LOOPBACK => r,
+                _ => return,
+            };
+            let iface = &mut state.ifaces[idx];
+            let mut req = [0u8; 64];
+            let n = arp::send_request(&iface.mac, &iface.ip, &hop, &mut req);
+            iface.transmit(&req[..n]);
+        }
+        IpAddr::V6(d) => {
+            let (idx, hop) = match state.route6(None, d) {
+                Some(r) if r.0 != LOOPBACK => r,
+                _ => return,
+            };
+            let (mac, src) = (state.ifaces[idx].mac, state.ifaces[idx].ip6_ll);
+            drop(state);
+            let req = icmpv6::build_neighbor_solicit(&hop, Some(&mac));
+            send_icmpv6_on(Some(idx), &src, &ipv6::solicited_node(&hop), req, ipv6::NDP_HOPS);
+        }
+    }
+}
+
+pub fn source_for(dst: &IpAddr) -> Option<IpAddr> {
+    let state = NET.lock();
+    match dst {
+        IpAddr::V4(d) => {
+            let (idx, _) = state.route4(d)?;
+            Some(IpAddr::V4(state.source4(idx, d))).filter(|a| !a.is_unspecified())
+        }
+        IpAddr::V6(d) => {
+            let (idx, _) = state.route6(None, d)?;
+            let iface = &state.ifaces[idx];
+            let src = if idx == LOOPBACK {
+                *d
+            } else if ipv6::is_link_local(d) || ipv6::is_multicast(d) {
+                iface.ip6_ll
+            } else {
+                iface.ip6
+            };
+            Some(IpAddr::V6(src)).filter(|a| !a.is_unspecified())
+        }
+    }
+}
+
+pub fn is_local(ip: &IpAddr) -> bool {
+    let state = NET.lock();
+    match ip {
+        IpAddr::V4(a) => state.ifaces.iter().any(|i| i.owns(a)),
+        IpAddr::V6(a) => state.ifaces.iter().any(|i| i.owns6(a)),
+    }
+}
+
+pub fn send_ip(src: &IpAddr, dst: &IpAddr, proto: u8, payload: &[u8]) -> bool {
+    match (src, dst) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => send_ipv4(Some(*s), d, proto, payload),
+        (_, IpAddr::V4(d)) => send_ipv4(None, d, proto, payload),
+        (IpAddr::V6(s), IpAddr::V6(d)) => send_ipv6(s, d, proto, ipv6::HOP_LIMIT, payload),
+        _ => false,
+    }
+}
+
+pub fn send_ipv6(src: &[u8; 16], dst: &[u8; 16], next: u8, hops: u8, payload: &[u8]) -> bool {
+    send_ipv6_on(None, src, dst, next, hops, payload)
+}
+
+fn send_ipv6_on(idx: Option<usize>, src: &[u8; 16], dst: &[u8; 16], next: u8, hops: u8, payload: &[u8]) -> bool {
     if !is_ready() {
         return false;
     }
     let mut state = NET.lock();
-    let mac = state.mac;
-    let ip = state.ip;
+    let route = match idx {
+        Some(i) => Some((i, *dst)),
+        None => state.route6(Some(src), dst),
+    };
+    let (idx, hop) = match route {
+        Some(r) => r,
+        None => return false,
+    };
+    let dst_mac = match state.neighbour6(idx, &hop) {
+        Some(m) => m,
+        None => {
+            drop(state);
+            solicit(&IpAddr::V6(*dst));
+            return false;
+        }
+    };
+    let iface = &mut state.ifaces[idx];
+    if ipv6::HEADER_LEN + payload.len() > cached_mtu(&IpAddr::V6(*dst)).unwrap_or(iface.mtu) {
+        return false;
+    }
+    let mut pkt = vec![0u8; ipv6::HEADER_LEN + payload.len()];
+    let n = ipv6::build(src, dst, next, hops, payload, &mut pkt);
+    let mut frame = vec![0u8; 14 + n];
+    let len = EthFrame::build(&dst_mac, &iface.mac, ETHERTYPE_IPV6, &pkt[..n], &mut frame);
+    let ok = n > 0 && len > 0 && iface.transmit(&frame[..len]);
+    drop(state);
+    if idx == LOOPBACK {
+        wake_netd();
+    }
+    ok
+}
 
-    let dst_mac = match state.arp.lookup(dst_ip) {
+fn send_ipv4(src: Option<[u8; 4]>, dst: &[u8; 4], proto: u8, payload: &[u8]) -> bool {
+    if !is_ready() {
+        return false;
+    }
+    let mut state = NET.lock();
+    let (idx, hop) = match state.route4(dst) {
+        Some(r) => r,
+        None => return false,
+    };
+    let dst_mac = match state.neighbour(idx, &hop) {
         Some(m) => m,
         None => {
-            let mut arp_req = [0u8; 64];
-            let n = arp::send_request(&mac, &ip, dst_ip, &mut arp_req);
-            if let Some(drv) = state.driver.as_mut() {
-                drv.send(&arp_req[..n]);
-            }
+            drop(state);
+            solicit(&IpAddr::V4(*dst));
             return false;
         }
     };
+    let src = src.unwrap_or_else(|| state.source4(idx, dst));
+    let iface = &mut state.ifaces[idx];
+    let mtu = cached_mtu(&IpAddr::V4(*dst)).unwrap_or(iface.mtu);
+    let pkts = ipv4::fragment(&src, dst, proto, payload, mtu, proto == ipv4::PROTO_TCP);
+    if pkts.is_empty() {
+        return false;
+    }
+    let ok = pkts.iter().all(|pkt| {
+        let mut frame = vec![0u8; 14 + pkt.len()];
+        let n = EthFrame::build(&dst_mac, &iface.mac, ETHERTYPE_IP, pkt, &mut frame);
+        n > 0 && iface.transmit(&frame[..n])
+    });
+    drop(state);
+    if idx == LOOPBACK {
+        wake_netd();
+    }
+    ok
+}
+
+pub fn send_raw_ipv4(pkt: &[u8]) -> bool {
+    if pkt.len() < 20 {
+        return false;
+    }
+    let dst = [pkt[16], pkt[17], pkt[18], pkt[19]];
+    let mut state = NET.lock();
+    let (idx, hop) = match state.route4(&dst) {
+        Some(r) => r,
+        None => return false,
+    };
+    let dst_mac = match state.neighbour(idx, &hop) {
+        Some(m) => m,
+        None => return false,
+    };
+    let iface = &mut state.ifaces[idx];
+    let mut frame = vec![0u8; 14 + pkt.len()];
+    let n = EthFrame::build(&dst_mac, &iface.mac, ETHERTYPE_IP, pkt, &mut frame);
+    let ok = n > 0 && iface.transmit(&frame[..n]);
+    drop(state);
+    if idx == LOOPBACK {
+        wake_netd();
+    }
+    ok
+}
 
-    let mut buf = [0u8; 1520];
-    let udp_len = udp::build(src_port, dst_port, data, &ip, dst_ip, &mut buf[34..]);
-    if udp_len == 0 { return false; }
-    let ip_len = ipv4::build(&ip, dst_ip, ipv4::PROTO_UDP,
-        &buf[34..34 + udp_len].to_vec(), &mut buf[14..]);
-    if ip_len == 0 { return false; }
-    let eth_len = EthFrame::build(&dst_mac, &mac, ETHERTYPE_IP,
-        &buf[14..14 + ip_len].to_vec(), &mut buf[..]);
+pub fn send_frame(frame: &[u8]) -> bool {
+    let mut state = NET.lock();
+    let idx = state.primary();
+    idx != LOOPBACK && state.ifaces[idx].transmit(frame)
+}
 
-    if let Some(drv) = state.driver.as_mut() {
-        if drv.send(&buf[..eth_len]) {
-            state.tx_count += 1;
-            return true;
+fn cached_mtu(dst: &IpAddr) -> Option<usize> {
+    let now = crate::vfs::procfs::uptime_ticks();
+    let mut cache = PMTU.lock();
+    match cache.get(dst) {
+        Some(&(mtu, expires)) if now < expires => Some(mtu),
+        Some(_) => {
+            cache.remove(dst);
+            None
         }
+        None => None,
     }
-    false
+}
+
+pub fn path_mtu(dst: &IpAddr) -> usize {
+    cached_mtu(dst).unwrap_or_else(|| {
+        let state = NET.lock();
+        let route = match dst {
+            IpAddr::V4(d) => state.route4(d).map(|r| r.0),
+            IpAddr::V6(d) => state.route6(None, d).map(|r| r.0),
+        };
+        route.map_or(ipv4::MTU, |i| state.ifaces[i].mtu)
+    })
+}
+
+pub fn set_path_mtu(dst: &IpAddr, mtu: usize) {
+    let min = if dst.is_v6() { ipv6::MIN_MTU } else { 68 };
+    if !(min..ipv4::MTU).contains(&mtu) {
+        return;
+    }
+    let expires = crate::vfs::procfs::uptime_ticks() + PMTU_TICKS;
+    PMTU.lock().insert(*dst, (mtu, expires));
+}
+
+pub fn send_udp(dst_ip: &[u8; 4], dst_port: u16, src_port: u16, data: &[u8]) -> bool {
+    let src = match source_for(&IpAddr::V4(*dst_ip)) {
+        Some(IpAddr::V4(s)) => s,
+        _ => return false,
+    };
+    let mut buf = [0u8; 1500];
+    let n = udp::build(src_port, dst_port, data, &src, dst_ip, &mut buf);
+    n > 0 && send_ipv4(Some(src), dst_ip, ipv4::PROTO_UDP, &buf[..n])
 }
 
 pub fn set_ip(ip: [u8; 4], gw: [u8; 4], mask: [u8; 4]) {
     let mut state = NET.lock();
-    state.ip = ip;
-    state.gw = gw;
-    state.mask = mask;
+    let idx = state.primary();
+    if idx == LOOPBACK {
+        return;
+    }
+    state.set_addr(idx, ip, mask);
+    state.routes.retain(|r| !(r.iface == idx && r.prefix == 0));
+    if gw != [0; 4] {
+        state.routes.add(Route { dest: [0; 4], prefix: 0, gw: Some(gw), iface: idx, metric: 0, origin: Origin::Static });
+    }
+}
+
+pub fn set_mtu(mtu: usize) {
+    let mut state = NET.lock();
+    let idx = state.primary();
+    if idx != LOOPBACK {
+        state.ifaces[idx].mtu = mtu.clamp(576, ipv4::MTU);
+    }
 }
 
-pub fn get_mac() -> [u8; 6] { NET.lock().mac }
-pub fn get_ip() -> [u8; 4] { NET.lock().ip }
+pub fn set_dhcp_routes(routes: &[dhcp::Route]) {
+    let mut state = NET.lock();
+    let idx = state.primary();
+    state.routes.retain(|r| r.origin != Origin::Dhcp);
+    if idx == LOOPBACK {
+        return;
+    }
+    for r in routes {
+        let gw = Some(r.gw).filter(|g| *g != [0; 4]);
+        state.routes.add(Route { dest: r.dest, prefix: r.prefix, gw, iface: idx, metric: 0, origin: Origin::Dhcp });
+    }
+}
 
-pub fn get_dns() -> [u8; 4] { NET.lock().dns }
-pub fn set_dns(dns: [u8; 4]) { NET.lock().dns = dns; }
+pub fn get_mac() -> [u8; 6] {
+    let state = NET.lock();
+    state.ifaces.get(state.primary()).map_or([0; 6], |i| i.mac)
+}
 
-pub fn cmd_dhcp() {
-    if !is_ready() {
+pub fn get_ip() -> [u8; 4] {
+    let state = NET.lock();
+    state.ifaces.get(state.primary()).map_or([0; 4], |i| i.ip)
+}
+
+pub const DEFAULT_DNS: IpAddr = IpAddr::V4([8, 8, 8, 8]);
+
+pub fn dns_servers() -> Vec<IpAddr> {
+    let dns = NET.lock().dns.clone();
+    if dns.is_empty() { vec![DEFAULT_DNS] } else { dns }
+}
+
+pub fn set_dns_servers(servers: Vec<IpAddr>) {
+    NET.lock().dns = servers;
+    dns::flush();
+}
+
+fn dns_list() -> String {
+    dns_servers().iter().map(|s| s.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+pub fn cmd_dhcp(arg: &str) {
+    if !has_nic() {
         crate::print_error!("net: no adapter");
         return;
     }
-    crate::print_info!("dhcp: sending discover...");
-    match dhcp::do_dhcp() {
-        Some(r) => {
-            set_ip(r.ip, r.gw, r.mask);
-            set_dns(r.dns);
-            crate::print_success!(
-                "dhcp: ip={}.{}.{}.{}  gw={}.{}.{}.{}  mask={}.{}.{}.{}  dns={}.{}.{}.{}",
-                r.ip[0], r.ip[1], r.ip[2], r.ip[3],
-                r.gw[0], r.gw[1], r.gw[2], r.gw[3],
-                r.mask[0], r.mask[1], r.mask[2], r.mask[3],
-                r.dns[0], r.dns[1], r.dns[2], r.dns[3],
-            );
-        }
-        None => crate::print_error!("dhcp: no response (timeout)"),
-    }
+    dhcp::cmd_dhcp(arg);
 }
 
 #[inline]
@@ -582,8 +1110,13 @@ pub fn cmd_ping(hostname: &str, target_ip: &[u8; 4], count: usize) {
         return;
     }
 
-    let our_ip = get_ip();
-    let our_mac = get_mac();
+    let our_ip = match source_for(&IpAddr::V4(*target_ip)) {
+        Some(IpAddr::V4(a)) => a,
+        _ => {
+            crate::print_error!("ping: network unreachable");
+            return;
+        }
+    };
     let ping_id: u16 = 0x4D4B;
     let payload = b"MikuOS ping data 56b padding xxxxxxxxxxxxxxxxxxxxxxxxxxxxx";
 
@@ -602,13 +1135,11 @@ pub fn cmd_ping(hostname: &str, target_ip: &[u8; 4], count: usize) {
     let mut rtt_max = 0u64;
     let mut rtt_sum = 0u64;
 
-    let dst_mac = match resolve_arp(target_ip, &our_ip, &our_mac) {
-        Some(m) => m,
-        None => {
-            crate::print_error!("ping: arp resolution failed");
-            return;
-        }
-    };
+    if !resolve_arp(target_ip) {
+        crate::print_error!("ping: arp resolution failed");
+        return;
+    }
+    let tap = RawTap::open();
 
     'ping: for seq in 1..=count {
         if CTRL_C.load(Ordering::SeqCst) {
@@ -620,18 +1151,9 @@ pub fn cmd_ping(hostname: &str, target_ip: &[u8; 4], count: usize) {
         let icmp_len = icmp::build_echo_request(ping_id, seq as u16, &payload[..56], &mut icmp_buf);
         let mut ip_buf = [0u8; 100];
         let ip_len = ipv4::build(&our_ip, target_ip, ipv4::PROTO_ICMP, &icmp_buf[..icmp_len], &mut ip_buf);
-        let mut eth_buf = [0u8; 128];
-        let eth_len = EthFrame::build(&dst_mac, &our_mac, ETHERTYPE_IP, &ip_buf[..ip_len], &mut eth_buf);
 
         let t_start = rdtsc();
-
-        {
-            let mut state = NET.lock();
-            if let Some(drv) = state.driver.as_mut() {
-                drv.send(&eth_buf[..eth_len]);
-            }
-            state.tx_count += 1;
-        }
+        send_raw_ipv4(&ip_buf[..ip_len]);
         sent += 1;
 
         let mut got_reply = false;
@@ -643,66 +1165,34 @@ pub fn cmd_ping(hostname: &str, target_ip: &[u8; 4], count: usize) {
                 break 'ping;
             }
 
-            let mut raw: [[u8; 1520]; 4] = [[0; 1520]; 4];
-            let mut raw_lens = [0usize; 4];
-            let mut raw_n = 0usize;
-
-            {
-                let mut state = NET.lock();
-                if let Some(drv) = state.driver.as_mut() {
-                    drv.recv(&mut |buf| {
-                        if raw_n < 4 {
-                            let l = buf.len().min(1520);
-                            raw[raw_n][..l].copy_from_slice(&buf[..l]);
-                            raw_lens[raw_n] = l;
-                            raw_n += 1;
-                        }
-                    });
-                }
-                state.rx_count += raw_n as u64;
-            }
-
-            for i in 0..raw_n {
-                let buf = &raw[i][..raw_lens[i]];
-                if let Some(frame) = EthFrame::parse(buf) {
-                    if frame.ethertype == ETHERTYPE_IP {
-                        if let Some(r) = icmp::parse_echo_reply(frame.payload) {
-                            if r.id == ping_id && r.seq == seq as u16 {
-                                let t_end = rdtsc();
-                                let khz = crate::timing::tsc_khz().max(1);
-                                let rtt_us = (t_end.wrapping_sub(t_start)) * 1000 / khz;
-                                let ri = rtt_us / 1000;
-                                let rf = (rtt_us % 1000) / 100;
-                                rtt_sum += rtt_us;
-                                if rtt_us < rtt_min { rtt_min = rtt_us; }
-                                if rtt_us > rtt_max { rtt_max = rtt_us; }
-                                received += 1;
-                                crate::cprintln!(100, 220, 150,
-                                    "64 bytes from {}.{}.{}.{}: icmp_seq={} ttl={} time={}.{} ms",
-                                    target_ip[0], target_ip[1], target_ip[2], target_ip[3],
-                                    seq, r.ttl, ri, rf
-                                );
-                                got_reply = true;
-                            }
-                        }
-                    }
-                    if frame.ethertype == ETHERTYPE_ARP {
-                        let mut state = NET.lock();
-                        let mc = state.mac;
-                        let ic = state.ip;
-                        let mut rep = [0u8; 64];
-                        let rlen = arp::handle(&frame, &mc, &ic, &mut state.arp, &mut rep);
-                        if rlen > 0 {
-                            let rc = rep;
-                            if let Some(drv) = state.driver.as_mut() { drv.send(&rc[..rlen]); }
-                        }
+            tap.recv(&mut |buf| {
+                if got_reply { return; }
+                let frame = match EthFrame::parse(buf) { Some(f) => f, None => return };
+                if frame.ethertype != ETHERTYPE_IP { return; }
+                if let Some(r) = icmp::parse_echo_reply(frame.payload) {
+                    if r.id == ping_id && r.seq == seq as u16 {
+                        let t_end = rdtsc();
+                        let khz = crate::timing::tsc_khz().max(1);
+                        let rtt_us = (t_end.wrapping_sub(t_start)) * 1000 / khz;
+                        let ri = rtt_us / 1000;
+                        let rf = (rtt_us % 1000) / 100;
+                        rtt_sum += rtt_us;
+                        if rtt_us < rtt_min { rtt_min = rtt_us; }
+                        if rtt_us > rtt_max { rtt_max = rtt_us; }
+                        received += 1;
+                        crate::cprintln!(100, 220, 150,
+                            "64 bytes from {}.{}.{}.{}: icmp_seq={} ttl={} time={}.{} ms",
+                            target_ip[0], target_ip[1], target_ip[2], target_ip[3],
+                            seq, r.ttl, ri, rf
+                        );
+                        got_reply = true;
                     }
                 }
-            }
+            });
 
             if got_reply { break; }
             if crate::vfs::procfs::uptime_ticks().wrapping_sub(t_start_wait) >= 2000 { break; }
-            core::hint::spin_loop();
+            crate::scheduler::yield_now();
         }
 
         if !got_reply && !CTRL_C.load(Ordering::SeqCst) {
@@ -735,89 +1225,132 @@ pub fn cmd_ping(hostname: &str, target_ip: &[u8; 4], count: usize) {
     }
 }
 
-fn is_same_subnet(ip: &[u8; 4], our_ip: &[u8; 4], mask: &[u8; 4]) -> bool {
-    ip[0] & mask[0] == our_ip[0] & mask[0]
-        && ip[1] & mask[1] == our_ip[1] & mask[1]
-        && ip[2] & mask[2] == our_ip[2] & mask[2]
-        && ip[3] & mask[3] == our_ip[3] & mask[3]
-}
+pub fn cmd_ping6(hostname: &str, target: &[u8; 16], count: usize) {
+    if !is_ready() {
+        crate::print_error!("ping6: no adapter");
+        return;
+    }
+    let src = match source_for(&IpAddr::V6(*target)) {
+        Some(IpAddr::V6(a)) => a,
+        _ => {
+            crate::print_error!("ping6: no ipv6 address (waiting for router advertisement?)");
+            return;
+        }
+    };
+    let ping_id: u16 = 0x4D36;
+    let payload = b"MikuOS ping6 data 56b padding xxxxxxxxxxxxxxxxxxxxxxxxxxxx";
+    let dst = IpAddr::V6(*target);
 
-fn net_recv_once() {
-    let mut raw: [[u8; 1520]; 4] = [[0; 1520]; 4];
-    let mut raw_lens = [0usize; 4];
-    let mut raw_n = 0usize;
-    {
-        let mut state = NET.lock();
-        if let Some(drv) = state.driver.as_mut() {
-            drv.recv(&mut |buf| {
-                if raw_n < 4 {
-                    let l = buf.len().min(1520);
-                    raw[raw_n][..l].copy_from_slice(&buf[..l]);
-                    raw_lens[raw_n] = l;
-                    raw_n += 1;
-                }
-            });
+    crate::cprintln!(57, 197, 187, "PING {} ({}) from {}: 56 data bytes", hostname, dst, IpAddr::V6(src));
+
+    CTRL_C.store(false, Ordering::SeqCst);
+    x86_64::instructions::interrupts::enable();
+
+    let start = crate::vfs::procfs::uptime_ticks();
+    while !neighbour_known(&dst) {
+        if CTRL_C.load(Ordering::SeqCst) || crate::vfs::procfs::uptime_ticks().wrapping_sub(start) >= 3 * crate::interrupts::PIT_HZ as u64 {
+            crate::print_error!("ping6: neighbor discovery failed");
+            return;
         }
-        state.rx_count += raw_n as u64;
+        solicit(&dst);
+        crate::scheduler::sleep(crate::interrupts::PIT_HZ as u64 / 2);
     }
-    for i in 0..raw_n {
-        let buf = &raw[i][..raw_lens[i]];
-        if let Some(frame) = EthFrame::parse(buf) {
-            if frame.ethertype == ETHERTYPE_ARP {
-                let mut state = NET.lock();
-                let mc = state.mac;
-                let ic = state.ip;
-                let mut rep = [0u8; 64];
-                let rlen = arp::handle(&frame, &mc, &ic, &mut state.arp, &mut rep);
-                if rlen > 0 {
-                    let rep_copy = rep;
-                    if let Some(drv) = state.driver.as_mut() {
-                        drv.send(&rep_copy[..rlen]);
+    let tap = RawTap::open();
+
+    let (mut sent, mut received) = (0usize, 0usize);
+    let (mut rtt_min, mut rtt_max, mut rtt_sum) = (u64::MAX, 0u64, 0u64);
+
+    'ping: for seq in 1..=count {
+        if CTRL_C.load(Ordering::SeqCst) {
+            crate::println!("^C");
+            break;
+        }
+        let echo = icmpv6::build_echo(icmpv6::ICMPV6_ECHO_REQUEST, ping_id, seq as u16, &payload[..56]);
+        let t_start = rdtsc();
+        send_icmpv6(&src, target, echo, ipv6::HOP_LIMIT);
+        sent += 1;
+
+        let mut got_reply = false;
+        let t_start_wait = crate::vfs::procfs::uptime_ticks();
+        loop {
+            if CTRL_C.load(Ordering::SeqCst) {
+                crate::println!("^C");
+                break 'ping;
+            }
+            tap.recv(&mut |buf| {
+                if got_reply { return; }
+                let frame = match EthFrame::parse(buf) { Some(f) => f, None => return };
+                if frame.ethertype != ETHERTYPE_IPV6 { return; }
+                if let Some(r) = icmpv6::parse_echo_reply(frame.payload) {
+                    if r.id == ping_id && r.seq == seq as u16 {
+                        let khz = crate::timing::tsc_khz().max(1);
+                        let rtt_us = rdtsc().wrapping_sub(t_start) * 1000 / khz;
+                        rtt_sum += rtt_us;
+                        rtt_min = rtt_min.min(rtt_us);
+                        rtt_max = rtt_max.max(rtt_us);
+                        received += 1;
+                        crate::cprintln!(100, 220, 150,
+                            "{} bytes from {}: icmp_seq={} hlim={} time={}.{} ms",
+                            r.data_len + 8, dst, seq, r.ttl, rtt_us / 1000, (rtt_us % 1000) / 100
+                        );
+                        got_reply = true;
                     }
                 }
-            }
+            });
+            if got_reply { break; }
+            if crate::vfs::procfs::uptime_ticks().wrapping_sub(t_start_wait) >= 2000 { break; }
+            crate::scheduler::yield_now();
         }
-    }
-}
 
-pub fn resolve_arp(target_ip: &[u8; 4], our_ip: &[u8; 4], our_mac: &[u8; 6]) -> Option<[u8; 6]> {
-    let (mask, gw) = {
-        let s = NET.lock();
-        (s.mask, s.gw)
-    };
+        if !got_reply && !CTRL_C.load(Ordering::SeqCst) {
+            crate::print_error!("request timeout for icmp_seq={}", seq);
+        }
+        if seq < count {
+            wait_rdtsc_ms(1000);
+        }
+    }
 
-    let arp_target = if is_same_subnet(target_ip, our_ip, &mask) {
-        *target_ip
-    } else {
-        gw
-    };
+    crate::cprintln!(57, 197, 187, "");
+    crate::cprintln!(57, 197, 187, "--- {} ping6 statistics ---", dst);
+    let loss = ((sent - received) * 100).checked_div(sent).unwrap_or(100);
+    crate::cprintln!(230, 240, 240,
+        "{} packets transmitted, {} received, {}% packet loss",
+        sent, received, loss
+    );
+    if received > 0 {
+        let avg = rtt_sum / received as u64;
+        crate::cprintln!(230, 240, 240,
+            "rtt min/avg/max = {}.{}/{}.{}/{}.{} ms",
+            rtt_min / 1000, (rtt_min % 1000) / 100,
+            avg / 1000, (avg % 1000) / 100,
+            rtt_max / 1000, (rtt_max % 1000) / 100,
+        );
+    }
+}
 
-    if let Some(m) = NET.lock().arp.lookup(&arp_target) {
-        return Some(m);
+pub fn resolve_arp(target_ip: &[u8; 4]) -> bool {
+    let dst = IpAddr::V4(*target_ip);
+    if neighbour_known(&dst) {
+        return true;
     }
 
     for _attempt in 0..5 {
-        if CTRL_C.load(Ordering::SeqCst) { return None; }
+        if CTRL_C.load(Ordering::SeqCst) { return false; }
 
-        {
-            let mut req = [0u8; 64];
-            let n = arp::send_request(our_mac, our_ip, &arp_target, &mut req);
-            let mut s = NET.lock();
-            if let Some(d) = s.driver.as_mut() { d.send(&req[..n]); }
-        }
+        solicit(&dst);
 
         let start = crate::vfs::procfs::uptime_ticks();
         loop {
-            if CTRL_C.load(Ordering::SeqCst) { return None; }
-            net_recv_once();
-            if let Some(m) = NET.lock().arp.lookup(&arp_target) {
-                return Some(m);
+            if CTRL_C.load(Ordering::SeqCst) { return false; }
+            poll();
+            if neighbour_known(&dst) {
+                return true;
             }
             if crate::vfs::procfs::uptime_ticks().wrapping_sub(start) >= 500 { break; }
             core::hint::spin_loop();
         }
     }
-    None
+    false
 }
 
 pub fn cmd_net(args: &str) {
@@ -832,17 +1365,17 @@ pub fn cmd_net(args: &str) {
             parts.next().unwrap_or(""),
             parts.next().unwrap_or(""),
         ),
-        "dns" => match parse_ip(parts.next().unwrap_or("")) {
-            Some(ip) => {
-                set_dns(ip);
-                crate::print_success!("dns set: {}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]);
-            }
-            None => {
-                let d = get_dns();
-                crate::cprintln!(230, 240, 240,
-                    "dns: {}.{}.{}.{}", d[0], d[1], d[2], d[3]);
+        "dns" => {
+            let servers: Option<Vec<IpAddr>> = parts.by_ref().map(IpAddr::parse).collect();
+            match servers {
+                Some(s) if !s.is_empty() => {
+                    set_dns_servers(s);
+                    crate::print_success!("dns set: {}", dns_list());
+                }
+                Some(_) => crate::cprintln!(230, 240, 240, "dns: {}", dns_list()),
+                None => crate::print_error!("net: bad dns address"),
             }
-        },
+        }
         "send" => cmd_send(
             parts.next().unwrap_or(""),
             parts.next().unwrap_or(""),
@@ -850,38 +1383,56 @@ pub fn cmd_net(args: &str) {
         ),
         "pci" => cmd_pci_scan(),
         "arp" => cmd_arp(),
-        _ => crate::println!("net status|poll|ip <ip> <gw> <mask>|dns [<ip>]|send ...|pci|arp"),
+        "ndp" => cmd_ndp(),
+        "sockets" => socket::cmd_sockets(),
+        _ => crate::println!("net status|poll|ip <ip> <gw> <mask>|dns [<ip>...]|send ...|pci|arp|ndp|sockets"),
     }
 }
 
 fn cmd_status() {
-    if !is_ready() { crate::print_error!("net: no adapter"); return; }
+    let dns = dns_list();
     let state = NET.lock();
-    let drv_name = *DRIVER_NAME.lock();
-    let link = state.driver.as_ref().map(|d| d.link_up()).unwrap_or(false);
-    let mac = state.mac;
-    crate::cprintln!(57, 197, 187, "  driver: {}", drv_name);
-    if link {
-        crate::cprintln!(100, 220, 150, "  link:   up");
-    } else {
-        crate::cprintln!(255, 80, 80,  "  link:   down");
+    for iface in &state.ifaces {
+        let link = iface.up && iface.driver.link_up();
+        let mac = iface.mac;
+        crate::cprintln!(57, 197, 187, "{}:", iface.name);
+        crate::cprintln!(57, 197, 187, "  driver: {}", iface.kind);
+        if link {
+            crate::cprintln!(100, 220, 150, "  link:   up");
+        } else {
+            crate::cprintln!(255, 80, 80,  "  link:   down");
+        }
+        if !iface.is_loopback() {
+            crate::cprintln!(230, 240, 240,
+                "  mac:    {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
+        }
+        crate::cprintln!(230, 240, 240,
+            "  ip:     {}/{}", IpAddr::V4(iface.ip), route::prefix_len(&iface.mask));
+        if iface.ip6_ll != UNSPECIFIED {
+            crate::cprintln!(230, 240, 240, "  ip6:    {} (link)", IpAddr::V6(iface.ip6_ll));
+        }
+        if iface.ip6 != UNSPECIFIED {
+            crate::cprintln!(230, 240, 240, "  ip6:    {}/{}", IpAddr::V6(iface.ip6), iface.prefix6);
+        }
+        if iface.gw6 != UNSPECIFIED {
+            crate::cprintln!(230, 240, 240, "  gw6:    {}", IpAddr::V6(iface.gw6));
+        }
+        crate::cprintln!(120, 200, 200, "  tx:     {}", iface.tx_count);
+        crate::cprintln!(120, 200, 200, "  rx:     {}", iface.rx_count);
     }
-    crate::cprintln!(230, 240, 240,
-        "  mac:    {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
-    crate::cprintln!(230, 240, 240,
-        "  ip:     {}.{}.{}.{}", state.ip[0], state.ip[1], state.ip[2], state.ip[3]);
-    crate::cprintln!(230, 240, 240,
-        "  gw:     {}.{}.{}.{}", state.gw[0], state.gw[1], state.gw[2], state.gw[3]);
-    crate::cprintln!(230, 240, 240,
-        "  mask:   {}.{}.{}.{}", state.mask[0], state.mask[1], state.mask[2], state.mask[3]);
-    crate::cprintln!(230, 240, 240,
-        "  dns:    {}.{}.{}.{}", state.dns[0], state.dns[1], state.dns[2], state.dns[3]);
-    crate::cprintln!(120, 200, 200, "  tx:     {}", state.tx_count);
-    crate::cprintln!(120, 200, 200, "  rx:     {}", state.rx_count);
+    if let Some(r) = state.routes.iter().find(|r| r.prefix == 0) {
+        let gw = r.gw.map_or(String::from("on-link"), |g| IpAddr::V4(g).to_string());
+        crate::cprintln!(230, 240, 240, "gw:       {} dev {}", gw, state.ifaces[r.iface].name);
+    }
+    crate::cprintln!(230, 240, 240, "dns:      {}", dns);
 }
 
 fn cmd_setip(ip_str: &str, gw_str: &str, mask_str: &str) {
+    if !has_nic() {
+        crate::print_error!("net: no adapter");
+        return;
+    }
     let ip = parse_ip(ip_str).unwrap_or([10, 0, 2, 15]);
     let gw = parse_ip(gw_str).unwrap_or([10, 0, 2, 2]);
     let mask = parse_ip(mask_str).unwrap_or([255, 255, 255, 0]);
@@ -905,10 +1456,10 @@ fn cmd_send(ip_str: &str, port_str: &str, msg: &str) {
 
 fn cmd_pci_scan() {
     let (devs, n) = pci::scan();
-    if n == 0 { crate::cprintln!(120, 140, 140, "no nics found"); return; }
+    let nics = || devs.iter().take(n).filter(|d| d.class == pci::CLASS_NETWORK);
+    if nics().next().is_none() { crate::cprintln!(120, 140, 140, "no nics found"); return; }
     crate::cprintln!(57, 197, 187, "network cards (PCI class 0x02):");
-    for i in 0..n {
-        let d = &devs[i];
+    for d in nics() {
         crate::cprintln!(230, 240, 240,
             "  [{:02x}:{:02x}.{}] {:04x}:{:04x}  {}  irq={}",
             d.bus, d.dev, d.func, d.vendor, d.device,
@@ -919,10 +1470,10 @@ fn cmd_pci_scan() {
 
 fn cmd_arp() {
     let state = NET.lock();
-    crate::cprintln!(57, 197, 187, "arp table:");
-    let mut found = false;
-    for e in &state.arp.entries {
-        if e.valid {
+    for iface in state.ifaces.iter().filter(|i| !i.is_loopback()) {
+        crate::cprintln!(57, 197, 187, "arp table ({}):", iface.name);
+        let mut found = false;
+        for e in iface.arp.entries.iter().filter(|e| e.valid) {
             found = true;
             crate::cprintln!(230, 240, 240,
                 "  {}.{}.{}.{}  ->  {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
@@ -930,8 +1481,239 @@ fn cmd_arp() {
                 e.mac[0], e.mac[1], e.mac[2], e.mac[3], e.mac[4], e.mac[5]
             );
         }
+        if !found { crate::cprintln!(120, 140, 140, "  (empty)"); }
+    }
+}
+
+fn cmd_ndp() {
+    let state = NET.lock();
+    for iface in state.ifaces.iter().filter(|i| !i.is_loopback()) {
+        crate::cprintln!(57, 197, 187, "neighbor cache ({}):", iface.name);
+        let mut found = false;
+        for e in iface.ndp.entries.iter().filter(|e| e.valid) {
+            found = true;
+            crate::cprintln!(230, 240, 240,
+                "  {}  ->  {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                IpAddr::V6(e.ip), e.mac[0], e.mac[1], e.mac[2], e.mac[3], e.mac[4], e.mac[5]
+            );
+        }
+        if !found { crate::cprintln!(120, 140, 140, "  (empty)"); }
+    }
+}
+
+pub fn cmd_ip(args: &str) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let rest = args.get(1..).unwrap_or(&[]);
+    match args.first().copied().unwrap_or("addr") {
+        "addr" | "a" => ip_addr(rest),
+        "link" | "l" => ip_link(rest),
+        "route" | "r" => ip_route(rest),
+        _ => crate::println!("ip addr|link|route ..."),
+    }
+}
+
+fn arg<'a>(args: &[&'a str], key: &str) -> Option<&'a str> {
+    args.iter().position(|a| *a == key).and_then(|i| args.get(i + 1).copied())
+}
+
+fn iface_index(state: &NetState, args: &[&str]) -> Option<usize> {
+    let name = arg(args, "dev")?;
+    let idx = state.find(name);
+    if idx.is_none() {
+        crate::print_error!("ip: no such device: {}", name);
+    }
+    idx
+}
+
+fn ip_addr(args: &[&str]) {
+    let mut state = NET.lock();
+    match args.first().copied().unwrap_or("show") {
+        "show" => {
+            for (n, iface) in state.ifaces.iter().enumerate() {
+                crate::cprintln!(57, 197, 187, "{}: {}: <{}> mtu {}",
+                    n + 1, iface.name, if iface.up { "UP" } else { "DOWN" }, iface.mtu);
+                if iface.ip != [0; 4] {
+                    crate::cprintln!(230, 240, 240, "    inet {}/{} brd {}",
+                        IpAddr::V4(iface.ip), route::prefix_len(&iface.mask), IpAddr::V4(iface.broadcast()));
+                }
+                if iface.ip6 != UNSPECIFIED {
+                    crate::cprintln!(230, 240, 240, "    inet6 {}/{}", IpAddr::V6(iface.ip6), iface.prefix6);
+                }
+                if iface.ip6_ll != UNSPECIFIED {
+                    crate::cprintln!(230, 240, 240, "    inet6 {}/64 scope link", IpAddr::V6(iface.ip6_ll));
+                }
+            }
+        }
+        op @ ("add" | "del") => {
+            let (ip, prefix) = match args.get(1).and_then(|a| route::parse_cidr(a)) {
+                Some(c) => c,
+                None => {
+                    crate::print_error!("ip: bad address");
+                    return;
+                }
+            };
+            let idx = match iface_index(&state, args) {
+                Some(i) => i,
+                None => {
+                    crate::println!("ip addr {} <addr>/<len> dev <if>", op);
+                    return;
+                }
+            };
+            if op == "add" {
+                state.set_addr(idx, ip, route::prefix_mask(prefix));
+            } else if state.ifaces[idx].ip == ip {
+                state.set_addr(idx, [0; 4], [0; 4]);
+            } else {
+                crate::print_error!("ip: address not assigned");
+                return;
+            }
+            crate::print_success!("{} {}/{} dev {}", op, IpAddr::V4(ip), prefix, state.ifaces[idx].name);
+        }
+        _ => crate::println!("ip addr [show|add|del <addr>/<len> dev <if>]"),
+    }
+}
+
+fn ip_link(args: &[&str]) {
+    let mut state = NET.lock();
+    match args.first().copied().unwrap_or("show") {
+        "show" => {
+            for (n, iface) in state.ifaces.iter().enumerate() {
+                let m = iface.mac;
+                crate::cprintln!(57, 197, 187, "{}: {}: <{}{}> mtu {}  {}",
+                    n + 1, iface.name,
+                    if iface.up { "UP" } else { "DOWN" },
+                    if iface.driver.link_up() { ",LOWER_UP" } else { "" },
+                    iface.mtu, iface.kind);
+                crate::cprintln!(230, 240, 240,
+                    "    link/{} {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}  irq {}  tx {}  rx {}",
+                    if iface.is_loopback() { "loopback" } else { "ether" },
+                    m[0], m[1], m[2], m[3], m[4], m[5], iface.irq, iface.tx_count, iface.rx_count);
+            }
+        }
+        "set" => {
+            let idx = match args.get(1).and_then(|n| state.find(n)) {
+                Some(i) => i,
+                None => {
+                    crate::print_error!("ip: no such device");
+                    return;
+                }
+            };
+            let iface = &mut state.ifaces[idx];
+            let max = if iface.is_loopback() { iface::LOOPBACK_MTU } else { ipv4::MTU };
+            match (args.get(2).copied(), args.get(3).and_then(|m| m.parse::<usize>().ok())) {
+                (Some("up"), _) => iface.up = true,
+                (Some("down"), _) => iface.up = false,
+                (Some("mtu"), Some(mtu)) if (576..=max).contains(&mtu) => iface.mtu = mtu,
+                (Some("mtu"), _) => {
+                    crate::print_error!("ip: mtu must be 576..{}", max);
+                    return;
+                }
+                _ => {
+                    crate::println!("ip link set <if> up|down|mtu <n>");
+                    return;
+                }
+            }
+            crate::print_success!("{}: {} mtu {}", iface.name, if iface.up { "up" } else { "down" }, iface.mtu);
+        }
+        _ => crate::println!("ip link [show|set <if> up|down|mtu <n>]"),
+    }
+}
+
+fn route_line(state: &NetState, r: &Route) -> String {
+    let dest = if r.prefix == 0 {
+        String::from("default")
+    } else {
+        format!("{}/{}", IpAddr::V4(r.dest), r.prefix)
+    };
+    let via = r.gw.map_or(String::new(), |g| format!(" via {}", IpAddr::V4(g)));
+    let iface = &state.ifaces[r.iface];
+    let src = if r.gw.is_none() && r.origin == Origin::Kernel {
+        format!(" scope link src {}", IpAddr::V4(iface.ip))
+    } else {
+        String::new()
+    };
+    format!("{}{} dev {} proto {} metric {}{}", dest, via, iface.name, r.origin.as_str(), r.metric, src)
+}
+
+fn ip_route(args: &[&str]) {
+    let mut state = NET.lock();
+    match args.first().copied().unwrap_or("show") {
+        "show" => {
+            for r in state.routes.iter() {
+                let down = !state.ifaces[r.iface].up;
+                let line = route_line(&state, r);
+                if down {
+                    crate::cprintln!(120, 140, 140, "{} linkdown", line);
+                } else {
+                    crate::cprintln!(230, 240, 240, "{}", line);
+                }
+            }
+        }
+        "add" => {
+            let (dest, prefix) = match args.get(1).and_then(|a| route::parse_cidr(a)) {
+                Some(c) => c,
+                None => {
+                    crate::println!("ip route add <dst>/<len>|default [via <gw>] [dev <if>] [metric <n>]");
+                    return;
+                }
+            };
+            let gw = match arg(args, "via").map(parse_ip) {
+                Some(None) => {
+                    crate::print_error!("ip: bad gateway");
+                    return;
+                }
+                Some(g) => g,
+                None => None,
+            };
+            let metric = arg(args, "metric").and_then(|m| m.parse().ok()).unwrap_or(0);
+            let iface = match (arg(args, "dev"), gw) {
+                (Some(_), _) => iface_index(&state, args),
+                (None, Some(g)) => state.ifaces.iter().position(|i| !i.is_loopback() && i.on_link(&g)),
+                (None, None) => None,
+            };
+            let iface = match iface {
+                Some(i) => i,
+                None => {
+                    crate::print_error!("ip: nexthop is not on a connected network");
+                    return;
+                }
+            };
+            let r = Route { dest, prefix, gw, iface, metric, origin: Origin::Static };
+            if state.routes.add(r) {
+                crate::print_success!("route added: {}", route_line(&state, &r));
+            } else {
+                crate::print_error!("ip: routing table full");
+            }
+        }
+        "del" => {
+            let removed = args.get(1)
+                .and_then(|a| route::parse_cidr(a))
+                .is_some_and(|(dest, prefix)| state.routes.remove(&dest, prefix));
+            if removed {
+                crate::print_success!("route deleted");
+            } else {
+                crate::print_error!("ip: no such route");
+            }
+        }
+        "get" => {
+            let dst = match args.get(1).and_then(|a| parse_ip(a)) {
+                Some(d) => d,
+                None => {
+                    crate::println!("ip route get <addr>");
+                    return;
+                }
+            };
+            match state.route4(&dst) {
+                Some((idx, hop)) => {
+                    let via = if hop == dst { String::new() } else { format!(" via {}", IpAddr::V4(hop)) };
+                    crate::cprintln!(230, 240, 240, "{}{} dev {} src {}",
+                        IpAddr::V4(dst), via, state.ifaces[idx].name, IpAddr::V4(state.source4(idx, &dst)));
+                }
+                None => crate::print_error!("ip: network unreachable"),
+            }
+        }
+        _ => crate::println!("ip route [show|add|del|get]"),
     }
-    if !found { crate::cprintln!(120, 140, 140, "  (empty)"); }
 }
 
 fn parse_ip(s: &str) -> Option<[u8; 4]> {
@@ -1,4 +1,5 @@
 use super::eth::{EthFrame, ETHERTYPE_IP};
+use super::ip::IpAddr;
 use super::ipv4;
 use super::udp;
 use super::CTRL_C;
@@ -8,6 +9,7 @@ const NTP_DELTA: u64 = 70 * 365 * 24 * 3600 + 17 * 24 * 3600;
 
 const NTP_PORT: u16 = 123;
 const NTP_SRC_PORT: u16 = 32123;
+const TIMEOUT_TICKS: u64 = 5 * crate::interrupts::PIT_HZ as u64;
 
 pub struct NtpResult {
     pub unix_secs: u64,
@@ -94,10 +96,11 @@ pub fn sync(server_ip: &[u8; 4]) -> Option<NtpResult> {
     if !super::is_ready() { return None; }
     CTRL_C.store(false, Ordering::SeqCst);
 
-    let our_ip  = super::get_ip();
-    let our_mac = super::get_mac();
-
-    let dst_mac = super::resolve_arp(server_ip, &our_ip, &our_mac)?;
+    let our_ip = match super::source_for(&IpAddr::V4(*server_ip))? {
+        IpAddr::V4(a) => a,
+        IpAddr::V6(_) => return None,
+    };
+    if !super::resolve_arp(server_ip) { return None; }
 
     let mut ntp_pkt = [0u8; 48];
     build_ntp_request(&mut ntp_pkt);
@@ -110,77 +113,39 @@ pub fn sync(server_ip: &[u8; 4]) -> Option<NtpResult> {
     let ip_len = ipv4::build(&our_ip, server_ip, ipv4::PROTO_UDP, &udp_buf[..udp_len], &mut ip_buf);
     if ip_len == 0 { return None; }
 
-    let mut eth_buf = [0u8; 150];
-    let eth_len = EthFrame::build(&dst_mac, &our_mac, ETHERTYPE_IP, &ip_buf[..ip_len], &mut eth_buf);
-    if eth_len == 0 { return None; }
-
-    {
-        let mut state = super::NET.lock();
-        if let Some(drv) = state.driver.as_mut() {
-            drv.send(&eth_buf[..eth_len]);
-            state.tx_count += 1;
-        }
-    }
+    let tap = super::RawTap::open();
+    super::send_raw_ipv4(&ip_buf[..ip_len]);
 
     crate::log!("ntp: request sent to {}.{}.{}.{}", server_ip[0], server_ip[1], server_ip[2], server_ip[3]);
 
-    for _ in 0..3_000_000 {
+    let start = crate::vfs::procfs::uptime_ticks();
+    while crate::vfs::procfs::uptime_ticks().wrapping_sub(start) < TIMEOUT_TICKS {
         if CTRL_C.load(Ordering::SeqCst) { return None; }
 
-        let mut raw: [[u8; 1520]; 4] = [[0; 1520]; 4];
-        let mut raw_lens = [0usize; 4];
-        let mut raw_n = 0usize;
-
-        {
-            let mut state = super::NET.lock();
-            if let Some(drv) = state.driver.as_mut() {
-                drv.recv(&mut |buf| {
-                    if raw_n < 4 {
-                        let l = buf.len().min(1520);
-                        raw[raw_n][..l].copy_from_slice(&buf[..l]);
-                        raw_lens[raw_n] = l;
-                        raw_n += 1;
-                    }
-                });
-            }
-        }
-
-        for i in 0..raw_n {
-            let buf = &raw[i][..raw_lens[i]];
-            let frame = match EthFrame::parse(buf) { Some(f) => f, None => continue };
-
-            if frame.ethertype == super::eth::ETHERTYPE_ARP {
-                let mut state = super::NET.lock();
-                let mc = state.mac; let ic = state.ip;
-                let mut rep = [0u8; 64];
-                let rlen = super::arp::handle(&frame, &mc, &ic, &mut state.arp, &mut rep);
-                if rlen > 0 {
-                    let rc = rep;
-                    if let Some(drv) = state.driver.as_mut() { drv.send(&rc[..rlen]); }
-                }
-                continue;
-            }
-
-            if frame.ethertype != ETHERTYPE_IP { continue; }
-            let ip = match ipv4::Ipv4Header::parse(frame.payload) { Some(h) => h, None => continue };
-            if ip.proto != ipv4::PROTO_UDP { continue; }
-            if ip.src != *server_ip { continue; }
+        let mut result = None;
+        tap.recv(&mut |buf| {
+            if result.is_some() { return; }
+            let frame = match EthFrame::parse(buf) { Some(f) => f, None => return };
+            if frame.ethertype != ETHERTYPE_IP { return; }
+            let ip = match ipv4::Ipv4Header::parse(frame.payload) { Some(h) => h, None => return };
+            if ip.proto != ipv4::PROTO_UDP { return; }
+            if ip.src != *server_ip { return; }
 
             let payload = ip.payload(frame.payload);
-            if payload.len() < 8 { continue; }
+            if payload.len() < 8 { return; }
 
             let src_port = u16::from_be_bytes([payload[0], payload[1]]);
             let dst_port = u16::from_be_bytes([payload[2], payload[3]]);
-            if src_port != NTP_PORT || dst_port != NTP_SRC_PORT { continue; }
+            if src_port != NTP_PORT || dst_port != NTP_SRC_PORT { return; }
 
-            let ntp_data = &payload[8..];
-            if let Some(result) = parse_ntp_response(ntp_data) {
-                crate::log!("ntp: got time unix={} stratum={}", result.unix_secs, result.stratum);
-                return Some(result);
-            }
+            result = parse_ntp_response(&payload[8..]);
+        });
+        if let Some(result) = result {
+            crate::log!("ntp: got time unix={} stratum={}", result.unix_secs, result.stratum);
+            return Some(result);
         }
 
-        core::hint::spin_loop();
+        crate::scheduler::yield_now();
     }
 
     crate::log_err!("ntp: timeout");
@@ -189,7 +154,9 @@ pub fn sync(server_ip: &[u8; 4]) -> Option<NtpResult> {
 
 pub fn cmd_ntp(arg: &str) {
     let server_ip = if arg.is_empty() {
-        [216, 239, 35, 0] 
+        super::dhcp::lease()
+            .and_then(|l| l.info.ntp.first().copied())
+            .unwrap_or([216, 239, 35, 0])
     } else {
         match parse_ip(arg) {
             Some(ip) => ip,
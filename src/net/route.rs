@@ -0,0 +1,113 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
+pub const MAX_ROUTES: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Origin {
+    Kernel,
+    Static,
+    Dhcp,
+}
+
+impl Origin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Origin::Kernel => "kernel",
+            Origin::Static => "static",
+            Origin::Dhcp   => "dhcp",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Route {
+    pub dest:   [u8; 4],
+    pub prefix: u8,
+    pub gw:     Option<[u8; 4]>,
+    pub iface:  usize,
+    pub metric: u32,
+    pub origin: Origin,
+}
+
+impl Route {
+    pub fn matches(&self, dst: &[u8; 4]) -> bool {
+        let mask = prefix_mask(self.prefix);
+        (0..4).all(|i| dst[i] & mask[i] == self.dest[i] & mask[i])
+    }
+}
+
+pub struct RouteTable {
+    routes: Vec<Route>,
+}
+
+impl RouteTable {
+    pub const fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Route> {
+        self.routes.iter()
+    }
+
+    pub fn lookup(&self, dst: &[u8; 4], usable: impl Fn(usize) -> bool) -> Option<Route> {
+        self.routes.iter()
+            .filter(|r| r.matches(dst) && usable(r.iface))
+            .max_by(|a, b| a.prefix.cmp(&b.prefix).then(b.metric.cmp(&a.metric)))
+            .copied()
+    }
+
+    pub fn add(&mut self, mut r: Route) -> bool {
+        r.dest = network(&r.dest, r.prefix);
+        if let Some(e) = self.routes.iter_mut()
+            .find(|e| e.dest == r.dest && e.prefix == r.prefix && e.iface == r.iface && e.metric == r.metric)
+        {
+            *e = r;
+            return true;
+        }
+        if self.routes.len() >= MAX_ROUTES {
+            return false;
+        }
+        self.routes.push(r);
+        true
+    }
+
+    pub fn remove(&mut self, dest: &[u8; 4], prefix: u8) -> bool {
+        let dest = network(dest, prefix);
+        let before = self.routes.len();
+        self.routes.retain(|r| !(r.dest == dest && r.prefix == prefix));
+        self.routes.len() != before
+    }
+
+    pub fn retain(&mut self, keep: impl Fn(&Route) -> bool) {
+        self.routes.retain(|r| keep(r));
+    }
+}
+
+pub fn prefix_mask(prefix: u8) -> [u8; 4] {
+    let bits = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix.min(32) as u32) };
+    bits.to_be_bytes()
+}
+
+pub fn prefix_len(mask: &[u8; 4]) -> u8 {
+    u32::from_be_bytes(*mask).leading_ones() as u8
+}
+
+pub fn network(ip: &[u8; 4], prefix: u8) -> [u8; 4] {
+    let mask = prefix_mask(prefix);
+    core::array::from_fn(|i| ip[i] & mask[i])
+}
+
+pub fn parse_cidr(s: &str) -> Option<([u8; 4], u8)> {
+    if s == "default" {
+        return Some(([0; 4], 0));
+    }
+    let (addr, prefix) = match s.split_once('/') {
+        Some((a, p)) => (a, p.parse().ok().filter(|&p: &u8| p <= 32)?),
+        None         => (s, 32),
+    };
+    match super::ip::IpAddr::parse(addr)? {
+        super::ip::IpAddr::V4(a) => Some((a, prefix)),
+        _ => None,
+    }
+}
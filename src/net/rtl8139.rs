@@ -1,4 +1,4 @@
-use super::pci::PciDevice;
+use crate::pci::PciDevice;
 use super::NetworkDriver;
 use alloc::boxed::Box;
 use x86_64::instructions::port::Port;
@@ -135,4 +135,7 @@ impl NetworkDriver for Rtl8139 {
     fn get_mac(&self) -> [u8; 6] {
         self.mac
     }
+    fn ack_irq(&mut self) {
+        self.write16(0x3E, self.read16(0x3E));
+    }
 }
@@ -1,4 +1,4 @@
-use super::pci::PciDevice;
+use crate::pci::PciDevice;
 use super::NetworkDriver;
 use alloc::boxed::Box;
 
@@ -208,4 +208,13 @@ impl NetworkDriver for Rtl8168 {
     fn get_mac(&self) -> [u8; 6] {
         self.mac
     }
+
+    fn enable_irq(&mut self) {
+        self.write16(0x3E, 0xFFFF);
+        self.write16(0x3C, 0x0001 | 0x0002 | 0x0010 | 0x0020);
+    }
+
+    fn ack_irq(&mut self) {
+        self.write16(0x3E, 0xFFFF);
+    }
 }
@@ -0,0 +1,1487 @@
+extern crate alloc;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use core::fmt;
+use super::icmp::{self, IcmpError};
+use super::icmpv6::{self, Icmpv6Error};
+use super::ip::IpAddr;
+use super::ipv4;
+use super::tcp::{self, TcpSegment, TcpState, FLAG_ACK, FLAG_FIN, FLAG_PSH, FLAG_RST, FLAG_SYN};
+use super::udp::{self, UdpHeader, MAX_UDP_PAYLOAD};
+use crate::interrupts::PIT_HZ;
+use crate::syscall::{
+    EADDRINUSE, EADDRNOTAVAIL, EAFNOSUPPORT, EAGAIN, EALREADY, ECONNREFUSED, ECONNRESET,
+    EDESTADDRREQ, EHOSTUNREACH, EINPROGRESS, EINTR, EINVAL, EISCONN, EMSGSIZE, ENETDOWN,
+    ENETUNREACH, ENOTCONN, EOPNOTSUPP, EPIPE, ETIMEDOUT,
+};
+
+pub const AF_INET:      u64 = 2;
+pub const AF_INET6:     u64 = 10;
+pub const SOCK_STREAM:  u64 = 1;
+pub const SOCK_DGRAM:   u64 = 2;
+pub const SHUT_RD:      u64 = 0;
+pub const SHUT_WR:      u64 = 1;
+pub const SHUT_RDWR:    u64 = 2;
+pub const SOCKADDR_LEN: usize = 16;
+pub const SOCKADDR6_LEN: usize = 28;
+
+const EPHEMERAL_FIRST: u16   = 49152;
+const EPHEMERAL_COUNT: u16   = 16384;
+const RX_CAPACITY:     usize = 256 * 1024;
+const TX_CAPACITY:     usize = 256 * 1024;
+const MSS:             usize = 1460;
+const DEFAULT_MSS:     usize = 536;
+const WSCALE:          u8    = 3;
+const DUPACK_THRESH:   u32   = 3;
+const DGRAM_QUEUE:     usize = 64;
+const OOO_MAX_SEGS:    usize = 256;
+const MAX_BACKLOG:     usize = 128;
+const MAX_RETRIES:     u32   = 8;
+const RTO_TICKS:       u64   = PIT_HZ as u64;
+const RTO_MIN:         u64   = PIT_HZ as u64;
+const RTO_MAX:         u64   = 60 * PIT_HZ as u64;
+const ARP_WAIT_TICKS:  u64   = 3 * PIT_HZ as u64;
+const LINGER_TICKS:    u64   = 60 * PIT_HZ as u64;
+const MSL_TICKS:       u64   = 30 * PIT_HZ as u64;
+const SOCKET_CAUSE:    &str  = "socket";
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Proto {
+    Tcp,
+    Udp,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Endpoint {
+    pub ip:   IpAddr,
+    pub port: u16,
+}
+
+impl Endpoint {
+    pub const ANY: Self = Self { ip: IpAddr::UNSPECIFIED_V4, port: 0 };
+
+    pub fn new(ip: impl Into<IpAddr>, port: u16) -> Self {
+        Self { ip: ip.into(), port }
+    }
+
+    pub fn from_sockaddr(buf: &[u8], family: u64) -> Result<Self, i64> {
+        if buf.len() < 8 {
+            return Err(EINVAL);
+        }
+        let port = u16::from_be_bytes([buf[2], buf[3]]);
+        match (u16::from_le_bytes([buf[0], buf[1]]) as u64, family) {
+            (AF_INET, AF_INET) => Ok(Self::new([buf[4], buf[5], buf[6], buf[7]], port)),
+            (AF_INET6, AF_INET6) if buf.len() >= 24 => {
+                let mut a = [0u8; 16];
+                a.copy_from_slice(&buf[8..24]);
+                Ok(Self::new(IpAddr::from_v6(a), port))
+            }
+            (AF_INET6, AF_INET6) => Err(EINVAL),
+            _ => Err(EAFNOSUPPORT),
+        }
+    }
+
+    pub fn to_sockaddr(self, family: u64) -> Vec<u8> {
+        let mut out = vec![0u8; if family == AF_INET6 { SOCKADDR6_LEN } else { SOCKADDR_LEN }];
+        out[0..2].copy_from_slice(&(family as u16).to_le_bytes());
+        out[2..4].copy_from_slice(&self.port.to_be_bytes());
+        match IpAddr::from_v6(self.ip.to_v6()) {
+            ip if family == AF_INET6 => out[8..24].copy_from_slice(&ip.to_v6()),
+            IpAddr::V4(a)            => out[4..8].copy_from_slice(&a),
+            IpAddr::V6(_)            => {}
+        }
+        out
+    }
+
+    fn is_any_ip(self) -> bool {
+        self.ip.is_unspecified()
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ip {
+            IpAddr::V4(_) => write!(f, "{}:{}", self.ip, self.port),
+            IpAddr::V6(_) => write!(f, "[{}]:{}", self.ip, self.port),
+        }
+    }
+}
+
+type SockKey = (Proto, Endpoint, Endpoint);
+
+static TABLE: Mutex<BTreeMap<SockKey, Arc<Socket>>> = Mutex::new(BTreeMap::new());
+static NEXT_PORT: AtomicU16 = AtomicU16::new(0);
+
+fn with_table<R>(f: impl FnOnce(&mut BTreeMap<SockKey, Arc<Socket>>) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut TABLE.lock()))
+}
+
+fn ticks() -> u64 {
+    crate::vfs::procfs::uptime_ticks()
+}
+
+fn lookup(proto: Proto, local: Endpoint, remote: Endpoint) -> Option<Arc<Socket>> {
+    let wild4 = Endpoint::new(IpAddr::UNSPECIFIED_V4, local.port);
+    let wild6 = Endpoint::new(IpAddr::UNSPECIFIED_V6, local.port);
+    let wilds: &[Endpoint] = if local.ip.is_v6() { &[wild6] } else { &[wild4, wild6] };
+    with_table(|t| {
+        [local].iter().chain(wilds)
+            .flat_map(|&l| [(l, remote), (l, Endpoint::ANY)])
+            .find_map(|(l, r)| t.get(&(proto, l, r)).cloned())
+    })
+}
+
+fn port_taken(t: &BTreeMap<SockKey, Arc<Socket>>, proto: Proto, local: Endpoint) -> bool {
+    t.keys().any(|&(p, l, r)| {
+        p == proto && r == Endpoint::ANY && l.port == local.port
+            && (l.ip == local.ip || l.is_any_ip() || local.is_any_ip())
+    })
+}
+
+fn ephemeral(t: &BTreeMap<SockKey, Arc<Socket>>, proto: Proto) -> Option<u16> {
+    if NEXT_PORT.load(Ordering::Relaxed) == 0 {
+        NEXT_PORT.store(tcp::random_isn() as u16 | 1, Ordering::Relaxed);
+    }
+    (0..EPHEMERAL_COUNT)
+        .map(|_| EPHEMERAL_FIRST + NEXT_PORT.fetch_add(1, Ordering::Relaxed) % EPHEMERAL_COUNT)
+        .find(|&port| !t.keys().any(|&(p, l, _)| p == proto && l.port == port))
+}
+
+fn resolve(ip: IpAddr) -> Result<(), i64> {
+    let pid   = crate::scheduler::current_pid();
+    let start = ticks();
+    let mut asked = None;
+    loop {
+        if super::neighbour_known(&ip) {
+            return Ok(());
+        }
+        let now = ticks();
+        if now.wrapping_sub(start) >= ARP_WAIT_TICKS {
+            return Err(EHOSTUNREACH);
+        }
+        if asked.is_none_or(|t: u64| now.wrapping_sub(t) >= RTO_TICKS / 2) {
+            super::solicit(&ip);
+            asked = Some(now);
+        }
+        if crate::signal::has_pending(pid) {
+            return Err(EINTR);
+        }
+        crate::scheduler::sleep(1);
+    }
+}
+
+struct Segment {
+    src:     Endpoint,
+    dst:     Endpoint,
+    seq:     u32,
+    ack:     u32,
+    flags:   u8,
+    window:  u16,
+    options: Vec<u8>,
+    data:    Vec<u8>,
+}
+
+fn transmit(segs: Vec<Segment>) {
+    for seg in segs {
+        let mut buf = vec![0u8; 60 + seg.data.len()];
+        let n = tcp::build(
+            seg.src.port, seg.dst.port, seg.seq, seg.ack, seg.flags, seg.window,
+            &seg.options, &seg.data, &seg.src.ip, &seg.dst.ip, &mut buf,
+        );
+        if n > 0 {
+            super::send_ip(&seg.src.ip, &seg.dst.ip, ipv4::PROTO_TCP, &buf[..n]);
+        }
+    }
+}
+
+fn reset(local: Endpoint, remote: Endpoint, seg: &TcpSegment, len: usize) -> Vec<Segment> {
+    if seg.flags & FLAG_RST != 0 {
+        return Vec::new();
+    }
+    let seg_len = len as u32
+        + (seg.flags & FLAG_SYN != 0) as u32
+        + (seg.flags & FLAG_FIN != 0) as u32;
+    let (seq, ack, flags) = if seg.flags & FLAG_ACK != 0 {
+        (seg.ack, 0, FLAG_RST)
+    } else {
+        (0, seg.seq.wrapping_add(seg_len), FLAG_RST | FLAG_ACK)
+    };
+    vec![Segment { src: local, dst: remote, seq, ack, flags, window: 0, options: Vec::new(), data: Vec::new() }]
+}
+
+fn synchronized(state: TcpState) -> bool {
+    matches!(
+        state,
+        TcpState::Established | TcpState::CloseWait | TcpState::FinWait1
+            | TcpState::FinWait2 | TcpState::Closing | TcpState::LastAck
+    )
+}
+
+struct Inner {
+    key:         Option<SockKey>,
+    local:       Endpoint,
+    remote:      Endpoint,
+    state:       TcpState,
+    iss:         u32,
+    snd_una:     u32,
+    snd_nxt:     u32,
+    snd_wnd:     u32,
+    rcv_nxt:     u32,
+    mss:         usize,
+    snd_wscale:  u8,
+    rcv_wscale:  u8,
+    cwnd:        usize,
+    ssthresh:    usize,
+    dupacks:     u32,
+    recover:     Option<u32>,
+    srtt:        u64,
+    rttvar:      u64,
+    rto:         u64,
+    rtt_seq:     u32,
+    rtt_at:      u64,
+    tx:          VecDeque<u8>,
+    rx:          VecDeque<u8>,
+    ooo:         VecDeque<(u32, Vec<u8>)>,
+    fin_at:      Option<u32>,
+    dgrams:      VecDeque<(Endpoint, Vec<u8>)>,
+    backlog:     VecDeque<Arc<Socket>>,
+    pending:     usize,
+    max_backlog: usize,
+    parent:      Option<Weak<Socket>>,
+    fin_sent:    bool,
+    peer_fin:    bool,
+    rd_shut:     bool,
+    wr_shut:     bool,
+    rto_at:      u64,
+    linger_at:   u64,
+    retries:     u32,
+    error:       i64,
+    soft_error:  i64,
+    timeout:     u64,
+    deadline:    u64,
+    events:      u64,
+    waiters:     Vec<u64>,
+}
+
+impl Inner {
+    fn new() -> Self {
+        Self {
+            key:         None,
+            local:       Endpoint::ANY,
+            remote:      Endpoint::ANY,
+            state:       TcpState::Closed,
+            iss:         0,
+            snd_una:     0,
+            snd_nxt:     0,
+            snd_wnd:     0,
+            rcv_nxt:     0,
+            mss:         DEFAULT_MSS,
+            snd_wscale:  0,
+            rcv_wscale:  0,
+            cwnd:        0,
+            ssthresh:    usize::MAX,
+            dupacks:     0,
+            recover:     None,
+            srtt:        0,
+            rttvar:      0,
+            rto:         RTO_TICKS,
+            rtt_seq:     0,
+            rtt_at:      0,
+            tx:          VecDeque::new(),
+            rx:          VecDeque::new(),
+            ooo:         VecDeque::new(),
+            fin_at:      None,
+            dgrams:      VecDeque::new(),
+            backlog:     VecDeque::new(),
+            pending:     0,
+            max_backlog: 0,
+            parent:      None,
+            fin_sent:    false,
+            peer_fin:    false,
+            rd_shut:     false,
+            wr_shut:     false,
+            rto_at:      0,
+            linger_at:   0,
+            retries:     0,
+            error:       0,
+            soft_error:  0,
+            timeout:     0,
+            deadline:    0,
+            events:      0,
+            waiters:     Vec::new(),
+        }
+    }
+
+    fn wake_all(&mut self) {
+        self.events = self.events.wrapping_add(1);
+        self.deadline = 0;
+        for pid in self.waiters.drain(..) {
+            crate::scheduler::wakeup(pid);
+        }
+    }
+
+    fn wait(&mut self, pid: u64, until: u64) {
+        if !self.waiters.contains(&pid) {
+            self.waiters.push(pid);
+        }
+        if until != 0 && (self.deadline == 0 || until < self.deadline) {
+            self.deadline = until;
+        }
+    }
+
+    fn space(&self) -> usize {
+        RX_CAPACITY - self.rx.len()
+    }
+
+    fn window(&self, flags: u8) -> u16 {
+        if flags & FLAG_SYN != 0 {
+            self.space().min(u16::MAX as usize) as u16
+        } else {
+            (self.space() >> self.rcv_wscale).min(u16::MAX as usize) as u16
+        }
+    }
+
+    fn syn_options(&self, flags: u8) -> Vec<u8> {
+        let mut opts = vec![tcp::OPT_MSS, 4];
+        opts.extend_from_slice(&(MSS as u16).to_be_bytes());
+        if flags & FLAG_ACK == 0 || self.rcv_wscale != 0 {
+            opts.extend_from_slice(&[tcp::OPT_NOP, tcp::OPT_WSCALE, 3, self.rcv_wscale]);
+        }
+        opts
+    }
+
+    fn seg(&self, seq: u32, flags: u8, data: Vec<u8>) -> Segment {
+        Segment {
+            src:     self.local,
+            dst:     self.remote,
+            seq,
+            ack:     self.rcv_nxt,
+            flags,
+            window:  self.window(flags),
+            options: if flags & FLAG_SYN != 0 { self.syn_options(flags) } else { Vec::new() },
+            data,
+        }
+    }
+
+    fn in_flight(&self) -> usize {
+        self.snd_nxt.wrapping_sub(self.snd_una) as usize
+    }
+
+    fn arm(&mut self, now: u64) {
+        if self.in_flight() == 0 {
+            self.rto_at = 0;
+        } else if self.rto_at == 0 {
+            self.rto_at = now + (self.rto << self.retries.min(6)).min(RTO_MAX);
+        }
+    }
+
+    fn fail(&mut self, code: i64) {
+        self.state  = TcpState::Closed;
+        self.error  = code;
+        self.rto_at = 0;
+        self.tx.clear();
+        self.ooo.clear();
+        self.wake_all();
+    }
+
+    fn open(&mut self, local: Endpoint, remote: Endpoint, state: TcpState) {
+        self.local      = local;
+        self.remote     = remote;
+        self.state      = state;
+        self.error      = 0;
+        self.soft_error = 0;
+        self.retries    = 0;
+        self.rto_at     = 0;
+        self.rcv_wscale = WSCALE;
+        self.iss        = tcp::random_isn();
+        self.snd_una    = self.iss;
+        self.snd_nxt    = self.iss.wrapping_add(1);
+    }
+
+    fn negotiate(&mut self, syn: &TcpSegment) {
+        let path = super::path_mtu(&self.remote.ip).saturating_sub(self.remote.ip.header_len() + 20);
+        self.mss = syn.mss.map_or(DEFAULT_MSS, |m| m as usize).min(path).clamp(64, MSS);
+        match syn.wscale {
+            Some(shift) => self.snd_wscale = shift,
+            None => {
+                self.snd_wscale = 0;
+                self.rcv_wscale = 0;
+            }
+        }
+        self.snd_wnd  = syn.window as u32;
+        self.cwnd     = (4 * self.mss).min((2 * self.mss).max(4380));
+        self.ssthresh = usize::MAX;
+    }
+
+    fn sample_rtt(&mut self, ticks: u64) {
+        let r = ticks.max(1) << 3;
+        if self.srtt == 0 {
+            self.srtt   = r;
+            self.rttvar = r / 2;
+        } else {
+            self.rttvar = (3 * self.rttvar + self.srtt.abs_diff(r)) / 4;
+            self.srtt   = (7 * self.srtt + r) / 8;
+        }
+        self.rto = ((self.srtt + (4 * self.rttvar).max(8)) >> 3).clamp(RTO_MIN, RTO_MAX);
+    }
+
+    fn send_window(&self) -> usize {
+        (self.snd_wnd as usize).min(self.cwnd.max(self.mss))
+    }
+
+    fn output(&mut self, out: &mut Vec<Segment>, now: u64) {
+        if !synchronized(self.state) || self.state == TcpState::FinWait2 {
+            return;
+        }
+        while !self.fin_sent {
+            let flight = self.in_flight();
+            let unsent = self.tx.len() - flight;
+            let room = if flight == 0 {
+                self.send_window().max(1)
+            } else {
+                self.send_window().saturating_sub(flight)
+            };
+            let n = unsent.min(room).min(self.mss);
+            if n == 0 {
+                break;
+            }
+            let data: Vec<u8> = self.tx.range(flight..flight + n).copied().collect();
+            out.push(self.seg(self.snd_nxt, FLAG_PSH | FLAG_ACK, data));
+            self.snd_nxt = self.snd_nxt.wrapping_add(n as u32);
+            if self.rtt_at == 0 {
+                self.rtt_at  = now;
+                self.rtt_seq = self.snd_nxt;
+            }
+        }
+        if self.wr_shut && !self.fin_sent && self.in_flight() == self.tx.len() {
+            out.push(self.seg(self.snd_nxt, FLAG_FIN | FLAG_ACK, Vec::new()));
+            self.snd_nxt  = self.snd_nxt.wrapping_add(1);
+            self.fin_sent = true;
+            self.state = match self.state {
+                TcpState::Established => TcpState::FinWait1,
+                TcpState::CloseWait   => TcpState::LastAck,
+                s                     => s,
+            };
+        }
+        self.arm(now);
+    }
+
+    fn retransmit_head(&mut self, out: &mut Vec<Segment>) {
+        self.rtt_at = 0;
+        let n = self.tx.len().min(self.mss).min(self.in_flight());
+        if n > 0 {
+            let data: Vec<u8> = self.tx.range(..n).copied().collect();
+            out.push(self.seg(self.snd_una, FLAG_PSH | FLAG_ACK, data));
+        } else if self.fin_sent {
+            out.push(self.seg(self.snd_una, FLAG_FIN | FLAG_ACK, Vec::new()));
+        }
+    }
+
+    fn time_wait(&mut self, now: u64) {
+        self.state     = TcpState::TimeWait;
+        self.rto_at    = 0;
+        self.linger_at = now + 2 * MSL_TICKS;
+    }
+
+    fn timeout(&mut self, now: u64, out: &mut Vec<Segment>) {
+        if self.state == TcpState::TimeWait {
+            if now >= self.linger_at {
+                self.state = TcpState::Closed;
+                self.wake_all();
+            }
+            return;
+        }
+        if self.linger_at != 0 && now >= self.linger_at && self.state != TcpState::Closed {
+            self.fail(ETIMEDOUT);
+            return;
+        }
+        if self.rto_at == 0 || now < self.rto_at {
+            return;
+        }
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.fail(if self.soft_error != 0 { self.soft_error } else { ETIMEDOUT });
+            return;
+        }
+        self.rto_at = 0;
+        self.rtt_at = 0;
+        match self.state {
+            TcpState::SynSent     => out.push(self.seg(self.iss, FLAG_SYN, Vec::new())),
+            TcpState::SynReceived => out.push(self.seg(self.iss, FLAG_SYN | FLAG_ACK, Vec::new())),
+            _ => {
+                self.ssthresh = (self.in_flight() / 2).max(2 * self.mss);
+                self.cwnd     = self.mss;
+                self.dupacks  = 0;
+                self.recover  = None;
+                self.snd_nxt  = self.snd_una;
+                self.fin_sent = false;
+                self.output(out, now);
+            }
+        }
+        self.arm(now);
+    }
+
+    fn on_dupack(&mut self, out: &mut Vec<Segment>, now: u64) {
+        self.dupacks += 1;
+        if self.recover.is_some() {
+            self.cwnd += self.mss;
+        } else if self.dupacks == DUPACK_THRESH {
+            self.ssthresh = (self.in_flight() / 2).max(2 * self.mss);
+            self.recover  = Some(self.snd_nxt);
+            self.retransmit_head(out);
+            self.cwnd = self.ssthresh + DUPACK_THRESH as usize * self.mss;
+        }
+        self.output(out, now);
+    }
+
+    fn on_ack(&mut self, ack: u32, acked: usize, out: &mut Vec<Segment>, now: u64) {
+        self.dupacks = 0;
+        if self.rtt_at != 0 && !tcp::wrapping_gt(self.rtt_seq, ack) {
+            self.sample_rtt(now.saturating_sub(self.rtt_at));
+            self.rtt_at = 0;
+        }
+        match self.recover {
+            Some(recover) if tcp::wrapping_gt(recover, ack) => {
+                self.retransmit_head(out);
+                self.cwnd = self.cwnd.saturating_sub(acked).max(self.mss) + self.mss;
+            }
+            Some(_) => {
+                self.cwnd    = self.ssthresh.min(self.in_flight().max(self.mss) + self.mss);
+                self.recover = None;
+            }
+            None if self.cwnd < self.ssthresh => self.cwnd += acked.min(self.mss),
+            None => self.cwnd += (self.mss * self.mss / self.cwnd).max(1),
+        }
+    }
+
+    fn icmp_error(&mut self, code: i64, seq: u32, mtu: usize, now: u64, out: &mut Vec<Segment>) {
+        if seq.wrapping_sub(self.snd_una) >= self.snd_nxt.wrapping_sub(self.snd_una).max(1) {
+            return;
+        }
+        match self.state {
+            TcpState::SynSent | TcpState::SynReceived if code != EMSGSIZE => self.fail(code),
+            _ if code == ECONNREFUSED => self.fail(code),
+            _ if code == EMSGSIZE => {
+                let mss = mtu.saturating_sub(self.remote.ip.header_len() + 20).max(64);
+                if mss >= self.mss {
+                    return;
+                }
+                self.mss = mss;
+                if synchronized(self.state) && self.in_flight() > 0 {
+                    self.snd_nxt  = self.snd_una;
+                    self.fin_sent = false;
+                    self.rtt_at   = 0;
+                    self.rto_at   = 0;
+                    self.output(out, now);
+                }
+            }
+            _ => self.soft_error = code,
+        }
+    }
+
+    fn deliver(&mut self, data: &[u8]) {
+        let take = if self.rd_shut { data.len() } else { data.len().min(self.space()) };
+        if !self.rd_shut {
+            self.rx.extend(&data[..take]);
+        }
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(take as u32);
+    }
+
+    fn reassemble(&mut self, seq: u32, data: &[u8]) {
+        let ahead = seq.wrapping_sub(self.rcv_nxt) as usize;
+        let behind = self.rcv_nxt.wrapping_sub(seq) as usize;
+        if (seq.wrapping_sub(self.rcv_nxt) as i32) > 0 {
+            let rcv_nxt = self.rcv_nxt;
+            let off = |s: u32| s.wrapping_sub(rcv_nxt) as usize;
+            let mut start = ahead;
+            let mut end   = ahead + data.len().min(self.space().saturating_sub(ahead));
+            let pos = self.ooo.iter().position(|(s, _)| off(*s) > start).unwrap_or(self.ooo.len());
+            if pos > 0 {
+                let (s, d) = &self.ooo[pos - 1];
+                start = start.max(off(*s) + d.len());
+            }
+            let queued: usize = self.ooo.iter().map(|(_, d)| d.len()).sum();
+            end = end.min(start + self.space().saturating_sub(queued));
+            if start >= end || self.ooo.len() >= OOO_MAX_SEGS {
+                return;
+            }
+            while let Some((s, d)) = self.ooo.get(pos) {
+                if off(*s) >= end {
+                    break;
+                }
+                if off(*s) + d.len() > end {
+                    end = off(*s);
+                    break;
+                }
+                self.ooo.remove(pos);
+            }
+            if start >= end {
+                return;
+            }
+            let data = &data[start - ahead..end - ahead];
+            self.ooo.insert(pos, (rcv_nxt.wrapping_add(start as u32), data.to_vec()));
+            return;
+        }
+        if behind < data.len() {
+            self.deliver(&data[behind..]);
+        }
+        while let Some((s, _)) = self.ooo.front() {
+            if (s.wrapping_sub(self.rcv_nxt) as i32) > 0 {
+                break;
+            }
+            let (s, d) = self.ooo.pop_front().unwrap();
+            let skip = self.rcv_nxt.wrapping_sub(s) as usize;
+            if skip < d.len() {
+                self.deliver(&d[skip..]);
+            }
+        }
+    }
+
+    fn segment(&mut self, seg: &TcpSegment, data: &[u8], now: u64, out: &mut Vec<Segment>) {
+        if seg.flags & FLAG_RST != 0 {
+            match self.state {
+                TcpState::SynSent => {
+                    if seg.flags & FLAG_ACK != 0 && seg.ack == self.snd_nxt {
+                        self.fail(ECONNREFUSED);
+                    }
+                }
+                TcpState::Closed | TcpState::Listen | TcpState::TimeWait => {}
+                _ => {
+                    if seg.seq.wrapping_sub(self.rcv_nxt) as usize <= self.space() {
+                        self.fail(ECONNRESET);
+                    }
+                }
+            }
+            return;
+        }
+
+        match self.state {
+            TcpState::SynSent => {
+                if seg.flags & (FLAG_SYN | FLAG_ACK) != FLAG_SYN | FLAG_ACK {
+                    return;
+                }
+                if seg.ack != self.snd_nxt {
+                    out.extend(reset(self.local, self.remote, seg, data.len()));
+                    return;
+                }
+                if self.retries == 0 && self.rtt_at != 0 {
+                    self.sample_rtt(now.saturating_sub(self.rtt_at));
+                }
+                self.negotiate(seg);
+                self.rcv_nxt = seg.seq.wrapping_add(1);
+                self.snd_una = seg.ack;
+                self.state   = TcpState::Established;
+                self.retries = 0;
+                self.rto_at  = 0;
+                self.rtt_at  = 0;
+                out.push(self.seg(self.snd_nxt, FLAG_ACK, Vec::new()));
+                self.output(out, now);
+                return;
+            }
+            TcpState::SynReceived => {
+                if seg.flags & FLAG_SYN != 0 {
+                    out.push(self.seg(self.iss, FLAG_SYN | FLAG_ACK, Vec::new()));
+                    return;
+                }
+                if seg.flags & FLAG_ACK == 0 {
+                    return;
+                }
+                if seg.ack != self.snd_nxt {
+                    out.extend(reset(self.local, self.remote, seg, data.len()));
+                    return;
+                }
+                if self.retries == 0 && self.rtt_at != 0 {
+                    self.sample_rtt(now.saturating_sub(self.rtt_at));
+                }
+                self.snd_una = seg.ack;
+                self.snd_wnd = (seg.window as u32) << self.snd_wscale;
+                self.state   = TcpState::Established;
+                self.retries = 0;
+                self.rto_at  = 0;
+                self.rtt_at  = 0;
+            }
+            TcpState::Closed | TcpState::Listen => return,
+            _ => {}
+        }
+
+        if seg.flags & FLAG_SYN != 0 {
+            out.push(self.seg(self.snd_nxt, FLAG_ACK, Vec::new()));
+            return;
+        }
+        if seg.flags & FLAG_ACK == 0 {
+            return;
+        }
+
+        let acked  = seg.ack.wrapping_sub(self.snd_una) as usize;
+        let flight = self.in_flight();
+        let wnd    = (seg.window as u32) << self.snd_wscale;
+        if acked == 0 && flight > 0 && data.is_empty() && seg.flags & FLAG_FIN == 0 && wnd == self.snd_wnd {
+            self.on_dupack(out, now);
+        }
+        if acked <= flight {
+            self.snd_wnd = wnd;
+        }
+        if acked > 0 && acked <= flight {
+            let fin_acked = self.fin_sent && seg.ack == self.snd_nxt;
+            let bytes = (acked - fin_acked as usize).min(self.tx.len());
+            self.tx.drain(..bytes);
+            self.snd_una = seg.ack;
+            self.retries = 0;
+            self.rto_at  = 0;
+            self.on_ack(seg.ack, acked, out, now);
+            if fin_acked {
+                match self.state {
+                    TcpState::FinWait1 => self.state = TcpState::FinWait2,
+                    TcpState::Closing  => self.time_wait(now),
+                    TcpState::LastAck  => self.state = TcpState::Closed,
+                    _ => {}
+                }
+            }
+        }
+
+        let open = matches!(
+            self.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        );
+        let mut ack_now = false;
+        if !data.is_empty() {
+            ack_now = true;
+            if open {
+                self.reassemble(seg.seq, data);
+            }
+        }
+
+        if seg.flags & FLAG_FIN != 0 {
+            ack_now = true;
+            if open {
+                self.fin_at = Some(seg.seq.wrapping_add(data.len() as u32));
+            } else if self.state == TcpState::TimeWait {
+                self.time_wait(now);
+            }
+        }
+        if !self.peer_fin && self.fin_at == Some(self.rcv_nxt) {
+            self.rcv_nxt  = self.rcv_nxt.wrapping_add(1);
+            self.peer_fin = true;
+            self.ooo.clear();
+            match self.state {
+                TcpState::Established => self.state = TcpState::CloseWait,
+                TcpState::FinWait1    => self.state = TcpState::Closing,
+                TcpState::FinWait2    => self.time_wait(now),
+                _ => {}
+            }
+        }
+
+        if ack_now {
+            out.push(self.seg(self.snd_nxt, FLAG_ACK, Vec::new()));
+        }
+        self.output(out, now);
+    }
+}
+
+pub struct Socket {
+    pub proto:  Proto,
+    pub family: u64,
+    inner:      Mutex<Inner>,
+}
+
+impl Socket {
+    pub fn new(proto: Proto) -> Arc<Self> {
+        Self::with_family(proto, AF_INET)
+    }
+
+    pub fn with_family(proto: Proto, family: u64) -> Arc<Self> {
+        Arc::new(Self { proto, family, inner: Mutex::new(Inner::new()) })
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Inner) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.inner.lock()))
+    }
+
+    pub fn local(&self) -> Endpoint {
+        self.with(|s| s.local)
+    }
+
+    pub fn peer(&self) -> Endpoint {
+        self.with(|s| s.remote)
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.with(|s| s.state)
+    }
+
+    pub fn set_timeout(&self, ticks: u64) {
+        self.with(|s| s.timeout = ticks);
+    }
+
+    pub fn available(&self) -> usize {
+        self.with(|s| match self.proto {
+            Proto::Tcp => s.rx.len(),
+            Proto::Udp => s.dgrams.front().map_or(0, |(_, d)| d.len()),
+        })
+    }
+
+    fn hash(self: &Arc<Self>, key: SockKey) -> bool {
+        let me = self.clone();
+        let ok = with_table(|t| {
+            if t.contains_key(&key) {
+                return false;
+            }
+            t.insert(key, me);
+            true
+        });
+        if ok {
+            self.with(|s| s.key = Some(key));
+        }
+        ok
+    }
+
+    fn rehash(self: &Arc<Self>, key: SockKey) -> Result<(), i64> {
+        let old = self.with(|s| s.key);
+        let me  = self.clone();
+        with_table(|t| {
+            if t.contains_key(&key) {
+                return Err(EADDRINUSE);
+            }
+            if let Some(old) = old {
+                t.remove(&old);
+            }
+            t.insert(key, me);
+            Ok(())
+        })?;
+        self.with(|s| s.key = Some(key));
+        Ok(())
+    }
+
+    fn unhash(&self) {
+        if let Some(key) = self.with(|s| s.key.take()) {
+            with_table(|t| t.remove(&key));
+        }
+    }
+
+    fn wait<T>(
+        &self,
+        nonblock: bool,
+        mut ready: impl FnMut(&mut Inner, &mut Vec<Segment>) -> Option<Result<T, i64>>,
+    ) -> Result<T, i64> {
+        let pid   = crate::scheduler::current_pid();
+        let start = ticks();
+        loop {
+            let mut out = Vec::new();
+            let (r, seen, timeout) = self.with(|s| (ready(s, &mut out), s.events, s.timeout));
+            transmit(out);
+            if let Some(r) = r {
+                return r;
+            }
+            let until = if timeout != 0 { start + timeout } else { 0 };
+            if nonblock || (until != 0 && ticks() >= until) {
+                return Err(EAGAIN);
+            }
+            let mut interrupted = false;
+            crate::scheduler::block_unless(SOCKET_CAUSE, || {
+                if crate::signal::has_pending(pid) {
+                    interrupted = true;
+                    return true;
+                }
+                let mut s = self.inner.lock();
+                if s.events != seen || (until != 0 && ticks() >= until) {
+                    return true;
+                }
+                s.wait(pid, until);
+                false
+            });
+            if interrupted {
+                return Err(EINTR);
+            }
+        }
+    }
+
+    fn settle(self: &Arc<Self>, prev: TcpState, out: Vec<Segment>) {
+        transmit(out);
+        let (state, parent) = self.with(|s| (s.state, s.parent.clone()));
+        if prev == TcpState::SynReceived && state != TcpState::SynReceived {
+            let queued = parent.and_then(|p| p.upgrade()).is_some_and(|p| {
+                p.with(|l| {
+                    l.pending = l.pending.saturating_sub(1);
+                    if state == TcpState::Closed || l.state != TcpState::Listen {
+                        return false;
+                    }
+                    l.backlog.push_back(self.clone());
+                    l.wake_all();
+                    true
+                })
+            });
+            if !queued && state != TcpState::Closed {
+                self.abort();
+                return;
+            }
+        }
+        if state == TcpState::Closed && prev != TcpState::Closed {
+            self.unhash();
+        }
+    }
+
+    fn abort(self: &Arc<Self>) {
+        let mut out = Vec::new();
+        self.with(|s| {
+            if synchronized(s.state) || s.state == TcpState::SynReceived {
+                out.push(s.seg(s.snd_nxt, FLAG_RST | FLAG_ACK, Vec::new()));
+            }
+            s.fail(ECONNRESET);
+        });
+        transmit(out);
+        self.unhash();
+    }
+
+    pub fn bind(self: &Arc<Self>, mut ep: Endpoint) -> Result<(), i64> {
+        if self.with(|s| s.key.is_some()) {
+            return Err(EINVAL);
+        }
+        if !ep.is_any_ip() && !super::is_local(&ep.ip) {
+            return Err(EADDRNOTAVAIL);
+        }
+        let proto = self.proto;
+        let me    = self.clone();
+        let key = with_table(|t| {
+            if ep.port == 0 {
+                ep.port = ephemeral(t, proto).ok_or(EADDRINUSE)?;
+            } else if port_taken(t, proto, ep) {
+                return Err(EADDRINUSE);
+            }
+            let key = (proto, ep, Endpoint::ANY);
+            t.insert(key, me);
+            Ok(key)
+        })?;
+        self.with(|s| {
+            s.key   = Some(key);
+            s.local = ep;
+        });
+        Ok(())
+    }
+
+    fn autobind(self: &Arc<Self>) -> Result<(), i64> {
+        if self.with(|s| s.key.is_none()) {
+            let any = if self.family == AF_INET6 { IpAddr::UNSPECIFIED_V6 } else { IpAddr::UNSPECIFIED_V4 };
+            self.bind(Endpoint::new(any, 0))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn listen(self: &Arc<Self>, backlog: usize) -> Result<(), i64> {
+        if self.proto != Proto::Tcp {
+            return Err(EOPNOTSUPP);
+        }
+        self.autobind()?;
+        self.with(|s| match s.state {
+            TcpState::Closed if s.remote == Endpoint::ANY => {
+                s.state       = TcpState::Listen;
+                s.max_backlog = backlog.clamp(1, MAX_BACKLOG);
+                Ok(())
+            }
+            TcpState::Listen => {
+                s.max_backlog = backlog.clamp(1, MAX_BACKLOG);
+                Ok(())
+            }
+            _ => Err(EINVAL),
+        })
+    }
+
+    pub fn accept(&self, nonblock: bool) -> Result<Arc<Socket>, i64> {
+        if self.proto != Proto::Tcp {
+            return Err(EOPNOTSUPP);
+        }
+        self.wait(nonblock, |s, _| {
+            if s.state != TcpState::Listen {
+                return Some(Err(EINVAL));
+            }
+            s.backlog.pop_front().map(Ok)
+        })
+    }
+
+    pub fn connect(self: &Arc<Self>, remote: Endpoint, nonblock: bool) -> Result<(), i64> {
+        if remote.is_any_ip() || remote.port == 0 {
+            return Err(EADDRNOTAVAIL);
+        }
+        if !super::is_ready() {
+            return Err(ENETDOWN);
+        }
+        if self.proto == Proto::Udp {
+            self.autobind()?;
+            self.rehash((Proto::Udp, self.local(), remote))?;
+            self.with(|s| s.remote = remote);
+            return Ok(());
+        }
+
+        match self.with(|s| s.state) {
+            TcpState::Closed => {}
+            TcpState::SynSent | TcpState::SynReceived => return Err(EALREADY),
+            TcpState::Listen => return Err(EINVAL),
+            _ => return Err(EISCONN),
+        }
+        self.autobind()?;
+        let mut local = self.local();
+        if local.is_any_ip() {
+            local.ip = super::source_for(&remote.ip).ok_or(ENETUNREACH)?;
+        }
+        self.rehash((Proto::Tcp, local, remote))?;
+        if let Err(e) = resolve(remote.ip) {
+            self.unhash();
+            return Err(e);
+        }
+
+        let mut out = Vec::new();
+        self.with(|s| {
+            s.open(local, remote, TcpState::SynSent);
+            s.rcv_nxt = 0;
+            s.snd_wnd = 0;
+            s.rtt_at  = ticks();
+            out.push(s.seg(s.iss, FLAG_SYN, Vec::new()));
+            s.arm(s.rtt_at);
+        });
+        transmit(out);
+        if nonblock {
+            return Err(EINPROGRESS);
+        }
+        self.wait(false, |s, _| match s.state {
+            TcpState::SynSent => None,
+            TcpState::Closed  => Some(Err(if s.error != 0 { s.error } else { ECONNREFUSED })),
+            _                 => Some(Ok(())),
+        })
+    }
+
+    pub fn send(self: &Arc<Self>, data: &[u8], to: Option<Endpoint>, nonblock: bool) -> Result<usize, i64> {
+        match self.proto {
+            Proto::Tcp => self.send_stream(data, nonblock),
+            Proto::Udp => self.send_dgram(data, to),
+        }
+    }
+
+    fn send_stream(&self, data: &[u8], nonblock: bool) -> Result<usize, i64> {
+        let mut done = 0usize;
+        let r = self.wait(nonblock, |s, out| {
+            match s.state {
+                TcpState::SynSent | TcpState::SynReceived => return None,
+                TcpState::Established | TcpState::CloseWait => {}
+                TcpState::Closed if s.error != 0 => return Some(Err(s.error)),
+                TcpState::Closed | TcpState::Listen if s.remote == Endpoint::ANY => {
+                    return Some(Err(ENOTCONN));
+                }
+                _ => return Some(Err(EPIPE)),
+            }
+            if s.wr_shut {
+                return Some(Err(EPIPE));
+            }
+            let n = (TX_CAPACITY - s.tx.len()).min(data.len() - done);
+            if n > 0 {
+                s.tx.extend(&data[done..done + n]);
+                done += n;
+                s.output(out, ticks());
+            }
+            if done == data.len() {
+                return Some(Ok(done));
+            }
+            None
+        });
+        match r {
+            Err(EAGAIN | EINTR) if done > 0 => Ok(done),
+            r => r,
+        }
+    }
+
+    fn send_dgram(self: &Arc<Self>, data: &[u8], to: Option<Endpoint>) -> Result<usize, i64> {
+        let (wr_shut, remote, error) = self.with(|s| (s.wr_shut, s.remote, core::mem::take(&mut s.error)));
+        if wr_shut {
+            return Err(EPIPE);
+        }
+        if error != 0 {
+            return Err(error);
+        }
+        let dst = match to {
+            Some(ep) => ep,
+            None if remote != Endpoint::ANY => remote,
+            None => return Err(EDESTADDRREQ),
+        };
+        let limit = if dst.ip.is_v6() { super::path_mtu(&dst.ip) - 48 } else { MAX_UDP_PAYLOAD };
+        if data.len() > limit {
+            return Err(EMSGSIZE);
+        }
+        if !super::is_ready() {
+            return Err(ENETDOWN);
+        }
+        self.autobind()?;
+        let src_ip = super::source_for(&dst.ip).ok_or(ENETUNREACH)?;
+        resolve(dst.ip)?;
+
+        let mut pkt = vec![0u8; 8 + data.len()];
+        let n = udp::build_ip(self.local().port, dst.port, data, &src_ip, &dst.ip, &mut pkt);
+        if super::send_ip(&src_ip, &dst.ip, ipv4::PROTO_UDP, &pkt[..n]) {
+            Ok(data.len())
+        } else {
+            Err(EHOSTUNREACH)
+        }
+    }
+
+    pub fn recv(&self, buf: &mut [u8], nonblock: bool) -> Result<(usize, Endpoint), i64> {
+        match self.proto {
+            Proto::Tcp => self.wait(nonblock, |s, out| {
+                if !s.rx.is_empty() {
+                    let was = s.space();
+                    let n   = buf.len().min(s.rx.len());
+                    for (dst, src) in buf.iter_mut().zip(s.rx.drain(..n)) {
+                        *dst = src;
+                    }
+                    if synchronized(s.state) && was < 2 * s.mss && s.space() >= 2 * s.mss {
+                        out.push(s.seg(s.snd_nxt, FLAG_ACK, Vec::new()));
+                    }
+                    return Some(Ok((n, s.remote)));
+                }
+                if s.peer_fin || s.rd_shut {
+                    return Some(Ok((0, s.remote)));
+                }
+                match s.state {
+                    TcpState::Closed if s.error != 0 => Some(Err(s.error)),
+                    TcpState::Listen => Some(Err(ENOTCONN)),
+                    TcpState::Closed if s.remote == Endpoint::ANY => Some(Err(ENOTCONN)),
+                    TcpState::Closed => Some(Ok((0, s.remote))),
+                    _ => None,
+                }
+            }),
+            Proto::Udp => self.wait(nonblock, |s, _| {
+                if let Some((from, d)) = s.dgrams.pop_front() {
+                    let n = buf.len().min(d.len());
+                    buf[..n].copy_from_slice(&d[..n]);
+                    return Some(Ok((n, from)));
+                }
+                if s.error != 0 {
+                    return Some(Err(core::mem::take(&mut s.error)));
+                }
+                if s.rd_shut {
+                    return Some(Ok((0, s.remote)));
+                }
+                None
+            }),
+        }
+    }
+
+    pub fn shutdown(&self, how: u64) -> Result<(), i64> {
+        if how > SHUT_RDWR {
+            return Err(EINVAL);
+        }
+        let mut out = Vec::new();
+        let r = self.with(|s| {
+            let connected = match self.proto {
+                Proto::Tcp => synchronized(s.state)
+                    || matches!(s.state, TcpState::SynSent | TcpState::SynReceived),
+                Proto::Udp => s.remote != Endpoint::ANY,
+            };
+            if !connected {
+                return Err(ENOTCONN);
+            }
+            if how != SHUT_WR {
+                s.rd_shut = true;
+                s.rx.clear();
+                s.dgrams.clear();
+            }
+            if how != SHUT_RD {
+                s.wr_shut = true;
+                if self.proto == Proto::Tcp {
+                    s.output(&mut out, ticks());
+                }
+            }
+            s.wake_all();
+            Ok(())
+        });
+        transmit(out);
+        r
+    }
+
+    pub fn close(self: &Arc<Self>) {
+        if self.proto == Proto::Udp {
+            self.with(|s| {
+                s.rd_shut = true;
+                s.wake_all();
+            });
+            self.unhash();
+            return;
+        }
+        let now = ticks();
+        let mut out = Vec::new();
+        let (state, backlog) = self.with(|s| {
+            let backlog = core::mem::take(&mut s.backlog);
+            match s.state {
+                TcpState::Established | TcpState::CloseWait if !s.rx.is_empty() => {
+                    out.push(s.seg(s.snd_nxt, FLAG_RST | FLAG_ACK, Vec::new()));
+                    s.fail(ECONNRESET);
+                }
+                TcpState::SynSent | TcpState::Listen => s.state = TcpState::Closed,
+                _ => {
+                    s.rd_shut = true;
+                    s.wr_shut = true;
+                    s.output(&mut out, now);
+                }
+            }
+            if !matches!(s.state, TcpState::Closed | TcpState::TimeWait) {
+                s.linger_at = now + LINGER_TICKS;
+            }
+            s.wake_all();
+            (s.state, backlog)
+        });
+        transmit(out);
+        for child in backlog {
+            child.abort();
+        }
+        if state == TcpState::Closed {
+            self.unhash();
+        }
+    }
+}
+
+fn accept_syn(listener: &Arc<Socket>, local: Endpoint, remote: Endpoint, seg: &TcpSegment, len: usize) {
+    if seg.flags & FLAG_RST != 0 {
+        return;
+    }
+    if seg.flags & FLAG_ACK != 0 {
+        transmit(reset(local, remote, seg, len));
+        return;
+    }
+    if seg.flags & FLAG_SYN == 0 {
+        return;
+    }
+    let room = listener.with(|l| {
+        if l.backlog.len() + l.pending >= l.max_backlog {
+            return false;
+        }
+        l.pending += 1;
+        true
+    });
+    if !room {
+        return;
+    }
+
+    let child = Socket::with_family(Proto::Tcp, listener.family);
+    let mut out = Vec::new();
+    child.with(|c| {
+        c.open(local, remote, TcpState::SynReceived);
+        c.parent  = Some(Arc::downgrade(listener));
+        c.rcv_nxt = seg.seq.wrapping_add(1);
+        c.rtt_at  = ticks();
+        c.negotiate(seg);
+        out.push(c.seg(c.iss, FLAG_SYN | FLAG_ACK, Vec::new()));
+        c.arm(c.rtt_at);
+    });
+    if child.hash((Proto::Tcp, local, remote)) {
+        transmit(out);
+    } else {
+        listener.with(|l| l.pending = l.pending.saturating_sub(1));
+    }
+}
+
+pub fn tcp_input(src: IpAddr, dst: IpAddr, buf: &[u8]) {
+    let seg = match TcpSegment::parse(buf) {
+        Some(s) => s,
+        None => return,
+    };
+    let data   = seg.payload(buf);
+    let local  = Endpoint::new(dst, seg.dst_port);
+    let remote = Endpoint::new(src, seg.src_port);
+
+    let mut sock = lookup(Proto::Tcp, local, remote);
+    if let Some(s) = &sock {
+        let reuse = seg.flags & (FLAG_SYN | FLAG_ACK | FLAG_RST) == FLAG_SYN
+            && s.with(|s| s.state == TcpState::TimeWait && tcp::wrapping_gt(seg.seq, s.rcv_nxt));
+        if reuse {
+            s.with(|s| s.state = TcpState::Closed);
+            s.unhash();
+            sock = lookup(Proto::Tcp, local, remote);
+        }
+    }
+    let sock = match sock {
+        Some(s) => s,
+        None => {
+            transmit(reset(local, remote, &seg, data.len()));
+            return;
+        }
+    };
+
+    let mut out = Vec::new();
+    let prev = sock.with(|s| {
+        let prev = s.state;
+        match prev {
+            TcpState::Listen => {}
+            TcpState::Closed => out.extend(reset(local, remote, &seg, data.len())),
+            _ => {
+                s.segment(&seg, data, ticks(), &mut out);
+                s.wake_all();
+            }
+        }
+        prev
+    });
+    if prev == TcpState::Listen {
+        accept_syn(&sock, local, remote, &seg, data.len());
+        return;
+    }
+    sock.settle(prev, out);
+}
+
+pub fn udp_input(src: IpAddr, dst: IpAddr, buf: &[u8]) -> bool {
+    let hdr = match UdpHeader::parse(buf) {
+        Some(h) => h,
+        None => return false,
+    };
+    let local  = Endpoint::new(dst, hdr.dst_port);
+    let remote = Endpoint::new(src, hdr.src_port);
+    if let Some(sock) = lookup(Proto::Udp, local, remote) {
+        let data = hdr.payload(buf).to_vec();
+        sock.with(|s| {
+            if !s.rd_shut && s.dgrams.len() < DGRAM_QUEUE {
+                s.dgrams.push_back((remote, data));
+                s.wake_all();
+            }
+        });
+        true
+    } else {
+        false
+    }
+}
+
+pub fn icmp_input(err: &IcmpError) {
+    let code = match (err.kind, err.code) {
+        (icmp::ICMP_DEST_UNREACH, icmp::CODE_PROTO_UNREACH | icmp::CODE_PORT_UNREACH) => ECONNREFUSED,
+        (icmp::ICMP_DEST_UNREACH, icmp::CODE_FRAG_NEEDED) => {
+            super::set_path_mtu(&IpAddr::V4(err.inner.dst), err.mtu as usize);
+            EMSGSIZE
+        }
+        (icmp::ICMP_DEST_UNREACH, icmp::CODE_NET_UNREACH) => ENETUNREACH,
+        _ => EHOSTUNREACH,
+    };
+    transport_error(err.inner.proto, err.inner.src.into(), err.inner.dst.into(), code, err.transport);
+}
+
+pub fn icmpv6_input(err: &Icmpv6Error) {
+    let code = match (err.kind, err.code) {
+        (icmpv6::ICMPV6_DEST_UNREACH, icmpv6::CODE_PORT_UNREACH) => ECONNREFUSED,
+        (icmpv6::ICMPV6_DEST_UNREACH, icmpv6::CODE_NO_ROUTE) => ENETUNREACH,
+        (icmpv6::ICMPV6_PACKET_TOO_BIG, _) => {
+            super::set_path_mtu(&IpAddr::V6(err.inner.dst), err.mtu as usize);
+            EMSGSIZE
+        }
+        _ => EHOSTUNREACH,
+    };
+    transport_error(err.inner.next, err.inner.src.into(), err.inner.dst.into(), code, err.transport);
+}
+
+fn transport_error(proto: u8, src: IpAddr, dst: IpAddr, code: i64, t: &[u8]) {
+    let local  = Endpoint::new(src, u16::from_be_bytes([t[0], t[1]]));
+    let remote = Endpoint::new(dst, u16::from_be_bytes([t[2], t[3]]));
+    let proto = match proto {
+        ipv4::PROTO_TCP => Proto::Tcp,
+        ipv4::PROTO_UDP => Proto::Udp,
+        _ => return,
+    };
+    let sock = match lookup(proto, local, remote) {
+        Some(s) => s,
+        None => return,
+    };
+    match proto {
+        Proto::Udp => sock.with(|s| {
+            if s.remote == remote && code != EMSGSIZE {
+                s.error = code;
+                s.wake_all();
+            }
+        }),
+        Proto::Tcp => {
+            let seq = u32::from_be_bytes([t[4], t[5], t[6], t[7]]);
+            let mut out = Vec::new();
+            let prev = sock.with(|s| {
+                let prev = s.state;
+                if prev != TcpState::Listen {
+                    s.icmp_error(code, seq, super::path_mtu(&remote.ip), ticks(), &mut out);
+                    s.wake_all();
+                }
+                prev
+            });
+            if prev != TcpState::Listen {
+                sock.settle(prev, out);
+            }
+        }
+    }
+}
+
+pub fn tick() {
+    let now = ticks();
+    let socks: Vec<Arc<Socket>> = with_table(|t| t.values().cloned().collect());
+    for sock in socks {
+        let mut out = Vec::new();
+        let prev = sock.with(|s| {
+            if s.deadline != 0 && now >= s.deadline {
+                s.wake_all();
+            }
+            let prev = s.state;
+            if sock.proto == Proto::Tcp {
+                s.timeout(now, &mut out);
+            }
+            prev
+        });
+        if sock.proto == Proto::Tcp && prev != TcpState::Listen {
+            sock.settle(prev, out);
+        }
+    }
+}
+
+struct SockRow {
+    proto:  Proto,
+    local:  Endpoint,
+    remote: Endpoint,
+    state:  TcpState,
+    rx:     usize,
+    tx:     usize,
+    cwnd:   usize,
+    rto:    u64,
+}
+
+pub fn cmd_sockets() {
+    let rows: Vec<SockRow> = with_table(|t| t.values().cloned().collect::<Vec<_>>())
+        .iter()
+        .map(|sock| sock.with(|s| SockRow {
+            proto:  sock.proto,
+            local:  s.local,
+            remote: s.remote,
+            state:  s.state,
+            rx:     s.rx.len(),
+            tx:     s.tx.len(),
+            cwnd:   s.cwnd,
+            rto:    s.rto,
+        }))
+        .collect();
+
+    if rows.is_empty() {
+        crate::cprintln!(120, 140, 140, "no sockets");
+        return;
+    }
+    crate::cprintln!(57, 197, 187, "proto  local                  remote                 state        rx     tx     cwnd    rto");
+    for row in rows {
+        let (proto, state, cwnd, rto) = match row.proto {
+            Proto::Tcp => (
+                "tcp",
+                alloc::format!("{:?}", row.state),
+                alloc::format!("{}", row.cwnd),
+                alloc::format!("{}ms", row.rto * 1000 / PIT_HZ as u64),
+            ),
+            Proto::Udp => ("udp", String::from("-"), String::from("-"), String::from("-")),
+        };
+        let local  = alloc::format!("{}", row.local);
+        let remote = if row.remote == Endpoint::ANY {
+            String::from("*:*")
+        } else {
+            alloc::format!("{}", row.remote)
+        };
+        crate::cprintln!(230, 240, 240,
+            "{:<6} {:<22} {:<22} {:<12} {:<6} {:<6} {:<7} {}", proto, local, remote, state, row.rx, row.tx, cwnd, rto);
+    }
+}
@@ -1,14 +1,22 @@
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::sync::atomic::Ordering;
 use super::CTRL_C;
+use super::ip::IpAddr;
 use super::tcp::TcpSocket;
 use super::tls_crypto::{
     cbc_encrypt, cbc_decrypt, tls_pad, tls_unpad,
-    Sha256State, hmac_sha1, hmac_sha256, prf_sha256,
+    HashAlg, Sha256State, Sha512State, hmac, hmac_sha1, hmac_sha256, prf_sha256,
 };
 use super::tls_rsa::{parse_rsa_public_key, rsa_pkcs1_encrypt};
 use super::tls_ecdh::{ecdh_keypair, ecdh_shared};
-use super::tls_gcm::{aes128gcm_seal, aes128gcm_open, hkdf_extract, hkdf_expand_label, derive_secret};
-use super::tls_crypto::sha256;
+use super::tls_x25519::{x25519_keypair, x25519_shared};
+use super::tls_chacha::{chacha20poly1305_seal, chacha20poly1305_open};
+use super::tls_gcm::{aes_gcm_seal, aes_gcm_open, hkdf_extract, hkdf_expand_label, derive_secret};
+use super::tls_trust::{scheme_alg, verify_chain, verify_signature};
+use super::tls_x509::{CertError, Certificate, SigAlg};
+use super::tls_session::{self, Resume, Session};
 
 const RT_CHANGE_CIPHER_SPEC: u8 = 20;
 const RT_ALERT:              u8 = 21;
@@ -28,15 +36,27 @@ const CS_RSA_AES128_SHA:          [u8; 2] = [0x00, 0x2F];
 const CS_RSA_AES128_SHA256:       [u8; 2] = [0x00, 0x3C];
 const CS_ECDHE_RSA_AES128_SHA256: [u8; 2] = [0xC0, 0x27];
 const CS_ECDHE_RSA_AES128_SHA:    [u8; 2] = [0xC0, 0x13];
+const CS_ECDHE_ECDSA_AES128_SHA256: [u8; 2] = [0xC0, 0x23];
+const CS_ECDHE_ECDSA_AES128_SHA:    [u8; 2] = [0xC0, 0x09];
 const HT_SERVER_KEY_EXCHANGE:     u8      = 12;
 
-const CS_AES128_GCM_SHA256:     [u8; 2] = [0x13, 0x01];
+const CS_AES128_GCM_SHA256:       [u8; 2] = [0x13, 0x01];
+const CS_AES256_GCM_SHA384:       [u8; 2] = [0x13, 0x02];
+const CS_CHACHA20_POLY1305_SHA256: [u8; 2] = [0x13, 0x03];
 const HT_ENCRYPTED_EXTENSIONS:  u8      = 8;
 const HT_CERTIFICATE_VERIFY:    u8      = 15;
+const HT_NEW_SESSION_TICKET:    u8      = 4;
 const TLS13:                    [u8; 2] = [0x03, 0x04];
 
+const GROUP_SECP256R1: u16 = 0x0017;
+const GROUP_X25519:    u16 = 0x001D;
+
+const MAX_TICKET_LEN: usize = 1024;
+
 const ALERT_CLOSE_NOTIFY:          u8 = 0;
 const ALERT_HANDSHAKE_FAILURE:     u8 = 40;
+const ALERT_ILLEGAL_PARAMETER:     u8 = 47;
+const ALERT_DECRYPT_ERROR:         u8 = 51;
 const ALERT_PROTOCOL_VERSION:      u8 = 70;
 const ALERT_INSUFFICIENT_SECURITY: u8 = 71;
 const ALERT_INTERNAL_ERROR:        u8 = 80;
@@ -86,7 +106,7 @@ fn make_handshake(htype: u8, body: &[u8], out: &mut [u8]) -> usize {
 fn alert_desc(code: u8) -> &'static str {
     match code {
         ALERT_CLOSE_NOTIFY           => "close_notify",
-        40                           => "handshake_failure",
+        ALERT_HANDSHAKE_FAILURE      => "handshake_failure",
         ALERT_PROTOCOL_VERSION       => "protocol_version",
         ALERT_INSUFFICIENT_SECURITY  => "insufficient_security",
         ALERT_INTERNAL_ERROR         => "internal_error",
@@ -97,14 +117,156 @@ fn alert_desc(code: u8) -> &'static str {
         44                           => "certificate_revoked",
         45                           => "certificate_expired",
         46                           => "certificate_unknown",
-        47                           => "illegal_parameter",
+        ALERT_ILLEGAL_PARAMETER      => "illegal_parameter",
         48                           => "unknown_ca",
         50                           => "decode_error",
-        51                           => "decrypt_error",
+        ALERT_DECRYPT_ERROR          => "decrypt_error",
         _                            => "unknown",
     }
 }
 
+fn u24(b: &[u8]) -> usize {
+    ((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize
+}
+
+fn parse_cert_list(body: &[u8], tls13: bool) -> Vec<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut p = if tls13 { 1 + *body.first().unwrap_or(&0) as usize } else { 0 };
+    if p + 3 > body.len() { return out; }
+    let end = (p + 3 + u24(&body[p..])).min(body.len());
+    p += 3;
+    while p + 3 <= end {
+        let len = u24(&body[p..]);
+        p += 3;
+        if p + len > end { break; }
+        out.push(body[p..p+len].to_vec());
+        p += len;
+        if tls13 {
+            if p + 2 > end { break; }
+            p += 2 + u16::from_be_bytes([body[p], body[p+1]]) as usize;
+        }
+    }
+    out
+}
+
+fn parse_signature(body: &[u8]) -> Option<(u16, &[u8])> {
+    if body.len() < 4 { return None; }
+    let scheme = u16::from_be_bytes([body[0], body[1]]);
+    let len    = u16::from_be_bytes([body[2], body[3]]) as usize;
+    body.get(4..4 + len).map(|sig| (scheme, sig))
+}
+
+fn signed_by_leaf(chain: &[Vec<u8>], scheme: u16, msg: &[u8], sig: &[u8], tls13: bool) -> bool {
+    let leaf = match chain.first().and_then(|c| Certificate::parse(c).ok()) {
+        Some(l) => l,
+        None    => return false,
+    };
+    match scheme_alg(scheme) {
+        Some(SigAlg::RsaPkcs1(_)) if tls13 => false,
+        Some(alg) => verify_signature(alg, &leaf.key, msg, sig),
+        None      => false,
+    }
+}
+
+fn early_secret(h: HashAlg, psk: Option<&[u8]>) -> Vec<u8> {
+    let zeros = [0u8; 48];
+    hkdf_extract(h, &zeros[..h.len()], psk.unwrap_or(&zeros[..h.len()]))
+}
+
+fn psk_binder(h: HashAlg, psk: &[u8], partial_hello: &[u8]) -> Vec<u8> {
+    let binder_key  = derive_secret(h, &early_secret(h, Some(psk)), b"res binder", &h.digest(b""));
+    let mut fin_key = [0u8; 48];
+    hkdf_expand_label(h, &binder_key, b"finished", b"", &mut fin_key[..h.len()]);
+    hmac(h, &fin_key[..h.len()], &h.digest(partial_hello))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TlsError {
+    Connect,
+    Handshake,
+    Cert(CertError),
+}
+
+impl TlsError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TlsError::Connect   => "connection failed",
+            TlsError::Handshake => "handshake failed",
+            TlsError::Cert(e)   => e.as_str(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Aead {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Aead {
+    fn from_suite(cs: u16) -> Option<Self> {
+        match cs {
+            0x1301 => Some(Aead::Aes128Gcm),
+            0x1302 => Some(Aead::Aes256Gcm),
+            0x1303 => Some(Aead::ChaCha20Poly1305),
+            _      => None,
+        }
+    }
+
+    fn key_len(&self) -> usize {
+        match self {
+            Aead::Aes128Gcm => 16,
+            _               => 32,
+        }
+    }
+
+    fn hash(&self) -> HashAlg {
+        match self {
+            Aead::Aes256Gcm => HashAlg::Sha384,
+            _               => HashAlg::Sha256,
+        }
+    }
+
+    fn seal(&self, key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8], out: &mut [u8]) -> usize {
+        match self {
+            Aead::ChaCha20Poly1305 => chacha20poly1305_seal(key, nonce, aad, plaintext, out),
+            _ => aes_gcm_seal(&key[..self.key_len()], nonce, aad, plaintext, out),
+        }
+    }
+
+    fn open(&self, key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8], out: &mut [u8]) -> Option<usize> {
+        match self {
+            Aead::ChaCha20Poly1305 => chacha20poly1305_open(key, nonce, aad, ciphertext, out),
+            _ => aes_gcm_open(&key[..self.key_len()], nonce, aad, ciphertext, out),
+        }
+    }
+}
+
+struct Transcript {
+    sha256: Sha256State,
+    sha384: Sha512State,
+    hash:   HashAlg,
+}
+
+impl Transcript {
+    fn new() -> Self {
+        Self { sha256: Sha256State::new(), sha384: Sha512State::new384(), hash: HashAlg::Sha256 }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        self.sha384.update(data);
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        match self.hash {
+            HashAlg::Sha384 => self.sha384.clone_finalize()[..48].to_vec(),
+            _               => self.sha256.clone_finalize().to_vec(),
+        }
+    }
+}
+
 fn compute_mac(mac_len: usize, key: &[u8; 32], data: &[u8]) -> [u8; 32] {
     let mut out = [0u8; 32];
     if mac_len == 20 {
@@ -137,15 +299,26 @@ pub struct TlsStream {
     hs_len:           usize,
     ecdh_priv:        [u8; 32],
     server_ec_pub:    [u8; 65],
+    x25519_priv:      [u8; 32],
+    server_x25519:    [u8; 32],
+    ecdhe_group:      u16,
     use_ecdhe:        bool,
     tls13:            bool,
-    tls13_client_key: [u8; 16],
-    tls13_server_key: [u8; 16],
+    tls13_aead:       Aead,
+    tls13_client_key: [u8; 32],
+    tls13_server_key: [u8; 32],
     tls13_client_iv:  [u8; 12],
     tls13_server_iv:  [u8; 12],
     tls13_client_seq: u64,
     tls13_server_seq: u64,
+    tls13_res_master: Vec<u8>,
     alpn_h2:          bool,
+    verify:           bool,
+    cert_error:       Option<CertError>,
+    host:             String,
+    session_id:       Vec<u8>,
+    resumed:          bool,
+    closed:           bool,
     pub rx_buf:       [u8; 8192],
     pub rx_len:       usize,
 }
@@ -220,6 +393,14 @@ impl TlsStream {
     }
 
     fn decrypt_record(&mut self, data_off: usize, data_len: usize) -> Option<usize> {
+        let plain_data = self.open_record12(data_off, data_len)?;
+        let copy = plain_data.len().min(self.rx_buf.len() - self.rx_len);
+        self.rx_buf[self.rx_len..self.rx_len + copy].copy_from_slice(&plain_data[..copy]);
+        self.rx_len += copy;
+        Some(copy)
+    }
+
+    fn open_record12(&mut self, data_off: usize, data_len: usize) -> Option<Vec<u8>> {
         let ml = self.mac_len;
         if data_len < 16 + ml { return None; }
         let rtype = self.raw[0];
@@ -245,56 +426,43 @@ impl TlsStream {
             return None;
         }
         self.server_seq += 1;
-        let copy = plain_data.len().min(self.rx_buf.len() - self.rx_len);
-        self.rx_buf[self.rx_len..self.rx_len + copy].copy_from_slice(&plain_data[..copy]);
-        self.rx_len += copy;
-        Some(copy)
+        Some(plain_data.to_vec())
     }
 
-    fn derive_keys(&mut self, premaster: &[u8; 48]) {
+    fn derive_keys(&mut self, premaster: &[u8]) {
         let mut seed = [0u8; 64];
         seed[..32].copy_from_slice(&self.client_random);
         seed[32..].copy_from_slice(&self.server_random);
         prf_sha256(premaster, b"master secret", &seed, &mut self.master_secret);
-        let mut seed2 = [0u8; 64];
-        seed2[..32].copy_from_slice(&self.server_random);
-        seed2[32..].copy_from_slice(&self.client_random);
-        let ml = self.mac_len;
-        let kb_need = ml * 2 + 32;
-        let mut kb = [0u8; 128];
-        prf_sha256(&self.master_secret, b"key expansion", &seed2, &mut kb[..kb_need]);
-        self.client_mac_key[..ml].copy_from_slice(&kb[..ml]);
-        self.server_mac_key[..ml].copy_from_slice(&kb[ml..ml * 2]);
-        self.client_key.copy_from_slice(&kb[ml * 2..ml * 2 + 16]);
-        self.server_key.copy_from_slice(&kb[ml * 2 + 16..ml * 2 + 32]);
+        self.expand_keys();
     }
 
-    fn derive_keys_ecdhe(&mut self, shared: &[u8; 32]) {
+    fn expand_keys(&mut self) {
         let mut seed = [0u8; 64];
-        seed[..32].copy_from_slice(&self.client_random);
-        seed[32..].copy_from_slice(&self.server_random);
-        prf_sha256(shared, b"master secret", &seed, &mut self.master_secret);
-        let mut seed2 = [0u8; 64];
-        seed2[..32].copy_from_slice(&self.server_random);
-        seed2[32..].copy_from_slice(&self.client_random);
+        seed[..32].copy_from_slice(&self.server_random);
+        seed[32..].copy_from_slice(&self.client_random);
         let ml = self.mac_len;
         let kb_need = ml * 2 + 32;
         let mut kb = [0u8; 128];
-        prf_sha256(&self.master_secret, b"key expansion", &seed2, &mut kb[..kb_need]);
+        prf_sha256(&self.master_secret, b"key expansion", &seed, &mut kb[..kb_need]);
         self.client_mac_key[..ml].copy_from_slice(&kb[..ml]);
         self.server_mac_key[..ml].copy_from_slice(&kb[ml..ml * 2]);
         self.client_key.copy_from_slice(&kb[ml * 2..ml * 2 + 16]);
         self.server_key.copy_from_slice(&kb[ml * 2 + 16..ml * 2 + 32]);
     }
 
-    fn finished_verify(master: &[u8; 48], label: &[u8], hs_hash: &[u8; 32]) -> [u8; 12] {
+    fn finished_verify(master: &[u8; 48], label: &[u8], hs_hash: &[u8]) -> [u8; 12] {
         let mut out = [0u8; 12];
         prf_sha256(master, label, hs_hash, &mut out);
         out
     }
 
-    pub fn connect(host: &str, ip: [u8; 4], port: u16) -> Option<Self> {
-        let tcp = TcpSocket::connect(ip, port)?;
+    pub fn connect(host: &str, ip: impl Into<IpAddr>, port: u16) -> Option<Self> {
+        Self::connect_verified(host, ip, port, true).ok()
+    }
+
+    pub fn connect_verified(host: &str, ip: impl Into<IpAddr>, port: u16, verify: bool) -> Result<Self, TlsError> {
+        let tcp = TcpSocket::connect(ip, port).ok_or(TlsError::Connect)?;
         let mut stream = TlsStream {
             tcp,
             client_random:    [0u8; 32],
@@ -315,20 +483,64 @@ impl TlsStream {
             hs_len:           0,
             ecdh_priv:        [0u8; 32],
             server_ec_pub:    [0u8; 65],
+            x25519_priv:      [0u8; 32],
+            server_x25519:    [0u8; 32],
+            ecdhe_group:      GROUP_SECP256R1,
             use_ecdhe:        false,
             tls13:            false,
-            tls13_client_key: [0u8; 16],
-            tls13_server_key: [0u8; 16],
+            tls13_aead:       Aead::Aes128Gcm,
+            tls13_client_key: [0u8; 32],
+            tls13_server_key: [0u8; 32],
             tls13_client_iv:  [0u8; 12],
             tls13_server_iv:  [0u8; 12],
             tls13_client_seq: 0,
             tls13_server_seq: 0,
+            tls13_res_master: Vec::new(),
             alpn_h2:          false,
+            verify,
+            cert_error:       None,
+            host:             String::from(host),
+            session_id:       Vec::new(),
+            resumed:          false,
+            closed:           false,
             rx_buf:           [0u8; 8192],
             rx_len:           0,
         };
-        stream.do_handshake(host)?;
-        Some(stream)
+        if stream.do_handshake(host).is_none() {
+            return Err(stream.cert_error.map_or(TlsError::Handshake, TlsError::Cert));
+        }
+        Ok(stream)
+    }
+
+    fn abort(&mut self, alert: u8) -> Option<()> {
+        if self.tls13 {
+            self.tls13_send(RT_ALERT, &[2, alert]);
+        } else {
+            self.send_record(RT_ALERT, &[2, alert]);
+        }
+        None
+    }
+
+    fn ecdhe_shared(&self) -> Option<[u8; 32]> {
+        match self.ecdhe_group {
+            GROUP_X25519 => x25519_shared(&self.x25519_priv, &self.server_x25519),
+            _            => ecdh_shared(&self.ecdh_priv, &self.server_ec_pub),
+        }
+    }
+
+    fn check_chain(&mut self, chain: &[Vec<u8>], host: &str) -> bool {
+        match verify_chain(chain, host) {
+            Ok(()) => {
+                crate::log!("tls: certificate chain verified ({} certs)", chain.len());
+                true
+            }
+            Err(e) => {
+                crate::log_err!("tls: certificate rejected: {}", e.as_str());
+                self.cert_error = Some(e);
+                self.abort(e.alert());
+                false
+            }
+        }
     }
 
     fn tls13_send(&mut self, content_type: u8, data: &[u8]) {
@@ -344,7 +556,7 @@ impl TlsStream {
         let enc_len = plain_len + 16;
         let aad: [u8; 5] = [RT_APP_DATA, 0x03, 0x03, (enc_len >> 8) as u8, enc_len as u8];
         let mut enc = [0u8; 17448];
-        let enc_n = aes128gcm_seal(&self.tls13_client_key, &nonce, &aad, &plain[..plain_len], &mut enc);
+        let enc_n = self.tls13_aead.seal(&self.tls13_client_key, &nonce, &aad, &plain[..plain_len], &mut enc);
         let mut rec = [0u8; 17460];
         rec[0] = RT_APP_DATA;
         rec[1..3].copy_from_slice(&[0x03, 0x03]);
@@ -355,7 +567,7 @@ impl TlsStream {
     }
 
     fn do_handshake(&mut self, host: &str) -> Option<()> {
-        let mut hs_hash = Sha256State::new();
+        let mut hs_hash = Transcript::new();
 
         let unix_time = (crate::vfs::procfs::uptime_ticks() / 18) as u32;
         self.client_random[0..4].copy_from_slice(&unix_time.to_be_bytes());
@@ -365,23 +577,47 @@ impl TlsStream {
         fill_random(&mut ecdh_rand);
         let (ecdh_priv, ecdh_pub) = ecdh_keypair(&ecdh_rand);
         self.ecdh_priv = ecdh_priv;
+        fill_random(&mut ecdh_rand);
+        let (x25519_priv, x25519_pub) = x25519_keypair(&ecdh_rand);
+        self.x25519_priv = x25519_priv;
+
+        let cached = tls_session::take(host, self.tcp.remote_port, self.verify);
+        let offered_psk = match cached.as_ref().map(|c| &c.resume) {
+            Some(Resume::Tls13 { suite, ticket, .. }) if ticket.len() <= MAX_TICKET_LEN => {
+                Aead::from_suite(*suite).map(|a| a.hash())
+            }
+            _ => None,
+        };
 
         let sni_bytes = host.as_bytes();
-        let mut ch_body = [0u8; 800];
+        let mut ch_body = [0u8; 2048];
         let mut p = 0usize;
 
         ch_body[p..p+2].copy_from_slice(&[0x03, 0x03]); p += 2;
         ch_body[p..p+32].copy_from_slice(&self.client_random); p += 32;
-        ch_body[p] = 32; p += 1;
-        fill_random(&mut ch_body[p..p+32]); p += 32;
+        match cached.as_ref().map(|c| &c.resume) {
+            Some(Resume::Tls12 { id, .. }) => {
+                ch_body[p] = id.len() as u8; p += 1;
+                ch_body[p..p+id.len()].copy_from_slice(id); p += id.len();
+                self.session_id = id.clone();
+            }
+            _ => {
+                ch_body[p] = 32; p += 1;
+                fill_random(&mut ch_body[p..p+32]); p += 32;
+            }
+        }
 
-        ch_body[p..p+2].copy_from_slice(&[0, 12]); p += 2;
-        ch_body[p..p+2].copy_from_slice(&CS_AES128_GCM_SHA256);       p += 2;
-        ch_body[p..p+2].copy_from_slice(&CS_ECDHE_RSA_AES128_SHA256); p += 2;
-        ch_body[p..p+2].copy_from_slice(&CS_ECDHE_RSA_AES128_SHA);    p += 2;
-        ch_body[p..p+2].copy_from_slice(&CS_RSA_AES128_SHA256);       p += 2;
-        ch_body[p..p+2].copy_from_slice(&CS_RSA_AES128_SHA);          p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0x00, 0xFF]);               p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0, 22]); p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_AES128_GCM_SHA256);         p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_CHACHA20_POLY1305_SHA256);  p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_AES256_GCM_SHA384);         p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_ECDHE_ECDSA_AES128_SHA256); p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_ECDHE_RSA_AES128_SHA256);   p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_ECDHE_ECDSA_AES128_SHA);    p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_ECDHE_RSA_AES128_SHA);      p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_RSA_AES128_SHA256);         p += 2;
+        ch_body[p..p+2].copy_from_slice(&CS_RSA_AES128_SHA);            p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0x00, 0xFF]);                 p += 2;
 
         ch_body[p] = 1; p += 1;
         ch_body[p] = 0; p += 1;
@@ -396,11 +632,14 @@ impl TlsStream {
         ch_body[p..p+2].copy_from_slice(&TLS12); p += 2;
 
         ch_body[p..p+2].copy_from_slice(&[0x00, 0x33]); p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0, 71]);       p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0, 69]);       p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0x00, 0x17]);  p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0, 65]);        p += 2;
-        ch_body[p..p+65].copy_from_slice(&ecdh_pub);      p += 65;
+        ch_body[p..p+2].copy_from_slice(&[0, 107]);      p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0, 105]);      p += 2;
+        ch_body[p..p+2].copy_from_slice(&GROUP_X25519.to_be_bytes());    p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0, 32]);                       p += 2;
+        ch_body[p..p+32].copy_from_slice(&x25519_pub);                   p += 32;
+        ch_body[p..p+2].copy_from_slice(&GROUP_SECP256R1.to_be_bytes()); p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0, 65]);                       p += 2;
+        ch_body[p..p+65].copy_from_slice(&ecdh_pub);                     p += 65;
 
         ch_body[p..p+2].copy_from_slice(&[0, 0]);                                                       p += 2;
         ch_body[p..p+2].copy_from_slice(&((sni_bytes.len() + 5) as u16).to_be_bytes()); p += 2;
@@ -410,19 +649,22 @@ impl TlsStream {
         ch_body[p..p+sni_bytes.len()].copy_from_slice(sni_bytes); p += sni_bytes.len();
 
         ch_body[p..p+2].copy_from_slice(&[0x00, 0x0D]); p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0, 14]);       p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0, 12]);       p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0, 18]);       p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0, 16]);       p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0x04, 0x03]); p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0x05, 0x03]); p += 2;
         ch_body[p..p+2].copy_from_slice(&[0x08, 0x04]); p += 2;
         ch_body[p..p+2].copy_from_slice(&[0x08, 0x05]); p += 2;
         ch_body[p..p+2].copy_from_slice(&[0x08, 0x06]); p += 2;
         ch_body[p..p+2].copy_from_slice(&[0x04, 0x01]); p += 2;
         ch_body[p..p+2].copy_from_slice(&[0x05, 0x01]); p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0x04, 0x03]); p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0x06, 0x01]); p += 2;
 
         ch_body[p..p+2].copy_from_slice(&[0x00, 0x0A]); p += 2;
+        ch_body[p..p+2].copy_from_slice(&[0, 6]);        p += 2;
         ch_body[p..p+2].copy_from_slice(&[0, 4]);        p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0, 2]);        p += 2;
-        ch_body[p..p+2].copy_from_slice(&[0x00, 0x17]);  p += 2;
+        ch_body[p..p+2].copy_from_slice(&GROUP_X25519.to_be_bytes());    p += 2;
+        ch_body[p..p+2].copy_from_slice(&GROUP_SECP256R1.to_be_bytes()); p += 2;
 
         ch_body[p..p+2].copy_from_slice(&[0x00, 0x0B]); p += 2;
         ch_body[p..p+2].copy_from_slice(&[0, 2]);        p += 2;
@@ -437,26 +679,54 @@ impl TlsStream {
         ch_body[p] = 8; p += 1;
         ch_body[p..p+8].copy_from_slice(b"http/1.1"); p += 8;
 
+        if let (Some(h), Some(Session { resume: Resume::Tls13 { ticket, age_add, .. }, .. })) = (offered_psk, &cached) {
+            let hl  = h.len();
+            let age = cached.as_ref().map_or(0, |c| c.age_ms()).wrapping_add(*age_add);
+            ch_body[p..p+2].copy_from_slice(&[0x00, 0x2D]); p += 2;
+            ch_body[p..p+2].copy_from_slice(&[0, 2]);        p += 2;
+            ch_body[p] = 1; p += 1;
+            ch_body[p] = 1; p += 1;
+
+            let id_len = 2 + ticket.len() + 4;
+            ch_body[p..p+2].copy_from_slice(&[0x00, 0x29]); p += 2;
+            ch_body[p..p+2].copy_from_slice(&((2 + id_len + 3 + hl) as u16).to_be_bytes()); p += 2;
+            ch_body[p..p+2].copy_from_slice(&(id_len as u16).to_be_bytes());                 p += 2;
+            ch_body[p..p+2].copy_from_slice(&(ticket.len() as u16).to_be_bytes());           p += 2;
+            ch_body[p..p+ticket.len()].copy_from_slice(ticket); p += ticket.len();
+            ch_body[p..p+4].copy_from_slice(&age.to_be_bytes()); p += 4;
+            ch_body[p..p+2].copy_from_slice(&((1 + hl) as u16).to_be_bytes()); p += 2;
+            ch_body[p] = hl as u8; p += 1;
+            p += hl;
+        }
+
         let ext_len = p - ext_start - 2;
         ch_body[ext_start..ext_start+2].copy_from_slice(&(ext_len as u16).to_be_bytes());
 
-        let mut hs_msg = [0u8; 900];
+        let mut hs_msg = [0u8; 2100];
         let hs_len = make_handshake(HT_CLIENT_HELLO, &ch_body[..p], &mut hs_msg);
+        if let (Some(h), Some(Session { resume: Resume::Tls13 { psk, .. }, .. })) = (offered_psk, &cached) {
+            let hl     = h.len();
+            let binder = psk_binder(h, psk, &hs_msg[..hs_len - 3 - hl]);
+            hs_msg[hs_len - hl..hs_len].copy_from_slice(&binder);
+        }
         hs_hash.update(&hs_msg[..hs_len]);
         self.send_record(RT_HANDSHAKE, &hs_msg[..hs_len]);
-        crate::log!("tls: ClientHello sent (SNI={}, TLS1.3+ECDHE+RSA)", host);
+        crate::log!("tls: ClientHello sent (SNI={}, TLS1.3+X25519/P-256)", host);
 
-        let mut server_cert      = [0u8; 8192];
-        let mut cert_len         = 0usize;
+        let mut chain: Vec<Vec<u8>> = Vec::new();
+        let mut ske_params       = Vec::new();
+        let mut ske_sig          = None;
+        let mut cv_ok            = false;
         let mut got_shd          = false;
+        let mut psk_accepted     = false;
         let mut tls13_agreed     = false;
         let mut handshake_done   = false;
-        let mut server_hs_key    = [0u8; 16];
+        let mut server_hs_key    = [0u8; 32];
         let mut server_hs_iv     = [0u8; 12];
-        let mut client_hs_key    = [0u8; 16];
+        let mut client_hs_key    = [0u8; 32];
         let mut client_hs_iv     = [0u8; 12];
-        let mut hs_secret        = [0u8; 32];
-        let mut hs_hash_after_sh = [0u8; 32];
+        let mut hs_secret        = Vec::new();
+        let mut hs_hash_after_sh = Vec::new();
 
         'recv: for _ in 0..100_000 {
             if CTRL_C.load(Ordering::SeqCst) { return None; }
@@ -509,6 +779,7 @@ impl TlsStream {
                                 let sid_len = hmsg[38] as usize;
                                 let cs_off  = 38 + 1 + sid_len;
                                 if cs_off + 2 <= hmsg.len() {
+                                    let offered_id = core::mem::replace(&mut self.session_id, hmsg[39..cs_off].to_vec());
                                     let cs = u16::from_be_bytes([hmsg[cs_off], hmsg[cs_off+1]]);
                                     self.selected_cipher = cs;
                                     crate::log!("tls: ServerHello cipher=0x{:04X}", cs);
@@ -529,12 +800,22 @@ impl TlsStream {
                                                 }
                                             }
                                             if etype == 0x0033 && elen >= 4 && ep + elen <= ext_end {
+                                                let group   = u16::from_be_bytes([hmsg[ep], hmsg[ep+1]]);
                                                 let key_len = u16::from_be_bytes([hmsg[ep+2], hmsg[ep+3]]) as usize;
-                                                if ep + 4 + key_len <= ext_end && key_len == 65 {
-                                                    self.server_ec_pub.copy_from_slice(&hmsg[ep+4..ep+4+65]);
-                                                    crate::log!("tls: ServerHello key_share received");
+                                                if ep + 4 + key_len <= ext_end {
+                                                    let key = &hmsg[ep+4..ep+4+key_len];
+                                                    match (group, key_len) {
+                                                        (GROUP_X25519, 32)    => self.server_x25519.copy_from_slice(key),
+                                                        (GROUP_SECP256R1, 65) => self.server_ec_pub.copy_from_slice(key),
+                                                        _ => {}
+                                                    }
+                                                    self.ecdhe_group = group;
+                                                    crate::log!("tls: ServerHello key_share group=0x{:04X}", group);
                                                 }
                                             }
+                                            if etype == 0x0029 && elen == 2 && ep + elen <= ext_end {
+                                                psk_accepted = offered_psk.is_some() && hmsg[ep] == 0 && hmsg[ep+1] == 0;
+                                            }
                                             if etype == 0x0010 && elen >= 3 && ep + elen <= ext_end {
                                                 let proto_len = hmsg[ep+2] as usize;
                                                 if ep + 3 + proto_len <= ext_end {
@@ -548,23 +829,50 @@ impl TlsStream {
                                         }
                                     }
                                     if !tls13_agreed {
-                                        self.mac_len  = match cs { 0x003C | 0xC027 => 32, _ => 20 };
-                                        self.use_ecdhe = cs == 0xC027 || cs == 0xC013;
+                                        self.mac_len  = match cs { 0x003C | 0xC027 | 0xC023 => 32, _ => 20 };
+                                        self.use_ecdhe = matches!(cs, 0xC027 | 0xC013 | 0xC023 | 0xC009);
+                                        if let Some(Resume::Tls12 { suite, master, .. }) = cached.as_ref().map(|c| &c.resume) {
+                                            if !offered_id.is_empty() && offered_id == self.session_id && *suite == cs {
+                                                self.master_secret = *master;
+                                                self.resumed       = true;
+                                            }
+                                        }
                                     }
                                     if tls13_agreed {
-                                        let shared = ecdh_shared(&self.ecdh_priv, &self.server_ec_pub)?;
-                                        let hash_after_sh = hs_hash.clone_finalize();
-                                        hs_hash_after_sh  = hash_after_sh;
-                                        let zeros32      = [0u8; 32];
-                                        let early_secret = hkdf_extract(&zeros32, &zeros32);
-                                        let derived_salt = derive_secret(&early_secret, b"derived", &sha256(b""));
-                                        hs_secret        = hkdf_extract(&derived_salt, &shared);
-                                        let c_hs = derive_secret(&hs_secret, b"c hs traffic", &hash_after_sh);
-                                        let s_hs = derive_secret(&hs_secret, b"s hs traffic", &hash_after_sh);
-                                        hkdf_expand_label(&c_hs, b"key", b"", &mut client_hs_key);
-                                        hkdf_expand_label(&c_hs, b"iv",  b"", &mut client_hs_iv);
-                                        hkdf_expand_label(&s_hs, b"key", b"", &mut server_hs_key);
-                                        hkdf_expand_label(&s_hs, b"iv",  b"", &mut server_hs_iv);
+                                        let aead = match Aead::from_suite(cs) {
+                                            Some(a) => a,
+                                            None    => return self.abort(ALERT_HANDSHAKE_FAILURE),
+                                        };
+                                        let shared = match self.ecdhe_shared() {
+                                            Some(s) => s,
+                                            None    => return self.abort(ALERT_HANDSHAKE_FAILURE),
+                                        };
+                                        self.tls13_aead = aead;
+                                        let h  = aead.hash();
+                                        let kl = aead.key_len();
+                                        let psk = match cached.as_ref().map(|c| &c.resume) {
+                                            Some(Resume::Tls13 { psk, .. }) if psk_accepted => Some(&psk[..]),
+                                            _ => None,
+                                        };
+                                        if psk.is_some() && offered_psk != Some(h) {
+                                            return self.abort(ALERT_ILLEGAL_PARAMETER);
+                                        }
+                                        self.resumed = psk.is_some();
+                                        hs_hash.hash = h;
+                                        let hash_after_sh = hs_hash.digest();
+                                        let early        = early_secret(h, psk);
+                                        let derived_salt = derive_secret(h, &early, b"derived", &h.digest(b""));
+                                        hs_secret        = hkdf_extract(h, &derived_salt, &shared);
+                                        let c_hs = derive_secret(h, &hs_secret, b"c hs traffic", &hash_after_sh);
+                                        let s_hs = derive_secret(h, &hs_secret, b"s hs traffic", &hash_after_sh);
+                                        hkdf_expand_label(h, &c_hs, b"key", b"", &mut client_hs_key[..kl]);
+                                        hkdf_expand_label(h, &c_hs, b"iv",  b"", &mut client_hs_iv);
+                                        hkdf_expand_label(h, &s_hs, b"key", b"", &mut server_hs_key[..kl]);
+                                        hkdf_expand_label(h, &s_hs, b"iv",  b"", &mut server_hs_iv);
+                                        hs_hash_after_sh = hash_after_sh;
+                                        self.tls13_client_key = client_hs_key;
+                                        self.tls13_client_iv  = client_hs_iv;
+                                        self.tls13_client_seq = 0;
                                         self.tls13 = true;
                                         crate::log!("tls: handshake keys derived");
                                     }
@@ -572,30 +880,30 @@ impl TlsStream {
                             }
                         }
                         HT_CERTIFICATE => {
-                            if hlen > 6 {
-                                let ctx_len  = hmsg[4] as usize;
-                                let list_off = 5 + ctx_len;
-                                if list_off + 6 <= hmsg.len() {
-                                    let first_cert_len = ((hmsg[list_off+3] as usize) << 16)
-                                                       | ((hmsg[list_off+4] as usize) << 8)
-                                                       |  (hmsg[list_off+5] as usize);
-                                    cert_len = first_cert_len.min(8192);
-                                    if list_off + 6 + cert_len <= hmsg.len() {
-                                        server_cert[..cert_len].copy_from_slice(&hmsg[list_off+6..list_off+6+cert_len]);
-                                    }
-                                    crate::log!("tls: Certificate received ({} bytes)", cert_len);
-                                }
+                            chain = parse_cert_list(&hmsg[4..], false);
+                            crate::log!("tls: Certificate received ({} certs)", chain.len());
+                            if self.verify && !self.check_chain(&chain, host) {
+                                return None;
                             }
                         }
                         HT_SERVER_HELLO_DONE => { got_shd = true; }
                         HT_SERVER_KEY_EXCHANGE => {
                             let body = &hmsg[4..];
-                            if body.len() >= 69 && body[0] == 3 {
+                            if body.len() >= 4 && body[0] == 3 {
                                 let curve  = u16::from_be_bytes([body[1], body[2]]);
                                 let pt_len = body[3] as usize;
-                                if curve == 0x0017 && pt_len == 65 {
-                                    self.server_ec_pub.copy_from_slice(&body[4..69]);
-                                    crate::log!("tls: ServerKeyExchange EC secp256r1");
+                                let end    = 4 + pt_len;
+                                let known  = matches!((curve, pt_len), (GROUP_X25519, 32) | (GROUP_SECP256R1, 65));
+                                if known && body.len() >= end {
+                                    if curve == GROUP_X25519 {
+                                        self.server_x25519.copy_from_slice(&body[4..end]);
+                                    } else {
+                                        self.server_ec_pub.copy_from_slice(&body[4..end]);
+                                    }
+                                    self.ecdhe_group = curve;
+                                    ske_params = body[..end].to_vec();
+                                    ske_sig    = parse_signature(&body[end..]).map(|(s, sig)| (s, sig.to_vec()));
+                                    crate::log!("tls: ServerKeyExchange group=0x{:04X}", curve);
                                 }
                             }
                         }
@@ -618,7 +926,7 @@ impl TlsStream {
                 let aad: [u8; 5] = [RT_APP_DATA, 0x03, 0x03,
                     (rec_len >> 8) as u8, rec_len as u8];
                 let mut plain = [0u8; 17408];
-                if let Some(n) = aes128gcm_open(&server_hs_key, &nonce, &aad, &payload, &mut plain) {
+                if let Some(n) = self.tls13_aead.open(&server_hs_key, &nonce, &aad, &payload, &mut plain) {
                     if n > 0 {
                         let content_type = plain[n-1];
                         if content_type == 21 {
@@ -642,6 +950,7 @@ impl TlsStream {
                                     break;
                                 }
                                 let hmsg = &self.hs_buf[hp..hp+4+hlen];
+                                let transcript = hs_hash.digest();
                                 hs_hash.update(hmsg);
                                 crate::log!("tls13: inner htype={} hlen={} hp={} hs_len={}", htype, hlen, hp, self.hs_len);
                                 match htype {
@@ -670,50 +979,68 @@ impl TlsStream {
                                         }
                                     }
                                     HT_CERTIFICATE => {
-                                        let ctx_len  = if hmsg.len() > 4 { hmsg[4] as usize } else { 0 };
-                                        let list_off = 5 + ctx_len;
-                                        if list_off + 6 <= hmsg.len() {
-                                            let first_cert_len = ((hmsg[list_off+3] as usize) << 16)
-                                                               | ((hmsg[list_off+4] as usize) << 8)
-                                                               |  (hmsg[list_off+5] as usize);
-                                            cert_len = first_cert_len.min(8192);
-                                            if list_off + 6 + cert_len <= hmsg.len() {
-                                                server_cert[..cert_len].copy_from_slice(
-                                                    &hmsg[list_off+6..list_off+6+cert_len]);
-                                            }
-                                            crate::log!("tls13: Certificate {} bytes", cert_len);
+                                        chain = parse_cert_list(&hmsg[4..], true);
+                                        crate::log!("tls13: Certificate ({} certs)", chain.len());
+                                        if self.verify && !self.check_chain(&chain, host) {
+                                            return None;
                                         }
                                     }
                                     HT_CERTIFICATE_VERIFY => {
                                         crate::log!("tls13: CertificateVerify");
+                                        if self.verify {
+                                            let mut signed = Vec::with_capacity(130);
+                                            signed.extend_from_slice(&[0x20; 64]);
+                                            signed.extend_from_slice(b"TLS 1.3, server CertificateVerify\0");
+                                            signed.extend_from_slice(&transcript);
+                                            cv_ok = parse_signature(&hmsg[4..])
+                                                .is_some_and(|(scheme, sig)| signed_by_leaf(&chain, scheme, &signed, sig, true));
+                                            if !cv_ok {
+                                                crate::log_err!("tls13: CertificateVerify signature invalid");
+                                                self.cert_error = Some(CertError::BadSignature);
+                                                return self.abort(ALERT_DECRYPT_ERROR);
+                                            }
+                                        }
                                     }
                                     HT_FINISHED => {
                                         crate::log!("tls13: ServerFinished received");
-                                        let hash_after_sf = hs_hash.clone_finalize();
-                                        let zeros32    = [0u8; 32];
-                                        let derived2   = derive_secret(&hs_secret, b"derived", &sha256(b""));
-                                        let app_secret = hkdf_extract(&derived2, &zeros32);
-                                        let c_app      = derive_secret(&app_secret, b"c ap traffic", &hash_after_sf);
-                                        let s_app      = derive_secret(&app_secret, b"s ap traffic", &hash_after_sf);
-                                        hkdf_expand_label(&s_app, b"key", b"", &mut self.tls13_server_key);
-                                        hkdf_expand_label(&s_app, b"iv",  b"", &mut self.tls13_server_iv);
+                                        let h    = hs_hash.hash;
+                                        let kl   = self.tls13_aead.key_len();
+                                        let s_hs = derive_secret(h, &hs_secret, b"s hs traffic", &hs_hash_after_sh);
+                                        let mut s_fin_key = [0u8; 48];
+                                        hkdf_expand_label(h, &s_hs, b"finished", b"", &mut s_fin_key[..h.len()]);
+                                        if hmac(h, &s_fin_key[..h.len()], &transcript)[..] != hmsg[4..] {
+                                            crate::log_err!("tls13: server Finished mismatch");
+                                            return self.abort(ALERT_DECRYPT_ERROR);
+                                        }
+                                        if self.verify && !cv_ok && !self.resumed {
+                                            crate::log_err!("tls13: server not authenticated");
+                                            self.cert_error = Some(if chain.is_empty() { CertError::NoCertificate } else { CertError::BadSignature });
+                                            return self.abort(ALERT_HANDSHAKE_FAILURE);
+                                        }
+                                        let hash_after_sf = hs_hash.digest();
+                                        let zeros      = [0u8; 48];
+                                        let derived2   = derive_secret(h, &hs_secret, b"derived", &h.digest(b""));
+                                        let app_secret = hkdf_extract(h, &derived2, &zeros[..h.len()]);
+                                        let c_app      = derive_secret(h, &app_secret, b"c ap traffic", &hash_after_sf);
+                                        let s_app      = derive_secret(h, &app_secret, b"s ap traffic", &hash_after_sf);
+                                        hkdf_expand_label(h, &s_app, b"key", b"", &mut self.tls13_server_key[..kl]);
+                                        hkdf_expand_label(h, &s_app, b"iv",  b"", &mut self.tls13_server_iv);
                                         self.tls13_server_seq = 0;
 
-                                        let hash_for_fin = hs_hash.clone_finalize();
-                                        let c_hs = derive_secret(&hs_secret, b"c hs traffic", &hs_hash_after_sh);
-                                        let mut fin_key = [0u8; 32];
-                                        hkdf_expand_label(&c_hs, b"finished", b"", &mut fin_key);
-                                        let verify = hmac_sha256(&fin_key, &hash_for_fin);
-                                        let mut fin_body = [0u8; 40];
+                                        let hash_for_fin = hs_hash.digest();
+                                        let c_hs = derive_secret(h, &hs_secret, b"c hs traffic", &hs_hash_after_sh);
+                                        let mut fin_key = [0u8; 48];
+                                        hkdf_expand_label(h, &c_hs, b"finished", b"", &mut fin_key[..h.len()]);
+                                        let verify = hmac(h, &fin_key[..h.len()], &hash_for_fin);
+                                        let mut fin_body = [0u8; 56];
                                         let fin_len = make_handshake(HT_FINISHED, &verify, &mut fin_body);
 
-                                        self.tls13_client_key = client_hs_key;
-                                        self.tls13_client_iv  = client_hs_iv;
-                                        self.tls13_client_seq = 0;
                                         self.tls13_send(RT_HANDSHAKE, &fin_body[..fin_len]);
+                                        hs_hash.update(&fin_body[..fin_len]);
+                                        self.tls13_res_master = derive_secret(h, &app_secret, b"res master", &hs_hash.digest());
 
-                                        hkdf_expand_label(&c_app, b"key", b"", &mut self.tls13_client_key);
-                                        hkdf_expand_label(&c_app, b"iv",  b"", &mut self.tls13_client_iv);
+                                        hkdf_expand_label(h, &c_app, b"key", b"", &mut self.tls13_client_key[..kl]);
+                                        hkdf_expand_label(h, &c_app, b"iv",  b"", &mut self.tls13_client_iv);
                                         self.tls13_client_seq = 0;
                                         crate::log!("tls13: ClientFinished sent");
                                         handshake_done = true;
@@ -737,7 +1064,7 @@ impl TlsStream {
 
             if rtype == RT_CHANGE_CIPHER_SPEC { self.consume(5+rec_len); continue; }
 
-            if rtype == RT_HANDSHAKE && !tls13_agreed && got_shd {
+            if rtype == RT_HANDSHAKE && !tls13_agreed && (got_shd || self.resumed) {
                 self.consume(5+rec_len);
                 break 'recv;
             }
@@ -751,31 +1078,63 @@ impl TlsStream {
                 return None;
             }
             self.cipher_active = true;
-            crate::log!("tls: handshake complete ({}{})", self.cipher_name(),
-                if self.alpn_h2 { " h2" } else { "" });
+            crate::log!("tls: handshake complete ({}{}{})", self.cipher_name(),
+                if self.alpn_h2 { " h2" } else { "" },
+                if self.resumed { " resumed" } else { "" });
+            return Some(());
+        }
+
+        if self.resumed {
+            self.expand_keys();
+            let server_vd = Self::finished_verify(&self.master_secret, b"server finished", &hs_hash.digest());
+            let fin = self.tls12_recv_finished(&server_vd)?;
+            hs_hash.update(&fin);
+            self.send_record(RT_CHANGE_CIPHER_SPEC, &[1]);
+            self.cipher_active = true;
+            let vd = Self::finished_verify(&self.master_secret, b"client finished", &hs_hash.digest());
+            let mut fin_body = [0u8; 20];
+            let fin_len      = make_handshake(HT_FINISHED, &vd, &mut fin_body);
+            self.send_record(RT_HANDSHAKE, &fin_body[..fin_len]);
+            crate::log!("tls: handshake complete ({} resumed)", self.cipher_name());
+            self.store_tls12_session();
             return Some(());
         }
 
-        if cert_len == 0 {
+        if chain.is_empty() {
             crate::log_err!("tls: no certificate received");
             return None;
         }
 
+        if self.verify && self.use_ecdhe {
+            let mut signed = Vec::with_capacity(64 + ske_params.len());
+            signed.extend_from_slice(&self.client_random);
+            signed.extend_from_slice(&self.server_random);
+            signed.extend_from_slice(&ske_params);
+            let ok = ske_sig.as_ref()
+                .is_some_and(|(scheme, sig): &(u16, Vec<u8>)| signed_by_leaf(&chain, *scheme, &signed, sig, false));
+            if !ok {
+                crate::log_err!("tls: ServerKeyExchange signature invalid");
+                self.cert_error = Some(CertError::BadSignature);
+                return self.abort(ALERT_DECRYPT_ERROR);
+            }
+        }
+
         if self.use_ecdhe {
-            let shared = match ecdh_shared(&self.ecdh_priv, &self.server_ec_pub) {
+            let shared = match self.ecdhe_shared() {
                 Some(s) => s,
                 None => { crate::log_err!("tls: ECDH failed"); return None; }
             };
-            self.derive_keys_ecdhe(&shared);
+            self.derive_keys(&shared);
+            let point: &[u8] = if self.ecdhe_group == GROUP_X25519 { &x25519_pub } else { &ecdh_pub };
             let mut cke_body = [0u8; 66];
-            cke_body[0] = 65;
-            cke_body[1..66].copy_from_slice(&ecdh_pub);
+            cke_body[0] = point.len() as u8;
+            cke_body[1..1 + point.len()].copy_from_slice(point);
             let mut hs_cke = [0u8; 80];
-            let hs_cke_len = make_handshake(HT_CLIENT_KEY_EXCHANGE, &cke_body, &mut hs_cke);
+            let hs_cke_len = make_handshake(HT_CLIENT_KEY_EXCHANGE, &cke_body[..1 + point.len()], &mut hs_cke);
             hs_hash.update(&hs_cke[..hs_cke_len]);
             self.send_record(RT_HANDSHAKE, &hs_cke[..hs_cke_len]);
         } else {
-            let rsa_key = match parse_rsa_public_key(&server_cert[..cert_len]) {
+            let rsa_key = match parse_rsa_public_key(&chain[0]) {
                 Some(k) => k,
                 None => { crate::log_err!("tls: RSA key parse failed"); return None; }
             };
@@ -798,33 +1157,39 @@ impl TlsStream {
         self.send_record(RT_CHANGE_CIPHER_SPEC, &[1]);
         self.cipher_active = true;
 
-        let hs_digest = hs_hash.clone_finalize();
+        let hs_digest = hs_hash.digest();
         let vd        = Self::finished_verify(&self.master_secret, b"client finished", &hs_digest);
         let mut fin_body = [0u8; 20];
         let fin_len      = make_handshake(HT_FINISHED, &vd, &mut fin_body);
         self.send_record(RT_HANDSHAKE, &fin_body[..fin_len]);
         crate::log!("tls: ClientFinished sent (TLS1.2)");
+        hs_hash.update(&fin_body[..fin_len]);
+        let server_vd = Self::finished_verify(&self.master_secret, b"server finished", &hs_hash.digest());
 
+        self.tls12_recv_finished(&server_vd)?;
+        crate::log!("tls: handshake complete ({})", self.cipher_name());
+        self.store_tls12_session();
+        Some(())
+    }
+
+    fn tls12_recv_finished(&mut self, expected: &[u8; 12]) -> Option<[u8; 16]> {
         let mut got_ccs = false;
-        let mut got_fin = false;
-        'server_fin: for _ in 0..50_000 {
+        for _ in 0..50_000 {
             if CTRL_C.load(Ordering::SeqCst) { return None; }
             if !self.tcp_fill(5, 20000) {
-                if self.tcp.peer_closed { break 'server_fin; }
+                if self.tcp.peer_closed { break; }
                 continue;
             }
             let rtype   = self.raw[0];
             let rec_len = u16::from_be_bytes([self.raw[3], self.raw[4]]) as usize;
             if !self.tcp_fill(5+rec_len, 2000) {
-                if self.tcp.peer_closed { break 'server_fin; }
+                if self.tcp.peer_closed { break; }
                 continue;
             }
             if rtype == RT_ALERT {
                 if got_ccs {
-                    if let Some(dec_len) = self.decrypt_record(5, rec_len) {
-                        let off = self.rx_len - dec_len;
-                        crate::log_err!("tls: Alert (enc) {}", alert_desc(self.rx_buf[off+1]));
-                        self.rx_len -= dec_len;
+                    if let Some(alert) = self.open_record12(5, rec_len) {
+                        crate::log_err!("tls: Alert (enc) {}", alert_desc(alert.get(1).copied().unwrap_or(0)));
                     }
                 } else {
                     crate::log_err!("tls: Alert {}", alert_desc(self.raw[6]));
@@ -834,21 +1199,78 @@ impl TlsStream {
             }
             if rtype == RT_CHANGE_CIPHER_SPEC { got_ccs = true; }
             if rtype == RT_HANDSHAKE && got_ccs {
-                if self.decrypt_record(5, rec_len).is_some() { got_fin = true; }
+                if let Some(fin) = self.open_record12(5, rec_len) {
+                    self.consume(5+rec_len);
+                    if fin.len() != 16 || fin[..4] != [HT_FINISHED, 0, 0, 12] || fin[4..] != expected[..] {
+                        crate::log_err!("tls: server Finished mismatch");
+                        self.abort(ALERT_DECRYPT_ERROR);
+                        return None;
+                    }
+                    return fin.try_into().ok();
+                }
             }
             self.consume(5+rec_len);
-            if got_fin { break 'server_fin; }
         }
+        crate::log_err!("tls: handshake failed");
+        None
+    }
 
-        if got_fin {
-            crate::log!("tls: handshake complete ({})", self.cipher_name());
-            Some(())
-        } else {
-            crate::log_err!("tls: handshake failed");
-            None
+    fn store_tls12_session(&self) {
+        if self.session_id.is_empty() { return; }
+        tls_session::store(&self.host, self.tcp.remote_port, self.verify, Resume::Tls12 {
+            suite:  self.selected_cipher,
+            id:     self.session_id.clone(),
+            master: self.master_secret,
+        });
+    }
+
+    fn on_post_handshake(&mut self, data: &[u8]) {
+        let mut p = 0;
+        while p + 4 <= data.len() {
+            let end = p + 4 + u24(&data[p+1..]);
+            if end > data.len() { break; }
+            if data[p] == HT_NEW_SESSION_TICKET {
+                self.on_session_ticket(&data[p+4..end]);
+            }
+            p = end;
         }
     }
 
+    fn on_session_ticket(&mut self, body: &[u8]) -> Option<()> {
+        if self.tls13_res_master.is_empty() { return None; }
+        let lifetime = u32::from_be_bytes(body.get(0..4)?.try_into().ok()?);
+        let age_add  = u32::from_be_bytes(body.get(4..8)?.try_into().ok()?);
+        let nlen     = *body.get(8)? as usize;
+        let nonce    = body.get(9..9 + nlen)?;
+        let tp       = 9 + nlen;
+        let tlen     = u16::from_be_bytes(body.get(tp..tp + 2)?.try_into().ok()?) as usize;
+        let ticket   = body.get(tp + 2..tp + 2 + tlen)?;
+        if lifetime == 0 || ticket.is_empty() || ticket.len() > MAX_TICKET_LEN { return None; }
+        let h = self.tls13_aead.hash();
+        let mut psk = alloc::vec![0u8; h.len()];
+        hkdf_expand_label(h, &self.tls13_res_master, b"resumption", nonce, &mut psk);
+        tls_session::store(&self.host, self.tcp.remote_port, self.verify, Resume::Tls13 {
+            suite:  self.selected_cipher,
+            psk,
+            ticket: ticket.to_vec(),
+            age_add,
+            lifetime,
+        });
+        crate::log!("tls13: session ticket stored (lifetime {}s)", lifetime);
+        Some(())
+    }
+
+    fn tls13_open_record(&mut self, rec_len: usize, plain: &mut [u8]) -> Option<(u8, usize)> {
+        let mut nonce = self.tls13_server_iv;
+        let seq_be    = self.tls13_server_seq.to_be_bytes();
+        for i in 0..8 { nonce[4+i] ^= seq_be[i]; }
+        self.tls13_server_seq += 1;
+        let aad: [u8; 5] = [RT_APP_DATA, 0x03, 0x03, (rec_len >> 8) as u8, rec_len as u8];
+        let n   = self.tls13_aead.open(&self.tls13_server_key, &nonce, &aad, &self.raw[5..5+rec_len], plain)?;
+        let end = plain[..n].iter().rposition(|&b| b != 0)?;
+        Some((plain[end], end))
+    }
+
     pub fn send(&mut self, data: &[u8]) -> bool {
         const CHUNK: usize = 1024;
         let mut off = 0;
@@ -882,27 +1304,21 @@ impl TlsStream {
             if self.tls13 {
                 crate::log!("tls13: recv_all rtype={} len={} seq={}", rtype, rec_len, self.tls13_server_seq);
                 if rtype == RT_APP_DATA {
-                    let payload = self.raw[5..5+rec_len].to_vec();
-                    let mut nonce = self.tls13_server_iv;
-                    let seq_be    = self.tls13_server_seq.to_be_bytes();
-                    for i in 0..8 { nonce[4+i] ^= seq_be[i]; }
-                    self.tls13_server_seq += 1;
-                    let aad: [u8; 5] = [RT_APP_DATA, 0x03, 0x03,
-                        (rec_len >> 8) as u8, rec_len as u8];
                     let mut plain = [0u8; 17408];
-                    if let Some(n) = aes128gcm_open(&self.tls13_server_key, &nonce, &aad, &payload, &mut plain) {
-                        if n > 0 {
-                            let content_type = plain[n-1];
-                            if content_type == 21 && n >= 3 {
+                    match self.tls13_open_record(rec_len, &mut plain) {
+                        Some((RT_ALERT, n)) => {
+                            if n >= 2 {
                                 crate::log!("tls13: recv_all ALERT level={} desc={}", plain[0], plain[1]);
                             }
-                            crate::log!("tls13: recv_all decrypted n={} ctype={}", n, content_type);
-                            if content_type == RT_ALERT { break; }
-                            let data_len = n - 1;
-                            let copy = data_len.min(self.rx_buf.len() - self.rx_len);
+                            break;
+                        }
+                        Some((RT_HANDSHAKE, n)) => self.on_post_handshake(&plain[..n]),
+                        Some((_, n)) => {
+                            let copy = n.min(self.rx_buf.len() - self.rx_len);
                             self.rx_buf[self.rx_len..self.rx_len+copy].copy_from_slice(&plain[..copy]);
                             self.rx_len += copy;
                         }
+                        None => {}
                     }
                 }
                 self.consume(5+rec_len);
@@ -926,8 +1342,43 @@ impl TlsStream {
         &self.rx_buf[..self.rx_len]
     }
 
+    pub fn recv_into(&mut self, out: &mut Vec<u8>, timeout: usize) -> Option<usize> {
+        if self.closed { return None; }
+        if !self.tcp_fill(5, timeout) {
+            return if self.tcp.peer_closed { None } else { Some(0) };
+        }
+        let rtype   = self.raw[0];
+        let rec_len = u16::from_be_bytes([self.raw[3], self.raw[4]]) as usize;
+        if !self.tcp_fill(5+rec_len, timeout.max(500)) {
+            return if self.tcp.peer_closed { None } else { Some(0) };
+        }
+        let got = match (self.tls13, rtype) {
+            (true, RT_APP_DATA) => {
+                let mut plain = [0u8; 17408];
+                match self.tls13_open_record(rec_len, &mut plain) {
+                    Some((RT_APP_DATA, n)) => { out.extend_from_slice(&plain[..n]); Some(n) }
+                    Some((RT_HANDSHAKE, n)) => { self.on_post_handshake(&plain[..n]); Some(0) }
+                    _ => None,
+                }
+            }
+            (false, RT_APP_DATA) => self.open_record12(5, rec_len).map(|d| {
+                out.extend_from_slice(&d);
+                d.len()
+            }),
+            (_, RT_ALERT) => None,
+            _ => Some(0),
+        };
+        self.consume(5+rec_len);
+        if got.is_none() { self.closed = true; }
+        got
+    }
+
     pub fn close(&mut self) {
-        self.send_record(RT_ALERT, &[1, ALERT_CLOSE_NOTIFY]);
+        if self.tls13 {
+            self.tls13_send(RT_ALERT, &[1, ALERT_CLOSE_NOTIFY]);
+        } else {
+            self.send_record(RT_ALERT, &[1, ALERT_CLOSE_NOTIFY]);
+        }
         self.tcp.close();
     }
 
@@ -936,7 +1387,7 @@ impl TlsStream {
     }
 
     pub fn is_closed(&self) -> bool {
-        self.tcp.peer_closed
+        self.closed || self.tcp.peer_closed
     }
 
     pub fn recv_chunk(&mut self, timeout: usize) -> &[u8] {
@@ -950,25 +1401,17 @@ impl TlsStream {
             if !self.tcp_fill(5+rec_len, 500) { break; }
             if self.tls13 {
                 if rtype == RT_APP_DATA {
-                    let payload = self.raw[5..5+rec_len].to_vec();
-                    let mut nonce = self.tls13_server_iv;
-                    let seq_be    = self.tls13_server_seq.to_be_bytes();
-                    for i in 0..8 { nonce[4+i] ^= seq_be[i]; }
-                    self.tls13_server_seq += 1;
-                    let aad: [u8; 5] = [RT_APP_DATA, 0x03, 0x03,
-                        (rec_len >> 8) as u8, rec_len as u8];
                     let mut plain = [0u8; 17408];
-                    if let Some(n) = aes128gcm_open(&self.tls13_server_key, &nonce, &aad, &payload, &mut plain) {
-                        if n > 0 {
-                            let content_type = plain[n-1];
-                            if content_type == RT_APP_DATA {
-                                let copy = (n-1).min(self.rx_buf.len());
-                                self.rx_buf[..copy].copy_from_slice(&plain[..copy]);
-                                self.rx_len = copy;
-                                self.consume(5+rec_len);
-                                break;
-                            }
+                    match self.tls13_open_record(rec_len, &mut plain) {
+                        Some((RT_APP_DATA, n)) => {
+                            let copy = n.min(self.rx_buf.len());
+                            self.rx_buf[..copy].copy_from_slice(&plain[..copy]);
+                            self.rx_len = copy;
+                            self.consume(5+rec_len);
+                            break;
                         }
+                        Some((RT_HANDSHAKE, n)) => self.on_post_handshake(&plain[..n]),
+                        _ => {}
                     }
                     self.consume(5+rec_len);
                 } else {
@@ -995,6 +1438,10 @@ impl TlsStream {
     pub fn cipher_name(&self) -> &'static str {
         match self.selected_cipher {
             0x1301 => "TLS13_AES_128_GCM_SHA256",
+            0x1302 => "TLS13_AES_256_GCM_SHA384",
+            0x1303 => "TLS13_CHACHA20_POLY1305_SHA256",
+            0xC023 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256",
+            0xC009 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
             0x003C => "TLS_RSA_WITH_AES_128_CBC_SHA256",
             0x002F => "TLS_RSA_WITH_AES_128_CBC_SHA",
             0xC027 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA256",
@@ -1007,6 +1454,10 @@ impl TlsStream {
 
     pub fn is_h2(&self) -> bool { self.alpn_h2 }
 
+    pub fn is_resumed(&self) -> bool { self.resumed }
+
+    pub fn is_verified(&self) -> bool { self.verify }
+
     pub fn try_recv_more(&mut self) -> bool {
         if !self.tcp_fill(5, 200) { return false; }
         let rtype   = self.raw[0];
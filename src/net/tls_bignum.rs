@@ -1,4 +1,4 @@
-pub const LIMBS: usize = 64;
+pub const LIMBS: usize = 128;
 pub type BigNum = [u32; LIMBS];
 
 pub fn bn_zero() -> BigNum { [0u32; LIMBS] }
@@ -57,43 +57,57 @@ pub fn bn_add(a: &BigNum, b: &BigNum) -> (BigNum, bool) {
     (r, carry != 0)
 }
 
+pub fn bn_limbs(a: &[u32]) -> usize {
+    a.iter().rposition(|&l| l != 0).map_or(0, |i| i + 1)
+}
+
+pub fn bn_bits(a: &BigNum) -> usize {
+    match bn_limbs(a) {
+        0 => 0,
+        l => l * 32 - a[l - 1].leading_zeros() as usize,
+    }
+}
+
 fn bn_mul_full(a: &BigNum, b: &BigNum) -> [u32; LIMBS * 2] {
     let mut r = [0u32; LIMBS * 2];
-    for i in 0..LIMBS {
+    let bl = bn_limbs(b);
+    for i in 0..bn_limbs(a) {
+        if a[i] == 0 { continue; }
         let mut carry: u64 = 0;
-        for j in 0..LIMBS {
+        for j in 0..bl {
             let prod = a[i] as u64 * b[j] as u64 + r[i+j] as u64 + carry;
             r[i+j] = prod as u32;
             carry = prod >> 32;
         }
-        if i + LIMBS < LIMBS * 2 {
-            r[i + LIMBS] = r[i + LIMBS].wrapping_add(carry as u32);
-        }
+        r[i + bl] = carry as u32;
     }
     r
 }
 
 fn bn_reduce_double(x: &[u32; LIMBS * 2], n: &BigNum) -> BigNum {
+    let nl = bn_limbs(n).max(1);
     let mut rem = [0u32; LIMBS + 1];
 
-    for i in (0..LIMBS * 2).rev() {
+    for i in (0..bn_limbs(x)).rev() {
         for bit in (0..32u32).rev() {
-            let next_bit = (x[i] >> bit) & 1;
-
-            let mut carry = next_bit;
-            for j in 0..=LIMBS {
-                let nc = rem[j] >> 31;
-                rem[j] = (rem[j] << 1) | carry;
+            let mut carry = (x[i] >> bit) & 1;
+            for limb in rem.iter_mut().take(nl + 1) {
+                let nc = *limb >> 31;
+                *limb = (*limb << 1) | carry;
                 carry = nc;
             }
 
-            let hi = rem[LIMBS];
-            let mut temp = [0u32; LIMBS];
-            temp.copy_from_slice(&rem[..LIMBS]);
-            if hi != 0 || bn_cmp(&temp, n) != core::cmp::Ordering::Less {
-                let sub = bn_sub(&temp, n);
-                rem[..LIMBS].copy_from_slice(&sub);
-                rem[LIMBS] = 0;
+            let ge = rem[nl] != 0 || (0..nl).rev()
+                .find(|&j| rem[j] != n[j])
+                .is_none_or(|j| rem[j] > n[j]);
+            if ge {
+                let mut borrow = 0u64;
+                for j in 0..nl {
+                    let d = (rem[j] as u64).wrapping_sub(n[j] as u64).wrapping_sub(borrow);
+                    rem[j] = d as u32;
+                    borrow = (d >> 63) & 1;
+                }
+                rem[nl] = rem[nl].wrapping_sub(borrow as u32);
             }
         }
     }
@@ -0,0 +1,227 @@
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]); s[d] ^= s[a]; s[d] = s[d].rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]); s[b] ^= s[c]; s[b] = s[b].rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]); s[d] ^= s[a]; s[d] = s[d].rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]); s[b] ^= s[c]; s[b] = s[b].rotate_left(7);
+}
+
+pub fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut init = [0u32; 16];
+    init[0] = 0x61707865;
+    init[1] = 0x3320646e;
+    init[2] = 0x79622d32;
+    init[3] = 0x6b206574;
+    for i in 0..8 {
+        init[4 + i] = u32::from_le_bytes(key[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+    init[12] = counter;
+    for i in 0..3 {
+        init[13 + i] = u32::from_le_bytes(nonce[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for i in 0..16 {
+        out[i * 4..i * 4 + 4].copy_from_slice(&s[i].wrapping_add(init[i]).to_le_bytes());
+    }
+    out
+}
+
+pub fn chacha20_xor(key: &[u8; 32], counter: u32, nonce: &[u8; 12], input: &[u8], out: &mut [u8]) {
+    for (n, (src, dst)) in input.chunks(64).zip(out.chunks_mut(64)).enumerate() {
+        let ks = chacha20_block(key, counter.wrapping_add(n as u32), nonce);
+        for i in 0..src.len() {
+            dst[i] = src[i] ^ ks[i];
+        }
+    }
+}
+
+pub struct Poly1305 {
+    r:   [u32; 5],
+    h:   [u32; 5],
+    pad: [u32; 4],
+    buf: [u8; 16],
+    len: usize,
+}
+
+impl Poly1305 {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let le = |i: usize| u32::from_le_bytes(key[i..i + 4].try_into().unwrap());
+        Self {
+            r: [
+                le(0)        & 0x3ffffff,
+                (le(3) >> 2) & 0x3ffff03,
+                (le(6) >> 4) & 0x3ffc0ff,
+                (le(9) >> 6) & 0x3f03fff,
+                (le(12) >> 8) & 0x00fffff,
+            ],
+            h:   [0; 5],
+            pad: [le(16), le(20), le(24), le(28)],
+            buf: [0; 16],
+            len: 0,
+        }
+    }
+
+    fn block(&mut self, m: &[u8; 16], hibit: u32) {
+        let le = |i: usize| u32::from_le_bytes(m[i..i + 4].try_into().unwrap());
+        let [r0, r1, r2, r3, r4] = self.r;
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let h = &mut self.h;
+
+        h[0] += le(0)         & 0x3ffffff;
+        h[1] += (le(3) >> 2)  & 0x3ffffff;
+        h[2] += (le(6) >> 4)  & 0x3ffffff;
+        h[3] += (le(9) >> 6)  & 0x3ffffff;
+        h[4] += (le(12) >> 8) | hibit;
+
+        let m = |a: u32, b: u32| a as u64 * b as u64;
+        let d0 = m(h[0], r0) + m(h[1], s4) + m(h[2], s3) + m(h[3], s2) + m(h[4], s1);
+        let mut d1 = m(h[0], r1) + m(h[1], r0) + m(h[2], s4) + m(h[3], s3) + m(h[4], s2);
+        let mut d2 = m(h[0], r2) + m(h[1], r1) + m(h[2], r0) + m(h[3], s4) + m(h[4], s3);
+        let mut d3 = m(h[0], r3) + m(h[1], r2) + m(h[2], r1) + m(h[3], r0) + m(h[4], s4);
+        let mut d4 = m(h[0], r4) + m(h[1], r3) + m(h[2], r2) + m(h[3], r1) + m(h[4], r0);
+
+        let mut c = d0 >> 26; h[0] = d0 as u32 & 0x3ffffff;
+        d1 += c; c = d1 >> 26; h[1] = d1 as u32 & 0x3ffffff;
+        d2 += c; c = d2 >> 26; h[2] = d2 as u32 & 0x3ffffff;
+        d3 += c; c = d3 >> 26; h[3] = d3 as u32 & 0x3ffffff;
+        d4 += c; c = d4 >> 26; h[4] = d4 as u32 & 0x3ffffff;
+        h[0] += c as u32 * 5;
+        let c = h[0] >> 26; h[0] &= 0x3ffffff;
+        h[1] += c;
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.len > 0 {
+            let take = (16 - self.len).min(data.len());
+            self.buf[self.len..self.len + take].copy_from_slice(&data[..take]);
+            self.len += take;
+            data = &data[take..];
+            if self.len < 16 {
+                return;
+            }
+            let b = self.buf;
+            self.block(&b, 1 << 24);
+            self.len = 0;
+        }
+        let mut chunks = data.chunks_exact(16);
+        for c in &mut chunks {
+            self.block(c.try_into().unwrap(), 1 << 24);
+        }
+        let rem = chunks.remainder();
+        self.buf[..rem.len()].copy_from_slice(rem);
+        self.len = rem.len();
+    }
+
+    pub fn finalize(mut self) -> [u8; 16] {
+        if self.len > 0 {
+            let mut b = [0u8; 16];
+            b[..self.len].copy_from_slice(&self.buf[..self.len]);
+            b[self.len] = 1;
+            self.block(&b, 0);
+        }
+
+        let h = &mut self.h;
+        let mut c = h[1] >> 26; h[1] &= 0x3ffffff;
+        h[2] += c; c = h[2] >> 26; h[2] &= 0x3ffffff;
+        h[3] += c; c = h[3] >> 26; h[3] &= 0x3ffffff;
+        h[4] += c; c = h[4] >> 26; h[4] &= 0x3ffffff;
+        h[0] += c * 5; c = h[0] >> 26; h[0] &= 0x3ffffff;
+        h[1] += c;
+
+        let mut g = [0u32; 5];
+        g[0] = h[0].wrapping_add(5); c = g[0] >> 26; g[0] &= 0x3ffffff;
+        g[1] = h[1].wrapping_add(c); c = g[1] >> 26; g[1] &= 0x3ffffff;
+        g[2] = h[2].wrapping_add(c); c = g[2] >> 26; g[2] &= 0x3ffffff;
+        g[3] = h[3].wrapping_add(c); c = g[3] >> 26; g[3] &= 0x3ffffff;
+        g[4] = h[4].wrapping_add(c).wrapping_sub(1 << 26);
+
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        let w0 = h[0] | (h[1] << 26);
+        let w1 = (h[1] >> 6) | (h[2] << 20);
+        let w2 = (h[2] >> 12) | (h[3] << 14);
+        let w3 = (h[3] >> 18) | (h[4] << 8);
+
+        let mut out = [0u8; 16];
+        let mut f = 0u64;
+        for (i, w) in [w0, w1, w2, w3].iter().enumerate() {
+            f = (f >> 32) + *w as u64 + self.pad[i] as u64;
+            out[i * 4..i * 4 + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        out
+    }
+}
+
+pub fn poly1305(key: &[u8; 32], msg: &[u8]) -> [u8; 16] {
+    let mut p = Poly1305::new(key);
+    p.update(msg);
+    p.finalize()
+}
+
+fn aead_tag(otk: &[u8; 32], aad: &[u8], ct: &[u8]) -> [u8; 16] {
+    let zeros = [0u8; 16];
+    let mut p = Poly1305::new(otk);
+    p.update(aad);
+    p.update(&zeros[..(16 - aad.len() % 16) % 16]);
+    p.update(ct);
+    p.update(&zeros[..(16 - ct.len() % 16) % 16]);
+    p.update(&(aad.len() as u64).to_le_bytes());
+    p.update(&(ct.len() as u64).to_le_bytes());
+    p.finalize()
+}
+
+fn one_time_key(key: &[u8; 32], nonce: &[u8; 12]) -> [u8; 32] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut otk = [0u8; 32];
+    otk.copy_from_slice(&block[..32]);
+    otk
+}
+
+pub fn chacha20poly1305_seal(
+    key:       &[u8; 32],
+    nonce:     &[u8; 12],
+    aad:       &[u8],
+    plaintext: &[u8],
+    out:       &mut [u8],
+) -> usize {
+    let ct_len = plaintext.len();
+    chacha20_xor(key, 1, nonce, plaintext, &mut out[..ct_len]);
+    let tag = aead_tag(&one_time_key(key, nonce), aad, &out[..ct_len]);
+    out[ct_len..ct_len + 16].copy_from_slice(&tag);
+    ct_len + 16
+}
+
+pub fn chacha20poly1305_open(
+    key:        &[u8; 32],
+    nonce:      &[u8; 12],
+    aad:        &[u8],
+    ciphertext: &[u8],
+    out:        &mut [u8],
+) -> Option<usize> {
+    if ciphertext.len() < 16 { return None; }
+    let ct_len  = ciphertext.len() - 16;
+    let ct_data = &ciphertext[..ct_len];
+    let tag     = aead_tag(&one_time_key(key, nonce), aad, ct_data);
+
+    let mut diff = 0u8;
+    for i in 0..16 { diff |= tag[i] ^ ciphertext[ct_len + i]; }
+    if diff != 0 { return None; }
+
+    chacha20_xor(key, 1, nonce, ct_data, &mut out[..ct_len]);
+    Some(ct_len)
+}
@@ -1,3 +1,6 @@
+extern crate alloc;
+use alloc::vec::Vec;
+
 const SBOX: [u8; 256] = [
     0x63,0x7c,0x77,0x7b,0xf2,0x6b,0x6f,0xc5,0x30,0x01,0x67,0x2b,0xfe,0xd7,0xab,0x76,
     0xca,0x82,0xc9,0x7d,0xfa,0x59,0x47,0xf0,0xad,0xd4,0xa2,0xaf,0x9c,0xa4,0x72,0xc0,
@@ -54,41 +57,40 @@ fn gf_mul(mut a: u8, mut b: u8) -> u8 {
     p
 }
 
-pub struct Aes128 {
-    rk: [u8; 176],
+pub struct Aes {
+    rk: [u8; 240],
+    nr: usize,
 }
 
-impl Aes128 {
-    pub fn new(key: &[u8; 16]) -> Self {
-        let mut rk = [0u8; 176];
-        rk[..16].copy_from_slice(key);
-        for i in 1..=10usize {
-            let base = i * 16;
-            let prev = base - 16;
-            let t0 = SBOX[rk[base-3] as usize]; 
-            let t1 = SBOX[rk[base-2] as usize]; 
-            let t2 = SBOX[rk[base-1] as usize]; 
-            let t3 = SBOX[rk[base-4] as usize]; 
-            rk[base]   = rk[prev]   ^ t0 ^ RCON[i-1];
-            rk[base+1] = rk[prev+1] ^ t1;
-            rk[base+2] = rk[prev+2] ^ t2;
-            rk[base+3] = rk[prev+3] ^ t3;
-            for j in 4..16 {
-                rk[base+j] = rk[prev+j] ^ rk[base+j-4];
+impl Aes {
+    pub fn new(key: &[u8]) -> Self {
+        let nk = key.len() / 4;
+        let nr = nk + 6;
+        let mut rk = [0u8; 240];
+        rk[..key.len()].copy_from_slice(key);
+        for i in nk..4 * (nr + 1) {
+            let mut t = [rk[i*4-4], rk[i*4-3], rk[i*4-2], rk[i*4-1]];
+            if i % nk == 0 {
+                t = [SBOX[t[1] as usize] ^ RCON[i/nk - 1], SBOX[t[2] as usize], SBOX[t[3] as usize], SBOX[t[0] as usize]];
+            } else if nk > 6 && i % nk == 4 {
+                t = [SBOX[t[0] as usize], SBOX[t[1] as usize], SBOX[t[2] as usize], SBOX[t[3] as usize]];
+            }
+            for j in 0..4 {
+                rk[i*4+j] = rk[(i-nk)*4+j] ^ t[j];
             }
         }
-        Self { rk }
+        Self { rk, nr }
     }
 
     pub fn encrypt_block(&self, b: &mut [u8; 16]) {
         for i in 0..16 { b[i] ^= self.rk[i]; }
-        for round in 1..=10usize {
+        for round in 1..=self.nr {
             for i in 0..16 { b[i] = SBOX[b[i] as usize]; }
             let tmp = b[1]; b[1] = b[5]; b[5] = b[9]; b[9] = b[13]; b[13] = tmp;
             let tmp = b[2]; b[2] = b[10]; b[10] = tmp;
             let tmp = b[6]; b[6] = b[14]; b[14] = tmp;
             let tmp = b[15]; b[15] = b[11]; b[11] = b[7]; b[7] = b[3]; b[3] = tmp;
-            if round < 10 {
+            if round < self.nr {
                 for col in 0..4usize {
                     let s0 = b[col*4]; let s1 = b[col*4+1];
                     let s2 = b[col*4+2]; let s3 = b[col*4+3];
@@ -104,8 +106,9 @@ impl Aes128 {
     }
 
     pub fn decrypt_block(&self, b: &mut [u8; 16]) {
-        for i in 0..16 { b[i] ^= self.rk[160 + i]; }
-        for round in (0..10usize).rev() {
+        let last = self.nr * 16;
+        for i in 0..16 { b[i] ^= self.rk[last + i]; }
+        for round in (0..self.nr).rev() {
             let tmp = b[13]; b[13] = b[9]; b[9] = b[5]; b[5] = b[1]; b[1] = tmp;
             let tmp = b[2]; b[2] = b[10]; b[10] = tmp;
             let tmp = b[6]; b[6] = b[14]; b[14] = tmp;
@@ -128,7 +131,7 @@ impl Aes128 {
 }
 
 pub fn cbc_encrypt(key: &[u8; 16], iv: &[u8; 16], plaintext: &[u8], out: &mut [u8]) -> usize {
-    let aes = Aes128::new(key);
+    let aes = Aes::new(key);
     let mut prev = *iv;
     let n = plaintext.len();
     for i in 0..(n / 16) {
@@ -143,7 +146,7 @@ pub fn cbc_encrypt(key: &[u8; 16], iv: &[u8; 16], plaintext: &[u8], out: &mut [u
 }
 
 pub fn cbc_decrypt(key: &[u8; 16], iv: &[u8; 16], ciphertext: &[u8], out: &mut [u8]) -> usize {
-    let aes = Aes128::new(key);
+    let aes = Aes::new(key);
     let mut prev = *iv;
     let n = ciphertext.len();
     for i in 0..(n / 16) {
@@ -349,6 +352,185 @@ pub fn sha256(data: &[u8]) -> [u8; 32] {
     s.finalize()
 }
 
+pub struct Sha512State {
+    h: [u64; 8],
+    buf: [u8; 128],
+    buf_len: usize,
+    total: u128,
+}
+
+const K512: [u64; 80] = [
+    0x428a2f98d728ae22,0x7137449123ef65cd,0xb5c0fbcfec4d3b2f,0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,0x59f111f1b605d019,0x923f82a4af194f9b,0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,0x12835b0145706fbe,0x243185be4ee4b28c,0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,0x80deb1fe3b1696b1,0x9bdc06a725c71235,0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,0xefbe4786384f25e3,0x0fc19dc68b8cd5b5,0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,0x4a7484aa6ea6e483,0x5cb0a9dcbd41fbd4,0x76f988da831153b5,
+    0x983e5152ee66dfab,0xa831c66d2db43210,0xb00327c898fb213f,0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,0xd5a79147930aa725,0x06ca6351e003826f,0x142929670a0e6e70,
+    0x27b70a8546d22ffc,0x2e1b21385c26c926,0x4d2c6dfc5ac42aed,0x53380d139d95b3df,
+    0x650a73548baf63de,0x766a0abb3c77b2a8,0x81c2c92e47edaee6,0x92722c851482353b,
+    0xa2bfe8a14cf10364,0xa81a664bbc423001,0xc24b8b70d0f89791,0xc76c51a30654be30,
+    0xd192e819d6ef5218,0xd69906245565a910,0xf40e35855771202a,0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,0x1e376c085141ab53,0x2748774cdf8eeb99,0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,0x4ed8aa4ae3418acb,0x5b9cca4f7763e373,0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,0x78a5636f43172f60,0x84c87814a1f0ab72,0x8cc702081a6439ec,
+    0x90befffa23631e28,0xa4506cebde82bde9,0xbef9a3f7b2c67915,0xc67178f2e372532b,
+    0xca273eceea26619c,0xd186b8c721c0c207,0xeada7dd6cde0eb1e,0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,0x0a637dc5a2c898a6,0x113f9804bef90dae,0x1b710b35131c471b,
+    0x28db77f523047d84,0x32caab7b40c72493,0x3c9ebe0a15c9bebc,0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,0x597f299cfc657e2a,0x5fcb6fab3ad6faec,0x6c44198c4a475817,
+];
+
+impl Sha512State {
+    pub fn new() -> Self {
+        Self::with_iv([
+            0x6a09e667f3bcc908,0xbb67ae8584caa73b,0x3c6ef372fe94f82b,0xa54ff53a5f1d36f1,
+            0x510e527fade682d1,0x9b05688c2b3e6c1f,0x1f83d9abfb41bd6b,0x5be0cd19137e2179,
+        ])
+    }
+
+    pub fn new384() -> Self {
+        Self::with_iv([
+            0xcbbb9d5dc1059ed8,0x629a292a367cd507,0x9159015a3070dd17,0x152fecd8f70e5939,
+            0x67332667ffc00b31,0x8eb44a8768581511,0xdb0c2e0d64f98fa7,0x47b5481dbefa4fa4,
+        ])
+    }
+
+    fn with_iv(h: [u64; 8]) -> Self {
+        Self { h, buf: [0u8; 128], buf_len: 0, total: 0 }
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u64; 80];
+        for (i, chunk) in self.buf.chunks_exact(8).enumerate() {
+            w[i] = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        for i in 16..80 {
+            let s0 = w[i-15].rotate_right(1)^w[i-15].rotate_right(8)^(w[i-15]>>7);
+            let s1 = w[i-2].rotate_right(19)^w[i-2].rotate_right(61)^(w[i-2]>>6);
+            w[i] = w[i-16].wrapping_add(s0).wrapping_add(w[i-7]).wrapping_add(s1);
+        }
+        let (mut a,mut b,mut c,mut d,mut e,mut f,mut g,mut h) =
+            (self.h[0],self.h[1],self.h[2],self.h[3],self.h[4],self.h[5],self.h[6],self.h[7]);
+        for i in 0..80 {
+            let s1_val = e.rotate_right(14)^e.rotate_right(18)^e.rotate_right(41);
+            let ch = (e&f)^((!e)&g);
+            let t1 = h.wrapping_add(s1_val).wrapping_add(ch).wrapping_add(K512[i]).wrapping_add(w[i]);
+            let s0_val = a.rotate_right(28)^a.rotate_right(34)^a.rotate_right(39);
+            let maj = (a&b)^(a&c)^(b&c);
+            let t2 = s0_val.wrapping_add(maj);
+            h=g; g=f; f=e; e=d.wrapping_add(t1);
+            d=c; c=b; b=a; a=t1.wrapping_add(t2);
+        }
+        self.h[0]=self.h[0].wrapping_add(a); self.h[1]=self.h[1].wrapping_add(b);
+        self.h[2]=self.h[2].wrapping_add(c); self.h[3]=self.h[3].wrapping_add(d);
+        self.h[4]=self.h[4].wrapping_add(e); self.h[5]=self.h[5].wrapping_add(f);
+        self.h[6]=self.h[6].wrapping_add(g); self.h[7]=self.h[7].wrapping_add(h);
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.buf[self.buf_len] = b;
+            self.buf_len += 1;
+            self.total += 8;
+            if self.buf_len == 128 {
+                self.compress();
+                self.buf_len = 0;
+            }
+        }
+    }
+
+    pub fn clone_finalize(&self) -> [u8; 64] {
+        let copy = Sha512State {
+            h:       self.h,
+            buf:     self.buf,
+            buf_len: self.buf_len,
+            total:   self.total,
+        };
+        copy.finalize()
+    }
+
+    pub fn finalize(mut self) -> [u8; 64] {
+        let bit_len = self.total;
+        self.update(&[0x80]);
+        while self.buf_len != 112 { self.update(&[0x00]); }
+        self.update(&bit_len.to_be_bytes());
+        let mut out = [0u8; 64];
+        for i in 0..8 { out[i*8..(i+1)*8].copy_from_slice(&self.h[i].to_be_bytes()); }
+        out
+    }
+}
+
+pub fn sha384(data: &[u8]) -> [u8; 48] {
+    let mut s = Sha512State::new384();
+    s.update(data);
+    let mut out = [0u8; 48];
+    out.copy_from_slice(&s.finalize()[..48]);
+    out
+}
+
+pub fn sha512(data: &[u8]) -> [u8; 64] {
+    let mut s = Sha512State::new();
+    s.update(data);
+    s.finalize()
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HashAlg {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlg {
+    pub fn len(&self) -> usize {
+        match self {
+            HashAlg::Sha1   => 20,
+            HashAlg::Sha256 => 32,
+            HashAlg::Sha384 => 48,
+            HashAlg::Sha512 => 64,
+        }
+    }
+
+    pub fn block_len(&self) -> usize {
+        match self {
+            HashAlg::Sha1 | HashAlg::Sha256   => 64,
+            HashAlg::Sha384 | HashAlg::Sha512 => 128,
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlg::Sha1   => sha1(data).to_vec(),
+            HashAlg::Sha256 => sha256(data).to_vec(),
+            HashAlg::Sha384 => sha384(data).to_vec(),
+            HashAlg::Sha512 => sha512(data).to_vec(),
+        }
+    }
+
+    pub fn digest_info(&self) -> &'static [u8] {
+        match self {
+            HashAlg::Sha1   => &[0x30,0x21,0x30,0x09,0x06,0x05,0x2b,0x0e,0x03,0x02,0x1a,0x05,0x00,0x04,0x14],
+            HashAlg::Sha256 => &[0x30,0x31,0x30,0x0d,0x06,0x09,0x60,0x86,0x48,0x01,0x65,0x03,0x04,0x02,0x01,0x05,0x00,0x04,0x20],
+            HashAlg::Sha384 => &[0x30,0x41,0x30,0x0d,0x06,0x09,0x60,0x86,0x48,0x01,0x65,0x03,0x04,0x02,0x02,0x05,0x00,0x04,0x30],
+            HashAlg::Sha512 => &[0x30,0x51,0x30,0x0d,0x06,0x09,0x60,0x86,0x48,0x01,0x65,0x03,0x04,0x02,0x03,0x05,0x00,0x04,0x40],
+        }
+    }
+}
+
+pub fn hmac(hash: HashAlg, key: &[u8], data: &[u8]) -> Vec<u8> {
+    let block = hash.block_len();
+    let mut k = if key.len() > block { hash.digest(key) } else { key.to_vec() };
+    k.resize(block, 0);
+    let mut inner: Vec<u8> = k.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(data);
+    let mut outer: Vec<u8> = k.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&hash.digest(&inner));
+    hash.digest(&outer)
+}
+
 pub fn hmac_sha1(key: &[u8], data: &[u8]) -> [u8; 20] {
     let mut k = [0u8; 64];
     if key.len() > 64 {
@@ -263,3 +263,116 @@ pub fn ecdh_shared(priv_key: &[u8; 32], pub_point: &[u8; 65]) -> Option<[u8; 32]
     fe_to_bytes(&sx, &mut out);
     Some(out)
 }
+
+const N: Fe = [
+    0xFC632551, 0xF3B9CAC2, 0xA7179E84, 0xBCE6FAAD,
+    0xFFFFFFFF, 0xFFFFFFFF, 0x00000000, 0xFFFFFFFF,
+];
+
+const B: Fe = [
+    0x27D2604B, 0x3BCE3C3E, 0xCC53B0F6, 0x651D06B0,
+    0x769886BC, 0xB3EBBD55, 0xAA3A93E7, 0x5AC635D8,
+];
+
+fn sc_sub_n(r: &mut Fe) {
+    let mut borrow: i64 = 0;
+    for i in 0..8 {
+        let d = r[i] as i64 - N[i] as i64 - borrow;
+        if d < 0 { r[i] = (d + (1i64 << 32)) as u32; borrow = 1; }
+        else      { r[i] = d as u32;                  borrow = 0; }
+    }
+}
+
+fn sc_reduce(mut r: Fe) -> Fe {
+    if fe_cmp(&r, &N) != core::cmp::Ordering::Less { sc_sub_n(&mut r); }
+    r
+}
+
+fn sc_mul(a: &Fe, b: &Fe) -> Fe {
+    let mut wide = [0u32; 16];
+    for i in 0..8 {
+        let mut carry: u64 = 0;
+        for j in 0..8 {
+            let t = a[i] as u64 * b[j] as u64 + wide[i+j] as u64 + carry;
+            wide[i+j] = t as u32;
+            carry     = t >> 32;
+        }
+        wide[i+8] = carry as u32;
+    }
+    let mut rem = fe_zero();
+    for i in (0..16).rev() {
+        for bit in (0..32).rev() {
+            let top = rem[7] >> 31;
+            for j in (1..8).rev() { rem[j] = (rem[j] << 1) | (rem[j-1] >> 31); }
+            rem[0] = (rem[0] << 1) | ((wide[i] >> bit) & 1);
+            if top != 0 || fe_cmp(&rem, &N) != core::cmp::Ordering::Less { sc_sub_n(&mut rem); }
+        }
+    }
+    rem
+}
+
+fn sc_inv(a: &Fe) -> Fe {
+    let mut exp = N;
+    exp[0] -= 2;
+    let mut r = fe_one();
+    for i in (0..256).rev() {
+        r = sc_mul(&r, &r);
+        if (exp[i / 32] >> (i % 32)) & 1 == 1 {
+            r = sc_mul(&r, a);
+        }
+    }
+    r
+}
+
+fn scalar_from_be(b: &[u8]) -> Option<Fe> {
+    let b = &b[b.iter().position(|&x| x != 0).unwrap_or(b.len())..];
+    if b.len() > 32 { return None; }
+    let mut buf = [0u8; 32];
+    buf[32 - b.len()..].copy_from_slice(b);
+    Some(fe_from_bytes(&buf))
+}
+
+fn on_curve(x: &Fe, y: &Fe) -> bool {
+    if fe_cmp(x, &P) != core::cmp::Ordering::Less || fe_cmp(y, &P) != core::cmp::Ordering::Less {
+        return false;
+    }
+    let rhs = fe_add(&fe_sub(&fe_mul(&fe_sqr(x), x), &fe_triple(x)), &B);
+    fe_sqr(y) == rhs
+}
+
+pub fn ecdsa_verify(pub_point: &[u8], digest: &[u8], r: &[u8], s: &[u8]) -> bool {
+    if pub_point.len() != 65 || pub_point[0] != 0x04 { return false; }
+    let qx = fe_from_bytes(&pub_point[1..33]);
+    let qy = fe_from_bytes(&pub_point[33..65]);
+    if !on_curve(&qx, &qy) { return false; }
+
+    let (r, s) = match (scalar_from_be(r), scalar_from_be(s)) {
+        (Some(r), Some(s)) => (r, s),
+        _ => return false,
+    };
+    for v in [&r, &s] {
+        if fe_is_zero(v) || fe_cmp(v, &N) != core::cmp::Ordering::Less { return false; }
+    }
+
+    let mut e_bytes = [0u8; 32];
+    let d = &digest[..digest.len().min(32)];
+    e_bytes[32 - d.len()..].copy_from_slice(d);
+    let e = sc_reduce(fe_from_bytes(&e_bytes));
+
+    let w  = sc_inv(&s);
+    let (mut u1b, mut u2b) = ([0u8; 32], [0u8; 32]);
+    fe_to_bytes(&sc_mul(&e, &w), &mut u1b);
+    fe_to_bytes(&sc_mul(&r, &w), &mut u2b);
+
+    let p1 = scalar_mul(&u1b, &GX, &GY);
+    let p2 = scalar_mul(&u2b, &qx, &qy);
+    let sum = match (p1, p2) {
+        (Some((x1, y1)), Some((x2, y2))) => jac_to_affine(&jac_add_affine(&jac_from_affine(&x1, &y1), &x2, &y2)),
+        (Some(p), None) | (None, Some(p)) => Some(p),
+        (None, None) => None,
+    };
+    match sum {
+        Some((x, _)) => sc_reduce(x) == r,
+        None => false,
+    }
+}
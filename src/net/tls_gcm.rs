@@ -1,4 +1,6 @@
-use super::tls_crypto::{Aes128, hmac_sha256, sha256};
+extern crate alloc;
+use alloc::vec::Vec;
+use super::tls_crypto::{hmac, Aes, HashAlg};
 
 fn ghash_mul(x: &mut [u64; 2], h: &[u64; 2]) {
     let mut z = [0u64; 2];
@@ -60,7 +62,7 @@ fn ghash(h: &[u64; 2], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
     out
 }
 
-fn ctr_block(aes: &Aes128, nonce_base: &[u8; 12], counter: u32) -> [u8; 16] {
+fn ctr_block(aes: &Aes, nonce_base: &[u8; 12], counter: u32) -> [u8; 16] {
     let mut block = [0u8; 16];
     block[..12].copy_from_slice(nonce_base);
     block[12..16].copy_from_slice(&counter.to_be_bytes());
@@ -68,14 +70,14 @@ fn ctr_block(aes: &Aes128, nonce_base: &[u8; 12], counter: u32) -> [u8; 16] {
     block
 }
 
-pub fn aes128gcm_seal(
-    key:        &[u8; 16],
+pub fn aes_gcm_seal(
+    key:        &[u8],
     nonce:      &[u8; 12],
     aad:        &[u8],
     plaintext:  &[u8],
     out:        &mut [u8],
 ) -> usize {
-    let aes = Aes128::new(key);
+    let aes = Aes::new(key);
 
     let mut h_block = [0u8; 16];
     aes.encrypt_block(&mut h_block);
@@ -99,9 +101,7 @@ pub fn aes128gcm_seal(
         }
     }
 
-    let mut ciphertext_only = [0u8; 16384];
-    ciphertext_only[..ct_len].copy_from_slice(&out[..ct_len]);
-    let tag_raw = ghash(&h, aad, &ciphertext_only[..ct_len]);
+    let tag_raw = ghash(&h, aad, &out[..ct_len]);
 
     let mut tag = [0u8; 16];
     for i in 0..16 { tag[i] = tag_raw[i] ^ ectr0[i]; }
@@ -110,8 +110,8 @@ pub fn aes128gcm_seal(
     ct_len + 16
 }
 
-pub fn aes128gcm_open(
-    key:        &[u8; 16],
+pub fn aes_gcm_open(
+    key:        &[u8],
     nonce:      &[u8; 12],
     aad:        &[u8],
     ciphertext: &[u8],
@@ -122,7 +122,7 @@ pub fn aes128gcm_open(
     let ct_data = &ciphertext[..ct_len];
     let tag_in  = &ciphertext[ct_len..];
 
-    let aes = Aes128::new(key);
+    let aes = Aes::new(key);
 
     let mut h_block = [0u8; 16];
     aes.encrypt_block(&mut h_block);
@@ -155,55 +155,39 @@ pub fn aes128gcm_open(
     Some(ct_len)
 }
 
-pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
-    hmac_sha256(salt, ikm)
+pub fn hkdf_extract(hash: HashAlg, salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+    hmac(hash, salt, ikm)
 }
 
-pub fn hkdf_expand(prk: &[u8; 32], info: &[u8], out: &mut [u8]) {
-    let mut t     = [0u8; 32];
+pub fn hkdf_expand(hash: HashAlg, prk: &[u8], info: &[u8], out: &mut [u8]) {
+    let mut t     = Vec::new();
     let mut pos   = 0usize;
     let mut count = 1u8;
     while pos < out.len() {
-        let mut input = [0u8; 256];
-        let mut ilen  = 0usize;
-        if count > 1 {
-            input[..32].copy_from_slice(&t);
-            ilen += 32;
-        }
-        input[ilen..ilen + info.len()].copy_from_slice(info);
-        ilen += info.len();
-        input[ilen] = count;
-        ilen += 1;
-        t = hmac_sha256(prk, &input[..ilen]);
-        let take = (out.len() - pos).min(32);
+        let mut input = t;
+        input.extend_from_slice(info);
+        input.push(count);
+        t = hmac(hash, prk, &input);
+        let take = (out.len() - pos).min(t.len());
         out[pos..pos + take].copy_from_slice(&t[..take]);
         pos   += take;
         count += 1;
     }
 }
 
-pub fn hkdf_expand_label(prk: &[u8; 32], label: &[u8], context: &[u8], out: &mut [u8]) {
-    let len    = out.len() as u16;
-    let label_full = {
-        let mut buf = [0u8; 64];
-        buf[..6].copy_from_slice(b"tls13 ");
-        buf[6..6 + label.len()].copy_from_slice(label);
-        (buf, 6 + label.len())
-    };
-
-    let mut info = [0u8; 256];
-    let mut ip   = 0usize;
-    info[ip..ip+2].copy_from_slice(&len.to_be_bytes()); ip += 2;
-    info[ip] = label_full.1 as u8; ip += 1;
-    info[ip..ip + label_full.1].copy_from_slice(&label_full.0[..label_full.1]); ip += label_full.1;
-    info[ip] = context.len() as u8; ip += 1;
-    info[ip..ip + context.len()].copy_from_slice(context); ip += context.len();
-
-    hkdf_expand(prk, &info[..ip], out);
+pub fn hkdf_expand_label(hash: HashAlg, prk: &[u8], label: &[u8], context: &[u8], out: &mut [u8]) {
+    let mut info = Vec::with_capacity(10 + label.len() + context.len());
+    info.extend_from_slice(&(out.len() as u16).to_be_bytes());
+    info.push((6 + label.len()) as u8);
+    info.extend_from_slice(b"tls13 ");
+    info.extend_from_slice(label);
+    info.push(context.len() as u8);
+    info.extend_from_slice(context);
+    hkdf_expand(hash, prk, &info, out);
 }
 
-pub fn derive_secret(secret: &[u8; 32], label: &[u8], transcript_hash: &[u8; 32]) -> [u8; 32] {
-    let mut out = [0u8; 32];
-    hkdf_expand_label(secret, label, transcript_hash, &mut out);
+pub fn derive_secret(hash: HashAlg, secret: &[u8], label: &[u8], transcript_hash: &[u8]) -> Vec<u8> {
+    let mut out = alloc::vec![0u8; hash.len()];
+    hkdf_expand_label(hash, secret, label, transcript_hash, &mut out);
     out
 }
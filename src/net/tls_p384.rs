@@ -0,0 +1,305 @@
+use core::cmp::Ordering;
+
+type Fe = [u64; 6];
+
+const P: Fe = [
+    0x00000000FFFFFFFF, 0xFFFFFFFF00000000, 0xFFFFFFFFFFFFFFFE,
+    0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF,
+];
+
+const N: Fe = [
+    0xECEC196ACCC52973, 0x581A0DB248B0A77A, 0xC7634D81F4372DDF,
+    0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF,
+];
+
+const B: Fe = [
+    0x2A85C8EDD3EC2AEF, 0xC656398D8A2ED19D, 0x0314088F5013875A,
+    0x181D9C6EFE814112, 0x988E056BE3F82D19, 0xB3312FA7E23EE7E4,
+];
+
+const GX: Fe = [
+    0x3A545E3872760AB7, 0x5502F25DBF55296C, 0x59F741E082542A38,
+    0x6E1D3B628BA79B98, 0x8EB1C71EF320AD74, 0xAA87CA22BE8B0537,
+];
+
+const GY: Fe = [
+    0x7A431D7C90EA0E5F, 0x0A60B1CE1D7E819D, 0xE9DA3113B5F0B8C0,
+    0xF8F41DBD289A147C, 0x5D9E98BF9292DC29, 0x3617DE4A96262C6F,
+];
+
+const ONE: Fe = [1, 0, 0, 0, 0, 0];
+
+fn fe_is_zero(a: &Fe) -> bool { a.iter().all(|&x| x == 0) }
+
+fn fe_from_bytes(b: &[u8]) -> Fe {
+    let mut r = [0u64; 6];
+    for i in 0..6 {
+        r[5 - i] = u64::from_be_bytes(b[8 * i..8 * i + 8].try_into().unwrap());
+    }
+    r
+}
+
+fn fe_cmp(a: &Fe, b: &Fe) -> Ordering {
+    for i in (0..6).rev() {
+        match a[i].cmp(&b[i]) {
+            Ordering::Equal => continue,
+            o => return o,
+        }
+    }
+    Ordering::Equal
+}
+
+fn raw_sub(a: &Fe, b: &Fe) -> (Fe, bool) {
+    let mut r = [0u64; 6];
+    let mut borrow = false;
+    for i in 0..6 {
+        let (d, b1) = a[i].overflowing_sub(b[i]);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        r[i]   = d;
+        borrow = b1 || b2;
+    }
+    (r, borrow)
+}
+
+fn raw_add(a: &Fe, b: &Fe) -> (Fe, bool) {
+    let mut r = [0u64; 6];
+    let mut carry = false;
+    for i in 0..6 {
+        let (s, c1) = a[i].overflowing_add(b[i]);
+        let (s, c2) = s.overflowing_add(carry as u64);
+        r[i]  = s;
+        carry = c1 || c2;
+    }
+    (r, carry)
+}
+
+struct Modulus {
+    m:     Fe,
+    m0inv: u64,
+    r2:    Fe,
+}
+
+impl Modulus {
+    fn new(m: Fe) -> Self {
+        let mut inv = 1u64;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(m[0].wrapping_mul(inv)));
+        }
+        let mut md = Self { m, m0inv: inv.wrapping_neg(), r2: ONE };
+        let mut r = ONE;
+        for _ in 0..768 {
+            r = md.add(&r, &r);
+        }
+        md.r2 = r;
+        md
+    }
+
+    fn add(&self, a: &Fe, b: &Fe) -> Fe {
+        let (s, carry) = raw_add(a, b);
+        if carry || fe_cmp(&s, &self.m) != Ordering::Less {
+            raw_sub(&s, &self.m).0
+        } else {
+            s
+        }
+    }
+
+    fn sub(&self, a: &Fe, b: &Fe) -> Fe {
+        let (d, borrow) = raw_sub(a, b);
+        if borrow { raw_add(&d, &self.m).0 } else { d }
+    }
+
+    fn mul(&self, a: &Fe, b: &Fe) -> Fe {
+        let mut t = [0u64; 8];
+        for bi in b.iter() {
+            let mut c = 0u128;
+            for j in 0..6 {
+                let v = t[j] as u128 + a[j] as u128 * *bi as u128 + c;
+                t[j] = v as u64;
+                c    = v >> 64;
+            }
+            let v = t[6] as u128 + c;
+            t[6] = v as u64;
+            t[7] = (v >> 64) as u64;
+
+            let mm = t[0].wrapping_mul(self.m0inv);
+            let mut c = (t[0] as u128 + mm as u128 * self.m[0] as u128) >> 64;
+            for j in 1..6 {
+                let v = t[j] as u128 + mm as u128 * self.m[j] as u128 + c;
+                t[j - 1] = v as u64;
+                c        = v >> 64;
+            }
+            let v = t[6] as u128 + c;
+            t[5] = v as u64;
+            t[6] = t[7] + (v >> 64) as u64;
+        }
+        let r: Fe = t[..6].try_into().unwrap();
+        if t[6] != 0 || fe_cmp(&r, &self.m) != Ordering::Less {
+            raw_sub(&r, &self.m).0
+        } else {
+            r
+        }
+    }
+
+    fn to_mont(&self, a: &Fe) -> Fe { self.mul(a, &self.r2) }
+    fn demont(&self, a: &Fe) -> Fe { self.mul(a, &ONE) }
+
+    fn pow(&self, a: &Fe, exp: &Fe) -> Fe {
+        let mut r = self.to_mont(&ONE);
+        for i in (0..384).rev() {
+            r = self.mul(&r, &r);
+            if (exp[i / 64] >> (i % 64)) & 1 == 1 {
+                r = self.mul(&r, a);
+            }
+        }
+        r
+    }
+
+    fn inv(&self, a: &Fe) -> Fe {
+        let exp = raw_sub(&self.m, &[2, 0, 0, 0, 0, 0]).0;
+        self.pow(a, &exp)
+    }
+}
+
+struct JacPoint { x: Fe, y: Fe, z: Fe }
+
+struct Curve {
+    f:   Modulus,
+    one: Fe,
+}
+
+impl Curve {
+    fn new() -> Self {
+        let f   = Modulus::new(P);
+        let one = f.to_mont(&ONE);
+        Self { f, one }
+    }
+
+    fn inf(&self) -> JacPoint { JacPoint { x: self.one, y: self.one, z: [0; 6] } }
+
+    fn dbl(&self, a: &Fe) -> Fe { self.f.add(a, a) }
+
+    fn double(&self, p: &JacPoint) -> JacPoint {
+        let f = &self.f;
+        if fe_is_zero(&p.z) || fe_is_zero(&p.y) { return self.inf(); }
+        let z2 = f.mul(&p.z, &p.z);
+        let m  = f.mul(&f.sub(&p.x, &z2), &f.add(&p.x, &z2));
+        let t  = f.add(&self.dbl(&m), &m);
+        let y2 = f.mul(&p.y, &p.y);
+        let u  = self.dbl(&self.dbl(&f.mul(&p.x, &y2)));
+        let x3 = f.sub(&f.mul(&t, &t), &self.dbl(&u));
+        let z3 = self.dbl(&f.mul(&p.y, &p.z));
+        let y4 = f.mul(&y2, &y2);
+        let y3 = f.sub(&f.mul(&t, &f.sub(&u, &x3)), &self.dbl(&self.dbl(&self.dbl(&y4))));
+        JacPoint { x: x3, y: y3, z: z3 }
+    }
+
+    fn add_affine(&self, p: &JacPoint, ax: &Fe, ay: &Fe) -> JacPoint {
+        let f = &self.f;
+        if fe_is_zero(&p.z) { return JacPoint { x: *ax, y: *ay, z: self.one }; }
+        let z2 = f.mul(&p.z, &p.z);
+        let z3 = f.mul(&z2, &p.z);
+        let h  = f.sub(&f.mul(ax, &z2), &p.x);
+        let r  = f.sub(&f.mul(ay, &z3), &p.y);
+        if fe_is_zero(&h) {
+            return if fe_is_zero(&r) {
+                self.double(&JacPoint { x: *ax, y: *ay, z: self.one })
+            } else {
+                self.inf()
+            };
+        }
+        let h2   = f.mul(&h, &h);
+        let h3   = f.mul(&h2, &h);
+        let x1h2 = f.mul(&p.x, &h2);
+        let x3   = f.sub(&f.sub(&f.mul(&r, &r), &h3), &self.dbl(&x1h2));
+        let y3   = f.sub(&f.mul(&r, &f.sub(&x1h2, &x3)), &f.mul(&p.y, &h3));
+        let z3   = f.mul(&p.z, &h);
+        JacPoint { x: x3, y: y3, z: z3 }
+    }
+
+    fn to_affine(&self, p: &JacPoint) -> Option<(Fe, Fe)> {
+        let f = &self.f;
+        if fe_is_zero(&p.z) { return None; }
+        let zi  = f.inv(&p.z);
+        let zi2 = f.mul(&zi, &zi);
+        let zi3 = f.mul(&zi2, &zi);
+        Some((f.mul(&p.x, &zi2), f.mul(&p.y, &zi3)))
+    }
+
+    fn scalar_mul(&self, k: &Fe, px: &Fe, py: &Fe) -> JacPoint {
+        let mut r = self.inf();
+        for i in (0..384).rev() {
+            r = self.double(&r);
+            if (k[i / 64] >> (i % 64)) & 1 == 1 {
+                r = self.add_affine(&r, px, py);
+            }
+        }
+        r
+    }
+
+    fn on_curve(&self, x: &Fe, y: &Fe) -> bool {
+        let f   = &self.f;
+        let x3  = f.mul(&f.mul(x, x), x);
+        let rhs = f.add(&f.sub(&x3, &f.add(&self.dbl(x), x)), &f.to_mont(&B));
+        f.mul(y, y) == rhs
+    }
+}
+
+fn scalar_from_be(b: &[u8]) -> Option<Fe> {
+    let b = &b[b.iter().position(|&x| x != 0).unwrap_or(b.len())..];
+    if b.len() > 48 { return None; }
+    let mut buf = [0u8; 48];
+    buf[48 - b.len()..].copy_from_slice(b);
+    Some(fe_from_bytes(&buf))
+}
+
+pub fn ecdsa_p384_verify(pub_point: &[u8], digest: &[u8], r: &[u8], s: &[u8]) -> bool {
+    if pub_point.len() != 97 || pub_point[0] != 0x04 { return false; }
+    let (qx, qy) = (fe_from_bytes(&pub_point[1..49]), fe_from_bytes(&pub_point[49..97]));
+    if fe_cmp(&qx, &P) != Ordering::Less || fe_cmp(&qy, &P) != Ordering::Less { return false; }
+
+    let (r, s) = match (scalar_from_be(r), scalar_from_be(s)) {
+        (Some(r), Some(s)) => (r, s),
+        _ => return false,
+    };
+    for v in [&r, &s] {
+        if fe_is_zero(v) || fe_cmp(v, &N) != Ordering::Less { return false; }
+    }
+
+    let curve = Curve::new();
+    let f     = &curve.f;
+    let (qx, qy) = (f.to_mont(&qx), f.to_mont(&qy));
+    if !curve.on_curve(&qx, &qy) { return false; }
+
+    let mut e_bytes = [0u8; 48];
+    let d = &digest[..digest.len().min(48)];
+    e_bytes[48 - d.len()..].copy_from_slice(d);
+    let mut e = fe_from_bytes(&e_bytes);
+    if fe_cmp(&e, &N) != Ordering::Less {
+        e = raw_sub(&e, &N).0;
+    }
+
+    let n  = Modulus::new(N);
+    let w  = n.inv(&n.to_mont(&s));
+    let u1 = n.demont(&n.mul(&n.to_mont(&e), &w));
+    let u2 = n.demont(&n.mul(&n.to_mont(&r), &w));
+
+    let p1 = curve.scalar_mul(&u1, &f.to_mont(&GX), &f.to_mont(&GY));
+    let p2 = curve.scalar_mul(&u2, &qx, &qy);
+    let sum = match (curve.to_affine(&p1), curve.to_affine(&p2)) {
+        (Some((x1, y1)), Some((x2, y2))) => {
+            curve.to_affine(&curve.add_affine(&JacPoint { x: x1, y: y1, z: curve.one }, &x2, &y2))
+        }
+        (Some(p), None) | (None, Some(p)) => Some(p),
+        (None, None) => None,
+    };
+    match sum {
+        Some((x, _)) => {
+            let mut x = f.demont(&x);
+            if fe_cmp(&x, &N) != Ordering::Less {
+                x = raw_sub(&x, &N).0;
+            }
+            x == r
+        }
+        None => false,
+    }
+}
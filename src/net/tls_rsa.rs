@@ -1,4 +1,8 @@
-use super::tls_bignum::{bn_from_bytes_be, bn_to_bytes_be, bn_powmod_u32, BigNum};
+extern crate alloc;
+use alloc::vec;
+use alloc::vec::Vec;
+use super::tls_bignum::{bn_bits, bn_cmp, bn_from_bytes_be, bn_to_bytes_be, bn_powmod_u32, BigNum, LIMBS};
+use super::tls_crypto::HashAlg;
 
 pub struct RsaPublicKey {
     pub n:     BigNum,
@@ -32,28 +36,34 @@ pub fn parse_rsa_public_key(cert: &[u8]) -> Option<RsaPublicKey> {
     if p >= cert.len() || cert[p] != 0x00 { return None; }
     p += 1;
 
-    if p >= cert.len() || cert[p] != 0x30 { return None; }
+    rsa_key_from_der(&cert[p..])
+}
+
+pub fn rsa_key_from_der(der: &[u8]) -> Option<RsaPublicKey> {
+    let mut p = 0;
+    if p >= der.len() || der[p] != 0x30 { return None; }
     p += 1;
-    read_len(cert, &mut p)?;
+    read_len(der, &mut p)?;
 
-    if p >= cert.len() || cert[p] != 0x02 { return None; }
+    if p >= der.len() || der[p] != 0x02 { return None; }
     p += 1;
-    let n_len = read_len(cert, &mut p)?;
-    if p + n_len > cert.len() { return None; }
-    let (n_bytes, n_actual) = if cert[p] == 0x00 {
-        (&cert[p+1..p+n_len], n_len - 1)
+    let n_len = read_len(der, &mut p)?;
+    if n_len == 0 || p + n_len > der.len() { return None; }
+    let (n_bytes, n_actual) = if der[p] == 0x00 {
+        (&der[p+1..p+n_len], n_len - 1)
     } else {
-        (&cert[p..p+n_len], n_len)
+        (&der[p..p+n_len], n_len)
     };
+    if n_actual > LIMBS * 4 { return None; }
     let n = bn_from_bytes_be(n_bytes);
     p += n_len;
 
-    if p >= cert.len() || cert[p] != 0x02 { return None; }
+    if p >= der.len() || der[p] != 0x02 { return None; }
     p += 1;
-    let e_len = read_len(cert, &mut p)?;
-    if p + e_len > cert.len() { return None; }
+    let e_len = read_len(der, &mut p)?;
+    if e_len == 0 || e_len > 4 || p + e_len > der.len() { return None; }
     let mut e = 0u32;
-    for i in 0..e_len.min(4) { e = (e << 8) | cert[p + i] as u32; }
+    for i in 0..e_len { e = (e << 8) | der[p + i] as u32; }
 
     Some(RsaPublicKey { n, e, n_len: n_actual })
 }
@@ -88,3 +98,69 @@ pub fn rsa_pkcs1_encrypt(key: &RsaPublicKey, data: &[u8], out: &mut [u8; 256]) -
     
     k
 }
+
+fn rsa_public_op(key: &RsaPublicKey, sig: &[u8]) -> Option<Vec<u8>> {
+    if sig.len() != key.n_len || key.e == 0 { return None; }
+    let s = bn_from_bytes_be(sig);
+    if bn_cmp(&s, &key.n) != core::cmp::Ordering::Less { return None; }
+    let m = bn_powmod_u32(&s, key.e, &key.n);
+    let mut em = vec![0u8; key.n_len];
+    bn_to_bytes_be(&m, &mut em);
+    Some(em)
+}
+
+pub fn rsa_pkcs1_verify(key: &RsaPublicKey, hash: HashAlg, msg: &[u8], sig: &[u8]) -> bool {
+    let em = match rsa_public_op(key, sig) { Some(e) => e, None => return false };
+    let prefix = hash.digest_info();
+    let digest = hash.digest(msg);
+    let t_len = prefix.len() + digest.len();
+    if em.len() < t_len + 11 { return false; }
+    let ps_end = em.len() - t_len - 1;
+    em[0] == 0x00 && em[1] == 0x01
+        && em[2..ps_end].iter().all(|&b| b == 0xFF)
+        && em[ps_end] == 0x00
+        && em[ps_end + 1..ps_end + 1 + prefix.len()] == *prefix
+        && em[em.len() - digest.len()..] == digest[..]
+}
+
+fn mgf1(hash: HashAlg, seed: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + 64);
+    let mut counter = 0u32;
+    while out.len() < len {
+        let mut buf = seed.to_vec();
+        buf.extend_from_slice(&counter.to_be_bytes());
+        out.extend_from_slice(&hash.digest(&buf));
+        counter += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+pub fn rsa_pss_verify(key: &RsaPublicKey, hash: HashAlg, msg: &[u8], sig: &[u8]) -> bool {
+    let em_full = match rsa_public_op(key, sig) { Some(e) => e, None => return false };
+    let em_bits = bn_bits(&key.n) - 1;
+    let em_len  = em_bits.div_ceil(8);
+    let em = &em_full[em_full.len() - em_len..];
+    if em_full[..em_full.len() - em_len].iter().any(|&b| b != 0) { return false; }
+
+    let h_len = hash.len();
+    let s_len = h_len;
+    if em_len < h_len + s_len + 2 || em[em_len - 1] != 0xBC { return false; }
+
+    let db_len = em_len - h_len - 1;
+    let h = &em[db_len..em_len - 1];
+    let top_mask = 0xFFu8 >> (8 * em_len - em_bits);
+    if em[0] & !top_mask != 0 { return false; }
+
+    let mut db = mgf1(hash, h, db_len);
+    for (d, m) in db.iter_mut().zip(&em[..db_len]) { *d ^= m; }
+    db[0] &= top_mask;
+
+    let pad = db_len - s_len - 1;
+    if db[..pad].iter().any(|&b| b != 0) || db[pad] != 0x01 { return false; }
+
+    let mut m = vec![0u8; 8];
+    m.extend_from_slice(&hash.digest(msg));
+    m.extend_from_slice(&db[pad + 1..]);
+    hash.digest(&m) == h
+}
@@ -0,0 +1,67 @@
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+const MAX_SESSIONS:       usize = 16;
+const MAX_TICKET_SECS:    u64   = 7 * 24 * 3600;
+const TLS12_SESSION_SECS: u64   = 3600;
+
+pub enum Resume {
+    Tls13 { suite: u16, psk: Vec<u8>, ticket: Vec<u8>, age_add: u32, lifetime: u32 },
+    Tls12 { suite: u16, id: Vec<u8>, master: [u8; 48] },
+}
+
+pub struct Session {
+    key:        String,
+    verified:   bool,
+    issued:     u64,
+    pub resume: Resume,
+}
+
+impl Session {
+    fn lifetime_ticks(&self) -> u64 {
+        let secs = match &self.resume {
+            Resume::Tls13 { lifetime, .. } => (*lifetime as u64).min(MAX_TICKET_SECS),
+            Resume::Tls12 { .. }           => TLS12_SESSION_SECS,
+        };
+        secs * crate::interrupts::PIT_HZ as u64
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        now.saturating_sub(self.issued) >= self.lifetime_ticks()
+    }
+
+    pub fn age_ms(&self) -> u32 {
+        let ticks = ticks().saturating_sub(self.issued);
+        (ticks * 1000 / crate::interrupts::PIT_HZ as u64) as u32
+    }
+}
+
+static CACHE: Mutex<Vec<Session>> = Mutex::new(Vec::new());
+
+fn ticks() -> u64 {
+    crate::vfs::procfs::uptime_ticks()
+}
+
+fn cache_key(host: &str, port: u16) -> String {
+    format!("{}:{}", host.to_ascii_lowercase(), port)
+}
+
+pub fn take(host: &str, port: u16, verify: bool) -> Option<Session> {
+    let key = cache_key(host, port);
+    let now = ticks();
+    let mut cache = CACHE.lock();
+    cache.retain(|s| !s.expired(now));
+    let idx = cache.iter().rposition(|s| s.key == key && (s.verified || !verify))?;
+    Some(cache.remove(idx))
+}
+
+pub fn store(host: &str, port: u16, verified: bool, resume: Resume) {
+    let mut cache = CACHE.lock();
+    if cache.len() >= MAX_SESSIONS {
+        cache.remove(0);
+    }
+    cache.push(Session { key: cache_key(host, port), verified, issued: ticks(), resume });
+}
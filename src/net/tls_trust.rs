@@ -0,0 +1,229 @@
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use super::tls_crypto::HashAlg;
+use super::tls_ecdh::ecdsa_verify;
+use super::tls_p384::ecdsa_p384_verify;
+use super::tls_rsa::{rsa_pkcs1_verify, rsa_pss_verify};
+use super::tls_x509::{read_integer, CertError, Certificate, Der, PublicKey, SigAlg};
+
+const BUNDLE:    &str  = include_str!("../../assets/ca-bundle.pem");
+const CERTS_DIR: &str  = "/etc/ssl/certs";
+const MAX_DEPTH: usize = 8;
+const MAX_FILES: usize = 64;
+
+static ANCHORS: Mutex<Option<Arc<Vec<Certificate>>>> = Mutex::new(None);
+
+fn b64_val(c: u8) -> Option<u32> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as u32),
+        b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+        b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+fn b64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for c in text.bytes() {
+        if c == b'=' { break; }
+        if c.is_ascii_whitespace() { continue; }
+        acc = (acc << 6) | b64_val(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+pub fn pem_decode(text: &str) -> Vec<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+        rest = &rest[start + 27..];
+        let end = match rest.find("-----END CERTIFICATE-----") {
+            Some(e) => e,
+            None    => break,
+        };
+        if let Some(der) = b64_decode(&rest[..end]) {
+            out.push(der);
+        }
+        rest = &rest[end..];
+    }
+    out
+}
+
+fn add_pem(store: &mut Vec<Certificate>, text: &str) {
+    for der in pem_decode(text) {
+        if let Ok(cert) = Certificate::parse(&der) {
+            if !store.iter().any(|c| c.tbs == cert.tbs) {
+                store.push(cert);
+            }
+        }
+    }
+}
+
+fn scan_certs_dir() -> Vec<String> {
+    crate::vfs::core::with_vfs(|vfs| -> Vec<String> {
+        let mut files = Vec::new();
+        let dir_id = match vfs.resolve_path(0, CERTS_DIR) {
+            Ok(id) => id,
+            Err(_) => return files,
+        };
+        let mut entries = [crate::vfs::types::DirEntry::empty(); MAX_FILES];
+        let count = vfs.readdir(dir_id, &mut entries).unwrap_or(0);
+        for e in entries.iter().take(count) {
+            let name = e.get_name();
+            if name.ends_with(".pem") || name.ends_with(".crt") {
+                files.push(format!("{}/{}", CERTS_DIR, name));
+            }
+        }
+        files
+    })
+}
+
+fn load() -> Vec<Certificate> {
+    let mut store = Vec::new();
+    add_pem(&mut store, BUNDLE);
+    let builtin = store.len();
+    for path in scan_certs_dir() {
+        if let Some(text) = crate::vfs_read::read_file(&path).and_then(|d| String::from_utf8(d).ok()) {
+            add_pem(&mut store, &text);
+        }
+    }
+    crate::log!("tls: {} trust anchors ({} builtin, {} from {})",
+        store.len(), builtin, store.len() - builtin, CERTS_DIR);
+    store
+}
+
+pub fn anchors() -> Arc<Vec<Certificate>> {
+    let mut guard = ANCHORS.lock();
+    if let Some(a) = guard.as_ref() {
+        return a.clone();
+    }
+    let a = Arc::new(load());
+    *guard = Some(a.clone());
+    a
+}
+
+pub fn scheme_alg(scheme: u16) -> Option<SigAlg> {
+    Some(match scheme {
+        0x0401 => SigAlg::RsaPkcs1(HashAlg::Sha256),
+        0x0501 => SigAlg::RsaPkcs1(HashAlg::Sha384),
+        0x0601 => SigAlg::RsaPkcs1(HashAlg::Sha512),
+        0x0403 => SigAlg::Ecdsa(HashAlg::Sha256),
+        0x0503 => SigAlg::Ecdsa(HashAlg::Sha384),
+        0x0804 => SigAlg::RsaPss(HashAlg::Sha256),
+        0x0805 => SigAlg::RsaPss(HashAlg::Sha384),
+        0x0806 => SigAlg::RsaPss(HashAlg::Sha512),
+        _ => return None,
+    })
+}
+
+pub fn verify_signature(alg: SigAlg, key: &PublicKey, msg: &[u8], sig: &[u8]) -> bool {
+    if alg.hash() == HashAlg::Sha1 {
+        return false;
+    }
+    match (alg, key) {
+        (SigAlg::RsaPkcs1(h), PublicKey::Rsa(k)) => rsa_pkcs1_verify(k, h, msg, sig),
+        (SigAlg::RsaPss(h),   PublicKey::Rsa(k)) => rsa_pss_verify(k, h, msg, sig),
+        (SigAlg::Ecdsa(h), PublicKey::EcP256(q) | PublicKey::EcP384(q)) => {
+            let mut outer = Der::new(sig);
+            let mut seq = match outer.read(0x30) {
+                Some(s) => Der::new(s),
+                None    => return false,
+            };
+            let (r, s) = match (read_integer(&mut seq), read_integer(&mut seq)) {
+                (Some(r), Some(s)) => (r, s),
+                _ => return false,
+            };
+            let digest = h.digest(msg);
+            match key {
+                PublicKey::EcP384(_) => ecdsa_p384_verify(q, &digest, &r, &s),
+                _                    => ecdsa_verify(q, &digest, &r, &s),
+            }
+        }
+        _ => false,
+    }
+}
+
+fn check_time(cert: &Certificate, now: u64) -> Result<(), CertError> {
+    if now == 0 {
+        return Err(CertError::NoClock);
+    }
+    if now < cert.not_before {
+        return Err(CertError::NotYetValid);
+    }
+    if now > cert.not_after {
+        return Err(CertError::Expired);
+    }
+    Ok(())
+}
+
+fn check_issuer(child: &Certificate, issuer: &Certificate, depth: usize, anchor: bool, now: u64) -> Result<(), CertError> {
+    if !anchor && (!issuer.is_ca || !issuer.can_sign) {
+        return Err(CertError::NotCa);
+    }
+    if !anchor && !issuer.server_auth {
+        return Err(CertError::NotServerAuth);
+    }
+    if issuer.path_len.is_some_and(|p| depth > p as usize) {
+        return Err(CertError::PathTooLong);
+    }
+    if child.sig_alg.hash() == HashAlg::Sha1 {
+        return Err(CertError::WeakSignature);
+    }
+    if !verify_signature(child.sig_alg, &issuer.key, &child.tbs, &child.signature) {
+        return Err(CertError::BadSignature);
+    }
+    check_time(issuer, now)
+}
+
+pub fn verify_chain(chain: &[Vec<u8>], host: &str) -> Result<(), CertError> {
+    let leaf = Certificate::parse(chain.first().ok_or(CertError::NoCertificate)?)?;
+    if !leaf.matches_host(host) {
+        return Err(CertError::HostMismatch);
+    }
+
+    if !leaf.server_auth {
+        return Err(CertError::NotServerAuth);
+    }
+
+    let now = crate::vfs::procfs::wall_clock();
+    check_time(&leaf, now)?;
+
+    let intermediates: Vec<Certificate> = chain[1..].iter()
+        .filter_map(|d| Certificate::parse(d).ok())
+        .collect();
+    let anchors = anchors();
+
+    let mut cur = &leaf;
+    let mut err = CertError::UnknownIssuer;
+    for depth in 0..MAX_DEPTH {
+        for root in anchors.iter().filter(|r| r.subject == cur.issuer) {
+            match check_issuer(cur, root, depth, true, now) {
+                Ok(())  => return Ok(()),
+                Err(e)  => err = e,
+            }
+        }
+        let next = intermediates.iter()
+            .filter(|c| c.subject == cur.issuer && !core::ptr::eq(*c, cur))
+            .find(|c| match check_issuer(cur, c, depth, false, now) {
+                Ok(())  => true,
+                Err(e)  => { err = e; false }
+            });
+        match next {
+            Some(c) => cur = c,
+            None    => return Err(err),
+        }
+    }
+    Err(CertError::PathTooLong)
+}
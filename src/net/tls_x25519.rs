@@ -0,0 +1,201 @@
+type Fe = [u64; 5];
+
+const MASK51: u64 = (1 << 51) - 1;
+
+fn fe_from_bytes(b: &[u8; 32]) -> Fe {
+    let le = |i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap());
+    [
+        le(0)         & MASK51,
+        (le(6) >> 3)  & MASK51,
+        (le(12) >> 6) & MASK51,
+        (le(19) >> 1) & MASK51,
+        (le(24) >> 12) & MASK51,
+    ]
+}
+
+fn fe_carry(mut f: Fe) -> Fe {
+    for i in 0..4 {
+        f[i + 1] += f[i] >> 51;
+        f[i] &= MASK51;
+    }
+    f[0] += 19 * (f[4] >> 51);
+    f[4] &= MASK51;
+    f[1] += f[0] >> 51;
+    f[0] &= MASK51;
+    f
+}
+
+fn fe_to_bytes(f: &Fe) -> [u8; 32] {
+    let mut t = fe_carry(fe_carry(*f));
+    let mut q = (t[0] + 19) >> 51;
+    for limb in t.iter().skip(1) {
+        q = (limb + q) >> 51;
+    }
+    t[0] += 19 * q;
+    for i in 0..4 {
+        t[i + 1] += t[i] >> 51;
+        t[i] &= MASK51;
+    }
+    t[4] &= MASK51;
+
+    let mut out = [0u8; 32];
+    let words = [
+        t[0] | (t[1] << 51),
+        (t[1] >> 13) | (t[2] << 38),
+        (t[2] >> 26) | (t[3] << 25),
+        (t[3] >> 39) | (t[4] << 12),
+    ];
+    for (i, w) in words.iter().enumerate() {
+        out[i * 8..i * 8 + 8].copy_from_slice(&w.to_le_bytes());
+    }
+    out
+}
+
+fn fe_add(a: &Fe, b: &Fe) -> Fe {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3], a[4] + b[4]]
+}
+
+fn fe_sub(a: &Fe, b: &Fe) -> Fe {
+    const P4_0: u64 = 0x1fffffffffffb4;
+    const P4_N: u64 = 0x1ffffffffffffc;
+    fe_carry([
+        a[0] + P4_0 - b[0],
+        a[1] + P4_N - b[1],
+        a[2] + P4_N - b[2],
+        a[3] + P4_N - b[3],
+        a[4] + P4_N - b[4],
+    ])
+}
+
+fn fe_mul(a: &Fe, b: &Fe) -> Fe {
+    let m = |x: u64, y: u64| x as u128 * y as u128;
+    let b19 = [b[0], b[1] * 19, b[2] * 19, b[3] * 19, b[4] * 19];
+    let t0 = m(a[0], b[0]) + m(a[1], b19[4]) + m(a[2], b19[3]) + m(a[3], b19[2]) + m(a[4], b19[1]);
+    let t1 = m(a[0], b[1]) + m(a[1], b[0])   + m(a[2], b19[4]) + m(a[3], b19[3]) + m(a[4], b19[2]);
+    let t2 = m(a[0], b[2]) + m(a[1], b[1])   + m(a[2], b[0])   + m(a[3], b19[4]) + m(a[4], b19[3]);
+    let t3 = m(a[0], b[3]) + m(a[1], b[2])   + m(a[2], b[1])   + m(a[3], b[0])   + m(a[4], b19[4]);
+    let t4 = m(a[0], b[4]) + m(a[1], b[3])   + m(a[2], b[2])   + m(a[3], b[1])   + m(a[4], b[0]);
+
+    let mask = MASK51 as u128;
+    let t1 = t1 + (t0 >> 51);
+    let t2 = t2 + (t1 >> 51);
+    let t3 = t3 + (t2 >> 51);
+    let t4 = t4 + (t3 >> 51);
+    let r0 = (t0 & mask) + (t4 >> 51) * 19;
+    [
+        (r0 & mask) as u64,
+        (t1 & mask) as u64 + (r0 >> 51) as u64,
+        (t2 & mask) as u64,
+        (t3 & mask) as u64,
+        (t4 & mask) as u64,
+    ]
+}
+
+fn fe_sq(a: &Fe) -> Fe {
+    fe_mul(a, a)
+}
+
+fn fe_mul_small(a: &Fe, k: u64) -> Fe {
+    let mut r = [0u64; 5];
+    let mut c = 0u128;
+    for i in 0..5 {
+        c += a[i] as u128 * k as u128;
+        r[i] = (c as u64) & MASK51;
+        c >>= 51;
+    }
+    r[0] += c as u64 * 19;
+    r[1] += r[0] >> 51;
+    r[0] &= MASK51;
+    r
+}
+
+fn fe_sq_n(a: &Fe, n: usize) -> Fe {
+    let mut r = *a;
+    for _ in 0..n {
+        r = fe_sq(&r);
+    }
+    r
+}
+
+fn fe_invert(z: &Fe) -> Fe {
+    let z2       = fe_sq(z);
+    let z9       = fe_mul(&fe_sq_n(&z2, 2), z);
+    let z11      = fe_mul(&z9, &z2);
+    let z2_5_0   = fe_mul(&fe_sq(&z11), &z9);
+    let z2_10_0  = fe_mul(&fe_sq_n(&z2_5_0, 5), &z2_5_0);
+    let z2_20_0  = fe_mul(&fe_sq_n(&z2_10_0, 10), &z2_10_0);
+    let z2_40_0  = fe_mul(&fe_sq_n(&z2_20_0, 20), &z2_20_0);
+    let z2_50_0  = fe_mul(&fe_sq_n(&z2_40_0, 10), &z2_10_0);
+    let z2_100_0 = fe_mul(&fe_sq_n(&z2_50_0, 50), &z2_50_0);
+    let z2_200_0 = fe_mul(&fe_sq_n(&z2_100_0, 100), &z2_100_0);
+    let z2_250_0 = fe_mul(&fe_sq_n(&z2_200_0, 50), &z2_50_0);
+    fe_mul(&fe_sq_n(&z2_250_0, 5), &z11)
+}
+
+fn fe_cswap(a: &mut Fe, b: &mut Fe, swap: u64) {
+    let mask = 0u64.wrapping_sub(swap);
+    for i in 0..5 {
+        let t = mask & (a[i] ^ b[i]);
+        a[i] ^= t;
+        b[i] ^= t;
+    }
+}
+
+pub fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0]  &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = fe_from_bytes(u);
+    let mut x2: Fe = [1, 0, 0, 0, 0];
+    let mut z2: Fe = [0; 5];
+    let mut x3 = x1;
+    let mut z3: Fe = [1, 0, 0, 0, 0];
+    let mut swap = 0u64;
+
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        fe_cswap(&mut x2, &mut x3, swap);
+        fe_cswap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a   = fe_carry(fe_add(&x2, &z2));
+        let aa  = fe_sq(&a);
+        let b   = fe_sub(&x2, &z2);
+        let bb  = fe_sq(&b);
+        let e   = fe_sub(&aa, &bb);
+        let c   = fe_carry(fe_add(&x3, &z3));
+        let d   = fe_sub(&x3, &z3);
+        let da  = fe_mul(&d, &a);
+        let cb  = fe_mul(&c, &b);
+        x3 = fe_sq(&fe_carry(fe_add(&da, &cb)));
+        z3 = fe_mul(&x1, &fe_sq(&fe_sub(&da, &cb)));
+        x2 = fe_mul(&aa, &bb);
+        z2 = fe_mul(&e, &fe_carry(fe_add(&aa, &fe_mul_small(&e, 121665))));
+    }
+    fe_cswap(&mut x2, &mut x3, swap);
+    fe_cswap(&mut z2, &mut z3, swap);
+
+    fe_to_bytes(&fe_mul(&x2, &fe_invert(&z2)))
+}
+
+pub fn x25519_base(scalar: &[u8; 32]) -> [u8; 32] {
+    let mut base = [0u8; 32];
+    base[0] = 9;
+    x25519(scalar, &base)
+}
+
+pub fn x25519_keypair(rand_bytes: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let public = x25519_base(rand_bytes);
+    (*rand_bytes, public)
+}
+
+pub fn x25519_shared(priv_key: &[u8; 32], pub_key: &[u8; 32]) -> Option<[u8; 32]> {
+    let shared = x25519(priv_key, pub_key);
+    if shared.iter().all(|&b| b == 0) {
+        return None;
+    }
+    Some(shared)
+}
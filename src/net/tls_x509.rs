@@ -0,0 +1,429 @@
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use super::ip::IpAddr;
+use super::tls_crypto::HashAlg;
+use super::tls_rsa::{rsa_key_from_der, RsaPublicKey};
+
+const TAG_BOOLEAN:     u8 = 0x01;
+const TAG_INTEGER:     u8 = 0x02;
+const TAG_BIT_STRING:  u8 = 0x03;
+const TAG_OCTET:       u8 = 0x04;
+const TAG_OID:         u8 = 0x06;
+const TAG_UTC_TIME:    u8 = 0x17;
+const TAG_GEN_TIME:    u8 = 0x18;
+const TAG_SEQUENCE:    u8 = 0x30;
+const TAG_SET:         u8 = 0x31;
+const TAG_VERSION:     u8 = 0xA0;
+const TAG_EXTENSIONS:  u8 = 0xA3;
+const SAN_DNS:         u8 = 0x82;
+const SAN_IP:          u8 = 0x87;
+
+const OID_RSA:         &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_RSA_SHA1:    &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x05];
+const OID_RSA_PSS:     &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0a];
+const OID_RSA_SHA256:  &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_RSA_SHA384:  &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_RSA_SHA512:  &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const OID_EC_PUBKEY:   &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256:        &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_P384:        &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_ECDSA_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+const OID_SHA256:      &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const OID_SHA384:      &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+const OID_SHA512:      &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+const OID_CN:          &[u8] = &[0x55, 0x04, 0x03];
+const OID_KEY_USAGE:   &[u8] = &[0x55, 0x1d, 0x0f];
+const OID_SAN:         &[u8] = &[0x55, 0x1d, 0x11];
+const OID_BASIC:       &[u8] = &[0x55, 0x1d, 0x13];
+const OID_EXT_KU:      &[u8] = &[0x55, 0x1d, 0x25];
+const OID_ANY_EKU:     &[u8] = &[0x55, 0x1d, 0x25, 0x00];
+const OID_SERVER_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+
+const KU_KEY_CERT_SIGN: u8 = 0x04;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CertError {
+    Malformed,
+    NoCertificate,
+    UnsupportedKey,
+    BadSignature,
+    UnknownIssuer,
+    NotCa,
+    PathTooLong,
+    Expired,
+    NotYetValid,
+    HostMismatch,
+    NoClock,
+    WeakSignature,
+    CriticalExt,
+    NotServerAuth,
+}
+
+impl CertError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CertError::Malformed      => "malformed certificate",
+            CertError::NoCertificate  => "no certificate",
+            CertError::UnsupportedKey => "unsupported key or signature algorithm",
+            CertError::BadSignature   => "bad signature",
+            CertError::UnknownIssuer  => "unknown issuer",
+            CertError::NotCa          => "issuer is not a CA",
+            CertError::PathTooLong    => "path length exceeded",
+            CertError::Expired        => "certificate expired",
+            CertError::NotYetValid    => "certificate not yet valid",
+            CertError::HostMismatch   => "hostname mismatch",
+            CertError::NoClock        => "system clock not set, cannot check validity (use -k to skip)",
+            CertError::WeakSignature  => "SHA-1 signatures are not accepted",
+            CertError::CriticalExt    => "unsupported critical extension",
+            CertError::NotServerAuth  => "certificate not valid for server authentication",
+        }
+    }
+
+    pub fn alert(&self) -> u8 {
+        match self {
+            CertError::UnsupportedKey | CertError::WeakSignature
+            | CertError::CriticalExt | CertError::NotServerAuth => 43,
+            CertError::Expired | CertError::NotYetValid => 45,
+            CertError::UnknownIssuer | CertError::NotCa | CertError::PathTooLong => 48,
+            _ => 42,
+        }
+    }
+}
+
+pub struct Der<'a> {
+    data: &'a [u8],
+    pos:  usize,
+}
+
+impl<'a> Der<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    pub fn read_any(&mut self) -> Option<(u8, &'a [u8], &'a [u8])> {
+        let start = self.pos;
+        let tag = *self.data.get(self.pos)?;
+        let mut p = self.pos + 1;
+        let b = *self.data.get(p)? as usize;
+        p += 1;
+        let len = if b & 0x80 == 0 {
+            b
+        } else {
+            let nb = b & 0x7f;
+            if nb == 0 || nb > 4 { return None; }
+            let mut l = 0usize;
+            for _ in 0..nb {
+                l = (l << 8) | *self.data.get(p)? as usize;
+                p += 1;
+            }
+            l
+        };
+        let end = p.checked_add(len).filter(|&e| e <= self.data.len())?;
+        self.pos = end;
+        Some((tag, &self.data[p..end], &self.data[start..end]))
+    }
+
+    pub fn read(&mut self, tag: u8) -> Option<&'a [u8]> {
+        if self.peek()? != tag { return None; }
+        self.read_any().map(|(_, body, _)| body)
+    }
+
+    pub fn read_raw(&mut self, tag: u8) -> Option<&'a [u8]> {
+        if self.peek()? != tag { return None; }
+        self.read_any().map(|(_, _, raw)| raw)
+    }
+
+    pub fn optional(&mut self, tag: u8) -> Option<&'a [u8]> {
+        if self.peek() == Some(tag) { self.read(tag) } else { None }
+    }
+}
+
+pub fn read_integer(der: &mut Der) -> Option<Vec<u8>> {
+    let v = der.read(TAG_INTEGER)?;
+    let start = v.iter().position(|&b| b != 0).unwrap_or(v.len());
+    Some(v[start..].to_vec())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SigAlg {
+    RsaPkcs1(HashAlg),
+    RsaPss(HashAlg),
+    Ecdsa(HashAlg),
+}
+
+impl SigAlg {
+    pub fn hash(self) -> HashAlg {
+        match self {
+            SigAlg::RsaPkcs1(h) | SigAlg::RsaPss(h) | SigAlg::Ecdsa(h) => h,
+        }
+    }
+}
+
+pub enum PublicKey {
+    Rsa(Box<RsaPublicKey>),
+    EcP256(Vec<u8>),
+    EcP384(Vec<u8>),
+}
+
+pub struct Certificate {
+    pub tbs:         Vec<u8>,
+    pub sig_alg:     SigAlg,
+    pub signature:   Vec<u8>,
+    pub issuer:      Vec<u8>,
+    pub subject:     Vec<u8>,
+    pub cn:          String,
+    pub not_before:  u64,
+    pub not_after:   u64,
+    pub key:         PublicKey,
+    pub dns_names:   Vec<String>,
+    pub ip_addrs:    Vec<IpAddr>,
+    pub has_san:     bool,
+    pub is_ca:       bool,
+    pub path_len:    Option<u8>,
+    pub can_sign:    bool,
+    pub server_auth: bool,
+}
+
+fn parse_pss_hash(params: &[u8]) -> Option<HashAlg> {
+    let mut fields = Der::new(Der::new(params).read(TAG_SEQUENCE)?);
+    let hash = match fields.optional(TAG_VERSION) {
+        Some(h) => {
+            let mut alg = Der::new(Der::new(h).read(TAG_SEQUENCE)?);
+            hash_from_oid(alg.read(TAG_OID)?)?
+        }
+        None => HashAlg::Sha1,
+    };
+    Some(hash)
+}
+
+fn hash_from_oid(oid: &[u8]) -> Option<HashAlg> {
+    match oid {
+        OID_SHA256 => Some(HashAlg::Sha256),
+        OID_SHA384 => Some(HashAlg::Sha384),
+        OID_SHA512 => Some(HashAlg::Sha512),
+        _ => None,
+    }
+}
+
+fn parse_sig_alg(body: &[u8]) -> Option<SigAlg> {
+    let mut d = Der::new(body);
+    let oid = d.read(TAG_OID)?;
+    Some(match oid {
+        OID_RSA_SHA1     => SigAlg::RsaPkcs1(HashAlg::Sha1),
+        OID_RSA_SHA256   => SigAlg::RsaPkcs1(HashAlg::Sha256),
+        OID_RSA_SHA384   => SigAlg::RsaPkcs1(HashAlg::Sha384),
+        OID_RSA_SHA512   => SigAlg::RsaPkcs1(HashAlg::Sha512),
+        OID_ECDSA_SHA256 => SigAlg::Ecdsa(HashAlg::Sha256),
+        OID_ECDSA_SHA384 => SigAlg::Ecdsa(HashAlg::Sha384),
+        OID_ECDSA_SHA512 => SigAlg::Ecdsa(HashAlg::Sha512),
+        OID_RSA_PSS      => SigAlg::RsaPss(parse_pss_hash(&body[d.pos..])?),
+        _ => return None,
+    })
+}
+
+fn parse_cn(name: &[u8]) -> String {
+    let mut rdns = Der::new(name);
+    while let Some(set) = rdns.read(TAG_SET) {
+        let mut atvs = Der::new(set);
+        while let Some(atv) = atvs.read(TAG_SEQUENCE) {
+            let mut f = Der::new(atv);
+            if f.read(TAG_OID) == Some(OID_CN) {
+                if let Some((_, v, _)) = f.read_any() {
+                    return String::from_utf8_lossy(v).into_owned();
+                }
+            }
+        }
+    }
+    String::new()
+}
+
+fn digits(s: &[u8]) -> Option<u64> {
+    s.iter().try_fold(0u64, |acc, &c| c.is_ascii_digit().then(|| acc * 10 + (c - b'0') as u64))
+}
+
+fn days_from_civil(y: u64, m: u64, d: u64) -> u64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp  = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn parse_time(tag: u8, v: &[u8]) -> Option<u64> {
+    let (year, rest) = match tag {
+        TAG_UTC_TIME if v.len() >= 13 => {
+            let yy = digits(&v[..2])?;
+            (if yy < 50 { 2000 + yy } else { 1900 + yy }, &v[2..])
+        }
+        TAG_GEN_TIME if v.len() >= 15 => (digits(&v[..4])?, &v[4..]),
+        _ => return None,
+    };
+    if *rest.last()? != b'Z' { return None; }
+    let month = digits(&rest[0..2])?;
+    let day   = digits(&rest[2..4])?;
+    let hour  = digits(&rest[4..6])?;
+    let min   = digits(&rest[6..8])?;
+    let sec   = digits(&rest[8..10])?;
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) { return None; }
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + min * 60 + sec)
+}
+
+fn parse_key(spki: &[u8]) -> Result<PublicKey, CertError> {
+    let mut d = Der::new(spki);
+    let mut alg = Der::new(d.read(TAG_SEQUENCE).ok_or(CertError::Malformed)?);
+    let oid = alg.read(TAG_OID).ok_or(CertError::Malformed)?;
+    let bits = d.read(TAG_BIT_STRING).filter(|b| b.first() == Some(&0)).ok_or(CertError::Malformed)?;
+    match oid {
+        OID_RSA => rsa_key_from_der(&bits[1..]).map(|k| PublicKey::Rsa(Box::new(k))).ok_or(CertError::UnsupportedKey),
+        OID_EC_PUBKEY => match (alg.read(TAG_OID), bits.len()) {
+            (Some(OID_P256), 66) => Ok(PublicKey::EcP256(bits[1..].to_vec())),
+            (Some(OID_P384), 98) => Ok(PublicKey::EcP384(bits[1..].to_vec())),
+            _ => Err(CertError::UnsupportedKey),
+        },
+        _ => Err(CertError::UnsupportedKey),
+    }
+}
+
+impl Certificate {
+    pub fn parse(der: &[u8]) -> Result<Self, CertError> {
+        Self::parse_inner(der).ok_or(CertError::Malformed)?
+    }
+
+    fn parse_inner(der: &[u8]) -> Option<Result<Self, CertError>> {
+        let mut outer = Der::new(Der::new(der).read(TAG_SEQUENCE)?);
+        let tbs_raw = outer.read_raw(TAG_SEQUENCE)?;
+        let sig_alg = match parse_sig_alg(outer.read(TAG_SEQUENCE)?) {
+            Some(a) => a,
+            None    => return Some(Err(CertError::UnsupportedKey)),
+        };
+        let sig_bits = outer.read(TAG_BIT_STRING).filter(|b| b.first() == Some(&0))?;
+
+        let mut tbs = Der::new(Der::new(tbs_raw).read(TAG_SEQUENCE)?);
+        tbs.optional(TAG_VERSION);
+        tbs.read(TAG_INTEGER)?;
+        tbs.read(TAG_SEQUENCE)?;
+        let issuer = tbs.read_raw(TAG_SEQUENCE)?;
+        let mut validity = Der::new(tbs.read(TAG_SEQUENCE)?);
+        let (t1, v1, _) = validity.read_any()?;
+        let (t2, v2, _) = validity.read_any()?;
+        let not_before = parse_time(t1, v1)?;
+        let not_after  = parse_time(t2, v2)?;
+        let subject = tbs.read_raw(TAG_SEQUENCE)?;
+        let subject_body = Der::new(subject).read(TAG_SEQUENCE)?;
+        let key = match parse_key(tbs.read(TAG_SEQUENCE)?) {
+            Ok(k)  => k,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let mut cert = Certificate {
+            tbs:        tbs_raw.to_vec(),
+            sig_alg,
+            signature:  sig_bits[1..].to_vec(),
+            issuer:     issuer.to_vec(),
+            subject:    subject.to_vec(),
+            cn:         parse_cn(subject_body),
+            not_before,
+            not_after,
+            key,
+            dns_names:  Vec::new(),
+            ip_addrs:   Vec::new(),
+            has_san:    false,
+            is_ca:      false,
+            path_len:   None,
+            can_sign:   true,
+            server_auth: true,
+        };
+
+        while !tbs.is_empty() {
+            let (tag, body, _) = tbs.read_any()?;
+            if tag == TAG_EXTENSIONS {
+                if let Err(e) = cert.parse_extensions(Der::new(body).read(TAG_SEQUENCE)?)? {
+                    return Some(Err(e));
+                }
+            }
+        }
+        Some(Ok(cert))
+    }
+
+    fn parse_extensions(&mut self, exts: &[u8]) -> Option<Result<(), CertError>> {
+        let mut list = Der::new(exts);
+        while let Some(ext) = list.read(TAG_SEQUENCE) {
+            let mut f = Der::new(ext);
+            let oid = f.read(TAG_OID)?;
+            let critical = f.optional(TAG_BOOLEAN).is_some_and(|b| b.first().is_some_and(|&v| v != 0));
+            let value = f.read(TAG_OCTET)?;
+            match oid {
+                OID_SAN => {
+                    self.has_san = true;
+                    let mut names = Der::new(Der::new(value).read(TAG_SEQUENCE)?);
+                    while let Some((tag, v, _)) = names.read_any() {
+                        match (tag, v.len()) {
+                            (SAN_DNS, _) => self.dns_names.push(String::from_utf8_lossy(v).into_owned()),
+                            (SAN_IP, 4)  => self.ip_addrs.push(IpAddr::V4(v.try_into().ok()?)),
+                            (SAN_IP, 16) => self.ip_addrs.push(IpAddr::V6(v.try_into().ok()?)),
+                            _ => {}
+                        }
+                    }
+                }
+                OID_BASIC => {
+                    let mut bc = Der::new(Der::new(value).read(TAG_SEQUENCE)?);
+                    self.is_ca = bc.optional(TAG_BOOLEAN).is_some_and(|b| b.first().is_some_and(|&v| v != 0));
+                    if let Some(n) = bc.optional(TAG_INTEGER) {
+                        self.path_len = n.last().copied();
+                    }
+                }
+                OID_KEY_USAGE => {
+                    let bits = Der::new(value).read(TAG_BIT_STRING)?;
+                    self.can_sign = bits.get(1).is_some_and(|&b| b & KU_KEY_CERT_SIGN != 0);
+                }
+                OID_EXT_KU => {
+                    let mut purposes = Der::new(Der::new(value).read(TAG_SEQUENCE)?);
+                    self.server_auth = false;
+                    while let Some(p) = purposes.read(TAG_OID) {
+                        self.server_auth |= p == OID_SERVER_AUTH || p == OID_ANY_EKU;
+                    }
+                }
+                _ if critical => return Some(Err(CertError::CriticalExt)),
+                _ => {}
+            }
+        }
+        Some(Ok(()))
+    }
+
+    pub fn matches_host(&self, host: &str) -> bool {
+        if let Some(ip) = IpAddr::parse(host) {
+            return self.ip_addrs.contains(&ip);
+        }
+        if self.has_san {
+            self.dns_names.iter().any(|n| host_matches(n, host))
+        } else {
+            host_matches(&self.cn, host)
+        }
+    }
+}
+
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.');
+    let host    = host.trim_end_matches('.');
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => match host.split_once('.') {
+            Some((label, rest)) => !label.is_empty() && rest.contains('.') && rest.eq_ignore_ascii_case(suffix),
+            None => false,
+        },
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
@@ -1,4 +1,8 @@
+extern crate alloc;
+use alloc::format;
+use alloc::string::ToString;
 use super::eth::{EthFrame, ETHERTYPE_IP};
+use super::ip::IpAddr;
 use super::{ipv4, icmp};
 use super::CTRL_C;
 use core::sync::atomic::Ordering;
@@ -51,14 +55,19 @@ fn build_icmp_with_ttl(
     total
 }
 
-pub fn run(hostname: &str, target_ip: &[u8; 4]) {
+pub fn run(hostname: &str, target_ip: &[u8; 4], resolve_names: bool) {
     if !super::is_ready() {
         crate::print_error!("traceroute: no adapter");
         return;
     }
 
-    let our_ip  = super::get_ip();
-    let our_mac = super::get_mac();
+    let our_ip = match super::source_for(&IpAddr::V4(*target_ip)) {
+        Some(IpAddr::V4(a)) => a,
+        _ => {
+            crate::print_error!("traceroute: network unreachable");
+            return;
+        }
+    };
 
     let payload = b"traceroute_probe_xxxxxxxxxxxxxxxx";
 
@@ -77,13 +86,10 @@ pub fn run(hostname: &str, target_ip: &[u8; 4]) {
             break;
         }
 
-        let gw_mac = match super::resolve_arp(target_ip, &our_ip, &our_mac) {
-            Some(m) => m,
-            None => {
-                crate::print_error!("{:2}  * * * (arp failed)", hop);
-                continue;
-            }
-        };
+        if !super::resolve_arp(target_ip) {
+            crate::print_error!("{:2}  * * * (arp failed)", hop);
+            continue;
+        }
 
         let seq = hop as u16;
         let t_start_rtt = rdtsc();
@@ -95,19 +101,12 @@ pub fn run(hostname: &str, target_ip: &[u8; 4]) {
         );
         if ip_len == 0 { continue; }
 
-        let mut eth_buf = [0u8; 220];
-        let eth_len = EthFrame::build(&gw_mac, &our_mac, ETHERTYPE_IP, &ip_buf[..ip_len], &mut eth_buf);
-        if eth_len == 0 { continue; }
-
-        {
-            let mut state = super::NET.lock();
-            if let Some(drv) = state.driver.as_mut() {
-                drv.send(&eth_buf[..eth_len]);
-            }
-            state.tx_count += 1;
-        }
+        let tap = super::RawTap::open();
+        super::send_raw_ipv4(&ip_buf[..ip_len]);
 
         let mut got = false;
+        let mut reached = false;
+        let mut hit = None;
         let t_start_wait = crate::vfs::procfs::uptime_ticks();
 
         loop {
@@ -116,32 +115,15 @@ pub fn run(hostname: &str, target_ip: &[u8; 4]) {
                 break 'hops;
             }
 
-            let mut raw: [[u8; 1520]; 4] = [[0; 1520]; 4];
-            let mut raw_lens = [0usize; 4];
-            let mut raw_n = 0usize;
-            {
-                let mut state = super::NET.lock();
-                if let Some(drv) = state.driver.as_mut() {
-                    drv.recv(&mut |buf| {
-                        if raw_n < 4 {
-                            let l = buf.len().min(1520);
-                            raw[raw_n][..l].copy_from_slice(&buf[..l]);
-                            raw_lens[raw_n] = l;
-                            raw_n += 1;
-                        }
-                    });
-                }
-            }
-
-            for i in 0..raw_n {
-                let buf = &raw[i][..raw_lens[i]];
-                let frame = match EthFrame::parse(buf) { Some(f) => f, None => continue };
-                if frame.ethertype != ETHERTYPE_IP { continue; }
-                let ip = match ipv4::Ipv4Header::parse(frame.payload) { Some(h) => h, None => continue };
-                if ip.proto != ipv4::PROTO_ICMP { continue; }
+            tap.recv(&mut |buf| {
+                if got { return; }
+                let frame = match EthFrame::parse(buf) { Some(f) => f, None => return };
+                if frame.ethertype != ETHERTYPE_IP { return; }
+                let ip = match ipv4::Ipv4Header::parse(frame.payload) { Some(h) => h, None => return };
+                if ip.proto != ipv4::PROTO_ICMP { return; }
 
                 let icmp = ip.payload(frame.payload);
-                if icmp.len() < 8 { continue; }
+                if icmp.len() < 8 { return; }
 
                 let icmp_type = icmp[0];
 
@@ -151,38 +133,41 @@ pub fn run(hostname: &str, target_ip: &[u8; 4]) {
 
                 match icmp_type {
                     11 => {
-                        let hop_ip = ip.src;
-                        crate::cprintln!(230, 240, 240,
-                            "{:2}  {}.{}.{}.{}  {}.{} ms",
-                            hop,
-                            hop_ip[0], hop_ip[1], hop_ip[2], hop_ip[3],
-                            rtt_ms_i, rtt_ms_f,
-                        );
+                        hit = Some((ip.src, rtt_ms_i, rtt_ms_f));
                         got = true;
                     }
                     0 => {
                         let echo_id  = u16::from_be_bytes([icmp[4], icmp[5]]);
                         let echo_seq = u16::from_be_bytes([icmp[6], icmp[7]]);
                         if echo_id == PROBE_ID && echo_seq == seq {
-                            crate::cprintln!(100, 220, 150,
-                                "{:2}  {}.{}.{}.{}  {}.{} ms  [destination reached]",
-                                hop,
-                                target_ip[0], target_ip[1], target_ip[2], target_ip[3],
-                                rtt_ms_i, rtt_ms_f,
-                            );
+                            hit = Some((*target_ip, rtt_ms_i, rtt_ms_f));
                             got = true;
-                            break 'hops;
+                            reached = true;
                         }
                     }
                     _ => {}
                 }
-
-                if got { break; }
+            });
+
+            if let Some((hop_ip, rtt_ms_i, rtt_ms_f)) = hit {
+                let addr = IpAddr::V4(hop_ip);
+                let name = if resolve_names { super::dns::reverse(&addr) } else { None };
+                let host = match name {
+                    Some(n) => format!("{} ({})", n, addr),
+                    None    => addr.to_string(),
+                };
+                if reached {
+                    crate::cprintln!(100, 220, 150,
+                        "{:2}  {}  {}.{} ms  [destination reached]", hop, host, rtt_ms_i, rtt_ms_f);
+                } else {
+                    crate::cprintln!(230, 240, 240, "{:2}  {}  {}.{} ms", hop, host, rtt_ms_i, rtt_ms_f);
+                }
             }
 
+            if reached { break 'hops; }
             if got { break; }
             if crate::vfs::procfs::uptime_ticks().wrapping_sub(t_start_wait) >= 2000 { break; }
-            core::hint::spin_loop();
+            crate::scheduler::yield_now();
         }
 
         if !got {
@@ -191,18 +176,22 @@ pub fn run(hostname: &str, target_ip: &[u8; 4]) {
     }
 }
 
-pub fn cmd_traceroute(arg: &str) {
+pub fn cmd_traceroute(args: &str) {
+    let mut resolve_names = true;
+    let mut arg = "";
+    for a in args.split_whitespace() {
+        if a == "-n" { resolve_names = false; } else { arg = a; }
+    }
     if arg.is_empty() {
-        crate::println!("Usage: traceroute <host>");
+        crate::println!("Usage: traceroute [-n] <host>");
         return;
     }
 
     let ip = if let Some(ip) = parse_ip(arg) {
         ip
     } else {
-        let dns = [8u8, 8, 8, 8];
         crate::print_info!("traceroute: resolving {}...", arg);
-        match super::dns::resolve(arg, &dns) {
+        match super::dns::resolve(arg) {
             Some(ip) => ip,
             None => {
                 crate::print_error!("traceroute: cannot resolve {}", arg);
@@ -211,7 +200,7 @@ pub fn cmd_traceroute(arg: &str) {
         }
     };
 
-    run(arg, &ip);
+    run(arg, &ip, resolve_names);
 }
 
 fn parse_ip(s: &str) -> Option<[u8; 4]> {
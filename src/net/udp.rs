@@ -1,6 +1,7 @@
+use super::ip::{self, IpAddr};
 use super::ipv4;
 
-pub const MAX_UDP_PAYLOAD: usize = 1472;
+pub const MAX_UDP_PAYLOAD: usize = ipv4::MAX_DATAGRAM - 28;
 
 #[derive(Clone, Copy, Debug)]
 pub struct UdpHeader {
@@ -35,6 +36,17 @@ pub fn build(
     src_ip: &[u8; 4],
     dst_ip: &[u8; 4],
     out: &mut [u8],
+) -> usize {
+    build_ip(src_port, dst_port, payload, &IpAddr::V4(*src_ip), &IpAddr::V4(*dst_ip), out)
+}
+
+pub fn build_ip(
+    src_port: u16,
+    dst_port: u16,
+    payload: &[u8],
+    src_ip: &IpAddr,
+    dst_ip: &IpAddr,
+    out: &mut [u8],
 ) -> usize {
     let udp_len = (8 + payload.len()) as u16;
     if out.len() < 8 + payload.len() {
@@ -51,53 +63,9 @@ pub fn build(
     out[7] = 0;
     out[8..8 + payload.len()].copy_from_slice(payload);
 
-    let csum = ipv4::udp_checksum(src_ip, dst_ip, &out[..8 + payload.len()]);
+    let csum = ip::pseudo_checksum(src_ip, dst_ip, ipv4::PROTO_UDP, &out[..8 + payload.len()]);
     out[6] = (csum >> 8) as u8;
     out[7] = csum as u8;
 
     8 + payload.len()
 }
-
-pub struct UdpSocket {
-    pub port: u16,
-    pub rx_buf: [u8; 1500],
-    pub rx_len: usize,
-    pub rx_src_ip: [u8; 4],
-    pub rx_src_port: u16,
-    pub has_data: bool,
-}
-
-impl UdpSocket {
-    pub const fn new(port: u16) -> Self {
-        Self {
-            port,
-            rx_buf: [0u8; 1500],
-            rx_len: 0,
-            rx_src_ip: [0; 4],
-            rx_src_port: 0,
-            has_data: false,
-        }
-    }
-
-    pub fn on_recv(&mut self, src_ip: &[u8; 4], src_port: u16, data: &[u8]) {
-        let len = data.len().min(1500);
-        self.rx_buf[..len].copy_from_slice(&data[..len]);
-        self.rx_len = len;
-        self.rx_src_ip = *src_ip;
-        self.rx_src_port = src_port;
-        self.has_data = true;
-    }
-
-    pub fn recv(&mut self) -> Option<(&[u8], [u8; 4], u16)> {
-        if self.has_data {
-            self.has_data = false;
-            Some((
-                &self.rx_buf[..self.rx_len],
-                self.rx_src_ip,
-                self.rx_src_port,
-            ))
-        } else {
-            None
-        }
-    }
-}
@@ -1,4 +1,4 @@
-use super::pci::PciDevice;
+use crate::pci::PciDevice;
 use super::NetworkDriver;
 use x86_64::instructions::port::Port;
 
@@ -290,4 +290,8 @@ impl NetworkDriver for VirtioNet {
     fn get_mac(&self) -> [u8; 6] {
         self.mac
     }
+
+    fn ack_irq(&mut self) {
+        let _ = ior8!(self.io_base, REG_ISR_STATUS);
+    }
 }
@@ -18,6 +18,13 @@ pub const DEV_RTL8169: u16 = 0x8169;
 pub const DEV_VIRTIO_NET: u16 = 0x1000;
 pub const DEV_VIRTIO_NET_MODERN: u16 = 0x1041;
 
+pub const CLASS_STORAGE: u8 = 0x01;
+pub const CLASS_NETWORK: u8 = 0x02;
+pub const SUBCLASS_SATA: u8 = 0x06;
+pub const PROG_IF_AHCI: u8 = 0x01;
+
+const MAX_DEVICES: usize = 64;
+
 #[derive(Clone, Copy, Debug)]
 pub struct PciDevice {
     pub bus: u8,
@@ -27,6 +34,7 @@ pub struct PciDevice {
     pub device: u16,
     pub class: u8,
     pub subclass: u8,
+    pub prog_if: u8,
     pub bars: [u32; 6],
     pub irq: u8,
 }
@@ -41,6 +49,7 @@ impl PciDevice {
             device: 0xFFFF,
             class: 0,
             subclass: 0,
+            prog_if: 0,
             bars: [0; 6],
             irq: 0,
         }
@@ -131,8 +140,8 @@ pub fn pci_write16(bus: u8, dev: u8, func: u8, offset: u8, val: u16) {
     );
 }
 
-pub fn scan() -> ([PciDevice; 32], usize) {
-    let mut devices = [PciDevice::empty(); 32];
+pub fn scan() -> ([PciDevice; MAX_DEVICES], usize) {
+    let mut devices = [PciDevice::empty(); MAX_DEVICES];
     let mut count = 0;
     for bus in 0..=255 {
         for dev in 0..32 {
@@ -144,11 +153,11 @@ pub fn scan() -> ([PciDevice; 32], usize) {
                     }
                     continue;
                 }
-                let class_rev = pci_read32(bus, dev, func, 0x08);
-                if (class_rev >> 24) as u8 == 0x02 && count < 32 {
+                if count < MAX_DEVICES {
+                    let class_rev = pci_read32(bus, dev, func, 0x08);
                     let mut bars = [0; 6];
-                    for i in 0..6 {
-                        bars[i] = pci_read32(bus, dev, func, 0x10 + (i as u8) * 4);
+                    for (i, bar) in bars.iter_mut().enumerate() {
+                        *bar = pci_read32(bus, dev, func, 0x10 + (i as u8) * 4);
                     }
                     devices[count] = PciDevice {
                         bus,
@@ -158,6 +167,7 @@ pub fn scan() -> ([PciDevice; 32], usize) {
                         device: (id >> 16) as u16,
                         class: (class_rev >> 24) as u8,
                         subclass: (class_rev >> 16) as u8,
+                        prog_if: (class_rev >> 8) as u8,
                         bars,
                         irq: pci_read8(bus, dev, func, 0x3C),
                     };
@@ -172,9 +182,16 @@ pub fn scan() -> ([PciDevice; 32], usize) {
     (devices, count)
 }
 
+pub fn find(class: u8, subclass: u8, prog_if: u8) -> Option<PciDevice> {
+    let (devs, n) = scan();
+    devs.into_iter()
+        .take(n)
+        .find(|d| d.class == class && d.subclass == subclass && d.prog_if == prog_if)
+}
+
 pub fn find_nic() -> Option<PciDevice> {
     let (devs, n) = scan();
-    devs.into_iter().take(n).find(|d| {
+    devs.into_iter().take(n).filter(|d| d.class == CLASS_NETWORK).find(|d| {
         d.vendor == VENDOR_INTEL || d.vendor == VENDOR_REALTEK || d.vendor == VENDOR_VIRTIO
     })
 }
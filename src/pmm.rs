@@ -1,3 +1,5 @@
+extern crate alloc;
+use alloc::collections::BTreeMap;
 use spin::Mutex;
 use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
@@ -107,6 +109,16 @@ impl FrameAllocator {
         None
     }
 
+    fn alloc_frames_below(&mut self, count: usize, limit: u64) -> Option<u64> {
+        let end = frame_cap().min((limit / FRAME_SIZE as u64) as usize);
+        let start_idx = self.find_contiguous(0, end, count)?;
+        for j in start_idx..(start_idx + count) {
+            self.mark_used(j);
+        }
+        self.used += count;
+        Some((start_idx * FRAME_SIZE) as u64)
+    }
+
     fn find_contiguous(&self, from: usize, to: usize, count: usize) -> Option<usize> {
         let mut consecutive = 0;
         let mut start_idx   = 0;
@@ -238,6 +250,10 @@ pub fn alloc_frames(count: usize) -> Option<u64> {
     PMM.lock().alloc_frames(count)
 }
 
+pub fn alloc_frames_below(count: usize, limit: u64) -> Option<u64> {
+    PMM.lock().alloc_frames_below(count, limit)
+}
+
 pub fn free_frame(phys: u64) {
     PMM.lock().free_frames(phys, 1);
 }
@@ -246,6 +262,30 @@ pub fn free_frames(phys: u64, count: usize) {
     PMM.lock().free_frames(phys, count);
 }
 
+static SHARED_REFS: Mutex<BTreeMap<u64, u32>> = Mutex::new(BTreeMap::new());
+
+pub fn share_frame(phys: u64) {
+    *SHARED_REFS.lock().entry(phys & !0xFFF).or_insert(0) += 1;
+}
+
+pub fn is_shared(phys: u64) -> bool {
+    SHARED_REFS.lock().contains_key(&(phys & !0xFFF))
+}
+
+pub fn drop_shared_ref(phys: u64) -> bool {
+    let mut refs = SHARED_REFS.lock();
+    let key = phys & !0xFFF;
+    match refs.get_mut(&key) {
+        Some(n) if *n > 1 => { *n -= 1; true }
+        Some(_) => { refs.remove(&key); true }
+        None => false,
+    }
+}
+
+pub fn shared_frames() -> usize {
+    SHARED_REFS.lock().len()
+}
+
 pub fn stats() -> (usize, usize) {
     let p = PMM.lock();
     (p.used, p.total)
@@ -16,6 +16,7 @@ pub const STATE_RUNNING: u8 = 1;
 pub const STATE_SLEEPING: u8 = 2;
 pub const STATE_BLOCKED:  u8 = 3;
 pub const STATE_DEAD:     u8 = 4;
+pub const STATE_STOPPED:  u8 = 5;
 
 static NEXT_PID: AtomicU64 = AtomicU64::new(1);
 
@@ -23,8 +24,32 @@ pub fn pid_range() -> u64 {
     NEXT_PID.load(Ordering::Relaxed)
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct UserContext {
+    pub rax:    u64,
+    pub rbx:    u64,
+    pub rcx:    u64,
+    pub rdx:    u64,
+    pub rsi:    u64,
+    pub rdi:    u64,
+    pub rbp:    u64,
+    pub r8:     u64,
+    pub r9:     u64,
+    pub r10:    u64,
+    pub r11:    u64,
+    pub r12:    u64,
+    pub r13:    u64,
+    pub r14:    u64,
+    pub r15:    u64,
+    pub rip:    u64,
+    pub rflags: u64,
+    pub rsp:    u64,
+}
+
 pub struct Process {
     pub pid:             u64,
+    pub ppid:            AtomicU64,
     pub name:            &'static str,
     pub is_idle:         bool,
     pub priority:        AtomicU8,
@@ -51,6 +76,9 @@ pub struct Process {
     pub stack:           Box<[u8]>,
     pub user_stack_phys: Option<u64>,
     pub brk:             AtomicU64,
+    pub fs_base:         AtomicU64,
+    pub sig_pending:     AtomicU64,
+    pub sig_blocked:     AtomicU64,
 }
 
 impl Process {
@@ -59,6 +87,7 @@ impl Process {
         let stack = vec![0u8; DEFAULT_STACK_SIZE].into_boxed_slice();
         Box::new(Self {
             pid:             NEXT_PID.fetch_add(1, Ordering::SeqCst),
+            ppid:            AtomicU64::new(0),
             name,
             is_idle:         false,
             priority:        AtomicU8::new(priority.clamp(1, 20)),
@@ -82,6 +111,9 @@ impl Process {
             stack,
             user_stack_phys:  None,
             brk:              AtomicU64::new(0),
+            fs_base:          AtomicU64::new(0),
+            sig_pending:      AtomicU64::new(0),
+            sig_blocked:      AtomicU64::new(0),
         })
     }
 
@@ -89,6 +121,7 @@ impl Process {
         let stack = vec![0u8; DEFAULT_STACK_SIZE].into_boxed_slice();
         Box::new(Self {
             pid:             0,
+            ppid:            AtomicU64::new(0),
             name:            "idle",
             is_idle:         true,
             priority:        AtomicU8::new(20),
@@ -112,6 +145,9 @@ impl Process {
             stack,
             user_stack_phys:  None,
             brk:              AtomicU64::new(0),
+            fs_base:          AtomicU64::new(0),
+            sig_pending:      AtomicU64::new(0),
+            sig_blocked:      AtomicU64::new(0),
         })
     }
 
@@ -142,6 +178,7 @@ impl Process {
             STATE_RUNNING                => "R",
             STATE_BLOCKED                => "B",
             STATE_DEAD                   => "X",
+            STATE_STOPPED                => "T",
             _                            => "?",
         }
     }
@@ -195,6 +232,15 @@ impl Process {
         Some(p)
     }
 
+    pub fn new_fork(name: &'static str, ppid: u64, cr3: u64, ctx: &UserContext, fs_base: u64) -> Box<Self> {
+        let mut p = Self::alloc_raw(name, 10, cr3);
+        let top = p.stack_top();
+        p.rsp.store(build_user_frame_ctx(top, ctx), Ordering::Relaxed);
+        p.ppid.store(ppid, Ordering::Relaxed);
+        p.fs_base.store(fs_base, Ordering::Relaxed);
+        p
+    }
+
     pub fn cleanup_user_address_space(&mut self) {
         if self.cr3 == 0 || self.cr3 == crate::vmm::kernel_cr3() { return; }
         let mut aspace = AddressSpace::from_raw(self.cr3);
@@ -216,6 +262,25 @@ fn build_user_frame(kernel_stack_top: u64, rip: u64, user_rsp: u64) -> u64 {
     )
 }
 
+fn build_user_frame_ctx(kernel_stack_top: u64, ctx: &UserContext) -> u64 {
+    let rsp = write_frame(
+        kernel_stack_top, ctx.rip,
+        crate::gdt::user_code_selector().0 as u64,
+        ctx.rsp,
+        crate::gdt::user_data_selector().0 as u64,
+    );
+    let regs = [
+        ctx.rax, ctx.rbx, ctx.rcx, ctx.rdx, ctx.rsi, ctx.rdi, ctx.rbp,
+        ctx.r8, ctx.r9, ctx.r10, ctx.r11, ctx.r12, ctx.r13, ctx.r14, ctx.r15,
+    ];
+    unsafe {
+        let f = rsp as *mut u64;
+        for (i, &r) in regs.iter().enumerate() { f.add(i).write(r); }
+        f.add(17).write(ctx.rflags | 0x202);
+    }
+    rsp
+}
+
 fn write_frame(kernel_stack_top: u64, rip: u64, cs: u64, iret_rsp: u64, ss: u64) -> u64 {
     let rsp = kernel_stack_top - FRAME_SIZE;
     unsafe {
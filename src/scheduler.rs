@@ -11,7 +11,7 @@ use x86_64::instructions::interrupts;
 
 use crate::process::{
     pid_range, Process, CPU_ALL,
-    STATE_BLOCKED, STATE_DEAD, STATE_READY, STATE_RUNNING, STATE_SLEEPING,
+    STATE_BLOCKED, STATE_DEAD, STATE_READY, STATE_RUNNING, STATE_SLEEPING, STATE_STOPPED,
 };
 
 const CPU_WINDOW_TICKS: u64   = 250;
@@ -201,6 +201,22 @@ static TOTAL_SWITCHES: AtomicU64 = AtomicU64::new(0);
 
 static PROC_TABLE: Mutex<BTreeMap<u64, Box<Process>>> = Mutex::new(BTreeMap::new());
 
+const WAIT_CAUSE: &str = "wait";
+
+struct Zombie {
+    ppid:   u64,
+    status: u64,
+}
+
+static ZOMBIES: Mutex<BTreeMap<u64, Zombie>> = Mutex::new(BTreeMap::new());
+
+pub enum WaitStatus {
+    Exited(u64, u64),
+    Running,
+    NoChild,
+    Interrupted,
+}
+
 pub trait Task: Send {
     fn run(self: Box<Self>);
 }
@@ -212,7 +228,7 @@ impl<F: FnOnce() + Send> Task for F {
 static WORK_QUEUE: Mutex<VecDeque<Box<dyn Task>>> = Mutex::new(VecDeque::new());
 
 pub fn submit_task<F: FnOnce() + Send + 'static>(f: F) {
-    WORK_QUEUE.lock().push_back(Box::new(f));
+    interrupts::without_interrupts(|| WORK_QUEUE.lock().push_back(Box::new(f)));
 }
 
 fn worker_loop() -> ! {
@@ -330,11 +346,13 @@ pub fn reap_dead() {
 
         if let Some(mut p) = table.remove(&pid) {
             drop(table);
-            crate::mmap::vma_cleanup(p.cr3);
             if let Some(phys) = p.user_stack_phys.take() {
                 crate::pmm::free_frames(phys, crate::process::USER_STACK_PAGES);
             }
-            if p.cr3 != 0 && p.cr3 != crate::vmm::kernel_cr3() {
+            if p.cr3 != 0 && p.cr3 != crate::vmm::kernel_cr3()
+                && crate::vmm::release_address_space(p.cr3)
+            {
+                crate::mmap::vma_cleanup(p.cr3);
                 let mut aspace = crate::vmm::AddressSpace { cr3: p.cr3 };
                 aspace.free_address_space();
             }
@@ -379,16 +397,159 @@ pub fn current_pid() -> u64 {
     CURRENT_PID.load(Ordering::Relaxed)
 }
 
+pub fn with_process<R>(pid: u64, f: impl FnOnce(&Process) -> R) -> Option<R> {
+    interrupts::without_interrupts(|| {
+        let ptr = unsafe { PROC_INDEX.get_raw(pid) };
+        if ptr.is_null() { return None; }
+        Some(f(unsafe { &*ptr }))
+    })
+}
+
 pub fn kill(pid: u64) {
+    terminate(pid, crate::signal::SIGKILL);
+}
+
+pub fn exit_status(code: u64) -> u64 {
+    (code & 0xFF) << 8
+}
+
+pub fn terminate(pid: u64, status: u64) {
+    interrupts::without_interrupts(|| {
+        let ptr = unsafe { PROC_INDEX.get_raw(pid) };
+        if ptr.is_null() { return; }
+        let p = unsafe { &*ptr };
+        if p.state.load(Ordering::Relaxed) == STATE_DEAD { return; }
+        p.state.store(STATE_DEAD, Ordering::Relaxed);
+        unsafe { RUN_QUEUE.remove_raw(pid) };
+
+        let ppid   = p.ppid.load(Ordering::Relaxed);
+        let parent = unsafe { PROC_INDEX.get_raw(ppid) };
+        let mut zombies = ZOMBIES.lock();
+        zombies.retain(|_, z| z.ppid != pid);
+        let notify = ppid != 0 && !parent.is_null()
+            && unsafe { &*parent }.state.load(Ordering::Relaxed) != STATE_DEAD;
+        if notify {
+            zombies.insert(pid, Zombie { ppid, status });
+        }
+        drop(zombies);
+        crate::signal::cleanup(pid);
+        crate::fdtable::release_deferred(pid);
+
+        let max = pid_range().min(MAX_PROCS as u64) as usize;
+        let arr = unsafe { &*PROC_INDEX.0.get() };
+        for &c in arr.iter().take(max) {
+            if c.is_null() { continue; }
+            let c = unsafe { &*c };
+            if c.ppid.load(Ordering::Relaxed) == pid {
+                c.ppid.store(0, Ordering::Relaxed);
+            }
+        }
+
+        crate::serial_println!("[sched] kill pid={} status={:#x}", pid, status);
+
+        if notify {
+            crate::signal::send(ppid, crate::signal::SIGCHLD);
+        }
+        if !parent.is_null() {
+            let pp = unsafe { &*parent };
+            if pp.state.load(Ordering::Relaxed) == STATE_BLOCKED
+                && core::ptr::eq(pp.blocked_cause.load(Ordering::Relaxed), WAIT_CAUSE.as_ptr())
+            {
+                wakeup(ppid);
+            }
+        }
+    });
+}
+
+fn has_live_child(ppid: u64, pid: i64) -> bool {
+    let max = pid_range().min(MAX_PROCS as u64) as usize;
+    let arr = unsafe { &*PROC_INDEX.0.get() };
+    arr.iter().take(max).any(|&c| {
+        if c.is_null() { return false; }
+        let c = unsafe { &*c };
+        c.ppid.load(Ordering::Relaxed) == ppid
+            && c.state.load(Ordering::Relaxed) != STATE_DEAD
+            && (pid <= 0 || c.pid == pid as u64)
+    })
+}
+
+pub fn wait_child(ppid: u64, pid: i64, nohang: bool) -> WaitStatus {
+    loop {
+        let r = interrupts::without_interrupts(|| {
+            let mut zombies = ZOMBIES.lock();
+            let found = zombies.iter()
+                .find(|(&zpid, z)| z.ppid == ppid && (pid <= 0 || zpid == pid as u64))
+                .map(|(&zpid, z)| (zpid, z.status));
+            if let Some((zpid, status)) = found {
+                zombies.remove(&zpid);
+                return WaitStatus::Exited(zpid, status);
+            }
+            drop(zombies);
+
+            if !has_live_child(ppid, pid) {
+                return WaitStatus::NoChild;
+            }
+            if crate::signal::has_pending(ppid) {
+                return WaitStatus::Interrupted;
+            }
+            if !nohang {
+                let ptr = unsafe { PROC_INDEX.get_raw(ppid) };
+                if !ptr.is_null() {
+                    let p = unsafe { &*ptr };
+                    p.blocked_cause.store(WAIT_CAUSE.as_ptr() as *mut u8, Ordering::Relaxed);
+                    p.state.store(STATE_BLOCKED, Ordering::Relaxed);
+                }
+            }
+            WaitStatus::Running
+        });
+
+        match r {
+            WaitStatus::Running if !nohang => unsafe { software_context_switch() },
+            other => return other,
+        }
+    }
+}
+
+pub fn stop(pid: u64) {
     interrupts::without_interrupts(|| {
         let ptr = unsafe { PROC_INDEX.get_raw(pid) };
         if ptr.is_null() { return; }
-        unsafe { &*ptr }.state.store(STATE_DEAD, Ordering::Relaxed);
+        let p = unsafe { &*ptr };
+        match p.state.load(Ordering::Relaxed) {
+            STATE_DEAD | STATE_STOPPED => return,
+            _ => {}
+        }
+        p.state.store(STATE_STOPPED, Ordering::Relaxed);
         unsafe { RUN_QUEUE.remove_raw(pid) };
-        crate::serial_println!("[sched] kill pid={}", pid);
+        crate::serial_println!("[sched] stop pid={}", pid);
+    });
+}
+
+pub fn resume(pid: u64) {
+    interrupts::without_interrupts(|| {
+        let ptr = unsafe { PROC_INDEX.get_raw(pid) };
+        if ptr.is_null() { return; }
+        let p = unsafe { &*ptr };
+        if p.state.load(Ordering::Relaxed) != STATE_STOPPED { return; }
+        let min_vr = MIN_VRUNTIME.load(Ordering::Relaxed);
+        let vr     = p.vruntime.load(Ordering::Relaxed).max(min_vr);
+        p.vruntime.store(vr, Ordering::Relaxed);
+        p.state.store(STATE_READY, Ordering::Relaxed);
+        unsafe { RUN_QUEUE.push_raw(ptr) };
+        crate::serial_println!("[sched] continue pid={}", pid);
     });
 }
 
+pub fn replace_cr3(pid: u64, cr3: u64) -> u64 {
+    interrupts::without_interrupts(|| {
+        let ptr = unsafe { PROC_INDEX.get_raw(pid) };
+        if ptr.is_null() { return 0; }
+        let old = unsafe { (*ptr).cr3 };
+        unsafe { (*ptr).cr3 = cr3 };
+        old
+    })
+}
+
 pub fn wakeup(pid: u64) {
     interrupts::without_interrupts(|| {
         let ptr = unsafe { PROC_INDEX.get_raw(pid) };
@@ -461,6 +622,22 @@ pub fn block_current(cause: &'static str) {
     unsafe { software_context_switch() }
 }
 
+pub fn block_unless<F: FnOnce() -> bool>(cause: &'static str, ready: F) {
+    let blocked = interrupts::without_interrupts(|| {
+        if ready() { return false; }
+        let curr = CURRENT_PID.load(Ordering::Relaxed);
+        let ptr  = unsafe { PROC_INDEX.get_raw(curr) };
+        if ptr.is_null() { return false; }
+        let p = unsafe { &*ptr };
+        p.blocked_cause.store(cause.as_ptr() as *mut u8, Ordering::Relaxed);
+        p.state.store(STATE_BLOCKED, Ordering::Relaxed);
+        true
+    });
+    if blocked {
+        unsafe { software_context_switch() }
+    }
+}
+
 pub fn total_switches() -> u64 {
     TOTAL_SWITCHES.load(Ordering::Relaxed)
 }
@@ -607,6 +784,14 @@ pub unsafe extern "C" fn schedule_from_isr(old_rsp: u64) -> u64 {
 
     crate::gdt::set_kernel_stack(new_rsp0);
 
+    if next_ptr != curr_ptr {
+        use x86_64::registers::model_specific::FsBase;
+        if !curr_ptr.is_null() {
+            (*curr_ptr).fs_base.store(FsBase::read().as_u64(), Ordering::Relaxed);
+        }
+        FsBase::write(x86_64::VirtAddr::new_truncate(next.fs_base.load(Ordering::Relaxed)));
+    }
+
     if old_cr3 != new_cr3 && new_cr3 != 0 {
         core::arch::asm!(
             "mov cr3, {}",
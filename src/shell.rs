@@ -1,7 +1,7 @@
 use crate::commands;
 use crate::commands::ext2_cmds;
 use crate::{console, cprint, cprintln, print, serial_println};
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use lazy_static::lazy_static;
 use pc_keyboard::DecodedKey;
 use spin::Mutex;
@@ -167,6 +167,16 @@ impl PendingCmd {
 }
 
 static PENDING: Mutex<PendingCmd> = Mutex::new(PendingCmd::new());
+static STATUS:      AtomicU8 = AtomicU8::new(0);
+static LAST_STATUS: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_status(code: u8) {
+    STATUS.store(code, Ordering::SeqCst);
+}
+
+pub fn last_status() -> u8 {
+    LAST_STATUS.load(Ordering::SeqCst)
+}
 
 lazy_static! {
     pub static ref SESSION: Mutex<Session> = Mutex::new(Session {
@@ -201,12 +211,14 @@ pub fn dispatcher(line: &str) {
     let (cmd, rest) = line.split_once(' ').unwrap_or((line, ""));
     let rest = rest.trim();
 
+    STATUS.store(0, Ordering::SeqCst);
     match cmd {
         "fs.list"   => ext2_cmds::cmd_fs_list(),
         "fs.select" => ext2_cmds::cmd_fs_select(rest),
         "fs.umount" => ext2_cmds::cmd_fs_umount(rest),
         _ => commands::execute(line),
     }
+    LAST_STATUS.store(STATUS.load(Ordering::SeqCst), Ordering::SeqCst);
 }
 
 pub fn process_pending() {
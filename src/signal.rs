@@ -0,0 +1,375 @@
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use core::sync::atomic::Ordering;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use crate::process::UserContext;
+use crate::syscall::SyscallFrame;
+
+pub const NSIG: usize = 32;
+
+pub const SIGHUP:    u64 = 1;
+pub const SIGINT:    u64 = 2;
+pub const SIGQUIT:   u64 = 3;
+pub const SIGILL:    u64 = 4;
+pub const SIGTRAP:   u64 = 5;
+pub const SIGABRT:   u64 = 6;
+pub const SIGBUS:    u64 = 7;
+pub const SIGFPE:    u64 = 8;
+pub const SIGKILL:   u64 = 9;
+pub const SIGUSR1:   u64 = 10;
+pub const SIGSEGV:   u64 = 11;
+pub const SIGUSR2:   u64 = 12;
+pub const SIGPIPE:   u64 = 13;
+pub const SIGALRM:   u64 = 14;
+pub const SIGTERM:   u64 = 15;
+pub const SIGCHLD:   u64 = 17;
+pub const SIGCONT:   u64 = 18;
+pub const SIGSTOP:   u64 = 19;
+pub const SIGTSTP:   u64 = 20;
+pub const SIGTTIN:   u64 = 21;
+pub const SIGTTOU:   u64 = 22;
+pub const SIGURG:    u64 = 23;
+pub const SIGWINCH:  u64 = 28;
+
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+pub const SA_NODEFER:   u64 = 0x4000_0000;
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+
+pub const SIG_BLOCK:   u64 = 0;
+pub const SIG_UNBLOCK: u64 = 1;
+pub const SIG_SETMASK: u64 = 2;
+
+const UNBLOCKABLE: u64 = bit(SIGKILL) | bit(SIGSTOP);
+const RED_ZONE:    u64 = 128;
+const USER_RFLAGS: u64 = 0x0CD5;
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct SigAction {
+    pub handler:  u64,
+    pub flags:    u64,
+    pub restorer: u64,
+    pub mask:     u64,
+}
+
+#[repr(C)]
+struct SigFrame {
+    restorer: u64,
+    signo:    u64,
+    mask:     u64,
+    ctx:      UserContext,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DefaultAction {
+    Terminate,
+    Abort,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+enum Delivery {
+    None,
+    Handler,
+    Terminated,
+    Stopped,
+}
+
+static ACTIONS: Mutex<BTreeMap<u64, [SigAction; NSIG]>> = Mutex::new(BTreeMap::new());
+
+pub const fn bit(sig: u64) -> u64 {
+    1 << (sig - 1)
+}
+
+pub fn valid(sig: u64) -> bool {
+    sig >= 1 && (sig as usize) < NSIG
+}
+
+fn default_action(sig: u64) -> DefaultAction {
+    match sig {
+        SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV => DefaultAction::Abort,
+        SIGCHLD | SIGURG | SIGWINCH                                       => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU                             => DefaultAction::Stop,
+        SIGCONT                                                           => DefaultAction::Continue,
+        _                                                                 => DefaultAction::Terminate,
+    }
+}
+
+pub fn from_name(name: &str) -> Option<u64> {
+    if let Ok(n) = name.parse::<u64>() {
+        return if valid(n) { Some(n) } else { None };
+    }
+    let name = name.strip_prefix("SIG").unwrap_or(name);
+    Some(match name {
+        "HUP"  => SIGHUP,  "INT"  => SIGINT,  "QUIT" => SIGQUIT, "ILL"  => SIGILL,
+        "TRAP" => SIGTRAP, "ABRT" => SIGABRT, "BUS"  => SIGBUS,  "FPE"  => SIGFPE,
+        "KILL" => SIGKILL, "USR1" => SIGUSR1, "SEGV" => SIGSEGV, "USR2" => SIGUSR2,
+        "PIPE" => SIGPIPE, "ALRM" => SIGALRM, "TERM" => SIGTERM, "CHLD" => SIGCHLD,
+        "CONT" => SIGCONT, "STOP" => SIGSTOP, "TSTP" => SIGTSTP, "TTIN" => SIGTTIN,
+        "TTOU" => SIGTTOU, "URG"  => SIGURG,  "WINCH" => SIGWINCH,
+        _ => return None,
+    })
+}
+
+fn action(pid: u64, sig: u64) -> SigAction {
+    interrupts::without_interrupts(|| {
+        ACTIONS.lock().get(&pid).map(|a| a[sig as usize]).unwrap_or_default()
+    })
+}
+
+pub fn get_action(pid: u64, sig: u64) -> SigAction {
+    action(pid, sig)
+}
+
+pub fn set_action(pid: u64, sig: u64, act: SigAction) -> SigAction {
+    interrupts::without_interrupts(|| {
+        let mut table = ACTIONS.lock();
+        let acts = table.entry(pid).or_insert([SigAction::default(); NSIG]);
+        let old = acts[sig as usize];
+        acts[sig as usize] = SigAction { mask: act.mask & !UNBLOCKABLE, ..act };
+        old
+    })
+}
+
+pub fn fork(parent: u64, child: u64) {
+    interrupts::without_interrupts(|| {
+        let mut table = ACTIONS.lock();
+        if let Some(acts) = table.get(&parent).copied() {
+            table.insert(child, acts);
+        }
+    });
+}
+
+pub fn exec(pid: u64) {
+    interrupts::without_interrupts(|| {
+        if let Some(acts) = ACTIONS.lock().get_mut(&pid) {
+            for a in acts.iter_mut() {
+                if a.handler != SIG_IGN {
+                    *a = SigAction::default();
+                }
+            }
+        }
+    });
+}
+
+pub fn cleanup(pid: u64) {
+    interrupts::without_interrupts(|| { ACTIONS.lock().remove(&pid); });
+}
+
+pub fn blocked(pid: u64) -> u64 {
+    crate::scheduler::with_process(pid, |p| p.sig_blocked.load(Ordering::Relaxed)).unwrap_or(0)
+}
+
+pub fn set_blocked(pid: u64, mask: u64) {
+    crate::scheduler::with_process(pid, |p| p.sig_blocked.store(mask & !UNBLOCKABLE, Ordering::Relaxed));
+}
+
+pub fn has_pending(pid: u64) -> bool {
+    crate::scheduler::with_process(pid, |p| {
+        p.sig_pending.load(Ordering::Relaxed) & !p.sig_blocked.load(Ordering::Relaxed) != 0
+    }).unwrap_or(false)
+}
+
+fn ignored(pid: u64, sig: u64) -> bool {
+    let act = action(pid, sig);
+    act.handler == SIG_IGN
+        || (act.handler == SIG_DFL && default_action(sig) == DefaultAction::Ignore)
+}
+
+pub fn send(pid: u64, sig: u64) -> bool {
+    let alive = crate::scheduler::with_process(pid, |p| {
+        p.state.load(Ordering::Relaxed) != crate::process::STATE_DEAD
+    }).unwrap_or(false);
+    if !alive { return false; }
+
+    match sig {
+        SIGKILL => {
+            crate::scheduler::terminate(pid, SIGKILL);
+            return true;
+        }
+        SIGSTOP => {
+            crate::scheduler::stop(pid);
+            return true;
+        }
+        SIGCONT => {
+            crate::scheduler::resume(pid);
+            crate::scheduler::with_process(pid, |p| {
+                let stops = bit(SIGTSTP) | bit(SIGTTIN) | bit(SIGTTOU);
+                p.sig_pending.fetch_and(!stops, Ordering::Relaxed);
+            });
+        }
+        _ => {}
+    }
+
+    if ignored(pid, sig) { return true; }
+
+    let wake = crate::scheduler::with_process(pid, |p| {
+        p.sig_pending.fetch_or(bit(sig), Ordering::Relaxed);
+        p.sig_blocked.load(Ordering::Relaxed) & bit(sig) == 0
+    }).unwrap_or(false);
+    if wake {
+        crate::scheduler::wakeup(pid);
+    }
+    crate::serial_println!("[signal] pid={} sig={}", pid, sig);
+    true
+}
+
+fn take_pending(pid: u64) -> Option<u64> {
+    crate::scheduler::with_process(pid, |p| {
+        let ready = p.sig_pending.load(Ordering::Relaxed) & !p.sig_blocked.load(Ordering::Relaxed);
+        if ready == 0 { return None; }
+        let sig = ready.trailing_zeros() as u64 + 1;
+        p.sig_pending.fetch_and(!bit(sig), Ordering::Relaxed);
+        Some(sig)
+    }).flatten()
+}
+
+fn push_frame(pid: u64, sig: u64, act: &SigAction, ctx: &mut UserContext) -> bool {
+    let cr3  = crate::syscall::current_cr3();
+    let size = core::mem::size_of::<SigFrame>() as u64;
+    let sp   = match ctx.rsp.checked_sub(RED_ZONE + size) {
+        Some(v) => (v & !0xF).wrapping_sub(8),
+        None    => return false,
+    };
+    if !crate::syscall::user_ptr_writable(cr3, sp, size) {
+        return false;
+    }
+
+    let mask  = blocked(pid);
+    let frame = SigFrame { restorer: act.restorer, signo: sig, mask, ctx: *ctx };
+    unsafe { (sp as *mut SigFrame).write(frame) };
+
+    let mut new_mask = mask | act.mask;
+    if act.flags & SA_NODEFER == 0 {
+        new_mask |= bit(sig);
+    }
+    set_blocked(pid, new_mask);
+    if act.flags & SA_RESETHAND != 0 {
+        set_action(pid, sig, SigAction::default());
+    }
+
+    *ctx = UserContext {
+        rdi:    sig,
+        rsi:    sp + core::mem::offset_of!(SigFrame, ctx) as u64,
+        rip:    act.handler,
+        rsp:    sp,
+        rflags: ctx.rflags & !0x0500,
+        ..*ctx
+    };
+    true
+}
+
+fn deliver(pid: u64, ctx: &mut UserContext) -> Delivery {
+    while let Some(sig) = take_pending(pid) {
+        let act = action(pid, sig);
+        match act.handler {
+            SIG_IGN => continue,
+            SIG_DFL => match default_action(sig) {
+                DefaultAction::Ignore | DefaultAction::Continue => continue,
+                DefaultAction::Terminate | DefaultAction::Abort => {
+                    crate::scheduler::terminate(pid, sig);
+                    return Delivery::Terminated;
+                }
+                DefaultAction::Stop => {
+                    crate::scheduler::stop(pid);
+                    return Delivery::Stopped;
+                }
+            },
+            _ => {
+                if push_frame(pid, sig, &act, ctx) {
+                    crate::serial_println!("[signal] pid={} sig={} -> handler {:#x}", pid, sig, act.handler);
+                    return Delivery::Handler;
+                }
+                crate::serial_println!("[signal] pid={} sig={} bad stack rsp={:#x}", pid, sig, ctx.rsp);
+                crate::scheduler::terminate(pid, SIGSEGV);
+                return Delivery::Terminated;
+            }
+        }
+    }
+    Delivery::None
+}
+
+pub fn deliver_syscall(frame: &mut SyscallFrame, ret: u64) -> u64 {
+    let pid = crate::scheduler::current_pid();
+    loop {
+        if !has_pending(pid) { return ret; }
+        let mut ctx = frame.user_context();
+        ctx.rax = ret;
+        match deliver(pid, &mut ctx) {
+            Delivery::None => return ret,
+            Delivery::Handler => {
+                frame.set_user_context(&ctx);
+                return ctx.rax;
+            }
+            Delivery::Terminated | Delivery::Stopped => crate::scheduler::yield_now(),
+        }
+    }
+}
+
+pub unsafe fn deliver_isr(mut rsp: u64) -> u64 {
+    loop {
+        let f = rsp as *mut u64;
+        if f.add(16).read() & 3 != 3 { return rsp; }
+
+        let pid = crate::scheduler::current_pid();
+        if !has_pending(pid) { return rsp; }
+
+        let regs: [u64; 20] = core::ptr::read(f as *const [u64; 20]);
+        let mut ctx = UserContext {
+            rax: regs[0],  rbx: regs[1],  rcx: regs[2],  rdx: regs[3],
+            rsi: regs[4],  rdi: regs[5],  rbp: regs[6],
+            r8:  regs[7],  r9:  regs[8],  r10: regs[9],  r11: regs[10],
+            r12: regs[11], r13: regs[12], r14: regs[13], r15: regs[14],
+            rip: regs[15], rflags: regs[17], rsp: regs[18],
+        };
+        match deliver(pid, &mut ctx) {
+            Delivery::None => return rsp,
+            Delivery::Handler => {
+                let out = [
+                    ctx.rax, ctx.rbx, ctx.rcx, ctx.rdx, ctx.rsi, ctx.rdi, ctx.rbp,
+                    ctx.r8, ctx.r9, ctx.r10, ctx.r11, ctx.r12, ctx.r13, ctx.r14, ctx.r15,
+                ];
+                for (i, &r) in out.iter().enumerate() { f.add(i).write(r); }
+                f.add(15).write(ctx.rip);
+                f.add(17).write(ctx.rflags);
+                f.add(18).write(ctx.rsp);
+                return rsp;
+            }
+            Delivery::Terminated | Delivery::Stopped => {
+                rsp = crate::scheduler::schedule_from_isr(rsp);
+            }
+        }
+    }
+}
+
+pub fn sigreturn(frame: &mut SyscallFrame) -> u64 {
+    let pid  = crate::scheduler::current_pid();
+    let cr3  = crate::syscall::current_cr3();
+    let sp   = frame.rsp.wrapping_sub(8);
+    let size = core::mem::size_of::<SigFrame>() as u64;
+
+    if !crate::syscall::user_ptr_writable(cr3, sp, size) {
+        crate::scheduler::terminate(pid, SIGSEGV);
+        crate::scheduler::yield_now();
+        return 0;
+    }
+
+    let sf  = unsafe { (sp as *const SigFrame).read() };
+    let ctx = sf.ctx;
+    if ctx.rip > crate::syscall::USER_MAX || ctx.rsp > crate::syscall::USER_MAX {
+        crate::scheduler::terminate(pid, SIGSEGV);
+        crate::scheduler::yield_now();
+        return 0;
+    }
+
+    set_blocked(pid, sf.mask);
+    frame.set_user_context(&UserContext {
+        rflags: (frame.rflags & !USER_RFLAGS) | (ctx.rflags & USER_RFLAGS) | 0x202,
+        ..ctx
+    });
+    ctx.rax
+}
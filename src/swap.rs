@@ -1,6 +1,8 @@
+extern crate alloc;
+use alloc::boxed::Box;
 use spin::Mutex;
-use crate::ata::{AtaDrive, AtaError};
 use crate::pmm;
+use crate::vfs::block_dev::{BlockDevice, BlockError};
 
 const SWAP_PAGE_SIZE: u32    = 4096;
 const SWAP_SECS_PER_PAGE: u32 = SWAP_PAGE_SIZE / 512;
@@ -72,6 +74,7 @@ impl SwapState {
 }
 
 static SWAP: Mutex<SwapState> = Mutex::new(SwapState::new());
+static SWAP_DEV: Mutex<Option<Box<dyn BlockDevice>>> = Mutex::new(None);
 
 pub fn swap_total_pages() -> u32  { SWAP.lock().total_pages }
 pub fn swap_used_pages()  -> u32  { SWAP.lock().used_pages }
@@ -88,24 +91,21 @@ pub fn swap_total_kb() -> u32 { swap_total_pages() * (SWAP_PAGE_SIZE / 1024) }
 pub fn swap_used_kb()  -> u32 { swap_used_pages()  * (SWAP_PAGE_SIZE / 1024) }
 pub fn swap_free_kb()  -> u32 { swap_free_pages()  * (SWAP_PAGE_SIZE / 1024) }
 
-fn read_page(drive: &mut AtaDrive, lba_base: u32, dst: *mut u8) -> Result<(), AtaError> {
-    for s in 0..SWAP_SECS_PER_PAGE {
-        let off = s as usize * 512;
-        let mut sec = [0u8; 512];
-        drive.read_sector(lba_base + s, &mut sec)?;
-        unsafe { core::ptr::copy_nonoverlapping(sec.as_ptr(), dst.add(off), 512); }
-    }
-    Ok(())
+fn read_page(dev: &mut dyn BlockDevice, lba: u64, dst: *mut u8) -> Result<(), BlockError> {
+    let page = unsafe { core::slice::from_raw_parts_mut(dst, SWAP_PAGE_SIZE as usize) };
+    dev.read(lba, SWAP_SECS_PER_PAGE, page)
+}
+
+fn write_page(dev: &mut dyn BlockDevice, lba: u64, src: *const u8) -> Result<(), BlockError> {
+    let page = unsafe { core::slice::from_raw_parts(src, SWAP_PAGE_SIZE as usize) };
+    dev.write(lba, SWAP_SECS_PER_PAGE, page)
 }
 
-fn write_page(drive: &mut AtaDrive, lba_base: u32, src: *const u8) -> Result<(), AtaError> {
-    for s in 0..SWAP_SECS_PER_PAGE {
-        let off = s as usize * 512;
-        let mut sec = [0u8; 512];
-        unsafe { core::ptr::copy_nonoverlapping(src.add(off), sec.as_mut_ptr(), 512); }
-        drive.write_sector(lba_base + s, &sec)?;
+fn with_device<R>(f: impl FnOnce(&mut dyn BlockDevice) -> Result<R, BlockError>) -> Result<R, SwapError> {
+    match SWAP_DEV.lock().as_deref_mut() {
+        Some(dev) => f(dev).map_err(SwapError::Io),
+        None      => Err(SwapError::NotActive),
     }
-    Ok(())
 }
 
 fn phys_to_virt(phys: u64) -> u64 {
@@ -113,11 +113,11 @@ fn phys_to_virt(phys: u64) -> u64 {
     phys + hhdm
 }
 
-fn lba_for_slot(slot: u32) -> u32 {
-    SWAP.lock().partition_lba + slot * SWAP_SECS_PER_PAGE
+fn lba_for_slot(slot: u32) -> u64 {
+    slot as u64 * SWAP_SECS_PER_PAGE as u64
 }
 
-pub fn swap_out_internal(phys_addr: u64, drive: &mut AtaDrive) -> Result<u32, SwapError> {
+pub fn swap_out_internal(phys_addr: u64) -> Result<u32, SwapError> {
     let slot = {
         let mut s = SWAP.lock();
         if !s.active { return Err(SwapError::NotActive); }
@@ -125,14 +125,16 @@ pub fn swap_out_internal(phys_addr: u64, drive: &mut AtaDrive) -> Result<u32, Sw
     };
 
     let lba_base = lba_for_slot(slot);
-    write_page(drive, lba_base, phys_to_virt(phys_addr) as *const u8)
-        .map_err(SwapError::Io)?;
+    if let Err(e) = with_device(|dev| write_page(dev, lba_base, phys_to_virt(phys_addr) as *const u8)) {
+        SWAP.lock().free_slot(slot);
+        return Err(e);
+    }
 
     crate::serial_println!("[swap] swap_out: phys={:#x} -> slot={}", phys_addr, slot);
     Ok(slot)
 }
 
-pub fn swap_in_internal(slot: u32, phys_addr: u64, drive: &mut AtaDrive) -> Result<(), SwapError> {
+pub fn swap_in_internal(slot: u32, phys_addr: u64) -> Result<(), SwapError> {
     {
         let s = SWAP.lock();
         if !s.active { return Err(SwapError::NotActive); }
@@ -140,20 +142,19 @@ pub fn swap_in_internal(slot: u32, phys_addr: u64, drive: &mut AtaDrive) -> Resu
     }
 
     let lba_base = lba_for_slot(slot);
-    read_page(drive, lba_base, phys_to_virt(phys_addr) as *mut u8)
-        .map_err(SwapError::Io)?;
+    with_device(|dev| read_page(dev, lba_base, phys_to_virt(phys_addr) as *mut u8))?;
 
     SWAP.lock().free_slot(slot);
     crate::serial_println!("[swap] swap_in: slot={} -> phys={:#x}", slot, phys_addr);
     Ok(())
 }
 
-pub fn swap_out(phys_addr: u64, drive: &mut AtaDrive) -> Result<u32, SwapError> {
-    swap_out_internal(phys_addr, drive)
+pub fn swap_out(phys_addr: u64) -> Result<u32, SwapError> {
+    swap_out_internal(phys_addr)
 }
 
-pub fn swap_in(slot: u32, phys_addr: u64, drive: &mut AtaDrive) -> Result<(), SwapError> {
-    swap_in_internal(slot, phys_addr, drive)
+pub fn swap_in(slot: u32, phys_addr: u64) -> Result<(), SwapError> {
+    swap_in_internal(slot, phys_addr)
 }
 
 pub fn free_swap_slot(slot: u32) {
@@ -165,16 +166,12 @@ pub fn try_reclaim_page() -> Option<u64> {
     crate::swap_map::alloc_or_evict()
 }
 
-pub fn mkswap(
-    mut drive: AtaDrive,
-    partition_lba: u32,
-    partition_sectors: u32,
-    label: &str,
-) -> Result<(), AtaError> {
-    let total_pages = partition_sectors / SWAP_SECS_PER_PAGE;
+pub fn mkswap(dev: &mut dyn BlockDevice, label: &str) -> Result<(), BlockError> {
+    let sectors     = dev.sector_count().min(u32::MAX as u64) as u32;
+    let total_pages = sectors / SWAP_SECS_PER_PAGE;
     if total_pages < 10 {
         crate::serial_println!("[swap] partition too small: {} pages", total_pages);
-        return Err(AtaError::DeviceFault);
+        return Err(BlockError::OutOfRange);
     }
 
     let mut page0 = [0u8; 4096];
@@ -184,7 +181,7 @@ pub fn mkswap(
     page0[4..8].copy_from_slice(&last_page.to_le_bytes());
     page0[8..12].copy_from_slice(&0u32.to_le_bytes());
 
-    let uuid = guid_pseudo_swap(partition_lba);
+    let uuid = guid_pseudo_swap(sectors);
     page0[12..28].copy_from_slice(&uuid);
 
     let lbytes = label.as_bytes();
@@ -193,24 +190,24 @@ pub fn mkswap(
 
     page0[4086..4096].copy_from_slice(SWAP_MAGIC);
 
-    write_page(&mut drive, partition_lba, page0.as_ptr())?;
+    write_page(dev, 0, page0.as_ptr())?;
+    dev.flush()?;
 
-    crate::serial_println!("[swap] mkswap: lba={} pages={} label='{}'", partition_lba, total_pages, label);
+    crate::serial_println!("[swap] mkswap: sectors={} pages={} label='{}'", sectors, total_pages, label);
     Ok(())
 }
 
 pub fn swapon(
-    mut drive: AtaDrive,
+    mut dev: Box<dyn BlockDevice>,
     drive_idx: usize,
     partition_lba: u32,
-    partition_sectors: u32,
 ) -> Result<u32, SwapError> {
     if SWAP.lock().active {
         return Err(SwapError::AlreadyActive);
     }
 
     let mut page0 = [0u8; 4096];
-    read_page(&mut drive, partition_lba, page0.as_mut_ptr())
+    read_page(&mut *dev, 0, page0.as_mut_ptr())
         .map_err(SwapError::Io)?;
 
     if &page0[4086..4096] != SWAP_MAGIC {
@@ -225,7 +222,12 @@ pub fn swapon(
     }
 
     let total_pages = last_page + 1;
+    let sectors     = dev.sector_count();
+    if sectors != 0 && total_pages as u64 * SWAP_SECS_PER_PAGE as u64 > sectors {
+        return Err(SwapError::InvalidMagic);
+    }
 
+    *SWAP_DEV.lock() = Some(dev);
     {
         let mut s = SWAP.lock();
         s.active        = true;
@@ -263,6 +265,8 @@ pub fn swapoff() -> Result<(), SwapError> {
     s.used_pages    = 0;
     s.partition_lba = 0;
     s.bitmap        = [0u64; SWAP_BITMAP_WORDS];
+    drop(s);
+    *SWAP_DEV.lock() = None;
     crate::serial_println!("[swap] swapoff ok");
     Ok(())
 }
@@ -280,7 +284,7 @@ fn guid_pseudo_swap(seed: u32) -> [u8; 16] {
 
 #[derive(Debug)]
 pub enum SwapError {
-    Io(AtaError),
+    Io(BlockError),
     AlreadyActive,
     NotActive,
     InvalidMagic,
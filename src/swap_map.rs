@@ -43,6 +43,11 @@ impl SwapMap {
         self.entries[idx] = ReverseEntry::empty();
     }
 
+    pub fn owner(&self, phys: u64) -> Option<u64> {
+        let idx = Self::frame_idx(phys);
+        if idx < MAX_TRACKED && self.entries[idx].is_used() { Some(self.entries[idx].cr3) } else { None }
+    }
+
     pub fn touch(&mut self, phys: u64) {
         let idx = Self::frame_idx(phys);
         if idx < MAX_TRACKED && self.entries[idx].is_used() {
@@ -106,10 +111,18 @@ pub fn untrack(phys: u64) {
     SWAP_MAP.lock().untrack(phys);
 }
 
+pub fn owner(phys: u64) -> Option<u64> {
+    SWAP_MAP.lock().owner(phys)
+}
+
 pub fn touch(phys: u64) {
     SWAP_MAP.lock().touch(phys);
 }
 
+pub fn set_pinned(phys: u64, pinned: bool) {
+    SWAP_MAP.lock().set_pinned(phys, pinned);
+}
+
 pub fn age_all() {
     SWAP_MAP.lock().age_all();
 }
@@ -140,8 +153,7 @@ pub fn evict_one() -> Option<u64> {
 
     let (phys, cr3, virt) = SWAP_MAP.lock().pick_victim_and_pin()?;
 
-    let mut drive = crate::ata::AtaDrive::from_idx(swap::swap_drive_idx());
-    let slot = match swap::swap_out_internal(phys, &mut drive) {
+    let slot = match swap::swap_out_internal(phys) {
         Ok(s) => s,
         Err(e) => {
             SWAP_MAP.lock().set_pinned(phys, false);
@@ -171,8 +183,7 @@ pub fn try_swapin(cr3: u64, page_addr: u64, slot: u32) -> bool {
         }
     };
 
-    let mut drive = crate::ata::AtaDrive::from_idx(swap::swap_drive_idx());
-    match swap::swap_in_internal(slot, phys, &mut drive) {
+    match swap::swap_in_internal(slot, phys) {
         Ok(()) => {}
         Err(e) => {
             crate::pmm::free_frame(phys);
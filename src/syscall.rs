@@ -1,44 +1,31 @@
 extern crate alloc;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use alloc::collections::BTreeMap;
 use core::sync::atomic::{AtomicU64, Ordering};
-use spin::Mutex;
 use x86_64::registers::model_specific::{Efer, EferFlags, LStar, Star, SFMask};
 use x86_64::registers::rflags::RFlags;
+use x86_64::structures::paging::PageTableFlags;
 use x86_64::VirtAddr;
 use crate::gdt;
 use crate::mmap;
+use crate::net::socket::{Endpoint, Proto, Socket, AF_INET, AF_INET6, SOCKADDR6_LEN, SOCKADDR_LEN, SOCK_DGRAM, SOCK_STREAM};
+use crate::process::{Process, UserContext};
 use crate::vmm::AddressSpace;
 
 const PAGE_SIZE: u64 = 4096;
-const USER_MAX: u64 = 0x0000_7FFF_FFFF_FFFF;
+pub(crate) const USER_MAX: u64 = 0x0000_7FFF_FFFF_FFFF;
+const USER_CS: u64 = 0x2B;
+const USER_DS: u64 = 0x23;
 
-struct OpenFile {
-    data: Vec<u8>,
-    offset: usize,
-}
-
-struct ProcessFds {
-    files: BTreeMap<u64, OpenFile>,
-    next_fd: u64,
-}
-
-impl ProcessFds {
-    fn new() -> Self {
-        Self {
-            files: BTreeMap::new(),
-            next_fd: 3,
-        }
-    }
-}
+pub const CLONE_VM:     u64 = 0x0000_0100;
+pub const CLONE_FILES:  u64 = 0x0000_0400;
+pub const CLONE_SETTLS: u64 = 0x0008_0000;
 
-static FD_TABLE: Mutex<BTreeMap<u64, ProcessFds>> = Mutex::new(BTreeMap::new());
+pub const WNOHANG: u64 = 1;
 
-fn with_fds<F: FnOnce(&mut ProcessFds) -> R, R>(pid: u64, f: F) -> R {
-    let mut table = FD_TABLE.lock();
-    let pfds = table.entry(pid).or_insert_with(ProcessFds::new);
-    f(pfds)
-}
+const EXEC_MAX_ARGS: usize = 64;
+const EXEC_MAX_STR:  u64   = 4096;
 
 pub fn init() {
     unsafe {
@@ -50,19 +37,83 @@ pub fn init() {
         gdt::kernel_code_selector(),
         gdt::kernel_data_selector(),
     ).unwrap();
+    assert_eq!(gdt::user_code_selector().0 as u64, USER_CS);
+    assert_eq!(gdt::user_data_selector().0 as u64, USER_DS);
     LStar::write(VirtAddr::new(syscall_handler as *const () as u64));
     SFMask::write(RFlags::INTERRUPT_FLAG);
     crate::serial_println!("[syscall] MikuOS native table ready");
 }
 
+#[repr(C)]
+pub struct SyscallFrame {
+    pub rax:    u64,
+    pub rdx:    u64,
+    pub rsi:    u64,
+    pub rdi:    u64,
+    pub r8:     u64,
+    pub r9:     u64,
+    pub r10:    u64,
+    pub r15:    u64,
+    pub r14:    u64,
+    pub r13:    u64,
+    pub r12:    u64,
+    pub rbx:    u64,
+    pub rbp:    u64,
+    pub rcx:    u64,
+    pub r11:    u64,
+    pub rip:    u64,
+    pub cs:     u64,
+    pub rflags: u64,
+    pub rsp:    u64,
+    pub ss:     u64,
+}
+
+impl SyscallFrame {
+    pub fn user_context(&self) -> UserContext {
+        UserContext {
+            rax: self.rax, rbx: self.rbx, rcx: self.rcx, rdx: self.rdx,
+            rsi: self.rsi, rdi: self.rdi, rbp: self.rbp,
+            r8:  self.r8,  r9:  self.r9,  r10: self.r10, r11: self.r11,
+            r12: self.r12, r13: self.r13, r14: self.r14, r15: self.r15,
+            rip: self.rip, rflags: self.rflags, rsp: self.rsp,
+        }
+    }
+
+    pub fn set_user_context(&mut self, ctx: &UserContext) {
+        self.rax    = ctx.rax;
+        self.rbx    = ctx.rbx;
+        self.rcx    = ctx.rcx;
+        self.rdx    = ctx.rdx;
+        self.rsi    = ctx.rsi;
+        self.rdi    = ctx.rdi;
+        self.rbp    = ctx.rbp;
+        self.r8     = ctx.r8;
+        self.r9     = ctx.r9;
+        self.r10    = ctx.r10;
+        self.r11    = ctx.r11;
+        self.r12    = ctx.r12;
+        self.r13    = ctx.r13;
+        self.r14    = ctx.r14;
+        self.r15    = ctx.r15;
+        self.rip    = ctx.rip;
+        self.rflags = ctx.rflags;
+        self.rsp    = ctx.rsp;
+    }
+}
+
 #[unsafe(naked)]
 unsafe extern "C" fn syscall_handler() {
     core::arch::naked_asm!(
         "swapgs",
         "mov gs:[8], rsp",
         "mov rsp, gs:[0]",
+        "push {uds}",
+        "push qword ptr gs:[8]",
+        "push r11",
+        "push {ucs}",
         "push rcx",
         "push r11",
+        "push rcx",
         "push rbp",
         "push rbx",
         "push r12",
@@ -72,12 +123,22 @@ unsafe extern "C" fn syscall_handler() {
         "push r10",
         "push r9",
         "push r8",
+        "push rdi",
+        "push rsi",
+        "push rdx",
+        "push rax",
+        "mov r9,  rsp",
         "mov r8,  r10",
         "mov rcx, rdx",
         "mov rdx, rsi",
         "mov rsi, rdi",
         "mov rdi, rax",
         "call {handler}",
+        "cli",
+        "add rsp, 8",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
         "pop r8",
         "pop r9",
         "pop r10",
@@ -87,16 +148,17 @@ unsafe extern "C" fn syscall_handler() {
         "pop r12",
         "pop rbx",
         "pop rbp",
-        "pop r11",
         "pop rcx",
-        "mov rsp, gs:[8]",
+        "pop r11",
         "swapgs",
-        "sysretq",
+        "iretq",
         handler = sym dispatch,
+        ucs = const USER_CS,
+        uds = const USER_DS,
     );
 }
 
-fn current_cr3() -> u64 {
+pub(crate) fn current_cr3() -> u64 {
     let (frame, _) = x86_64::registers::control::Cr3::read();
     frame.start_address().as_u64()
 }
@@ -136,8 +198,9 @@ fn user_ptr_mapped(cr3: u64, ptr: u64, len: u64) -> bool {
     ok
 }
 
-extern "C" fn dispatch(nr: u64, a1: u64, a2: u64, a3: u64, a4: u64) -> u64 {
-    match nr {
+extern "C" fn dispatch(nr: u64, a1: u64, a2: u64, a3: u64, a4: u64, frame: *mut SyscallFrame) -> u64 {
+    let frame = unsafe { &mut *frame };
+    let ret = match nr {
         0 => sys_exit(a1),
         1 => sys_write(a1, a2, a3),
         2 => sys_read(a1, a2, a3),
@@ -156,167 +219,542 @@ extern "C" fn dispatch(nr: u64, a1: u64, a2: u64, a3: u64, a4: u64) -> u64 {
         15 => sys_map_lib(a1, a2),
         16 => sys_sleep(a1),
         17 => sys_uptime(),
+        18 => sys_clone(0, 0, 0, frame),
+        19 => sys_clone(a1, a2, a3, frame),
+        20 => sys_execve(a1, a2, a3, a4, frame),
+        21 => sys_wait4(a1 as i64, a2, a3),
+        22 => sys_sigaction(a1, a2, a3),
+        23 => sys_sigprocmask(a1, a2, a3),
+        24 => sys_kill(a1, a2),
+        25 => crate::signal::sigreturn(frame),
+        26 => sys_pipe(a1, a2),
+        27 => sys_dup2(a1, a2),
+        28 => sys_fcntl(a1, a2, a3),
+        29 => sys_open2(a1, a2, a3, a4),
+        30 => sys_pread(a1, a2, a3, a4),
+        31 => sys_pwrite(a1, a2, a3, a4),
+        32 => sys_ftruncate(a1, a2),
+        33 => sys_unlink(a1, a2),
+        34 => sys_mkdir(a1, a2, a3),
+        35 => sys_rmdir(a1, a2),
+        36 => sys_rename(a1, a2, a3, a4),
+        37 => sys_stat(a1, a2, a3, true),
+        38 => sys_stat(a1, a2, a3, false),
+        39 => sys_fstat(a1, a2),
+        40 => sys_getdents(a1, a2, a3),
+        41 => sys_socket(a1, a2, a3),
+        42 => sys_bind(a1, a2, a3),
+        43 => sys_listen(a1, a2),
+        44 => sys_accept(a1, a2, a3),
+        45 => sys_connect(a1, a2, a3),
+        46 => sys_sendto(a1, a2, a3, a4),
+        47 => sys_recvfrom(a1, a2, a3, a4),
+        48 => sys_shutdown(a1, a2),
         _ => {
             crate::serial_println!("[syscall] unknown nr={}", nr);
             err(ENOSYS)
         }
+    };
+    crate::signal::deliver_syscall(frame, ret)
+}
+
+pub(crate) fn user_ptr_writable(cr3: u64, ptr: u64, len: u64) -> bool {
+    if ptr == 0 || len == 0 || ptr > USER_MAX {
+        return false;
     }
+    let end = match ptr.checked_add(len) {
+        Some(e) if e <= USER_MAX + 1 => e,
+        _ => return false,
+    };
+
+    let mut va = ptr & !0xFFF;
+    while va < end {
+        let mut pte = match crate::vmm::read_pte_raw(cr3, va) {
+            Some(p) => p,
+            None => return false,
+        };
+        if crate::swap_map::is_swap_pte(pte) {
+            if !crate::swap_map::try_swapin(cr3, va, crate::swap_map::slot_from_pte(pte)) {
+                return false;
+            }
+            pte = crate::vmm::read_pte_raw(cr3, va).unwrap_or(0);
+        }
+        let flags = PageTableFlags::from_bits_truncate(pte);
+        if !flags.contains(PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE)
+            || !flags.intersects(PageTableFlags::WRITABLE | crate::vmm::PTE_COW)
+        {
+            return false;
+        }
+        va += PAGE_SIZE;
+    }
+    true
 }
 
 fn err(code: i64) -> u64 { code as u64 }
 
-const ENOENT: i64 = -2;
-const EBADF: i64 = -9;
-const ENOMEM: i64 = -12;
-const EFAULT: i64 = -14;
-const EINVAL: i64 = -22;
-const ENOSYS: i64 = -38;
+pub(crate) const EPERM: i64 = -1;
+pub(crate) const ENOENT: i64 = -2;
+pub(crate) const ESRCH: i64 = -3;
+pub(crate) const EINTR: i64 = -4;
+pub(crate) const EIO: i64 = -5;
+pub(crate) const E2BIG: i64 = -7;
+pub(crate) const EBADF: i64 = -9;
+pub(crate) const ECHILD: i64 = -10;
+pub(crate) const EAGAIN: i64 = -11;
+pub(crate) const ENOMEM: i64 = -12;
+pub(crate) const EACCES: i64 = -13;
+pub(crate) const EFAULT: i64 = -14;
+pub(crate) const EBUSY: i64 = -16;
+pub(crate) const EEXIST: i64 = -17;
+pub(crate) const EXDEV: i64 = -18;
+pub(crate) const ENOTDIR: i64 = -20;
+pub(crate) const EISDIR: i64 = -21;
+pub(crate) const EINVAL: i64 = -22;
+pub(crate) const ENFILE: i64 = -23;
+pub(crate) const EMFILE: i64 = -24;
+pub(crate) const EFBIG: i64 = -27;
+pub(crate) const ENOSPC: i64 = -28;
+pub(crate) const ESPIPE: i64 = -29;
+pub(crate) const EROFS: i64 = -30;
+pub(crate) const EPIPE: i64 = -32;
+pub(crate) const ENAMETOOLONG: i64 = -36;
+pub(crate) const ENOSYS: i64 = -38;
+pub(crate) const ENOTEMPTY: i64 = -39;
+pub(crate) const ELOOP: i64 = -40;
+pub(crate) const ENOTSOCK: i64 = -88;
+pub(crate) const EDESTADDRREQ: i64 = -89;
+pub(crate) const EMSGSIZE: i64 = -90;
+pub(crate) const EPROTONOSUPPORT: i64 = -93;
+pub(crate) const EOPNOTSUPP: i64 = -95;
+pub(crate) const EAFNOSUPPORT: i64 = -97;
+pub(crate) const EADDRINUSE: i64 = -98;
+pub(crate) const EADDRNOTAVAIL: i64 = -99;
+pub(crate) const ENETDOWN: i64 = -100;
+pub(crate) const ENETUNREACH: i64 = -101;
+pub(crate) const ECONNRESET: i64 = -104;
+pub(crate) const EISCONN: i64 = -106;
+pub(crate) const ENOTCONN: i64 = -107;
+pub(crate) const ETIMEDOUT: i64 = -110;
+pub(crate) const ECONNREFUSED: i64 = -111;
+pub(crate) const EHOSTUNREACH: i64 = -113;
+pub(crate) const EALREADY: i64 = -114;
+pub(crate) const EINPROGRESS: i64 = -115;
+
+const IO_CHUNK: u64 = 65536;
 
-fn sys_exit(_code: u64) -> u64 {
+fn sys_exit(code: u64) -> u64 {
     let pid = current_pid();
-    FD_TABLE.lock().remove(&pid);
-    crate::scheduler::kill(pid);
+    crate::fdtable::release(pid);
+    crate::scheduler::terminate(pid, crate::scheduler::exit_status(code));
     crate::scheduler::yield_now();
     0
 }
 
 fn sys_write(fd: u64, ptr: u64, len: u64) -> u64 {
-    if fd != 1 && fd != 2 {
-        return err(EBADF);
-    }
+    let file = match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f,
+        None => return err(EBADF),
+    };
     if len == 0 {
         return 0;
     }
-    if len > 65536 {
-        return err(EINVAL);
-    }
+    let len = len.min(IO_CHUNK);
 
     let cr3 = current_cr3();
     if !user_ptr_mapped(cr3, ptr, len) {
         return err(EFAULT);
     }
 
-    let s = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
-    match core::str::from_utf8(s) {
-        Ok(t) => crate::print!("{}", t),
-        Err(_) => {
-            for &b in s {
-                crate::print!("{}", b as char);
-            }
-        }
+    let data = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) }.to_vec();
+    match crate::fdtable::write(file, &data) {
+        Ok(n) => n as u64,
+        Err(e) => err(e),
     }
-    len
 }
 
 fn sys_read(fd: u64, buf: u64, len: u64) -> u64 {
+    let file = match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f,
+        None => return err(EBADF),
+    };
     if len == 0 {
         return 0;
     }
 
     let cr3 = current_cr3();
-    if !user_ptr_mapped(cr3, buf, len) {
+    if !user_ptr_writable(cr3, buf, len) {
         return err(EFAULT);
     }
 
-    if fd == 0 {
+    if file.is_console() {
+        drop(file);
         return crate::user_stdin::read(buf, len);
     }
 
-    let pid = current_pid();
-    with_fds(pid, |pfds| {
-        let file = match pfds.files.get_mut(&fd) {
-            Some(f) => f,
-            None => return err(EBADF),
-        };
-
-        let remaining = file.data.len().saturating_sub(file.offset);
-        let to_copy = (len as usize).min(remaining);
-        if to_copy == 0 {
-            return 0;
-        }
-
-        unsafe {
-            core::ptr::copy_nonoverlapping(
-                file.data.as_ptr().add(file.offset),
-                buf as *mut u8,
-                to_copy,
-            );
+    let mut data = alloc::vec![0u8; len.min(IO_CHUNK) as usize];
+    match crate::fdtable::read(file, &mut data) {
+        Ok(n) => {
+            unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, n) };
+            n as u64
         }
-        file.offset += to_copy;
-        to_copy as u64
-    })
+        Err(e) => err(e),
+    }
 }
 
-fn sys_open(path_ptr: u64, path_len: u64) -> u64 {
+fn user_path(path_ptr: u64, path_len: u64) -> Result<String, i64> {
     if path_len == 0 || path_len > 4096 {
-        return err(EINVAL);
+        return Err(EINVAL);
     }
 
     let cr3 = current_cr3();
     if !user_ptr_mapped(cr3, path_ptr, path_len) {
-        return err(EFAULT);
+        return Err(EFAULT);
     }
 
     let path_bytes = unsafe {
         core::slice::from_raw_parts(path_ptr as *const u8, path_len as usize)
     };
 
-    let path = match core::str::from_utf8(path_bytes) {
-        Ok(s) => s,
-        Err(_) => return err(EINVAL),
-    };
-
-    let path_trimmed = path.trim_end_matches('\0');
+    match core::str::from_utf8(path_bytes) {
+        Ok(s) => Ok(String::from(s.trim_end_matches('\0'))),
+        Err(_) => Err(EINVAL),
+    }
+}
 
-    crate::serial_println!("[syscall] open '{}'", path_trimmed);
+fn sys_open(path_ptr: u64, path_len: u64) -> u64 {
+    sys_open2(path_ptr, path_len, crate::fdtable::O_RDONLY, 0)
+}
 
-    let data = match crate::vfs_read::read_file_or_solib(path_trimmed) {
-        Some(d) => d,
-        None => return err(ENOENT),
+fn sys_open2(path_ptr: u64, path_len: u64, flags: u64, mode: u64) -> u64 {
+    let path = match user_path(path_ptr, path_len) {
+        Ok(p) => p,
+        Err(e) => return err(e),
     };
 
-    crate::serial_println!("[syscall] open '{}' -> {} bytes", path_trimmed, data.len());
+    let file = match crate::fdtable::open(&path, flags, (mode & 0o7777) as u16) {
+        Ok(f) => f,
+        Err(e) => return err(e),
+    };
 
-    let pid = current_pid();
-    with_fds(pid, |pfds| {
-        let fd = pfds.next_fd;
-        pfds.next_fd += 1;
-        pfds.files.insert(fd, OpenFile { data, offset: 0 });
-        fd
-    })
+    match crate::fdtable::install(current_pid(), file, flags & crate::fdtable::O_CLOEXEC != 0) {
+        Ok(fd) => {
+            crate::serial_println!("[syscall] open '{}' flags={:#x} -> fd {}", path, flags, fd);
+            fd
+        }
+        Err(e) => err(e),
+    }
 }
 
 fn sys_close(fd: u64) -> u64 {
-    let pid = current_pid();
-    with_fds(pid, |pfds| {
-        if pfds.files.remove(&fd).is_some() {
-            0
-        } else {
-            err(EBADF)
-        }
-    })
+    match crate::fdtable::close(current_pid(), fd) {
+        Ok(()) => 0,
+        Err(e) => err(e),
+    }
 }
 
 fn sys_seek(fd: u64, offset: u64) -> u64 {
-    let pid = current_pid();
-    with_fds(pid, |pfds| {
-        let file = match pfds.files.get_mut(&fd) {
-            Some(f) => f,
-            None => return err(EBADF),
-        };
-        file.offset = (offset as usize).min(file.data.len());
-        file.offset as u64
-    })
+    match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f.seek(offset).unwrap_or_else(err),
+        None => err(EBADF),
+    }
 }
 
 fn sys_fsize(fd: u64) -> u64 {
-    let pid = current_pid();
-    with_fds(pid, |pfds| {
-        match pfds.files.get(&fd) {
-            Some(f) => f.data.len() as u64,
-            None => err(EBADF),
+    match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f.size().unwrap_or_else(err),
+        None => err(EBADF),
+    }
+}
+
+fn sys_pread(fd: u64, buf: u64, len: u64, offset: u64) -> u64 {
+    let file = match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f,
+        None => return err(EBADF),
+    };
+    if len == 0 {
+        return 0;
+    }
+
+    let cr3 = current_cr3();
+    if !user_ptr_writable(cr3, buf, len) {
+        return err(EFAULT);
+    }
+
+    let mut data = alloc::vec![0u8; len.min(IO_CHUNK) as usize];
+    match file.pread(&mut data, offset) {
+        Ok(n) => {
+            unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, n) };
+            n as u64
+        }
+        Err(e) => err(e),
+    }
+}
+
+fn sys_pwrite(fd: u64, ptr: u64, len: u64, offset: u64) -> u64 {
+    let file = match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f,
+        None => return err(EBADF),
+    };
+    if len == 0 {
+        return 0;
+    }
+    let len = len.min(IO_CHUNK);
+
+    let cr3 = current_cr3();
+    if !user_ptr_mapped(cr3, ptr, len) {
+        return err(EFAULT);
+    }
+
+    let data = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) }.to_vec();
+    match file.pwrite(&data, offset) {
+        Ok(n) => n as u64,
+        Err(e) => err(e),
+    }
+}
+
+fn sys_ftruncate(fd: u64, len: u64) -> u64 {
+    match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f.truncate(len).map(|_| 0).unwrap_or_else(err),
+        None => err(EBADF),
+    }
+}
+
+fn sys_unlink(path_ptr: u64, path_len: u64) -> u64 {
+    let r = user_path(path_ptr, path_len).and_then(|p| crate::vfs_write::unlink(&p));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn sys_mkdir(path_ptr: u64, path_len: u64, mode: u64) -> u64 {
+    let mode = (mode & 0o7777) as u16;
+    let r = user_path(path_ptr, path_len).and_then(|p| crate::vfs_write::mkdir(&p, mode));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn sys_rmdir(path_ptr: u64, path_len: u64) -> u64 {
+    let r = user_path(path_ptr, path_len).and_then(|p| crate::vfs_write::rmdir(&p));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn sys_rename(old_ptr: u64, old_len: u64, new_ptr: u64, new_len: u64) -> u64 {
+    let old = match user_path(old_ptr, old_len) {
+        Ok(p) => p,
+        Err(e) => return err(e),
+    };
+    let r = user_path(new_ptr, new_len).and_then(|new| crate::vfs_write::rename(&old, &new));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn put_stat(buf: u64, st: crate::vfs_stat::MikuStat) -> u64 {
+    let size = core::mem::size_of::<crate::vfs_stat::MikuStat>() as u64;
+    if !user_ptr_writable(current_cr3(), buf, size) {
+        return err(EFAULT);
+    }
+    unsafe { core::ptr::write_unaligned(buf as *mut crate::vfs_stat::MikuStat, st) };
+    0
+}
+
+fn sys_stat(path_ptr: u64, path_len: u64, buf: u64, follow: bool) -> u64 {
+    match user_path(path_ptr, path_len).and_then(|p| crate::vfs_stat::stat(&p, follow)) {
+        Ok(st) => put_stat(buf, st),
+        Err(e) => err(e),
+    }
+}
+
+fn sys_fstat(fd: u64, buf: u64) -> u64 {
+    let file = match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f,
+        None => return err(EBADF),
+    };
+    match file.stat() {
+        Ok(st) => put_stat(buf, st),
+        Err(e) => err(e),
+    }
+}
+
+fn sys_getdents(fd: u64, buf: u64, len: u64) -> u64 {
+    let file = match crate::fdtable::get(current_pid(), fd) {
+        Some(f) => f,
+        None => return err(EBADF),
+    };
+    if !user_ptr_writable(current_cr3(), buf, len) {
+        return err(EFAULT);
+    }
+
+    let mut data = alloc::vec![0u8; len.min(IO_CHUNK) as usize];
+    match file.getdents(&mut data) {
+        Ok(n) => {
+            unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, n) };
+            n as u64
+        }
+        Err(e) => err(e),
+    }
+}
+
+fn sys_pipe(fds_ptr: u64, flags: u64) -> u64 {
+    let cr3 = current_cr3();
+    if !user_ptr_writable(cr3, fds_ptr, 8) {
+        return err(EFAULT);
+    }
+    let (rfd, wfd) = match crate::fdtable::pipe(current_pid(), flags) {
+        Ok(p) => p,
+        Err(e) => return err(e),
+    };
+    unsafe {
+        *(fds_ptr as *mut i32) = rfd as i32;
+        *((fds_ptr + 4) as *mut i32) = wfd as i32;
+    }
+    crate::serial_println!("[syscall] pipe pid={} -> [{}, {}]", current_pid(), rfd, wfd);
+    0
+}
+
+fn user_sockaddr(ptr: u64, len: u64, family: u64) -> Result<Endpoint, i64> {
+    if len < 8 {
+        return Err(EINVAL);
+    }
+    let len = len.min(SOCKADDR6_LEN as u64);
+    if !user_ptr_mapped(current_cr3(), ptr, len) {
+        return Err(EFAULT);
+    }
+    Endpoint::from_sockaddr(unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) }, family)
+}
+
+fn put_sockaddr(ptr: u64, ep: Endpoint, family: u64) -> Result<(), i64> {
+    if ptr == 0 {
+        return Ok(());
+    }
+    let raw = ep.to_sockaddr(family);
+    let len = if family == AF_INET { raw.len().min(SOCKADDR_LEN) } else { raw.len() };
+    if !user_ptr_writable(current_cr3(), ptr, len as u64) {
+        return Err(EFAULT);
+    }
+    unsafe { core::ptr::copy_nonoverlapping(raw.as_ptr(), ptr as *mut u8, len) };
+    Ok(())
+}
+
+fn sock_fd(fd: u64) -> Result<(Arc<Socket>, bool), i64> {
+    let file = crate::fdtable::get(current_pid(), fd).ok_or(EBADF)?;
+    Ok((file.socket()?, file.nonblock()))
+}
+
+fn sys_socket(domain: u64, ty: u64, protocol: u64) -> u64 {
+    use crate::fdtable::{O_CLOEXEC, O_NONBLOCK};
+    if domain != AF_INET && domain != AF_INET6 {
+        return err(EAFNOSUPPORT);
+    }
+    let flags = ty & (O_NONBLOCK | O_CLOEXEC);
+    let proto = match (ty & !flags, protocol) {
+        (SOCK_STREAM, 0 | 6)  => Proto::Tcp,
+        (SOCK_DGRAM, 0 | 17)  => Proto::Udp,
+        (SOCK_STREAM | SOCK_DGRAM, _) => return err(EPROTONOSUPPORT),
+        _ => return err(EINVAL),
+    };
+    match crate::fdtable::socket(current_pid(), proto, domain, flags) {
+        Ok(fd) => {
+            crate::serial_println!("[syscall] socket pid={} {:?} -> fd {}", current_pid(), proto, fd);
+            fd
         }
-    })
+        Err(e) => err(e),
+    }
+}
+
+fn sys_bind(fd: u64, addr: u64, len: u64) -> u64 {
+    let r = sock_fd(fd).and_then(|(s, _)| s.bind(user_sockaddr(addr, len, s.family)?));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn sys_listen(fd: u64, backlog: u64) -> u64 {
+    let r = sock_fd(fd).and_then(|(s, _)| s.listen(backlog as usize));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn sys_accept(fd: u64, addr: u64, flags: u64) -> u64 {
+    let r = sock_fd(fd).and_then(|(s, nb)| {
+        let conn = s.accept(nb)?;
+        let (peer, family) = (conn.peer(), conn.family);
+        let fd = crate::fdtable::install_socket(current_pid(), conn, flags)?;
+        put_sockaddr(addr, peer, family)?;
+        Ok(fd)
+    });
+    r.unwrap_or_else(err)
+}
+
+fn sys_connect(fd: u64, addr: u64, len: u64) -> u64 {
+    let r = sock_fd(fd).and_then(|(s, nb)| s.connect(user_sockaddr(addr, len, s.family)?, nb));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn sys_sendto(fd: u64, ptr: u64, len: u64, addr: u64) -> u64 {
+    let (sock, nb) = match sock_fd(fd) {
+        Ok(s) => s,
+        Err(e) => return err(e),
+    };
+    let to = match addr {
+        0 => None,
+        _ => match user_sockaddr(addr, if sock.family == AF_INET6 { SOCKADDR6_LEN } else { SOCKADDR_LEN } as u64, sock.family) {
+            Ok(ep) => Some(ep),
+            Err(e) => return err(e),
+        },
+    };
+    let len = len.min(IO_CHUNK);
+    if len > 0 && !user_ptr_mapped(current_cr3(), ptr, len) {
+        return err(EFAULT);
+    }
+    let data = match len {
+        0 => Vec::new(),
+        _ => unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) }.to_vec(),
+    };
+    let r = match to {
+        None => crate::fdtable::sock_write(&sock, &data, nb),
+        to   => sock.send(&data, to, nb),
+    };
+    r.map(|n| n as u64).unwrap_or_else(err)
+}
+
+fn sys_recvfrom(fd: u64, buf: u64, len: u64, addr: u64) -> u64 {
+    let (sock, nb) = match sock_fd(fd) {
+        Ok(s) => s,
+        Err(e) => return err(e),
+    };
+    if len == 0 {
+        return 0;
+    }
+    if !user_ptr_writable(current_cr3(), buf, len) {
+        return err(EFAULT);
+    }
+    let mut data = alloc::vec![0u8; len.min(IO_CHUNK) as usize];
+    let r = sock.recv(&mut data, nb).and_then(|(n, from)| {
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, n) };
+        put_sockaddr(addr, from, sock.family)?;
+        Ok(n as u64)
+    });
+    r.unwrap_or_else(err)
+}
+
+fn sys_shutdown(fd: u64, how: u64) -> u64 {
+    let r = sock_fd(fd).and_then(|(s, _)| s.shutdown(how));
+    r.map(|_| 0).unwrap_or_else(err)
+}
+
+fn sys_dup2(old: u64, new: u64) -> u64 {
+    match crate::fdtable::dup2(current_pid(), old, new) {
+        Ok(fd) => fd,
+        Err(e) => err(e),
+    }
+}
+
+fn sys_fcntl(fd: u64, cmd: u64, arg: u64) -> u64 {
+    use crate::fdtable::{FD_CLOEXEC, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD, F_GETFL, F_SETFD, F_SETFL};
+    let pid = current_pid();
+    let r = match cmd {
+        F_DUPFD         => crate::fdtable::dup_min(pid, fd, arg, false),
+        F_DUPFD_CLOEXEC => crate::fdtable::dup_min(pid, fd, arg, true),
+        F_GETFD => crate::fdtable::cloexec(pid, fd).map(|c| if c { FD_CLOEXEC } else { 0 }),
+        F_SETFD => crate::fdtable::set_cloexec(pid, fd, arg & FD_CLOEXEC != 0).map(|_| 0),
+        F_GETFL => crate::fdtable::get(pid, fd).map(|f| f.status()).ok_or(EBADF),
+        F_SETFL => crate::fdtable::get(pid, fd).map(|f| f.set_status(arg)).map(|_| 0).ok_or(EBADF),
+        _ => Err(EINVAL),
+    };
+    r.unwrap_or_else(err)
 }
 
 fn sys_mmap(addr: u64, len: u64, prot: u64, flags: u64) -> u64 {
@@ -368,7 +806,11 @@ fn sys_getcwd(buf: u64, size: u64) -> u64 {
 }
 
 fn sys_set_tls(addr: u64) -> u64 {
+    if addr > USER_MAX {
+        return err(EINVAL);
+    }
     x86_64::registers::model_specific::FsBase::write(VirtAddr::new(addr));
+    crate::scheduler::with_process(current_pid(), |p| p.fs_base.store(addr, Ordering::Relaxed));
     crate::serial_println!("[syscall] set_tls={:#x}", addr);
     0
 }
@@ -413,3 +855,273 @@ fn sys_sleep(ticks: u64) -> u64 {
 fn sys_uptime() -> u64 {
     crate::interrupts::get_tick()
 }
+
+fn sys_clone(flags: u64, child_stack: u64, tls: u64, frame: &SyscallFrame) -> u64 {
+    if child_stack > USER_MAX || tls > USER_MAX {
+        return err(EINVAL);
+    }
+    if flags & CLONE_VM != 0 && child_stack == 0 {
+        return err(EINVAL);
+    }
+    let ppid = current_pid();
+    let cr3  = current_cr3();
+    if cr3 == crate::vmm::kernel_cr3() {
+        return err(EINVAL);
+    }
+
+    let child_cr3 = if flags & CLONE_VM != 0 {
+        crate::vmm::share_address_space(cr3);
+        cr3
+    } else {
+        let parent = AddressSpace::from_raw(cr3);
+        let child  = parent.fork_cow();
+        let _ = parent.into_raw();
+        match child {
+            Some(a) => {
+                let c = a.into_raw();
+                mmap::vma_fork(cr3, c);
+                c
+            }
+            None => return err(ENOMEM),
+        }
+    };
+
+    let mut ctx = frame.user_context();
+    ctx.rax = 0;
+    if child_stack != 0 {
+        ctx.rsp = child_stack;
+    }
+    let fs_base = if flags & CLONE_SETTLS != 0 {
+        tls
+    } else {
+        x86_64::registers::model_specific::FsBase::read().as_u64()
+    };
+
+    let name = crate::scheduler::with_process(ppid, |p| p.name).unwrap_or("user-elf");
+    let child = Process::new_fork(name, ppid, child_cr3, &ctx, fs_base);
+    let pid = child.pid;
+    child.sig_blocked.store(crate::signal::blocked(ppid), Ordering::Relaxed);
+    crate::signal::fork(ppid, pid);
+
+    if flags & (CLONE_VM | CLONE_FILES) == CLONE_VM | CLONE_FILES {
+        crate::fdtable::share(ppid, pid);
+    } else {
+        crate::fdtable::fork(ppid, pid);
+    }
+
+    crate::scheduler::add_user_process(child);
+    crate::serial_println!(
+        "[syscall] clone pid={} -> child={} flags={:#x}{}",
+        ppid, pid, flags, if child_cr3 == cr3 { " (shared vm)" } else { " (cow)" },
+    );
+    pid
+}
+
+fn user_cstr(cr3: u64, ptr: u64) -> Option<String> {
+    let mut out = Vec::new();
+    let mut va  = ptr;
+    while (va - ptr) < EXEC_MAX_STR {
+        if (va & 0xFFF == 0 || va == ptr) && !user_ptr_mapped(cr3, va, 1) {
+            return None;
+        }
+        let b = unsafe { *(va as *const u8) };
+        if b == 0 {
+            return String::from_utf8(out).ok();
+        }
+        out.push(b);
+        va += 1;
+    }
+    None
+}
+
+fn user_strv(cr3: u64, ptr: u64) -> Result<Vec<String>, i64> {
+    let mut out = Vec::new();
+    if ptr == 0 {
+        return Ok(out);
+    }
+    loop {
+        if out.len() >= EXEC_MAX_ARGS {
+            return Err(E2BIG);
+        }
+        let slot = ptr + (out.len() as u64) * 8;
+        if !user_ptr_mapped(cr3, slot, 8) {
+            return Err(EFAULT);
+        }
+        let p = unsafe { *(slot as *const u64) };
+        if p == 0 {
+            return Ok(out);
+        }
+        out.push(user_cstr(cr3, p).ok_or(EFAULT)?);
+    }
+}
+
+fn sys_execve(path_ptr: u64, path_len: u64, argv: u64, envp: u64, frame: &mut SyscallFrame) -> u64 {
+    if path_len == 0 || path_len > 4096 {
+        return err(EINVAL);
+    }
+    let cr3 = current_cr3();
+    if cr3 == crate::vmm::kernel_cr3() {
+        return err(EINVAL);
+    }
+    if !user_ptr_mapped(cr3, path_ptr, path_len) {
+        return err(EFAULT);
+    }
+
+    let path_bytes = unsafe {
+        core::slice::from_raw_parts(path_ptr as *const u8, path_len as usize)
+    };
+    let path = match core::str::from_utf8(path_bytes) {
+        Ok(s) => String::from(s.trim_end_matches('\0')),
+        Err(_) => return err(EINVAL),
+    };
+
+    let mut args = match user_strv(cr3, argv) {
+        Ok(v) => v,
+        Err(e) => return err(e),
+    };
+    let env = match user_strv(cr3, envp) {
+        Ok(v) => v,
+        Err(e) => return err(e),
+    };
+    if args.is_empty() {
+        args.push(path.clone());
+    }
+
+    let arg_refs: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let env_refs: Vec<&str> = env.iter().map(|e| e.as_str()).collect();
+
+    let image = match crate::exec_elf::exec_replace(&path, &arg_refs, &env_refs) {
+        Ok(i) => i,
+        Err(e) => {
+            crate::serial_println!("[syscall] execve '{}' failed: {}", path, e.as_str());
+            return err(match e {
+                crate::exec_elf::ExecError::FileNotFound => ENOENT,
+                crate::exec_elf::ExecError::NoAddressSpace => ENOMEM,
+                crate::exec_elf::ExecError::Load(crate::elf_loader::LoadError::OutOfMemory) => ENOMEM,
+                _ => EINVAL,
+            });
+        }
+    };
+
+    x86_64::registers::model_specific::FsBase::write(VirtAddr::zero());
+    crate::scheduler::with_process(current_pid(), |p| p.fs_base.store(0, Ordering::Relaxed));
+
+    crate::signal::exec(current_pid());
+    crate::fdtable::exec(current_pid());
+
+    frame.set_user_context(&UserContext {
+        r8:     image.tls_base,
+        rip:    image.entry,
+        rflags: 0x202,
+        rsp:    image.stack_top,
+        ..UserContext::default()
+    });
+    0
+}
+
+fn sys_wait4(pid: i64, status_ptr: u64, options: u64) -> u64 {
+    if options & !WNOHANG != 0 {
+        return err(EINVAL);
+    }
+    let cr3 = current_cr3();
+    if status_ptr != 0 && !user_ptr_writable(cr3, status_ptr, 4) {
+        return err(EFAULT);
+    }
+
+    match crate::scheduler::wait_child(current_pid(), pid, options & WNOHANG != 0) {
+        crate::scheduler::WaitStatus::Exited(child, status) => {
+            if status_ptr != 0 {
+                unsafe { (status_ptr as *mut u32).write_unaligned(status as u32) };
+            }
+            crate::serial_println!("[syscall] wait4 pid={} reaped={} status={:#x}", current_pid(), child, status);
+            child
+        }
+        crate::scheduler::WaitStatus::Running => 0,
+        crate::scheduler::WaitStatus::NoChild => err(ECHILD),
+        crate::scheduler::WaitStatus::Interrupted => err(EINTR),
+    }
+}
+
+fn sys_sigaction(sig: u64, act_ptr: u64, old_ptr: u64) -> u64 {
+    use crate::signal::{self, SigAction};
+    if !signal::valid(sig) {
+        return err(EINVAL);
+    }
+    let cr3  = current_cr3();
+    let size = core::mem::size_of::<SigAction>() as u64;
+    if act_ptr != 0 && !user_ptr_mapped(cr3, act_ptr, size) {
+        return err(EFAULT);
+    }
+    if old_ptr != 0 && !user_ptr_writable(cr3, old_ptr, size) {
+        return err(EFAULT);
+    }
+
+    let pid = current_pid();
+    let old = if act_ptr != 0 {
+        let act = unsafe { (act_ptr as *const SigAction).read_unaligned() };
+        if sig == signal::SIGKILL || sig == signal::SIGSTOP {
+            return err(EINVAL);
+        }
+        if act.handler > signal::SIG_IGN
+            && (act.handler > USER_MAX || act.restorer == 0 || act.restorer > USER_MAX)
+        {
+            return err(EINVAL);
+        }
+        signal::set_action(pid, sig, act)
+    } else {
+        signal::get_action(pid, sig)
+    };
+
+    if old_ptr != 0 {
+        unsafe { (old_ptr as *mut SigAction).write_unaligned(old) };
+    }
+    0
+}
+
+fn sys_sigprocmask(how: u64, set_ptr: u64, old_ptr: u64) -> u64 {
+    use crate::signal;
+    let cr3 = current_cr3();
+    if set_ptr != 0 && !user_ptr_mapped(cr3, set_ptr, 8) {
+        return err(EFAULT);
+    }
+    if old_ptr != 0 && !user_ptr_writable(cr3, old_ptr, 8) {
+        return err(EFAULT);
+    }
+
+    let pid = current_pid();
+    let old = signal::blocked(pid);
+    if set_ptr != 0 {
+        let set = unsafe { (set_ptr as *const u64).read_unaligned() };
+        let new = match how {
+            signal::SIG_BLOCK   => old | set,
+            signal::SIG_UNBLOCK => old & !set,
+            signal::SIG_SETMASK => set,
+            _ => return err(EINVAL),
+        };
+        signal::set_blocked(pid, new);
+    }
+    if old_ptr != 0 {
+        unsafe { (old_ptr as *mut u64).write_unaligned(old) };
+    }
+    0
+}
+
+fn sys_kill(pid: u64, sig: u64) -> u64 {
+    if sig != 0 && !crate::signal::valid(sig) {
+        return err(EINVAL);
+    }
+    let target = crate::scheduler::with_process(pid, |p| {
+        (p.cr3, p.state.load(Ordering::Relaxed))
+    });
+    let cr3 = match target {
+        Some((_, crate::process::STATE_DEAD)) | None => return err(ESRCH),
+        Some((cr3, _)) => cr3,
+    };
+    if pid == 0 || cr3 == crate::vmm::kernel_cr3() {
+        return err(EPERM);
+    }
+    if sig != 0 {
+        crate::signal::send(pid, sig);
+    }
+    0
+}
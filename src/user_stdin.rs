@@ -130,7 +130,7 @@ pub fn feed_char(c: char) {
             let pid = foreground_pid();
             if pid != 0 {
                 crate::print!("^C\n");
-                crate::scheduler::kill(pid);
+                crate::signal::send(pid, crate::signal::SIGINT);
             }
         }
         c if c >= ' ' && (c as u32) < 127 => {
@@ -155,6 +155,9 @@ pub fn read(buf_ptr: u64, len: u64) -> u64 {
         if foreground_pid() == 0 {
             return 0;
         }
+        if crate::signal::has_pending(crate::scheduler::current_pid()) {
+            return crate::syscall::EINTR as u64;
+        }
 
         {
             let mut inner = INNER.lock();
@@ -1,93 +1,222 @@
-use crate::vfs::types::*;
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::fdtable::{self, OpenFile, O_RDWR};
+
+pub const SECTOR_SIZE: usize = 512;
+
+const PROBE_LIMIT: u64 = u32::MAX as u64;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-pub enum BlockDevType {
-    RamDisk = 0,
-    AtaDisk = 1,
+pub enum BlockError {
+    NoDevice,
+    OutOfRange,
+    BufferTooSmall,
+    ReadOnly,
+    Timeout,
+    Io,
 }
 
-#[derive(Clone, Copy)]
-pub struct BlockDevice {
-    pub id: BlockDevId,
-    pub dev_type: BlockDevType,
-    pub block_size: u32,
-    pub total_blocks: u64,
-    pub name: NameBuf,
-    pub read_only: bool,
-    pub active: bool,
+pub trait BlockDevice: Send {
+    fn read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), BlockError>;
+    fn write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<(), BlockError>;
+    fn flush(&mut self) -> Result<(), BlockError>;
+    fn sector_count(&self) -> u64;
+    fn sector_size(&self) -> usize { SECTOR_SIZE }
 }
 
-impl BlockDevice {
-    pub const fn empty() -> Self {
-        Self {
-            id: INVALID_U8,
-            dev_type: BlockDevType::RamDisk,
-            block_size: BLOCK_SIZE as u32,
-            total_blocks: 0,
-            name: NameBuf::empty(),
-            read_only: false,
-            active: false,
-        }
+impl<T: BlockDevice + ?Sized> BlockDevice for Box<T> {
+    fn read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), BlockError> {
+        (**self).read(lba, count, buf)
+    }
+
+    fn write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<(), BlockError> {
+        (**self).write(lba, count, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        (**self).flush()
+    }
+
+    fn sector_count(&self) -> u64 {
+        (**self).sector_count()
+    }
+
+    fn sector_size(&self) -> usize {
+        (**self).sector_size()
+    }
+}
+
+pub fn check_io(dev: &dyn BlockDevice, lba: u64, count: u32, len: usize) -> Result<usize, BlockError> {
+    let bytes = count as usize * dev.sector_size();
+    if len < bytes {
+        return Err(BlockError::BufferTooSmall);
+    }
+    let total = dev.sector_count();
+    if total != 0 && lba.checked_add(count as u64).is_none_or(|end| end > total) {
+        return Err(BlockError::OutOfRange);
+    }
+    Ok(bytes)
+}
+
+pub fn probe_sectors(dev: &mut dyn BlockDevice) -> u64 {
+    let known = dev.sector_count();
+    if known != 0 {
+        return known;
+    }
+    let mut buf = vec![0u8; dev.sector_size()];
+    if dev.read(0, 1, &mut buf).is_err() {
+        return 0;
+    }
+    let mut lo: u64 = 0;
+    let mut hi: u64 = 1;
+    while hi < PROBE_LIMIT && dev.read(hi, 1, &mut buf).is_ok() {
+        lo = hi;
+        hi = (hi * 2).min(PROBE_LIMIT);
+    }
+    while lo + 1 < hi {
+        let mid = lo + (hi - lo) / 2;
+        if dev.read(mid, 1, &mut buf).is_ok() { lo = mid; } else { hi = mid; }
+    }
+    lo + 1
+}
+
+pub struct RamDisk {
+    data: Vec<u8>,
+}
+
+impl RamDisk {
+    pub fn new(sectors: u64) -> Self {
+        Self { data: vec![0u8; sectors as usize * SECTOR_SIZE] }
+    }
+
+    pub fn from_vec(mut data: Vec<u8>) -> Self {
+        let len = data.len() / SECTOR_SIZE * SECTOR_SIZE;
+        data.truncate(len);
+        Self { data }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), BlockError> {
+        let n   = check_io(self, lba, count, buf.len())?;
+        let off = lba as usize * SECTOR_SIZE;
+        buf[..n].copy_from_slice(&self.data[off..off + n]);
+        Ok(())
+    }
+
+    fn write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<(), BlockError> {
+        let n   = check_io(self, lba, count, buf.len())?;
+        let off = lba as usize * SECTOR_SIZE;
+        self.data[off..off + n].copy_from_slice(&buf[..n]);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(())
     }
 
-    pub fn size_bytes(&self) -> u64 {
-        self.total_blocks * self.block_size as u64
+    fn sector_count(&self) -> u64 {
+        (self.data.len() / SECTOR_SIZE) as u64
     }
 }
 
-pub struct BlockDevManager {
-    pub devices: [BlockDevice; MAX_BLOCK_DEVICES],
+const IMAGE_CACHE: usize = 4096;
+
+pub struct FileImage {
+    file:    Arc<OpenFile>,
+    cache:   BTreeMap<u64, Box<[u8; SECTOR_SIZE]>>,
+    dirty:   BTreeSet<u64>,
+    sectors: u64,
 }
 
-impl BlockDevManager {
-    pub const fn new() -> Self {
-        Self {
-            devices: [BlockDevice::empty(); MAX_BLOCK_DEVICES],
+impl FileImage {
+    pub fn open(path: &str) -> Result<Self, BlockError> {
+        let file = fdtable::open(path, O_RDWR, 0).map_err(|_| BlockError::NoDevice)?;
+        let size = file.size().map_err(|_| BlockError::Io)?;
+        let sectors = size / SECTOR_SIZE as u64;
+        if sectors == 0 {
+            return Err(BlockError::OutOfRange);
         }
+        crate::serial_println!("[blockdev] image '{}' {} sectors", path, sectors);
+        Ok(Self { file, cache: BTreeMap::new(), dirty: BTreeSet::new(), sectors })
     }
 
-    pub fn register(
-        &mut self,
-        dev_type: BlockDevType,
-        block_size: u32,
-        total_blocks: u64,
-        name: &str,
-    ) -> VfsResult<BlockDevId> {
-        for (i, dev) in self.devices.iter_mut().enumerate() {
-            if !dev.active {
-                dev.id = i as BlockDevId;
-                dev.dev_type = dev_type;
-                dev.block_size = block_size;
-                dev.total_blocks = total_blocks;
-                dev.name = NameBuf::from_str(name);
-                dev.active = true;
-                return Ok(i as BlockDevId);
+    fn writeback(&mut self) -> Result<(), BlockError> {
+        let mut runs: Vec<(u64, Vec<u8>)> = Vec::new();
+        for &lba in self.dirty.iter() {
+            let sec = &self.cache[&lba][..];
+            match runs.last_mut() {
+                Some((start, bytes)) if *start + (bytes.len() / SECTOR_SIZE) as u64 == lba => {
+                    bytes.extend_from_slice(sec);
+                }
+                _ => runs.push((lba, sec.to_vec())),
+            }
+        }
+        let mut failed = false;
+        for (lba, bytes) in runs.iter() {
+            if self.file.pwrite(bytes, lba * SECTOR_SIZE as u64) == Ok(bytes.len()) {
+                for l in *lba..*lba + (bytes.len() / SECTOR_SIZE) as u64 {
+                    self.dirty.remove(&l);
+                }
+            } else {
+                crate::serial_println!("[blockdev] image writeback failed at lba {}", lba);
+                failed = true;
             }
         }
-        Err(VfsError::NoSpace)
+        let dirty = &self.dirty;
+        self.cache.retain(|lba, _| dirty.contains(lba));
+        if failed { Err(BlockError::Io) } else { Ok(()) }
     }
+}
 
-    pub fn get(&self, id: BlockDevId) -> Option<&BlockDevice> {
-        let i = id as usize;
-        if i < MAX_BLOCK_DEVICES && self.devices[i].active {
-            Some(&self.devices[i])
-        } else {
-            None
+impl BlockDevice for FileImage {
+    fn read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<(), BlockError> {
+        let n   = check_io(self, lba, count, buf.len())?;
+        let off = lba * SECTOR_SIZE as u64;
+        let mut pos = 0usize;
+        while pos < n {
+            match self.file.pread(&mut buf[pos..n], off + pos as u64) {
+                Ok(0) | Err(_) => return Err(BlockError::Io),
+                Ok(k)          => pos += k,
+            }
+        }
+        for (l, sec) in self.cache.range(lba..lba + count as u64) {
+            let at = (l - lba) as usize * SECTOR_SIZE;
+            buf[at..at + SECTOR_SIZE].copy_from_slice(&sec[..]);
         }
+        Ok(())
     }
 
-    pub fn unregister(&mut self, id: BlockDevId) -> VfsResult<()> {
-        let i = id as usize;
-        if i < MAX_BLOCK_DEVICES && self.devices[i].active {
-            self.devices[i] = BlockDevice::empty();
-            Ok(())
-        } else {
-            Err(VfsError::NotFound)
+    fn write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<(), BlockError> {
+        check_io(self, lba, count, buf.len())?;
+        for (i, sec) in buf.chunks_exact(SECTOR_SIZE).take(count as usize).enumerate() {
+            let l = lba + i as u64;
+            self.cache.entry(l).or_insert_with(|| Box::new([0u8; SECTOR_SIZE])).copy_from_slice(sec);
+            self.dirty.insert(l);
         }
+        if self.dirty.len() > IMAGE_CACHE {
+            self.writeback()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        self.writeback()
     }
 
-    pub fn count(&self) -> usize {
-        self.devices.iter().filter(|d| d.active).count()
+    fn sector_count(&self) -> u64 {
+        self.sectors
     }
 }
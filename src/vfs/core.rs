@@ -5,6 +5,7 @@ use crate::vfs::hash::name_hash;
 use crate::vfs::mount::MountTable;
 use crate::vfs::pages::PageCache;
 use crate::vfs::path::PathWalker;
+use crate::vfs::permissions;
 use crate::vfs::procfs;
 use crate::vfs::types::*;
 use crate::vfs::vnode::VNode;
@@ -55,7 +56,6 @@ pub fn init_vfs() -> Result<(), &'static str> {
             core::ptr::addr_of_mut!((*ptr).ctx),
             ProcessContext::root_context(),
         );
-        core::ptr::write(core::ptr::addr_of_mut!((*ptr).ext2_mount_active), false);
         core::ptr::write(core::ptr::addr_of_mut!((*ptr).vnode_free_hint), 1);
 
         let vfs = &mut *ptr;
@@ -97,7 +97,6 @@ pub struct MikuVFS {
     pub mounts: MountTable,
     pub fd_table: FdTable,
     pub ctx: ProcessContext,
-    pub ext2_mount_active: bool,
     pub(crate) vnode_free_hint: usize,
 }
 
@@ -486,7 +485,7 @@ impl MikuVFS {
             }
         }
 
-        if self.nodes[parent].fs_type == FsType::Ext2 && self.nodes[parent].ext2_ino != 0 {
+        if self.nodes[parent].is_ext_backed() {
             return self.ext2_lazy_lookup(parent, name);
         }
 
@@ -494,7 +493,7 @@ impl MikuVFS {
     }
 
     fn ext2_lazy_lookup(&mut self, parent_vnode: usize, name: &str) -> VfsResult<usize> {
-        if !self.ext2_mount_active || !crate::commands::ext2_cmds::is_ext2_ready() {
+        if !crate::commands::ext2_cmds::slot_ready(self.nodes[parent_vnode].ext_slot) {
             return Err(VfsError::NotFound);
         }
 
@@ -503,7 +502,7 @@ impl MikuVFS {
             return Err(VfsError::NotFound);
         }
 
-        let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+        let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[parent_vnode].ext_slot, |fs| {
             let child_ino = match fs.ext2_lookup_in_dir(parent_ext2_ino, name) {
                 Ok(Some(ino)) => ino,
                 Ok(None) => return Err(VfsError::NotFound),
@@ -565,13 +564,14 @@ impl MikuVFS {
             parent_vnode as InodeId,
             name,
             kind,
-            crate::commands::ext2_cmds::active_fs_type(),
+            self.nodes[parent_vnode].fs_type,
             FileMode::new(perm),
             uid,
             gid,
             ts,
         );
         self.nodes[id].ext2_ino = child_ino;
+        self.nodes[id].ext_slot = self.nodes[parent_vnode].ext_slot;
         self.nodes[id].size = size;
         self.nodes[id].nlinks = nlinks;
         self.nodes[id].children_loaded = false;
@@ -624,7 +624,7 @@ impl MikuVFS {
         let mut child_infos: [ChildInfo; BATCH] = unsafe { core::mem::zeroed() };
         let mut child_count = 0usize;
 
-        let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+        let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[dir_vnode].ext_slot, |fs| {
             use crate::miku_extfs::structs::{FT_DIR, FT_SYMLINK};
 
             let inode = fs.read_inode(ext2_ino).map_err(|_| VfsError::IoError)?;
@@ -720,13 +720,14 @@ impl MikuVFS {
                     dir_vnode as InodeId,
                     name_str,
                     kind,
-                    crate::commands::ext2_cmds::active_fs_type(),
+                    self.nodes[dir_vnode].fs_type,
                     FileMode::new(ci.mode),
                     ci.uid,
                     ci.gid,
                     ts,
                 );
                 self.nodes[id].ext2_ino = ci.ino;
+                self.nodes[id].ext_slot = self.nodes[dir_vnode].ext_slot;
                 self.nodes[id].size = ci.size;
                 self.nodes[id].nlinks = ci.nlinks;
                 self.nodes[id].children_loaded = false;
@@ -813,26 +814,12 @@ impl MikuVFS {
     }
 
     fn check_access(&self, id: usize, flags: OpenFlags) -> VfsResult<()> {
-        if self.ctx.cred.is_root() {
-            return Ok(());
-        }
         let node = &self.nodes[id];
-        let who = if self.ctx.cred.euid == node.uid {
-            PermWho::Owner
-        } else if self.ctx.cred.in_group(node.gid) {
-            PermWho::Group
+        if permissions::check_open_flags(node.mode, node.uid, node.gid, &self.ctx.cred, flags) {
+            Ok(())
         } else {
-            PermWho::Other
-        };
-        let bits = node.mode.perm_bits_for(who);
-
-        if flags.readable() && (bits & 0o4) == 0 {
-            return Err(VfsError::PermissionDenied);
-        }
-        if flags.writable() && (bits & 0o2) == 0 {
-            return Err(VfsError::PermissionDenied);
+            Err(VfsError::PermissionDenied)
         }
-        Ok(())
     }
 
     fn check_dir_write(&self, dir_id: usize) -> VfsResult<()> {
@@ -858,7 +845,7 @@ impl MikuVFS {
     fn truncate_file(&mut self, id: usize) {
         if self.nodes[id].is_ext_backed() {
             let ino = self.nodes[id].ext2_ino;
-            let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| {
                 fs.ext3_truncate(ino)
             });
         }
@@ -869,21 +856,19 @@ impl MikuVFS {
     }
 
     fn truncate_to(&mut self, id: usize, new_size: u64) {
+        if self.nodes[id].is_ext_backed() {
+            let ino = self.nodes[id].ext2_ino;
+            let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| {
+                fs.ext3_resize(ino, new_size)
+            });
+        }
+
         let old_size = self.nodes[id].size;
         if new_size >= old_size {
             self.nodes[id].size = new_size;
             return;
         }
 
-        if self.nodes[id].is_ext_backed() {
-            if new_size == 0 {
-                let ino = self.nodes[id].ext2_ino;
-                let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
-                    fs.ext3_truncate(ino)
-                });
-            }
-        }
-
         let keep_pages = if new_size == 0 {
             0
         } else {
@@ -947,7 +932,7 @@ impl MikuVFS {
         }
         if self.nodes[eff].is_ext_backed() {
             let parent_ino = self.nodes[eff].ext2_ino;
-            let exists = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let exists = crate::commands::ext2_cmds::with_ext_slot(self.nodes[eff].ext_slot, |fs| {
                 fs.ext2_lookup_in_dir(parent_ino, name)
                     .map(|r| r.is_some())
                     .unwrap_or(false)
@@ -990,6 +975,111 @@ impl MikuVFS {
         self.nodes[dir_id].children_loaded = false;
     }
 
+    pub fn node_path(&self, id: usize) -> alloc::string::String {
+        let mut parts: alloc::vec::Vec<&str> = alloc::vec::Vec::new();
+        let mut cur = id;
+        while cur != 0 && self.valid_vnode(cur) {
+            parts.push(self.nodes[cur].get_name());
+            let p = self.nodes[cur].parent as usize;
+            if p == cur {
+                break;
+            }
+            cur = p;
+        }
+        if parts.is_empty() {
+            return alloc::string::String::from("/");
+        }
+        let mut path = alloc::string::String::new();
+        for part in parts.iter().rev() {
+            path.push('/');
+            path.push_str(part);
+        }
+        path
+    }
+
+    pub fn ext_mount_path(&self, slot: u8) -> Option<alloc::string::String> {
+        self.mounts.find_by_ext_slot(slot).map(|m| self.node_path(m.root_vnode as usize))
+    }
+
+    pub fn mount_ext(&mut self, cwd: usize, mountpoint: &str, slot: u8) -> VfsResult<usize> {
+        use crate::miku_extfs::structs::EXT2_ROOT_INO;
+
+        if self.mounts.find_by_ext_slot(slot).is_some() {
+            return Err(VfsError::AlreadyMounted);
+        }
+
+        let id = match self.resolve_path_follow(cwd, mountpoint) {
+            Ok(id) => id,
+            Err(VfsError::NotFound) => {
+                let (parent, name) = self.split_path(cwd, mountpoint)?;
+                self.mkdir(parent, name, FileMode::default_dir())?
+            }
+            Err(e) => return Err(e),
+        };
+        if !self.nodes[id].is_dir() {
+            return Err(VfsError::NotDirectory);
+        }
+        if id == 0 || self.nodes[id].is_mountpoint() {
+            return Err(VfsError::Busy);
+        }
+        if self.nodes[id].is_ext_backed() {
+            self.ext2_ensure_children_loaded(id)?;
+        }
+        if !self.is_dir_empty(id) {
+            return Err(VfsError::NotEmpty);
+        }
+
+        let covered_fs   = self.nodes[id].fs_type;
+        let covered_ino  = self.nodes[id].ext2_ino;
+        let covered_slot = self.nodes[id].ext_slot;
+        let fs_type      = crate::commands::ext2_cmds::slot_fs_type(slot);
+        let parent       = self.nodes[id].parent;
+
+        let mid = self.mounts.add_ext(fs_type, id as InodeId, parent, slot)?;
+        let m = &mut self.mounts.mounts[mid as usize];
+        m.covered_fs   = covered_fs;
+        m.covered_ino  = covered_ino;
+        m.covered_slot = covered_slot;
+
+        let node = &mut self.nodes[id];
+        node.fs_type         = fs_type;
+        node.ext2_ino        = EXT2_ROOT_INO;
+        node.ext_slot        = slot;
+        node.mount_id        = mid;
+        node.children_loaded = false;
+
+        crate::serial_println!("[vfs] mount ext slot {} at '{}' id={} mnt={}", slot, mountpoint, id, mid);
+        Ok(id)
+    }
+
+    pub fn umount_ext(&mut self, cwd: usize, path: &str) -> VfsResult<u8> {
+        let id = self.resolve_path_follow(cwd, path)?;
+        let m = match self.mounts.find_by_root(id as InodeId) {
+            Some(m) if m.ext_slot != INVALID_U8 => *m,
+            _ => return Err(VfsError::NotMounted),
+        };
+
+        let busy = self.nodes.iter().any(|n| {
+            n.active && n.ext_slot == m.ext_slot && n.refcount > 0
+        });
+        if busy {
+            return Err(VfsError::Busy);
+        }
+
+        self.evict_ext2_children(id);
+        self.mounts.remove(m.id)?;
+
+        let node = &mut self.nodes[id];
+        node.fs_type         = m.covered_fs;
+        node.ext2_ino        = m.covered_ino;
+        node.ext_slot        = m.covered_slot;
+        node.mount_id        = INVALID_U8;
+        node.children_loaded = false;
+
+        crate::serial_println!("[vfs] umount ext slot {} from '{}' id={}", m.ext_slot, path, id);
+        Ok(m.ext_slot)
+    }
+
     pub fn mkdir(&mut self, parent: usize, name: &str, mode: FileMode) -> VfsResult<usize> {
         Self::validate_name(name)?;
         let pid = self.effective_node(parent);
@@ -1022,12 +1112,13 @@ impl MikuVFS {
         if self.nodes[pid].is_ext_backed() {
             let parent_ino = self.nodes[pid].ext2_ino;
             let disk_mode = applied_mode.0;
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                 fs.ext3_create_dir(parent_ino, name, disk_mode)
             });
             match result {
                 Some(Ok(new_ino)) => {
                     self.nodes[id].ext2_ino = new_ino;
+                    self.nodes[id].ext_slot = self.nodes[pid].ext_slot;
                 }
                 Some(Err(_)) => {
                     self.nodes[id].active = false;
@@ -1043,7 +1134,7 @@ impl MikuVFS {
         if !self.nodes[pid].children.insert(name, id as InodeId) {
             if self.nodes[id].ext2_ino != 0 {
                 let parent_ino = self.nodes[pid].ext2_ino;
-                let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                     fs.ext3_delete_dir(parent_ino, name)
                 });
             }
@@ -1067,6 +1158,9 @@ impl MikuVFS {
         if id == 0 {
             return Err(VfsError::PermissionDenied);
         }
+        if self.nodes[id].is_mountpoint() {
+            return Err(VfsError::Busy);
+        }
         if self.is_readonly_fs(id) {
             return Err(VfsError::ReadOnly);
         }
@@ -1094,7 +1188,7 @@ impl MikuVFS {
             let nlen = dir_name.len().min(NAME_LEN);
             name_buf[..nlen].copy_from_slice(dir_name.as_bytes());
             let name_str = unsafe { core::str::from_utf8_unchecked(&name_buf[..nlen]) };
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                 fs.ext3_delete_dir(parent_ino, name_str)
             });
             match result {
@@ -1199,12 +1293,13 @@ impl MikuVFS {
         if self.nodes[pid].is_ext_backed() {
             let parent_ino = self.nodes[pid].ext2_ino;
             let disk_mode = applied_mode.0;
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                 fs.ext3_create_file(parent_ino, name, disk_mode)
             });
             match result {
                 Some(Ok(new_ino)) => {
                     self.nodes[id].ext2_ino = new_ino;
+                    self.nodes[id].ext_slot = self.nodes[pid].ext_slot;
                 }
                 Some(Err(_)) => {
                     self.nodes[id].active = false;
@@ -1225,7 +1320,7 @@ impl MikuVFS {
                 let flen = fname.len().min(NAME_LEN);
                 fname_buf[..flen].copy_from_slice(&fname.as_bytes()[..flen]);
                 let fname_str = unsafe { core::str::from_utf8_unchecked(&fname_buf[..flen]) };
-                let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                     fs.ext3_delete_file(parent_ino, fname_str)
                 });
             }
@@ -1274,12 +1369,13 @@ impl MikuVFS {
 
         if self.nodes[pid].is_ext_backed() {
             let parent_ino = self.nodes[pid].ext2_ino;
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                 fs.ext3_create_symlink(parent_ino, linkname, target)
             });
             match result {
                 Some(Ok(new_ino)) => {
                     self.nodes[id].ext2_ino = new_ino;
+                    self.nodes[id].ext_slot = self.nodes[pid].ext_slot;
                 }
                 Some(Err(_)) => {
                     self.nodes[id].active = false;
@@ -1295,7 +1391,7 @@ impl MikuVFS {
         if !self.nodes[pid].children.insert(linkname, id as InodeId) {
             if self.nodes[id].ext2_ino != 0 {
                 let parent_ino = self.nodes[pid].ext2_ino;
-                let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                     fs.ext3_delete_file(parent_ino, linkname)
                 });
             }
@@ -1354,7 +1450,7 @@ impl MikuVFS {
         {
             let parent_ino = self.nodes[pid].ext2_ino;
             let target_ino = self.nodes[target_id].ext2_ino;
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                 fs.ext3_hardlink(parent_ino, new_name, target_ino)
             });
             match result {
@@ -1367,7 +1463,7 @@ impl MikuVFS {
         if !self.nodes[pid].children.insert(new_name, target_id as InodeId) {
             if self.nodes[pid].is_ext_backed() && self.nodes[pid].ext2_ino != 0 {
                 let parent_ino = self.nodes[pid].ext2_ino;
-                let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                     fs.ext3_delete_file(parent_ino, new_name)
                 });
             }
@@ -1479,7 +1575,7 @@ impl MikuVFS {
                         let nlen = file_name.len().min(NAME_LEN);
                         name_buf[..nlen].copy_from_slice(file_name.as_bytes());
                         let name_str = unsafe { core::str::from_utf8_unchecked(&name_buf[..nlen]) };
-                        let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                        let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                             fs.ext3_delete_file(parent_ino, name_str)
                         });
                     }
@@ -1596,7 +1692,7 @@ impl MikuVFS {
     ) -> VfsResult<usize> {
         let ext2_ino = self.nodes[vid].ext2_ino;
 
-        let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+        let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[vid].ext_slot, |fs| {
             let inode = fs.read_inode(ext2_ino).map_err(|_| VfsError::IoError)?;
             let size = inode.size() as u64;
             if offset >= size {
@@ -1641,7 +1737,7 @@ impl MikuVFS {
         };
 
         let vnode_used = self.total_vnodes();
-        let mut proc_buf = [0u8; 192];
+        let mut proc_buf = [0u8; 1024];
 
         match procfs::proc_read(name_str, &mut proc_buf, vnode_used) {
             Ok(total) => {
@@ -1701,7 +1797,7 @@ impl MikuVFS {
         if is_append {
             if self.nodes[vid].is_ext2_backed() {
                 let ino = self.nodes[vid].ext2_ino;
-                let disk_size = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let disk_size = crate::commands::ext2_cmds::with_ext_slot(self.nodes[vid].ext_slot, |fs| {
                     fs.read_inode(ino).map(|i| i.size()).unwrap_or(0)
                 });
                 if let Some(sz) = disk_size {
@@ -1774,7 +1870,7 @@ impl MikuVFS {
         let ext2_ino = self.nodes[vid].ext2_ino;
         let is_sync = self.fd_table.get(fd).map(|f| f.flags.has(OpenFlags::SYNC)).unwrap_or(false);
 
-        let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+        let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[vid].ext_slot, |fs| {
             fs.ext3_write_file(ext2_ino, data, offset)
                 .map_err(|_| VfsError::IoError)
         });
@@ -1795,7 +1891,7 @@ impl MikuVFS {
         self.nodes[vid].flags.dirty = true;
 
         if is_sync {
-            let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| fs.sync());
+            let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[vid].ext_slot, |fs| fs.sync());
             self.nodes[vid].flags.dirty = false;
         }
 
@@ -1803,6 +1899,29 @@ impl MikuVFS {
         Ok(n)
     }
 
+    pub fn ftruncate(&mut self, fd: usize, new_size: u64) -> VfsResult<()> {
+        let file = self.fd_table.get(fd)?;
+        if !file.flags.writable() {
+            return Err(VfsError::BadFd);
+        }
+        let vid = file.vnode_id as usize;
+        if !self.valid_vnode(vid) {
+            return Err(VfsError::BadFd);
+        }
+        if !self.nodes[vid].is_regular() {
+            return Err(VfsError::InvalidArgument);
+        }
+        if self.nodes[vid].flags.immutable {
+            return Err(VfsError::PermissionDenied);
+        }
+        if new_size > AddressSpace::max_size() && !self.nodes[vid].is_ext_backed() {
+            return Err(VfsError::FileTooLarge);
+        }
+        self.truncate_to(vid, new_size);
+        crate::serial_println!("[vfs] ftruncate fd={} vnode={} size={}", fd, vid, new_size);
+        Ok(())
+    }
+
     pub fn seek(&mut self, fd: usize, whence: SeekFrom) -> VfsResult<u64> {
         let file = self.fd_table.get(fd)?;
         let vid = file.vnode_id as usize;
@@ -1837,7 +1956,7 @@ impl MikuVFS {
         }
 
         if self.nodes[vid].fs_type.is_ext_family() {
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[vid].ext_slot, |fs| {
                 fs.sync().map_err(|_| VfsError::IoError)
             });
             match result {
@@ -1877,7 +1996,7 @@ impl MikuVFS {
             let nlen = file_name.len().min(NAME_LEN);
             name_buf[..nlen].copy_from_slice(file_name.as_bytes());
             let name_str = unsafe { core::str::from_utf8_unchecked(&name_buf[..nlen]) };
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[pid].ext_slot, |fs| {
                 fs.ext3_delete_file(parent_ino, name_str)
             });
             match result {
@@ -1933,7 +2052,9 @@ impl MikuVFS {
         if !self.nodes[new_pid].is_dir() {
             return Err(VfsError::NotDirectory);
         }
-        if self.nodes[new_pid].fs_type != self.nodes[id].fs_type {
+        if self.nodes[new_pid].fs_type != self.nodes[id].fs_type
+            || self.nodes[new_pid].ext_slot != self.nodes[id].ext_slot
+        {
             return Err(VfsError::CrossDevice);
         }
 
@@ -1957,10 +2078,33 @@ impl MikuVFS {
         }
 
         let new_h = name_hash(new_base);
+        let mut victim = None;
         for cid in self.nodes[new_pid].children.find_by_hash(new_h) {
             let c = cid as usize;
             if c < MAX_VNODES && self.nodes[c].active && self.nodes[c].name_eq(new_base) && c != id {
-                return Err(VfsError::AlreadyExists);
+                victim = Some(c);
+                break;
+            }
+        }
+        if let Some(v) = victim {
+            if self.nodes[v].flags.immutable {
+                return Err(VfsError::PermissionDenied);
+            }
+            if self.nodes[v].is_mountpoint() {
+                return Err(VfsError::Busy);
+            }
+            match (self.nodes[id].is_dir(), self.nodes[v].is_dir()) {
+                (true, false) => return Err(VfsError::NotDirectory),
+                (false, true) => return Err(VfsError::IsDirectory),
+                (true, true) => {
+                    if self.nodes[v].is_ext_backed() && !self.nodes[v].children_loaded {
+                        self.ext2_ensure_children_loaded(v)?;
+                    }
+                    if !self.is_dir_empty(v) {
+                        return Err(VfsError::NotEmpty);
+                    }
+                }
+                (false, false) => {}
             }
         }
 
@@ -1977,8 +2121,12 @@ impl MikuVFS {
             let olen = old_name.len().min(NAME_LEN);
             old_buf[..olen].copy_from_slice(old_name.as_bytes());
             let old_str = unsafe { core::str::from_utf8_unchecked(&old_buf[..olen]) };
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
-                fs.ext3_rename(parent_ino, old_str, new_base)
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[old_pid].ext_slot, |fs| {
+                if victim.is_some() {
+                    fs.ext3_rename_replace(parent_ino, old_str, new_base)
+                } else {
+                    fs.ext3_rename(parent_ino, old_str, new_base)
+                }
             });
             match result {
                 Some(Ok(())) => {}
@@ -1987,6 +2135,25 @@ impl MikuVFS {
             }
         }
 
+        if let Some(v) = victim {
+            self.nodes[new_pid].children.remove(new_h, v as InodeId);
+            if self.nodes[v].is_dir() {
+                if self.nodes[new_pid].nlinks > 0 {
+                    self.nodes[new_pid].nlinks -= 1;
+                }
+                self.nodes[v].active = false;
+            } else {
+                self.nodes[v].nlinks = self.nodes[v].nlinks.saturating_sub(1);
+                if self.nodes[v].nlinks == 0 && self.nodes[v].refcount == 0 {
+                    self.free_file_pages(v);
+                    self.nodes[v].active = false;
+                    if v < self.vnode_free_hint {
+                        self.vnode_free_hint = v;
+                    }
+                }
+            }
+        }
+
         let mut old_name_buf = [0u8; NAME_LEN];
         let old_name_len = self.nodes[id].name.len as usize;
         old_name_buf[..old_name_len].copy_from_slice(&self.nodes[id].name.data[..old_name_len]);
@@ -2009,7 +2176,7 @@ impl MikuVFS {
             let _ = self.nodes[old_pid].children.insert(rollback_name, id as InodeId);
             if self.nodes[id].is_ext_backed() && self.nodes[old_pid].ext2_ino != 0 {
                 let parent_ino = self.nodes[old_pid].ext2_ino;
-                let _ = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let _ = crate::commands::ext2_cmds::with_ext_slot(self.nodes[old_pid].ext_slot, |fs| {
                     fs.ext3_rename(parent_ino, new_base, rollback_name)
                 });
             }
@@ -2037,7 +2204,7 @@ impl MikuVFS {
             return;
         }
         let ino = self.nodes[id].ext2_ino;
-        let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+        let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| {
             fs.read_inode(ino).map(|inode| (inode.size(), inode.blocks()))
         });
         if let Some(Ok((size, blocks))) = result {
@@ -2079,7 +2246,7 @@ impl MikuVFS {
 
         if self.nodes[id].is_ext_backed() {
             let ino = self.nodes[id].ext2_ino;
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| {
                 fs.ext2_chmod(ino, mode.0)
             });
             match result {
@@ -2115,7 +2282,7 @@ impl MikuVFS {
 
         if self.nodes[id].is_ext_backed() {
             let ino = self.nodes[id].ext2_ino;
-            let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+            let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| {
                 fs.ext2_chown(ino, new_uid, new_gid)
             });
             match result {
@@ -2147,7 +2314,7 @@ impl MikuVFS {
             }
             if self.nodes[id].is_ext_backed() {
                 let ino = self.nodes[id].ext2_ino;
-                let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| {
                     fs.ext2_chmod(ino, mode.0)
                 });
                 if matches!(result, Some(Err(_)) | None) {
@@ -2165,7 +2332,7 @@ impl MikuVFS {
             let new_gid = attr.gid.unwrap_or(self.nodes[id].gid);
             if self.nodes[id].is_ext_backed() {
                 let ino = self.nodes[id].ext2_ino;
-                let result = crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let result = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| {
                     fs.ext2_chown(ino, new_uid, new_gid)
                 });
                 if matches!(result, Some(Err(_)) | None) {
@@ -2199,7 +2366,7 @@ impl MikuVFS {
         let fs_type = self.nodes[id].fs_type;
 
         if fs_type.is_ext_family() {
-            let info = crate::commands::ext2_cmds::with_ext2_pub(|fs| fs.fs_info());
+            let info = crate::commands::ext2_cmds::with_ext_slot(self.nodes[id].ext_slot, |fs| fs.fs_info());
             if let Some(info) = info {
                 return Ok(StatFs {
                     fs_type,
@@ -9,6 +9,10 @@ pub struct MountEntry {
     pub flags: u32,
     pub active: bool,
     pub read_only: bool,
+    pub ext_slot: u8,
+    pub covered_fs: FsType,
+    pub covered_ino: u32,
+    pub covered_slot: u8,
 }
 
 impl MountEntry {
@@ -21,6 +25,10 @@ impl MountEntry {
             flags: 0,
             active: false,
             read_only: false,
+            ext_slot: INVALID_U8,
+            covered_fs: FsType::TmpFS,
+            covered_ino: 0,
+            covered_slot: INVALID_U8,
         }
     }
 }
@@ -55,6 +63,7 @@ impl MountTable {
                     flags: 0,
                     active: true,
                     read_only: matches!(fs_type, FsType::ProcFS),
+                    ..MountEntry::empty()
                 };
                 self.count += 1;
                 return Ok(i as u8);
@@ -76,6 +85,16 @@ impl MountTable {
         }
     }
 
+    pub fn add_ext(&mut self, fs_type: FsType, root: InodeId, parent: InodeId, slot: u8) -> VfsResult<u8> {
+        let id = self.add(fs_type, root, parent)?;
+        self.mounts[id as usize].ext_slot = slot;
+        Ok(id)
+    }
+
+    pub fn find_by_ext_slot(&self, slot: u8) -> Option<&MountEntry> {
+        self.iter().find(|m| m.ext_slot == slot)
+    }
+
     pub fn get(&self, id: u8) -> Option<&MountEntry> {
         let i = id as usize;
         if i < MAX_MOUNTS && self.mounts[i].active {
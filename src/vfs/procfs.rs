@@ -54,6 +54,12 @@ pub fn proc_read(name: &str, buf: &mut [u8], vnode_used: usize) -> VfsResult<usi
         }
         "stat" => format_stat(&mut tmp),
         "heap" => format_heap(&mut tmp),
+        "dhcp" => {
+            let text = crate::net::dhcp::lease_text();
+            let l = text.len().min(buf.len());
+            buf[..l].copy_from_slice(&text.as_bytes()[..l]);
+            return Ok(l);
+        }
         _ => return Err(VfsError::NotFound),
     };
 
@@ -175,5 +181,5 @@ fn write_u64(buf: &mut [u8; 192], pos: usize, val: u64) -> usize {
 }
 
 pub const PROC_ENTRIES: &[&str] = &[
-    "version", "uptime", "meminfo", "mounts", "cpuinfo", "stat", "heap",
+    "version", "uptime", "meminfo", "mounts", "cpuinfo", "stat", "heap", "dhcp",
 ];
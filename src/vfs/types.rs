@@ -2,7 +2,7 @@ pub const MAX_VNODES: usize = 256;
 pub const MAX_DENTRIES: usize = 128;
 pub const MAX_MOUNTS: usize = 8;
 pub const MAX_FDS: usize = 32;
-pub const MAX_OPEN_FILES: usize = 32;
+pub const MAX_OPEN_FILES: usize = 128;
 pub const MAX_DATA_PAGES: usize = 128;
 pub const MAX_XATTRS_PER_NODE: usize = 8;
 pub const MAX_LOCKS: usize = 16;
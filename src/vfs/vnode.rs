@@ -62,6 +62,7 @@ pub struct VNode {
     pub flags: VNodeFlags,
 
     pub ext2_ino: u32,
+    pub ext_slot: u8,
     pub children_loaded: bool,
 }
 
@@ -92,6 +93,7 @@ impl VNode {
             flags: VNodeFlags::empty(),
             active: false,
             ext2_ino: 0,
+            ext_slot: INVALID_U8,
             children_loaded: false,
         }
     }
@@ -155,6 +157,11 @@ impl VNode {
     pub fn is_mountpoint(&self) -> bool {
         self.mount_id != INVALID_U8
     }
+
+    #[inline]
+    pub fn in_ext_mount(&self) -> bool {
+        self.is_ext_backed() && self.ext_slot != INVALID_U8
+    }
     #[inline]
     pub fn name_eq(&self, name: &str) -> bool {
         self.name.eq_str(name)
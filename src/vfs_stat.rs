@@ -0,0 +1,230 @@
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::miku_extfs::structs::{
+    Inode, FT_BLKDEV, FT_CHRDEV, FT_DIR, FT_FIFO, FT_REG_FILE, FT_SOCK, FT_SYMLINK,
+};
+use crate::miku_extfs::MikuFS;
+use crate::syscall::{EINVAL, ELOOP, ENOENT};
+use crate::vfs::core::with_vfs;
+use crate::vfs::path::PathWalker;
+use crate::vfs::types::{VNodeKind, VNodeStat};
+use crate::vfs_write::ext_errno;
+
+pub const S_IFIFO:  u32 = 0o010000;
+pub const S_IFCHR:  u32 = 0o020000;
+pub const S_IFDIR:  u32 = 0o040000;
+pub const S_IFBLK:  u32 = 0o060000;
+pub const S_IFREG:  u32 = 0o100000;
+pub const S_IFLNK:  u32 = 0o120000;
+pub const S_IFSOCK: u32 = 0o140000;
+
+pub const DT_UNKNOWN: u8 = 0;
+pub const DT_FIFO:    u8 = 1;
+pub const DT_CHR:     u8 = 2;
+pub const DT_DIR:     u8 = 4;
+pub const DT_BLK:     u8 = 6;
+pub const DT_REG:     u8 = 8;
+pub const DT_LNK:     u8 = 10;
+pub const DT_SOCK:    u8 = 12;
+
+pub const EXT_VOLUME_DEV: u64 = 0x100;
+
+const DIRENT_HEADER: usize = 19;
+const DIRENT_BATCH:  usize = 256;
+const SYMLINK_MAX:   usize = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MikuStat {
+    pub dev:    u64,
+    pub ino:    u64,
+    pub mode:   u32,
+    pub nlink:  u32,
+    pub uid:    u32,
+    pub gid:    u32,
+    pub rdev:   u64,
+    pub size:   u64,
+    pub blocks: u64,
+    pub atime:  u64,
+    pub mtime:  u64,
+    pub ctime:  u64,
+}
+
+pub struct DirEnt {
+    pub ino:   u64,
+    pub dtype: u8,
+    pub name:  String,
+}
+
+fn kind_bits(kind: VNodeKind) -> u32 {
+    match kind {
+        VNodeKind::Regular                     => S_IFREG,
+        VNodeKind::Directory                   => S_IFDIR,
+        VNodeKind::Symlink                     => S_IFLNK,
+        VNodeKind::CharDevice                  => S_IFCHR,
+        VNodeKind::BlockDevice                 => S_IFBLK,
+        VNodeKind::Pipe | VNodeKind::Fifo      => S_IFIFO,
+        VNodeKind::Socket                      => S_IFSOCK,
+    }
+}
+
+fn kind_dtype(kind: VNodeKind) -> u8 {
+    match kind {
+        VNodeKind::Regular                     => DT_REG,
+        VNodeKind::Directory                   => DT_DIR,
+        VNodeKind::Symlink                     => DT_LNK,
+        VNodeKind::CharDevice                  => DT_CHR,
+        VNodeKind::BlockDevice                 => DT_BLK,
+        VNodeKind::Pipe | VNodeKind::Fifo      => DT_FIFO,
+        VNodeKind::Socket                      => DT_SOCK,
+    }
+}
+
+fn ext_dtype(file_type: u8) -> u8 {
+    match file_type {
+        FT_REG_FILE => DT_REG,
+        FT_DIR      => DT_DIR,
+        FT_SYMLINK  => DT_LNK,
+        FT_CHRDEV   => DT_CHR,
+        FT_BLKDEV   => DT_BLK,
+        FT_FIFO     => DT_FIFO,
+        FT_SOCK     => DT_SOCK,
+        _           => DT_UNKNOWN,
+    }
+}
+
+fn ticks_to_secs(ticks: u64) -> u64 {
+    ticks / crate::interrupts::PIT_HZ as u64
+}
+
+impl MikuStat {
+    pub fn from_vnode(st: &VNodeStat) -> Self {
+        let rdev = match st.kind {
+            VNodeKind::CharDevice | VNodeKind::BlockDevice => {
+                ((st.dev_major as u64) << 8) | st.dev_minor as u64
+            }
+            _ => 0,
+        };
+        Self {
+            dev:    st.fs_type as u64,
+            ino:    st.id as u64,
+            mode:   kind_bits(st.kind) | (st.mode.0 as u32 & 0o7777),
+            nlink:  st.nlinks as u32,
+            uid:    st.uid as u32,
+            gid:    st.gid as u32,
+            rdev,
+            size:   st.size,
+            blocks: st.blocks as u64 * 8,
+            atime:  ticks_to_secs(st.atime),
+            mtime:  ticks_to_secs(st.mtime),
+            ctime:  ticks_to_secs(st.ctime),
+        }
+    }
+
+    pub fn from_ext(ino: u32, inode: &Inode) -> Self {
+        Self {
+            dev:    EXT_VOLUME_DEV,
+            ino:    ino as u64,
+            mode:   inode.mode() as u32,
+            nlink:  inode.links_count() as u32,
+            uid:    inode.uid_full(),
+            gid:    inode.gid_full(),
+            rdev:   0,
+            size:   inode.size(),
+            blocks: inode.blocks_full(),
+            atime:  inode.atime() as u64,
+            mtime:  inode.mtime() as u64,
+            ctime:  inode.ctime() as u64,
+        }
+    }
+
+    pub fn special(mode: u32, size: u64) -> Self {
+        Self { mode, nlink: 1, size, ..Self::default() }
+    }
+}
+
+fn ext_lookup(fs: &mut MikuFS, path: &str, follow: bool) -> Result<u32, i64> {
+    let mut path = String::from(path);
+    for _ in 0..SYMLINK_MAX {
+        let ino = fs.resolve_path(&path).map_err(ext_errno)?;
+        let inode = fs.read_inode(ino).map_err(ext_errno)?;
+        if !follow || !inode.is_symlink() {
+            return Ok(ino);
+        }
+        let mut target = alloc::vec![0u8; inode.size() as usize];
+        let n = if inode.is_fast_symlink() {
+            let t = inode.fast_symlink_target();
+            target[..t.len()].copy_from_slice(t);
+            t.len()
+        } else {
+            fs.read_file(&inode, 0, &mut target).map_err(ext_errno)?
+        };
+        let target = core::str::from_utf8(&target[..n]).map_err(|_| EINVAL)?;
+        path = if target.starts_with('/') {
+            String::from(target)
+        } else {
+            let (dir, _) = PathWalker::split_last(&path);
+            alloc::format!("{}/{}", dir, target)
+        };
+    }
+    Err(ELOOP)
+}
+
+pub fn stat(path: &str, follow: bool) -> Result<MikuStat, i64> {
+    let r = with_vfs(|v| if follow { v.stat(0, path) } else { v.lstat(0, path) });
+    match r {
+        Ok(st) => Ok(MikuStat::from_vnode(&st)),
+        Err(crate::vfs::types::VfsError::NotFound) => {
+            let path = if path.starts_with('/') { String::from(path) } else { alloc::format!("/{}", path) };
+            crate::commands::ext2_cmds::with_ext2_pub(|fs| {
+                let ino = ext_lookup(fs, &path, follow)?;
+                let inode = fs.read_inode(ino).map_err(ext_errno)?;
+                Ok(MikuStat::from_ext(ino, &inode))
+            })
+            .unwrap_or(Err(ENOENT))
+        }
+        Err(e) => Err(crate::fdtable::vfs_errno(e)),
+    }
+}
+
+pub fn vfs_entries(fd: usize, skip: usize) -> Result<Vec<DirEnt>, i64> {
+    with_vfs(|v| {
+        let vid = v.fd_table.get(fd)?.vnode_id as usize;
+        let mut raw = alloc::vec![crate::vfs::types::DirEntry::empty(); skip + DIRENT_BATCH];
+        let n = v.readdir(vid, &mut raw)?;
+        Ok(raw[skip.min(n)..n]
+            .iter()
+            .map(|e| DirEnt {
+                ino:   e.inode_id as u64,
+                dtype: kind_dtype(e.kind),
+                name:  String::from(e.get_name()),
+            })
+            .collect())
+    })
+    .map_err(crate::fdtable::vfs_errno)
+}
+
+pub fn pack_dirents(entries: &[DirEnt], start: u64, buf: &mut [u8]) -> Result<(usize, usize), i64> {
+    let mut pos  = 0usize;
+    let mut used = 0usize;
+    for (i, e) in entries.iter().enumerate() {
+        let reclen = (DIRENT_HEADER + e.name.len() + 1 + 7) & !7;
+        if pos + reclen > buf.len() {
+            break;
+        }
+        let rec = &mut buf[pos..pos + reclen];
+        rec.fill(0);
+        rec[0..8].copy_from_slice(&e.ino.to_le_bytes());
+        rec[8..16].copy_from_slice(&(start + i as u64 + 1).to_le_bytes());
+        rec[16..18].copy_from_slice(&(reclen as u16).to_le_bytes());
+        rec[18] = e.dtype;
+        rec[DIRENT_HEADER..DIRENT_HEADER + e.name.len()].copy_from_slice(e.name.as_bytes());
+        pos  += reclen;
+        used += 1;
+    }
+    if used == 0 && !entries.is_empty() {
+        return Err(EINVAL);
+    }
+    Ok((pos, used))
+}
@@ -0,0 +1,48 @@
+extern crate alloc;
+use crate::fdtable::vfs_errno;
+use crate::miku_extfs::error::FsError;
+use crate::syscall::{
+    EEXIST, EFBIG, EINVAL, EIO, EISDIR, ELOOP, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, EROFS,
+};
+use crate::vfs::core::with_vfs;
+use crate::vfs::path::PathWalker;
+use crate::vfs::types::FileMode;
+
+pub fn ext_errno(e: FsError) -> i64 {
+    match e {
+        FsError::NotFound                                     => ENOENT,
+        FsError::NotDirectory                                 => ENOTDIR,
+        FsError::IsDirectory                                  => EISDIR,
+        FsError::NotRegularFile                               => EINVAL,
+        FsError::AlreadyExists                                => EEXIST,
+        FsError::NotEmpty                                     => ENOTEMPTY,
+        FsError::NoSpace | FsError::JournalFull
+        | FsError::ExtentFull                                 => ENOSPC,
+        FsError::FileTooLarge                                 => EFBIG,
+        FsError::ReadOnlyFs                                   => EROFS,
+        FsError::LinkLoop                                     => ELOOP,
+        _                                                     => EIO,
+    }
+}
+
+pub fn unlink(path: &str) -> Result<(), i64> {
+    with_vfs(|v| v.unlink(0, path)).map_err(vfs_errno)
+}
+
+pub fn mkdir(path: &str, mode: u16) -> Result<(), i64> {
+    with_vfs(|v| {
+        let (dir, name) = PathWalker::split_last(path);
+        let parent = v.resolve_path(0, dir)?;
+        v.mkdir(parent, name, FileMode::new(mode))
+    })
+    .map(|_| ())
+    .map_err(vfs_errno)
+}
+
+pub fn rmdir(path: &str) -> Result<(), i64> {
+    with_vfs(|v| v.rmdir(0, path)).map_err(vfs_errno)
+}
+
+pub fn rename(old: &str, new: &str) -> Result<(), i64> {
+    with_vfs(|v| v.rename(0, old, new)).map_err(vfs_errno)
+}
@@ -1,8 +1,16 @@
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
 use crate::pmm;
 use crate::grub;
 use x86_64::structures::paging::{page_table::PageTableEntry, PageTable, PageTableFlags};
 use x86_64::registers::control::Cr3;
 
+pub const PTE_COW: PageTableFlags = PageTableFlags::BIT_9;
+
+static ASPACE_REFS: Mutex<BTreeMap<u64, u32>> = Mutex::new(BTreeMap::new());
+
 pub struct AddressSpace {
     pub cr3: u64,
 }
@@ -100,9 +108,7 @@ impl AddressSpace {
             if crate::swap_map::is_swap_pte(pte) {
                 crate::swap::free_swap_slot(crate::swap_map::slot_from_pte(pte));
             } else if (&*p1)[p1i].flags().contains(PageTableFlags::PRESENT) {
-                let phys = (&*p1)[p1i].addr().as_u64();
-                crate::swap_map::untrack(phys);
-                pmm::free_frame(phys);
+                release_frame((&*p1)[p1i].addr().as_u64(), self.cr3);
             }
             (&mut *p1)[p1i].set_unused();
             x86_64::instructions::tlb::flush(x86_64::VirtAddr::new(virt));
@@ -154,9 +160,7 @@ impl AddressSpace {
                             if crate::swap_map::is_swap_pte(pte) {
                                 crate::swap::free_swap_slot(crate::swap_map::slot_from_pte(pte));
                             } else if (&*p1)[m].flags().contains(PageTableFlags::PRESENT) {
-                                let phys = (&*p1)[m].addr().as_u64();
-                                crate::swap_map::untrack(phys);
-                                pmm::free_frame(phys);
+                                release_frame((&*p1)[m].addr().as_u64(), self.cr3);
                             }
                         }
                         pmm::free_frame((&*p2)[k].addr().as_u64());
@@ -170,6 +174,113 @@ impl AddressSpace {
         self.cr3 = 0;
     }
 
+    pub fn fork_cow(&self) -> Option<AddressSpace> {
+        let child = AddressSpace::new_user()?;
+        let hhdm  = grub::hhdm();
+        let mut shared: Vec<(u64, u64, PageTableFlags)> = Vec::new();
+        unsafe {
+            let p4 = (self.cr3 + hhdm) as *mut PageTable;
+            for i in 0..256 {
+                if !(&*p4)[i].flags().contains(PageTableFlags::PRESENT) { continue; }
+                let p3 = ((&*p4)[i].addr().as_u64() + hhdm) as *mut PageTable;
+                for j in 0..512 {
+                    if !(&*p3)[j].flags().contains(PageTableFlags::PRESENT) { continue; }
+                    let p2 = ((&*p3)[j].addr().as_u64() + hhdm) as *mut PageTable;
+                    for k in 0..512 {
+                        if !(&*p2)[k].flags().contains(PageTableFlags::PRESENT) { continue; }
+                        let p1 = ((&*p2)[k].addr().as_u64() + hhdm) as *mut PageTable;
+                        for m in 0..512 {
+                            let virt = ((i as u64) << 39) | ((j as u64) << 30)
+                                | ((k as u64) << 21) | ((m as u64) << 12);
+                            let raw = *(&(&*p1)[m] as *const _ as *const u64);
+                            if crate::swap_map::is_swap_pte(raw) {
+                                let slot = crate::swap_map::slot_from_pte(raw);
+                                if !crate::swap_map::try_swapin(self.cr3, virt, slot) {
+                                    crate::serial_println!("[vmm] fork: swap-in failed at {:#x}", virt);
+                                    self.unwind_fork(&child, &shared);
+                                    return None;
+                                }
+                            }
+                            let entry = &mut (&mut *p1)[m];
+                            if !entry.flags().contains(PageTableFlags::PRESENT) { continue; }
+                            let phys  = entry.addr().as_u64();
+                            let orig  = entry.flags();
+                            let mut flags = orig;
+                            if flags.intersects(PageTableFlags::WRITABLE | PTE_COW) {
+                                flags.remove(PageTableFlags::WRITABLE);
+                                flags |= PTE_COW;
+                                entry.set_flags(flags);
+                            }
+                            if !child.map_page(virt, phys, flags) {
+                                if !pmm::is_shared(phys) { entry.set_flags(orig); }
+                                self.unwind_fork(&child, &shared);
+                                return None;
+                            }
+                            pmm::share_frame(phys);
+                            crate::swap_map::track(phys, self.cr3, virt, true);
+                            shared.push((virt, phys, orig));
+                        }
+                    }
+                }
+            }
+        }
+        x86_64::instructions::tlb::flush_all();
+        Some(child)
+    }
+
+    fn unwind_fork(&self, child: &AddressSpace, shared: &[(u64, u64, PageTableFlags)]) {
+        for &(virt, phys, orig) in shared {
+            child.unmap_page_no_free(virt);
+            pmm::drop_shared_ref(phys);
+            if pmm::is_shared(phys) {
+                crate::swap_map::track(phys, self.cr3, virt, true);
+            } else {
+                self.unmap_page_no_free(virt);
+                self.map_page(virt, phys, orig);
+            }
+        }
+        x86_64::instructions::tlb::flush_all();
+    }
+
+    pub fn handle_cow_fault(&self, virt: u64) -> bool {
+        let page  = virt & !0xFFF;
+        let flags = match self.get_page_flags(page) {
+            Some(f) if f.contains(PTE_COW) => f,
+            _ => return false,
+        };
+        let Some(old_phys) = self.virt_to_phys(page) else { return false; };
+        let mut new_flags = flags | PageTableFlags::WRITABLE;
+        new_flags.remove(PTE_COW);
+
+        if !pmm::is_shared(old_phys) {
+            self.unmap_page_no_free(page);
+            self.map_page(page, old_phys, new_flags);
+            x86_64::instructions::tlb::flush(x86_64::VirtAddr::new(page));
+            return true;
+        }
+
+        let Some(new_phys) = crate::swap_map::alloc_or_evict() else {
+            crate::serial_println!("[vmm] cow: out of memory at {:#x}", page);
+            return false;
+        };
+        let hhdm = grub::hhdm();
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                (old_phys + hhdm) as *const u8,
+                (new_phys + hhdm) as *mut u8,
+                4096,
+            );
+        }
+        self.unmap_page_no_free(page);
+        unshare_frame(old_phys, self.cr3);
+        if !self.map_page(page, new_phys, new_flags) {
+            pmm::free_frame(new_phys);
+            return false;
+        }
+        x86_64::instructions::tlb::flush(x86_64::VirtAddr::new(page));
+        true
+    }
+
     pub fn activate(&self) {
         unsafe {
             core::arch::asm!(
@@ -272,6 +383,45 @@ unsafe fn get_or_create(entry: &mut PageTableEntry, hhdm: u64) -> Option<*mut Pa
     }
 }
 
+fn unshare_frame(phys: u64, cr3: u64) {
+    pmm::drop_shared_ref(phys);
+    if pmm::is_shared(phys) { return; }
+    if crate::swap_map::owner(phys) == Some(cr3) {
+        crate::swap_map::untrack(phys);
+    } else if phys >= 0x40_0000 {
+        crate::swap_map::set_pinned(phys, false);
+    }
+}
+
+fn release_frame(phys: u64, cr3: u64) {
+    if pmm::is_shared(phys) {
+        unshare_frame(phys, cr3);
+        return;
+    }
+    crate::swap_map::untrack(phys);
+    pmm::free_frame(phys);
+}
+
+pub fn share_address_space(cr3: u64) {
+    *ASPACE_REFS.lock().entry(cr3).or_insert(1) += 1;
+}
+
+pub fn release_address_space(cr3: u64) -> bool {
+    let mut refs = ASPACE_REFS.lock();
+    match refs.get_mut(&cr3) {
+        Some(n) if *n > 2 => { *n -= 1; false }
+        Some(_) => { refs.remove(&cr3); false }
+        None => true,
+    }
+}
+
+pub fn handle_cow_fault(cr3: u64, virt: u64) -> bool {
+    let aspace = AddressSpace::from_raw(cr3);
+    let handled = aspace.handle_cow_fault(virt);
+    let _ = aspace.into_raw();
+    handled
+}
+
 pub fn kernel_cr3() -> u64 {
     let (frame, _) = Cr3::read();
     frame.start_address().as_u64()